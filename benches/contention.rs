@@ -0,0 +1,127 @@
+// How much readers slow down per-player updates, with the old single
+// `RwLock<HashMap>` against the `DashMap` and copy-on-write roster in
+// `GameState`. Run with `cargo bench --bench contention`.
+//
+// One writer stands in for the game loop, touching a random player for every
+// message; the readers stand in for broadcasts, dashboards and `inspect`,
+// walking every player over and over. The numbers only mean something with
+// more cores than threads; on fewer the threads mostly compete for CPU time.
+use game_server::codec::WireFormat;
+use game_server::endpoint::Endpoint;
+use game_server::state::{GameState, Player};
+use rand::Rng;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PLAYERS: usize = 1_000;
+const RUN_TIME: Duration = Duration::from_secs(1);
+
+fn player(id: usize) -> Player {
+    let mut player = Player::new(id, Endpoint::Local(id as u64), WireFormat::Bincode);
+    player.joined = true;
+    player
+}
+
+// Writer updates per second while `readers` threads run `read` in a loop
+fn measure(
+    readers: usize,
+    read: impl Fn() + Send + Sync + 'static,
+    write: impl Fn(usize) + Send + 'static,
+) -> f64 {
+    let read = Arc::new(read);
+    let stop = Arc::new(AtomicBool::new(false));
+    let reader_threads: Vec<_> = (0..readers)
+        .map(|_| {
+            let (read, stop) = (read.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    read();
+                }
+            })
+        })
+        .collect();
+
+    let mut rng = rand::thread_rng();
+    let started = Instant::now();
+    let mut updates = 0u64;
+    while started.elapsed() < RUN_TIME {
+        write(rng.gen_range(1..=PLAYERS));
+        updates += 1;
+    }
+    let rate = updates as f64 / started.elapsed().as_secs_f64();
+
+    stop.store(true, Ordering::Relaxed);
+    for reader in reader_threads {
+        reader.join().unwrap();
+    }
+    rate
+}
+
+fn global_lock(readers: usize) -> f64 {
+    let players: Arc<RwLock<HashMap<usize, Player>>> = Arc::new(RwLock::new(
+        (1..=PLAYERS).map(|id| (id, player(id))).collect(),
+    ));
+    let reading = players.clone();
+    measure(
+        readers,
+        move || {
+            let players = reading.read().unwrap();
+            for player in players.values().filter(|p| p.joined) {
+                black_box((player.endpoint, player.wire_format));
+            }
+        },
+        move |id| {
+            if let Some(player) = players.write().unwrap().get_mut(&id) {
+                player.last_seen = Instant::now();
+                player.messages_sent += 1;
+            }
+        },
+    )
+}
+
+fn sharded(readers: usize) -> f64 {
+    let state = Arc::new(GameState::default());
+    for id in 1..=PLAYERS {
+        state.players.insert(id, player(id));
+    }
+    state.rebuild_roster();
+    let reading = state.clone();
+    measure(
+        readers,
+        move || {
+            for recipient in reading.roster().iter() {
+                black_box((recipient.endpoint, recipient.wire_format));
+            }
+        },
+        move |id| {
+            if let Some(mut player) = state.players.get_mut(&id) {
+                player.last_seen = Instant::now();
+                player.messages_sent += 1;
+            }
+        },
+    )
+}
+
+fn main() {
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    println!(
+        "{} players on {} cores, writer updates per second",
+        PLAYERS, cores
+    );
+    println!(
+        "{:>8} {:>16} {:>16}",
+        "readers", "RwLock<HashMap>", "DashMap"
+    );
+    for readers in [0, 1, 2, 4] {
+        println!(
+            "{:>8} {:>16.0} {:>16.0}",
+            readers,
+            global_lock(readers),
+            sharded(readers)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+// Abilities players use with `UseAbility`, defined by name in the JSON file
+// `abilities.file` points at. Everything about a use is checked here rather
+// than trusted from the client: that the ability exists, that its target is
+// within `range` of the player, that it is off cooldown and that the player
+// has the energy it costs. Energy tops up once a second to `max_energy`. A
+// use that checks out goes off as an area event (see `crate::areas`) of the
+// ability's `radius` and `effect` at the target, named after the ability,
+// once its `cast_seconds` are up (see `crate::actions`).
+use crate::areas::{AreaConfig, AreaEffect};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// Longest ability name accepted, in bytes, like an area event's kind
+const MAX_NAME_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AbilityConfig {
+    // No file means no abilities
+    pub file: Option<PathBuf>,
+    pub max_energy: u32,
+    pub energy_per_second: u32,
+}
+
+impl Default for AbilityConfig {
+    fn default() -> Self {
+        AbilityConfig {
+            file: None,
+            max_energy: 100,
+            energy_per_second: 10,
+        }
+    }
+}
+
+impl AbilityConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.file.is_some() && self.max_energy == 0 {
+            return Err("`abilities.max_energy` must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Ability {
+    pub cooldown_seconds: f32,
+    // How long it takes to go off, during which the player's other actions
+    // wait; the checks are made, and the cooldown starts, when it does
+    pub cast_seconds: f32,
+    // How far from the player the target may be
+    pub range: f32,
+    pub cost: u32,
+    pub radius: f32,
+    pub effect: AreaEffect,
+}
+
+impl Default for Ability {
+    fn default() -> Self {
+        Ability {
+            cooldown_seconds: 1.0,
+            cast_seconds: 0.0,
+            range: 256.0,
+            cost: 0,
+            radius: 64.0,
+            effect: AreaEffect::None,
+        }
+    }
+}
+
+impl Ability {
+    fn validate(&self, config: &AbilityConfig, areas: &AreaConfig) -> Result<(), String> {
+        if !(self.cooldown_seconds.is_finite() && self.cooldown_seconds >= 0.0) {
+            return Err("`cooldown_seconds` must be a number of at least 0".to_string());
+        }
+        if !(self.cast_seconds.is_finite() && self.cast_seconds >= 0.0) {
+            return Err("`cast_seconds` must be a number of at least 0".to_string());
+        }
+        if !(self.range.is_finite() && self.range >= 0.0) {
+            return Err("`range` must be a number of at least 0".to_string());
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0 && self.radius <= areas.max_radius) {
+            return Err(format!(
+                "`radius` must be positive and at most `areas.max_radius` ({})",
+                areas.max_radius
+            ));
+        }
+        if self.cost > config.max_energy {
+            return Err(format!(
+                "`cost` is more than `abilities.max_energy` ({})",
+                config.max_energy
+            ));
+        }
+        if let AreaEffect::Knockback { distance } = self.effect {
+            if !distance.is_finite() {
+                return Err("a knockback needs a finite distance".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+// A player's energy and when each ability they used is ready again
+#[derive(Debug)]
+struct Caster {
+    energy: u32,
+    ready_at: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Used {
+    // Seconds until the ability can be used again
+    pub ready_in: f32,
+    pub energy: u32,
+}
+
+#[derive(Debug)]
+pub struct Abilities {
+    pub config: AbilityConfig,
+    pub abilities: BTreeMap<String, Ability>,
+    casters: HashMap<usize, Caster>,
+}
+
+impl Abilities {
+    pub fn load(config: AbilityConfig, path: &Path, areas: &AreaConfig) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let abilities: BTreeMap<String, Ability> =
+            serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        for (name, ability) in &abilities {
+            if name.is_empty() || name.len() > MAX_NAME_LEN {
+                return Err(format!(
+                    "ability names must be 1 to {} bytes, `{}` isn't",
+                    MAX_NAME_LEN, name
+                ));
+            }
+            ability
+                .validate(&config, areas)
+                .map_err(|e| format!("ability `{}`: {}", name, e))?;
+        }
+        Ok(Abilities {
+            config,
+            abilities,
+            casters: HashMap::new(),
+        })
+    }
+
+    // Check a use of `name` from `from` at `target`, and if it goes, charge
+    // for it and start its cooldown
+    pub fn use_ability(
+        &mut self,
+        player_id: usize,
+        name: &str,
+        from: (f32, f32),
+        target: (f32, f32),
+        tick: u64,
+        snapshot_rate: u32,
+    ) -> Result<(&Ability, Used), String> {
+        let ability = self
+            .abilities
+            .get(name)
+            .ok_or_else(|| format!("there is no ability `{}`", name))?;
+        if !(target.0.is_finite() && target.1.is_finite()) {
+            return Err("an ability needs a finite target".to_string());
+        }
+        if (target.0 - from.0).hypot(target.1 - from.1) > ability.range {
+            return Err(format!("`{}` reaches at most {}", name, ability.range));
+        }
+        let max_energy = self.config.max_energy;
+        let caster = self.casters.entry(player_id).or_insert_with(|| Caster {
+            energy: max_energy,
+            ready_at: HashMap::new(),
+        });
+        let rate = snapshot_rate.max(1) as f32;
+        if let Some(&ready_at) = caster.ready_at.get(name) {
+            if tick < ready_at {
+                return Err(format!(
+                    "`{}` is ready in {:.1} seconds",
+                    name,
+                    (ready_at - tick) as f32 / rate
+                ));
+            }
+        }
+        if caster.energy < ability.cost {
+            return Err(format!(
+                "`{}` costs {} energy and you have {}",
+                name, ability.cost, caster.energy
+            ));
+        }
+        caster.energy -= ability.cost;
+        let cooldown = (ability.cooldown_seconds * rate).ceil() as u64;
+        caster.ready_at.insert(name.to_string(), tick + cooldown);
+        let used = Used {
+            ready_in: cooldown as f32 / rate,
+            energy: caster.energy,
+        };
+        Ok((ability, used))
+    }
+
+    // Once a second; players already at full energy drop out
+    pub fn regenerate(&mut self, tick: u64) {
+        let (max, per_second) = (self.config.max_energy, self.config.energy_per_second);
+        self.casters.retain(|_, caster| {
+            caster.energy = caster.energy.saturating_add(per_second).min(max);
+            caster.ready_at.retain(|_, &mut ready_at| ready_at > tick);
+            caster.energy < max || !caster.ready_at.is_empty()
+        });
+    }
+
+    // Ticks until the player's `name` is off cooldown, 0 when it is
+    pub fn ready_in(&self, player_id: usize, name: &str, tick: u64) -> u64 {
+        self.casters
+            .get(&player_id)
+            .and_then(|caster| caster.ready_at.get(name))
+            .map_or(0, |&ready_at| ready_at.saturating_sub(tick))
+    }
+
+    pub fn energy(&self, player_id: usize) -> u32 {
+        self.casters
+            .get(&player_id)
+            .map_or(self.config.max_energy, |caster| caster.energy)
+    }
+
+    pub fn forget(&mut self, player_id: usize) {
+        self.casters.remove(&player_id);
+    }
+}
@@ -0,0 +1,263 @@
+// Registered accounts. An account outlives any one connection: players log in
+// to it each session and get the same account id back, whatever player id the
+// connection was given. Ids are random, so account files from different
+// servers can be merged without clashes. Each account also carries the
+// player UUID its players go by (see `state::Player::uuid`).
+use crate::analytics::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+pub type AccountId = u64;
+// Who a player is beyond any one connection or process: their account's, or
+// a guest's own for as long as its session lasts
+pub type PlayerUuid = Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AccountConfig {
+    // Whether new accounts can be created with `Register`
+    pub registration: bool,
+    pub min_password_len: usize,
+    pub max_password_len: usize,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        AccountConfig {
+            registration: true,
+            min_password_len: 8,
+            max_password_len: 128,
+        }
+    }
+}
+
+impl AccountConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_password_len == 0 || self.min_password_len > self.max_password_len {
+            return Err(
+                "`accounts.min_password_len` must be between 1 and `accounts.max_password_len`"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> Result<(), String> {
+        let len = password.chars().count();
+        if len < self.min_password_len || len > self.max_password_len {
+            return Err(format!(
+                "passwords must be {}-{} characters",
+                self.min_password_len, self.max_password_len
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub id: AccountId,
+    // As registered; lookups ignore case
+    pub username: String,
+    // Argon2 PHC string
+    pub password_hash: String,
+    // Unix timestamps
+    pub created: u64,
+    pub last_login: u64,
+    // Nil until `assign_uuids` runs, for accounts made before there were any
+    #[serde(default)]
+    pub uuid: PlayerUuid,
+}
+
+// Every account: those with passwords by lowercased username, those from the
+// identity provider by token subject, and Steam players' by SteamID
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Accounts {
+    pub accounts: BTreeMap<String, Account>,
+    pub external: BTreeMap<String, Account>,
+    pub steam: BTreeMap<u64, Account>,
+}
+
+impl Accounts {
+    pub const STORAGE_KEY: &'static str = "accounts";
+
+    pub fn find(&self, username: &str) -> Option<&Account> {
+        self.accounts.get(&username.to_ascii_lowercase())
+    }
+
+    // An account of any kind
+    pub fn by_id(&self, id: AccountId) -> Option<&Account> {
+        self.accounts
+            .values()
+            .chain(self.external.values())
+            .chain(self.steam.values())
+            .find(|a| a.id == id)
+    }
+
+    // `None` if the username is taken
+    pub fn create(&mut self, username: &str, password_hash: String) -> Option<&Account> {
+        let key = username.to_ascii_lowercase();
+        if self.accounts.contains_key(&key) {
+            return None;
+        }
+        let account = self.new_account(username, password_hash);
+        Some(self.accounts.entry(key).or_insert(account))
+    }
+
+    // The account for a token subject, created on its first login. It has no
+    // password and `find` doesn't see it, so only a token reaches it.
+    pub fn external_login(&mut self, subject: &str) -> &Account {
+        if !self.external.contains_key(subject) {
+            let account = self.new_account(subject, String::new());
+            self.external.insert(subject.to_string(), account);
+        }
+        let account = self.external.get_mut(subject).unwrap();
+        account.last_login = unix_now();
+        account
+    }
+
+    // Likewise for a SteamID, named after it
+    pub fn steam_login(&mut self, steam_id: u64) -> &Account {
+        if !self.steam.contains_key(&steam_id) {
+            let account = self.new_account(&steam_id.to_string(), String::new());
+            self.steam.insert(steam_id, account);
+        }
+        let account = self.steam.get_mut(&steam_id).unwrap();
+        account.last_login = unix_now();
+        account
+    }
+
+    fn new_account(&self, username: &str, password_hash: String) -> Account {
+        let id = loop {
+            let id = rand::random::<AccountId>();
+            if id != 0 && self.by_id(id).is_none() {
+                break id;
+            }
+        };
+        let now = unix_now();
+        Account {
+            id,
+            username: username.to_string(),
+            password_hash,
+            created: now,
+            last_login: now,
+            uuid: PlayerUuid::new_v4(),
+        }
+    }
+
+    // Delete an account of any kind, returning it
+    pub fn remove(&mut self, id: AccountId) -> Option<Account> {
+        if let Some(key) = self
+            .accounts
+            .iter()
+            .find(|(_, a)| a.id == id)
+            .map(|(k, _)| k.clone())
+        {
+            return self.accounts.remove(&key);
+        }
+        if let Some(key) = self
+            .external
+            .iter()
+            .find(|(_, a)| a.id == id)
+            .map(|(k, _)| k.clone())
+        {
+            return self.external.remove(&key);
+        }
+        let key = self
+            .steam
+            .iter()
+            .find(|(_, a)| a.id == id)
+            .map(|(&k, _)| k)?;
+        self.steam.remove(&key)
+    }
+
+    // Once loaded: give accounts from before player UUIDs one. Returns whether
+    // any needed one, in which case the accounts should be saved right away
+    // so the UUIDs they got stick.
+    pub fn assign_uuids(&mut self) -> bool {
+        let mut assigned = false;
+        let all = self
+            .accounts
+            .values_mut()
+            .chain(self.external.values_mut())
+            .chain(self.steam.values_mut());
+        for account in all.filter(|account| account.uuid.is_nil()) {
+            account.uuid = PlayerUuid::new_v4();
+            assigned = true;
+        }
+        assigned
+    }
+
+    pub fn logged_in(&mut self, username: &str) {
+        if let Some(account) = self.accounts.get_mut(&username.to_ascii_lowercase()) {
+            account.last_login = unix_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames_are_unique_whatever_their_case() {
+        let mut accounts = Accounts::default();
+        let id = accounts.create("Alice", "hash".to_string()).unwrap().id;
+        assert!(accounts.create("alice", "other".to_string()).is_none());
+        let found = accounts.find("ALICE").unwrap();
+        assert_eq!((found.id, found.username.as_str()), (id, "Alice"));
+        assert_eq!(found.password_hash, "hash");
+        assert!(accounts.find("bob").is_none());
+    }
+
+    #[test]
+    fn ids_reach_every_kind_of_account() {
+        let mut accounts = Accounts::default();
+        let local = accounts.create("alice", String::new()).unwrap().id;
+        let external = accounts.external_login("subject").id;
+        let steam = accounts.steam_login(76561197960287930).id;
+        assert_ne!(local, 0);
+        assert_eq!(accounts.external_login("subject").id, external);
+        assert_eq!(accounts.steam_login(76561197960287930).id, steam);
+        // Only a token reaches an external account
+        assert!(accounts.find("subject").is_none());
+        for id in [local, external, steam] {
+            assert_eq!(accounts.by_id(id).map(|a| a.id), Some(id));
+            assert_eq!(accounts.remove(id).map(|a| a.id), Some(id));
+            assert!(accounts.by_id(id).is_none());
+        }
+        assert!(accounts.remove(local).is_none());
+    }
+
+    #[test]
+    fn passwords_are_held_to_the_configured_length() {
+        let config = AccountConfig {
+            min_password_len: 3,
+            max_password_len: 5,
+            ..AccountConfig::default()
+        };
+        assert!(config.check_password("ab").is_err());
+        assert!(config.check_password("abc").is_ok());
+        // Characters count, not bytes
+        assert!(config.check_password("ééééé").is_ok());
+        assert!(config.check_password("abcdef").is_err());
+        assert!(AccountConfig::default().validate().is_ok());
+        let inverted = AccountConfig {
+            min_password_len: 6,
+            ..config
+        };
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn accounts_without_uuids_get_one() {
+        let mut accounts = Accounts::default();
+        accounts.create("alice", String::new());
+        assert!(!accounts.assign_uuids());
+        accounts.accounts.get_mut("alice").unwrap().uuid = PlayerUuid::nil();
+        assert!(accounts.assign_uuids());
+        assert!(!accounts.find("alice").unwrap().uuid.is_nil());
+    }
+}
@@ -0,0 +1,141 @@
+// Achievements: counters of gameplay events per account, and the goals that
+// unlock once a counter reaches them. Definitions come from the config and from
+// JSON data files; progress is persisted. Guests earn nothing.
+use crate::accounts::AccountId;
+use crate::analytics::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AchievementConfig {
+    pub definitions: Vec<Achievement>,
+    // JSON files holding a list of definitions each
+    pub files: Vec<PathBuf>,
+    // What `load` read from `files`
+    #[serde(skip)]
+    loaded: Vec<Achievement>,
+}
+
+impl AchievementConfig {
+    // Read `files` again, replacing whatever they held before
+    pub fn load(&mut self) -> Result<(), String> {
+        let mut loaded = Vec::new();
+        for path in &self.files {
+            let data =
+                fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            let definitions: Vec<Achievement> = serde_json::from_slice(&data)
+                .map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+            loaded.extend(definitions);
+        }
+        self.loaded = loaded;
+        Ok(())
+    }
+
+    // Definitions from the config and from the files last loaded
+    pub fn all(&self) -> impl Iterator<Item = &Achievement> {
+        self.definitions.iter().chain(&self.loaded)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut ids = BTreeSet::new();
+        for achievement in self.all() {
+            if achievement.id.is_empty() || achievement.name.is_empty() {
+                return Err("achievements need an `id` and a `name`".to_string());
+            }
+            if achievement.goal == 0 {
+                return Err(format!(
+                    "achievement `{}` needs a positive `goal`",
+                    achievement.id
+                ));
+            }
+            if !ids.insert(&achievement.id) {
+                return Err(format!("achievement `{}` is defined twice", achievement.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+// What achievements count
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum GameEvent {
+    Login,
+    ChatMessage,
+    Whisper,
+    RoomCreated,
+    RoomJoined,
+    FriendMade,
+    PartyJoined,
+    // Founding a guild counts too
+    GuildJoined,
+    FlagCaptured,
+    // Counted for every member of the winning team in the room
+    RoundWon,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Achievement {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub event: GameEvent,
+    // How many times `event` has to happen
+    #[serde(default = "default_goal")]
+    pub goal: u64,
+}
+
+fn default_goal() -> u64 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AchievementProgress {
+    pub counts: BTreeMap<AccountId, BTreeMap<GameEvent, u64>>,
+    // Achievement ids by account, with when they were unlocked in unix seconds
+    pub unlocked: BTreeMap<AccountId, BTreeMap<String, u64>>,
+}
+
+impl AchievementProgress {
+    pub const STORAGE_KEY: &'static str = "achievements";
+
+    // Count one `event` for `account`, returning the achievements that it
+    // just unlocked
+    pub fn record<'a>(
+        &mut self,
+        account: AccountId,
+        event: GameEvent,
+        definitions: impl Iterator<Item = &'a Achievement>,
+    ) -> Vec<&'a Achievement> {
+        let count = self
+            .counts
+            .entry(account)
+            .or_default()
+            .entry(event)
+            .or_default();
+        *count += 1;
+        let count = *count;
+        let unlocked = self.unlocked.entry(account).or_default();
+        let now = unix_now();
+        let newly: Vec<&Achievement> = definitions
+            .filter(|a| a.event == event && count >= a.goal && !unlocked.contains_key(&a.id))
+            .collect();
+        for achievement in &newly {
+            unlocked.insert(achievement.id.clone(), now);
+        }
+        if unlocked.is_empty() {
+            self.unlocked.remove(&account);
+        }
+        newly
+    }
+
+    pub fn forget(&mut self, account: AccountId) {
+        self.counts.remove(&account);
+        self.unlocked.remove(&account);
+    }
+}
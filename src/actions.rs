@@ -0,0 +1,129 @@
+// Each player's queue of gameplay actions: abilities they use and entities
+// they interact with. A request is checked for what can't change when it
+// arrives, then queued, and the simulation runs the queue in order, so
+// actions go off on the tick rather than whenever a message happens to be
+// read. An ability still on cooldown holds the queue until it is ready, or
+// is dropped after `max_wait_seconds`; one with a cast time (see
+// `crate::abilities`) holds it while it casts.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ActionConfig {
+    // Actions a player can have waiting, besides one casting
+    pub max_queued: usize,
+    // How long an action waits on a cooldown before it is dropped
+    pub max_wait_seconds: f32,
+}
+
+impl Default for ActionConfig {
+    fn default() -> Self {
+        ActionConfig {
+            max_queued: 4,
+            max_wait_seconds: 1.0,
+        }
+    }
+}
+
+impl ActionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_queued == 0 {
+            return Err("`actions.max_queued` must be at least 1".to_string());
+        }
+        if !(self.max_wait_seconds.is_finite() && self.max_wait_seconds >= 0.0) {
+            return Err("`actions.max_wait_seconds` must be a number of at least 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Ability { name: String, x: f32, y: f32 },
+    Interact { id: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Queued {
+    pub action: Action,
+    // The simulation tick it was queued on
+    pub queued_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct PlayerActions {
+    pending: VecDeque<Queued>,
+    // What is casting and the tick it goes off on
+    casting: Option<(Action, u64)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ActionQueues {
+    players: HashMap<usize, PlayerActions>,
+}
+
+impl ActionQueues {
+    pub fn push(
+        &mut self,
+        player_id: usize,
+        action: Action,
+        tick: u64,
+        max_queued: usize,
+    ) -> Result<(), String> {
+        let actions = self.players.entry(player_id).or_default();
+        if actions.pending.len() >= max_queued {
+            return Err(format!("at most {} actions can wait", max_queued));
+        }
+        actions.pending.push_back(Queued {
+            action,
+            queued_at: tick,
+        });
+        Ok(())
+    }
+
+    // Players with anything queued or casting
+    pub fn players(&self) -> Vec<usize> {
+        self.players.keys().copied().collect()
+    }
+
+    pub fn front(&self, player_id: usize) -> Option<&Queued> {
+        self.players.get(&player_id)?.pending.front()
+    }
+
+    pub fn pop(&mut self, player_id: usize) -> Option<Queued> {
+        self.players.get_mut(&player_id)?.pending.pop_front()
+    }
+
+    pub fn is_casting(&self, player_id: usize) -> bool {
+        self.players
+            .get(&player_id)
+            .is_some_and(|actions| actions.casting.is_some())
+    }
+
+    pub fn cast(&mut self, player_id: usize, action: Action, goes_off: u64) {
+        self.players.entry(player_id).or_default().casting = Some((action, goes_off));
+    }
+
+    // The cast that goes off by `tick`, if there is one
+    pub fn finish_cast(&mut self, player_id: usize, tick: u64) -> Option<Action> {
+        let actions = self.players.get_mut(&player_id)?;
+        match actions.casting.take()? {
+            (action, goes_off) if goes_off <= tick => Some(action),
+            casting => {
+                actions.casting = Some(casting);
+                None
+            }
+        }
+    }
+
+    // Players left with nothing to do drop out
+    pub fn prune(&mut self) {
+        self.players
+            .retain(|_, actions| !actions.pending.is_empty() || actions.casting.is_some());
+    }
+
+    pub fn forget(&mut self, player_id: usize) {
+        self.players.remove(&player_id);
+    }
+}
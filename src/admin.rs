@@ -0,0 +1,527 @@
+use crate::areas::{AreaEffect, AreaEvent};
+use crate::chat::ChannelKey;
+use crate::cidr::IpRange;
+use crate::difficulty::DifficultyChange;
+use crate::entities::Entity;
+use crate::protocol::LocalizedText;
+use crate::roles::Role;
+use crate::rooms::RoomId;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::mpsc;
+
+pub const HELP: &str = "\
+commands:
+  status                              show server modes, player count and buffer pool use
+  maintenance on [drain] [message]    reject new joins, optionally dropping current players
+  maintenance off
+  whitelist on|off                    only let whitelisted identities join
+  whitelist add|remove <identity>
+  whitelist list
+  allow add|remove <range>            only let these addresses connect, e.g. `allow add 10.0.0.0/8`
+  allow list
+  deny add|remove <range>             refuse connections from an address or CIDR range
+  deny list
+  kick <player> [message]             disconnect a player
+  ban <player> [message]              disconnect a player and keep their address out
+  unban <identity>
+  broadcast <message>                 show a message to every player
+  announce <key> [name=value...]      show every player a message from the translation files
+  reload                              re-read the config file
+  drain [seconds]                     let matches finish, then exit for a restart
+  drain cancel
+  pause                               freeze the simulation: modes, round clocks and moves
+  resume
+  timescale <factor>                  run the simulation slower or faster, e.g. `timescale 0.5`
+  stats [player]                      retention totals, or one connected player's playtime
+  inspect [pointer]                   dump the live state as JSON, e.g. `inspect /players/3`
+  grant <player> <role>               give a connected player moderator, admin or owner rights
+  revoke <player>                     take a player's role away
+  mail <account> <message>            leave a notice in an account's mailbox
+  give <account> <item> [quantity]    send an account an item grant by mail
+  inventory <account>                 list what an account holds
+  export <account>                    dump everything stored about an account as JSON
+  erase <account>                     irreversibly delete an account and its data
+  history <channel> [count]           recent chat, e.g. `history room:3` or `history party:2 50`
+  trail <player> [seconds]            where a player has been and what they did lately
+  area <room> <x> <y> <radius> [kind] [knockback]
+                                      set off an area event in a room or `lobby`, e.g. `area 3 0 0 100 explosion 50`
+  spawn <room> <kind> <x> <y> [key=value...]
+                                      add an entity to a room or `lobby`, e.g. `spawn 3 crate 10 20 loot=ammo`
+  despawn <entity>                    remove a spawned entity
+  entities <room>                     list the entities in a room or `lobby`
+  authority <entity> <player>|server  let a player in its room simulate an entity, or take it back
+  loot <room> <table> <x> <y>         roll a loot table at a spot in a room or `lobby`
+  bot add <room> [path]               add a bot to a room, walking one of `bots.paths` or roaming
+  bot remove <player>|all
+  bot list
+  difficulty <room> [name=value...]   show or turn a room's NPC multipliers, e.g. `difficulty 3 health=2 spawn_rate=1.5`
+  matches [count]                     recent rounds and scenarios, with the difficulty they were played at
+  traffic [player]                    what each connection sent and was sent, or one player's by message type
+  dice [count]                        the dice seed and the latest draws, 20 unless given
+  help";
+
+// Operator commands, from the console or any other admin interface
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Help,
+    Status,
+    Maintenance {
+        enabled: bool,
+        drain: bool,
+        message: Option<String>,
+    },
+    WhitelistOnly(bool),
+    WhitelistAdd(String),
+    WhitelistRemove(String),
+    WhitelistList,
+    AllowAdd(IpRange),
+    AllowRemove(IpRange),
+    AllowList,
+    DenyAdd(IpRange),
+    DenyRemove(IpRange),
+    DenyList,
+    Kick {
+        player_id: usize,
+        message: Option<String>,
+    },
+    Grant {
+        player_id: usize,
+        role: Role,
+    },
+    Revoke {
+        player_id: usize,
+    },
+    Ban {
+        player_id: usize,
+        message: Option<String>,
+    },
+    Unban(String),
+    Broadcast(String),
+    // An announcement clients translate themselves
+    Announce(LocalizedText),
+    Reload,
+    // Wait at most this many seconds, or `drain.deadline_secs`
+    Drain(Option<u64>),
+    DrainCancel,
+    Pause,
+    Resume,
+    TimeScale(f32),
+    // A JSON pointer into the dump; `None` shows everything
+    Inspect(Option<String>),
+    Stats(Option<usize>),
+    // Accounts are named by id or username
+    Mail {
+        account: String,
+        message: String,
+    },
+    Give {
+        account: String,
+        item: String,
+        quantity: u32,
+    },
+    History {
+        channel: ChannelKey,
+        count: usize,
+    },
+    Inventory(String),
+    Export(String),
+    Erase(String),
+    // The last this many seconds, or all that is kept
+    Trail {
+        player_id: usize,
+        seconds: Option<u64>,
+    },
+    Area(AreaEvent),
+    Spawn {
+        room: Option<RoomId>,
+        entity: Entity,
+    },
+    Despawn(u64),
+    Entities(Option<RoomId>),
+    // `None` is the server
+    Authority {
+        entity: u64,
+        to: Option<usize>,
+    },
+    Loot {
+        room: Option<RoomId>,
+        table: String,
+        x: f32,
+        y: f32,
+    },
+    AddBot {
+        room_id: RoomId,
+        path: Option<String>,
+    },
+    // `None` is every bot
+    RemoveBot(Option<usize>),
+    Bots,
+    // An empty change shows the room's difficulty
+    Difficulty {
+        room_id: RoomId,
+        change: DifficultyChange,
+    },
+    Matches(usize),
+    // `None` lists every player
+    Traffic(Option<usize>),
+    // The seed and the latest this many draws
+    Dice(usize),
+}
+
+impl AdminCommand {
+    // The least privileged role allowed to run this command
+    pub fn required_role(&self) -> Role {
+        match self {
+            AdminCommand::Help
+            | AdminCommand::Status
+            | AdminCommand::WhitelistList
+            | AdminCommand::AllowList
+            | AdminCommand::DenyList
+            | AdminCommand::Kick { .. }
+            | AdminCommand::Broadcast(_)
+            | AdminCommand::Announce(_)
+            | AdminCommand::Inspect(_)
+            | AdminCommand::Stats(_)
+            | AdminCommand::Mail { .. }
+            | AdminCommand::History { .. }
+            | AdminCommand::Trail { .. }
+            | AdminCommand::Entities(_)
+            | AdminCommand::Bots
+            | AdminCommand::Matches(_)
+            | AdminCommand::Traffic(_)
+            | AdminCommand::Dice(_)
+            | AdminCommand::Inventory(_) => Role::Moderator,
+            AdminCommand::Maintenance { .. }
+            | AdminCommand::WhitelistOnly(_)
+            | AdminCommand::WhitelistAdd(_)
+            | AdminCommand::WhitelistRemove(_)
+            | AdminCommand::AllowAdd(_)
+            | AdminCommand::AllowRemove(_)
+            | AdminCommand::DenyAdd(_)
+            | AdminCommand::DenyRemove(_)
+            | AdminCommand::Ban { .. }
+            | AdminCommand::Unban(_)
+            | AdminCommand::Reload
+            | AdminCommand::Drain(_)
+            | AdminCommand::DrainCancel
+            | AdminCommand::Pause
+            | AdminCommand::Resume
+            | AdminCommand::TimeScale(_)
+            | AdminCommand::Give { .. }
+            | AdminCommand::Export(_)
+            | AdminCommand::Area(_)
+            | AdminCommand::Spawn { .. }
+            | AdminCommand::Despawn(_)
+            | AdminCommand::Authority { .. }
+            | AdminCommand::Loot { .. }
+            | AdminCommand::AddBot { .. }
+            | AdminCommand::RemoveBot(_)
+            | AdminCommand::Difficulty { .. } => Role::Admin,
+            AdminCommand::Grant { .. } | AdminCommand::Revoke { .. } | AdminCommand::Erase(_) => {
+                Role::Owner
+            }
+        }
+    }
+}
+
+// What a command printed, or why it failed
+pub type AdminResult = Result<String, String>;
+
+// A command, who issued it, and where to send its result
+pub struct AdminRequest {
+    pub command: AdminCommand,
+    // The console runs as `Role::Owner`; players run with their granted role
+    pub role: Role,
+    pub reply: mpsc::Sender<AdminResult>,
+}
+
+fn parse_toggle(word: Option<&str>) -> Result<bool, String> {
+    match word {
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        _ => Err("expected `on` or `off`".to_string()),
+    }
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some("help") => AdminCommand::Help,
+            Some("status") => AdminCommand::Status,
+            Some("maintenance") => {
+                let enabled = parse_toggle(words.next())?;
+                let mut rest: Vec<&str> = words.collect();
+                let drain = enabled && rest.first() == Some(&"drain");
+                if drain {
+                    rest.remove(0);
+                }
+                let message = (enabled && !rest.is_empty()).then(|| rest.join(" "));
+                AdminCommand::Maintenance {
+                    enabled,
+                    drain,
+                    message,
+                }
+            }
+            Some("whitelist") => match words.next() {
+                Some(word @ ("on" | "off")) => AdminCommand::WhitelistOnly(word == "on"),
+                Some("add") => AdminCommand::WhitelistAdd(identity(words.next())?),
+                Some("remove") => AdminCommand::WhitelistRemove(identity(words.next())?),
+                Some("list") => AdminCommand::WhitelistList,
+                _ => return Err("usage: whitelist on|off|add|remove|list".to_string()),
+            },
+            Some("allow") => match words.next() {
+                Some("add") => AdminCommand::AllowAdd(ip_range(words.next())?),
+                Some("remove") => AdminCommand::AllowRemove(ip_range(words.next())?),
+                Some("list") => AdminCommand::AllowList,
+                _ => return Err("usage: allow add|remove|list".to_string()),
+            },
+            Some("deny") => match words.next() {
+                Some("add") => AdminCommand::DenyAdd(ip_range(words.next())?),
+                Some("remove") => AdminCommand::DenyRemove(ip_range(words.next())?),
+                Some("list") => AdminCommand::DenyList,
+                _ => return Err("usage: deny add|remove|list".to_string()),
+            },
+            Some("kick") => AdminCommand::Kick {
+                player_id: player_id(words.next())?,
+                message: rest(words),
+            },
+            Some("ban") => AdminCommand::Ban {
+                player_id: player_id(words.next())?,
+                message: rest(words),
+            },
+            Some("unban") => AdminCommand::Unban(identity(words.next())?),
+            Some("broadcast") => AdminCommand::Broadcast(rest(words).ok_or("missing message")?),
+            Some("announce") => {
+                let mut text = LocalizedText::new(words.next().ok_or("missing message key")?);
+                for word in words {
+                    let (name, value) = word
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected name=value, got `{}`", word))?;
+                    text = text.with(name, value);
+                }
+                AdminCommand::Announce(text)
+            }
+            Some("reload") => AdminCommand::Reload,
+            Some("drain") => match words.next() {
+                Some("cancel") => AdminCommand::DrainCancel,
+                Some(word) => AdminCommand::Drain(Some(
+                    word.parse().map_err(|_| "expected a number of seconds")?,
+                )),
+                None => AdminCommand::Drain(None),
+            },
+            Some("pause") => AdminCommand::Pause,
+            Some("resume") => AdminCommand::Resume,
+            Some("timescale") => AdminCommand::TimeScale(
+                words
+                    .next()
+                    .ok_or("missing factor")?
+                    .parse()
+                    .map_err(|_| "expected a factor, like 0.5")?,
+            ),
+            Some("stats") => match words.next() {
+                Some(word) => AdminCommand::Stats(Some(player_id(Some(word))?)),
+                None => AdminCommand::Stats(None),
+            },
+            Some("inspect") => AdminCommand::Inspect(words.next().map(str::to_string)),
+            Some("grant") => AdminCommand::Grant {
+                player_id: player_id(words.next())?,
+                role: words.next().ok_or("missing role")?.parse()?,
+            },
+            Some("revoke") => AdminCommand::Revoke {
+                player_id: player_id(words.next())?,
+            },
+            Some("mail") => AdminCommand::Mail {
+                account: account(words.next())?,
+                message: rest(words).ok_or("missing message")?,
+            },
+            Some("give") => AdminCommand::Give {
+                account: account(words.next())?,
+                item: words.next().ok_or("missing item")?.to_string(),
+                quantity: match words.next() {
+                    Some(word) => word
+                        .parse()
+                        .ok()
+                        .filter(|&quantity| quantity > 0)
+                        .ok_or("expected a positive quantity")?,
+                    None => 1,
+                },
+            },
+            Some("inventory") => AdminCommand::Inventory(account(words.next())?),
+            Some("export") => AdminCommand::Export(account(words.next())?),
+            Some("erase") => AdminCommand::Erase(account(words.next())?),
+            Some("history") => AdminCommand::History {
+                channel: words.next().ok_or("missing channel")?.parse()?,
+                count: match words.next() {
+                    Some(word) => word.parse().map_err(|_| "expected a line count")?,
+                    None => 20,
+                },
+            },
+            Some("trail") => AdminCommand::Trail {
+                player_id: player_id(words.next())?,
+                seconds: match words.next() {
+                    Some(word) => Some(word.parse().map_err(|_| "expected a number of seconds")?),
+                    None => None,
+                },
+            },
+            Some("area") => {
+                let room = room(words.next())?;
+                let mut number = |what: &str| -> Result<f32, String> {
+                    let word = words.next().ok_or_else(|| format!("missing {}", what))?;
+                    word.parse()
+                        .map_err(|_| format!("expected a number for {}", what))
+                };
+                let (x, y, radius) = (number("x")?, number("y")?, number("radius")?);
+                let kind = words.next().unwrap_or("explosion").to_string();
+                let effect = match words.next() {
+                    Some(word) => AreaEffect::Knockback {
+                        distance: word.parse().map_err(|_| "expected a knockback distance")?,
+                    },
+                    None => AreaEffect::None,
+                };
+                AdminCommand::Area(AreaEvent {
+                    room,
+                    kind,
+                    x,
+                    y,
+                    radius,
+                    effect,
+                })
+            }
+            Some("spawn") => {
+                let room = room(words.next())?;
+                let kind = words.next().ok_or("missing kind")?.to_string();
+                let mut number = |what: &str| -> Result<f32, String> {
+                    let word = words.next().ok_or_else(|| format!("missing {}", what))?;
+                    word.parse()
+                        .map_err(|_| format!("expected a number for {}", what))
+                };
+                let (x, y) = (number("x")?, number("y")?);
+                let mut metadata = BTreeMap::new();
+                for word in words {
+                    let (key, value) = word
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected key=value, got `{}`", word))?;
+                    metadata.insert(key.to_string(), value.to_string());
+                }
+                AdminCommand::Spawn {
+                    room,
+                    entity: Entity {
+                        kind,
+                        x,
+                        y,
+                        metadata,
+                    },
+                }
+            }
+            Some("despawn") => AdminCommand::Despawn(entity_id(words.next())?),
+            Some("entities") => AdminCommand::Entities(room(words.next())?),
+            Some("loot") => {
+                let room = room(words.next())?;
+                let table = words.next().ok_or("missing table")?.to_string();
+                let mut number = |what: &str| -> Result<f32, String> {
+                    let word = words.next().ok_or_else(|| format!("missing {}", what))?;
+                    word.parse()
+                        .map_err(|_| format!("expected a number for {}", what))
+                };
+                let (x, y) = (number("x")?, number("y")?);
+                AdminCommand::Loot { room, table, x, y }
+            }
+            Some("authority") => AdminCommand::Authority {
+                entity: entity_id(words.next())?,
+                to: match words.next() {
+                    Some("server") => None,
+                    word => Some(player_id(word)?),
+                },
+            },
+            Some("bot") => match words.next() {
+                Some("add") => AdminCommand::AddBot {
+                    room_id: match room(words.next())? {
+                        Some(room_id) => room_id,
+                        None => return Err("bots can only be added to a room".to_string()),
+                    },
+                    path: words.next().map(str::to_string),
+                },
+                Some("remove") => AdminCommand::RemoveBot(match words.next() {
+                    Some("all") => None,
+                    word => Some(player_id(word)?),
+                }),
+                Some("list") => AdminCommand::Bots,
+                _ => return Err("usage: bot add|remove|list".to_string()),
+            },
+            Some("difficulty") => {
+                let Some(room_id) = room(words.next())? else {
+                    return Err("the lobby's difficulty can't be changed".to_string());
+                };
+                let mut change = DifficultyChange::default();
+                for word in words {
+                    let (name, value) = word
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected name=value, got `{}`", word))?;
+                    let value = value
+                        .parse()
+                        .map_err(|_| format!("expected a number for {}", name))?;
+                    change.set(name, value)?;
+                }
+                AdminCommand::Difficulty { room_id, change }
+            }
+            Some("matches") => AdminCommand::Matches(match words.next() {
+                Some(word) => word.parse().map_err(|_| "expected a match count")?,
+                None => 20,
+            }),
+            Some("traffic") => match words.next() {
+                Some(word) => AdminCommand::Traffic(Some(player_id(Some(word))?)),
+                None => AdminCommand::Traffic(None),
+            },
+            Some("dice") => AdminCommand::Dice(match words.next() {
+                Some(word) => word.parse().map_err(|_| "expected a draw count")?,
+                None => 20,
+            }),
+            Some(other) => return Err(format!("unknown command `{}` (try `help`)", other)),
+            None => return Err("empty command".to_string()),
+        };
+        Ok(command)
+    }
+}
+
+fn identity(word: Option<&str>) -> Result<String, String> {
+    word.map(str::to_string)
+        .ok_or_else(|| "missing identity".to_string())
+}
+
+fn account(word: Option<&str>) -> Result<String, String> {
+    word.map(str::to_string)
+        .ok_or_else(|| "missing account".to_string())
+}
+
+fn room(word: Option<&str>) -> Result<Option<RoomId>, String> {
+    match word.ok_or("missing room")? {
+        "lobby" => Ok(None),
+        word => Ok(Some(
+            word.parse().map_err(|_| "expected a room id or `lobby`")?,
+        )),
+    }
+}
+
+fn entity_id(word: Option<&str>) -> Result<u64, String> {
+    word.and_then(|w| w.parse().ok())
+        .ok_or_else(|| "expected an entity id".to_string())
+}
+
+fn ip_range(word: Option<&str>) -> Result<IpRange, String> {
+    word.ok_or("missing address or range")?.parse()
+}
+
+// The remaining words joined back together, if there are any
+fn rest<'a>(words: impl Iterator<Item = &'a str>) -> Option<String> {
+    let words: Vec<&str> = words.collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+fn player_id(word: Option<&str>) -> Result<usize, String> {
+    word.and_then(|w| w.parse().ok())
+        .ok_or_else(|| "expected a player id".to_string())
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Lifetime totals for one identity (see `Player::identities`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlayerStats {
+    pub sessions: u64,
+    // Only finished sessions; add the live one for a connected player
+    pub playtime_secs: u64,
+    pub messages_sent: u64,
+    // Unix timestamps
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+// Per-identity session history, persisted across restarts
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Analytics {
+    pub players: BTreeMap<String, PlayerStats>,
+}
+
+impl Analytics {
+    pub const STORAGE_KEY: &'static str = "analytics";
+
+    pub fn session_started(&mut self, identity: &str) {
+        let now = unix_now();
+        let stats = self.players.entry(identity.to_string()).or_default();
+        if stats.sessions == 0 {
+            stats.first_seen = now;
+        }
+        stats.sessions += 1;
+        stats.last_seen = now;
+    }
+
+    pub fn session_ended(&mut self, identity: &str, playtime: Duration, messages_sent: u64) {
+        let stats = self.players.entry(identity.to_string()).or_default();
+        stats.playtime_secs += playtime.as_secs();
+        stats.messages_sent += messages_sent;
+        stats.last_seen = unix_now();
+    }
+
+    pub fn summary(&self) -> String {
+        let returning = self.players.values().filter(|s| s.sessions > 1).count();
+        let sessions: u64 = self.players.values().map(|s| s.sessions).sum();
+        let playtime: u64 = self.players.values().map(|s| s.playtime_secs).sum();
+        format!(
+            "unique players: {}, returning: {}, sessions: {}, total playtime: {}h {}m",
+            self.players.len(),
+            returning,
+            sessions,
+            playtime / 3600,
+            playtime / 60 % 60
+        )
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
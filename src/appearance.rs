@@ -0,0 +1,62 @@
+// The avatar catalog players pick their appearance from. The server only
+// knows how many skins and accessories exist; clients map the indexes to art.
+use crate::protocol::Appearance;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AppearanceConfig {
+    // Valid skins are 0 up to but not including `skins`; likewise accessories
+    pub skins: u32,
+    pub accessories: u32,
+    // Accessories one avatar may wear at once
+    pub max_accessories: usize,
+}
+
+impl Default for AppearanceConfig {
+    fn default() -> Self {
+        AppearanceConfig {
+            skins: 8,
+            accessories: 32,
+            max_accessories: 4,
+        }
+    }
+}
+
+impl AppearanceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.skins == 0 {
+            return Err("`appearance.skins` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+
+    // Why `appearance` isn't in the catalog, if it isn't
+    pub fn check(&self, appearance: &Appearance) -> Result<(), String> {
+        if appearance.skin >= self.skins {
+            return Err(format!(
+                "skin {} does not exist (0-{})",
+                appearance.skin,
+                self.skins - 1
+            ));
+        }
+        if appearance.color > 0xffffff {
+            return Err(format!("{:#x} is not an 0xRRGGBB color", appearance.color));
+        }
+        if appearance.accessories.len() > self.max_accessories {
+            return Err(format!(
+                "at most {} accessories can be worn at once",
+                self.max_accessories
+            ));
+        }
+        for (i, &accessory) in appearance.accessories.iter().enumerate() {
+            if accessory >= self.accessories {
+                return Err(format!("accessory {} does not exist", accessory));
+            }
+            if appearance.accessories[..i].contains(&accessory) {
+                return Err(format!("accessory {} is listed twice", accessory));
+            }
+        }
+        Ok(())
+    }
+}
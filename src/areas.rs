@@ -0,0 +1,112 @@
+// Events over an area rather than one player, such as an explosion at (x, y)
+// catching everyone within `radius`. The game loop finds who is caught with
+// the spatial index, applies the event's effect to them, and only tells the
+// players close enough to see it: those within `areas.view_distance` of its
+// edge. Games raise them through `ServerHandle::area_event`; admins with
+// `area`.
+use crate::rooms::RoomId;
+use serde::{Deserialize, Serialize};
+
+// Longest `kind` accepted, in bytes
+const MAX_KIND_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AreaConfig {
+    // How far past its radius, in world units, an event can be seen
+    pub view_distance: f32,
+    pub max_radius: f32,
+}
+
+impl Default for AreaConfig {
+    fn default() -> Self {
+        AreaConfig {
+            view_distance: 512.0,
+            max_radius: 2048.0,
+        }
+    }
+}
+
+impl AreaConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.view_distance.is_finite() && self.view_distance >= 0.0) {
+            return Err("`areas.view_distance` must be a number of at least 0".to_string());
+        }
+        if !(self.max_radius.is_finite() && self.max_radius > 0.0) {
+            return Err("`areas.max_radius` must be a positive number".to_string());
+        }
+        Ok(())
+    }
+}
+
+// What happens to the players an event catches
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum AreaEffect {
+    // Clients are told who was caught and do the rest
+    #[default]
+    None,
+    // Push each player `distance` straight away from the center
+    Knockback {
+        distance: f32,
+    },
+}
+
+impl AreaEffect {
+    // Where the effect puts a player standing at `position`, if it moves them
+    pub fn apply(&self, center: (f32, f32), position: (f32, f32)) -> Option<(f32, f32)> {
+        match *self {
+            AreaEffect::None => None,
+            AreaEffect::Knockback { distance } => {
+                let (dx, dy) = (position.0 - center.0, position.1 - center.1);
+                let length = dx.hypot(dy);
+                // Someone at the very center is pushed along the x axis
+                let (dx, dy) = if length > 0.0 {
+                    (dx / length, dy / length)
+                } else {
+                    (1.0, 0.0)
+                };
+                Some((position.0 + dx * distance, position.1 + dy * distance))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AreaEvent {
+    // `None` is the lobby
+    pub room: Option<RoomId>,
+    // What clients show, such as `explosion`
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    #[serde(default)]
+    pub effect: AreaEffect,
+}
+
+impl AreaEvent {
+    pub fn validate(&self, config: &AreaConfig) -> Result<(), String> {
+        if self.kind.is_empty() || self.kind.len() > MAX_KIND_LEN {
+            return Err(format!(
+                "an area event's kind must be 1 to {} bytes",
+                MAX_KIND_LEN
+            ));
+        }
+        if !(self.x.is_finite() && self.y.is_finite()) {
+            return Err("an area event needs a finite position".to_string());
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0 && self.radius <= config.max_radius) {
+            return Err(format!(
+                "an area event's radius must be above 0 and at most {}",
+                config.max_radius
+            ));
+        }
+        if let AreaEffect::Knockback { distance } = self.effect {
+            if !distance.is_finite() {
+                return Err("knockback needs a finite distance".to_string());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+// Bots: players the server itself runs, to fill matches and to soak-test the
+// whole player pipeline. Each bot is an in-process client (see
+// `server::LocalClient`) that connects, says `Hello`, moves with
+// `PlayerPosition` and reads everything sent to it as any client does, so
+// it holds a seat and a team and goes through the same handling as a
+// player. Operators add bots with `bot add`, and a bot in a match gives up
+// its seat to any queued player who could take it. With `fill` on, every
+// running match has its teams topped up to `matchmaking.team_size` with
+// bots, who leave teams that players overfill.
+//
+// A bot given one of `paths` walks its waypoints round and round. Any other
+// bot gives chase to the nearest player off its team within `sight`, and
+// otherwise wanders within `wander` of where it entered its room. Bots move
+// at `speed` units a second of simulated time.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BotConfig {
+    pub fill: bool,
+    // The most bots there may be at once
+    pub max: usize,
+    pub speed: f32,
+    pub wander: f32,
+    // 0 for bots that never give chase
+    pub sight: f32,
+    pub paths: BTreeMap<String, Vec<(f32, f32)>>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            fill: false,
+            max: 64,
+            speed: 5.0,
+            wander: 20.0,
+            sight: 15.0,
+            paths: BTreeMap::new(),
+        }
+    }
+}
+
+impl BotConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let distances = [
+            ("speed", self.speed),
+            ("wander", self.wander),
+            ("sight", self.sight),
+        ];
+        for (name, value) in distances {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("`bots.{}` must be 0 or more", name));
+            }
+        }
+        for (name, path) in &self.paths {
+            if path.is_empty() {
+                return Err(format!("bot path `{}` needs at least one waypoint", name));
+            }
+            if path.iter().any(|&(x, y)| !(x.is_finite() && y.is_finite())) {
+                return Err(format!(
+                    "bot path `{}` has a waypoint that isn't a number",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where a step of at most `distance` from `from` towards `to` ends, and
+// whether it gets there
+pub fn step_toward(from: (f32, f32), to: (f32, f32), distance: f32) -> ((f32, f32), bool) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let left = dx.hypot(dy);
+    if left <= distance {
+        return (to, true);
+    }
+    let t = distance / left;
+    ((from.0 + dx * t, from.1 + dy * t), false)
+}
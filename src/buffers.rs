@@ -0,0 +1,97 @@
+// Reusable encode buffers. Each encoding worker owns a `BufferPool`, so taking
+// and returning buffers never locks; the counters are shared so the console and
+// dashboards can see how well the pool is doing.
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Idle buffers kept per pool; more than this is a burst we let the allocator absorb
+const MAX_POOLED: usize = 64;
+// Buffers grown past this by an unusually large message are freed, not kept
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+#[derive(Default)]
+pub struct BufferStats {
+    allocated: AtomicU64,
+    reused: AtomicU64,
+    discarded: AtomicU64,
+    pooled: AtomicUsize,
+}
+
+impl BufferStats {
+    pub fn snapshot(&self) -> PoolStats {
+        PoolStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+            pooled: self.pooled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Totals since startup, across every pool
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    // Buffers taken when the pool was empty
+    pub allocated: u64,
+    pub reused: u64,
+    // Buffers given back but freed, because the pool was full or they were too big
+    pub discarded: u64,
+    // Buffers sitting idle right now
+    pub pooled: usize,
+}
+
+impl PoolStats {
+    // Share of buffers served from the pool, in percent
+    pub fn reuse_percent(&self) -> u64 {
+        let taken = self.allocated + self.reused;
+        (self.reused * 100).checked_div(taken).unwrap_or(0)
+    }
+}
+
+pub struct BufferPool {
+    free: Vec<Vec<u8>>,
+    stats: Arc<BufferStats>,
+}
+
+impl BufferPool {
+    pub fn new(stats: Arc<BufferStats>) -> Self {
+        BufferPool {
+            free: Vec::new(),
+            stats,
+        }
+    }
+
+    // An empty buffer, with whatever capacity it had when it was given back
+    pub fn take(&mut self) -> Vec<u8> {
+        match self.free.pop() {
+            Some(buffer) => {
+                self.stats.reused.fetch_add(1, Ordering::Relaxed);
+                self.stats.pooled.fetch_sub(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                self.stats.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn give_back(&mut self, mut buffer: Vec<u8>) {
+        if self.free.len() >= MAX_POOLED || buffer.capacity() > MAX_POOLED_CAPACITY {
+            self.stats.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        buffer.clear();
+        self.free.push(buffer);
+        self.stats.pooled.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        self.stats
+            .pooled
+            .fetch_sub(self.free.len(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,171 @@
+// Recording of everything clients send, and a replayer that plays a recording
+// back against a server as if the same clients had connected again.
+//
+// A capture file is `MAGIC` followed by records of
+// `[micros since start u64][connection u64][kind u8][len u32][data]`, all
+// little-endian. Connections are numbered by
+// `crate::endpoint::Endpoint::raw_id`.
+use message_io::network::{Endpoint, Transport};
+use message_io::node::{self, NodeEvent};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"GSCAP\x00\x00\x01";
+
+const KIND_CONNECTED: u8 = 0;
+const KIND_FRAME: u8 = 1;
+const KIND_DISCONNECTED: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureEvent {
+    // The client's address, for reference only
+    Connected(String),
+    Frame(Vec<u8>),
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub at: Duration,
+    pub connection: u64,
+    pub event: CaptureEvent,
+}
+
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    started: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        Ok(CaptureWriter {
+            out,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, connection: u64, event: &CaptureEvent) -> io::Result<()> {
+        let (kind, data) = match event {
+            CaptureEvent::Connected(addr) => (KIND_CONNECTED, addr.as_bytes()),
+            CaptureEvent::Frame(data) => (KIND_FRAME, data.as_slice()),
+            CaptureEvent::Disconnected => (KIND_DISCONNECTED, &[][..]),
+        };
+        let micros = self.started.elapsed().as_micros() as u64;
+        self.out.write_all(&micros.to_le_bytes())?;
+        self.out.write_all(&connection.to_le_bytes())?;
+        self.out.write_all(&[kind])?;
+        self.out.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.out.write_all(data)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub fn read_capture(path: &Path) -> io::Result<Vec<Record>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a capture file"));
+    }
+    let mut records = Vec::new();
+    // A capture cut short by a crash just ends at the last whole record
+    while let Some(record) = read_record(&mut input)? {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn read_record(input: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut header = [0; 21];
+    match input.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let micros = u64::from_le_bytes(header[0..8].try_into().unwrap());
+    let connection = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let len = u32::from_le_bytes(header[17..21].try_into().unwrap());
+    let mut data = vec![0; len as usize];
+    match input.read_exact(&mut data) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let event = match header[16] {
+        KIND_CONNECTED => CaptureEvent::Connected(String::from_utf8_lossy(&data).into_owned()),
+        KIND_FRAME => CaptureEvent::Frame(data),
+        KIND_DISCONNECTED => CaptureEvent::Disconnected,
+        kind => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown record kind {}", kind),
+            ))
+        }
+    };
+    Ok(Some(Record {
+        at: Duration::from_micros(micros),
+        connection,
+        event,
+    }))
+}
+
+// Play a capture against the server at `target`, opening one connection per
+// recorded client. `speed` scales the original timing: 1.0 is real time, 10.0
+// ten times faster, and 0 sends everything as fast as possible.
+pub fn replay(path: &Path, target: &str, speed: f64) -> io::Result<()> {
+    let records = read_capture(path)?;
+    println!("Replaying {} records against {}", records.len(), target);
+
+    let (handler, listener) = node::split::<()>();
+    // Server replies aren't part of the replay, but the node has to keep reading
+    let task = listener.for_each_async(|_: NodeEvent<()>| {});
+
+    let started = Instant::now();
+    let mut connections: HashMap<u64, Endpoint> = HashMap::new();
+    let mut frames = 0;
+    for record in records {
+        if speed > 0.0 {
+            let due = record.at.div_f64(speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        match record.event {
+            CaptureEvent::Connected(addr) => {
+                let (endpoint, _) = handler
+                    .network()
+                    .connect_sync(Transport::FramedTcp, target)?;
+                println!("Connection {} (originally {})", record.connection, addr);
+                connections.insert(record.connection, endpoint);
+            }
+            CaptureEvent::Frame(data) => {
+                if let Some(&endpoint) = connections.get(&record.connection) {
+                    handler.network().send(endpoint, &data);
+                    frames += 1;
+                }
+            }
+            CaptureEvent::Disconnected => {
+                if let Some(endpoint) = connections.remove(&record.connection) {
+                    handler.network().remove(endpoint.resource_id());
+                }
+            }
+        }
+    }
+    println!(
+        "Replayed {} frames in {:.1}s",
+        frames,
+        started.elapsed().as_secs_f64()
+    );
+    handler.stop();
+    drop(task);
+    Ok(())
+}
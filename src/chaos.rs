@@ -0,0 +1,73 @@
+// Simulated bad networks for testing clients. Only debug builds accept a
+// `chaos` section in the config.
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChaosConfig {
+    // Which way the effects apply
+    pub inbound: bool,
+    pub outbound: bool,
+    pub drop_percent: f64,
+    pub duplicate_percent: f64,
+    // Reordered messages are held back an extra `reorder_ms` so later ones overtake them
+    pub reorder_percent: f64,
+    pub reorder_ms: u64,
+    // Every message is delayed by `delay_ms` plus up to `jitter_ms`
+    pub delay_ms: u64,
+    pub jitter_ms: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            inbound: true,
+            outbound: true,
+            drop_percent: 0.0,
+            duplicate_percent: 0.0,
+            reorder_percent: 0.0,
+            reorder_ms: 100,
+            delay_ms: 0,
+            jitter_ms: 0,
+        }
+    }
+}
+
+impl ChaosConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let percents = [
+            self.drop_percent,
+            self.duplicate_percent,
+            self.reorder_percent,
+        ];
+        if percents.iter().any(|p| !(0.0..=100.0).contains(p)) {
+            return Err("chaos percentages must be between 0 and 100".to_string());
+        }
+        Ok(())
+    }
+
+    // When to deliver each copy of a message: none if it is dropped, two if
+    // it is duplicated
+    pub fn plan(&self) -> Vec<Duration> {
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.drop_percent / 100.0) {
+            return Vec::new();
+        }
+        let copies = if rng.gen_bool(self.duplicate_percent / 100.0) {
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let mut delay = self.delay_ms + rng.gen_range(0..=self.jitter_ms);
+                if rng.gen_bool(self.reorder_percent / 100.0) {
+                    delay += self.reorder_ms;
+                }
+                Duration::from_millis(delay)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,228 @@
+// Recent chat, kept per channel in bounded buffers so that late joiners can
+// catch up with `FetchChatHistory` and moderators can read back with
+// `history`. Channels are keyed by where they live: the global channel is
+// one, every room and the lobby has its own, and so on.
+use crate::accounts::AccountId;
+use crate::parties::PartyId;
+use crate::protocol::{ChatChannel, ChatLine};
+use crate::rooms::{RoomId, TeamId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChatHistoryConfig {
+    // Lines kept per channel; 0 keeps none
+    pub max_lines: usize,
+    // The most lines one `FetchChatHistory` returns
+    pub max_fetch: usize,
+    // Keep history across restarts. Only the global channel, the lobby and
+    // rooms that still exist after the restart survive it.
+    pub persist: bool,
+}
+
+impl Default for ChatHistoryConfig {
+    fn default() -> Self {
+        ChatHistoryConfig {
+            max_lines: 100,
+            max_fetch: 50,
+            persist: false,
+        }
+    }
+}
+
+impl ChatHistoryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_fetch == 0 {
+            return Err("`chat_history.max_fetch` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+// One channel's buffer. Written as `global`, `room:lobby`, `room:3`,
+// `team:3:1`, `party:2` or `spectator:3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKey {
+    Global,
+    Room(Option<RoomId>),
+    Team(RoomId, TeamId),
+    Party(PartyId),
+    Spectator(RoomId),
+}
+
+impl ChannelKey {
+    pub fn channel(&self) -> ChatChannel {
+        match self {
+            ChannelKey::Global => ChatChannel::Global,
+            ChannelKey::Room(_) => ChatChannel::Room,
+            ChannelKey::Team(..) => ChatChannel::Team,
+            ChannelKey::Party(_) => ChatChannel::Party,
+            ChannelKey::Spectator(_) => ChatChannel::Spectator,
+        }
+    }
+
+    // The room the channel belongs to, if it belongs to one
+    fn room(&self) -> Option<RoomId> {
+        match *self {
+            ChannelKey::Room(room) => room,
+            ChannelKey::Team(room, _) | ChannelKey::Spectator(room) => Some(room),
+            ChannelKey::Global | ChannelKey::Party(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ChannelKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelKey::Global => write!(f, "global"),
+            ChannelKey::Room(None) => write!(f, "room:lobby"),
+            ChannelKey::Room(Some(room)) => write!(f, "room:{}", room),
+            ChannelKey::Team(room, team) => write!(f, "team:{}:{}", room, team),
+            ChannelKey::Party(party) => write!(f, "party:{}", party),
+            ChannelKey::Spectator(room) => write!(f, "spectator:{}", room),
+        }
+    }
+}
+
+impl FromStr for ChannelKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let number = |part: &str| {
+            part.parse::<u32>()
+                .map_err(|_| format!("`{}` is not a number", part))
+        };
+        let key = match parts.as_slice() {
+            ["global"] => ChannelKey::Global,
+            ["room", "lobby"] => ChannelKey::Room(None),
+            ["room", room] => ChannelKey::Room(Some(number(room)?)),
+            ["team", room, team] => ChannelKey::Team(
+                number(room)?,
+                team.parse()
+                    .map_err(|_| format!("`{}` is not a team", team))?,
+            ),
+            ["party", party] => ChannelKey::Party(number(party)?),
+            ["spectator", room] => ChannelKey::Spectator(number(room)?),
+            _ => {
+                return Err(format!(
+                    "unknown channel `{}`; use global, room:<id|lobby>, team:<room>:<team>, party:<id> or spectator:<room>",
+                    s
+                ))
+            }
+        };
+        Ok(key)
+    }
+}
+
+// A line as kept: players only ever see the `ChatLine`, but the sender's
+// account stays with it so the line can be exported or erased with the rest
+// of the account
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryLine {
+    #[serde(flatten)]
+    pub line: ChatLine,
+    #[serde(default)]
+    pub account: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ChatHistory {
+    // By `ChannelKey`, written out; oldest line first
+    pub channels: BTreeMap<String, VecDeque<HistoryLine>>,
+    pub next_id: u64,
+}
+
+impl ChatHistory {
+    pub const STORAGE_KEY: &'static str = "chat_history";
+
+    pub fn record(
+        &mut self,
+        key: ChannelKey,
+        mut line: ChatLine,
+        account: Option<AccountId>,
+        max_lines: usize,
+    ) {
+        self.next_id += 1;
+        if max_lines == 0 {
+            return;
+        }
+        line.id = self.next_id;
+        let lines = self.channels.entry(key.to_string()).or_default();
+        lines.push_back(HistoryLine { line, account });
+        while lines.len() > max_lines {
+            lines.pop_front();
+        }
+    }
+
+    // Up to `limit` of the latest lines older than the line `before`, oldest first
+    pub fn fetch(&self, key: ChannelKey, before: Option<u64>, limit: usize) -> Vec<ChatLine> {
+        let Some(lines) = self.channels.get(&key.to_string()) else {
+            return Vec::new();
+        };
+        let mut found: Vec<ChatLine> = lines
+            .iter()
+            .rev()
+            .map(|kept| &kept.line)
+            .filter(|line| before.is_none_or(|before| line.id < before))
+            .take(limit)
+            .cloned()
+            .collect();
+        found.reverse();
+        found
+    }
+
+    // Every line `account` sent that is still kept, with its channel
+    pub fn sent_by(&self, account: AccountId) -> Vec<(String, ChatLine)> {
+        self.channels
+            .iter()
+            .flat_map(|(key, lines)| {
+                lines
+                    .iter()
+                    .filter(move |kept| kept.account == Some(account))
+                    .map(move |kept| (key.clone(), kept.line.clone()))
+            })
+            .collect()
+    }
+
+    // Drop everything `account` said; returns how many lines went
+    pub fn forget_account(&mut self, account: AccountId) -> usize {
+        let mut forgotten = 0;
+        for lines in self.channels.values_mut() {
+            let before = lines.len();
+            lines.retain(|kept| kept.account != Some(account));
+            forgotten += before - lines.len();
+        }
+        self.channels.retain(|_, lines| !lines.is_empty());
+        forgotten
+    }
+
+    // Drop the channels of a room that closed
+    pub fn forget_room(&mut self, room: RoomId) {
+        self.channels.retain(|key, _| {
+            key.parse::<ChannelKey>()
+                .map_or(true, |k| k.room() != Some(room))
+        });
+    }
+
+    pub fn forget_party(&mut self, party: PartyId) {
+        self.channels.remove(&ChannelKey::Party(party).to_string());
+    }
+
+    // After a restart: parties and teams are gone, and so are rooms that
+    // `room_exists` doesn't know
+    pub fn retain_lasting(&mut self, room_exists: impl Fn(RoomId) -> bool) {
+        self.channels
+            .retain(|key, _| match key.parse::<ChannelKey>() {
+                Ok(ChannelKey::Global) | Ok(ChannelKey::Room(None)) => true,
+                Ok(ChannelKey::Room(Some(room))) | Ok(ChannelKey::Spectator(room)) => {
+                    room_exists(room)
+                }
+                _ => false,
+            });
+    }
+}
@@ -0,0 +1,154 @@
+// Address ranges in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`. A
+// bare address is a range of one.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpRange {
+    // With the bits past `prefix` cleared
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients on a dual-stack socket show up as `::ffff:a.b.c.d`
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                u32::from(ip) & v4_mask(self.prefix) == u32::from(network)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                u128::from(ip) & v6_mask(self.prefix) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn v4_mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+fn v6_mask(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{}` is not an address or CIDR range", s);
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let address = address.to_canonical();
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        let network = match address {
+            IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & v4_mask(prefix))),
+            IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & v6_mask(prefix))),
+        };
+        Ok(IpRange { network, prefix })
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IpRange> for String {
+    fn from(range: IpRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = if self.network.is_ipv4() { 32 } else { 128 };
+        if self.prefix == max {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(s: &str) -> IpRange {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges_and_bare_addresses() {
+        assert_eq!(range("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(range("192.168.0.7").to_string(), "192.168.0.7");
+        assert_eq!(range("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(range("::1").to_string(), "::1");
+        assert_eq!(range("0.0.0.0/0").to_string(), "0.0.0.0/0");
+    }
+
+    #[test]
+    fn refuses_what_is_not_a_range() {
+        for s in [
+            "",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0/8",
+            "10.0.0.0/-1",
+            "host/8",
+        ] {
+            assert!(s.parse::<IpRange>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn mapped_addresses_are_ipv4() {
+        assert_eq!(range("::ffff:10.0.0.1/8"), range("10.0.0.0/8"));
+        assert!(range("10.0.0.0/8").contains(ip("::ffff:10.9.9.9")));
+    }
+
+    #[test]
+    fn contains_only_its_own_addresses() {
+        let v4 = range("10.0.0.0/8");
+        assert!(v4.contains(ip("10.255.255.255")));
+        assert!(!v4.contains(ip("11.0.0.0")));
+        assert!(!v4.contains(ip("::a00:1")));
+        assert!(range("0.0.0.0/0").contains(ip("203.0.113.9")));
+
+        let v6 = range("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::")));
+        assert!(!v6.contains(ip("10.0.0.1")));
+        assert!(range("::/0").contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn serde_uses_the_string_form() {
+        let parsed: IpRange = serde_json::from_str("\"172.16.0.0/12\"").unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), "\"172.16.0.0/12\"");
+        assert!(serde_json::from_str::<IpRange>("\"172.16.0.0/40\"").is_err());
+    }
+}
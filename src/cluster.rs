@@ -0,0 +1,161 @@
+// One logical server spanning machines. Gateways (`--gateway`) hold the
+// client connections and carry their frames, undecoded, over a link to one of
+// the workers (`--worker <n>`), which run the game. `cluster.workers` is the
+// routing table, the same on every node: each worker's link address and the
+// configured rooms it hosts. Worker n numbers the rooms and players it creates
+// from a block of its own, so a room id says which worker owns the room. A
+// player joining a room on another worker is moved there: their worker sends
+// the session through the gateway, which re-attaches the connection to the
+// room's worker, and the join goes ahead on it.
+//
+// Each worker keeps its own stores, lobby and room list, so parties and chat
+// stay within a worker and accounts only follow a moved player between
+// workers that share them.
+use crate::rooms::RoomId;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::ops::Range;
+
+// Each worker numbers its rooms and players within a block this big
+pub const ROOM_ID_BLOCK: RoomId = 1 << 24;
+const PLAYER_ID_BLOCK: usize = 1 << 40;
+// Room ids are 32 bits, so this many blocks fit
+const MAX_WORKERS: usize = 256;
+// Frames on a link hold one client frame at most, or one session
+const MAX_LINK_FRAME: usize = 16 << 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ClusterConfig {
+    pub workers: Vec<WorkerConfig>,
+    // Argon2 PHC string (see `--hash-password`) of the token gateways send;
+    // workers check it on every link
+    pub token_hash: Option<String>,
+    // What a gateway sends
+    pub token: Option<String>,
+    // Set by `--worker <n>`: this process is `workers[n]`
+    #[serde(skip)]
+    pub worker: Option<usize>,
+    // Set by `--gateway`
+    #[serde(skip)]
+    pub gateway: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkerConfig {
+    // Where gateways connect to the worker
+    pub link_addr: String,
+    // Names of the configured rooms it hosts
+    #[serde(default)]
+    pub rooms: Vec<String>,
+}
+
+impl ClusterConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.workers.len() > MAX_WORKERS {
+            return Err(format!("`cluster.workers` holds at most {}", MAX_WORKERS));
+        }
+        if self.gateway && self.worker.is_some() {
+            return Err("`--gateway` and `--worker` can't be used together".to_string());
+        }
+        if let Some(index) = self.worker {
+            if index >= self.workers.len() {
+                return Err(format!("`--worker {}` is not in `cluster.workers`", index));
+            }
+            if self.token_hash.is_none() {
+                return Err("`--worker` needs `cluster.token_hash`".to_string());
+            }
+        }
+        if self.gateway && (self.workers.is_empty() || self.token.is_none()) {
+            return Err("`--gateway` needs `cluster.workers` and `cluster.token`".to_string());
+        }
+        Ok(())
+    }
+
+    // Whether this process hosts the configured room called `name`
+    pub fn hosts(&self, name: &str) -> bool {
+        match self.worker {
+            Some(index) => self.workers[index].rooms.iter().any(|room| room == name),
+            None => true,
+        }
+    }
+
+    // Rooms this process creates are numbered after this
+    pub fn first_room_id(&self) -> RoomId {
+        self.worker
+            .map_or(0, |index| index as RoomId * ROOM_ID_BLOCK)
+    }
+
+    // The ids this server gives its players; 0 is never one
+    pub fn player_ids(&self) -> Range<usize> {
+        match self.worker {
+            Some(index) => index * PLAYER_ID_BLOCK + 1..(index + 1) * PLAYER_ID_BLOCK,
+            None => 1..usize::MAX,
+        }
+    }
+
+    // The worker that owns `room`, when it isn't this one
+    pub fn remote_owner(&self, room: RoomId) -> Option<usize> {
+        let own = self.worker?;
+        let owner = (room.saturating_sub(1) / ROOM_ID_BLOCK) as usize;
+        (owner != own && owner < self.workers.len()).then_some(owner)
+    }
+}
+
+// What gateways and workers tell each other. Connections are numbered by the
+// gateway holding them.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LinkFrame {
+    // Gateway to worker: a client connected from `addr`
+    Open {
+        connection: u64,
+        addr: SocketAddr,
+    },
+    // Either way: a client frame, exactly as on the client's own connection
+    Data {
+        connection: u64,
+        data: Vec<u8>,
+    },
+    // Gateway to worker: the client went away. Worker to gateway: close it.
+    Close {
+        connection: u64,
+    },
+    // Worker to gateway: carry the connection over to `worker`
+    Move {
+        connection: u64,
+        worker: usize,
+        transfer: Vec<u8>,
+    },
+    // Gateway to worker: a connection moved here, with what its last worker
+    // put in `transfer`
+    Adopt {
+        connection: u64,
+        addr: SocketAddr,
+        transfer: Vec<u8>,
+    },
+}
+
+// A 4-byte big-endian length, then the frame in bincode
+pub fn write_frame(writer: &mut impl Write, frame: &LinkFrame) -> io::Result<()> {
+    let body = bincode::serialize(frame).map_err(io::Error::other)?;
+    let mut data = Vec::with_capacity(4 + body.len());
+    data.extend((body.len() as u32).to_be_bytes());
+    data.extend(body);
+    writer.write_all(&data)
+}
+
+pub fn read_frame(reader: &mut impl Read) -> io::Result<LinkFrame> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_LINK_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("link frame of {} bytes", len),
+        ));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    bincode::deserialize(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
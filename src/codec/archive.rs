@@ -0,0 +1,60 @@
+// rkyv snapshots: the archived bytes are the in-memory layout, so a client
+// reads players straight out of the received buffer without deserializing.
+//
+// The layout is whatever rkyv derives for `Snapshot`, laid out like the
+// FlatBuffers `Snapshot` with one column per field. Changing a field here breaks
+// clients built against the old layout, so treat it like a protocol bump.
+use crate::protocol::PlayerSnapshot;
+use rkyv::rancor::Error;
+use rkyv::util::AlignedVec;
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub ids: Vec<u64>,
+    pub xs: Vec<f32>,
+    pub ys: Vec<f32>,
+    pub stale: Vec<bool>,
+}
+
+impl ArchivedSnapshot {
+    // Iterate the players without copying the buffer
+    pub fn players(&self) -> impl Iterator<Item = PlayerSnapshot> + '_ {
+        self.ids
+            .iter()
+            .zip(self.xs.iter())
+            .zip(self.ys.iter())
+            .zip(self.stale.iter())
+            .map(|(((id, x), y), &stale)| PlayerSnapshot {
+                id: id.to_native() as usize,
+                x: x.to_native(),
+                y: y.to_native(),
+                stale,
+            })
+    }
+}
+
+pub fn build_snapshot(tick: u64, players: &[PlayerSnapshot]) -> Vec<u8> {
+    let snapshot = Snapshot {
+        tick,
+        ids: players.iter().map(|p| p.id as u64).collect(),
+        xs: players.iter().map(|p| p.x).collect(),
+        ys: players.iter().map(|p| p.y).collect(),
+        stale: players.iter().map(|p| p.stale).collect(),
+    };
+    rkyv::to_bytes::<Error>(&snapshot)
+        .expect("snapshots always serialize")
+        .into_vec()
+}
+
+// Validate and borrow a snapshot buffer. rkyv needs the bytes aligned, which a
+// decoded frame's buffer may not be, so pass it through `align` first
+pub fn read_snapshot(buf: &AlignedVec) -> Result<&ArchivedSnapshot, Error> {
+    rkyv::access::<ArchivedSnapshot, Error>(buf)
+}
+
+pub fn align(buf: &[u8]) -> AlignedVec {
+    let mut aligned = AlignedVec::with_capacity(buf.len());
+    aligned.extend_from_slice(buf);
+    aligned
+}
@@ -0,0 +1,131 @@
+// Tagged, length-prefixed bincode frames.
+//
+// A frame is `[tag: u32 LE][len: u32 LE][payload: len bytes]`, where `tag` is
+// the `ClientMessage` variant index and `payload` is the bincode encoding of
+// that variant's fields. Because the payload length is explicit, a peer that
+// doesn't know a tag can skip the frame instead of misreading it. Variant
+// indices are the tags, so new variants must only ever be appended.
+use super::DecodeError;
+use crate::protocol::ClientMessage;
+use bincode::Options;
+use std::io::Read;
+use strum::EnumCount;
+
+const HEADER_LEN: usize = 8;
+
+pub fn encode(message: &ClientMessage) -> Vec<u8> {
+    let mut frame = Vec::new();
+    encode_into(message, &mut frame);
+    frame
+}
+
+// Appends the frame to `buffer`
+pub fn encode_into(message: &ClientMessage, buffer: &mut Vec<u8>) {
+    let start = buffer.len();
+    bincode::serialize_into(&mut *buffer, message).unwrap();
+    // Slot the payload length in between the tag and the fields
+    let len = (buffer.len() - start - 4) as u32;
+    buffer.splice(start + 4..start + 4, len.to_le_bytes());
+}
+
+pub fn decode(data: &[u8]) -> Result<ClientMessage, DecodeError> {
+    if data.len() < HEADER_LEN {
+        return Err(DecodeError::Truncated);
+    }
+    let tag = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    if tag as usize >= ClientMessage::COUNT {
+        return Err(DecodeError::UnknownMessage(Some(tag)));
+    }
+    let payload = data
+        .get(HEADER_LEN..HEADER_LEN + len)
+        .ok_or(DecodeError::Truncated)?;
+
+    // Stitch the tag back in front of the fields so serde sees a plain enum
+    super::bincode_options(HEADER_LEN + len)
+        .deserialize_from((&data[0..4]).chain(payload))
+        .map_err(DecodeError::Bincode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{DecodeLimits, WireFormat};
+    use crate::protocol::TerrainCell;
+
+    fn chat(message: &str) -> ClientMessage {
+        ClientMessage::UpdateMessage {
+            id: 7,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let message = chat("hello");
+        assert_eq!(decode(&encode(&message)).unwrap(), message);
+    }
+
+    #[test]
+    fn refuses_short_frames() {
+        let frame = encode(&chat("hello"));
+        assert!(matches!(decode(&frame[..5]), Err(DecodeError::Truncated)));
+        // The header promises more than follows it
+        assert!(matches!(
+            decode(&frame[..frame.len() - 1]),
+            Err(DecodeError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn refuses_unknown_tags() {
+        let mut frame = encode(&chat("hello"));
+        let tag = ClientMessage::COUNT as u32;
+        frame[0..4].copy_from_slice(&tag.to_le_bytes());
+        assert!(matches!(
+            decode(&frame),
+            Err(DecodeError::UnknownMessage(Some(t))) if t == tag
+        ));
+    }
+
+    #[test]
+    fn length_prefixes_cannot_outgrow_the_frame() {
+        let mut frame = encode(&chat("hello"));
+        // The string's own length prefix, right after the 8-byte header and
+        // the 8-byte player id
+        frame[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(decode(&frame), Err(DecodeError::Bincode(_))));
+    }
+
+    #[test]
+    fn decode_within_enforces_limits() {
+        let limits = DecodeLimits {
+            max_frame_bytes: 64,
+            max_string_bytes: 8,
+            max_collection_len: 2,
+        };
+        let format = WireFormat::TaggedBincode;
+        let within = |message: &ClientMessage| format.decode_within(&encode(message), &limits);
+
+        assert!(within(&chat("short")).is_ok());
+        assert!(matches!(
+            within(&chat("far too long")),
+            Err(DecodeError::OverLimit(_))
+        ));
+        assert!(matches!(
+            within(&chat(&"x".repeat(100))),
+            Err(DecodeError::OverLimit(_))
+        ));
+        let cell = |x| TerrainCell {
+            x,
+            y: 0,
+            value: "s".to_string(),
+        };
+        let chunk = ClientMessage::TerrainChunk {
+            x: 0,
+            y: 0,
+            cells: vec![cell(0), cell(1), cell(2)],
+        };
+        assert!(matches!(within(&chunk), Err(DecodeError::OverLimit(_))));
+    }
+}
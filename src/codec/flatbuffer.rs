@@ -0,0 +1,125 @@
+// Hand-written FlatBuffers accessors for `proto/snapshot.fbs`.
+//
+// The layout mirrors what `flatc --rust` would generate for the `Snapshot`
+// table; keep the vtable slots in sync with the schema file.
+use crate::protocol::PlayerSnapshot;
+use flatbuffers::{
+    FlatBufferBuilder, Follow, ForwardsUOffset, InvalidFlatbuffer, Table, VOffsetT, Vector,
+    Verifiable, Verifier,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot<'a> {
+    table: Table<'a>,
+}
+
+impl<'a> Follow<'a> for Snapshot<'a> {
+    type Inner = Snapshot<'a>;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+        Snapshot {
+            table: Table::new(buf, loc),
+        }
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    pub const VT_TICK: VOffsetT = 4;
+    pub const VT_IDS: VOffsetT = 6;
+    pub const VT_XS: VOffsetT = 8;
+    pub const VT_YS: VOffsetT = 10;
+    pub const VT_STALE: VOffsetT = 12;
+
+    pub fn tick(&self) -> u64 {
+        // Safety: the buffer was verified against the schema in `read_snapshot`
+        unsafe { self.table.get::<u64>(Self::VT_TICK, Some(0)).unwrap() }
+    }
+
+    pub fn ids(&self) -> Option<Vector<'a, u64>> {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<Vector<'a, u64>>>(Self::VT_IDS, None)
+        }
+    }
+
+    pub fn xs(&self) -> Option<Vector<'a, f32>> {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<Vector<'a, f32>>>(Self::VT_XS, None)
+        }
+    }
+
+    pub fn ys(&self) -> Option<Vector<'a, f32>> {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<Vector<'a, f32>>>(Self::VT_YS, None)
+        }
+    }
+
+    pub fn stale(&self) -> Option<Vector<'a, bool>> {
+        unsafe {
+            self.table
+                .get::<ForwardsUOffset<Vector<'a, bool>>>(Self::VT_STALE, None)
+        }
+    }
+
+    // Iterate the players without copying the buffer
+    pub fn players(&self) -> impl Iterator<Item = PlayerSnapshot> + 'a {
+        let ids = self.ids();
+        let xs = self.xs();
+        let ys = self.ys();
+        let stale = self.stale();
+        let len = [
+            ids.map(|v| v.len()),
+            xs.map(|v| v.len()),
+            ys.map(|v| v.len()),
+        ]
+        .into_iter()
+        .map(Option::unwrap_or_default)
+        .min()
+        .unwrap_or(0);
+        (0..len).map(move |i| PlayerSnapshot {
+            id: ids.unwrap().get(i) as usize,
+            x: xs.unwrap().get(i),
+            y: ys.unwrap().get(i),
+            // Buffers from before `stale` existed have none
+            stale: stale.is_some_and(|v| i < v.len() && v.get(i)),
+        })
+    }
+}
+
+impl Verifiable for Snapshot<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<u64>("tick", Self::VT_TICK, false)?
+            .visit_field::<ForwardsUOffset<Vector<u64>>>("ids", Self::VT_IDS, false)?
+            .visit_field::<ForwardsUOffset<Vector<f32>>>("xs", Self::VT_XS, false)?
+            .visit_field::<ForwardsUOffset<Vector<f32>>>("ys", Self::VT_YS, false)?
+            .visit_field::<ForwardsUOffset<Vector<bool>>>("stale", Self::VT_STALE, false)?
+            .finish();
+        Ok(())
+    }
+}
+
+pub fn build_snapshot(tick: u64, players: &[PlayerSnapshot]) -> Vec<u8> {
+    let mut builder = FlatBufferBuilder::with_capacity(32 + players.len() * 16);
+    let ids = builder.create_vector_from_iter(players.iter().map(|p| p.id as u64));
+    let xs = builder.create_vector_from_iter(players.iter().map(|p| p.x));
+    let ys = builder.create_vector_from_iter(players.iter().map(|p| p.y));
+    let stale = builder.create_vector_from_iter(players.iter().map(|p| p.stale));
+
+    let start = builder.start_table();
+    builder.push_slot::<u64>(Snapshot::VT_TICK, tick, 0);
+    builder.push_slot_always(Snapshot::VT_IDS, ids);
+    builder.push_slot_always(Snapshot::VT_XS, xs);
+    builder.push_slot_always(Snapshot::VT_YS, ys);
+    builder.push_slot_always(Snapshot::VT_STALE, stale);
+    let root = builder.end_table(start);
+    builder.finish(root, None);
+    builder.finished_data().to_vec()
+}
+
+// Verify and borrow a snapshot buffer
+pub fn read_snapshot(buf: &[u8]) -> Result<Snapshot<'_>, InvalidFlatbuffer> {
+    flatbuffers::root::<Snapshot>(buf)
+}
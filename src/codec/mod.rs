@@ -0,0 +1,229 @@
+use crate::protocol::ClientMessage;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "rkyv")]
+pub mod archive;
+pub mod envelope;
+#[cfg(feature = "flatbuffers")]
+pub mod flatbuffer;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+
+// The schemas are always exported, even when the server itself is built
+// without the matching feature, so client bindings can be generated from any build.
+pub const PROTO_SCHEMA: &str = include_str!("../../proto/game.proto");
+pub const FLATBUFFERS_SCHEMA: &str = include_str!("../../proto/snapshot.fbs");
+
+// How `ClientMessage` frames are laid out on the wire
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Bincode,
+    Protobuf,
+    // Bincode inside a tagged, length-prefixed envelope, so peers can skip
+    // message types they don't know about (see `envelope`)
+    TaggedBincode,
+}
+
+impl WireFormat {
+    // Whether this build of the server can encode and decode the format
+    pub fn is_available(self) -> bool {
+        match self {
+            WireFormat::Bincode => true,
+            WireFormat::Protobuf => cfg!(feature = "protobuf"),
+            WireFormat::TaggedBincode => true,
+        }
+    }
+
+    pub fn encode(self, message: &ClientMessage) -> Vec<u8> {
+        match self {
+            WireFormat::Bincode => bincode::serialize(message).unwrap(),
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => protobuf::encode(message),
+            #[cfg(not(feature = "protobuf"))]
+            WireFormat::Protobuf => unreachable!("protobuf support is not compiled in"),
+            WireFormat::TaggedBincode => envelope::encode(message),
+        }
+    }
+
+    // Like `encode`, but into a buffer the caller can reuse; `buffer` is
+    // cleared first
+    pub fn encode_into(self, message: &ClientMessage, buffer: &mut Vec<u8>) {
+        buffer.clear();
+        match self {
+            WireFormat::Bincode => bincode::serialize_into(&mut *buffer, message).unwrap(),
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => protobuf::encode_into(message, buffer),
+            #[cfg(not(feature = "protobuf"))]
+            WireFormat::Protobuf => unreachable!("protobuf support is not compiled in"),
+            WireFormat::TaggedBincode => envelope::encode_into(message, buffer),
+        }
+    }
+
+    pub fn decode(self, data: &[u8]) -> Result<ClientMessage, DecodeError> {
+        match self {
+            WireFormat::Bincode => bincode_options(data.len())
+                .deserialize(data)
+                .map_err(DecodeError::Bincode),
+            #[cfg(feature = "protobuf")]
+            WireFormat::Protobuf => protobuf::decode(data)
+                .map_err(DecodeError::Protobuf)?
+                .ok_or(DecodeError::UnknownMessage(None)),
+            #[cfg(not(feature = "protobuf"))]
+            WireFormat::Protobuf => Err(DecodeError::Unavailable(self)),
+            WireFormat::TaggedBincode => envelope::decode(data),
+        }
+    }
+
+    // `decode` for frames from clients, refusing anything over `limits`
+    pub fn decode_within(
+        self,
+        data: &[u8],
+        limits: &DecodeLimits,
+    ) -> Result<ClientMessage, DecodeError> {
+        if data.len() > limits.max_frame_bytes {
+            return Err(DecodeError::OverLimit(format!("{}-byte frame", data.len())));
+        }
+        let message = self.decode(data)?;
+        let (string, collection) = message.largest_fields();
+        if string > limits.max_string_bytes {
+            return Err(DecodeError::OverLimit(format!("{}-byte string", string)));
+        }
+        if collection > limits.max_collection_len {
+            return Err(DecodeError::OverLimit(format!(
+                "{}-entry collection",
+                collection
+            )));
+        }
+        Ok(message)
+    }
+}
+
+// What `bincode::deserialize` uses, but refusing to read past `limit` bytes, so
+// a length prefix can't make it allocate more than the frame could hold
+pub(crate) fn bincode_options(limit: usize) -> impl bincode::Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit as u64)
+}
+
+// The most a client may send. Stream transports refuse an oversized frame
+// from its length prefix, before reading any of it; the rest is checked as
+// frames are decoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct DecodeLimits {
+    pub max_frame_bytes: usize,
+    pub max_string_bytes: usize,
+    // Entries in any list, or bytes in any embedded buffer
+    pub max_collection_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_frame_bytes: 64 * 1024,
+            max_string_bytes: 4096,
+            max_collection_len: 256,
+        }
+    }
+}
+
+// Pick the first format offered by the client that this build supports
+pub fn negotiate<T: Copy>(offered: &[T], is_available: impl Fn(T) -> bool, fallback: T) -> T {
+    offered
+        .iter()
+        .copied()
+        .find(|format| is_available(*format))
+        .unwrap_or(fallback)
+}
+
+// How world snapshots are laid out on the wire. `Native` snapshots are ordinary
+// `WorldSnapshot` messages in the connection's wire format; `FlatBuffers`
+// snapshots carry a `proto/snapshot.fbs` buffer inside a `FlatSnapshot` message;
+// `Rkyv` snapshots carry an archived `codec::archive::Snapshot` inside an
+// `ArchivedSnapshot` message; `Delta` snapshots are `DeltaSnapshot` messages
+// against the last snapshot the client acked (see `crate::deltas`). Only ever
+// append, like `WireFormat`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Native,
+    FlatBuffers,
+    Rkyv,
+    Delta,
+}
+
+impl SnapshotFormat {
+    pub fn is_available(self) -> bool {
+        match self {
+            SnapshotFormat::Native => true,
+            SnapshotFormat::FlatBuffers => cfg!(feature = "flatbuffers"),
+            SnapshotFormat::Rkyv => cfg!(feature = "rkyv"),
+            SnapshotFormat::Delta => true,
+        }
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireFormat::Bincode => write!(f, "bincode"),
+            WireFormat::Protobuf => write!(f, "protobuf"),
+            WireFormat::TaggedBincode => write!(f, "tagged-bincode"),
+        }
+    }
+}
+
+impl FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match s {
+            "bincode" => WireFormat::Bincode,
+            "protobuf" => WireFormat::Protobuf,
+            "tagged-bincode" => WireFormat::TaggedBincode,
+            _ => return Err(format!("unknown wire format `{}`", s)),
+        };
+        if !format.is_available() {
+            return Err(format!(
+                "wire format `{}` is not available in this build (enable the `{}` feature)",
+                s, s
+            ));
+        }
+        Ok(format)
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Bincode(bincode::Error),
+    #[cfg(feature = "protobuf")]
+    Protobuf(prost::DecodeError),
+    // A well-formed frame carrying a message type this build doesn't know,
+    // with its tag when the format exposes one
+    UnknownMessage(Option<u32>),
+    Truncated,
+    Unavailable(WireFormat),
+    // The frame, or something in it, is larger than `DecodeLimits` allows
+    OverLimit(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Bincode(e) => write!(f, "bincode: {}", e),
+            #[cfg(feature = "protobuf")]
+            DecodeError::Protobuf(e) => write!(f, "protobuf: {}", e),
+            DecodeError::UnknownMessage(Some(tag)) => write!(f, "unknown message type {}", tag),
+            DecodeError::UnknownMessage(None) => write!(f, "unknown message type"),
+            DecodeError::Truncated => write!(f, "truncated frame"),
+            DecodeError::Unavailable(format) => write!(f, "{} support is not compiled in", format),
+            DecodeError::OverLimit(what) => write!(f, "{} is over the limit", what),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
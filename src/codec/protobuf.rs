@@ -0,0 +1,3427 @@
+// Prost mirrors of the messages in `proto/game.proto`.
+//
+// These types must stay in sync with the schema file by hand: every
+// `ClientMessage` variant maps to exactly one `envelope::Kind` with the same tag,
+// save the retired ones whose tags the schema reserves.
+// `ClientMessage` variant maps to exactly one `envelope::Kind` with the same tag.
+use crate::accounts::PlayerUuid;
+use crate::codec;
+use crate::crafting;
+use crate::difficulty;
+use crate::guilds;
+use crate::mail;
+use crate::protocol::{self, ClientMessage};
+use crate::room_settings;
+use crate::rooms::TeamId;
+use crate::shops;
+use prost::Message;
+use std::collections::BTreeMap;
+
+// Declares a protobuf enum mirroring a protocol enum variant-for-variant, with
+// conversions both ways. `decode` maps unknown values to the first variant,
+// matching proto3 semantics for open enums.
+macro_rules! mirror_enum {
+    ($name:ident => $target:path { $first:ident = $first_value:literal $(, $variant:ident = $value:literal)* $(,)? }) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+        #[repr(i32)]
+        pub enum $name {
+            $first = $first_value,
+            $($variant = $value,)*
+        }
+
+        impl From<$target> for $name {
+            fn from(value: $target) -> Self {
+                use $target as Target;
+                match value {
+                    Target::$first => $name::$first,
+                    $(Target::$variant => $name::$variant,)*
+                }
+            }
+        }
+
+        impl From<$name> for $target {
+            fn from(value: $name) -> Self {
+                use $target as Target;
+                match value {
+                    $name::$first => Target::$first,
+                    $($name::$variant => Target::$variant,)*
+                }
+            }
+        }
+
+        impl $name {
+            pub fn decode(value: i32) -> $target {
+                $name::try_from(value).unwrap_or($name::$first).into()
+            }
+
+            pub fn encode(value: $target) -> i32 {
+                $name::from(value) as i32
+            }
+        }
+    };
+}
+
+mirror_enum!(WireFormat => codec::WireFormat {
+    Bincode = 0,
+    Protobuf = 1,
+    TaggedBincode = 2,
+});
+
+mirror_enum!(SnapshotFormat => codec::SnapshotFormat {
+    Native = 0,
+    FlatBuffers = 1,
+    Rkyv = 2,
+    Delta = 3,
+});
+
+mirror_enum!(ErrorCode => protocol::ErrorCode {
+    MalformedMessage = 0,
+    UnknownMessage = 1,
+    UnexpectedMessage = 2,
+    ProtocolMismatch = 3,
+    PlayerMismatch = 4,
+    InvalidRoom = 5,
+    WrongPassword = 6,
+    HandshakeRequired = 7,
+    InvalidRequest = 8,
+    PermissionDenied = 9,
+    RateLimited = 10,
+    Replayed = 11,
+});
+
+mirror_enum!(GuildRank => guilds::GuildRank {
+    Member = 0,
+    Officer = 1,
+    Leader = 2,
+});
+
+mirror_enum!(MailKind => mail::MailKind {
+    Whisper = 0,
+    Notice = 1,
+    Items = 2,
+});
+
+mirror_enum!(CraftError => crafting::CraftError {
+    UnknownRecipe = 0,
+    NotLoggedIn = 1,
+    MissingItems = 2,
+    InventoryFull = 3,
+});
+
+mirror_enum!(TradeError => shops::TradeError {
+    NoShop = 0,
+    OutOfReach = 1,
+    NotTraded = 2,
+    NotLoggedIn = 3,
+    MissingItems = 4,
+    InventoryFull = 5,
+});
+
+mirror_enum!(GameMode => room_settings::GameMode {
+    FreePlay = 0,
+    CaptureTheFlag = 1,
+    KingOfTheHill = 2,
+});
+
+mirror_enum!(ChatChannel => protocol::ChatChannel {
+    Global = 0,
+    Room = 1,
+    Team = 2,
+    Party = 3,
+    Spectator = 4,
+});
+
+mirror_enum!(ObjectiveState => protocol::ObjectiveState {
+    Active = 0,
+    Completed = 1,
+    Dropped = 2,
+});
+
+mirror_enum!(DisconnectReason => protocol::DisconnectReason {
+    Kicked = 0,
+    Banned = 1,
+    Timeout = 2,
+    ServerShutdown = 3,
+    ProtocolError = 4,
+    Maintenance = 5,
+    NotWhitelisted = 6,
+    WrongPassword = 7,
+    Throttled = 8,
+    Restarting = 9,
+    Upgrading = 10,
+    Migrated = 11,
+    ServerFull = 12,
+});
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerPosition {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AssignPlayerId {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UpdateMessage {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OtherPlayerConnected {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Hello {
+    #[prost(uint32, tag = "1")]
+    pub protocol_version: u32,
+    #[prost(enumeration = "WireFormat", repeated, tag = "2")]
+    pub wire_formats: Vec<i32>,
+    #[prost(enumeration = "SnapshotFormat", repeated, tag = "3")]
+    pub snapshot_formats: Vec<i32>,
+    #[prost(string, optional, tag = "4")]
+    pub password: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Welcome {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "WireFormat", tag = "2")]
+    pub wire_format: i32,
+    #[prost(enumeration = "SnapshotFormat", tag = "3")]
+    pub snapshot_format: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerSnapshot {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+    #[prost(bool, tag = "4")]
+    pub stale: bool,
+}
+
+impl From<&protocol::PlayerSnapshot> for PlayerSnapshot {
+    fn from(p: &protocol::PlayerSnapshot) -> Self {
+        PlayerSnapshot {
+            id: p.id as u64,
+            x: p.x,
+            y: p.y,
+            stale: p.stale,
+        }
+    }
+}
+
+impl From<PlayerSnapshot> for protocol::PlayerSnapshot {
+    fn from(p: PlayerSnapshot) -> Self {
+        protocol::PlayerSnapshot {
+            id: p.id as usize,
+            x: p.x,
+            y: p.y,
+            stale: p.stale,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WorldSnapshot {
+    #[prost(uint64, tag = "1")]
+    pub tick: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub players: Vec<PlayerSnapshot>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FlatSnapshot {
+    #[prost(bytes = "vec", tag = "1")]
+    pub buffer: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchivedSnapshot {
+    #[prost(bytes = "vec", tag = "1")]
+    pub buffer: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ServerError {
+    #[prost(enumeration = "ErrorCode", tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub context: String,
+    #[prost(message, optional, tag = "3")]
+    pub localized: Option<LocalizedText>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Disconnected {
+    #[prost(enumeration = "DisconnectReason", tag = "1")]
+    pub reason: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(message, optional, tag = "3")]
+    pub localized: Option<LocalizedText>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CreateRoom {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, optional, tag = "2")]
+    pub password: Option<String>,
+    #[prost(message, optional, tag = "3")]
+    pub settings: Option<RoomSettings>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomSettings {
+    #[prost(uint32, optional, tag = "1")]
+    pub max_players: Option<u32>,
+    #[prost(string, optional, tag = "2")]
+    pub map: Option<String>,
+    #[prost(enumeration = "GameMode", optional, tag = "3")]
+    pub mode: Option<i32>,
+    #[prost(bool, optional, tag = "4")]
+    pub friendly_fire: Option<bool>,
+    #[prost(string, optional, tag = "5")]
+    pub scenario: Option<String>,
+}
+
+impl From<&room_settings::RoomSettings> for RoomSettings {
+    fn from(s: &room_settings::RoomSettings) -> Self {
+        RoomSettings {
+            max_players: s.max_players,
+            map: s.map.clone(),
+            mode: s.mode.map(GameMode::encode),
+            friendly_fire: s.friendly_fire,
+            scenario: s.scenario.clone(),
+        }
+    }
+}
+
+impl From<RoomSettings> for room_settings::RoomSettings {
+    fn from(s: RoomSettings) -> Self {
+        room_settings::RoomSettings {
+            max_players: s.max_players,
+            map: s.map,
+            mode: s.mode.map(GameMode::decode),
+            friendly_fire: s.friendly_fire,
+            scenario: s.scenario,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct JoinRoom {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(string, optional, tag = "2")]
+    pub password: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LeaveRoom {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomJoined {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomLeft {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConsoleCommand {
+    #[prost(string, tag = "1")]
+    pub line: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConsoleOutput {
+    #[prost(string, tag = "1")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Announcement {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(message, optional, tag = "2")]
+    pub localized: Option<LocalizedText>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LocalizedText {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, repeated, tag = "2")]
+    pub params: Vec<TextParam>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TextParam {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+impl From<&protocol::LocalizedText> for LocalizedText {
+    fn from(text: &protocol::LocalizedText) -> Self {
+        LocalizedText {
+            key: text.key.clone(),
+            params: text
+                .params
+                .iter()
+                .map(|param| TextParam {
+                    name: param.name.clone(),
+                    value: param.value.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<LocalizedText> for protocol::LocalizedText {
+    fn from(text: LocalizedText) -> Self {
+        protocol::LocalizedText {
+            key: text.key,
+            params: text
+                .params
+                .into_iter()
+                .map(|param| protocol::TextParam {
+                    name: param.name,
+                    value: param.value,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct QueryPlayerStats {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerStats {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+    #[prost(uint64, tag = "2")]
+    pub sessions: u64,
+    #[prost(uint64, tag = "3")]
+    pub playtime_secs: u64,
+    #[prost(uint64, tag = "4")]
+    pub messages_sent: u64,
+    #[prost(uint64, tag = "5")]
+    pub first_seen: u64,
+    #[prost(uint64, tag = "6")]
+    pub last_seen: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SetName {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerNamed {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub tag: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChatMessage {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    #[prost(string, tag = "3")]
+    pub message: String,
+    #[prost(enumeration = "ChatChannel", tag = "4")]
+    pub channel: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Appearance {
+    #[prost(uint32, tag = "1")]
+    pub skin: u32,
+    #[prost(uint32, tag = "2")]
+    pub color: u32,
+    #[prost(uint32, repeated, tag = "3")]
+    pub accessories: Vec<u32>,
+}
+
+impl From<&protocol::Appearance> for Appearance {
+    fn from(a: &protocol::Appearance) -> Self {
+        Appearance {
+            skin: a.skin,
+            color: a.color,
+            accessories: a.accessories.clone(),
+        }
+    }
+}
+
+impl From<Appearance> for protocol::Appearance {
+    fn from(a: Appearance) -> Self {
+        protocol::Appearance {
+            skin: a.skin,
+            color: a.color,
+            accessories: a.accessories,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NamedPlayer {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    #[prost(float, tag = "3")]
+    pub x: f32,
+    #[prost(float, tag = "4")]
+    pub y: f32,
+    #[prost(message, optional, tag = "5")]
+    pub appearance: Option<Appearance>,
+    #[prost(string, optional, tag = "6")]
+    pub tag: Option<String>,
+    #[prost(uint32, optional, tag = "7")]
+    pub level: Option<u32>,
+}
+
+impl From<&protocol::NamedPlayer> for NamedPlayer {
+    fn from(p: &protocol::NamedPlayer) -> Self {
+        NamedPlayer {
+            id: p.id as u64,
+            name: p.name.clone(),
+            x: p.x,
+            y: p.y,
+            appearance: Some(Appearance::from(&p.appearance)),
+            tag: p.tag.clone(),
+            level: p.level,
+        }
+    }
+}
+
+impl From<NamedPlayer> for protocol::NamedPlayer {
+    fn from(p: NamedPlayer) -> Self {
+        protocol::NamedPlayer {
+            id: p.id as usize,
+            name: p.name,
+            tag: p.tag,
+            x: p.x,
+            y: p.y,
+            appearance: p.appearance.map(Into::into).unwrap_or_default(),
+            level: p.level,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct JoinSnapshot {
+    #[prost(uint32, optional, tag = "1")]
+    pub room_id: Option<u32>,
+    #[prost(message, repeated, tag = "2")]
+    pub players: Vec<NamedPlayer>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerAppearance {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(uint32, tag = "2")]
+    pub skin: u32,
+    #[prost(uint32, tag = "3")]
+    pub color: u32,
+    #[prost(uint32, repeated, tag = "4")]
+    pub accessories: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Register {
+    #[prost(string, tag = "1")]
+    pub username: String,
+    #[prost(string, tag = "2")]
+    pub password: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Login {
+    #[prost(string, tag = "1")]
+    pub username: String,
+    #[prost(string, tag = "2")]
+    pub password: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LoggedIn {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(string, tag = "2")]
+    pub username: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TokenLogin {
+    #[prost(string, tag = "1")]
+    pub token: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SteamLogin {
+    #[prost(string, tag = "1")]
+    pub ticket: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FriendAdd {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FriendAccept {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FriendRemove {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Friend {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(string, tag = "2")]
+    pub username: String,
+    #[prost(bool, tag = "3")]
+    pub online: bool,
+    #[prost(uint32, optional, tag = "4")]
+    pub room_id: Option<u32>,
+    #[prost(string, tag = "5")]
+    pub uuid: String,
+}
+
+impl From<&protocol::Friend> for Friend {
+    fn from(f: &protocol::Friend) -> Self {
+        Friend {
+            account_id: f.account_id,
+            uuid: f.uuid.to_string(),
+            username: f.username.clone(),
+            online: f.online,
+            room_id: f.room_id,
+        }
+    }
+}
+
+impl From<Friend> for protocol::Friend {
+    fn from(f: Friend) -> Self {
+        protocol::Friend {
+            account_id: f.account_id,
+            uuid: decode_uuid(&f.uuid),
+            username: f.username,
+            online: f.online,
+            room_id: f.room_id,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FriendList {
+    #[prost(message, repeated, tag = "1")]
+    pub friends: Vec<Friend>,
+    #[prost(message, repeated, tag = "2")]
+    pub incoming: Vec<Friend>,
+    #[prost(message, repeated, tag = "3")]
+    pub outgoing: Vec<Friend>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FriendPresence {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(bool, tag = "2")]
+    pub online: bool,
+    #[prost(uint32, optional, tag = "3")]
+    pub room_id: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Whisper {
+    #[prost(uint64, tag = "1")]
+    pub target_id: u64,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WhisperMessage {
+    #[prost(uint64, tag = "1")]
+    pub from_id: u64,
+    #[prost(uint64, tag = "2")]
+    pub to_id: u64,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(string, tag = "4")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Mute {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Unmute {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyInvite {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyInvited {
+    #[prost(uint32, tag = "1")]
+    pub party_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub from_id: u64,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyAccept {
+    #[prost(uint32, tag = "1")]
+    pub party_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyLeave {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyUpdate {
+    #[prost(uint32, tag = "1")]
+    pub party_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub leader_id: u64,
+    #[prost(uint64, repeated, tag = "3")]
+    pub members: Vec<u64>,
+    #[prost(uint64, repeated, tag = "4")]
+    pub invited: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyLeft {
+    #[prost(uint32, tag = "1")]
+    pub party_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyChat {
+    #[prost(string, tag = "1")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PartyChatMessage {
+    #[prost(uint32, tag = "1")]
+    pub party_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub from_id: u64,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(string, tag = "4")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildCreate {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, optional, tag = "2")]
+    pub tag: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildInvite {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildInvited {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub from_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildJoin {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildLeave {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildKick {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildSetRank {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(enumeration = "GuildRank", tag = "2")]
+    pub rank: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildChat {
+    #[prost(string, tag = "1")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildChatMessage {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+    #[prost(uint64, tag = "2")]
+    pub from_id: u64,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(string, tag = "4")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct QueryGuildRoster {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildMember {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(string, tag = "2")]
+    pub username: String,
+    #[prost(enumeration = "GuildRank", tag = "3")]
+    pub rank: i32,
+    #[prost(bool, tag = "4")]
+    pub online: bool,
+}
+
+impl From<&protocol::GuildMember> for GuildMember {
+    fn from(m: &protocol::GuildMember) -> Self {
+        GuildMember {
+            account_id: m.account_id,
+            username: m.username.clone(),
+            rank: GuildRank::encode(m.rank),
+            online: m.online,
+        }
+    }
+}
+
+impl From<GuildMember> for protocol::GuildMember {
+    fn from(m: GuildMember) -> Self {
+        protocol::GuildMember {
+            account_id: m.account_id,
+            username: m.username,
+            rank: GuildRank::decode(m.rank),
+            online: m.online,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildRoster {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, optional, tag = "3")]
+    pub tag: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub members: Vec<GuildMember>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GuildLeft {
+    #[prost(uint32, tag = "1")]
+    pub guild_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WhisperAccount {
+    #[prost(uint64, tag = "1")]
+    pub account_id: u64,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ItemGrant {
+    #[prost(string, tag = "1")]
+    pub item: String,
+    #[prost(uint32, tag = "2")]
+    pub quantity: u32,
+    #[prost(string, optional, tag = "3")]
+    pub id: Option<String>,
+}
+
+impl From<&mail::ItemGrant> for ItemGrant {
+    fn from(grant: &mail::ItemGrant) -> Self {
+        ItemGrant {
+            item: grant.item.clone(),
+            quantity: grant.quantity,
+            id: grant.id.map(|id| id.to_string()),
+        }
+    }
+}
+
+impl From<ItemGrant> for mail::ItemGrant {
+    fn from(grant: ItemGrant) -> Self {
+        mail::ItemGrant {
+            item: grant.item,
+            quantity: grant.quantity,
+            id: grant.id.as_deref().map(decode_item_id),
+        }
+    }
+}
+
+// An id that doesn't parse becomes the nil id, which names no item, rather
+// than no id, which would mean any
+fn decode_item_id(id: &str) -> mail::ItemId {
+    id.parse().unwrap_or_default()
+}
+
+// Likewise the nil UUID names no player
+fn decode_uuid(uuid: &str) -> PlayerUuid {
+    uuid.parse().unwrap_or_default()
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Mail {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "MailKind", tag = "2")]
+    pub kind: i32,
+    #[prost(uint64, tag = "3")]
+    pub sent_at: u64,
+    #[prost(uint64, optional, tag = "4")]
+    pub from_account: Option<u64>,
+    #[prost(string, optional, tag = "5")]
+    pub from_name: Option<String>,
+    #[prost(string, tag = "6")]
+    pub text: String,
+    #[prost(message, repeated, tag = "7")]
+    pub items: Vec<ItemGrant>,
+}
+
+impl From<&mail::Mail> for Mail {
+    fn from(m: &mail::Mail) -> Self {
+        Mail {
+            id: m.id,
+            kind: MailKind::encode(m.kind),
+            sent_at: m.sent_at,
+            from_account: m.from_account,
+            from_name: m.from_name.clone(),
+            text: m.text.clone(),
+            items: m.items.iter().map(ItemGrant::from).collect(),
+        }
+    }
+}
+
+impl From<Mail> for mail::Mail {
+    fn from(m: Mail) -> Self {
+        mail::Mail {
+            id: m.id,
+            kind: MailKind::decode(m.kind),
+            sent_at: m.sent_at,
+            from_account: m.from_account,
+            from_name: m.from_name,
+            text: m.text,
+            items: m.items.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MailReceived {
+    #[prost(message, repeated, tag = "1")]
+    pub mail: Vec<Mail>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AchievementUnlocked {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+    #[prost(string, tag = "2")]
+    pub achievement_id: String,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Emote {
+    #[prost(string, tag = "1")]
+    pub id: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerEmote {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+    #[prost(string, tag = "2")]
+    pub emote: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Chat {
+    #[prost(enumeration = "ChatChannel", tag = "1")]
+    pub channel: i32,
+    #[prost(string, tag = "2")]
+    pub text: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SetTeam {
+    #[prost(uint32, optional, tag = "1")]
+    pub team: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Spectate {
+    #[prost(bool, tag = "1")]
+    pub spectating: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerTeam {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+    #[prost(uint32, optional, tag = "2")]
+    pub team: Option<u32>,
+    #[prost(bool, tag = "3")]
+    pub spectating: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FetchChatHistory {
+    #[prost(enumeration = "ChatChannel", tag = "1")]
+    pub channel: i32,
+    #[prost(uint64, optional, tag = "2")]
+    pub before: Option<u64>,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChatLine {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(uint64, tag = "2")]
+    pub sent_at: u64,
+    #[prost(uint64, tag = "3")]
+    pub from_id: u64,
+    #[prost(string, optional, tag = "4")]
+    pub name: Option<String>,
+    #[prost(string, tag = "5")]
+    pub text: String,
+}
+
+impl From<&protocol::ChatLine> for ChatLine {
+    fn from(line: &protocol::ChatLine) -> Self {
+        ChatLine {
+            id: line.id,
+            sent_at: line.sent_at,
+            from_id: line.from_id as u64,
+            name: line.name.clone(),
+            text: line.text.clone(),
+        }
+    }
+}
+
+impl From<ChatLine> for protocol::ChatLine {
+    fn from(line: ChatLine) -> Self {
+        protocol::ChatLine {
+            id: line.id,
+            sent_at: line.sent_at,
+            from_id: line.from_id as usize,
+            name: line.name,
+            text: line.text,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChatHistory {
+    #[prost(enumeration = "ChatChannel", tag = "1")]
+    pub channel: i32,
+    #[prost(message, repeated, tag = "2")]
+    pub lines: Vec<ChatLine>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SessionToken {
+    #[prost(string, tag = "1")]
+    pub token: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Resume {
+    #[prost(string, tag = "1")]
+    pub token: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Standby {
+    #[prost(string, tag = "1")]
+    pub addr: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AreaEvent {
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+    #[prost(float, tag = "4")]
+    pub radius: f32,
+    #[prost(uint64, repeated, tag = "5")]
+    pub caught: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Trigger {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(bool, tag = "2")]
+    pub entered: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PointCaptured {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint32, tag = "2")]
+    pub team: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Flag {
+    #[prost(uint32, tag = "1")]
+    pub team: u32,
+    #[prost(uint64, optional, tag = "2")]
+    pub carrier: Option<u64>,
+    #[prost(float, tag = "3")]
+    pub x: f32,
+    #[prost(float, tag = "4")]
+    pub y: f32,
+    #[prost(bool, tag = "5")]
+    pub home: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TeamScore {
+    #[prost(uint32, tag = "1")]
+    pub team: u32,
+    #[prost(uint32, tag = "2")]
+    pub score: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DropFlag {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoundOver {
+    #[prost(uint32, optional, tag = "1")]
+    pub winner: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct HillControl {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint32, optional, tag = "2")]
+    pub team: Option<u32>,
+    #[prost(bool, tag = "3")]
+    pub contested: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoundTime {
+    #[prost(uint32, tag = "1")]
+    pub remaining: u32,
+    #[prost(bool, tag = "2")]
+    pub overtime: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MapVoteStarted {
+    #[prost(string, repeated, tag = "1")]
+    pub maps: Vec<String>,
+    #[prost(uint32, tag = "2")]
+    pub seconds: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MapVote {
+    #[prost(uint32, tag = "1")]
+    pub choice: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MapChanged {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntitySpawned {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(string, tag = "2")]
+    pub kind: String,
+    #[prost(float, tag = "3")]
+    pub x: f32,
+    #[prost(float, tag = "4")]
+    pub y: f32,
+    #[prost(btree_map = "string, string", tag = "5")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityDespawned {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityAuthority {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub owner: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TransferAuthority {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub to: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MoveEntity {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntityMoved {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UseAbility {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(float, tag = "2")]
+    pub x: f32,
+    #[prost(float, tag = "3")]
+    pub y: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AbilityUsed {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(float, tag = "2")]
+    pub ready_in: f32,
+    #[prost(uint32, tag = "3")]
+    pub energy: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Experience {
+    #[prost(uint64, tag = "1")]
+    pub xp: u64,
+    #[prost(uint32, tag = "2")]
+    pub level: u32,
+    #[prost(uint64, optional, tag = "3")]
+    pub next: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LevelUp {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+    #[prost(uint32, tag = "2")]
+    pub level: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PlayerLevel {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(uint32, tag = "2")]
+    pub level: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Interact {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LootDropped {
+    #[prost(float, tag = "1")]
+    pub x: f32,
+    #[prost(float, tag = "2")]
+    pub y: f32,
+    #[prost(message, repeated, tag = "3")]
+    pub items: Vec<ItemGrant>,
+    #[prost(uint64, optional, tag = "4")]
+    pub player_id: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Inventory {
+    #[prost(message, repeated, tag = "1")]
+    pub items: Vec<ItemGrant>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Craft {
+    #[prost(string, tag = "1")]
+    pub recipe_id: String,
+    #[prost(uint64, tag = "2")]
+    pub nonce: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Crafted {
+    #[prost(string, tag = "1")]
+    pub recipe_id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub items: Vec<ItemGrant>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CraftFailed {
+    #[prost(string, tag = "1")]
+    pub recipe_id: String,
+    #[prost(enumeration = "CraftError", tag = "2")]
+    pub code: i32,
+    #[prost(message, repeated, tag = "3")]
+    pub missing: Vec<ItemGrant>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Buy {
+    #[prost(uint64, tag = "1")]
+    pub entity: u64,
+    #[prost(string, tag = "2")]
+    pub item: String,
+    #[prost(uint32, tag = "3")]
+    pub quantity: u32,
+    #[prost(uint64, tag = "4")]
+    pub nonce: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sell {
+    #[prost(uint64, tag = "1")]
+    pub entity: u64,
+    #[prost(string, tag = "2")]
+    pub item: String,
+    #[prost(uint32, tag = "3")]
+    pub quantity: u32,
+    #[prost(string, optional, tag = "4")]
+    pub id: Option<String>,
+    #[prost(uint64, tag = "5")]
+    pub nonce: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ShopOffer {
+    #[prost(string, tag = "1")]
+    pub item: String,
+    #[prost(uint32, optional, tag = "2")]
+    pub buy: Option<u32>,
+    #[prost(uint32, optional, tag = "3")]
+    pub sell: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Shop {
+    #[prost(uint64, tag = "1")]
+    pub entity: u64,
+    #[prost(string, tag = "2")]
+    pub currency: String,
+    #[prost(message, repeated, tag = "3")]
+    pub offers: Vec<ShopOffer>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Traded {
+    #[prost(uint64, tag = "1")]
+    pub entity: u64,
+    #[prost(string, tag = "2")]
+    pub item: String,
+    #[prost(uint32, tag = "3")]
+    pub quantity: u32,
+    #[prost(uint32, tag = "4")]
+    pub price: u32,
+    #[prost(bool, tag = "5")]
+    pub selling: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TradeFailed {
+    #[prost(uint64, tag = "1")]
+    pub entity: u64,
+    #[prost(string, tag = "2")]
+    pub item: String,
+    #[prost(enumeration = "TradeError", tag = "3")]
+    pub code: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub missing: Vec<ItemGrant>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FindMatch {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LeaveQueue {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Queued {
+    #[prost(uint32, optional, tag = "1")]
+    pub position: Option<u32>,
+    #[prost(uint32, tag = "2")]
+    pub open_slots: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MatchFound {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(uint32, tag = "2")]
+    pub team: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SnapshotAck {
+    #[prost(uint64, tag = "1")]
+    pub tick: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DeltaSnapshot {
+    #[prost(uint64, tag = "1")]
+    pub tick: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub baseline: Option<u64>,
+    #[prost(message, repeated, tag = "3")]
+    pub players: Vec<PlayerSnapshot>,
+    #[prost(uint64, repeated, tag = "4")]
+    pub removed: Vec<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SimulationPaused {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SimulationResumed {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeScale {
+    #[prost(float, tag = "1")]
+    pub scale: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomFilters {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(enumeration = "GameMode", optional, tag = "2")]
+    pub mode: Option<i32>,
+    #[prost(bool, tag = "3")]
+    pub not_full: bool,
+    #[prost(bool, optional, tag = "4")]
+    pub password: Option<bool>,
+}
+
+impl From<&protocol::RoomFilters> for RoomFilters {
+    fn from(f: &protocol::RoomFilters) -> Self {
+        RoomFilters {
+            name: f.name.clone(),
+            mode: f.mode.map(GameMode::encode),
+            not_full: f.not_full,
+            password: f.password,
+        }
+    }
+}
+
+impl From<RoomFilters> for protocol::RoomFilters {
+    fn from(f: RoomFilters) -> Self {
+        protocol::RoomFilters {
+            name: f.name,
+            mode: f.mode.map(GameMode::decode),
+            not_full: f.not_full,
+            password: f.password,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ListRooms {
+    #[prost(message, optional, tag = "1")]
+    pub filters: Option<RoomFilters>,
+    #[prost(uint32, optional, tag = "2")]
+    pub after: Option<u32>,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomSummary {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(enumeration = "GameMode", tag = "3")]
+    pub mode: i32,
+    #[prost(uint32, tag = "4")]
+    pub players: u32,
+    #[prost(uint32, optional, tag = "5")]
+    pub max_players: Option<u32>,
+    #[prost(bool, tag = "6")]
+    pub password: bool,
+}
+
+impl From<&protocol::RoomSummary> for RoomSummary {
+    fn from(room: &protocol::RoomSummary) -> Self {
+        RoomSummary {
+            room_id: room.room_id,
+            name: room.name.clone(),
+            mode: GameMode::encode(room.mode),
+            players: room.players,
+            max_players: room.max_players,
+            password: room.password,
+        }
+    }
+}
+
+impl From<RoomSummary> for protocol::RoomSummary {
+    fn from(room: RoomSummary) -> Self {
+        protocol::RoomSummary {
+            room_id: room.room_id,
+            name: room.name,
+            mode: GameMode::decode(room.mode),
+            players: room.players,
+            max_players: room.max_players,
+            password: room.password,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomList {
+    #[prost(message, repeated, tag = "1")]
+    pub rooms: Vec<RoomSummary>,
+    #[prost(bool, tag = "2")]
+    pub more: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct InviteToRoom {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Invite {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub from_id: u64,
+    #[prost(string, optional, tag = "4")]
+    pub from_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AcceptInvite {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomHost {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(uint64, optional, tag = "2")]
+    pub player_id: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct KickFromRoom {
+    #[prost(uint64, tag = "1")]
+    pub player_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct UpdateRoomSettings {
+    #[prost(message, optional, tag = "1")]
+    pub settings: Option<RoomSettings>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StartMatch {}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RoomSettingsChanged {
+    #[prost(uint32, tag = "1")]
+    pub room_id: u32,
+    #[prost(message, optional, tag = "2")]
+    pub settings: Option<RoomSettings>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EnterWorld {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CellPosition {
+    #[prost(sint32, tag = "1")]
+    pub x: i32,
+    #[prost(sint32, tag = "2")]
+    pub y: i32,
+}
+
+impl From<protocol::CellPosition> for CellPosition {
+    fn from(position: protocol::CellPosition) -> Self {
+        CellPosition {
+            x: position.x,
+            y: position.y,
+        }
+    }
+}
+
+impl From<CellPosition> for protocol::CellPosition {
+    fn from(position: CellPosition) -> Self {
+        protocol::CellPosition {
+            x: position.x,
+            y: position.y,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TerrainChange {
+    #[prost(string, optional, tag = "1")]
+    pub seen: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub value: Option<String>,
+}
+
+impl From<&protocol::TerrainChange> for TerrainChange {
+    fn from(change: &protocol::TerrainChange) -> Self {
+        TerrainChange {
+            seen: change.seen.clone(),
+            value: change.value.clone(),
+        }
+    }
+}
+
+impl From<TerrainChange> for protocol::TerrainChange {
+    fn from(change: TerrainChange) -> Self {
+        protocol::TerrainChange {
+            seen: change.seen,
+            value: change.value,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ModifyWorld {
+    #[prost(message, optional, tag = "1")]
+    pub position: Option<CellPosition>,
+    #[prost(message, optional, tag = "2")]
+    pub change: Option<TerrainChange>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TerrainChanged {
+    #[prost(sint32, tag = "1")]
+    pub x: i32,
+    #[prost(sint32, tag = "2")]
+    pub y: i32,
+    #[prost(string, optional, tag = "3")]
+    pub value: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TerrainCell {
+    #[prost(sint32, tag = "1")]
+    pub x: i32,
+    #[prost(sint32, tag = "2")]
+    pub y: i32,
+    #[prost(string, tag = "3")]
+    pub value: String,
+}
+
+impl From<&protocol::TerrainCell> for TerrainCell {
+    fn from(cell: &protocol::TerrainCell) -> Self {
+        TerrainCell {
+            x: cell.x,
+            y: cell.y,
+            value: cell.value.clone(),
+        }
+    }
+}
+
+impl From<TerrainCell> for protocol::TerrainCell {
+    fn from(cell: TerrainCell) -> Self {
+        protocol::TerrainCell {
+            x: cell.x,
+            y: cell.y,
+            value: cell.value,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TerrainChunk {
+    #[prost(sint32, tag = "1")]
+    pub x: i32,
+    #[prost(sint32, tag = "2")]
+    pub y: i32,
+    #[prost(message, repeated, tag = "3")]
+    pub cells: Vec<TerrainCell>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ForgetChunk {
+    #[prost(sint32, tag = "1")]
+    pub x: i32,
+    #[prost(sint32, tag = "2")]
+    pub y: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Objective {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub text: String,
+    #[prost(enumeration = "ObjectiveState", tag = "3")]
+    pub state: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct DifficultyChanged {
+    #[prost(float, tag = "1")]
+    pub health: f32,
+    #[prost(float, tag = "2")]
+    pub damage: f32,
+    #[prost(float, tag = "3")]
+    pub spawn_rate: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NetStats {
+    #[prost(uint64, tag = "1")]
+    pub sequence: u64,
+    #[prost(uint64, tag = "2")]
+    pub bytes_in: u64,
+    #[prost(uint64, tag = "3")]
+    pub bytes_out: u64,
+    #[prost(uint32, optional, tag = "4")]
+    pub rtt: Option<u32>,
+    #[prost(float, optional, tag = "5")]
+    pub loss: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NetStatsAck {
+    #[prost(uint64, tag = "1")]
+    pub sequence: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Identity {
+    #[prost(string, tag = "1")]
+    pub uuid: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AbilityCasting {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(float, tag = "2")]
+    pub cast_seconds: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Envelope {
+    #[prost(
+        oneof = "envelope::Kind",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 131, 133, 134, 135, 136, 137, 138, 139, 140, 141"
+    )]
+    pub kind: Option<envelope::Kind>,
+}
+
+pub mod envelope {
+    use super::*;
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        #[prost(message, tag = "1")]
+        PlayerPosition(PlayerPosition),
+        #[prost(message, tag = "2")]
+        AssignPlayerId(AssignPlayerId),
+        #[prost(message, tag = "3")]
+        UpdateMessage(UpdateMessage),
+        #[prost(message, tag = "4")]
+        OtherPlayerConnected(OtherPlayerConnected),
+        #[prost(message, tag = "5")]
+        Hello(Hello),
+        #[prost(message, tag = "6")]
+        Welcome(Welcome),
+        #[prost(message, tag = "7")]
+        WorldSnapshot(WorldSnapshot),
+        #[prost(message, tag = "8")]
+        FlatSnapshot(FlatSnapshot),
+        #[prost(message, tag = "9")]
+        ServerError(ServerError),
+        #[prost(message, tag = "10")]
+        Disconnected(Disconnected),
+        #[prost(message, tag = "11")]
+        CreateRoom(CreateRoom),
+        #[prost(message, tag = "12")]
+        JoinRoom(JoinRoom),
+        #[prost(message, tag = "13")]
+        LeaveRoom(LeaveRoom),
+        #[prost(message, tag = "14")]
+        RoomJoined(RoomJoined),
+        #[prost(message, tag = "15")]
+        RoomLeft(RoomLeft),
+        #[prost(message, tag = "16")]
+        ConsoleCommand(ConsoleCommand),
+        #[prost(message, tag = "17")]
+        ConsoleOutput(ConsoleOutput),
+        #[prost(message, tag = "18")]
+        Announcement(Announcement),
+        #[prost(message, tag = "19")]
+        QueryPlayerStats(QueryPlayerStats),
+        #[prost(message, tag = "20")]
+        PlayerStats(PlayerStats),
+        #[prost(message, tag = "21")]
+        ArchivedSnapshot(ArchivedSnapshot),
+        #[prost(message, tag = "22")]
+        SetName(SetName),
+        #[prost(message, tag = "23")]
+        PlayerNamed(PlayerNamed),
+        #[prost(message, tag = "24")]
+        ChatMessage(ChatMessage),
+        #[prost(message, tag = "25")]
+        JoinSnapshot(JoinSnapshot),
+        #[prost(message, tag = "26")]
+        PlayerAppearance(PlayerAppearance),
+        #[prost(message, tag = "27")]
+        Register(Register),
+        #[prost(message, tag = "28")]
+        Login(Login),
+        #[prost(message, tag = "29")]
+        LoggedIn(LoggedIn),
+        #[prost(message, tag = "30")]
+        TokenLogin(TokenLogin),
+        #[prost(message, tag = "31")]
+        SteamLogin(SteamLogin),
+        #[prost(message, tag = "32")]
+        FriendAdd(FriendAdd),
+        #[prost(message, tag = "33")]
+        FriendAccept(FriendAccept),
+        #[prost(message, tag = "34")]
+        FriendRemove(FriendRemove),
+        #[prost(message, tag = "35")]
+        FriendList(FriendList),
+        #[prost(message, tag = "36")]
+        FriendPresence(FriendPresence),
+        #[prost(message, tag = "37")]
+        Whisper(Whisper),
+        #[prost(message, tag = "38")]
+        WhisperMessage(WhisperMessage),
+        #[prost(message, tag = "39")]
+        Mute(Mute),
+        #[prost(message, tag = "40")]
+        Unmute(Unmute),
+        #[prost(message, tag = "41")]
+        PartyInvite(PartyInvite),
+        #[prost(message, tag = "42")]
+        PartyInvited(PartyInvited),
+        #[prost(message, tag = "43")]
+        PartyAccept(PartyAccept),
+        #[prost(message, tag = "44")]
+        PartyLeave(PartyLeave),
+        #[prost(message, tag = "45")]
+        PartyUpdate(PartyUpdate),
+        #[prost(message, tag = "46")]
+        PartyLeft(PartyLeft),
+        #[prost(message, tag = "47")]
+        PartyChat(PartyChat),
+        #[prost(message, tag = "48")]
+        PartyChatMessage(PartyChatMessage),
+        #[prost(message, tag = "49")]
+        GuildCreate(GuildCreate),
+        #[prost(message, tag = "50")]
+        GuildInvite(GuildInvite),
+        #[prost(message, tag = "51")]
+        GuildInvited(GuildInvited),
+        #[prost(message, tag = "52")]
+        GuildJoin(GuildJoin),
+        #[prost(message, tag = "53")]
+        GuildLeave(GuildLeave),
+        #[prost(message, tag = "54")]
+        GuildKick(GuildKick),
+        #[prost(message, tag = "55")]
+        GuildSetRank(GuildSetRank),
+        #[prost(message, tag = "56")]
+        GuildChat(GuildChat),
+        #[prost(message, tag = "57")]
+        GuildChatMessage(GuildChatMessage),
+        #[prost(message, tag = "58")]
+        QueryGuildRoster(QueryGuildRoster),
+        #[prost(message, tag = "59")]
+        GuildRoster(GuildRoster),
+        #[prost(message, tag = "60")]
+        GuildLeft(GuildLeft),
+        #[prost(message, tag = "61")]
+        WhisperAccount(WhisperAccount),
+        #[prost(message, tag = "62")]
+        MailReceived(MailReceived),
+        #[prost(message, tag = "63")]
+        AchievementUnlocked(AchievementUnlocked),
+        #[prost(message, tag = "64")]
+        Emote(Emote),
+        #[prost(message, tag = "65")]
+        PlayerEmote(PlayerEmote),
+        #[prost(message, tag = "66")]
+        Chat(Chat),
+        #[prost(message, tag = "67")]
+        SetTeam(SetTeam),
+        #[prost(message, tag = "68")]
+        Spectate(Spectate),
+        #[prost(message, tag = "69")]
+        PlayerTeam(PlayerTeam),
+        #[prost(message, tag = "70")]
+        FetchChatHistory(FetchChatHistory),
+        #[prost(message, tag = "71")]
+        ChatHistory(ChatHistory),
+        #[prost(message, tag = "72")]
+        SessionToken(SessionToken),
+        #[prost(message, tag = "73")]
+        Resume(Resume),
+        #[prost(message, tag = "74")]
+        Standby(Standby),
+        #[prost(message, tag = "75")]
+        AreaEvent(AreaEvent),
+        #[prost(message, tag = "76")]
+        Trigger(Trigger),
+        #[prost(message, tag = "77")]
+        PointCaptured(PointCaptured),
+        #[prost(message, tag = "78")]
+        Flag(Flag),
+        #[prost(message, tag = "79")]
+        TeamScore(TeamScore),
+        #[prost(message, tag = "80")]
+        DropFlag(DropFlag),
+        #[prost(message, tag = "82")]
+        HillControl(HillControl),
+        #[prost(message, tag = "83")]
+        RoundTime(RoundTime),
+        #[prost(message, tag = "84")]
+        RoundOver(RoundOver),
+        #[prost(message, tag = "85")]
+        MapVoteStarted(MapVoteStarted),
+        #[prost(message, tag = "86")]
+        MapVote(MapVote),
+        #[prost(message, tag = "87")]
+        MapChanged(MapChanged),
+        #[prost(message, tag = "88")]
+        EntitySpawned(EntitySpawned),
+        #[prost(message, tag = "89")]
+        EntityDespawned(EntityDespawned),
+        #[prost(message, tag = "90")]
+        EntityAuthority(EntityAuthority),
+        #[prost(message, tag = "91")]
+        TransferAuthority(TransferAuthority),
+        #[prost(message, tag = "92")]
+        MoveEntity(MoveEntity),
+        #[prost(message, tag = "93")]
+        EntityMoved(EntityMoved),
+        #[prost(message, tag = "94")]
+        UseAbility(UseAbility),
+        #[prost(message, tag = "95")]
+        AbilityUsed(AbilityUsed),
+        #[prost(message, tag = "96")]
+        Experience(Experience),
+        #[prost(message, tag = "97")]
+        LevelUp(LevelUp),
+        #[prost(message, tag = "98")]
+        PlayerLevel(PlayerLevel),
+        #[prost(message, tag = "99")]
+        Interact(Interact),
+        #[prost(message, tag = "100")]
+        LootDropped(LootDropped),
+        #[prost(message, tag = "101")]
+        Inventory(Inventory),
+        #[prost(message, tag = "102")]
+        Craft(Craft),
+        #[prost(message, tag = "103")]
+        Crafted(Crafted),
+        #[prost(message, tag = "104")]
+        CraftFailed(CraftFailed),
+        #[prost(message, tag = "105")]
+        Buy(Buy),
+        #[prost(message, tag = "106")]
+        Sell(Sell),
+        #[prost(message, tag = "107")]
+        Shop(Shop),
+        #[prost(message, tag = "108")]
+        Traded(Traded),
+        #[prost(message, tag = "109")]
+        TradeFailed(TradeFailed),
+        #[prost(message, tag = "110")]
+        FindMatch(FindMatch),
+        #[prost(message, tag = "111")]
+        LeaveQueue(LeaveQueue),
+        #[prost(message, tag = "112")]
+        Queued(Queued),
+        #[prost(message, tag = "113")]
+        MatchFound(MatchFound),
+        #[prost(message, tag = "114")]
+        SnapshotAck(SnapshotAck),
+        #[prost(message, tag = "115")]
+        DeltaSnapshot(DeltaSnapshot),
+        #[prost(message, tag = "116")]
+        SimulationPaused(SimulationPaused),
+        #[prost(message, tag = "117")]
+        SimulationResumed(SimulationResumed),
+        #[prost(message, tag = "118")]
+        TimeScale(TimeScale),
+        #[prost(message, tag = "119")]
+        ListRooms(ListRooms),
+        #[prost(message, tag = "120")]
+        RoomList(RoomList),
+        #[prost(message, tag = "121")]
+        InviteToRoom(InviteToRoom),
+        #[prost(message, tag = "122")]
+        Invite(Invite),
+        #[prost(message, tag = "123")]
+        AcceptInvite(AcceptInvite),
+        #[prost(message, tag = "124")]
+        RoomHost(RoomHost),
+        #[prost(message, tag = "125")]
+        KickFromRoom(KickFromRoom),
+        #[prost(message, tag = "126")]
+        UpdateRoomSettings(UpdateRoomSettings),
+        #[prost(message, tag = "127")]
+        StartMatch(StartMatch),
+        #[prost(message, tag = "128")]
+        RoomSettingsChanged(RoomSettingsChanged),
+        #[prost(message, tag = "129")]
+        EnterWorld(EnterWorld),
+        #[prost(message, tag = "131")]
+        TerrainChanged(TerrainChanged),
+        #[prost(message, tag = "133")]
+        TerrainChunk(TerrainChunk),
+        #[prost(message, tag = "134")]
+        ForgetChunk(ForgetChunk),
+        #[prost(message, tag = "135")]
+        ModifyWorld(ModifyWorld),
+        #[prost(message, tag = "136")]
+        Objective(Objective),
+        #[prost(message, tag = "137")]
+        DifficultyChanged(DifficultyChanged),
+        #[prost(message, tag = "138")]
+        NetStats(NetStats),
+        #[prost(message, tag = "139")]
+        NetStatsAck(NetStatsAck),
+        #[prost(message, tag = "140")]
+        Identity(Identity),
+        #[prost(message, tag = "141")]
+        AbilityCasting(AbilityCasting),
+    }
+}
+
+impl From<&ClientMessage> for Envelope {
+    fn from(message: &ClientMessage) -> Self {
+        use envelope::Kind;
+        let kind = match message {
+            ClientMessage::PlayerPosition { id, x, y } => Kind::PlayerPosition(PlayerPosition {
+                id: *id as u64,
+                x: *x,
+                y: *y,
+            }),
+            ClientMessage::AssignPlayerId { id } => {
+                Kind::AssignPlayerId(AssignPlayerId { id: *id as u64 })
+            }
+            ClientMessage::UpdateMessage { id, message } => Kind::UpdateMessage(UpdateMessage {
+                id: *id as u64,
+                message: message.clone(),
+            }),
+            ClientMessage::OtherPlayerConnected { id, x, y } => {
+                Kind::OtherPlayerConnected(OtherPlayerConnected {
+                    id: *id as u64,
+                    x: *x,
+                    y: *y,
+                })
+            }
+            ClientMessage::Hello {
+                protocol_version,
+                wire_formats,
+                snapshot_formats,
+                password,
+            } => Kind::Hello(Hello {
+                protocol_version: *protocol_version,
+                wire_formats: wire_formats
+                    .iter()
+                    .map(|f| WireFormat::encode(*f))
+                    .collect(),
+                snapshot_formats: snapshot_formats
+                    .iter()
+                    .map(|f| SnapshotFormat::encode(*f))
+                    .collect(),
+                password: password.clone(),
+            }),
+            ClientMessage::Welcome {
+                id,
+                wire_format,
+                snapshot_format,
+            } => Kind::Welcome(Welcome {
+                id: *id as u64,
+                wire_format: WireFormat::encode(*wire_format),
+                snapshot_format: SnapshotFormat::encode(*snapshot_format),
+            }),
+            ClientMessage::WorldSnapshot { tick, players } => Kind::WorldSnapshot(WorldSnapshot {
+                tick: *tick,
+                players: players.iter().map(PlayerSnapshot::from).collect(),
+            }),
+            ClientMessage::FlatSnapshot { buffer } => Kind::FlatSnapshot(FlatSnapshot {
+                buffer: buffer.clone(),
+            }),
+            ClientMessage::ServerError {
+                code,
+                context,
+                localized,
+            } => Kind::ServerError(ServerError {
+                code: ErrorCode::encode(*code),
+                context: context.clone(),
+                localized: localized.as_ref().map(Into::into),
+            }),
+            ClientMessage::Disconnected {
+                reason,
+                message,
+                localized,
+            } => Kind::Disconnected(Disconnected {
+                reason: DisconnectReason::encode(*reason),
+                message: message.clone(),
+                localized: localized.as_ref().map(Into::into),
+            }),
+            ClientMessage::CreateRoom {
+                name,
+                password,
+                settings,
+            } => Kind::CreateRoom(CreateRoom {
+                name: name.clone(),
+                password: password.clone(),
+                settings: Some(settings.into()),
+            }),
+            ClientMessage::JoinRoom { room_id, password } => Kind::JoinRoom(JoinRoom {
+                room_id: *room_id,
+                password: password.clone(),
+            }),
+            ClientMessage::LeaveRoom => Kind::LeaveRoom(LeaveRoom {}),
+            ClientMessage::RoomJoined { room_id, name } => Kind::RoomJoined(RoomJoined {
+                room_id: *room_id,
+                name: name.clone(),
+            }),
+            ClientMessage::RoomLeft { room_id } => Kind::RoomLeft(RoomLeft { room_id: *room_id }),
+            ClientMessage::ConsoleCommand { line } => {
+                Kind::ConsoleCommand(ConsoleCommand { line: line.clone() })
+            }
+            ClientMessage::ConsoleOutput { text } => {
+                Kind::ConsoleOutput(ConsoleOutput { text: text.clone() })
+            }
+            ClientMessage::Announcement { text, localized } => Kind::Announcement(Announcement {
+                text: text.clone(),
+                localized: localized.as_ref().map(Into::into),
+            }),
+            ClientMessage::QueryPlayerStats { player_id } => {
+                Kind::QueryPlayerStats(QueryPlayerStats {
+                    player_id: *player_id as u64,
+                })
+            }
+            ClientMessage::PlayerStats {
+                player_id,
+                sessions,
+                playtime_secs,
+                messages_sent,
+                first_seen,
+                last_seen,
+            } => Kind::PlayerStats(PlayerStats {
+                player_id: *player_id as u64,
+                sessions: *sessions,
+                playtime_secs: *playtime_secs,
+                messages_sent: *messages_sent,
+                first_seen: *first_seen,
+                last_seen: *last_seen,
+            }),
+            ClientMessage::ArchivedSnapshot { buffer } => {
+                Kind::ArchivedSnapshot(ArchivedSnapshot {
+                    buffer: buffer.clone(),
+                })
+            }
+            ClientMessage::SetName { name } => Kind::SetName(SetName { name: name.clone() }),
+            ClientMessage::PlayerNamed { id, name, tag } => Kind::PlayerNamed(PlayerNamed {
+                id: *id as u64,
+                name: name.clone(),
+                tag: tag.clone(),
+            }),
+            ClientMessage::ChatMessage {
+                id,
+                name,
+                message,
+                channel,
+            } => Kind::ChatMessage(ChatMessage {
+                id: *id as u64,
+                name: name.clone(),
+                message: message.clone(),
+                channel: ChatChannel::encode(*channel),
+            }),
+            ClientMessage::JoinSnapshot { room_id, players } => Kind::JoinSnapshot(JoinSnapshot {
+                room_id: *room_id,
+                players: players.iter().map(NamedPlayer::from).collect(),
+            }),
+            ClientMessage::PlayerAppearance {
+                id,
+                skin,
+                color,
+                accessories,
+            } => Kind::PlayerAppearance(PlayerAppearance {
+                id: *id as u64,
+                skin: *skin,
+                color: *color,
+                accessories: accessories.clone(),
+            }),
+            ClientMessage::Register { username, password } => Kind::Register(Register {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            ClientMessage::Login { username, password } => Kind::Login(Login {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            ClientMessage::LoggedIn {
+                account_id,
+                username,
+            } => Kind::LoggedIn(LoggedIn {
+                account_id: *account_id,
+                username: username.clone(),
+            }),
+            ClientMessage::TokenLogin { token } => Kind::TokenLogin(TokenLogin {
+                token: token.clone(),
+            }),
+            ClientMessage::SteamLogin { ticket } => Kind::SteamLogin(SteamLogin {
+                ticket: ticket.clone(),
+            }),
+            ClientMessage::FriendAdd { player_id } => Kind::FriendAdd(FriendAdd {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::FriendAccept { account_id } => Kind::FriendAccept(FriendAccept {
+                account_id: *account_id,
+            }),
+            ClientMessage::FriendRemove { account_id } => Kind::FriendRemove(FriendRemove {
+                account_id: *account_id,
+            }),
+            ClientMessage::FriendList {
+                friends,
+                incoming,
+                outgoing,
+            } => Kind::FriendList(FriendList {
+                friends: friends.iter().map(Friend::from).collect(),
+                incoming: incoming.iter().map(Friend::from).collect(),
+                outgoing: outgoing.iter().map(Friend::from).collect(),
+            }),
+            ClientMessage::FriendPresence {
+                account_id,
+                online,
+                room_id,
+            } => Kind::FriendPresence(FriendPresence {
+                account_id: *account_id,
+                online: *online,
+                room_id: *room_id,
+            }),
+            ClientMessage::Whisper { target_id, text } => Kind::Whisper(Whisper {
+                target_id: *target_id as u64,
+                text: text.clone(),
+            }),
+            ClientMessage::WhisperMessage {
+                from_id,
+                to_id,
+                name,
+                text,
+            } => Kind::WhisperMessage(WhisperMessage {
+                from_id: *from_id as u64,
+                to_id: *to_id as u64,
+                name: name.clone(),
+                text: text.clone(),
+            }),
+            ClientMessage::Mute { player_id } => Kind::Mute(Mute {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::Unmute { player_id } => Kind::Unmute(Unmute {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::PartyInvite { player_id } => Kind::PartyInvite(PartyInvite {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::PartyInvited {
+                party_id,
+                from_id,
+                name,
+            } => Kind::PartyInvited(PartyInvited {
+                party_id: *party_id,
+                from_id: *from_id as u64,
+                name: name.clone(),
+            }),
+            ClientMessage::PartyAccept { party_id } => Kind::PartyAccept(PartyAccept {
+                party_id: *party_id,
+            }),
+            ClientMessage::PartyLeave => Kind::PartyLeave(PartyLeave {}),
+            ClientMessage::PartyUpdate {
+                party_id,
+                leader_id,
+                members,
+                invited,
+            } => Kind::PartyUpdate(PartyUpdate {
+                party_id: *party_id,
+                leader_id: *leader_id as u64,
+                members: members.iter().map(|&id| id as u64).collect(),
+                invited: invited.iter().map(|&id| id as u64).collect(),
+            }),
+            ClientMessage::PartyLeft { party_id } => Kind::PartyLeft(PartyLeft {
+                party_id: *party_id,
+            }),
+            ClientMessage::PartyChat { text } => Kind::PartyChat(PartyChat { text: text.clone() }),
+            ClientMessage::PartyChatMessage {
+                party_id,
+                from_id,
+                name,
+                text,
+            } => Kind::PartyChatMessage(PartyChatMessage {
+                party_id: *party_id,
+                from_id: *from_id as u64,
+                name: name.clone(),
+                text: text.clone(),
+            }),
+            ClientMessage::GuildCreate { name, tag } => Kind::GuildCreate(GuildCreate {
+                name: name.clone(),
+                tag: tag.clone(),
+            }),
+            ClientMessage::GuildInvite { player_id } => Kind::GuildInvite(GuildInvite {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::GuildInvited {
+                guild_id,
+                name,
+                from_id,
+            } => Kind::GuildInvited(GuildInvited {
+                guild_id: *guild_id,
+                name: name.clone(),
+                from_id: *from_id as u64,
+            }),
+            ClientMessage::GuildJoin { guild_id } => Kind::GuildJoin(GuildJoin {
+                guild_id: *guild_id,
+            }),
+            ClientMessage::GuildLeave => Kind::GuildLeave(GuildLeave {}),
+            ClientMessage::GuildKick { account_id } => Kind::GuildKick(GuildKick {
+                account_id: *account_id,
+            }),
+            ClientMessage::GuildSetRank { account_id, rank } => Kind::GuildSetRank(GuildSetRank {
+                account_id: *account_id,
+                rank: GuildRank::encode(*rank),
+            }),
+            ClientMessage::GuildChat { text } => Kind::GuildChat(GuildChat { text: text.clone() }),
+            ClientMessage::GuildChatMessage {
+                guild_id,
+                from_id,
+                name,
+                text,
+            } => Kind::GuildChatMessage(GuildChatMessage {
+                guild_id: *guild_id,
+                from_id: *from_id as u64,
+                name: name.clone(),
+                text: text.clone(),
+            }),
+            ClientMessage::QueryGuildRoster { guild_id } => {
+                Kind::QueryGuildRoster(QueryGuildRoster {
+                    guild_id: *guild_id,
+                })
+            }
+            ClientMessage::GuildRoster {
+                guild_id,
+                name,
+                tag,
+                members,
+            } => Kind::GuildRoster(GuildRoster {
+                guild_id: *guild_id,
+                name: name.clone(),
+                tag: tag.clone(),
+                members: members.iter().map(GuildMember::from).collect(),
+            }),
+            ClientMessage::GuildLeft { guild_id } => Kind::GuildLeft(GuildLeft {
+                guild_id: *guild_id,
+            }),
+            ClientMessage::WhisperAccount { account_id, text } => {
+                Kind::WhisperAccount(WhisperAccount {
+                    account_id: *account_id,
+                    text: text.clone(),
+                })
+            }
+            ClientMessage::MailReceived { mail } => Kind::MailReceived(MailReceived {
+                mail: mail.iter().map(Mail::from).collect(),
+            }),
+            ClientMessage::AchievementUnlocked {
+                player_id,
+                achievement_id,
+                name,
+            } => Kind::AchievementUnlocked(AchievementUnlocked {
+                player_id: *player_id as u64,
+                achievement_id: achievement_id.clone(),
+                name: name.clone(),
+            }),
+            ClientMessage::Emote { id } => Kind::Emote(Emote { id: id.clone() }),
+            ClientMessage::PlayerEmote { player_id, emote } => Kind::PlayerEmote(PlayerEmote {
+                player_id: *player_id as u64,
+                emote: emote.clone(),
+            }),
+            ClientMessage::Chat { channel, text } => Kind::Chat(Chat {
+                channel: ChatChannel::encode(*channel),
+                text: text.clone(),
+            }),
+            ClientMessage::SetTeam { team } => Kind::SetTeam(SetTeam {
+                team: team.map(u32::from),
+            }),
+            ClientMessage::Spectate { spectating } => Kind::Spectate(Spectate {
+                spectating: *spectating,
+            }),
+            ClientMessage::PlayerTeam {
+                player_id,
+                team,
+                spectating,
+            } => Kind::PlayerTeam(PlayerTeam {
+                player_id: *player_id as u64,
+                team: team.map(u32::from),
+                spectating: *spectating,
+            }),
+            ClientMessage::FetchChatHistory {
+                channel,
+                before,
+                limit,
+            } => Kind::FetchChatHistory(FetchChatHistory {
+                channel: ChatChannel::encode(*channel),
+                before: *before,
+                limit: *limit,
+            }),
+            ClientMessage::ChatHistory { channel, lines } => Kind::ChatHistory(ChatHistory {
+                channel: ChatChannel::encode(*channel),
+                lines: lines.iter().map(ChatLine::from).collect(),
+            }),
+            ClientMessage::SessionToken { token } => Kind::SessionToken(SessionToken {
+                token: token.clone(),
+            }),
+            ClientMessage::Resume { token } => Kind::Resume(Resume {
+                token: token.clone(),
+            }),
+            ClientMessage::Standby { addr } => Kind::Standby(Standby { addr: addr.clone() }),
+            ClientMessage::AreaEvent {
+                kind,
+                x,
+                y,
+                radius,
+                caught,
+            } => Kind::AreaEvent(AreaEvent {
+                kind: kind.clone(),
+                x: *x,
+                y: *y,
+                radius: *radius,
+                caught: caught.iter().map(|&id| id as u64).collect(),
+            }),
+            ClientMessage::Trigger { name, entered } => Kind::Trigger(Trigger {
+                name: name.clone(),
+                entered: *entered,
+            }),
+            ClientMessage::PointCaptured { name, team } => Kind::PointCaptured(PointCaptured {
+                name: name.clone(),
+                team: u32::from(*team),
+            }),
+            ClientMessage::Flag {
+                team,
+                carrier,
+                x,
+                y,
+                home,
+            } => Kind::Flag(Flag {
+                team: u32::from(*team),
+                carrier: carrier.map(|id| id as u64),
+                x: *x,
+                y: *y,
+                home: *home,
+            }),
+            ClientMessage::TeamScore { team, score } => Kind::TeamScore(TeamScore {
+                team: u32::from(*team),
+                score: *score,
+            }),
+            ClientMessage::DropFlag => Kind::DropFlag(DropFlag {}),
+            // Retired, with its tag reserved; it goes out empty, which
+            // receivers skip
+            ClientMessage::RoundWon { .. } => return Envelope { kind: None },
+            ClientMessage::RoundOver { winner } => Kind::RoundOver(RoundOver {
+                winner: winner.map(u32::from),
+            }),
+            ClientMessage::RoundTime {
+                remaining,
+                overtime,
+            } => Kind::RoundTime(RoundTime {
+                remaining: *remaining,
+                overtime: *overtime,
+            }),
+            ClientMessage::MapVoteStarted { maps, seconds } => {
+                Kind::MapVoteStarted(MapVoteStarted {
+                    maps: maps.clone(),
+                    seconds: *seconds,
+                })
+            }
+            ClientMessage::MapVote { choice } => Kind::MapVote(MapVote { choice: *choice }),
+            ClientMessage::MapChanged { name } => {
+                Kind::MapChanged(MapChanged { name: name.clone() })
+            }
+            ClientMessage::EntitySpawned {
+                id,
+                kind,
+                x,
+                y,
+                metadata,
+            } => Kind::EntitySpawned(EntitySpawned {
+                id: *id,
+                kind: kind.clone(),
+                x: *x,
+                y: *y,
+                metadata: metadata.clone(),
+            }),
+            ClientMessage::EntityDespawned { id } => {
+                Kind::EntityDespawned(EntityDespawned { id: *id })
+            }
+            ClientMessage::EntityAuthority { id, owner } => {
+                Kind::EntityAuthority(EntityAuthority {
+                    id: *id,
+                    owner: owner.map(|id| id as u64),
+                })
+            }
+            ClientMessage::TransferAuthority { id, to } => {
+                Kind::TransferAuthority(TransferAuthority {
+                    id: *id,
+                    to: to.map(|id| id as u64),
+                })
+            }
+            ClientMessage::MoveEntity { id, x, y } => Kind::MoveEntity(MoveEntity {
+                id: *id,
+                x: *x,
+                y: *y,
+            }),
+            ClientMessage::EntityMoved { id, x, y } => Kind::EntityMoved(EntityMoved {
+                id: *id,
+                x: *x,
+                y: *y,
+            }),
+            ClientMessage::UseAbility { name, x, y } => Kind::UseAbility(UseAbility {
+                name: name.clone(),
+                x: *x,
+                y: *y,
+            }),
+            ClientMessage::AbilityUsed {
+                name,
+                ready_in,
+                energy,
+            } => Kind::AbilityUsed(AbilityUsed {
+                name: name.clone(),
+                ready_in: *ready_in,
+                energy: *energy,
+            }),
+            ClientMessage::Experience { xp, level, next } => Kind::Experience(Experience {
+                xp: *xp,
+                level: *level,
+                next: *next,
+            }),
+            ClientMessage::LevelUp { player_id, level } => Kind::LevelUp(LevelUp {
+                player_id: *player_id as u64,
+                level: *level,
+            }),
+            ClientMessage::PlayerLevel { id, level } => Kind::PlayerLevel(PlayerLevel {
+                id: *id as u64,
+                level: *level,
+            }),
+            ClientMessage::Interact { id } => Kind::Interact(Interact { id: *id }),
+            ClientMessage::LootDropped {
+                x,
+                y,
+                items,
+                player_id,
+            } => Kind::LootDropped(LootDropped {
+                x: *x,
+                y: *y,
+                items: items.iter().map(ItemGrant::from).collect(),
+                player_id: player_id.map(|id| id as u64),
+            }),
+            ClientMessage::Inventory { items } => Kind::Inventory(Inventory {
+                items: items.iter().map(ItemGrant::from).collect(),
+            }),
+            ClientMessage::Craft { recipe_id, nonce } => Kind::Craft(Craft {
+                recipe_id: recipe_id.clone(),
+                nonce: *nonce,
+            }),
+            ClientMessage::Crafted { recipe_id, items } => Kind::Crafted(Crafted {
+                recipe_id: recipe_id.clone(),
+                items: items.iter().map(ItemGrant::from).collect(),
+            }),
+            ClientMessage::CraftFailed {
+                recipe_id,
+                code,
+                missing,
+            } => Kind::CraftFailed(CraftFailed {
+                recipe_id: recipe_id.clone(),
+                code: CraftError::encode(*code),
+                missing: missing.iter().map(ItemGrant::from).collect(),
+            }),
+            ClientMessage::Buy {
+                entity,
+                item,
+                quantity,
+                nonce,
+            } => Kind::Buy(Buy {
+                entity: *entity,
+                item: item.clone(),
+                quantity: *quantity,
+                nonce: *nonce,
+            }),
+            ClientMessage::Sell {
+                entity,
+                item,
+                quantity,
+                id,
+                nonce,
+            } => Kind::Sell(Sell {
+                entity: *entity,
+                item: item.clone(),
+                quantity: *quantity,
+                id: id.map(|id| id.to_string()),
+                nonce: *nonce,
+            }),
+            ClientMessage::Shop {
+                entity,
+                currency,
+                offers,
+            } => Kind::Shop(Shop {
+                entity: *entity,
+                currency: currency.clone(),
+                offers: offers
+                    .iter()
+                    .map(|offer| ShopOffer {
+                        item: offer.item.clone(),
+                        buy: offer.buy,
+                        sell: offer.sell,
+                    })
+                    .collect(),
+            }),
+            ClientMessage::Traded {
+                entity,
+                item,
+                quantity,
+                price,
+                selling,
+            } => Kind::Traded(Traded {
+                entity: *entity,
+                item: item.clone(),
+                quantity: *quantity,
+                price: *price,
+                selling: *selling,
+            }),
+            ClientMessage::TradeFailed {
+                entity,
+                item,
+                code,
+                missing,
+            } => Kind::TradeFailed(TradeFailed {
+                entity: *entity,
+                item: item.clone(),
+                code: TradeError::encode(*code),
+                missing: missing.iter().map(ItemGrant::from).collect(),
+            }),
+            ClientMessage::FindMatch => Kind::FindMatch(FindMatch {}),
+            ClientMessage::LeaveQueue => Kind::LeaveQueue(LeaveQueue {}),
+            ClientMessage::Queued {
+                position,
+                open_slots,
+            } => Kind::Queued(Queued {
+                position: *position,
+                open_slots: *open_slots,
+            }),
+            ClientMessage::MatchFound { room_id, team } => Kind::MatchFound(MatchFound {
+                room_id: *room_id,
+                team: u32::from(*team),
+            }),
+            ClientMessage::SnapshotAck { tick } => Kind::SnapshotAck(SnapshotAck { tick: *tick }),
+            ClientMessage::DeltaSnapshot {
+                tick,
+                baseline,
+                players,
+                removed,
+            } => Kind::DeltaSnapshot(DeltaSnapshot {
+                tick: *tick,
+                baseline: *baseline,
+                players: players.iter().map(PlayerSnapshot::from).collect(),
+                removed: removed.iter().map(|&id| id as u64).collect(),
+            }),
+            ClientMessage::SimulationPaused => Kind::SimulationPaused(SimulationPaused {}),
+            ClientMessage::SimulationResumed => Kind::SimulationResumed(SimulationResumed {}),
+            ClientMessage::TimeScale { scale } => Kind::TimeScale(TimeScale { scale: *scale }),
+            ClientMessage::ListRooms {
+                filters,
+                after,
+                limit,
+            } => Kind::ListRooms(ListRooms {
+                filters: Some(filters.into()),
+                after: *after,
+                limit: *limit,
+            }),
+            ClientMessage::RoomList { rooms, more } => Kind::RoomList(RoomList {
+                rooms: rooms.iter().map(RoomSummary::from).collect(),
+                more: *more,
+            }),
+            ClientMessage::InviteToRoom { player_id } => Kind::InviteToRoom(InviteToRoom {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::Invite {
+                room_id,
+                name,
+                from_id,
+                from_name,
+            } => Kind::Invite(Invite {
+                room_id: *room_id,
+                name: name.clone(),
+                from_id: *from_id as u64,
+                from_name: from_name.clone(),
+            }),
+            ClientMessage::AcceptInvite { room_id } => {
+                Kind::AcceptInvite(AcceptInvite { room_id: *room_id })
+            }
+            ClientMessage::RoomHost { room_id, player_id } => Kind::RoomHost(RoomHost {
+                room_id: *room_id,
+                player_id: player_id.map(|id| id as u64),
+            }),
+            ClientMessage::KickFromRoom { player_id } => Kind::KickFromRoom(KickFromRoom {
+                player_id: *player_id as u64,
+            }),
+            ClientMessage::UpdateRoomSettings { settings } => {
+                Kind::UpdateRoomSettings(UpdateRoomSettings {
+                    settings: Some(settings.into()),
+                })
+            }
+            ClientMessage::StartMatch => Kind::StartMatch(StartMatch {}),
+            ClientMessage::RoomSettingsChanged { room_id, settings } => {
+                Kind::RoomSettingsChanged(RoomSettingsChanged {
+                    room_id: *room_id,
+                    settings: Some(settings.into()),
+                })
+            }
+            ClientMessage::EnterWorld { name } => {
+                Kind::EnterWorld(EnterWorld { name: name.clone() })
+            }
+            // Retired like `RoundWon`
+            ClientMessage::EditTerrain { .. } => return Envelope { kind: None },
+            ClientMessage::ModifyWorld { position, change } => Kind::ModifyWorld(ModifyWorld {
+                position: Some((*position).into()),
+                change: Some(change.into()),
+            }),
+            ClientMessage::TerrainChanged { x, y, value } => Kind::TerrainChanged(TerrainChanged {
+                x: *x,
+                y: *y,
+                value: value.clone(),
+            }),
+            // Retired like `RoundWon`
+            ClientMessage::Terrain { .. } => return Envelope { kind: None },
+            ClientMessage::TerrainChunk { x, y, cells } => Kind::TerrainChunk(TerrainChunk {
+                x: *x,
+                y: *y,
+                cells: cells.iter().map(TerrainCell::from).collect(),
+            }),
+            ClientMessage::ForgetChunk { x, y } => Kind::ForgetChunk(ForgetChunk { x: *x, y: *y }),
+            ClientMessage::HillControl {
+                name,
+                team,
+                contested,
+            } => Kind::HillControl(HillControl {
+                name: name.clone(),
+                team: team.map(u32::from),
+                contested: *contested,
+            }),
+            ClientMessage::Objective { name, text, state } => Kind::Objective(Objective {
+                name: name.clone(),
+                text: text.clone(),
+                state: ObjectiveState::encode(*state),
+            }),
+            ClientMessage::DifficultyChanged { difficulty } => {
+                Kind::DifficultyChanged(DifficultyChanged {
+                    health: difficulty.health,
+                    damage: difficulty.damage,
+                    spawn_rate: difficulty.spawn_rate,
+                })
+            }
+            ClientMessage::NetStats {
+                sequence,
+                bytes_in,
+                bytes_out,
+                rtt,
+                loss,
+            } => Kind::NetStats(NetStats {
+                sequence: *sequence,
+                bytes_in: *bytes_in,
+                bytes_out: *bytes_out,
+                rtt: *rtt,
+                loss: *loss,
+            }),
+            ClientMessage::NetStatsAck { sequence } => Kind::NetStatsAck(NetStatsAck {
+                sequence: *sequence,
+            }),
+            ClientMessage::Identity { uuid } => Kind::Identity(Identity {
+                uuid: uuid.to_string(),
+            }),
+            ClientMessage::AbilityCasting { name, cast_seconds } => {
+                Kind::AbilityCasting(AbilityCasting {
+                    name: name.clone(),
+                    cast_seconds: *cast_seconds,
+                })
+            }
+        };
+        Envelope { kind: Some(kind) }
+    }
+}
+
+impl From<envelope::Kind> for ClientMessage {
+    fn from(kind: envelope::Kind) -> Self {
+        use envelope::Kind;
+        match kind {
+            Kind::PlayerPosition(m) => ClientMessage::PlayerPosition {
+                id: m.id as usize,
+                x: m.x,
+                y: m.y,
+            },
+            Kind::AssignPlayerId(m) => ClientMessage::AssignPlayerId { id: m.id as usize },
+            Kind::UpdateMessage(m) => ClientMessage::UpdateMessage {
+                id: m.id as usize,
+                message: m.message,
+            },
+            Kind::OtherPlayerConnected(m) => ClientMessage::OtherPlayerConnected {
+                id: m.id as usize,
+                x: m.x,
+                y: m.y,
+            },
+            Kind::Hello(m) => ClientMessage::Hello {
+                protocol_version: m.protocol_version,
+                wire_formats: m.wire_formats.into_iter().map(WireFormat::decode).collect(),
+                snapshot_formats: m
+                    .snapshot_formats
+                    .into_iter()
+                    .map(SnapshotFormat::decode)
+                    .collect(),
+                password: m.password,
+            },
+            Kind::Welcome(m) => ClientMessage::Welcome {
+                id: m.id as usize,
+                wire_format: WireFormat::decode(m.wire_format),
+                snapshot_format: SnapshotFormat::decode(m.snapshot_format),
+            },
+            Kind::WorldSnapshot(m) => ClientMessage::WorldSnapshot {
+                tick: m.tick,
+                players: m.players.into_iter().map(Into::into).collect(),
+            },
+            Kind::FlatSnapshot(m) => ClientMessage::FlatSnapshot { buffer: m.buffer },
+            Kind::ServerError(m) => ClientMessage::ServerError {
+                code: ErrorCode::decode(m.code),
+                context: m.context,
+                localized: m.localized.map(Into::into),
+            },
+            Kind::Disconnected(m) => ClientMessage::Disconnected {
+                reason: DisconnectReason::decode(m.reason),
+                message: m.message,
+                localized: m.localized.map(Into::into),
+            },
+            Kind::CreateRoom(m) => ClientMessage::CreateRoom {
+                name: m.name,
+                password: m.password,
+                settings: m.settings.map(Into::into).unwrap_or_default(),
+            },
+            Kind::JoinRoom(m) => ClientMessage::JoinRoom {
+                room_id: m.room_id,
+                password: m.password,
+            },
+            Kind::LeaveRoom(_) => ClientMessage::LeaveRoom,
+            Kind::RoomJoined(m) => ClientMessage::RoomJoined {
+                room_id: m.room_id,
+                name: m.name,
+            },
+            Kind::RoomLeft(m) => ClientMessage::RoomLeft { room_id: m.room_id },
+            Kind::ConsoleCommand(m) => ClientMessage::ConsoleCommand { line: m.line },
+            Kind::ConsoleOutput(m) => ClientMessage::ConsoleOutput { text: m.text },
+            Kind::Announcement(m) => ClientMessage::Announcement {
+                text: m.text,
+                localized: m.localized.map(Into::into),
+            },
+            Kind::QueryPlayerStats(m) => ClientMessage::QueryPlayerStats {
+                player_id: m.player_id as usize,
+            },
+            Kind::PlayerStats(m) => ClientMessage::PlayerStats {
+                player_id: m.player_id as usize,
+                sessions: m.sessions,
+                playtime_secs: m.playtime_secs,
+                messages_sent: m.messages_sent,
+                first_seen: m.first_seen,
+                last_seen: m.last_seen,
+            },
+            Kind::ArchivedSnapshot(m) => ClientMessage::ArchivedSnapshot { buffer: m.buffer },
+            Kind::SetName(m) => ClientMessage::SetName { name: m.name },
+            Kind::PlayerNamed(m) => ClientMessage::PlayerNamed {
+                id: m.id as usize,
+                name: m.name,
+                tag: m.tag,
+            },
+            Kind::ChatMessage(m) => ClientMessage::ChatMessage {
+                id: m.id as usize,
+                name: m.name,
+                message: m.message,
+                channel: ChatChannel::decode(m.channel),
+            },
+            Kind::JoinSnapshot(m) => ClientMessage::JoinSnapshot {
+                room_id: m.room_id,
+                players: m.players.into_iter().map(Into::into).collect(),
+            },
+            Kind::PlayerAppearance(m) => ClientMessage::PlayerAppearance {
+                id: m.id as usize,
+                skin: m.skin,
+                color: m.color,
+                accessories: m.accessories,
+            },
+            Kind::Register(m) => ClientMessage::Register {
+                username: m.username,
+                password: m.password,
+            },
+            Kind::Login(m) => ClientMessage::Login {
+                username: m.username,
+                password: m.password,
+            },
+            Kind::LoggedIn(m) => ClientMessage::LoggedIn {
+                account_id: m.account_id,
+                username: m.username,
+            },
+            Kind::TokenLogin(m) => ClientMessage::TokenLogin { token: m.token },
+            Kind::SteamLogin(m) => ClientMessage::SteamLogin { ticket: m.ticket },
+            Kind::FriendAdd(m) => ClientMessage::FriendAdd {
+                player_id: m.player_id as usize,
+            },
+            Kind::FriendAccept(m) => ClientMessage::FriendAccept {
+                account_id: m.account_id,
+            },
+            Kind::FriendRemove(m) => ClientMessage::FriendRemove {
+                account_id: m.account_id,
+            },
+            Kind::FriendList(m) => ClientMessage::FriendList {
+                friends: m.friends.into_iter().map(Into::into).collect(),
+                incoming: m.incoming.into_iter().map(Into::into).collect(),
+                outgoing: m.outgoing.into_iter().map(Into::into).collect(),
+            },
+            Kind::FriendPresence(m) => ClientMessage::FriendPresence {
+                account_id: m.account_id,
+                online: m.online,
+                room_id: m.room_id,
+            },
+            Kind::Whisper(m) => ClientMessage::Whisper {
+                target_id: m.target_id as usize,
+                text: m.text,
+            },
+            Kind::WhisperMessage(m) => ClientMessage::WhisperMessage {
+                from_id: m.from_id as usize,
+                to_id: m.to_id as usize,
+                name: m.name,
+                text: m.text,
+            },
+            Kind::Mute(m) => ClientMessage::Mute {
+                player_id: m.player_id as usize,
+            },
+            Kind::Unmute(m) => ClientMessage::Unmute {
+                player_id: m.player_id as usize,
+            },
+            Kind::PartyInvite(m) => ClientMessage::PartyInvite {
+                player_id: m.player_id as usize,
+            },
+            Kind::PartyInvited(m) => ClientMessage::PartyInvited {
+                party_id: m.party_id,
+                from_id: m.from_id as usize,
+                name: m.name,
+            },
+            Kind::PartyAccept(m) => ClientMessage::PartyAccept {
+                party_id: m.party_id,
+            },
+            Kind::PartyLeave(_) => ClientMessage::PartyLeave,
+            Kind::PartyUpdate(m) => ClientMessage::PartyUpdate {
+                party_id: m.party_id,
+                leader_id: m.leader_id as usize,
+                members: m.members.into_iter().map(|id| id as usize).collect(),
+                invited: m.invited.into_iter().map(|id| id as usize).collect(),
+            },
+            Kind::PartyLeft(m) => ClientMessage::PartyLeft {
+                party_id: m.party_id,
+            },
+            Kind::PartyChat(m) => ClientMessage::PartyChat { text: m.text },
+            Kind::PartyChatMessage(m) => ClientMessage::PartyChatMessage {
+                party_id: m.party_id,
+                from_id: m.from_id as usize,
+                name: m.name,
+                text: m.text,
+            },
+            Kind::GuildCreate(m) => ClientMessage::GuildCreate {
+                name: m.name,
+                tag: m.tag,
+            },
+            Kind::GuildInvite(m) => ClientMessage::GuildInvite {
+                player_id: m.player_id as usize,
+            },
+            Kind::GuildInvited(m) => ClientMessage::GuildInvited {
+                guild_id: m.guild_id,
+                name: m.name,
+                from_id: m.from_id as usize,
+            },
+            Kind::GuildJoin(m) => ClientMessage::GuildJoin {
+                guild_id: m.guild_id,
+            },
+            Kind::GuildLeave(_) => ClientMessage::GuildLeave,
+            Kind::GuildKick(m) => ClientMessage::GuildKick {
+                account_id: m.account_id,
+            },
+            Kind::GuildSetRank(m) => ClientMessage::GuildSetRank {
+                account_id: m.account_id,
+                rank: GuildRank::decode(m.rank),
+            },
+            Kind::GuildChat(m) => ClientMessage::GuildChat { text: m.text },
+            Kind::GuildChatMessage(m) => ClientMessage::GuildChatMessage {
+                guild_id: m.guild_id,
+                from_id: m.from_id as usize,
+                name: m.name,
+                text: m.text,
+            },
+            Kind::QueryGuildRoster(m) => ClientMessage::QueryGuildRoster {
+                guild_id: m.guild_id,
+            },
+            Kind::GuildRoster(m) => ClientMessage::GuildRoster {
+                guild_id: m.guild_id,
+                name: m.name,
+                tag: m.tag,
+                members: m.members.into_iter().map(Into::into).collect(),
+            },
+            Kind::GuildLeft(m) => ClientMessage::GuildLeft {
+                guild_id: m.guild_id,
+            },
+            Kind::WhisperAccount(m) => ClientMessage::WhisperAccount {
+                account_id: m.account_id,
+                text: m.text,
+            },
+            Kind::MailReceived(m) => ClientMessage::MailReceived {
+                mail: m.mail.into_iter().map(Into::into).collect(),
+            },
+            Kind::AchievementUnlocked(m) => ClientMessage::AchievementUnlocked {
+                player_id: m.player_id as usize,
+                achievement_id: m.achievement_id,
+                name: m.name,
+            },
+            Kind::Emote(m) => ClientMessage::Emote { id: m.id },
+            Kind::PlayerEmote(m) => ClientMessage::PlayerEmote {
+                player_id: m.player_id as usize,
+                emote: m.emote,
+            },
+            Kind::Chat(m) => ClientMessage::Chat {
+                channel: ChatChannel::decode(m.channel),
+                text: m.text,
+            },
+            Kind::SetTeam(m) => ClientMessage::SetTeam {
+                team: m.team.map(|team| team as TeamId),
+            },
+            Kind::Spectate(m) => ClientMessage::Spectate {
+                spectating: m.spectating,
+            },
+            Kind::PlayerTeam(m) => ClientMessage::PlayerTeam {
+                player_id: m.player_id as usize,
+                team: m.team.map(|team| team as TeamId),
+                spectating: m.spectating,
+            },
+            Kind::FetchChatHistory(m) => ClientMessage::FetchChatHistory {
+                channel: ChatChannel::decode(m.channel),
+                before: m.before,
+                limit: m.limit,
+            },
+            Kind::ChatHistory(m) => ClientMessage::ChatHistory {
+                channel: ChatChannel::decode(m.channel),
+                lines: m.lines.into_iter().map(Into::into).collect(),
+            },
+            Kind::SessionToken(m) => ClientMessage::SessionToken { token: m.token },
+            Kind::Resume(m) => ClientMessage::Resume { token: m.token },
+            Kind::Standby(m) => ClientMessage::Standby { addr: m.addr },
+            Kind::AreaEvent(m) => ClientMessage::AreaEvent {
+                kind: m.kind,
+                x: m.x,
+                y: m.y,
+                radius: m.radius,
+                caught: m.caught.into_iter().map(|id| id as usize).collect(),
+            },
+            Kind::Trigger(m) => ClientMessage::Trigger {
+                name: m.name,
+                entered: m.entered,
+            },
+            Kind::PointCaptured(m) => ClientMessage::PointCaptured {
+                name: m.name,
+                team: m.team as TeamId,
+            },
+            Kind::Flag(m) => ClientMessage::Flag {
+                team: m.team as TeamId,
+                carrier: m.carrier.map(|id| id as usize),
+                x: m.x,
+                y: m.y,
+                home: m.home,
+            },
+            Kind::TeamScore(m) => ClientMessage::TeamScore {
+                team: m.team as TeamId,
+                score: m.score,
+            },
+            Kind::DropFlag(_) => ClientMessage::DropFlag,
+            Kind::RoundOver(m) => ClientMessage::RoundOver {
+                winner: m.winner.map(|team| team as TeamId),
+            },
+            Kind::RoundTime(m) => ClientMessage::RoundTime {
+                remaining: m.remaining,
+                overtime: m.overtime,
+            },
+            Kind::MapVoteStarted(m) => ClientMessage::MapVoteStarted {
+                maps: m.maps,
+                seconds: m.seconds,
+            },
+            Kind::MapVote(m) => ClientMessage::MapVote { choice: m.choice },
+            Kind::MapChanged(m) => ClientMessage::MapChanged { name: m.name },
+            Kind::EntitySpawned(m) => ClientMessage::EntitySpawned {
+                id: m.id,
+                kind: m.kind,
+                x: m.x,
+                y: m.y,
+                metadata: m.metadata,
+            },
+            Kind::EntityDespawned(m) => ClientMessage::EntityDespawned { id: m.id },
+            Kind::EntityAuthority(m) => ClientMessage::EntityAuthority {
+                id: m.id,
+                owner: m.owner.map(|id| id as usize),
+            },
+            Kind::TransferAuthority(m) => ClientMessage::TransferAuthority {
+                id: m.id,
+                to: m.to.map(|id| id as usize),
+            },
+            Kind::MoveEntity(m) => ClientMessage::MoveEntity {
+                id: m.id,
+                x: m.x,
+                y: m.y,
+            },
+            Kind::EntityMoved(m) => ClientMessage::EntityMoved {
+                id: m.id,
+                x: m.x,
+                y: m.y,
+            },
+            Kind::UseAbility(m) => ClientMessage::UseAbility {
+                name: m.name,
+                x: m.x,
+                y: m.y,
+            },
+            Kind::AbilityUsed(m) => ClientMessage::AbilityUsed {
+                name: m.name,
+                ready_in: m.ready_in,
+                energy: m.energy,
+            },
+            Kind::Experience(m) => ClientMessage::Experience {
+                xp: m.xp,
+                level: m.level,
+                next: m.next,
+            },
+            Kind::LevelUp(m) => ClientMessage::LevelUp {
+                player_id: m.player_id as usize,
+                level: m.level,
+            },
+            Kind::PlayerLevel(m) => ClientMessage::PlayerLevel {
+                id: m.id as usize,
+                level: m.level,
+            },
+            Kind::Interact(m) => ClientMessage::Interact { id: m.id },
+            Kind::LootDropped(m) => ClientMessage::LootDropped {
+                x: m.x,
+                y: m.y,
+                items: m.items.into_iter().map(Into::into).collect(),
+                player_id: m.player_id.map(|id| id as usize),
+            },
+            Kind::Inventory(m) => ClientMessage::Inventory {
+                items: m.items.into_iter().map(Into::into).collect(),
+            },
+            Kind::Craft(m) => ClientMessage::Craft {
+                recipe_id: m.recipe_id,
+                nonce: m.nonce,
+            },
+            Kind::Crafted(m) => ClientMessage::Crafted {
+                recipe_id: m.recipe_id,
+                items: m.items.into_iter().map(Into::into).collect(),
+            },
+            Kind::CraftFailed(m) => ClientMessage::CraftFailed {
+                recipe_id: m.recipe_id,
+                code: CraftError::decode(m.code),
+                missing: m.missing.into_iter().map(Into::into).collect(),
+            },
+            Kind::Buy(m) => ClientMessage::Buy {
+                entity: m.entity,
+                item: m.item,
+                quantity: m.quantity,
+                nonce: m.nonce,
+            },
+            Kind::Sell(m) => ClientMessage::Sell {
+                entity: m.entity,
+                item: m.item,
+                quantity: m.quantity,
+                id: m.id.as_deref().map(decode_item_id),
+                nonce: m.nonce,
+            },
+            Kind::Shop(m) => ClientMessage::Shop {
+                entity: m.entity,
+                currency: m.currency,
+                offers: m
+                    .offers
+                    .into_iter()
+                    .map(|offer| shops::ShopOffer {
+                        item: offer.item,
+                        buy: offer.buy,
+                        sell: offer.sell,
+                    })
+                    .collect(),
+            },
+            Kind::Traded(m) => ClientMessage::Traded {
+                entity: m.entity,
+                item: m.item,
+                quantity: m.quantity,
+                price: m.price,
+                selling: m.selling,
+            },
+            Kind::TradeFailed(m) => ClientMessage::TradeFailed {
+                entity: m.entity,
+                item: m.item,
+                code: TradeError::decode(m.code),
+                missing: m.missing.into_iter().map(Into::into).collect(),
+            },
+            Kind::FindMatch(_) => ClientMessage::FindMatch,
+            Kind::LeaveQueue(_) => ClientMessage::LeaveQueue,
+            Kind::Queued(m) => ClientMessage::Queued {
+                position: m.position,
+                open_slots: m.open_slots,
+            },
+            Kind::MatchFound(m) => ClientMessage::MatchFound {
+                room_id: m.room_id,
+                team: m.team as TeamId,
+            },
+            Kind::SnapshotAck(m) => ClientMessage::SnapshotAck { tick: m.tick },
+            Kind::DeltaSnapshot(m) => ClientMessage::DeltaSnapshot {
+                tick: m.tick,
+                baseline: m.baseline,
+                players: m.players.into_iter().map(Into::into).collect(),
+                removed: m.removed.into_iter().map(|id| id as usize).collect(),
+            },
+            Kind::SimulationPaused(_) => ClientMessage::SimulationPaused,
+            Kind::SimulationResumed(_) => ClientMessage::SimulationResumed,
+            Kind::TimeScale(m) => ClientMessage::TimeScale { scale: m.scale },
+            Kind::ListRooms(m) => ClientMessage::ListRooms {
+                filters: m.filters.map(Into::into).unwrap_or_default(),
+                after: m.after,
+                limit: m.limit,
+            },
+            Kind::RoomList(m) => ClientMessage::RoomList {
+                rooms: m.rooms.into_iter().map(Into::into).collect(),
+                more: m.more,
+            },
+            Kind::InviteToRoom(m) => ClientMessage::InviteToRoom {
+                player_id: m.player_id as usize,
+            },
+            Kind::Invite(m) => ClientMessage::Invite {
+                room_id: m.room_id,
+                name: m.name,
+                from_id: m.from_id as usize,
+                from_name: m.from_name,
+            },
+            Kind::AcceptInvite(m) => ClientMessage::AcceptInvite { room_id: m.room_id },
+            Kind::RoomHost(m) => ClientMessage::RoomHost {
+                room_id: m.room_id,
+                player_id: m.player_id.map(|id| id as usize),
+            },
+            Kind::KickFromRoom(m) => ClientMessage::KickFromRoom {
+                player_id: m.player_id as usize,
+            },
+            Kind::UpdateRoomSettings(m) => ClientMessage::UpdateRoomSettings {
+                settings: m.settings.map(Into::into).unwrap_or_default(),
+            },
+            Kind::StartMatch(_) => ClientMessage::StartMatch,
+            Kind::RoomSettingsChanged(m) => ClientMessage::RoomSettingsChanged {
+                room_id: m.room_id,
+                settings: m.settings.map(Into::into).unwrap_or_default(),
+            },
+            Kind::EnterWorld(m) => ClientMessage::EnterWorld { name: m.name },
+            Kind::ModifyWorld(m) => ClientMessage::ModifyWorld {
+                position: m.position.map(Into::into).unwrap_or_default(),
+                change: m.change.map(Into::into).unwrap_or_default(),
+            },
+            Kind::TerrainChanged(m) => ClientMessage::TerrainChanged {
+                x: m.x,
+                y: m.y,
+                value: m.value,
+            },
+            Kind::TerrainChunk(m) => ClientMessage::TerrainChunk {
+                x: m.x,
+                y: m.y,
+                cells: m.cells.into_iter().map(Into::into).collect(),
+            },
+            Kind::ForgetChunk(m) => ClientMessage::ForgetChunk { x: m.x, y: m.y },
+            Kind::HillControl(m) => ClientMessage::HillControl {
+                name: m.name,
+                team: m.team.map(|team| team as TeamId),
+                contested: m.contested,
+            },
+            Kind::Objective(m) => ClientMessage::Objective {
+                name: m.name,
+                text: m.text,
+                state: ObjectiveState::decode(m.state),
+            },
+            Kind::DifficultyChanged(m) => ClientMessage::DifficultyChanged {
+                difficulty: difficulty::Difficulty {
+                    health: m.health,
+                    damage: m.damage,
+                    spawn_rate: m.spawn_rate,
+                },
+            },
+            Kind::NetStats(m) => ClientMessage::NetStats {
+                sequence: m.sequence,
+                bytes_in: m.bytes_in,
+                bytes_out: m.bytes_out,
+                rtt: m.rtt,
+                loss: m.loss,
+            },
+            Kind::NetStatsAck(m) => ClientMessage::NetStatsAck {
+                sequence: m.sequence,
+            },
+            Kind::Identity(m) => ClientMessage::Identity {
+                uuid: decode_uuid(&m.uuid),
+            },
+            Kind::AbilityCasting(m) => ClientMessage::AbilityCasting {
+                name: m.name,
+                cast_seconds: m.cast_seconds,
+            },
+        }
+    }
+}
+
+pub fn encode(message: &ClientMessage) -> Vec<u8> {
+    Envelope::from(message).encode_to_vec()
+}
+
+// Appends to `buffer`; a `Vec` never runs out of room, so this can't fail
+pub fn encode_into(message: &ClientMessage, buffer: &mut Vec<u8>) {
+    Envelope::from(message).encode_raw(buffer);
+}
+
+pub fn decode(data: &[u8]) -> Result<Option<ClientMessage>, prost::DecodeError> {
+    let envelope = Envelope::decode(data)?;
+    Ok(envelope.kind.map(ClientMessage::from))
+}
@@ -0,0 +1,80 @@
+// Slash commands: chat lines starting with `/`, like `/who` or `/tp 3`, that
+// the server runs rather than sends on. The server has a few of its own; the
+// game hosting it adds more with `ServerHandle::register_command`, each with
+// its usage, a line of help for `/help` and the role it needs, if any. A line
+// starting with `//` is chat, sent with one slash.
+use crate::roles::Role;
+use serde::Serialize;
+
+const MAX_NAME_CHARS: usize = 24;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    // Without the slash: lowercase letters, digits, `-` and `_`
+    pub name: String,
+    // What follows the name, like `<player>` or `[x y]`
+    pub usage: String,
+    pub help: String,
+    // `None` lets anyone run it
+    pub role: Option<Role>,
+}
+
+impl CommandSpec {
+    pub fn new(name: &str, usage: &str, help: &str, role: Option<Role>) -> Self {
+        CommandSpec {
+            name: name.to_string(),
+            usage: usage.to_string(),
+            help: help.to_string(),
+            role,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+        if self.name.is_empty()
+            || self.name.chars().count() > MAX_NAME_CHARS
+            || !self.name.chars().all(valid)
+        {
+            return Err(format!(
+                "command name `{}` must be 1 to {} lowercase letters, digits, `-` or `_`",
+                self.name, MAX_NAME_CHARS
+            ));
+        }
+        if self.help.trim().is_empty() {
+            return Err(format!("command /{} needs help text", self.name));
+        }
+        Ok(())
+    }
+
+    // Whether a player with `role` may run it
+    pub fn allows(&self, role: Option<Role>) -> bool {
+        role >= self.role
+    }
+
+    // `/tp <player>|<x> <y>`, as `/help` shows it
+    pub fn synopsis(&self) -> String {
+        match self.usage.is_empty() {
+            true => format!("/{}", self.name),
+            false => format!("/{} {}", self.name, self.usage),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Chat(String),
+    // The name, lowercased, and the words after it
+    Command(String, Vec<String>),
+}
+
+pub fn parse(text: &str) -> Line {
+    let Some(rest) = text.strip_prefix('/') else {
+        return Line::Chat(text.to_string());
+    };
+    if rest.starts_with('/') {
+        return Line::Chat(rest.to_string());
+    }
+    let mut words = rest.split_whitespace();
+    let name = words.next().unwrap_or("").to_lowercase();
+    Line::Command(name, words.map(str::to_string).collect())
+}
@@ -0,0 +1,564 @@
+use crate::abilities::AbilityConfig;
+use crate::accounts::AccountConfig;
+use crate::achievements::AchievementConfig;
+use crate::actions::ActionConfig;
+use crate::appearance::AppearanceConfig;
+use crate::areas::AreaConfig;
+use crate::bots::BotConfig;
+use crate::chaos::ChaosConfig;
+use crate::chat::ChatHistoryConfig;
+use crate::cidr::IpRange;
+use crate::cluster::ClusterConfig;
+use crate::codec::{DecodeLimits, WireFormat};
+use crate::crafting::CraftingConfig;
+use crate::deltas::DeltaConfig;
+use crate::dice::DiceConfig;
+use crate::emotes::EmoteConfig;
+use crate::entities::EntityConfig;
+use crate::friends::FriendConfig;
+use crate::guests::GuestConfig;
+use crate::guilds::GuildConfig;
+use crate::handoff::{HandoffConfig, MigrationConfig};
+use crate::health::HealthConfig;
+use crate::inventory::InventoryConfig;
+use crate::jwt::JwtConfig;
+use crate::locale::LocaleConfig;
+use crate::loot::LootConfig;
+use crate::mail::MailConfig;
+use crate::matchmaking::MatchmakingConfig;
+use crate::names::NameConfig;
+use crate::net_stats::NetStatsConfig;
+use crate::overload::OverloadConfig;
+use crate::parties::PartyConfig;
+use crate::progression::ProgressionConfig;
+use crate::quic::QuicConfig;
+use crate::rates::UpdateRateConfig;
+use crate::replication::ReplicationConfig;
+use crate::room_settings::RoomSettingsConfig;
+use crate::rotation::RotationConfig;
+use crate::shards::DeadReckoningConfig;
+use crate::shops::ShopConfig;
+use crate::sight::SightConfig;
+use crate::steam::SteamConfig;
+use crate::throttle::ThrottleConfig;
+use crate::trails::TrailConfig;
+use crate::webhooks::WebhookEvent;
+use crate::whispers::WhisperConfig;
+use crate::worlds::WorldConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    // Host and port, e.g. `0.0.0.0:3042` or, for IPv6, `[::]:3042`; where
+    // IPv6 sockets are dual-stack (Linux's default) the latter takes IPv4
+    // clients as well
+    pub listen_addr: String,
+    // More addresses to accept clients on, e.g. `[::1]:3042` beside
+    // `127.0.0.1:3042`
+    pub listen_addrs: Vec<String>,
+    // Also accept clients on this Unix domain socket (Unix only), e.g. local
+    // admin tools and test harnesses that shouldn't need a network port.
+    // They speak the same protocol, and are not trusted like in-process ones.
+    pub unix_socket: Option<PathBuf>,
+    pub wire_format: WireFormat,
+    // World snapshots sent per second to handshaken clients
+    pub snapshot_rate: u32,
+    // Drop clients that send nothing for this many seconds; 0 keeps them forever
+    pub idle_timeout_secs: u64,
+    // Worker threads the rooms are spread across; defaults to one per core
+    pub shards: usize,
+    // Where persistent server state is kept
+    pub data_dir: PathBuf,
+    // Argon2 PHC string (see `--hash-password`); when set, clients must send it in `Hello`
+    pub password_hash: Option<String>,
+    // Rooms that exist from startup and are never removed, even when empty
+    pub rooms: Vec<RoomConfig>,
+    // Where to accept Source RCON connections; requires `rcon_password_hash`
+    pub rcon_listen_addr: Option<String>,
+    pub rcon_password_hash: Option<String>,
+    // Where to serve the JSON admin API (`http-api` feature); requests must
+    // send `Authorization: Bearer <token>` matching `http_token_hash`
+    pub http_listen_addr: Option<String>,
+    pub http_token_hash: Option<String>,
+    // Accepting clients over QUIC as well (`quic` feature)
+    pub quic: QuicConfig,
+    // Periodic traffic, round trip and loss reports to each client
+    pub net_stats: NetStatsConfig,
+    // Discord/Slack compatible endpoints told about server events
+    pub webhooks: Vec<WebhookConfig>,
+    // Record every inbound frame here, for `--replay`
+    pub capture_path: Option<PathBuf>,
+    // Debug builds only: drop, delay, duplicate and reorder traffic
+    pub chaos: Option<ChaosConfig>,
+    // Clients sending anything bigger are disconnected
+    pub decode_limits: DecodeLimits,
+    // Limits on how often and how many times one address may connect
+    pub throttle: ThrottleConfig,
+    // Addresses or CIDR ranges. When `ip_allow` is non-empty only addresses in
+    // it may connect; anything in `ip_deny` is refused either way.
+    pub ip_allow: Vec<IpRange>,
+    pub ip_deny: Vec<IpRange>,
+    // What players may call themselves
+    pub names: NameConfig,
+    // The skins and accessories avatars may use
+    pub appearance: AppearanceConfig,
+    // Registration and login with `Register` and `Login`
+    pub accounts: AccountConfig,
+    // Login with tokens from an outside identity provider
+    pub jwt: JwtConfig,
+    // Login with Steam session tickets
+    pub steam: SteamConfig,
+    // What players may do without logging in
+    pub guests: GuestConfig,
+    pub friends: FriendConfig,
+    pub whispers: WhisperConfig,
+    pub parties: PartyConfig,
+    pub guilds: GuildConfig,
+    // Mailboxes for offline whispers, notices and item grants
+    pub mail: MailConfig,
+    pub achievements: AchievementConfig,
+    pub emotes: EmoteConfig,
+    pub chat_history: ChatHistoryConfig,
+    // The locale of server text and the translation files it comes from
+    pub localization: LocaleConfig,
+    // How `drain` winds the server down for a restart
+    pub drain: DrainConfig,
+    // Handing live state to a new process for an upgrade
+    pub handoff: HandoffConfig,
+    // Moving sessions to a new connection when a client's address changes
+    pub migration: MigrationConfig,
+    // Streaming state to standbys, or following a primary as one
+    pub replication: ReplicationConfig,
+    // Gateways, workers and which rooms each worker hosts
+    pub cluster: ClusterConfig,
+    // Liveness and readiness checks
+    pub health: HealthConfig,
+    // What to give up when ticks run over their budget
+    pub overload: OverloadConfig,
+    // Each player's recent movement and actions, for moderators and emotes
+    pub trails: TrailConfig,
+    // How big area events can be and how far off they are seen
+    pub areas: AreaConfig,
+    // Obstacles and triggers for the lobby, like a room's `map`
+    pub lobby_map: Option<PathBuf>,
+    // Limits on the entities spawned into rooms at runtime
+    pub entities: EntityConfig,
+    // Where ability definitions are read from, and the energy they cost
+    pub abilities: AbilityConfig,
+    // How many abilities and interactions a player can have queued, and how
+    // long one waits on a cooldown
+    pub actions: ActionConfig,
+    // What gameplay events are worth in XP, and the XP each level takes
+    pub progression: ProgressionConfig,
+    // Where loot tables are read from, and how they are rolled
+    pub loot: LootConfig,
+    // The seed behind loot, crits and `/roll`, and how big a roll may be
+    pub dice: DiceConfig,
+    // How much each account's inventory holds
+    pub inventory: InventoryConfig,
+    // Where crafting recipes are read from
+    pub crafting: CraftingConfig,
+    // Where shops are read from, and what they are paid in
+    pub shops: ShopConfig,
+    // How many players a team of a running match takes before backfill
+    // stops filling it
+    pub matchmaking: MatchmakingConfig,
+    // How long snapshots wait for a moving player's next move before
+    // guessing where they are, and for how long they guess
+    pub dead_reckoning: DeadReckoningConfig,
+    // How long a delta client's last ack stays usable as a baseline before
+    // it is sent a keyframe
+    pub deltas: DeltaConfig,
+    // Separate send rates for positions, the scoreboard and the world, within
+    // `snapshot_rate`
+    pub update_rates: UpdateRateConfig,
+    // What players may set on rooms they create
+    pub room_settings: RoomSettingsConfig,
+    // The named worlds players can enter, kept on disk
+    pub worlds: WorldConfig,
+    // What obstacles hide, besides blocking moves
+    pub sight: SightConfig,
+    // Server-run players, and how they fill matches and move
+    pub bots: BotConfig,
+    // Scenario files by the names rooms play them under (see
+    // `crate::scenarios`)
+    pub scenarios: BTreeMap<String, PathBuf>,
+    // The command line this config was built from, kept for `reload`
+    #[serde(skip)]
+    args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomConfig {
+    pub name: String,
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    // Guests may not join
+    #[serde(default)]
+    pub ranked: bool,
+    // A JSON file of obstacles and triggers (see `crate::maps`)
+    #[serde(default)]
+    pub map: Option<PathBuf>,
+    // Maps to play in turn instead of `map` (see `crate::rotation`)
+    #[serde(default)]
+    pub rotation: Option<RotationConfig>,
+    // One of `scenarios`, run for whoever is in the room
+    #[serde(default)]
+    pub scenario: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Event names to send (see `WebhookEvent::NAMES`); empty sends everything
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DrainConfig {
+    // How long `drain` waits for matches to end when not given a time
+    pub deadline_secs: u64,
+    // What the process exits with once drained, so a supervisor can tell a
+    // restart from a shutdown or a crash
+    pub exit_code: i32,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        DrainConfig {
+            deadline_secs: 300,
+            exit_code: 75,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen_addr: "0.0.0.0:3042".to_string(),
+            listen_addrs: Vec::new(),
+            unix_socket: None,
+            wire_format: WireFormat::Bincode,
+            snapshot_rate: 20,
+            idle_timeout_secs: 0,
+            shards: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            data_dir: PathBuf::from("data"),
+            password_hash: None,
+            rooms: Vec::new(),
+            rcon_listen_addr: None,
+            rcon_password_hash: None,
+            http_listen_addr: None,
+            http_token_hash: None,
+            quic: QuicConfig::default(),
+            net_stats: NetStatsConfig::default(),
+            webhooks: Vec::new(),
+            capture_path: None,
+            chaos: None,
+            decode_limits: DecodeLimits::default(),
+            throttle: ThrottleConfig::default(),
+            ip_allow: Vec::new(),
+            ip_deny: Vec::new(),
+            names: NameConfig::default(),
+            appearance: AppearanceConfig::default(),
+            accounts: AccountConfig::default(),
+            jwt: JwtConfig::default(),
+            steam: SteamConfig::default(),
+            guests: GuestConfig::default(),
+            friends: FriendConfig::default(),
+            whispers: WhisperConfig::default(),
+            parties: PartyConfig::default(),
+            guilds: GuildConfig::default(),
+            mail: MailConfig::default(),
+            achievements: AchievementConfig::default(),
+            emotes: EmoteConfig::default(),
+            chat_history: ChatHistoryConfig::default(),
+            localization: LocaleConfig::default(),
+            drain: DrainConfig::default(),
+            handoff: HandoffConfig::default(),
+            migration: MigrationConfig::default(),
+            replication: ReplicationConfig::default(),
+            cluster: ClusterConfig::default(),
+            health: HealthConfig::default(),
+            overload: OverloadConfig::default(),
+            trails: TrailConfig::default(),
+            areas: AreaConfig::default(),
+            lobby_map: None,
+            entities: EntityConfig::default(),
+            abilities: AbilityConfig::default(),
+            actions: ActionConfig::default(),
+            progression: ProgressionConfig::default(),
+            loot: LootConfig::default(),
+            dice: DiceConfig::default(),
+            inventory: InventoryConfig::default(),
+            crafting: CraftingConfig::default(),
+            shops: ShopConfig::default(),
+            matchmaking: MatchmakingConfig::default(),
+            dead_reckoning: DeadReckoningConfig::default(),
+            deltas: DeltaConfig::default(),
+            update_rates: UpdateRateConfig::default(),
+            room_settings: RoomSettingsConfig::default(),
+            worlds: WorldConfig::default(),
+            sight: SightConfig::default(),
+            bots: BotConfig::default(),
+            scenarios: BTreeMap::new(),
+            args: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+
+    // Whether remote players must log in before they join
+    pub fn login_required(&self) -> bool {
+        self.jwt.required || self.steam.required || !self.guests.allowed
+    }
+
+    // Load a JSON config file; missing fields keep their defaults
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&data).map_err(|e| format!("invalid {}: {}", path.display(), e))
+    }
+
+    // Build a config from command line flags. `--config <file>` is applied
+    // first wherever it appears, and the other flags override the file.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) => {
+                let path = args.get(i + 1).ok_or("missing value for `--config`")?;
+                ServerConfig::from_file(Path::new(path))?
+            }
+            None => ServerConfig::default(),
+        };
+
+        config.args = args.to_vec();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for `{}`", flag))
+            };
+            match flag.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--listen" => config.listen_addr = value()?.clone(),
+                "--unix-socket" => config.unix_socket = Some(PathBuf::from(value()?)),
+                "--wire-format" => config.wire_format = value()?.parse()?,
+                "--snapshot-rate" => {
+                    config.snapshot_rate = value()?
+                        .parse()
+                        .map_err(|_| "`--snapshot-rate` must be a positive integer")?
+                }
+                "--data-dir" => config.data_dir = PathBuf::from(value()?),
+                "--idle-timeout" => {
+                    config.idle_timeout_secs = value()?
+                        .parse()
+                        .map_err(|_| "`--idle-timeout` must be a number of seconds")?
+                }
+                "--rcon-listen" => config.rcon_listen_addr = Some(value()?.clone()),
+                "--capture" => config.capture_path = Some(PathBuf::from(value()?)),
+                "--http-listen" => config.http_listen_addr = Some(value()?.clone()),
+                "--quic-listen" => config.quic.listen_addr = Some(value()?.clone()),
+                "--take-over" => config.handoff.take_over = true,
+                "--standby" => config.replication.standby = true,
+                "--gateway" => config.cluster.gateway = true,
+                "--worker" => {
+                    config.cluster.worker = Some(
+                        value()?
+                            .parse()
+                            .map_err(|_| "`--worker` must be an index into `cluster.workers`")?,
+                    )
+                }
+                _ => return Err(format!("unknown argument `{}`", flag)),
+            }
+        }
+        config.achievements.load()?;
+        config.localization.load()?;
+        config.validate()?;
+        // A worker only creates the configured rooms it hosts
+        config.rooms.retain(|room| config.cluster.hosts(&room.name));
+        Ok(config)
+    }
+
+    // `listen_addr` and then `listen_addrs`
+    pub fn bind_addrs(&self) -> Vec<&str> {
+        let mut addrs = vec![self.listen_addr.as_str()];
+        addrs.extend(self.listen_addrs.iter().map(String::as_str));
+        addrs
+    }
+
+    // Build the config again from the same command line, picking up changes
+    // to the config file
+    pub fn reload(&self) -> Result<Self, String> {
+        if !self.args.iter().any(|arg| arg == "--config") {
+            return Err("the server was not started with `--config`".to_string());
+        }
+        ServerConfig::from_args(&self.args)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.wire_format.is_available() {
+            return Err(format!(
+                "wire format `{}` is not available in this build",
+                self.wire_format
+            ));
+        }
+        let addrs = self.bind_addrs();
+        if let Some((index, addr)) = addrs
+            .iter()
+            .enumerate()
+            .find(|&(index, addr)| addrs[..index].contains(addr))
+        {
+            return Err(format!(
+                "`{}` is listed twice (address {})",
+                addr,
+                index + 1
+            ));
+        }
+        if self.unix_socket.is_some() && !cfg!(unix) {
+            return Err("Unix domain sockets are not available on this platform".to_string());
+        }
+        if self.snapshot_rate == 0 {
+            return Err("`snapshot_rate` must be a positive integer".to_string());
+        }
+        if self.shards == 0 {
+            return Err("`shards` must be a positive integer".to_string());
+        }
+        let limits = &self.decode_limits;
+        if [
+            limits.max_frame_bytes,
+            limits.max_string_bytes,
+            limits.max_collection_len,
+        ]
+        .contains(&0)
+        {
+            return Err("`decode_limits` must all be positive integers".to_string());
+        }
+        self.throttle.validate()?;
+        self.names.validate()?;
+        self.appearance.validate()?;
+        self.accounts.validate()?;
+        self.jwt.validate()?;
+        self.steam.validate()?;
+        self.guests.validate()?;
+        self.friends.validate()?;
+        self.whispers.validate()?;
+        self.parties.validate()?;
+        self.guilds.validate()?;
+        self.mail.validate()?;
+        self.achievements.validate()?;
+        self.emotes.validate()?;
+        self.chat_history.validate()?;
+        self.localization.validate()?;
+        self.handoff.validate()?;
+        self.migration.validate()?;
+        self.replication.validate()?;
+        self.cluster.validate()?;
+        self.health.validate()?;
+        self.overload.validate()?;
+        self.areas.validate()?;
+        self.abilities.validate()?;
+        self.actions.validate()?;
+        self.progression.validate()?;
+        self.loot.validate()?;
+        self.dice.validate()?;
+        self.inventory.validate()?;
+        self.shops.validate()?;
+        self.matchmaking.validate()?;
+        self.dead_reckoning.validate()?;
+        self.deltas.validate()?;
+        self.update_rates.validate(self.snapshot_rate)?;
+        self.room_settings.validate()?;
+        self.worlds.validate()?;
+        self.sight.validate()?;
+        self.bots.validate()?;
+        if self.trails.rewind_ticks > self.trails.seconds * self.snapshot_rate as u64 {
+            return Err(
+                "`trails.rewind_ticks` reaches back past what `trails.seconds` keeps".to_string(),
+            );
+        }
+        for room in &self.rooms {
+            if let Some(scenario) = &room.scenario {
+                if !self.scenarios.contains_key(scenario) {
+                    return Err(format!(
+                        "room `{}` plays scenario `{}`, which is not in `scenarios`",
+                        room.name, scenario
+                    ));
+                }
+            }
+            let Some(rotation) = &room.rotation else {
+                continue;
+            };
+            if room.map.is_some() {
+                return Err(format!(
+                    "room `{}` can't have both `map` and `rotation`",
+                    room.name
+                ));
+            }
+            rotation
+                .validate()
+                .map_err(|e| format!("room `{}`: {}", room.name, e))?;
+        }
+        for worker in &self.cluster.workers {
+            if let Some(name) = worker
+                .rooms
+                .iter()
+                .find(|name| !self.rooms.iter().any(|room| &room.name == *name))
+            {
+                return Err(format!(
+                    "`cluster.workers` hosts `{}`, which is not in `rooms`",
+                    name
+                ));
+            }
+        }
+        if self.handoff.take_over && self.replication.standby {
+            return Err("`--take-over` and `--standby` can't be used together".to_string());
+        }
+        // 0 is a clean shutdown, 1 a server error and 2 a bad config
+        if [0, 1, 2].contains(&self.drain.exit_code) {
+            return Err("`drain.exit_code` must not be 0, 1 or 2".to_string());
+        }
+        if self.rcon_listen_addr.is_some() && self.rcon_password_hash.is_none() {
+            return Err("RCON needs `rcon_password_hash` in the config file".to_string());
+        }
+        if self.http_listen_addr.is_some() {
+            if !cfg!(feature = "http-api") {
+                return Err("the HTTP API is not available in this build".to_string());
+            }
+            if self.http_token_hash.is_none() {
+                return Err("the HTTP API needs `http_token_hash` in the config file".to_string());
+            }
+        }
+        self.quic.validate()?;
+        self.net_stats.validate()?;
+        if let Some(chaos) = &self.chaos {
+            if !cfg!(debug_assertions) {
+                return Err("chaos mode is only available in debug builds".to_string());
+            }
+            chaos.validate()?;
+        }
+        for name in self.webhooks.iter().flat_map(|hook| &hook.events) {
+            if !WebhookEvent::NAMES.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown webhook event `{}` (expected one of {})",
+                    name,
+                    WebhookEvent::NAMES.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
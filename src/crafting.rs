@@ -0,0 +1,72 @@
+// Crafting recipes, by id, from the JSON file `crafting.file` points at. A
+// `Craft` takes a recipe's `inputs` out of the player's inventory (see
+// `crate::inventory`) and puts its `outputs` in, all at once or not at all;
+// a craft that can't go ahead is answered with a `CraftFailed` saying why.
+use crate::mail::ItemGrant;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CraftingConfig {
+    // No file means no recipes
+    pub file: Option<PathBuf>,
+}
+
+// Why a craft didn't go ahead
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CraftError {
+    UnknownRecipe,
+    // Guests have no inventory to craft from
+    NotLoggedIn,
+    MissingItems,
+    // What it makes wouldn't fit (see `crate::inventory`)
+    InventoryFull,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recipe {
+    pub inputs: Vec<ItemGrant>,
+    pub outputs: Vec<ItemGrant>,
+}
+
+impl Recipe {
+    fn validate(&self) -> Result<(), String> {
+        if self.outputs.is_empty() {
+            return Err("a recipe needs at least one output".to_string());
+        }
+        for grants in [&self.inputs, &self.outputs] {
+            let mut items = BTreeSet::new();
+            for grant in grants {
+                if grant.item.is_empty() || grant.quantity == 0 {
+                    return Err("recipe items need a name and a positive quantity".to_string());
+                }
+                if !items.insert(&grant.item) {
+                    return Err(format!("`{}` is listed twice", grant.item));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Recipes {
+    pub recipes: BTreeMap<String, Recipe>,
+}
+
+impl Recipes {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let recipes: BTreeMap<String, Recipe> =
+            serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        for (id, recipe) in &recipes {
+            recipe
+                .validate()
+                .map_err(|e| format!("recipe `{}`: {}", id, e))?;
+        }
+        Ok(Recipes { recipes })
+    }
+}
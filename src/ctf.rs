@@ -0,0 +1,317 @@
+// Capture the flag, played in any room whose map (see `crate::maps`) has a
+// `ctf` section. Each team listed in `flags` has a flag at its base. Touching
+// another team's flag picks it up; touching your own where it lies dropped
+// sends it home; bringing an enemy flag to your own while that is at home
+// scores a capture. A carrier who leaves the room, loses their team or sends
+// `DropFlag` drops it where they stand, and a dropped flag goes home by itself
+// after `return_seconds`. The first team to `score_limit` wins the round.
+//
+// It doubles as an example for anyone writing a mode of their own: a mode
+// keeps its own state, is stepped once a tick with where the room's members
+// are, and hands back what happened for the server to tell clients about. It
+// never touches players or the network itself.
+use crate::maps::Occupant;
+use crate::rooms::TeamId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlagBase {
+    pub team: TeamId,
+    pub at: (f32, f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CtfMap {
+    pub flags: Vec<FlagBase>,
+    // How close, in world units, a player has to get to touch a flag
+    pub pickup_radius: f32,
+    // How long a dropped flag lies before it goes home; 0 leaves it there
+    pub return_seconds: f32,
+    // Captures that win a round; 0 plays on forever
+    pub score_limit: u32,
+}
+
+impl Default for CtfMap {
+    fn default() -> Self {
+        CtfMap {
+            flags: Vec::new(),
+            pickup_radius: 32.0,
+            return_seconds: 30.0,
+            score_limit: 3,
+        }
+    }
+}
+
+impl CtfMap {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.flags.len() < 2 {
+            return Err("`ctf` needs a flag for at least two teams".to_string());
+        }
+        for (index, flag) in self.flags.iter().enumerate() {
+            if self.flags[..index]
+                .iter()
+                .any(|other| other.team == flag.team)
+            {
+                return Err(format!("team {} has two flags", flag.team));
+            }
+            if !(flag.at.0.is_finite() && flag.at.1.is_finite()) {
+                return Err(format!("team {}'s flag needs a finite base", flag.team));
+            }
+        }
+        if !(self.pickup_radius.is_finite() && self.pickup_radius > 0.0) {
+            return Err("`ctf.pickup_radius` must be a positive number".to_string());
+        }
+        if !(self.return_seconds.is_finite() && self.return_seconds >= 0.0) {
+            return Err("`ctf.return_seconds` must be a number of at least 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagState {
+    Home,
+    // `at` follows the carrier, so a dropped flag lands where they last were
+    Carried {
+        by: usize,
+        at: (f32, f32),
+    },
+    // Whoever dropped it can't pick it straight back up; a teammate can
+    Dropped {
+        at: (f32, f32),
+        since: u64,
+        by: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Flag {
+    pub team: TeamId,
+    pub base: (f32, f32),
+    pub state: FlagState,
+}
+
+impl Flag {
+    pub fn position(&self) -> (f32, f32) {
+        match self.state {
+            FlagState::Home => self.base,
+            FlagState::Carried { at, .. } | FlagState::Dropped { at, .. } => at,
+        }
+    }
+
+    pub fn carrier(&self) -> Option<usize> {
+        match self.state {
+            FlagState::Carried { by, .. } => Some(by),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CtfEvent {
+    Taken {
+        flag: TeamId,
+        by: usize,
+    },
+    Dropped {
+        flag: TeamId,
+        by: usize,
+    },
+    // `by` is `None` when the flag went home by itself
+    Returned {
+        flag: TeamId,
+        by: Option<usize>,
+    },
+    // `score` is the team's captures with this one
+    Captured {
+        flag: TeamId,
+        by: usize,
+        team: TeamId,
+        score: u32,
+    },
+    // The round ends; the game stands as it is until `reset`
+    Won {
+        team: TeamId,
+    },
+}
+
+#[derive(Debug)]
+pub struct Ctf {
+    pub map: CtfMap,
+    flags: Vec<Flag>,
+    scores: BTreeMap<TeamId, u32>,
+}
+
+impl Ctf {
+    pub fn new(map: CtfMap) -> Self {
+        let flags = map
+            .flags
+            .iter()
+            .map(|base| Flag {
+                team: base.team,
+                base: base.at,
+                state: FlagState::Home,
+            })
+            .collect();
+        let scores = map.flags.iter().map(|base| (base.team, 0)).collect();
+        Ctf { map, flags, scores }
+    }
+
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    pub fn flag(&self, team: TeamId) -> Option<&Flag> {
+        self.flags.iter().find(|flag| flag.team == team)
+    }
+
+    // Every team with a flag, and its captures this round
+    pub fn scores(&self) -> impl Iterator<Item = (TeamId, u32)> + '_ {
+        self.scores.iter().map(|(&team, &score)| (team, score))
+    }
+
+    pub fn score(&self, team: TeamId) -> u32 {
+        self.scores.get(&team).copied().unwrap_or(0)
+    }
+
+    // Which flag the player has, by index
+    fn carrying(&self, player_id: usize) -> Option<usize> {
+        self.flags
+            .iter()
+            .position(|flag| flag.carrier() == Some(player_id))
+    }
+
+    // The player puts down whatever flag they carry
+    pub fn drop_flag(&mut self, player_id: usize, tick: u64) -> Option<CtfEvent> {
+        let index = self.carrying(player_id)?;
+        let flag = &mut self.flags[index];
+        flag.state = FlagState::Dropped {
+            at: flag.position(),
+            since: tick,
+            by: player_id,
+        };
+        Some(CtfEvent::Dropped {
+            flag: flag.team,
+            by: player_id,
+        })
+    }
+
+    // One tick with the room's members where `occupants` says
+    pub fn step(&mut self, occupants: &[Occupant], tick: u64, snapshot_rate: u32) -> Vec<CtfEvent> {
+        let mut events = Vec::new();
+
+        // Carriers who are gone, or no longer on a team that may hold the
+        // flag, drop it; the rest take it with them
+        for flag in &mut self.flags {
+            let FlagState::Carried { by, at } = flag.state else {
+                continue;
+            };
+            let carrier = occupants.iter().find(|occupant| occupant.id == by);
+            match carrier {
+                Some(carrier) if carrier.team.is_some_and(|team| team != flag.team) => {
+                    flag.state = FlagState::Carried {
+                        by,
+                        at: carrier.position,
+                    };
+                }
+                _ => {
+                    flag.state = FlagState::Dropped {
+                        at,
+                        since: tick,
+                        by,
+                    };
+                    events.push(CtfEvent::Dropped {
+                        flag: flag.team,
+                        by,
+                    });
+                }
+            }
+        }
+
+        if self.map.return_seconds > 0.0 {
+            let lies_for = (self.map.return_seconds * snapshot_rate as f32) as u64;
+            for flag in &mut self.flags {
+                if let FlagState::Dropped { since, .. } = flag.state {
+                    if tick.saturating_sub(since) >= lies_for {
+                        flag.state = FlagState::Home;
+                        events.push(CtfEvent::Returned {
+                            flag: flag.team,
+                            by: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        let radius = self.map.pickup_radius;
+        for occupant in occupants {
+            let Some(team) = occupant.team else {
+                continue;
+            };
+            for index in 0..self.flags.len() {
+                let (x, y) = self.flags[index].position();
+                let (px, py) = occupant.position;
+                if (px - x).hypot(py - y) > radius {
+                    continue;
+                }
+                let flag = &self.flags[index];
+                match (flag.team == team, flag.state) {
+                    (true, FlagState::Dropped { .. }) => {
+                        self.flags[index].state = FlagState::Home;
+                        events.push(CtfEvent::Returned {
+                            flag: team,
+                            by: Some(occupant.id),
+                        });
+                    }
+                    (true, FlagState::Home) => {
+                        let Some(taken) = self.carrying(occupant.id) else {
+                            continue;
+                        };
+                        self.flags[taken].state = FlagState::Home;
+                        let score = self.scores.entry(team).or_default();
+                        *score += 1;
+                        let score = *score;
+                        events.push(CtfEvent::Captured {
+                            flag: self.flags[taken].team,
+                            by: occupant.id,
+                            team,
+                            score,
+                        });
+                        if self.map.score_limit > 0 && score >= self.map.score_limit {
+                            events.push(CtfEvent::Won { team });
+                            return events;
+                        }
+                    }
+                    (false, FlagState::Dropped { by, .. }) if by == occupant.id => {}
+                    (false, FlagState::Home | FlagState::Dropped { .. }) => {
+                        if self.carrying(occupant.id).is_some() {
+                            continue;
+                        }
+                        self.flags[index].state = FlagState::Carried {
+                            by: occupant.id,
+                            at: occupant.position,
+                        };
+                        events.push(CtfEvent::Taken {
+                            flag: self.flags[index].team,
+                            by: occupant.id,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        events
+    }
+
+    // Every flag home and scores back to 0, for a new round
+    pub fn reset(&mut self) {
+        for flag in &mut self.flags {
+            flag.state = FlagState::Home;
+        }
+        for score in self.scores.values_mut() {
+            *score = 0;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// Delta snapshots, for clients that negotiate `SnapshotFormat::Delta`. A
+// client acks each snapshot it applies with `SnapshotAck`, and the next one
+// it is sent lists only the players who moved since the last it acked, and
+// the ids of those who left. A client that hasn't acked anything within
+// `deltas.baseline_ms`, or whose last ack was for another room, gets a
+// keyframe instead: every player, against no baseline. Each shard worker
+// keeps the recent snapshots of its rooms with delta clients in them to diff
+// against, and what each of those clients last acked. Where line of sight
+// hides players from a client (see `crate::sight`), it also keeps who was
+// left out of each snapshot that client was sent, so it is diffed against
+// what it actually got.
+use crate::protocol::PlayerSnapshot;
+use crate::rooms::RoomId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeltaConfig {
+    pub baseline_ms: u64,
+}
+
+impl Default for DeltaConfig {
+    fn default() -> Self {
+        DeltaConfig { baseline_ms: 1000 }
+    }
+}
+
+impl DeltaConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.baseline_ms == 0 {
+            return Err("`deltas.baseline_ms` must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    // How many ticks of snapshots are kept, at least one
+    pub fn window(&self, snapshot_rate: u32) -> u64 {
+        (self.baseline_ms * snapshot_rate as u64)
+            .div_ceil(1000)
+            .max(1)
+    }
+}
+
+// The ids left out of snapshots, by tick, oldest first
+type HiddenByTick = VecDeque<(u64, Vec<usize>)>;
+
+#[derive(Debug, Default)]
+pub struct Baselines {
+    // Each room's snapshots, by tick, oldest first
+    history: HashMap<Option<RoomId>, VecDeque<(u64, Vec<PlayerSnapshot>)>>,
+    // The last tick each client acked, and in which room
+    acked: HashMap<usize, (Option<RoomId>, u64)>,
+    // Who was left out of each client's snapshots in a room
+    hidden: HashMap<(Option<RoomId>, usize), HiddenByTick>,
+}
+
+impl Baselines {
+    pub fn ack(&mut self, room: Option<RoomId>, player_id: usize, tick: u64) {
+        let acked = self.acked.entry(player_id).or_insert((room, tick));
+        // Acks can arrive out of order; only ever move forward in one room
+        if acked.0 != room || acked.1 < tick {
+            *acked = (room, tick);
+        }
+    }
+
+    // Keep `snapshot` as the room's at `tick`, dropping any more than
+    // `window` ticks old
+    pub fn record(
+        &mut self,
+        room: Option<RoomId>,
+        tick: u64,
+        snapshot: &[PlayerSnapshot],
+        window: u64,
+    ) {
+        let history = self.history.entry(room).or_default();
+        history.push_back((tick, snapshot.to_vec()));
+        while history
+            .front()
+            .is_some_and(|&(kept, _)| kept + window < tick)
+        {
+            history.pop_front();
+        }
+    }
+
+    // Keep who was left out of the snapshot `player_id` was sent for `tick`
+    pub fn hide(
+        &mut self,
+        room: Option<RoomId>,
+        player_id: usize,
+        tick: u64,
+        hidden: Vec<usize>,
+        window: u64,
+    ) {
+        let history = self.hidden.entry((room, player_id)).or_default();
+        history.push_back((tick, hidden));
+        while history
+            .front()
+            .is_some_and(|&(kept, _)| kept + window < tick)
+        {
+            history.pop_front();
+        }
+    }
+
+    fn hidden_at(&self, room: Option<RoomId>, player_id: usize, tick: u64) -> &[usize] {
+        self.hidden
+            .get(&(room, player_id))
+            .and_then(|history| history.iter().find(|(kept, _)| *kept == tick))
+            .map_or(&[], |(_, hidden)| hidden.as_slice())
+    }
+
+    // The snapshot `player_id` last acked in `room`, if it is still kept. One
+    // that left players out can only be diffed against by `seen_baseline`.
+    pub fn baseline(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+    ) -> Option<(u64, &[PlayerSnapshot])> {
+        let (tick, snapshot) = self.whole_baseline(room, player_id)?;
+        match self.hidden_at(room, player_id, tick).is_empty() {
+            true => Some((tick, snapshot)),
+            false => None,
+        }
+    }
+
+    // The snapshot `player_id` last acked in `room` as they were sent it
+    pub fn seen_baseline(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+    ) -> Option<(u64, Vec<PlayerSnapshot>)> {
+        let (tick, snapshot) = self.whole_baseline(room, player_id)?;
+        let hidden = self.hidden_at(room, player_id, tick);
+        let seen = snapshot
+            .iter()
+            .filter(|player| !hidden.contains(&player.id))
+            .copied()
+            .collect();
+        Some((tick, seen))
+    }
+
+    fn whole_baseline(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+    ) -> Option<(u64, &[PlayerSnapshot])> {
+        let &(acked_room, tick) = self.acked.get(&player_id)?;
+        if acked_room != room {
+            return None;
+        }
+        let (_, snapshot) = self
+            .history
+            .get(&room)?
+            .iter()
+            .find(|(kept, _)| *kept == tick)?;
+        Some((tick, snapshot))
+    }
+
+    // Forget the acks of clients no longer in the room they acked in, and
+    // the history of rooms no one has acked in
+    pub fn retain(&mut self, present: impl Fn(Option<RoomId>, usize) -> bool) {
+        self.acked
+            .retain(|&player_id, &mut (room, _)| present(room, player_id));
+        self.hidden
+            .retain(|&(room, player_id), _| present(room, player_id));
+        let rooms: HashSet<Option<RoomId>> = self.acked.values().map(|&(room, _)| room).collect();
+        self.history.retain(|room, _| rooms.contains(room));
+    }
+}
+
+// What changed from `baseline` to `current`: the players who are new or
+// moved, and the ids of those who are gone
+pub fn delta(
+    baseline: &[PlayerSnapshot],
+    current: &[PlayerSnapshot],
+) -> (Vec<PlayerSnapshot>, Vec<usize>) {
+    let before: HashMap<usize, &PlayerSnapshot> = baseline.iter().map(|p| (p.id, p)).collect();
+    let changed = current
+        .iter()
+        .filter(|player| before.get(&player.id).is_none_or(|old| *old != *player))
+        .copied()
+        .collect();
+    let now: HashSet<usize> = current.iter().map(|p| p.id).collect();
+    let removed = baseline
+        .iter()
+        .map(|p| p.id)
+        .filter(|id| !now.contains(id))
+        .collect();
+    (changed, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOM: Option<RoomId> = Some(1);
+
+    fn at(id: usize, x: f32) -> PlayerSnapshot {
+        PlayerSnapshot {
+            id,
+            x,
+            y: 0.0,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn deltas_list_changes_and_departures() {
+        let before = [at(1, 0.0), at(2, 0.0), at(3, 0.0)];
+        let after = [at(1, 0.0), at(2, 1.0), at(4, 0.0)];
+        let (changed, removed) = delta(&before, &after);
+        assert_eq!(changed, [at(2, 1.0), at(4, 0.0)]);
+        assert_eq!(removed, [3]);
+        assert_eq!(delta(&after, &after), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn baselines_are_the_last_ack_still_kept() {
+        let mut baselines = Baselines::default();
+        for tick in 1..=5 {
+            baselines.record(ROOM, tick, &[at(1, tick as f32)], 2);
+        }
+        assert!(baselines.baseline(ROOM, 7).is_none());
+        baselines.ack(ROOM, 7, 4);
+        // An older ack arriving late doesn't move it back
+        baselines.ack(ROOM, 7, 3);
+        assert_eq!(baselines.baseline(ROOM, 7), Some((4, &[at(1, 4.0)][..])));
+        // Only ticks 3 to 5 are kept
+        baselines.ack(Some(2), 8, 2);
+        baselines.ack(ROOM, 8, 2);
+        assert!(baselines.baseline(ROOM, 8).is_none());
+        // An ack from another room is no baseline here
+        baselines.ack(Some(2), 7, 5);
+        assert!(baselines.baseline(ROOM, 7).is_none());
+    }
+
+    #[test]
+    fn retain_drops_clients_and_rooms_no_longer_there() {
+        let mut baselines = Baselines::default();
+        baselines.record(ROOM, 1, &[at(1, 0.0)], 10);
+        baselines.record(Some(2), 1, &[at(2, 0.0)], 10);
+        baselines.ack(ROOM, 1, 1);
+        baselines.ack(Some(2), 2, 1);
+        baselines.retain(|room, player_id| room == ROOM && player_id == 1);
+        assert!(baselines.baseline(ROOM, 1).is_some());
+        assert!(baselines.baseline(Some(2), 2).is_none());
+        assert!(!baselines.history.contains_key(&Some(2)));
+    }
+
+    #[test]
+    fn windows_cover_the_baseline_time() {
+        let config = DeltaConfig { baseline_ms: 1000 };
+        assert_eq!(config.window(20), 20);
+        assert_eq!(DeltaConfig { baseline_ms: 1 }.window(20), 1);
+        assert_eq!(DeltaConfig { baseline_ms: 60 }.window(20), 2);
+        assert!(DeltaConfig { baseline_ms: 0 }.validate().is_err());
+    }
+}
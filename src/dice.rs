@@ -0,0 +1,302 @@
+// The server's randomness, for anything whose outcome a client could profit
+// from guessing or faking: loot drops, the game's crits through
+// `ServerHandle::roll`, and players' `/roll`. Every draw is numbered, and
+// draw n gets its own RNG seeded from the seed and n, so the seed printed at
+// startup and a draw's number, as logged and listed by `dice`, are enough to
+// play that draw back with `Dice::replay`, whatever came before it.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiceConfig {
+    // The same seed draws the same outcomes; none picks one at startup.
+    // Falls back to `loot.seed`, which seeded loot alone before.
+    pub seed: Option<u64>,
+    // How many recent draws `dice` can list
+    pub history: usize,
+    // The most dice and sides one roll may have
+    pub max_dice: u32,
+    pub max_sides: u32,
+}
+
+impl Default for DiceConfig {
+    fn default() -> Self {
+        DiceConfig {
+            seed: None,
+            history: 100,
+            max_dice: 100,
+            max_sides: 1000,
+        }
+    }
+}
+
+impl DiceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_dice == 0 || self.max_sides < 2 {
+            return Err(
+                "`dice.max_dice` must be at least 1 and `dice.max_sides` at least 2".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+// Dice notation: `2d6+3`, `d20`, `1d100-5`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceExpression {
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: i64,
+}
+
+// What a bare `/roll` rolls
+impl Default for DiceExpression {
+    fn default() -> Self {
+        DiceExpression {
+            count: 1,
+            sides: 100,
+            modifier: 0,
+        }
+    }
+}
+
+impl fmt::Display for DiceExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.modifier {
+            0 => Ok(()),
+            m if m > 0 => write!(f, "+{}", m),
+            m => write!(f, "{}", m),
+        }
+    }
+}
+
+impl FromStr for DiceExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{}` is not dice, like 2d6+3 or d20", s);
+        let lower = s.trim().to_ascii_lowercase();
+        let (count, rest) = lower.split_once('d').ok_or_else(invalid)?;
+        let count = match count {
+            "" => 1,
+            count => count.parse().map_err(|_| invalid())?,
+        };
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(at) => (&rest[..at], rest[at..].parse().map_err(|_| invalid())?),
+            None => (rest, 0),
+        };
+        let sides = sides.parse().map_err(|_| invalid())?;
+        Ok(DiceExpression {
+            count,
+            sides,
+            modifier,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DiceRoll {
+    // The draw it came from
+    pub number: u64,
+    pub expression: DiceExpression,
+    pub dice: Vec<u32>,
+    pub total: i64,
+}
+
+impl fmt::Display for DiceRoll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dice: Vec<String> = self.dice.iter().map(|d| d.to_string()).collect();
+        write!(f, "{}: [{}]", self.expression, dice.join(", "))?;
+        if self.expression.modifier != 0 {
+            write!(f, " {:+}", self.expression.modifier)?;
+        }
+        write!(f, " = {} (draw #{})", self.total, self.number)
+    }
+}
+
+// One draw, as `dice` lists it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Draw {
+    pub number: u64,
+    pub purpose: String,
+    pub player_id: Option<usize>,
+    // What came of it, for dice rolls
+    pub outcome: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Dice {
+    seed: u64,
+    next: u64,
+    history: VecDeque<Draw>,
+    config: DiceConfig,
+}
+
+impl Dice {
+    pub fn new(config: DiceConfig, fallback_seed: Option<u64>) -> Self {
+        let seed = config
+            .seed
+            .or(fallback_seed)
+            .unwrap_or_else(rand::random::<u64>);
+        Dice {
+            seed,
+            next: 0,
+            history: VecDeque::new(),
+            config,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // How many draws there have been
+    pub fn draws(&self) -> u64 {
+        self.next
+    }
+
+    // The RNG draw `number` under `seed` had
+    pub fn replay(seed: u64, number: u64) -> StdRng {
+        // The seed and number are mixed by splitmix64 so that neighbouring
+        // draws' RNGs don't start out alike
+        let mut z = seed ^ number.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        StdRng::seed_from_u64(z ^ (z >> 31))
+    }
+
+    // A fresh RNG for one outcome, and the draw's number to log with it
+    pub fn draw(&mut self, purpose: &str, player_id: Option<usize>) -> (u64, StdRng) {
+        let number = self.next;
+        self.next += 1;
+        if self.config.history > 0 {
+            if self.history.len() == self.config.history {
+                self.history.pop_front();
+            }
+            self.history.push_back(Draw {
+                number,
+                purpose: purpose.to_string(),
+                player_id,
+                outcome: None,
+            });
+        }
+        (number, Dice::replay(self.seed, number))
+    }
+
+    pub fn roll(
+        &mut self,
+        expression: DiceExpression,
+        purpose: &str,
+        player_id: Option<usize>,
+    ) -> Result<DiceRoll, String> {
+        if expression.count == 0 || expression.count > self.config.max_dice {
+            return Err(format!("roll 1 to {} dice", self.config.max_dice));
+        }
+        if expression.sides < 2 || expression.sides > self.config.max_sides {
+            return Err(format!("dice have 2 to {} sides", self.config.max_sides));
+        }
+        let (number, mut rng) = self.draw(purpose, player_id);
+        let dice: Vec<u32> = (0..expression.count)
+            .map(|_| rng.gen_range(1..=expression.sides))
+            .collect();
+        let total = dice
+            .iter()
+            .map(|&d| d as i64)
+            .sum::<i64>()
+            .saturating_add(expression.modifier);
+        let roll = DiceRoll {
+            number,
+            expression,
+            dice,
+            total,
+        };
+        if let Some(draw) = self.history.back_mut().filter(|d| d.number == number) {
+            draw.outcome = Some(roll.to_string());
+        }
+        Ok(roll)
+    }
+
+    // The latest `count` draws, oldest first
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Draw> {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dice(s: &str) -> DiceExpression {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_dice_notation() {
+        let expected = |count, sides, modifier| DiceExpression {
+            count,
+            sides,
+            modifier,
+        };
+        assert_eq!(dice("2d6+3"), expected(2, 6, 3));
+        assert_eq!(dice("d20"), expected(1, 20, 0));
+        assert_eq!(dice("1d100-5"), expected(1, 100, -5));
+        assert_eq!(dice(" 3D8 "), expected(3, 8, 0));
+    }
+
+    #[test]
+    fn refuses_what_is_not_dice() {
+        for s in [
+            "", "d", "2d", "20", "xd6", "2d6+", "2d6+x", "2d6*2", "-1d6", "2d-6",
+        ] {
+            assert!(s.parse::<DiceExpression>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for s in ["2d6+3", "1d20", "1d100-5"] {
+            assert_eq!(dice(s).to_string(), s);
+        }
+        assert_eq!(DiceExpression::default().to_string(), "1d100");
+    }
+
+    #[test]
+    fn rolls_stay_within_limits() {
+        let mut dice = Dice::new(DiceConfig::default(), Some(1));
+        let roll = dice.roll(self::dice("4d6+2"), "test", None).unwrap();
+        assert_eq!(roll.dice.len(), 4);
+        assert!(roll.dice.iter().all(|&d| (1..=6).contains(&d)));
+        assert_eq!(
+            roll.total,
+            roll.dice.iter().map(|&d| d as i64).sum::<i64>() + 2
+        );
+        assert!(dice.roll(self::dice("101d6"), "test", None).is_err());
+        assert!(dice.roll(self::dice("1d1"), "test", None).is_err());
+        assert!(dice.roll(self::dice("1d1001"), "test", None).is_err());
+    }
+
+    #[test]
+    fn draws_replay_from_seed_and_number() {
+        let mut first = Dice::new(DiceConfig::default(), Some(42));
+        let mut second = Dice::new(DiceConfig::default(), Some(42));
+        let a: Vec<_> = (0..3)
+            .map(|_| first.roll(dice("3d20"), "a", None).unwrap())
+            .collect();
+        let b: Vec<_> = (0..3)
+            .map(|_| second.roll(dice("3d20"), "b", None).unwrap())
+            .collect();
+        assert_eq!(a, b);
+
+        let mut rng = Dice::replay(42, 1);
+        let replayed: Vec<u32> = (0..3).map(|_| rng.gen_range(1..=20)).collect();
+        assert_eq!(replayed, a[1].dice);
+    }
+}
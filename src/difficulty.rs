@@ -0,0 +1,97 @@
+// Difficulty: per-room multipliers for PvE, 1 for a room left as it is.
+// Operators turn them with `difficulty` and scenarios with their
+// `difficulty` action, at any time; the room's clients are sent
+// `DifficultyChanged` whenever they change and on entering a room that
+// isn't at 1 across the board. NPC health and damage are the client's to
+// scale, since the server simulates neither. Spawn rate quickens (or, below
+// 1, slows) a scenario's `every` events that spawn anything. Every round
+// and finished scenario is recorded in match history at the difficulty it
+// was played at (see `crate::history`).
+use serde::{Deserialize, Serialize};
+
+// The most any multiplier can be turned up to
+const MAX_MULTIPLIER: f32 = 100.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Difficulty {
+    // NPC health
+    pub health: f32,
+    // Damage NPCs deal
+    pub damage: f32,
+    pub spawn_rate: f32,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty {
+            health: 1.0,
+            damage: 1.0,
+            spawn_rate: 1.0,
+        }
+    }
+}
+
+// The multipliers to change, leaving the rest as they are
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DifficultyChange {
+    pub health: Option<f32>,
+    pub damage: Option<f32>,
+    pub spawn_rate: Option<f32>,
+}
+
+impl DifficultyChange {
+    pub fn is_empty(&self) -> bool {
+        *self == DifficultyChange::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let multipliers = [
+            ("health", self.health),
+            ("damage", self.damage),
+            ("spawn_rate", self.spawn_rate),
+        ];
+        for (name, value) in multipliers {
+            if value.is_some_and(|value| !(value > 0.0 && value <= MAX_MULTIPLIER)) {
+                return Err(format!(
+                    "`{}` must be above 0 and at most {}",
+                    name, MAX_MULTIPLIER
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Set one of the multipliers by name, e.g. from `health=1.5`
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let multiplier = match name {
+            "health" => &mut self.health,
+            "damage" => &mut self.damage,
+            "spawn_rate" => &mut self.spawn_rate,
+            _ => {
+                return Err(format!(
+                    "unknown multiplier `{}` (health, damage or spawn_rate)",
+                    name
+                ))
+            }
+        };
+        *multiplier = Some(value);
+        Ok(())
+    }
+}
+
+impl Difficulty {
+    pub fn apply(&mut self, change: &DifficultyChange) {
+        self.health = change.health.unwrap_or(self.health);
+        self.damage = change.damage.unwrap_or(self.damage);
+        self.spawn_rate = change.spawn_rate.unwrap_or(self.spawn_rate);
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "health x{}, damage x{}, spawn rate x{}",
+            self.health, self.damage, self.spawn_rate
+        )
+    }
+}
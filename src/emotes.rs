@@ -0,0 +1,44 @@
+// Emotes: short animations from a configured set. They only reach players
+// standing near the sender in the same room, and each player has a cooldown
+// between emotes.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EmoteConfig {
+    // The emote ids clients may send; empty turns emotes off
+    pub emotes: Vec<String>,
+    // How far away, in world units, an emote can be seen
+    pub radius: f32,
+    pub cooldown_ms: u64,
+}
+
+impl Default for EmoteConfig {
+    fn default() -> Self {
+        EmoteConfig {
+            emotes: ["wave", "cheer", "laugh", "dance", "sit"]
+                .map(str::to_string)
+                .to_vec(),
+            radius: 256.0,
+            cooldown_ms: 1000,
+        }
+    }
+}
+
+impl EmoteConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            return Err("`emotes.radius` must be a positive number".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_millis(self.cooldown_ms)
+    }
+
+    pub fn knows(&self, id: &str) -> bool {
+        self.emotes.iter().any(|emote| emote == id)
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::net::SocketAddr;
+
+// Set on the ids of in-process clients so they never collide with network ones
+const LOCAL_ID_BIT: u64 = 1 << 63;
+// The same for clients behind a gateway, whose link number goes above the
+// gateway's own connection number
+const GATEWAY_ID_BIT: u64 = 1 << 62;
+const GATEWAY_CONNECTION_BITS: u32 = 40;
+// And for QUIC connections (see `crate::quic`) and Unix domain socket ones
+const QUIC_ID_BIT: u64 = 1 << 61;
+const UNIX_ID_BIT: u64 = 1 << 60;
+
+// One end of a client connection: a TCP peer, a client running in the
+// same process (see `server::spawn`), a client connected to a gateway (see
+// `crate::cluster`), a QUIC peer (see `crate::quic`), or a peer on the Unix
+// domain socket (see `unix_socket`)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Endpoint {
+    Tcp {
+        connection: u64,
+        addr: SocketAddr,
+    },
+    Local(u64),
+    Gateway {
+        link: u32,
+        connection: u64,
+        addr: SocketAddr,
+    },
+    Quic {
+        connection: u64,
+        addr: SocketAddr,
+    },
+    Unix(u64),
+}
+
+impl Endpoint {
+    // The peer's address; in-process and Unix socket clients have none. IPv4
+    // clients of a dual-stack socket come as themselves, not `::ffff:a.b.c.d`,
+    // so bans and throttles see one address whichever socket they used.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        let addr = match self {
+            Endpoint::Local(_) | Endpoint::Unix(_) => return None,
+            Endpoint::Tcp { addr, .. }
+            | Endpoint::Gateway { addr, .. }
+            | Endpoint::Quic { addr, .. } => *addr,
+        };
+        Some(SocketAddr::new(addr.ip().to_canonical(), addr.port()))
+    }
+
+    // A number unique among the server's open connections
+    pub fn raw_id(&self) -> u64 {
+        match self {
+            Endpoint::Tcp { connection, .. } => *connection,
+            Endpoint::Local(id) => LOCAL_ID_BIT | id,
+            Endpoint::Gateway {
+                link, connection, ..
+            } => {
+                let connection = connection & ((1 << GATEWAY_CONNECTION_BITS) - 1);
+                GATEWAY_ID_BIT | (u64::from(*link) << GATEWAY_CONNECTION_BITS) | connection
+            }
+            Endpoint::Quic { connection, .. } => QUIC_ID_BIT | connection,
+            Endpoint::Unix(id) => UNIX_ID_BIT | id,
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, Endpoint::Local(_))
+    }
+}
+
+// For logs: which protocol a bound address takes clients over
+pub fn family(addr: SocketAddr) -> &'static str {
+    match addr {
+        SocketAddr::V4(_) => "IPv4",
+        SocketAddr::V6(_) => "IPv6",
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { addr, .. } => write!(f, "{}", addr),
+            Endpoint::Local(id) => write!(f, "local client {}", id),
+            Endpoint::Gateway { link, addr, .. } => write!(f, "{} via gateway {}", addr, link),
+            Endpoint::Quic { addr, .. } => write!(f, "{} over QUIC", addr),
+            Endpoint::Unix(id) => write!(f, "Unix socket client {}", id),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Things in a room other than its players, such as pickups, props or markers,
+// that the game hosting the server, scripts and admins spawn at runtime. The
+// server only keeps and replicates them; what a kind means is up to clients.
+// Each has a kind, a position and free-form metadata, is announced to its
+// room with `EntitySpawned` and `EntityDespawned`, and to anyone entering the
+// room later. Entities go when despawned, or when their room closes.
+//
+// The server has authority over an entity, meaning only it moves it, until
+// it hands that to a player in the room, such as the driver of a vehicle the
+// client simulates. That player moves it with `MoveEntity` and can pass it on
+// or give it back; the server takes it back when they leave the room or
+// disconnect.
+use crate::rooms::RoomId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Longest kind, metadata key and metadata value accepted, in bytes
+const MAX_KIND_LEN: usize = 32;
+const MAX_KEY_LEN: usize = 32;
+const MAX_VALUE_LEN: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EntityConfig {
+    // 0 turns spawning off
+    pub max_per_room: usize,
+    pub max_metadata: usize,
+}
+
+impl Default for EntityConfig {
+    fn default() -> Self {
+        EntityConfig {
+            max_per_room: 1024,
+            max_metadata: 16,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Entity {
+    pub fn validate(&self, config: &EntityConfig) -> Result<(), String> {
+        if self.kind.is_empty() || self.kind.len() > MAX_KIND_LEN {
+            return Err(format!(
+                "an entity's kind must be 1 to {} bytes",
+                MAX_KIND_LEN
+            ));
+        }
+        if !(self.x.is_finite() && self.y.is_finite()) {
+            return Err("an entity needs a finite position".to_string());
+        }
+        if self.metadata.len() > config.max_metadata {
+            return Err(format!(
+                "an entity can have at most {} metadata entries",
+                config.max_metadata
+            ));
+        }
+        for (key, value) in &self.metadata {
+            if key.is_empty() || key.len() > MAX_KEY_LEN || value.len() > MAX_VALUE_LEN {
+                return Err(format!(
+                    "metadata keys must be 1 to {} bytes and values at most {}",
+                    MAX_KEY_LEN, MAX_VALUE_LEN
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Entities {
+    rooms: HashMap<Option<RoomId>, BTreeMap<u64, Entity>>,
+    // Which room each entity is in
+    placed: HashMap<u64, Option<RoomId>>,
+    // The player with authority over each entity the server doesn't simulate
+    authority: HashMap<u64, usize>,
+    last_id: u64,
+}
+
+impl Entities {
+    pub fn spawn(
+        &mut self,
+        config: &EntityConfig,
+        room: Option<RoomId>,
+        entity: Entity,
+    ) -> Result<u64, String> {
+        entity.validate(config)?;
+        let entities = self.rooms.entry(room).or_default();
+        if entities.len() >= config.max_per_room {
+            return Err(format!(
+                "a room can hold at most {} entities",
+                config.max_per_room
+            ));
+        }
+        self.last_id += 1;
+        entities.insert(self.last_id, entity);
+        self.placed.insert(self.last_id, room);
+        Ok(self.last_id)
+    }
+
+    pub fn despawn(&mut self, id: u64) -> Option<(Option<RoomId>, Entity)> {
+        let room = self.placed.remove(&id)?;
+        let entities = self.rooms.get_mut(&room)?;
+        let entity = entities.remove(&id)?;
+        if entities.is_empty() {
+            self.rooms.remove(&room);
+        }
+        self.authority.remove(&id);
+        Some((room, entity))
+    }
+
+    pub fn room_of(&self, id: u64) -> Option<Option<RoomId>> {
+        self.placed.get(&id).copied()
+    }
+
+    pub fn get(&self, id: u64) -> Option<(Option<RoomId>, &Entity)> {
+        let room = self.room_of(id)?;
+        Some((room, self.rooms.get(&room)?.get(&id)?))
+    }
+
+    // `None` is the server
+    pub fn authority(&self, id: u64) -> Option<usize> {
+        self.authority.get(&id).copied()
+    }
+
+    pub fn set_authority(&mut self, id: u64, owner: Option<usize>) {
+        match owner {
+            Some(player_id) => self.authority.insert(id, player_id),
+            None => self.authority.remove(&id),
+        };
+    }
+
+    // The entities a player has authority over
+    pub fn held_by(&self, player_id: usize) -> Vec<u64> {
+        self.authority
+            .iter()
+            .filter(|&(_, &owner)| owner == player_id)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    // Returns the entity's room, if there is such an entity
+    pub fn move_to(&mut self, id: u64, x: f32, y: f32) -> Option<Option<RoomId>> {
+        let room = self.room_of(id)?;
+        let entity = self.rooms.get_mut(&room)?.get_mut(&id)?;
+        entity.x = x;
+        entity.y = y;
+        Some(room)
+    }
+
+    pub fn in_room(&self, room: Option<RoomId>) -> impl Iterator<Item = (u64, &Entity)> {
+        self.rooms
+            .get(&room)
+            .into_iter()
+            .flat_map(|entities| entities.iter().map(|(&id, entity)| (id, entity)))
+    }
+
+    // Let go of the entities of rooms `open` says are gone; returns how many
+    pub fn retain_rooms(&mut self, open: impl Fn(RoomId) -> bool) -> usize {
+        let closed: Vec<Option<RoomId>> = self
+            .rooms
+            .keys()
+            .copied()
+            .filter(|room| room.is_some_and(|room| !open(room)))
+            .collect();
+        let mut dropped = 0;
+        for room in closed {
+            for id in self
+                .rooms
+                .remove(&room)
+                .into_iter()
+                .flat_map(|e| e.into_keys())
+            {
+                self.placed.remove(&id);
+                self.authority.remove(&id);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+}
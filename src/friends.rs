@@ -0,0 +1,135 @@
+// Friendships between accounts. A request waits until the other side accepts
+// it, and either side can end a friendship or drop a request at any time.
+use crate::accounts::AccountId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FriendConfig {
+    pub max_friends: usize,
+    // Unanswered requests an account may have waiting for it
+    pub max_requests: usize,
+}
+
+impl Default for FriendConfig {
+    fn default() -> Self {
+        FriendConfig {
+            max_friends: 200,
+            max_requests: 50,
+        }
+    }
+}
+
+impl FriendConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_friends == 0 || self.max_requests == 0 {
+            return Err(
+                "`friends.max_friends` and `friends.max_requests` must be positive integers"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Friends {
+    // Stored both ways round
+    pub friends: BTreeMap<AccountId, BTreeSet<AccountId>>,
+    // Pending requests by recipient, then sender
+    pub requests: BTreeMap<AccountId, BTreeSet<AccountId>>,
+}
+
+impl Friends {
+    pub const STORAGE_KEY: &'static str = "friends";
+
+    pub fn of(&self, account: AccountId) -> Vec<AccountId> {
+        self.friends
+            .get(&account)
+            .map(|friends| friends.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn are_friends(&self, a: AccountId, b: AccountId) -> bool {
+        self.friends
+            .get(&a)
+            .is_some_and(|friends| friends.contains(&b))
+    }
+
+    pub fn incoming(&self, account: AccountId) -> Vec<AccountId> {
+        self.requests
+            .get(&account)
+            .map(|senders| senders.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn outgoing(&self, account: AccountId) -> Vec<AccountId> {
+        self.requests
+            .iter()
+            .filter(|(_, senders)| senders.contains(&account))
+            .map(|(&recipient, _)| recipient)
+            .collect()
+    }
+
+    pub fn has_requested(&self, from: AccountId, to: AccountId) -> bool {
+        self.requests
+            .get(&to)
+            .is_some_and(|senders| senders.contains(&from))
+    }
+
+    pub fn request(&mut self, from: AccountId, to: AccountId) {
+        self.requests.entry(to).or_default().insert(from);
+    }
+
+    // Returns whether `from` had asked `to`
+    pub fn accept(&mut self, to: AccountId, from: AccountId) -> bool {
+        if !self.take_request(from, to) {
+            return false;
+        }
+        // They may have asked each other
+        self.take_request(to, from);
+        self.friends.entry(to).or_default().insert(from);
+        self.friends.entry(from).or_default().insert(to);
+        true
+    }
+
+    // End a friendship or drop a request in either direction; returns whether
+    // there was anything between them
+    pub fn remove(&mut self, a: AccountId, b: AccountId) -> bool {
+        let mut removed = self.take_request(a, b) | self.take_request(b, a);
+        for (one, other) in [(a, b), (b, a)] {
+            if let Some(friends) = self.friends.get_mut(&one) {
+                removed |= friends.remove(&other);
+                if friends.is_empty() {
+                    self.friends.remove(&one);
+                }
+            }
+        }
+        removed
+    }
+
+    // Drop every friendship and request `account` has, returning the accounts
+    // on the other end
+    pub fn forget(&mut self, account: AccountId) -> Vec<AccountId> {
+        let mut others: BTreeSet<AccountId> = self.friends.remove(&account).unwrap_or_default();
+        others.extend(self.requests.remove(&account).unwrap_or_default());
+        others.extend(self.outgoing(account));
+        for &other in &others {
+            self.remove(account, other);
+        }
+        others.into_iter().collect()
+    }
+
+    fn take_request(&mut self, from: AccountId, to: AccountId) -> bool {
+        let Some(senders) = self.requests.get_mut(&to) else {
+            return false;
+        };
+        let taken = senders.remove(&from);
+        if senders.is_empty() {
+            self.requests.remove(&to);
+        }
+        taken
+    }
+}
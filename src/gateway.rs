@@ -0,0 +1,239 @@
+// A gateway (see `crate::cluster`): takes client connections the way a server
+// would, but never decodes a frame. Each client is put on the worker with the
+// fewest of this gateway's clients, and its traffic goes back and forth over
+// that worker's link until the worker moves it or either side goes away.
+use crate::cluster::{self, LinkFrame};
+use crate::config::ServerConfig;
+use crate::endpoint::family;
+use message_io::network::{self, NetEvent, Transport};
+use message_io::node::{self, NodeHandler};
+use std::collections::HashMap;
+use std::io::{self, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+// How long to wait before dialling a lost worker again
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+// A worker that takes longer than this over a frame counts as lost
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Event {
+    Accepted(network::Endpoint),
+    Frame(network::Endpoint, Vec<u8>),
+    Disconnected(network::Endpoint),
+    LinkUp(usize, TcpStream),
+    Link(usize, LinkFrame),
+    LinkDown(usize),
+}
+
+struct Gateway {
+    network: NodeHandler<()>,
+    // The write half of each worker's link, while it is up
+    links: Vec<Option<TcpStream>>,
+    // Open client connections by number, and the worker each is on
+    clients: HashMap<u64, (network::Endpoint, usize)>,
+}
+
+// Serve as a gateway until the process is killed
+pub fn run(config: &ServerConfig) -> io::Result<()> {
+    let (events, received) = mpsc::channel();
+    let (handler, listener) = node::split::<()>();
+    for addr in config.bind_addrs() {
+        let (_, addr) = handler.network().listen(Transport::FramedTcp, addr)?;
+        println!(
+            "Gateway listening on {} ({}) for {} workers",
+            addr,
+            family(addr),
+            config.cluster.workers.len()
+        );
+    }
+    let token = config.cluster.token.clone().unwrap_or_default();
+    for (index, worker) in config.cluster.workers.iter().enumerate() {
+        spawn_link(
+            index,
+            worker.link_addr.clone(),
+            token.clone(),
+            events.clone(),
+        );
+    }
+    let clients = events.clone();
+    let _task = listener.for_each_async(move |event| {
+        let event = match event.network() {
+            NetEvent::Connected(_, _) => unreachable!(),
+            NetEvent::Accepted(endpoint, _) => Event::Accepted(endpoint),
+            NetEvent::Message(endpoint, data) => Event::Frame(endpoint, data.to_vec()),
+            NetEvent::Disconnected(endpoint) => Event::Disconnected(endpoint),
+        };
+        clients.send(event).ok();
+    });
+
+    let mut gateway = Gateway {
+        network: handler,
+        links: config.cluster.workers.iter().map(|_| None).collect(),
+        clients: HashMap::new(),
+    };
+    for event in received {
+        gateway.on_event(event);
+    }
+    Ok(())
+}
+
+// Keep a link to the worker on `addr` up, reporting everything it sends
+fn spawn_link(index: usize, addr: String, token: String, events: Sender<Event>) {
+    thread::spawn(move || loop {
+        match open_link(&addr, &token) {
+            Ok((writer, mut reader)) => {
+                println!("Linked to worker {} on {}", index, addr);
+                events.send(Event::LinkUp(index, writer)).ok();
+                let e = loop {
+                    match cluster::read_frame(&mut reader) {
+                        Ok(frame) => {
+                            events.send(Event::Link(index, frame)).ok();
+                        }
+                        Err(e) => break e,
+                    }
+                };
+                println!("Lost worker {}: {}", index, e);
+                events.send(Event::LinkDown(index)).ok();
+            }
+            Err(e) => println!("Failed to reach worker {} on {}: {}", index, addr, e),
+        }
+        thread::sleep(RECONNECT_INTERVAL);
+    });
+}
+
+fn open_link(addr: &str, token: &str) -> io::Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    writeln!(stream, "{}", token)?;
+    let reader = BufReader::new(stream.try_clone()?);
+    Ok((stream, reader))
+}
+
+impl Gateway {
+    fn on_event(&mut self, event: Event) {
+        match event {
+            Event::Accepted(endpoint) => self.on_accepted(endpoint),
+            Event::Frame(endpoint, data) => {
+                let connection = number(endpoint);
+                if let Some(&(_, worker)) = self.clients.get(&connection) {
+                    self.send(worker, &LinkFrame::Data { connection, data });
+                }
+            }
+            Event::Disconnected(endpoint) => {
+                let connection = number(endpoint);
+                if let Some((_, worker)) = self.clients.remove(&connection) {
+                    self.send(worker, &LinkFrame::Close { connection });
+                }
+            }
+            Event::LinkUp(worker, stream) => self.links[worker] = Some(stream),
+            Event::Link(worker, frame) => self.on_link_frame(worker, frame),
+            Event::LinkDown(worker) => self.link_down(worker),
+        }
+    }
+
+    fn on_accepted(&mut self, endpoint: network::Endpoint) {
+        let mut load = vec![0; self.links.len()];
+        for &(_, worker) in self.clients.values() {
+            load[worker] += 1;
+        }
+        let worker = (0..self.links.len())
+            .filter(|&worker| self.links[worker].is_some())
+            .min_by_key(|&worker| load[worker]);
+        let Some(worker) = worker else {
+            println!("No worker is up to take {}", endpoint.addr());
+            self.network.network().remove(endpoint.resource_id());
+            return;
+        };
+        let connection = number(endpoint);
+        self.clients.insert(connection, (endpoint, worker));
+        let open = LinkFrame::Open {
+            connection,
+            addr: endpoint.addr(),
+        };
+        self.send(worker, &open);
+    }
+
+    fn on_link_frame(&mut self, worker: usize, frame: LinkFrame) {
+        match frame {
+            LinkFrame::Data { connection, data } => {
+                if let Some(&(endpoint, on)) = self.clients.get(&connection) {
+                    if on == worker {
+                        self.network.network().send(endpoint, &data);
+                    }
+                }
+            }
+            LinkFrame::Close { connection } => {
+                if self
+                    .clients
+                    .get(&connection)
+                    .is_some_and(|&(_, on)| on == worker)
+                {
+                    self.close(connection);
+                }
+            }
+            LinkFrame::Move {
+                connection,
+                worker: to,
+                transfer,
+            } => {
+                let Some(&(endpoint, on)) = self.clients.get(&connection) else {
+                    return;
+                };
+                // The old worker has already let the player go, so a client
+                // with nowhere to go is closed
+                if on != worker || self.links.get(to).is_none_or(Option::is_none) {
+                    self.close(connection);
+                    return;
+                }
+                self.clients.insert(connection, (endpoint, to));
+                let adopt = LinkFrame::Adopt {
+                    connection,
+                    addr: endpoint.addr(),
+                    transfer,
+                };
+                self.send(to, &adopt);
+            }
+            LinkFrame::Open { .. } | LinkFrame::Adopt { .. } => {}
+        }
+    }
+
+    fn send(&mut self, worker: usize, frame: &LinkFrame) {
+        let Some(link) = self.links[worker].as_mut() else {
+            return;
+        };
+        if let Err(e) = cluster::write_frame(link, frame) {
+            println!("Lost worker {}: {}", worker, e);
+            self.link_down(worker);
+        }
+    }
+
+    // The worker's players are gone with it; they can reconnect to another
+    fn link_down(&mut self, worker: usize) {
+        if let Some(link) = self.links[worker].take() {
+            link.shutdown(std::net::Shutdown::Both).ok();
+        }
+        let stranded: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, &(_, on))| on == worker)
+            .map(|(&connection, _)| connection)
+            .collect();
+        for connection in stranded {
+            self.close(connection);
+        }
+    }
+
+    fn close(&mut self, connection: u64) {
+        if let Some((endpoint, _)) = self.clients.remove(&connection) {
+            self.network.network().remove(endpoint.resource_id());
+        }
+    }
+}
+
+fn number(endpoint: network::Endpoint) -> u64 {
+    endpoint.resource_id().raw() as u64
+}
@@ -0,0 +1,37 @@
+// What players who haven't logged in may do. Guests can play, but chat
+// slowly, leave nothing behind in analytics and stay out of ranked rooms.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GuestConfig {
+    // Off means every remote player has to log in before joining
+    pub allowed: bool,
+    // Chat lines a guest may send per `chat_window_secs`
+    pub chat_messages: usize,
+    pub chat_window_secs: u64,
+}
+
+impl Default for GuestConfig {
+    fn default() -> Self {
+        GuestConfig {
+            allowed: true,
+            chat_messages: 5,
+            chat_window_secs: 10,
+        }
+    }
+}
+
+impl GuestConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chat_window_secs == 0 {
+            return Err("`guests.chat_window_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn chat_window(&self) -> Duration {
+        Duration::from_secs(self.chat_window_secs)
+    }
+}
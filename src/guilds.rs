@@ -0,0 +1,221 @@
+// Guilds: persistent groups of accounts with a name, an optional tag shown
+// next to members' names, and ranks. Officers invite and kick; the leader
+// also sets ranks. The last member to leave disbands the guild.
+use crate::accounts::AccountId;
+use crate::names;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type GuildId = u32;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GuildConfig {
+    // Members plus outstanding invites
+    pub max_members: usize,
+    pub max_name_len: usize,
+    pub max_tag_len: usize,
+    // Show guild tags alongside display names
+    pub show_tags: bool,
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        GuildConfig {
+            max_members: 100,
+            max_name_len: 32,
+            max_tag_len: 5,
+            show_tags: true,
+        }
+    }
+}
+
+impl GuildConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_members == 0 || self.max_name_len == 0 || self.max_tag_len == 0 {
+            return Err(
+                "`guilds.max_members`, `guilds.max_name_len` and `guilds.max_tag_len` must be positive integers"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    // A trimmed guild name, or why it is unacceptable
+    pub fn check_name(&self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > self.max_name_len {
+            return Err(format!(
+                "guild names must be 1-{} characters",
+                self.max_name_len
+            ));
+        }
+        if name.chars().any(char::is_control) {
+            return Err("guild names can't contain control characters".to_string());
+        }
+        Ok(name.to_string())
+    }
+
+    // Tags are short, upper-cased and alphanumeric
+    pub fn check_tag(&self, tag: &str) -> Result<String, String> {
+        let tag = tag.trim();
+        if tag.is_empty()
+            || tag.chars().count() > self.max_tag_len
+            || !tag.chars().all(char::is_alphanumeric)
+        {
+            return Err(format!(
+                "guild tags must be 1-{} letters or digits",
+                self.max_tag_len
+            ));
+        }
+        Ok(tag.to_uppercase())
+    }
+}
+
+// Ordered from least to most senior so that `rank >= required` is the check
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum GuildRank {
+    Member,
+    Officer,
+    Leader,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Guild {
+    pub id: GuildId,
+    pub name: String,
+    pub tag: Option<String>,
+    pub members: BTreeMap<AccountId, GuildRank>,
+    #[serde(default)]
+    pub invited: BTreeSet<AccountId>,
+}
+
+impl Guild {
+    pub fn size(&self) -> usize {
+        self.members.len() + self.invited.len()
+    }
+
+    pub fn rank(&self, account: AccountId) -> Option<GuildRank> {
+        self.members.get(&account).copied()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Guilds {
+    pub guilds: BTreeMap<GuildId, Guild>,
+    pub next_id: GuildId,
+}
+
+impl Guilds {
+    pub const STORAGE_KEY: &'static str = "guilds";
+
+    pub fn get(&self, id: GuildId) -> Option<&Guild> {
+        self.guilds.get(&id)
+    }
+
+    // The guild `account` belongs to
+    pub fn of(&self, account: AccountId) -> Option<&Guild> {
+        self.guilds
+            .values()
+            .find(|guild| guild.members.contains_key(&account))
+    }
+
+    // Whether a guild already goes by `name`, or uses `tag`, ignoring case
+    pub fn taken(&self, name: &str, tag: Option<&str>) -> Option<&'static str> {
+        self.guilds.values().find_map(|guild| {
+            if names::same_name(&guild.name, name) {
+                Some("that guild name is taken")
+            } else if tag.is_some() && guild.tag.as_deref() == tag {
+                Some("that guild tag is taken")
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn create(&mut self, name: String, tag: Option<String>, leader: AccountId) -> GuildId {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.guilds.insert(
+            id,
+            Guild {
+                id,
+                name,
+                tag,
+                members: BTreeMap::from([(leader, GuildRank::Leader)]),
+                invited: BTreeSet::new(),
+            },
+        );
+        id
+    }
+
+    pub fn invite(&mut self, id: GuildId, account: AccountId) -> bool {
+        self.guilds
+            .get_mut(&id)
+            .is_some_and(|guild| guild.invited.insert(account))
+    }
+
+    // Turn an invite into membership; returns whether there was one
+    pub fn join(&mut self, id: GuildId, account: AccountId) -> bool {
+        let Some(guild) = self.guilds.get_mut(&id) else {
+            return false;
+        };
+        if !guild.invited.remove(&account) {
+            return false;
+        }
+        guild.members.insert(account, GuildRank::Member);
+        true
+    }
+
+    // Remove a member. A departing leader hands over to an officer, or failing
+    // that to a member; a guild left empty is disbanded. Returns whether the
+    // guild still exists.
+    pub fn leave(&mut self, id: GuildId, account: AccountId) -> bool {
+        let Some(guild) = self.guilds.get_mut(&id) else {
+            return false;
+        };
+        let rank = guild.members.remove(&account);
+        if guild.members.is_empty() {
+            self.guilds.remove(&id);
+            return false;
+        }
+        if rank == Some(GuildRank::Leader) {
+            let heir = guild
+                .members
+                .iter()
+                .max_by_key(|(_, &rank)| rank)
+                .map(|(&heir, _)| heir);
+            if let Some(heir) = heir {
+                guild.members.insert(heir, GuildRank::Leader);
+            }
+        }
+        true
+    }
+
+    // Withdraw every invite `account` holds, returning the guilds it had them from
+    pub fn uninvite(&mut self, account: AccountId) -> Vec<GuildId> {
+        self.guilds
+            .values_mut()
+            .filter_map(|guild| guild.invited.remove(&account).then_some(guild.id))
+            .collect()
+    }
+
+    // Handing out `Leader` makes the old leader an officer
+    pub fn set_rank(&mut self, id: GuildId, account: AccountId, rank: GuildRank) {
+        let Some(guild) = self.guilds.get_mut(&id) else {
+            return;
+        };
+        if rank == GuildRank::Leader {
+            for other in guild.members.values_mut() {
+                if *other == GuildRank::Leader {
+                    *other = GuildRank::Officer;
+                }
+            }
+        }
+        if let Some(current) = guild.members.get_mut(&account) {
+            *current = rank;
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Upgrades without downtime. A server with `handoff.listen_addr` set waits
+// there for a successor: a new process started with `--take-over` and the
+// same config. The successor proves it shares the data directory by sending
+// the secret written there, and gets back the rooms, parties and sessions.
+// The old process then tells its players to reconnect, writes out its stores
+// and exits, and the successor binds the same ports. Players who reconnect
+// and send `Resume` with their session token carry on where they were.
+//
+// The same token lets a client move its session to a new connection when its
+// address changes, e.g. a phone going from Wi-Fi to cellular. With
+// `migration.enabled`, a joined player whose connection drops is held in the
+// game for `migration.grace_secs`, and a `Resume` from a new connection takes
+// the player over, held or still connected, keeping its room, position,
+// party and account; an old connection still open is closed as `Migrated`.
+use crate::accounts::{AccountId, PlayerUuid};
+use crate::chat::ChatHistory;
+use crate::parties::{Parties, PartyId};
+use crate::protocol::Appearance;
+use crate::roles::Role;
+use crate::rooms::{RoomId, Rooms, TeamId};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+
+// Kept in the data directory by a server waiting for a successor
+pub const SECRET_FILE: &str = "handoff.secret";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HandoffConfig {
+    // A loopback address to wait for a successor on; `None` turns upgrades off
+    pub listen_addr: Option<String>,
+    // How long handed-over sessions wait to be resumed before their rooms and
+    // parties let go of them
+    pub resume_secs: u64,
+    // Set by `--take-over`: start from the state of the server on `listen_addr`
+    #[serde(skip)]
+    pub take_over: bool,
+}
+
+impl Default for HandoffConfig {
+    fn default() -> Self {
+        HandoffConfig {
+            listen_addr: None,
+            resume_secs: 30,
+            take_over: false,
+        }
+    }
+}
+
+impl HandoffConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(addr) = &self.listen_addr {
+            let addr: SocketAddr = addr
+                .parse()
+                .map_err(|_| format!("`handoff.listen_addr` `{}` is not an address", addr))?;
+            if !addr.ip().is_loopback() {
+                return Err("`handoff.listen_addr` must be a loopback address".to_string());
+            }
+        } else if self.take_over {
+            return Err("`--take-over` needs `handoff.listen_addr` in the config file".to_string());
+        }
+        if self.resume_secs == 0 {
+            return Err("`handoff.resume_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MigrationConfig {
+    pub enabled: bool,
+    // How long a dropped player waits for a new connection before leaving
+    pub grace_secs: u64,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        MigrationConfig {
+            enabled: false,
+            grace_secs: 30,
+        }
+    }
+}
+
+impl MigrationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.grace_secs == 0 {
+            return Err("`migration.grace_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Everything a successor needs that isn't already in the data directory
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Handoff {
+    pub next_player_id: usize,
+    pub rooms: Rooms,
+    pub parties: Parties,
+    // Sent even when persisted: disconnecting everyone for the upgrade closes
+    // rooms, which forgets their history on disk
+    pub chat_history: ChatHistory,
+    pub sessions: Vec<Session>,
+}
+
+// A player who can come back with `Resume { token }`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub token: String,
+    pub player_id: usize,
+    // Nil from a server too old to have given players one
+    #[serde(default)]
+    pub uuid: PlayerUuid,
+    pub name: Option<String>,
+    pub appearance: Appearance,
+    pub account: Option<AccountId>,
+    pub steam_id: Option<u64>,
+    pub role: Option<Role>,
+    pub room: Option<RoomId>,
+    pub position: Option<(f32, f32)>,
+    pub team: Option<TeamId>,
+    pub spectating: bool,
+    pub muted: BTreeSet<usize>,
+    pub party: Option<PartyId>,
+    // So frames replayed after a resume are still caught
+    #[serde(default)]
+    pub last_nonce: u64,
+}
+
+// The successor's side: collect the state of the server waiting on `addr`.
+// Returns once the old process has written out its stores and exited.
+pub fn take_over(addr: &str, data_dir: &Path) -> io::Result<Handoff> {
+    let secret = fs::read_to_string(data_dir.join(SECRET_FILE))?;
+    let mut stream = TcpStream::connect(addr)?;
+    writeln!(stream, "{}", secret.trim())?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Err(io::Error::other("the running server refused the handoff"));
+    }
+    serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn data_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("handoff-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(SECRET_FILE), "s3cret\n").unwrap();
+        dir
+    }
+
+    // A server waiting for a successor, answering one with `reply` if it
+    // brings the secret
+    fn waiting(reply: Vec<u8>) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut secret = String::new();
+            io::BufReader::new(&stream).read_line(&mut secret).unwrap();
+            if secret.trim() == "s3cret" {
+                stream.write_all(&reply).unwrap();
+            }
+            secret
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn takes_over_with_the_shared_secret() {
+        let handoff = Handoff {
+            next_player_id: 42,
+            ..Handoff::default()
+        };
+        let (addr, server) = waiting(serde_json::to_vec(&handoff).unwrap());
+        let dir = data_dir("take-over");
+        let taken = take_over(&addr, &dir).unwrap();
+        assert_eq!(taken.next_player_id, 42);
+        assert_eq!(server.join().unwrap(), "s3cret\n");
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn a_refused_handoff_is_an_error() {
+        let (addr, server) = waiting(Vec::new());
+        let dir = data_dir("refused");
+        fs::write(dir.join(SECRET_FILE), "wrong").unwrap();
+        assert!(take_over(&addr, &dir).is_err());
+        server.join().unwrap();
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn sessions_from_older_servers_load() {
+        let session: Session = serde_json::from_value(serde_json::json!({
+            "token": "t",
+            "player_id": 3,
+            "name": null,
+            "appearance": Appearance::default(),
+            "account": null,
+            "steam_id": null,
+            "role": null,
+            "room": null,
+            "position": [1.0, 2.0],
+            "team": null,
+            "spectating": false,
+            "muted": [],
+            "party": null,
+        }))
+        .unwrap();
+        assert!(session.uuid.is_nil());
+        assert_eq!(session.last_nonce, 0);
+        assert_eq!(session.position, Some((1.0, 2.0)));
+    }
+
+    #[test]
+    fn only_loopback_addresses_wait_for_a_successor() {
+        let config = |addr: &str| HandoffConfig {
+            listen_addr: Some(addr.to_string()),
+            ..HandoffConfig::default()
+        };
+        assert!(config("127.0.0.1:7000").validate().is_ok());
+        assert!(config("[::1]:7000").validate().is_ok());
+        assert!(config("0.0.0.0:7000").validate().is_err());
+        assert!(config("localhost:7000").validate().is_err());
+        let take_over = HandoffConfig {
+            take_over: true,
+            ..HandoffConfig::default()
+        };
+        assert!(take_over.validate().is_err());
+    }
+}
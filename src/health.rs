@@ -0,0 +1,188 @@
+// Liveness and readiness for orchestrators. The game loop reports every tick
+// here; `/healthz` and `/readyz` (with the `http-api` feature) read it without
+// going through the loop, so they still answer when it is wedged. Under
+// systemd with `WatchdogSec=`, the loop also pings the watchdog once a second.
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HealthConfig {
+    // Serve only `/healthz` and `/readyz` here, without a token, for probes
+    // that shouldn't reach the admin API. They are on `http_listen_addr` too.
+    pub listen_addr: Option<String>,
+    // No tick for this long and the server counts as wedged
+    pub stall_secs: u64,
+    // Ticks running later than this make the server not ready
+    pub max_tick_lag_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            listen_addr: None,
+            stall_secs: 10,
+            max_tick_lag_ms: 250,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen_addr.is_some() && !cfg!(feature = "http-api") {
+            return Err("`health.listen_addr` needs the `http-api` feature".to_string());
+        }
+        if self.stall_secs == 0 {
+            return Err("`health.stall_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub struct Health {
+    started: Instant,
+    // Milliseconds from `started` to the last tick
+    last_tick_ms: AtomicU64,
+    // How far past its schedule the last tick ran
+    tick_lag_ms: AtomicU64,
+    draining: AtomicBool,
+    // The slowest shard tick since the game loop last looked, in microseconds
+    shard_tick_us: AtomicU64,
+    // What the last tick cost, how many steps of `overload.shed` are taken and
+    // how many ticks have overrun since the start
+    tick_cost_us: AtomicU64,
+    shedding: AtomicUsize,
+    overruns: AtomicU64,
+    // The last failed storage write, until one succeeds
+    storage_error: Mutex<Option<String>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            started: Instant::now(),
+            last_tick_ms: AtomicU64::new(0),
+            tick_lag_ms: AtomicU64::new(0),
+            draining: AtomicBool::new(false),
+            shard_tick_us: AtomicU64::new(0),
+            tick_cost_us: AtomicU64::new(0),
+            shedding: AtomicUsize::new(0),
+            overruns: AtomicU64::new(0),
+            storage_error: Mutex::new(None),
+        }
+    }
+}
+
+impl Health {
+    pub fn ticked(&self, lag: Duration, draining: bool) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_tick_ms.store(now, Ordering::Relaxed);
+        self.tick_lag_ms
+            .store(lag.as_millis() as u64, Ordering::Relaxed);
+        self.draining.store(draining, Ordering::Relaxed);
+    }
+
+    pub fn since_last_tick(&self) -> Duration {
+        let last = Duration::from_millis(self.last_tick_ms.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+
+    pub fn tick_lag(&self) -> Duration {
+        Duration::from_millis(self.tick_lag_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn shard_ticked(&self, took: Duration) {
+        self.shard_tick_us
+            .fetch_max(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    // The slowest shard tick since the last call
+    pub fn take_shard_tick(&self) -> Duration {
+        Duration::from_micros(self.shard_tick_us.swap(0, Ordering::Relaxed))
+    }
+
+    pub fn loaded(&self, cost: Duration, shedding: usize, overruns: u64) {
+        self.tick_cost_us
+            .store(cost.as_micros() as u64, Ordering::Relaxed);
+        self.shedding.store(shedding, Ordering::Relaxed);
+        self.overruns.store(overruns, Ordering::Relaxed);
+    }
+
+    pub fn tick_cost(&self) -> Duration {
+        Duration::from_micros(self.tick_cost_us.load(Ordering::Relaxed))
+    }
+
+    pub fn shedding(&self) -> usize {
+        self.shedding.load(Ordering::Relaxed)
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn stored(&self, result: Result<(), String>) {
+        *self.storage_error.lock().unwrap() = result.err();
+    }
+
+    pub fn storage_error(&self) -> Option<String> {
+        self.storage_error.lock().unwrap().clone()
+    }
+}
+
+// The systemd notification socket, when started by a unit that watches one
+pub struct Watchdog {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    #[cfg(unix)]
+    addr: std::os::unix::net::SocketAddr,
+}
+
+impl Watchdog {
+    #[cfg(unix)]
+    pub fn from_env() -> Option<Watchdog> {
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        // A leading `@` is a Linux abstract socket
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name).ok()?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => return None,
+            None => SocketAddr::from_pathname(&path).ok()?,
+        };
+        let socket = UnixDatagram::unbound().ok()?;
+        Some(Watchdog { socket, addr })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env() -> Option<Watchdog> {
+        None
+    }
+
+    // Started and serving
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    // The game loop is still turning
+    pub fn alive(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    #[cfg(unix)]
+    fn notify(&self, state: &str) {
+        self.socket.send_to_addr(state.as_bytes(), &self.addr).ok();
+    }
+
+    #[cfg(not(unix))]
+    fn notify(&self, _state: &str) {}
+}
@@ -0,0 +1,79 @@
+// Match history: how each finished round and scenario went, newest last,
+// persisted across restarts and shown by `matches`. Only the latest
+// `MAX_MATCHES` are kept.
+use crate::analytics::unix_now;
+use crate::difficulty::Difficulty;
+use crate::rooms::{RoomId, TeamId};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const MAX_MATCHES: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchRecord {
+    // Unix timestamp
+    pub ended: u64,
+    // `None` is the lobby
+    pub room_id: Option<RoomId>,
+    pub room_name: String,
+    // The scenario finished, for a scenario; otherwise a round
+    #[serde(default)]
+    pub scenario: Option<String>,
+    // `None` for a draw, and for scenarios
+    #[serde(default)]
+    pub winner: Option<TeamId>,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MatchHistory {
+    pub matches: VecDeque<MatchRecord>,
+}
+
+impl MatchHistory {
+    pub const STORAGE_KEY: &'static str = "match_history";
+
+    pub fn record(
+        &mut self,
+        room_id: Option<RoomId>,
+        room_name: String,
+        scenario: Option<String>,
+        winner: Option<TeamId>,
+        difficulty: Difficulty,
+    ) {
+        self.matches.push_back(MatchRecord {
+            ended: unix_now(),
+            room_id,
+            room_name,
+            scenario,
+            winner,
+            difficulty,
+        });
+        while self.matches.len() > MAX_MATCHES {
+            self.matches.pop_front();
+        }
+    }
+}
+
+impl MatchRecord {
+    pub fn describe(&self) -> String {
+        let outcome = match (&self.scenario, self.winner) {
+            (Some(scenario), _) => format!("finished scenario {}", scenario),
+            (None, Some(team)) => format!("won by team {}", team),
+            (None, None) => "drawn".to_string(),
+        };
+        let room = match self.room_id {
+            Some(room_id) => format!("room {} ({})", room_id, self.room_name),
+            None => "the lobby".to_string(),
+        };
+        format!(
+            "{} in {}, {}, at {}",
+            self.ended,
+            room,
+            outcome,
+            self.difficulty.describe()
+        )
+    }
+}
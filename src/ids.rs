@@ -0,0 +1,143 @@
+// Handing out player ids. Ids come in order from the worker's block (see
+// `cluster::ClusterConfig::player_ids`), skipping any still held: by a
+// connected player, a session taken over in a handoff that may yet resume,
+// or a player moved here from another worker. Past the end of the block the
+// count starts over, so a freed id is only given out again once every other
+// one has been, long after anyone could still be talking about its last
+// owner. An id is never given to two players at once.
+use std::collections::HashSet;
+use std::ops::Range;
+
+#[derive(Debug)]
+pub struct PlayerIds {
+    range: Range<usize>,
+    next: usize,
+    held: HashSet<usize>,
+}
+
+impl Default for PlayerIds {
+    fn default() -> Self {
+        PlayerIds::new(1..usize::MAX)
+    }
+}
+
+impl PlayerIds {
+    pub fn new(range: Range<usize>) -> Self {
+        PlayerIds {
+            next: range.start,
+            range,
+            held: HashSet::new(),
+        }
+    }
+
+    // `None` once every id in the block is held
+    pub fn allocate(&mut self) -> Option<usize> {
+        if self.held.len() >= self.range.len() {
+            return None;
+        }
+        while self.held.contains(&self.next) {
+            self.advance();
+        }
+        let id = self.next;
+        self.held.insert(id);
+        self.advance();
+        Some(id)
+    }
+
+    // Hold an id that was handed out before, as a resumed or moved session
+    // brings it back; false if someone else holds it already
+    pub fn claim(&mut self, id: usize) -> bool {
+        self.held.insert(id)
+    }
+
+    pub fn release(&mut self, id: usize) {
+        self.held.remove(&id);
+    }
+
+    pub fn is_held(&self, id: usize) -> bool {
+        self.held.contains(&id)
+    }
+
+    // Where counting carries on from, for the next process after a handoff
+    pub fn next(&self) -> usize {
+        self.next
+    }
+
+    // Carry on counting where the previous process stopped; anything outside
+    // the block, like the 0 of a handoff without one, starts it over
+    pub fn resume_at(&mut self, next: usize) {
+        self.next = match self.range.contains(&next) {
+            true => next,
+            false => self.range.start,
+        };
+    }
+
+    fn advance(&mut self) {
+        self.next += 1;
+        if self.next >= self.range.end {
+            self.next = self.range.start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_in_order_and_runs_out() {
+        let mut ids = PlayerIds::new(1..4);
+        assert_eq!(ids.allocate(), Some(1));
+        assert_eq!(ids.allocate(), Some(2));
+        assert_eq!(ids.allocate(), Some(3));
+        assert_eq!(ids.allocate(), None);
+    }
+
+    #[test]
+    fn wraps_around_past_held_ids() {
+        let mut ids = PlayerIds::new(10..13);
+        let (a, b, c) = (ids.allocate(), ids.allocate(), ids.allocate());
+        assert_eq!((a, b, c), (Some(10), Some(11), Some(12)));
+        ids.release(11);
+        assert_eq!(ids.allocate(), Some(11));
+        ids.release(10);
+        ids.release(12);
+        // Counting carries on after 11 rather than reusing the lowest free id
+        assert_eq!(ids.allocate(), Some(12));
+        assert_eq!(ids.allocate(), Some(10));
+        assert_eq!(ids.allocate(), None);
+    }
+
+    #[test]
+    fn freed_ids_wait_for_the_rest_of_the_block() {
+        let mut ids = PlayerIds::new(1..6);
+        let first = ids.allocate().unwrap();
+        ids.release(first);
+        let next: Vec<_> = (0..4).map(|_| ids.allocate().unwrap()).collect();
+        assert_eq!(next, [2, 3, 4, 5]);
+        assert_eq!(ids.allocate(), Some(first));
+    }
+
+    #[test]
+    fn claimed_ids_are_skipped() {
+        let mut ids = PlayerIds::new(1..10);
+        assert!(ids.claim(1));
+        assert!(ids.claim(2));
+        assert!(!ids.claim(2));
+        assert_eq!(ids.allocate(), Some(3));
+        assert!(ids.is_held(1));
+    }
+
+    #[test]
+    fn resumes_only_inside_the_block() {
+        let mut ids = PlayerIds::new(100..200);
+        ids.resume_at(150);
+        assert_eq!(ids.allocate(), Some(150));
+        assert_eq!(ids.next(), 151);
+        ids.resume_at(0);
+        assert_eq!(ids.allocate(), Some(100));
+        ids.resume_at(199);
+        assert_eq!(ids.allocate(), Some(199));
+        assert_eq!(ids.next(), 100);
+    }
+}
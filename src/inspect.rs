@@ -0,0 +1,139 @@
+// Serializable views of the live server state, for debugging and dashboards
+use crate::accounts::{AccountId, PlayerUuid};
+use crate::buffers::PoolStats;
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::difficulty::Difficulty;
+use crate::guilds::GuildId;
+use crate::parties::PartyId;
+use crate::protocol::Appearance;
+use crate::roles::Role;
+use crate::rooms::{Room, RoomId, TeamId};
+use crate::state::{GameState, Player, ServerModes};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub struct PlayerInfo {
+    pub id: usize,
+    pub name: Option<String>,
+    pub appearance: Appearance,
+    pub account: Option<AccountId>,
+    pub steam_id: Option<u64>,
+    pub uuid: PlayerUuid,
+    pub guest: bool,
+    pub address: String,
+    pub x: f32,
+    pub y: f32,
+    pub message: String,
+    pub joined: bool,
+    pub room: Option<RoomId>,
+    pub party: Option<PartyId>,
+    pub guild: Option<GuildId>,
+    pub team: Option<TeamId>,
+    pub spectating: bool,
+    pub role: Option<Role>,
+    pub wire_format: WireFormat,
+    pub snapshot_format: Option<SnapshotFormat>,
+    pub connected_secs: u64,
+    pub idle_secs: u64,
+}
+
+impl PlayerInfo {
+    // The position comes from the player's shard
+    pub fn new(state: &GameState, p: &Player) -> Self {
+        let (x, y) = state.position(p).unwrap_or_default();
+        PlayerInfo {
+            id: p.id,
+            name: p.name.clone(),
+            appearance: p.appearance.clone(),
+            account: p.account,
+            steam_id: p.steam_id,
+            uuid: p.uuid,
+            guest: p.is_guest(),
+            address: p.endpoint.to_string(),
+            x,
+            y,
+            message: p.message.clone(),
+            joined: p.joined,
+            room: p.room,
+            party: p.party,
+            guild: p.guild,
+            team: p.team,
+            spectating: p.spectating,
+            role: p.role,
+            wire_format: p.wire_format,
+            snapshot_format: p.snapshot_format,
+            connected_secs: p.connected_at.elapsed().as_secs(),
+            idle_secs: p.last_seen.elapsed().as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub name: String,
+    pub protected: bool,
+    pub persistent: bool,
+    pub members: Vec<usize>,
+    pub difficulty: Difficulty,
+}
+
+impl From<&Room> for RoomInfo {
+    fn from(room: &Room) -> Self {
+        RoomInfo {
+            id: room.id,
+            name: room.name.clone(),
+            protected: room.is_protected(),
+            persistent: room.persistent,
+            members: room.members.iter().copied().collect(),
+            difficulty: room.difficulty,
+        }
+    }
+}
+
+// Everything the server knows, keyed by id so a pointer like `/players/3`
+// picks out player 3
+#[derive(Serialize)]
+pub struct StateDump {
+    pub tick: u64,
+    pub players: BTreeMap<usize, PlayerInfo>,
+    pub rooms: BTreeMap<RoomId, RoomInfo>,
+    pub modes: ServerModes,
+    pub buffers: PoolStats,
+}
+
+impl StateDump {
+    pub fn capture(state: &GameState, tick: u64) -> Self {
+        StateDump {
+            tick,
+            players: state
+                .players
+                .iter()
+                .map(|p| (p.id, PlayerInfo::new(state, &p)))
+                .collect(),
+            rooms: state
+                .rooms
+                .read()
+                .unwrap()
+                .iter()
+                .map(|room| (room.id, RoomInfo::from(room)))
+                .collect(),
+            modes: state.modes.read().unwrap().clone(),
+            buffers: state.buffers.snapshot(),
+        }
+    }
+
+    // The dump as JSON, or only the part at a JSON pointer such as
+    // `/players/3/room` or `/modes/whitelist`
+    pub fn to_json(&self, pointer: Option<&str>) -> Result<serde_json::Value, String> {
+        let value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        match pointer {
+            None | Some("" | "/") => Ok(value),
+            Some(pointer) => value
+                .pointer(pointer)
+                .cloned()
+                .ok_or_else(|| format!("nothing at `{}`", pointer)),
+        }
+    }
+}
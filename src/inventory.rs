@@ -0,0 +1,500 @@
+// What each account holds, by item, kept by the server so that what a
+// player spends, such as on crafting (see `crate::crafting`), can be checked
+// against it. Loot goes in here; item grants by mail are still the game's to
+// hand out. Items are opaque names, as in mail. Guests hold nothing.
+//
+// What an account can hold is capped by `inventory`: a number of slots,
+// each taking a stack of one item of up to its stack size, and a total
+// weight. Every pickup, loot grant, trade and craft is checked against the
+// caps before anything changes hands, so no client can end up holding what
+// couldn't fit. One that leaves an inventory no fuller than it was always
+// goes ahead, so lowering the caps never strands anyone.
+//
+// Each stack an account holds has an id of its own, minted by the server
+// when items come into being: rolled as loot, bought, crafted or granted by
+// mail. Items dropped on the ground keep theirs (see `crate::loot`), and a
+// trade can name the stack it sells from, so a message replayed after its
+// stack is gone is caught rather than taking another. The latest
+// `MAX_RETIRED` used-up ids are remembered, to refuse them coming back.
+use crate::accounts::AccountId;
+use crate::mail::{ItemGrant, ItemId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+const MAX_RETIRED: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InventoryConfig {
+    // No cap on slots or weight when unset
+    pub slots: Option<u32>,
+    pub max_weight: Option<f32>,
+    // The stack size of items `items` doesn't give one; unset stacks without
+    // limit
+    pub stack_size: Option<u32>,
+    pub items: BTreeMap<String, ItemRules>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ItemRules {
+    pub stack_size: Option<u32>,
+    // Of one item
+    pub weight: f32,
+}
+
+impl InventoryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .max_weight
+            .is_some_and(|max| !(max.is_finite() && max >= 0.0))
+        {
+            return Err("`inventory.max_weight` must be 0 or more".to_string());
+        }
+        if self.stack_size == Some(0) {
+            return Err("`inventory.stack_size` must be at least 1".to_string());
+        }
+        for (item, rules) in &self.items {
+            if rules.stack_size == Some(0) {
+                return Err(format!("item `{}` needs a stack size of at least 1", item));
+            }
+            if !(rules.weight.is_finite() && rules.weight >= 0.0) {
+                return Err(format!("item `{}` must weigh 0 or more", item));
+            }
+        }
+        Ok(())
+    }
+
+    // The slots and weight these items take up
+    fn load(&self, items: &BTreeMap<String, u32>) -> (u64, f64) {
+        let mut slots = 0;
+        let mut weight = 0.0;
+        for (item, &quantity) in items {
+            let rules = self.items.get(item);
+            let stack_size = rules.and_then(|rules| rules.stack_size).or(self.stack_size);
+            slots += match stack_size {
+                Some(stack_size) => (quantity as u64).div_ceil(stack_size as u64),
+                None => 1,
+            };
+            weight += rules.map_or(0.0, |rules| rules.weight as f64) * quantity as f64;
+        }
+        (slots, weight)
+    }
+
+    // Whether going from holding `before` to holding `after` breaks a cap
+    fn check(
+        &self,
+        before: &BTreeMap<String, u32>,
+        after: &BTreeMap<String, u32>,
+    ) -> Result<(), String> {
+        let (slots_before, weight_before) = self.load(before);
+        let (slots, weight) = self.load(after);
+        if let Some(max) = self.slots {
+            if slots > max as u64 && slots > slots_before {
+                return Err(format!("that needs {} slots of {}", slots, max));
+            }
+        }
+        if let Some(max) = self.max_weight {
+            if weight > max as f64 && weight > weight_before {
+                return Err(format!("that weighs {} of {} at most", weight, max));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Why items couldn't change hands
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExchangeError {
+    // What the account is short of
+    Missing(Vec<ItemGrant>),
+    // What cap it would break
+    Full(String),
+    // An item that is already held, or was used up: a replayed or forged
+    // message, or items being duplicated
+    Reused(ItemId),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Inventories {
+    // Each account's stacks, every one with an `id` of its own
+    pub stacks: BTreeMap<AccountId, Vec<ItemGrant>>,
+    // What accounts held before items had ids, given ids by `migrate`
+    #[serde(rename = "items", skip_serializing)]
+    legacy: BTreeMap<AccountId, BTreeMap<String, u32>>,
+    // The latest ids used up, to catch anything still trying to use them
+    retired: VecDeque<ItemId>,
+    // Who holds each stack, rebuilt by `migrate`
+    #[serde(skip)]
+    holders: HashMap<ItemId, AccountId>,
+}
+
+impl Inventories {
+    pub const STORAGE_KEY: &'static str = "inventories";
+
+    // Once loaded: give ids to what was held before there were any, and
+    // index who holds what
+    pub fn migrate(&mut self) {
+        for (account, items) in std::mem::take(&mut self.legacy) {
+            let stacks = self.stacks.entry(account).or_default();
+            stacks.extend(items.into_iter().map(|(item, quantity)| ItemGrant {
+                item,
+                quantity,
+                id: Some(ItemId::new_v4()),
+            }));
+        }
+        self.holders = self
+            .stacks
+            .iter()
+            .flat_map(|(&account, stacks)| {
+                stacks
+                    .iter()
+                    .filter_map(move |stack| Some((stack.id?, account)))
+            })
+            .collect();
+    }
+
+    // Every stack `account` holds, oldest first
+    pub fn of(&self, account: AccountId) -> Vec<ItemGrant> {
+        self.stacks.get(&account).cloned().unwrap_or_default()
+    }
+
+    pub fn holder(&self, id: ItemId) -> Option<AccountId> {
+        self.holders.get(&id).copied()
+    }
+
+    // How many of each item the account holds, in item order
+    fn totals(&self, account: AccountId) -> BTreeMap<String, u32> {
+        let mut totals = BTreeMap::new();
+        for stack in self.stacks.get(&account).into_iter().flatten() {
+            let total: &mut u32 = totals.entry(stack.item.clone()).or_default();
+            *total = total.saturating_add(stack.quantity);
+        }
+        totals
+    }
+
+    // What of `needs` the account is short of, by how many. A need with an
+    // id is short of whatever that stack doesn't hold.
+    pub fn missing(&self, account: AccountId, needs: &[ItemGrant]) -> Vec<ItemGrant> {
+        let totals = self.totals(account);
+        let stacks = self.stacks.get(&account);
+        needs
+            .iter()
+            .filter_map(|need| {
+                let have = match need.id {
+                    Some(id) => stacks
+                        .and_then(|stacks| stacks.iter().find(|stack| stack.id == Some(id)))
+                        .filter(|stack| stack.item == need.item)
+                        .map_or(0, |stack| stack.quantity),
+                    None => totals.get(&need.item).copied().unwrap_or(0),
+                };
+                let short = need.quantity.saturating_sub(have);
+                (short > 0).then(|| ItemGrant {
+                    quantity: short,
+                    ..need.clone()
+                })
+            })
+            .collect()
+    }
+
+    // Give `grants` if they fit, or change nothing and say why not, returning
+    // the stacks they went into
+    pub fn add(
+        &mut self,
+        config: &InventoryConfig,
+        account: AccountId,
+        grants: &[ItemGrant],
+    ) -> Result<Vec<ItemGrant>, ExchangeError> {
+        self.exchange(config, account, &[], grants)
+    }
+
+    // Take `take` and give `give` all at once, or change nothing and say why
+    // not, returning the stacks `give` went into. Taking an item by id takes
+    // from that stack; otherwise from the oldest stacks of it. New items,
+    // without an id, join the account's oldest stack of the same item or
+    // start one; items that already have an id keep it, and are refused if
+    // it is held or was used up.
+    pub fn exchange(
+        &mut self,
+        config: &InventoryConfig,
+        account: AccountId,
+        take: &[ItemGrant],
+        give: &[ItemGrant],
+    ) -> Result<Vec<ItemGrant>, ExchangeError> {
+        let retired = take
+            .iter()
+            .chain(give)
+            .filter_map(|grant| grant.id)
+            .filter(|id| self.retired.contains(id));
+        let held = give
+            .iter()
+            .filter_map(|grant| grant.id)
+            .filter(|&id| self.holder(id).is_some());
+        if let Some(id) = retired.chain(held).next() {
+            return Err(ExchangeError::Reused(id));
+        }
+        let missing = self.missing(account, take);
+        if !missing.is_empty() {
+            return Err(ExchangeError::Missing(missing));
+        }
+        let before = self.totals(account);
+        let mut stacks = self.stacks.get(&account).cloned().unwrap_or_default();
+        for grant in take {
+            let mut left = grant.quantity;
+            for stack in stacks.iter_mut().filter(|stack| {
+                stack.item == grant.item && grant.id.is_none_or(|id| stack.id == Some(id))
+            }) {
+                let taken = left.min(stack.quantity);
+                stack.quantity -= taken;
+                left -= taken;
+            }
+        }
+        let mut given = Vec::new();
+        for grant in give.iter().filter(|grant| grant.quantity > 0) {
+            let index = match grant.id {
+                Some(_) => None,
+                None => stacks
+                    .iter()
+                    .position(|stack| stack.item == grant.item && stack.quantity > 0),
+            };
+            let index = index.unwrap_or_else(|| {
+                stacks.push(ItemGrant {
+                    quantity: 0,
+                    id: grant.id.or_else(|| Some(ItemId::new_v4())),
+                    ..grant.clone()
+                });
+                stacks.len() - 1
+            });
+            let stack = &mut stacks[index];
+            stack.quantity = stack.quantity.saturating_add(grant.quantity);
+            given.push(ItemGrant {
+                quantity: grant.quantity,
+                ..stack.clone()
+            });
+        }
+        let mut after = BTreeMap::new();
+        for stack in &stacks {
+            let total: &mut u32 = after.entry(stack.item.clone()).or_default();
+            *total = total.saturating_add(stack.quantity);
+        }
+        after.retain(|_, &mut total| total > 0);
+        config.check(&before, &after).map_err(ExchangeError::Full)?;
+        let (kept, used_up): (Vec<ItemGrant>, Vec<ItemGrant>) =
+            stacks.into_iter().partition(|stack| stack.quantity > 0);
+        for id in used_up.iter().filter_map(|stack| stack.id) {
+            self.retire(id);
+        }
+        for id in kept.iter().filter_map(|stack| stack.id) {
+            self.holders.insert(id, account);
+        }
+        match kept.is_empty() {
+            true => self.stacks.remove(&account),
+            false => self.stacks.insert(account, kept),
+        };
+        Ok(given)
+    }
+
+    fn retire(&mut self, id: ItemId) {
+        self.holders.remove(&id);
+        self.retired.push_back(id);
+        while self.retired.len() > MAX_RETIRED {
+            self.retired.pop_front();
+        }
+    }
+
+    pub fn forget(&mut self, account: AccountId) {
+        for stack in self.stacks.remove(&account).into_iter().flatten() {
+            if let Some(id) = stack.id {
+                self.retire(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: AccountId = 1;
+
+    fn items(item: &str, quantity: u32) -> ItemGrant {
+        ItemGrant {
+            item: item.to_string(),
+            quantity,
+            id: None,
+        }
+    }
+
+    fn held(inventories: &Inventories, item: &str) -> u32 {
+        inventories.totals(ACCOUNT).get(item).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn exchanges_happen_whole_or_not_at_all() {
+        let config = InventoryConfig::default();
+        let mut inventories = Inventories::default();
+        inventories
+            .add(&config, ACCOUNT, &[items("wood", 5)])
+            .unwrap();
+        let take = [items("wood", 3), items("stone", 1)];
+        assert_eq!(
+            inventories.exchange(&config, ACCOUNT, &take, &[items("axe", 1)]),
+            Err(ExchangeError::Missing(vec![items("stone", 1)]))
+        );
+        assert_eq!(held(&inventories, "wood"), 5);
+        assert_eq!(held(&inventories, "axe"), 0);
+
+        let given = inventories
+            .exchange(&config, ACCOUNT, &take[..1], &[items("plank", 6)])
+            .unwrap();
+        assert_eq!(given.len(), 1);
+        assert_eq!(
+            (held(&inventories, "wood"), held(&inventories, "plank")),
+            (2, 6)
+        );
+        // Spending the rest leaves nothing behind
+        inventories
+            .exchange(
+                &config,
+                ACCOUNT,
+                &[items("wood", 2), items("plank", 6)],
+                &[],
+            )
+            .unwrap();
+        assert!(inventories.of(ACCOUNT).is_empty());
+    }
+
+    #[test]
+    fn caps_hold_only_against_growing() {
+        let config = InventoryConfig {
+            slots: Some(2),
+            max_weight: Some(10.0),
+            stack_size: Some(5),
+            items: BTreeMap::from([(
+                "ore".to_string(),
+                ItemRules {
+                    stack_size: None,
+                    weight: 3.0,
+                },
+            )]),
+        };
+        let mut inventories = Inventories::default();
+        // Eight stones take two stacks of five
+        inventories
+            .add(&config, ACCOUNT, &[items("stone", 8)])
+            .unwrap();
+        assert!(matches!(
+            inventories.add(&config, ACCOUNT, &[items("stone", 3)]),
+            Err(ExchangeError::Full(_))
+        ));
+        inventories
+            .add(&config, ACCOUNT, &[items("stone", 2)])
+            .unwrap();
+        assert_eq!(held(&inventories, "stone"), 10);
+
+        let mut inventories = Inventories::default();
+        inventories
+            .add(&config, ACCOUNT, &[items("ore", 3)])
+            .unwrap();
+        assert!(matches!(
+            inventories.add(&config, ACCOUNT, &[items("ore", 1)]),
+            Err(ExchangeError::Full(_))
+        ));
+
+        // Under tighter caps, whatever leaves the inventory no fuller goes ahead
+        let tighter = InventoryConfig {
+            max_weight: Some(1.0),
+            ..config
+        };
+        inventories
+            .exchange(&tighter, ACCOUNT, &[items("ore", 1)], &[])
+            .unwrap();
+        assert_eq!(held(&inventories, "ore"), 2);
+    }
+
+    #[test]
+    fn stacks_keep_their_ids_and_used_up_ones_stay_away() {
+        let config = InventoryConfig::default();
+        let mut inventories = Inventories::default();
+        let stack = inventories
+            .add(&config, ACCOUNT, &[items("sword", 1)])
+            .unwrap()
+            .remove(0);
+        let id = stack.id.unwrap();
+        assert_eq!(inventories.holder(id), Some(ACCOUNT));
+        // Already held
+        assert_eq!(
+            inventories.add(&config, 2, std::slice::from_ref(&stack)),
+            Err(ExchangeError::Reused(id))
+        );
+        // More of the same joins the stack
+        let more = inventories
+            .add(&config, ACCOUNT, &[items("sword", 1)])
+            .unwrap();
+        assert_eq!(more[0].id, Some(id));
+
+        inventories
+            .exchange(
+                &config,
+                ACCOUNT,
+                &[ItemGrant {
+                    quantity: 2,
+                    ..stack.clone()
+                }],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(inventories.holder(id), None);
+        // A replayed message naming the stack is caught
+        assert_eq!(
+            inventories.exchange(&config, ACCOUNT, std::slice::from_ref(&stack), &[]),
+            Err(ExchangeError::Reused(id))
+        );
+        assert_eq!(
+            inventories.add(&config, 2, &[stack]),
+            Err(ExchangeError::Reused(id))
+        );
+    }
+
+    #[test]
+    fn legacy_items_get_ids() {
+        let mut inventories: Inventories = serde_json::from_value(serde_json::json!({
+            "items": { "1": { "wood": 3 } },
+        }))
+        .unwrap();
+        inventories.migrate();
+        let stacks = inventories.of(ACCOUNT);
+        assert_eq!(stacks.len(), 1);
+        assert_eq!((stacks[0].item.as_str(), stacks[0].quantity), ("wood", 3));
+        assert_eq!(inventories.holder(stacks[0].id.unwrap()), Some(ACCOUNT));
+    }
+
+    #[test]
+    fn validates_caps() {
+        let bad = [
+            InventoryConfig {
+                max_weight: Some(-1.0),
+                ..InventoryConfig::default()
+            },
+            InventoryConfig {
+                stack_size: Some(0),
+                ..InventoryConfig::default()
+            },
+            InventoryConfig {
+                items: BTreeMap::from([(
+                    "ore".to_string(),
+                    ItemRules {
+                        stack_size: None,
+                        weight: f32::NAN,
+                    },
+                )]),
+                ..InventoryConfig::default()
+            },
+        ];
+        for config in bad {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+        assert!(InventoryConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+// Logging in with a JSON Web Token from an outside identity provider. The
+// server never issues tokens: it checks the signature against the configured
+// keys or the provider's JWKS, then the expiry, audience and issuer, and maps
+// the `sub` claim to an account.
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct JwtConfig {
+    // Remote players must send a valid `TokenLogin` before they join
+    pub required: bool,
+    pub keys: Vec<JwtKey>,
+    // Fetched on start, then every `jwks_refresh_secs`
+    pub jwks_url: Option<String>,
+    pub jwks_refresh_secs: u64,
+    // Tokens must carry an `aud` naming one of these
+    pub audience: Vec<String>,
+    pub issuer: Option<String>,
+    // Clock skew allowed when checking `exp` and `nbf`
+    pub leeway_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            required: false,
+            keys: Vec::new(),
+            jwks_url: None,
+            jwks_refresh_secs: 3600,
+            audience: Vec::new(),
+            issuer: None,
+            leeway_secs: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JwtKey {
+    // Matched against the token header's `kid`; a key without one is tried
+    // for every token
+    #[serde(default)]
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    // The shared secret for HS256/384/512, otherwise a PEM public key
+    pub key: String,
+}
+
+impl JwtKey {
+    fn decoding_key(&self) -> Result<DecodingKey, String> {
+        let key = self.key.as_bytes();
+        match self.algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                Ok(DecodingKey::from_secret(key))
+            }
+            Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(key),
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(key),
+            _ => DecodingKey::from_rsa_pem(key),
+        }
+        .map_err(|e| format!("bad {:?} key in `jwt.keys`: {}", self.algorithm, e))
+    }
+}
+
+impl JwtConfig {
+    pub fn enabled(&self) -> bool {
+        !self.keys.is_empty() || self.jwks_url.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.required && !self.enabled() {
+            return Err("`jwt.required` needs `jwt.keys` or `jwt.jwks_url`".to_string());
+        }
+        if self.enabled() && self.audience.is_empty() {
+            return Err("`jwt.audience` must name at least one audience".to_string());
+        }
+        if self.jwks_refresh_secs == 0 {
+            return Err("`jwt.jwks_refresh_secs` must be a positive integer".to_string());
+        }
+        for key in &self.keys {
+            key.decoding_key()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+struct Key {
+    kid: Option<String>,
+    algorithms: Vec<Algorithm>,
+    key: DecodingKey,
+}
+
+// The keys tokens are checked against: the configured ones, plus the last
+// JWKS fetched from the provider
+pub struct Verifier {
+    config: JwtConfig,
+    keys: Vec<Key>,
+    jwks: Vec<Key>,
+}
+
+impl Verifier {
+    pub fn new(config: JwtConfig) -> Self {
+        let mut verifier = Verifier {
+            config: JwtConfig::default(),
+            keys: Vec::new(),
+            jwks: Vec::new(),
+        };
+        verifier.reconfigure(config);
+        verifier
+    }
+
+    // Keeps the fetched JWKS; the caller refetches if the URL changed
+    pub fn reconfigure(&mut self, config: JwtConfig) {
+        // `JwtConfig::validate` already parsed every key
+        self.keys = config
+            .keys
+            .iter()
+            .filter_map(|key| {
+                Some(Key {
+                    kid: key.kid.clone(),
+                    algorithms: vec![key.algorithm],
+                    key: key.decoding_key().ok()?,
+                })
+            })
+            .collect();
+        self.config = config;
+    }
+
+    // Replace the provider's keys, returning how many were usable
+    pub fn set_jwks(&mut self, jwks: &JwkSet) -> usize {
+        self.jwks = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                Some(Key {
+                    kid: jwk.common.key_id.clone(),
+                    algorithms: jwk_algorithms(jwk),
+                    key: DecodingKey::from_jwk(jwk).ok()?,
+                })
+            })
+            .filter(|key| !key.algorithms.is_empty())
+            .collect();
+        self.jwks.len()
+    }
+
+    // The token's subject, or why the token was refused
+    pub fn verify(&self, token: &str) -> Result<String, String> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+        let candidates = self.keys.iter().chain(&self.jwks).filter(|key| {
+            key.algorithms.contains(&header.alg) && (key.kid.is_none() || key.kid == header.kid)
+        });
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.config.leeway_secs;
+        validation.set_audience(&self.config.audience);
+        let mut required = vec!["exp", "aud", "sub"];
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+            required.push("iss");
+        }
+        validation.set_required_spec_claims(&required);
+
+        let mut refusal = "no key matches the token".to_string();
+        for key in candidates {
+            match jsonwebtoken::decode::<Claims>(token, &key.key, &validation) {
+                Ok(data) => return Ok(data.claims.sub),
+                // Another key with the same `kid`, or none, may still fit
+                Err(e) if *e.kind() == ErrorKind::InvalidSignature => refusal = e.to_string(),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Err(refusal)
+    }
+}
+
+// What a JWKS key may verify: the algorithm it is pinned to, or every
+// signing algorithm of its kind
+fn jwk_algorithms(jwk: &Jwk) -> Vec<Algorithm> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string())
+            .ok()
+            .into_iter()
+            .collect();
+    }
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => Vec::new(),
+        },
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        // Shared secrets have no business in a public key set
+        AlgorithmParameters::OctetKey(_) => Vec::new(),
+    }
+}
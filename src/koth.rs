@@ -0,0 +1,163 @@
+// King of the hill, played in any room whose map (see `crate::maps`) has a
+// `koth` section. Each tick the team with the most players standing on a hill
+// holds it; if two or more teams tie for the most it is contested and no one
+// does. Every second a team holds a hill earns it `points_per_second`, so
+// holding two earns twice as fast. The first team to `score_limit` wins the
+// round, reported with the same `TeamScore` and `RoundOver` messages as
+// capture the flag (see `crate::ctf`).
+use crate::maps::{Occupant, Shape};
+use crate::rooms::TeamId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hill {
+    pub name: String,
+    pub area: Shape,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct KothMap {
+    pub hills: Vec<Hill>,
+    pub points_per_second: u32,
+    // Points that win a round; 0 plays on forever
+    pub score_limit: u32,
+}
+
+impl Default for KothMap {
+    fn default() -> Self {
+        KothMap {
+            hills: Vec::new(),
+            points_per_second: 1,
+            score_limit: 100,
+        }
+    }
+}
+
+impl KothMap {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.hills.is_empty() {
+            return Err("`koth` needs at least one hill".to_string());
+        }
+        for (index, hill) in self.hills.iter().enumerate() {
+            hill.area
+                .validate()
+                .map_err(|e| format!("hill `{}`: {}", hill.name, e))?;
+            if self.hills[..index]
+                .iter()
+                .any(|other| other.name == hill.name)
+            {
+                return Err(format!("two hills are called `{}`", hill.name));
+            }
+        }
+        if self.points_per_second == 0 {
+            return Err("`koth.points_per_second` must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Control {
+    #[default]
+    Empty,
+    Held(TeamId),
+    Contested,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KothEvent {
+    // Who holds the hill at `index` in `KothMap::hills` changed
+    Control { index: usize, control: Control },
+    Scored { team: TeamId, score: u32 },
+    // The round ends; scores stand as they are until `reset`
+    Won { team: TeamId },
+}
+
+#[derive(Debug)]
+pub struct Koth {
+    pub map: KothMap,
+    control: Vec<Control>,
+    // Ticks each team has held a hill for, counting each hill, this round
+    held: BTreeMap<TeamId, u64>,
+    scores: BTreeMap<TeamId, u32>,
+}
+
+impl Koth {
+    pub fn new(map: KothMap) -> Self {
+        let control = vec![Control::Empty; map.hills.len()];
+        Koth {
+            map,
+            control,
+            held: BTreeMap::new(),
+            scores: BTreeMap::new(),
+        }
+    }
+
+    // Each hill with who holds it
+    pub fn hills(&self) -> impl Iterator<Item = (&Hill, Control)> + '_ {
+        self.map.hills.iter().zip(self.control.iter().copied())
+    }
+
+    // Every team that has scored this round, or did before it was won
+    pub fn scores(&self) -> impl Iterator<Item = (TeamId, u32)> + '_ {
+        self.scores.iter().map(|(&team, &score)| (team, score))
+    }
+
+    // One tick with the room's members where `occupants` says
+    pub fn step(&mut self, occupants: &[Occupant], snapshot_rate: u32) -> Vec<KothEvent> {
+        let mut events = Vec::new();
+        for (index, hill) in self.map.hills.iter().enumerate() {
+            let mut counts: BTreeMap<TeamId, usize> = BTreeMap::new();
+            for occupant in occupants {
+                if let Some(team) = occupant.team {
+                    if hill.area.contains(occupant.position) {
+                        *counts.entry(team).or_default() += 1;
+                    }
+                }
+            }
+            let most = counts.values().copied().max().unwrap_or(0);
+            let mut leaders = counts.iter().filter(|&(_, &count)| count == most);
+            let control = match (leaders.next(), leaders.next()) {
+                (None, _) => Control::Empty,
+                (Some((&team, _)), None) => Control::Held(team),
+                (Some(_), Some(_)) => Control::Contested,
+            };
+            if self.control[index] != control {
+                self.control[index] = control;
+                events.push(KothEvent::Control { index, control });
+            }
+        }
+
+        let rate = snapshot_rate.max(1) as u64;
+        let points = self.map.points_per_second as u64;
+        for control in &self.control {
+            let Control::Held(team) = *control else {
+                continue;
+            };
+            let held = self.held.entry(team).or_default();
+            *held += 1;
+            let score = (*held * points / rate) as u32;
+            let scored = self.scores.entry(team).or_default();
+            if score <= *scored {
+                continue;
+            }
+            *scored = score;
+            events.push(KothEvent::Scored { team, score });
+            if self.map.score_limit > 0 && score >= self.map.score_limit {
+                events.push(KothEvent::Won { team });
+                break;
+            }
+        }
+        events
+    }
+
+    // Scores back to 0, for a new round
+    pub fn reset(&mut self) {
+        self.held.clear();
+        for score in self.scores.values_mut() {
+            *score = 0;
+        }
+    }
+}
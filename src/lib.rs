@@ -0,0 +1,74 @@
+pub mod abilities;
+pub mod accounts;
+pub mod achievements;
+pub mod actions;
+pub mod admin;
+pub mod analytics;
+pub mod appearance;
+pub mod areas;
+pub mod bots;
+pub mod buffers;
+pub mod capture;
+pub mod chaos;
+pub mod chat;
+pub mod cidr;
+pub mod cluster;
+pub mod codec;
+pub mod commands;
+pub mod config;
+pub mod crafting;
+pub mod ctf;
+pub mod deltas;
+pub mod dice;
+pub mod difficulty;
+pub mod emotes;
+pub mod endpoint;
+pub mod entities;
+pub mod friends;
+pub mod gateway;
+pub mod guests;
+pub mod guilds;
+pub mod handoff;
+pub mod health;
+pub mod history;
+pub mod ids;
+pub mod inspect;
+pub mod inventory;
+pub mod jwt;
+pub mod koth;
+pub mod locale;
+pub mod loot;
+pub mod mail;
+pub mod maps;
+pub mod matchmaking;
+pub mod names;
+pub mod net_stats;
+pub mod overload;
+pub mod parties;
+pub mod passwords;
+pub mod progression;
+pub mod protocol;
+pub mod quic;
+pub mod rates;
+pub mod reliability;
+pub mod replication;
+pub mod roles;
+pub mod room_settings;
+pub mod rooms;
+pub mod rotation;
+pub mod rounds;
+pub mod scenarios;
+pub mod server;
+pub mod shards;
+pub mod shops;
+pub mod sight;
+pub mod spatial;
+pub mod state;
+pub mod steam;
+pub mod storage;
+pub mod throttle;
+pub mod traffic;
+pub mod trails;
+pub mod webhooks;
+pub mod whispers;
+pub mod worlds;
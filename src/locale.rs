@@ -0,0 +1,264 @@
+// Server-originated text that clients can translate. Kick reasons, errors and
+// announcements carry a message key with named parameters next to the plain
+// text, so a client looks the key up in its own translations and only shows
+// the plain text for keys it doesn't know. The plain text is rendered in the
+// server's locale from translation files, falling back to built-in English.
+use crate::protocol::LocalizedText;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// The built-in English for every key the server sends. `{name}` stands for
+// the parameter called `name`.
+pub const ENGLISH: &[(&str, &str)] = &[
+    ("disconnect.banned", "you are banned from this server"),
+    ("disconnect.banned_by_operator", "banned by an operator"),
+    ("disconnect.kicked", "kicked by an operator"),
+    ("disconnect.bot_removed", "the bot was removed"),
+    (
+        "disconnect.address_blocked",
+        "your address is blocked on this server",
+    ),
+    (
+        "disconnect.address_not_allowed",
+        "your address is not allowed on this server",
+    ),
+    (
+        "disconnect.not_whitelisted",
+        "this server is whitelist-only",
+    ),
+    (
+        "disconnect.maintenance",
+        "the server is down for maintenance",
+    ),
+    ("disconnect.shutdown", "the server is shutting down"),
+    (
+        "disconnect.wrong_password",
+        "wrong or missing server password",
+    ),
+    (
+        "disconnect.protocol_version",
+        "incompatible protocol version",
+    ),
+    (
+        "disconnect.handshake_timeout",
+        "handshake not completed in time",
+    ),
+    ("disconnect.idle", "no data received"),
+    (
+        "disconnect.logged_in_elsewhere",
+        "logged in from another connection",
+    ),
+    ("disconnect.account_deleted", "your account was deleted"),
+    (
+        "disconnect.too_fast",
+        "too many connection attempts from your address",
+    ),
+    (
+        "disconnect.too_many",
+        "too many connections from your address",
+    ),
+    (
+        "disconnect.throttled",
+        "too many connection attempts; try again in {seconds}s",
+    ),
+    ("disconnect.flooding", "too many messages; slow down"),
+    (
+        "disconnect.server_full",
+        "the server is full; try again later",
+    ),
+    (
+        "disconnect.restarting",
+        "the server is restarting; reconnect in a moment",
+    ),
+    (
+        "disconnect.upgrading",
+        "the server is upgrading; reconnect to carry on",
+    ),
+    (
+        "disconnect.migrated",
+        "your session moved to another connection",
+    ),
+    (
+        "error.protocol_version",
+        "client speaks protocol {client}, server speaks {server}",
+    ),
+    (
+        "error.handshake_required",
+        "send Hello with the server password first",
+    ),
+    ("error.server_only", "only the server may send this message"),
+    (
+        "error.retired_message",
+        "this message is no longer accepted; send {use} instead",
+    ),
+    ("error.log_in_first", "log in first"),
+    ("error.wrong_login", "wrong username or password"),
+    (
+        "error.registration_closed",
+        "registration is closed on this server",
+    ),
+    ("error.username_taken", "`{name}` is already registered"),
+    ("error.no_room", "room {room} does not exist"),
+    (
+        "error.room_password",
+        "wrong or missing password for room {room}",
+    ),
+    (
+        "error.ranked_room",
+        "room {room} is ranked; log in to join it",
+    ),
+    ("error.room_full", "room {room} is full"),
+    ("error.no_invite", "no invite to room {room}"),
+    ("error.kicked_from_room", "you were kicked from room {room}"),
+    ("error.not_host", "only the room's host can do that"),
+    ("error.not_in_room", "not in a room"),
+    ("error.not_in_party", "not in a party"),
+    ("error.not_in_guild", "you are not in a guild"),
+    (
+        "error.guest_chat_rate",
+        "guests may send {count} chat messages every {seconds} seconds; log in to chat more",
+    ),
+    (
+        "error.whisper_rate",
+        "you may send {count} whispers every {seconds} seconds",
+    ),
+    ("error.emote_cooldown", "wait {ms}ms between emotes"),
+    (
+        "error.replayed",
+        "nonce {nonce} was already used; send one above {last}",
+    ),
+    (
+        "error.draining",
+        "the server is about to restart; no new matches can start",
+    ),
+    ("error.no_session", "no session to resume with that token"),
+    (
+        "error.resume_first",
+        "resume a session before doing anything else",
+    ),
+    ("error.unknown_command", "no command /{name}; try /help"),
+    ("error.command_role", "/{name} needs the {role} role"),
+    (
+        "announce.drain",
+        "the server will restart once current matches end, at most {seconds}s from now",
+    ),
+    ("announce.drain_cancelled", "the restart was called off"),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LocaleConfig {
+    // The language plain-text fallbacks are written in
+    pub locale: String,
+    // JSON files, each of the form `{"locale": "de", "messages": {key: text}}`.
+    // Operators can also define keys of their own here for `announce`.
+    pub files: Vec<PathBuf>,
+    // What `load` read from `files`, by locale and then key
+    #[serde(skip)]
+    loaded: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        LocaleConfig {
+            locale: "en".to_string(),
+            files: Vec::new(),
+            loaded: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TranslationFile {
+    locale: String,
+    messages: BTreeMap<String, String>,
+}
+
+impl LocaleConfig {
+    // Read `files` again, replacing whatever they held before. Later files
+    // win over earlier ones for the same locale and key.
+    pub fn load(&mut self) -> Result<(), String> {
+        let mut loaded: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for path in &self.files {
+            let data =
+                fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            let file: TranslationFile = serde_json::from_slice(&data)
+                .map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+            loaded.entry(file.locale).or_default().extend(file.messages);
+        }
+        self.loaded = loaded;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.locale != "en" && !self.loaded.contains_key(&self.locale) {
+            return Err(format!(
+                "`localization.locale` is `{}`, but no translation file is for it",
+                self.locale
+            ));
+        }
+        Ok(())
+    }
+
+    // Whether the server has text for `key` in its locale or in English
+    pub fn knows(&self, key: &str) -> bool {
+        self.template(key).is_some()
+    }
+
+    // `text` as plain text in the server's locale. A key nothing translates
+    // comes out as itself.
+    pub fn render(&self, text: &LocalizedText) -> String {
+        let mut rendered = self.template(&text.key).unwrap_or(&text.key).to_string();
+        for param in &text.params {
+            rendered = rendered.replace(&format!("{{{}}}", param.name), &param.value);
+        }
+        rendered
+    }
+
+    fn template(&self, key: &str) -> Option<&str> {
+        self.loaded
+            .get(&self.locale)
+            .and_then(|messages| messages.get(key))
+            .map(String::as_str)
+            .or_else(|| {
+                ENGLISH
+                    .iter()
+                    .find(|(known, _)| *known == key)
+                    .map(|(_, english)| *english)
+            })
+    }
+}
+
+// What the server says to a player: a translatable key, or free-form text
+// such as an operator's own kick message
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerText {
+    Keyed(LocalizedText),
+    Plain(String),
+}
+
+impl From<LocalizedText> for ServerText {
+    fn from(text: LocalizedText) -> Self {
+        ServerText::Keyed(text)
+    }
+}
+
+impl From<String> for ServerText {
+    fn from(text: String) -> Self {
+        ServerText::Plain(text)
+    }
+}
+
+impl From<&String> for ServerText {
+    fn from(text: &String) -> Self {
+        ServerText::Plain(text.clone())
+    }
+}
+
+impl From<&str> for ServerText {
+    fn from(text: &str) -> Self {
+        ServerText::Plain(text.to_string())
+    }
+}
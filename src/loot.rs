@@ -0,0 +1,177 @@
+// Loot tables, by name, from the JSON file `loot.file` points at, rolled on
+// the server with a draw from its dice (see `crate::dice`). A table is rolled when the game hosting the
+// server drops it, such as where an NPC it simulates died, when an admin
+// does, or when a player opens a chest: an entity (see `crate::entities`)
+// whose `loot` metadata names the table, which goes once opened. Each roll
+// picks one entry by weight, or nothing. Drops either spawn as `item`
+// entities at the spot, which players pick up the same way they open chests,
+// or go straight into the player's inventory (see `crate::inventory`), as
+// picked-up items do. Guests, drops no one opened and grants that would overfill
+// the inventory always spawn.
+use crate::mail::ItemGrant;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// The entity metadata naming a chest's table, and the kind and metadata of
+// the entities dropped items spawn as
+pub const LOOT_KEY: &str = "loot";
+pub const ITEM_KIND: &str = "item";
+pub const ITEM_KEY: &str = "item";
+pub const QUANTITY_KEY: &str = "quantity";
+// The dropped stack's id (see `crate::inventory`)
+pub const ITEM_ID_KEY: &str = "item_id";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LootConfig {
+    // No file means no loot
+    pub file: Option<PathBuf>,
+    // Seeds the dice when `dice.seed` doesn't
+    pub seed: Option<u64>,
+    // How close a player has to be to open a chest, pick an item up or trade
+    // with a shop
+    pub reach: f32,
+}
+
+impl Default for LootConfig {
+    fn default() -> Self {
+        LootConfig {
+            file: None,
+            seed: None,
+            reach: 64.0,
+        }
+    }
+}
+
+impl LootConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.reach.is_finite() && self.reach >= 0.0) {
+            return Err("`loot.reach` must be a number of at least 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    #[default]
+    Spawn,
+    Grant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LootEntry {
+    pub item: String,
+    #[serde(default = "one")]
+    pub weight: u32,
+    #[serde(default = "one")]
+    pub min: u32,
+    #[serde(default = "one")]
+    pub max: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LootTable {
+    pub rolls: u32,
+    // The weight of a roll dropping nothing
+    pub nothing: u32,
+    pub entries: Vec<LootEntry>,
+    pub delivery: Delivery,
+}
+
+impl Default for LootTable {
+    fn default() -> Self {
+        LootTable {
+            rolls: 1,
+            nothing: 0,
+            entries: Vec::new(),
+            delivery: Delivery::Spawn,
+        }
+    }
+}
+
+impl LootTable {
+    fn validate(&self) -> Result<(), String> {
+        if self.entries.is_empty() {
+            return Err("a loot table needs at least one entry".to_string());
+        }
+        for entry in &self.entries {
+            if entry.item.is_empty() || entry.weight == 0 {
+                return Err("loot entries need an `item` and a positive `weight`".to_string());
+            }
+            if entry.min == 0 || entry.min > entry.max {
+                return Err(format!(
+                    "`{}` needs a `min` of at least 1 and no more than its `max`",
+                    entry.item
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct LootTables {
+    pub tables: BTreeMap<String, LootTable>,
+}
+
+impl LootTables {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let tables: BTreeMap<String, LootTable> =
+            serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        for (name, table) in &tables {
+            table
+                .validate()
+                .map_err(|e| format!("table `{}`: {}", name, e))?;
+        }
+        Ok(LootTables { tables })
+    }
+
+    // What one opening of the table drops, the same items added together
+    pub fn roll(
+        &self,
+        name: &str,
+        rng: &mut impl Rng,
+    ) -> Result<(Delivery, Vec<ItemGrant>), String> {
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| format!("there is no loot table `{}`", name))?;
+        let total: u64 =
+            table.entries.iter().map(|e| e.weight as u64).sum::<u64>() + table.nothing as u64;
+        let mut drops: BTreeMap<&str, u32> = BTreeMap::new();
+        for _ in 0..table.rolls {
+            let mut pick = rng.gen_range(0..total);
+            let entry = table.entries.iter().find(|entry| {
+                if pick < entry.weight as u64 {
+                    return true;
+                }
+                pick -= entry.weight as u64;
+                false
+            });
+            if let Some(entry) = entry {
+                let quantity = rng.gen_range(entry.min..=entry.max);
+                let count = drops.entry(&entry.item).or_default();
+                *count = count.saturating_add(quantity);
+            }
+        }
+        let grants = drops
+            .into_iter()
+            .map(|(item, quantity)| ItemGrant {
+                item: item.to_string(),
+                quantity,
+                id: None,
+            })
+            .collect();
+        Ok((table.delivery, grants))
+    }
+}
@@ -0,0 +1,119 @@
+// Mail waiting for accounts: whispers sent while they were offline, notices
+// from operators and item grants. Everything waiting goes out in one
+// `MailReceived` when the account is next online, and leaves the mailbox.
+use crate::accounts::AccountId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+pub type MailId = u64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MailConfig {
+    // Whispers an account can have waiting; notices and item grants are
+    // never refused
+    pub max_whispers: usize,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig { max_whispers: 100 }
+    }
+}
+
+impl MailConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_whispers == 0 {
+            return Err("`mail.max_whispers` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailKind {
+    Whisper,
+    Notice,
+    Items,
+}
+
+// Items are opaque to the server; the game decides what `item` names
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ItemGrant {
+    pub item: String,
+    pub quantity: u32,
+    // The instance: unset in definitions such as loot tables and recipes,
+    // and on what is yet to be made (see `crate::inventory`)
+    #[serde(default)]
+    pub id: Option<ItemId>,
+}
+
+// Server-minted, never reused
+pub type ItemId = Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub id: MailId,
+    pub kind: MailKind,
+    // Unix seconds
+    pub sent_at: u64,
+    // Who wrote a whisper; notices and grants come from the server
+    pub from_account: Option<AccountId>,
+    pub from_name: Option<String>,
+    pub text: String,
+    pub items: Vec<ItemGrant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Mailboxes {
+    pub boxes: BTreeMap<AccountId, Vec<Mail>>,
+    pub next_id: MailId,
+}
+
+impl Mailboxes {
+    pub const STORAGE_KEY: &'static str = "mail";
+
+    pub fn count(&self, account: AccountId, kind: MailKind) -> usize {
+        self.boxes
+            .get(&account)
+            .map(|mail| mail.iter().filter(|m| m.kind == kind).count())
+            .unwrap_or(0)
+    }
+
+    // Store `mail` for `account`, giving it the next id
+    pub fn post(&mut self, account: AccountId, mut mail: Mail) -> MailId {
+        self.next_id += 1;
+        mail.id = self.next_id;
+        self.boxes.entry(account).or_default().push(mail);
+        self.next_id
+    }
+
+    // Everything waiting for `account`, oldest first
+    pub fn take(&mut self, account: AccountId) -> Vec<Mail> {
+        self.boxes.remove(&account).unwrap_or_default()
+    }
+
+    // Whispers `account` left in other mailboxes, still waiting there
+    pub fn sent_by(&self, account: AccountId) -> Vec<Mail> {
+        self.boxes
+            .values()
+            .flatten()
+            .filter(|mail| mail.from_account == Some(account))
+            .cloned()
+            .collect()
+    }
+
+    // Strip `account` from whispers it sent, leaving the text to whoever it
+    // was for
+    pub fn anonymize_sender(&mut self, account: AccountId) {
+        for mail in self.boxes.values_mut().flatten() {
+            if mail.from_account == Some(account) {
+                mail.from_account = None;
+                mail.from_name = None;
+            }
+        }
+    }
+}
@@ -1,123 +1,108 @@
-use bincode;
-use message_io::network::{NetEvent, Transport};
-use message_io::node::{self, NodeHandler};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-
-#[derive(Serialize, Deserialize, Debug)]
-enum ClientMessage {
-    PlayerPosition { id: usize, x: f32, y: f32 },
-    AssignPlayerId { id: usize },
-    UpdateMessage { id: usize, message: String },
-    OtherPlayerConnected { id: usize, x: f32, y: f32 },
-}
-
-#[derive(Clone)]
-struct Player {
-    id: usize,
-    endpoint: message_io::network::Endpoint,
-    x: f32,
-    y: f32,
-    message: String,
-}
-
-struct GameState {
-    players: RwLock<HashMap<usize, Player>>,
-}
+use game_server::capture;
+use game_server::codec::{FLATBUFFERS_SCHEMA, PROTO_SCHEMA};
+use game_server::config::ServerConfig;
+use game_server::gateway;
+use game_server::passwords;
+use game_server::server::{self, Exit};
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let (handler, listener) = node::split::<()>();
-    let game_state = Arc::new(GameState {
-        players: RwLock::new(HashMap::new()),
-    });
-    let mut next_player_id = 1;
-
-    handler
-        .network()
-        .listen(Transport::FramedTcp, "0.0.0.0:3042")
-        .unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let handler_clone = handler.clone();
-    let game_state_clone = Arc::clone(&game_state);
+    // `--emit-proto [path]` and `--emit-fbs [path]` write a schema for client
+    // code generation and exit
+    let schema = match args.first().map(String::as_str) {
+        Some("--emit-proto") => Some(PROTO_SCHEMA),
+        Some("--emit-fbs") => Some(FLATBUFFERS_SCHEMA),
+        _ => None,
+    };
+    if let Some(schema) = schema {
+        emit_schema(schema, args.get(1));
+        return;
+    }
 
-    listener.for_each(move |event| match event.network() {
-        NetEvent::Connected(_, _) => unreachable!(),
-        NetEvent::Accepted(endpoint, _) => {
-            println!("Client connected: {:?}", endpoint);
-            let player = Player {
-                id: next_player_id,
-                endpoint,
-                x: 0.0,
-                y: 0.0,
-                message: String::new(),
-            };
-            game_state_clone
-                .players
-                .write()
-                .unwrap()
-                .insert(next_player_id, player);
-            let message =
-                bincode::serialize(&ClientMessage::AssignPlayerId { id: next_player_id }).unwrap();
-            handler_clone.network().send(endpoint, &message);
+    // `--hash-password <password>` prints a hash for `password_hash` in the config file
+    if args.first().map(String::as_str) == Some("--hash-password") {
+        match args.get(1) {
+            Some(password) => println!("{}", passwords::hash_password(password)),
+            None => {
+                eprintln!("usage: --hash-password <password>");
+                process::exit(2);
+            }
+        }
+        return;
+    }
 
-            //send
-            next_player_id += 1;
+    // `--replay <file> [--target addr] [--speed factor]` plays a `--capture`
+    // recording against a running server
+    if args.first().map(String::as_str) == Some("--replay") {
+        if let Err(e) = replay(&args[1..]) {
+            eprintln!("{}", e);
+            process::exit(2);
         }
-        
-        NetEvent::Message(endpoint, data) => {
-            let message: ClientMessage = bincode::deserialize(&data).unwrap();
-            match message {
-                ClientMessage::PlayerPosition { id, x, y } => {
-                    // Update the player's position in the game state
-                    println!("Player position: {:?}", (id, x, y));
-                    let mut players = game_state_clone.players.write().unwrap();
-                    if let Some(player) = players.get_mut(&id) {
-                        player.x = x;
-                        player.y = y;
-                    }
+        return;
+    }
 
-                    // Broadcast the message to all other players
-                    let broadcast_data =
-                        bincode::serialize(&ClientMessage::PlayerPosition { id, x, y }).unwrap();
-                    broadcast_message(&handler_clone, &players, &broadcast_data, id);
-                }
-                ClientMessage::UpdateMessage { id, message } => {
-                    // Update the player's message in the game state
-                    let message_start_time = std::time::Instant::now();
-                    let mut players = game_state_clone.players.write().unwrap();
-                    if let Some(player) = players.get_mut(&id) {
-                        player.message = message.clone();
-                    }
+    let config = ServerConfig::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    // A gateway runs no game of its own, only links to the workers
+    if config.cluster.gateway {
+        if let Err(e) = gateway::run(&config) {
+            eprintln!("Gateway error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    let exit_code = config.drain.exit_code;
+    match server::run(config) {
+        Ok(Exit::Shutdown) => {}
+        // A supervisor restarts the server on this code, e.g. with a new binary
+        Ok(Exit::Restart) => process::exit(exit_code),
+        // The successor is waiting for this connection to close, which happens
+        // as the process exits and lets go of every port
+        Ok(Exit::HandedOff(_successor)) => process::exit(0),
+        Err(e) => {
+            eprintln!("Server error: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-                    // Broadcast the updated message to all players
-                    let broadcast_data =
-                        bincode::serialize(&ClientMessage::UpdateMessage { id, message }).unwrap();
-                    broadcast_message(&handler_clone, &players, &broadcast_data, id);
-                    println!("Message processing time: {:?}", message_start_time.elapsed());
-                }
-                ClientMessage::AssignPlayerId { id } => todo!(),
-                ClientMessage::OtherPlayerConnected { id, x, y } => {}
+fn replay(args: &[String]) -> Result<(), String> {
+    let usage = "usage: --replay <file> [--target addr] [--speed factor]";
+    let mut args = args.iter();
+    let path = args.next().ok_or(usage)?;
+    let mut target = "127.0.0.1:3042".to_string();
+    let mut speed = 1.0;
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(usage)?;
+        match flag.as_str() {
+            "--target" => target = value.clone(),
+            "--speed" => {
+                speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed: &f64| *speed >= 0.0)
+                    .ok_or("`--speed` must be a non-negative number")?
             }
+            _ => return Err(usage.to_string()),
         }
-        NetEvent::Disconnected(endpoint) => {
-            println!("Client disconnected: {:?}", endpoint);
-            let mut players = game_state_clone.players.write().unwrap();
-            players.retain(|_, player| player.endpoint != endpoint);
-        }
-    });
+    }
+    capture::replay(Path::new(path), &target, speed).map_err(|e| format!("Replay failed: {}", e))
 }
 
-// Function to broadcast a message to all connected clients except the sender
-fn broadcast_message(
-    handler: &NodeHandler<()>,
-    players: &HashMap<usize, Player>,
-    data: &[u8],
-    sender_id: usize,
-) {
-    for player in players.values() {
-        if player.id != sender_id {
-            handler.network().send(player.endpoint, data);
+fn emit_schema(schema: &str, path: Option<&String>) {
+    match path {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, schema) {
+                eprintln!("Failed to write {}: {}", path, e);
+                process::exit(1);
+            }
+            println!("Wrote schema to {}", path);
         }
+        None => print!("{}", schema),
     }
 }
@@ -0,0 +1,303 @@
+// Map data for a room, from the JSON file its config names in `map` (or
+// `lobby_map` for the lobby): obstacles no move may end inside, and trigger
+// volumes that notice players coming and going. Triggers are checked against
+// every member's position once a tick. Entering a teleporter moves the player
+// to its target; a capture point goes to a team that holds it alone for long
+// enough; zones and damage zones are only reported, for the game to act on.
+// Every enter and exit is sent to the player's client and to whatever listens
+// on `ServerHandle::trigger_events`. A `ctf` section makes the room a game of
+// capture the flag (see `crate::ctf`), and a `koth` section one of king of the
+// hill (see `crate::koth`), played in rounds timed by `round` (see
+// `crate::rounds`).
+use crate::ctf::CtfMap;
+use crate::koth::KothMap;
+use crate::rooms::{RoomId, TeamId};
+use crate::rounds::RoundConfig;
+use crate::sight;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "shape")]
+pub enum Shape {
+    Rect { min: (f32, f32), max: (f32, f32) },
+    Circle { center: (f32, f32), radius: f32 },
+}
+
+impl Shape {
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        match *self {
+            Shape::Rect { min, max } => {
+                (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&y)
+            }
+            Shape::Circle { center, radius } => (x - center.0).hypot(y - center.1) <= radius,
+        }
+    }
+
+    // How far along the line from `from` to `to` it enters the shape, from 0
+    // to 1; `None` if it misses, or starts inside
+    pub fn entered_by(&self, from: (f32, f32), to: (f32, f32)) -> Option<f32> {
+        if self.contains(from) {
+            return None;
+        }
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        match *self {
+            Shape::Rect { min, max } => {
+                let (mut enter, mut exit) = (0.0f32, 1.0f32);
+                for (start, delta, low, high) in
+                    [(from.0, dx, min.0, max.0), (from.1, dy, min.1, max.1)]
+                {
+                    if delta == 0.0 {
+                        if !(low..=high).contains(&start) {
+                            return None;
+                        }
+                        continue;
+                    }
+                    let (a, b) = ((low - start) / delta, (high - start) / delta);
+                    enter = enter.max(a.min(b));
+                    exit = exit.min(a.max(b));
+                    if enter > exit {
+                        return None;
+                    }
+                }
+                Some(enter)
+            }
+            Shape::Circle { center, radius } => {
+                let (fx, fy) = (from.0 - center.0, from.1 - center.1);
+                let a = dx * dx + dy * dy;
+                let b = 2.0 * (fx * dx + fy * dy);
+                let c = fx * fx + fy * fy - radius * radius;
+                let discriminant = b * b - 4.0 * a * c;
+                if a == 0.0 || discriminant < 0.0 {
+                    return None;
+                }
+                let t = (-b - discriminant.sqrt()) / (2.0 * a);
+                (0.0..=1.0).contains(&t).then_some(t)
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let finite = |(x, y): (f32, f32)| x.is_finite() && y.is_finite();
+        let valid = match *self {
+            Shape::Rect { min, max } => {
+                finite(min) && finite(max) && min.0 <= max.0 && min.1 <= max.1
+            }
+            Shape::Circle { center, radius } => {
+                finite(center) && radius.is_finite() && radius > 0.0
+            }
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(format!("invalid shape {:?}", self)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TriggerKind {
+    Zone,
+    Damage { per_second: f32 },
+    Teleport { to: (f32, f32) },
+    // Taken by the only team inside once it has held it for `seconds`
+    Capture { seconds: f32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trigger {
+    // Unique within the map; clients and listeners know triggers by it
+    pub name: String,
+    pub area: Shape,
+    #[serde(flatten)]
+    pub kind: TriggerKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MapData {
+    #[serde(default)]
+    pub obstacles: Vec<Shape>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub ctf: Option<CtfMap>,
+    #[serde(default)]
+    pub koth: Option<KothMap>,
+    #[serde(default)]
+    pub round: Option<RoundConfig>,
+}
+
+impl MapData {
+    pub fn load(path: &Path) -> Result<MapData, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let map: MapData = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for obstacle in &self.obstacles {
+            obstacle.validate()?;
+        }
+        for (index, trigger) in self.triggers.iter().enumerate() {
+            trigger
+                .area
+                .validate()
+                .map_err(|e| format!("trigger `{}`: {}", trigger.name, e))?;
+            if self.triggers[..index]
+                .iter()
+                .any(|other| other.name == trigger.name)
+            {
+                return Err(format!("two triggers are called `{}`", trigger.name));
+            }
+            let valid = match trigger.kind {
+                TriggerKind::Zone => true,
+                TriggerKind::Damage { per_second } => per_second.is_finite(),
+                TriggerKind::Teleport { to } => to.0.is_finite() && to.1.is_finite(),
+                TriggerKind::Capture { seconds } => seconds.is_finite() && seconds > 0.0,
+            };
+            if !valid {
+                return Err(format!("trigger `{}` has invalid settings", trigger.name));
+            }
+        }
+        if let Some(ctf) = &self.ctf {
+            ctf.validate()?;
+        }
+        if let Some(koth) = &self.koth {
+            koth.validate()?;
+        }
+        if self.ctf.is_some() && self.koth.is_some() {
+            return Err("a map can't have both `ctf` and `koth`".to_string());
+        }
+        if let Some(round) = &self.round {
+            if self.ctf.is_none() && self.koth.is_none() {
+                return Err("`round` needs a `ctf` or `koth` section to time".to_string());
+            }
+            round.validate()?;
+        }
+        Ok(())
+    }
+
+    // Whether an obstacle covers `position`
+    pub fn blocked(&self, position: (f32, f32)) -> bool {
+        self.obstacles
+            .iter()
+            .any(|obstacle| obstacle.contains(position))
+    }
+
+    // Whether an obstacle stands between two points (see `crate::sight`)
+    pub fn line_of_sight(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        sight::line_of_sight(&self.obstacles, from, to)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerChange {
+    Entered(usize),
+    Left(usize),
+    Captured(TeamId),
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TriggerEvent {
+    pub room: Option<RoomId>,
+    pub trigger: String,
+    pub kind: TriggerKind,
+    pub change: TriggerChange,
+}
+
+// A room member as the triggers see them
+pub struct Occupant {
+    pub id: usize,
+    pub position: (f32, f32),
+    pub team: Option<TeamId>,
+}
+
+#[derive(Debug, Default)]
+struct Capture {
+    owner: Option<TeamId>,
+    // The team taking the point and for how many ticks it has held it alone
+    taking: Option<TeamId>,
+    held: u64,
+}
+
+// A room's map and who is inside each of its triggers
+#[derive(Debug)]
+pub struct MapState {
+    pub map: MapData,
+    inside: Vec<BTreeSet<usize>>,
+    captures: Vec<Capture>,
+}
+
+impl MapState {
+    pub fn new(map: MapData) -> Self {
+        let count = map.triggers.len();
+        MapState {
+            map,
+            inside: (0..count).map(|_| BTreeSet::new()).collect(),
+            captures: (0..count).map(|_| Capture::default()).collect(),
+        }
+    }
+
+    // One tick with everyone in the room where `occupants` says. Anyone not
+    // in it any more has left every trigger they were in.
+    pub fn evaluate(
+        &mut self,
+        room: Option<RoomId>,
+        occupants: &[Occupant],
+        snapshot_rate: u32,
+    ) -> Vec<TriggerEvent> {
+        let mut events = Vec::new();
+        for (index, trigger) in self.map.triggers.iter().enumerate() {
+            let here: Vec<&Occupant> = occupants
+                .iter()
+                .filter(|occupant| trigger.area.contains(occupant.position))
+                .collect();
+            let now: BTreeSet<usize> = here.iter().map(|occupant| occupant.id).collect();
+            let event = |change| TriggerEvent {
+                room,
+                trigger: trigger.name.clone(),
+                kind: trigger.kind.clone(),
+                change,
+            };
+            for &id in self.inside[index].difference(&now) {
+                events.push(event(TriggerChange::Left(id)));
+            }
+            for &id in now.difference(&self.inside[index]) {
+                events.push(event(TriggerChange::Entered(id)));
+            }
+            self.inside[index] = now;
+
+            let TriggerKind::Capture { seconds } = trigger.kind else {
+                continue;
+            };
+            let teams: BTreeSet<TeamId> =
+                here.iter().filter_map(|occupant| occupant.team).collect();
+            let capture = &mut self.captures[index];
+            let holder = match (teams.len(), teams.first()) {
+                (1, Some(&team)) if capture.owner != Some(team) => team,
+                // Empty, contested, or already held by the only team there
+                _ => {
+                    capture.taking = None;
+                    capture.held = 0;
+                    continue;
+                }
+            };
+            if capture.taking == Some(holder) {
+                capture.held += 1;
+            } else {
+                capture.taking = Some(holder);
+                capture.held = 1;
+            }
+            if capture.held as f32 >= seconds * snapshot_rate as f32 {
+                capture.owner = Some(holder);
+                capture.taking = None;
+                capture.held = 0;
+                events.push(event(TriggerChange::Captured(holder)));
+            }
+        }
+        events
+    }
+}
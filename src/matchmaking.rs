@@ -0,0 +1,82 @@
+// Backfill into running matches. A match is a room playing a team mode (see
+// `crate::ctf` and `crate::koth`), with room for `team_size` players on each
+// of its teams: capture the flag's are the teams with a flag, other modes'
+// are `teams`. Players in the lobby queue with `FindMatch`, and whenever a
+// match has an open slot, because someone left it or it never filled, the
+// longest-waiting player who may join it is put on its smallest team. They
+// are caught up on it as anyone entering a room is. A party leader queues for
+// the whole party, which is seated together on one team, so only a match with
+// that many open slots on a team takes it. Queued players are told their
+// place and how many slots are open whenever either changes.
+use crate::rooms::TeamId;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MatchmakingConfig {
+    pub team_size: u32,
+    pub teams: Vec<TeamId>,
+}
+
+impl Default for MatchmakingConfig {
+    fn default() -> Self {
+        MatchmakingConfig {
+            team_size: 4,
+            teams: vec![0, 1],
+        }
+    }
+}
+
+impl MatchmakingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.team_size == 0 {
+            return Err("`matchmaking.team_size` must be at least 1".to_string());
+        }
+        if self.teams.is_empty() {
+            return Err("`matchmaking.teams` needs at least one team".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MatchQueue {
+    waiting: VecDeque<usize>,
+    // The open slots queued players were last told about
+    pub advertised: u32,
+    // Whether anyone's place changed since they were last told
+    pub changed: bool,
+}
+
+impl MatchQueue {
+    // Returns false if the player was already queued
+    pub fn join(&mut self, player_id: usize) -> bool {
+        if self.waiting.contains(&player_id) {
+            return false;
+        }
+        self.waiting.push_back(player_id);
+        self.changed = true;
+        true
+    }
+
+    pub fn leave(&mut self, player_id: usize) -> bool {
+        let before = self.waiting.len();
+        self.waiting.retain(|&id| id != player_id);
+        self.changed |= self.waiting.len() != before;
+        self.waiting.len() != before
+    }
+
+    pub fn contains(&self, player_id: usize) -> bool {
+        self.waiting.contains(&player_id)
+    }
+
+    // Longest-waiting first
+    pub fn waiting(&self) -> impl Iterator<Item = usize> + '_ {
+        self.waiting.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
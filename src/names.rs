@@ -0,0 +1,101 @@
+// Rules for player display names. Names are ASCII letters, digits, `_`, `-`,
+// `.` and single inner spaces, and are compared case-insensitively, so
+// "Alice" and "alice" can't both be in one room.
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NameConfig {
+    // In characters
+    pub min_len: usize,
+    pub max_len: usize,
+    // Refused anywhere in a name, ignoring case, punctuation and look-alike
+    // digits, so `B.4.D` matches `bad`
+    pub blocked_words: Vec<String>,
+    // Names nobody may take, so players can't pass themselves off as staff
+    pub reserved: Vec<String>,
+}
+
+impl Default for NameConfig {
+    fn default() -> Self {
+        let words = |list: &[&str]| list.iter().map(|w| w.to_string()).collect();
+        NameConfig {
+            min_len: 3,
+            max_len: 16,
+            blocked_words: words(&["fuck", "shit", "cunt", "bitch", "whore", "nazi"]),
+            reserved: words(&[
+                "admin",
+                "administrator",
+                "moderator",
+                "mod",
+                "owner",
+                "server",
+                "system",
+                "console",
+            ]),
+        }
+    }
+}
+
+impl NameConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_len == 0 || self.min_len > self.max_len {
+            return Err("`names.min_len` must be between 1 and `names.max_len`".to_string());
+        }
+        Ok(())
+    }
+
+    // The name as it will be shown, with surrounding spaces trimmed, or why
+    // it can't be used
+    pub fn check(&self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        let len = name.chars().count();
+        if len < self.min_len || len > self.max_len {
+            return Err(format!(
+                "names must be {}-{} characters",
+                self.min_len, self.max_len
+            ));
+        }
+        let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ' ');
+        if !name.chars().all(allowed) || name.contains("  ") {
+            return Err(
+                "names may only use letters, digits, `_`, `-`, `.` and single spaces".to_string(),
+            );
+        }
+        let folded = fold(name);
+        if self.reserved.iter().any(|word| fold(word) == folded) {
+            return Err(format!("`{}` is reserved", name));
+        }
+        if self
+            .blocked_words
+            .iter()
+            .map(|word| fold(word))
+            .any(|word| !word.is_empty() && folded.contains(&word))
+        {
+            return Err("that name is not allowed".to_string());
+        }
+        Ok(name.to_string())
+    }
+}
+
+// Whether two names would look the same to players
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+// Lowercase letters and digits, with digits that stand in for letters swapped
+// back
+fn fold(name: &str) -> String {
+    name.chars()
+        .filter_map(|c| match c.to_ascii_lowercase() {
+            '0' => Some('o'),
+            '1' => Some('i'),
+            '3' => Some('e'),
+            '4' => Some('a'),
+            '5' => Some('s'),
+            '7' => Some('t'),
+            c if c.is_ascii_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
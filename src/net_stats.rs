@@ -0,0 +1,99 @@
+// Net graphs. With `net_stats.enabled`, every joined client is sent
+// `NetStats` each `interval_ms`: the bytes it has sent and been sent since it
+// connected (see `crate::traffic`), its round trip time and how many reports
+// went missing. Clients answer each report with `NetStatsAck`, which is what
+// the round trip is timed by; reports go the unreliable way, so over QUIC
+// the loss is that of its datagrams. Clients that never ack get neither.
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Reports the loss is worked out over
+const LOSS_WINDOW: usize = 32;
+// A report not acked within this long counts as lost
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NetStatsConfig {
+    pub enabled: bool,
+    pub interval_ms: u64,
+}
+
+impl Default for NetStatsConfig {
+    fn default() -> Self {
+        NetStatsConfig {
+            enabled: false,
+            interval_ms: 1000,
+        }
+    }
+}
+
+impl NetStatsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.interval_ms < 100 {
+            return Err("`net_stats.interval_ms` must be at least 100".to_string());
+        }
+        Ok(())
+    }
+}
+
+// One player's reports: which were acked, and the round trip so far
+#[derive(Debug, Clone, Default)]
+pub struct NetProbe {
+    next_sequence: u64,
+    // The last `LOSS_WINDOW` reports, oldest first: sequence, when it was
+    // sent, and whether it was acked
+    sent: VecDeque<(u64, Instant, bool)>,
+    // Smoothed like TCP's, in milliseconds
+    rtt: Option<f32>,
+}
+
+impl NetProbe {
+    // The sequence number for the next report
+    pub fn report(&mut self, now: Instant) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        if self.sent.len() == LOSS_WINDOW {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((sequence, now, false));
+        sequence
+    }
+
+    // Acks for reports never sent, already acked, or past the window do nothing
+    pub fn acked(&mut self, sequence: u64, now: Instant) {
+        let Some((_, sent_at, acked)) = self.sent.iter_mut().find(|(s, _, _)| *s == sequence)
+        else {
+            return;
+        };
+        if *acked {
+            return;
+        }
+        *acked = true;
+        let sample = now.duration_since(*sent_at).as_secs_f32() * 1000.0;
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt + (sample - rtt) / 8.0,
+            None => sample,
+        });
+    }
+
+    // In milliseconds; `None` until a report is acked
+    pub fn rtt(&self) -> Option<u32> {
+        self.rtt.map(|rtt| rtt.round() as u32)
+    }
+
+    // The share of recent reports lost, from 0 to 1, leaving out those still
+    // in flight; `None` until a report is acked
+    pub fn loss(&self, now: Instant) -> Option<f32> {
+        self.rtt?;
+        let settled: Vec<bool> = self
+            .sent
+            .iter()
+            .filter(|(_, sent_at, acked)| *acked || now.duration_since(*sent_at) >= ACK_TIMEOUT)
+            .map(|&(_, _, acked)| acked)
+            .collect();
+        let lost = settled.iter().filter(|acked| !**acked).count();
+        Some(lost as f32 / settled.len().max(1) as f32)
+    }
+}
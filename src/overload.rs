@@ -0,0 +1,157 @@
+// Noticing when ticks run over their budget, and shedding work until they
+// don't. A tick costs whichever is worse: how late the game loop got to it, or
+// how long the slowest shard took over its snapshots. After `overrun_ticks`
+// overruns in a row the next step in `shed` is taken; after `recover_ticks`
+// ticks within budget the last step taken is undone.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Shed {
+    // Stop sending snapshots of the lobby
+    LobbySnapshots,
+    // Drop idle players, prune the throttle and expire sessions every few
+    // seconds rather than every tick or second
+    Housekeeping,
+    // Send room snapshots half as often, down to `min_snapshot_rate`
+    HalveSnapshots,
+}
+
+impl Shed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shed::LobbySnapshots => "lobby snapshots",
+            Shed::Housekeeping => "housekeeping",
+            Shed::HalveSnapshots => "half the snapshot rate",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OverloadConfig {
+    // A tick costing more than this overruns; the tick interval by default
+    pub budget_ms: Option<u64>,
+    pub overrun_ticks: u32,
+    pub recover_ticks: u32,
+    // Steps taken in order, one more each time overruns keep up
+    pub shed: Vec<Shed>,
+    // Halving never takes snapshots below this many a second
+    pub min_snapshot_rate: u32,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        OverloadConfig {
+            budget_ms: None,
+            overrun_ticks: 20,
+            recover_ticks: 200,
+            shed: vec![
+                Shed::LobbySnapshots,
+                Shed::Housekeeping,
+                Shed::HalveSnapshots,
+                Shed::HalveSnapshots,
+            ],
+            min_snapshot_rate: 5,
+        }
+    }
+}
+
+impl OverloadConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.budget_ms == Some(0) {
+            return Err("`overload.budget_ms` must be a positive integer".to_string());
+        }
+        if self.overrun_ticks == 0 || self.recover_ticks == 0 {
+            return Err(
+                "`overload.overrun_ticks` and `overload.recover_ticks` must be positive integers"
+                    .to_string(),
+            );
+        }
+        if self.min_snapshot_rate == 0 {
+            return Err("`overload.min_snapshot_rate` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub enum Change {
+    Shed(Shed),
+    Restored(Shed),
+}
+
+// How many steps are taken and the ticks that led there
+#[derive(Default)]
+pub struct Overload {
+    level: usize,
+    // Overruns in a row, and ticks within budget in a row
+    late: u32,
+    on_time: u32,
+    overruns: u64,
+}
+
+impl Overload {
+    // Count one tick, taking or undoing a step when it is time to
+    pub fn record(
+        &mut self,
+        config: &OverloadConfig,
+        cost: Duration,
+        budget: Duration,
+    ) -> Option<Change> {
+        // The steps may have been cut by a reload
+        self.level = self.level.min(config.shed.len());
+        if cost > budget {
+            self.overruns += 1;
+            self.late += 1;
+            self.on_time = 0;
+            if self.late >= config.overrun_ticks && self.level < config.shed.len() {
+                self.late = 0;
+                self.level += 1;
+                return Some(Change::Shed(config.shed[self.level - 1]));
+            }
+        } else {
+            self.on_time += 1;
+            self.late = 0;
+            if self.on_time >= config.recover_ticks && self.level > 0 {
+                self.on_time = 0;
+                self.level -= 1;
+                return Some(Change::Restored(config.shed[self.level]));
+            }
+        }
+        None
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    // Whether overruns kept up through the last tick
+    pub fn overrunning(&self) -> bool {
+        self.late > 0
+    }
+
+    pub fn shedding(&self, config: &OverloadConfig, step: Shed) -> bool {
+        config.shed[..self.level.min(config.shed.len())].contains(&step)
+    }
+
+    // Room snapshots go out every this many ticks
+    pub fn snapshot_every(&self, config: &OverloadConfig, snapshot_rate: u32) -> u64 {
+        let halvings = config.shed[..self.level.min(config.shed.len())]
+            .iter()
+            .filter(|&&step| step == Shed::HalveSnapshots)
+            .count();
+        let mut every = 1;
+        for _ in 0..halvings {
+            if snapshot_rate / (every * 2) < config.min_snapshot_rate {
+                break;
+            }
+            every *= 2;
+        }
+        every as u64
+    }
+}
@@ -0,0 +1,124 @@
+// Parties: players who move between rooms together. The leader invites and
+// chooses where the party goes; when they join or leave a room the rest of the
+// party follows. A party lasts as long as its members' sessions.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type PartyId = u32;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PartyConfig {
+    // Members plus outstanding invites
+    pub max_size: usize,
+}
+
+impl Default for PartyConfig {
+    fn default() -> Self {
+        PartyConfig { max_size: 8 }
+    }
+}
+
+impl PartyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size < 2 {
+            return Err("`parties.max_size` must be at least 2".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Party {
+    pub id: PartyId,
+    pub leader: usize,
+    // Includes the leader
+    pub members: BTreeSet<usize>,
+    pub invited: BTreeSet<usize>,
+}
+
+impl Party {
+    pub fn size(&self) -> usize {
+        self.members.len() + self.invited.len()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Parties {
+    parties: BTreeMap<PartyId, Party>,
+    next_id: PartyId,
+}
+
+impl Parties {
+    pub fn get(&self, id: PartyId) -> Option<&Party> {
+        self.parties.get(&id)
+    }
+
+    pub fn create(&mut self, leader: usize) -> PartyId {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.parties.insert(
+            id,
+            Party {
+                id,
+                leader,
+                members: BTreeSet::from([leader]),
+                invited: BTreeSet::new(),
+            },
+        );
+        id
+    }
+
+    pub fn invite(&mut self, id: PartyId, player_id: usize) -> bool {
+        self.parties
+            .get_mut(&id)
+            .is_some_and(|party| party.invited.insert(player_id))
+    }
+
+    // Turn an invite into membership; returns whether there was one
+    pub fn join(&mut self, id: PartyId, player_id: usize) -> bool {
+        let Some(party) = self.parties.get_mut(&id) else {
+            return false;
+        };
+        if !party.invited.remove(&player_id) {
+            return false;
+        }
+        party.members.insert(player_id);
+        true
+    }
+
+    // Remove a member, handing the lead to the longest-connected member left.
+    // A party with fewer than two members afterwards is disbanded and its last
+    // member returned.
+    pub fn leave(&mut self, id: PartyId, player_id: usize) -> Left {
+        let Some(party) = self.parties.get_mut(&id) else {
+            return Left::Gone;
+        };
+        party.members.remove(&player_id);
+        if party.members.len() < 2 {
+            let last = party.members.first().copied();
+            self.parties.remove(&id);
+            return Left::Disbanded(last);
+        }
+        if party.leader == player_id {
+            party.leader = *party.members.first().unwrap();
+        }
+        Left::Remaining
+    }
+
+    // Withdraw every invite to a player who is leaving the server, returning
+    // the parties that had invited them
+    pub fn forget_invites(&mut self, player_id: usize) -> Vec<PartyId> {
+        self.parties
+            .values_mut()
+            .filter_map(|party| party.invited.remove(&player_id).then_some(party.id))
+            .collect()
+    }
+}
+
+// What became of a party someone left
+pub enum Left {
+    Remaining,
+    Disbanded(Option<usize>),
+    Gone,
+}
@@ -0,0 +1,154 @@
+// Experience and levels per account. Gameplay events (see
+// `crate::achievements::GameEvent`) are worth the XP `progression.xp` gives
+// them, and the total XP decides the level through `curve`, starting at
+// level 1. XP is persisted; guests earn none.
+use crate::accounts::AccountId;
+use crate::achievements::GameEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// How much total XP each level takes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum LevelCurve {
+    // Every level takes the same XP
+    Linear { xp_per_level: u64 },
+    // Level 2 takes `base`, and each level after `factor` times the one before
+    Exponential { base: u64, factor: f64 },
+    // The total XP for level 2, 3 and on; the last is the highest level
+    Table { levels: Vec<u64> },
+}
+
+impl Default for LevelCurve {
+    fn default() -> Self {
+        LevelCurve::Exponential {
+            base: 100,
+            factor: 1.5,
+        }
+    }
+}
+
+impl LevelCurve {
+    // The total XP that reaching `level` takes, or `None` past the curve's end
+    pub fn xp_for(&self, level: u32) -> Option<u64> {
+        if level <= 1 {
+            return Some(0);
+        }
+        let steps = level - 1;
+        match self {
+            LevelCurve::Linear { xp_per_level } => xp_per_level.checked_mul(steps as u64),
+            LevelCurve::Exponential { base, factor } => {
+                // Sum of a geometric series, in floating point
+                let total = if *factor == 1.0 {
+                    *base as f64 * steps as f64
+                } else {
+                    *base as f64 * (factor.powi(steps as i32) - 1.0) / (factor - 1.0)
+                };
+                (total.is_finite() && total < u64::MAX as f64).then(|| total.round() as u64)
+            }
+            LevelCurve::Table { levels } => levels.get(steps as usize - 1).copied(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            LevelCurve::Linear { xp_per_level: 0 } => {
+                Err("`progression.curve.xp_per_level` must be at least 1".to_string())
+            }
+            LevelCurve::Exponential { base, factor }
+                if *base == 0 || !(factor.is_finite() && *factor >= 1.0) =>
+            {
+                Err(
+                    "an exponential curve needs a positive `base` and a `factor` of at least 1"
+                        .to_string(),
+                )
+            }
+            LevelCurve::Table { levels }
+                if levels.first() == Some(&0) || levels.windows(2).any(|w| w[0] >= w[1]) =>
+            {
+                Err("`progression.curve.levels` must be positive and rising".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProgressionConfig {
+    // Events not listed are worth nothing
+    pub xp: BTreeMap<GameEvent, u64>,
+    pub curve: LevelCurve,
+    pub max_level: u32,
+}
+
+impl Default for ProgressionConfig {
+    fn default() -> Self {
+        ProgressionConfig {
+            xp: BTreeMap::from([
+                (GameEvent::RoomJoined, 5),
+                (GameEvent::FlagCaptured, 50),
+                (GameEvent::RoundWon, 100),
+            ]),
+            curve: LevelCurve::default(),
+            max_level: 100,
+        }
+    }
+}
+
+impl ProgressionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_level == 0 {
+            return Err("`progression.max_level` must be at least 1".to_string());
+        }
+        self.curve.validate()
+    }
+
+    // The level `xp` reaches
+    pub fn level(&self, xp: u64) -> u32 {
+        let mut level = 1;
+        while level < self.max_level
+            && self
+                .curve
+                .xp_for(level + 1)
+                .is_some_and(|needed| xp >= needed)
+        {
+            level += 1;
+        }
+        level
+    }
+
+    // The total XP the level after `level` takes, if there is one
+    pub fn next(&self, level: u32) -> Option<u64> {
+        if level >= self.max_level {
+            return None;
+        }
+        self.curve.xp_for(level + 1)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Progression {
+    pub xp: BTreeMap<AccountId, u64>,
+}
+
+impl Progression {
+    pub const STORAGE_KEY: &'static str = "progression";
+
+    pub fn xp(&self, account: AccountId) -> u64 {
+        self.xp.get(&account).copied().unwrap_or(0)
+    }
+
+    // Returns the account's XP before and after
+    pub fn award(&mut self, account: AccountId, amount: u64) -> (u64, u64) {
+        let xp = self.xp.entry(account).or_default();
+        let before = *xp;
+        *xp = xp.saturating_add(amount);
+        (before, *xp)
+    }
+
+    pub fn forget(&mut self, account: AccountId) {
+        self.xp.remove(&account);
+    }
+}
@@ -0,0 +1,1282 @@
+use crate::accounts::{AccountId, PlayerUuid};
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::crafting::CraftError;
+use crate::difficulty::Difficulty;
+use crate::guilds::{GuildId, GuildRank};
+use crate::mail::{ItemGrant, ItemId, Mail};
+use crate::parties::PartyId;
+use crate::room_settings::{GameMode, RoomSettings};
+use crate::rooms::{RoomId, TeamId};
+use crate::shops::{ShopOffer, TradeError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use strum::{EnumCount, IntoStaticStr};
+
+// Bumped whenever the handshake or message layout changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 13;
+
+// Messages exchanged between the server and its clients, in both directions.
+// Variant order is part of the wire format: only ever append new variants.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, EnumCount, IntoStaticStr)]
+pub enum ClientMessage {
+    PlayerPosition {
+        id: usize,
+        x: f32,
+        y: f32,
+    },
+    AssignPlayerId {
+        id: usize,
+    },
+    UpdateMessage {
+        id: usize,
+        message: String,
+    },
+    OtherPlayerConnected {
+        id: usize,
+        x: f32,
+        y: f32,
+    },
+    // Sent by the client right after connecting, in the server's default wire
+    // format, listing the encodings it understands in order of preference
+    Hello {
+        protocol_version: u32,
+        wire_formats: Vec<WireFormat>,
+        snapshot_formats: Vec<SnapshotFormat>,
+        // Required when the server is password-protected
+        password: Option<String>,
+    },
+    // The server's answer to `Hello`; every later frame uses the chosen formats
+    Welcome {
+        id: usize,
+        wire_format: WireFormat,
+        snapshot_format: SnapshotFormat,
+    },
+    WorldSnapshot {
+        tick: u64,
+        players: Vec<PlayerSnapshot>,
+    },
+    // A `proto/snapshot.fbs` buffer, for clients that negotiated FlatBuffers snapshots
+    FlatSnapshot {
+        buffer: Vec<u8>,
+    },
+    // Why the server refused to act on a request; `context` is free-form
+    // detail. Server text like this also comes `localized` when it has a
+    // message key, with the plain text as the fallback.
+    ServerError {
+        code: ErrorCode,
+        context: String,
+        localized: Option<LocalizedText>,
+    },
+    // The last message before the server closes the connection
+    Disconnected {
+        reason: DisconnectReason,
+        message: String,
+        localized: Option<LocalizedText>,
+    },
+    // Create a room and join it; the password, if any, protects later joins.
+    // `settings` override the server's for this room (see
+    // `crate::room_settings`).
+    CreateRoom {
+        name: String,
+        password: Option<String>,
+        settings: RoomSettings,
+    },
+    JoinRoom {
+        room_id: RoomId,
+        password: Option<String>,
+    },
+    LeaveRoom,
+    RoomJoined {
+        room_id: RoomId,
+        name: String,
+    },
+    RoomLeft {
+        room_id: RoomId,
+    },
+    // An admin console line from a player with a role, and the server's answer
+    ConsoleCommand {
+        line: String,
+    },
+    ConsoleOutput {
+        text: String,
+    },
+    // A message from the operators to every player
+    Announcement {
+        text: String,
+        localized: Option<LocalizedText>,
+    },
+    // Ask for a connected player's lifetime totals, answered with `PlayerStats`
+    QueryPlayerStats {
+        player_id: usize,
+    },
+    // Totals include the player's current session; timestamps are Unix seconds
+    PlayerStats {
+        player_id: usize,
+        sessions: u64,
+        playtime_secs: u64,
+        messages_sent: u64,
+        first_seen: u64,
+        last_seen: u64,
+    },
+    // An archived `codec::archive::Snapshot`, for clients that negotiated rkyv snapshots
+    ArchivedSnapshot {
+        buffer: Vec<u8>,
+    },
+    // Pick a display name, answered with `PlayerNamed` or a `ServerError`
+    SetName {
+        name: String,
+    },
+    // Someone in the receiver's room took or changed their name, or their
+    // guild tag changed
+    PlayerNamed {
+        id: usize,
+        name: String,
+        tag: Option<String>,
+    },
+    // A chat line with its sender's name, for clients that sent `Hello`;
+    // legacy clients get `UpdateMessage` instead, and only for global chat
+    ChatMessage {
+        id: usize,
+        name: Option<String>,
+        message: String,
+        channel: ChatChannel,
+    },
+    // Everyone already in the room the receiver just entered, or in the lobby
+    JoinSnapshot {
+        room_id: Option<RoomId>,
+        players: Vec<NamedPlayer>,
+    },
+    // Sent by a client to change its avatar, and by the server to everyone in
+    // the room once accepted. `color` is 0xRRGGBB; `skin` and `accessories`
+    // index the server's catalog (see `appearance::AppearanceConfig`).
+    PlayerAppearance {
+        id: usize,
+        skin: u32,
+        color: u32,
+        accessories: Vec<u32>,
+    },
+    // Create an account and log in to it. Every kind of login is allowed
+    // before joining, once past the server password; when guests aren't let
+    // in, remote players only join by logging in.
+    Register {
+        username: String,
+        password: String,
+    },
+    Login {
+        username: String,
+        password: String,
+    },
+    // The answer to a successful login of any kind
+    LoggedIn {
+        account_id: AccountId,
+        username: String,
+    },
+    // Log in with a JWT from the server's identity provider
+    TokenLogin {
+        token: String,
+    },
+    // Log in with a hex-encoded Steam session ticket
+    SteamLogin {
+        ticket: String,
+    },
+    // Ask an online, logged-in player to be friends, or accept if they asked
+    // first
+    FriendAdd {
+        player_id: usize,
+    },
+    FriendAccept {
+        account_id: AccountId,
+    },
+    // End a friendship, or decline or withdraw a request
+    FriendRemove {
+        account_id: AccountId,
+    },
+    // The receiver's friends and pending requests, sent on login and whenever
+    // they change
+    FriendList {
+        friends: Vec<Friend>,
+        incoming: Vec<Friend>,
+        outgoing: Vec<Friend>,
+    },
+    // A friend came online, went offline or moved to another room
+    FriendPresence {
+        account_id: AccountId,
+        online: bool,
+        room_id: Option<RoomId>,
+    },
+    // A private message to one joined player, answered with `WhisperMessage`
+    Whisper {
+        target_id: usize,
+        text: String,
+    },
+    // A whisper, delivered to its target and echoed back to its sender
+    WhisperMessage {
+        from_id: usize,
+        to_id: usize,
+        name: Option<String>,
+        text: String,
+    },
+    // Stop or resume receiving whispers and chat from a player, for the rest
+    // of the session
+    Mute {
+        player_id: usize,
+    },
+    Unmute {
+        player_id: usize,
+    },
+    // Invite a joined player to the sender's party, starting one if needed;
+    // only the leader may invite
+    PartyInvite {
+        player_id: usize,
+    },
+    PartyInvited {
+        party_id: PartyId,
+        from_id: usize,
+        name: Option<String>,
+    },
+    PartyAccept {
+        party_id: PartyId,
+    },
+    PartyLeave,
+    // The receiver's party, sent to every member whenever it changes
+    PartyUpdate {
+        party_id: PartyId,
+        leader_id: usize,
+        members: Vec<usize>,
+        invited: Vec<usize>,
+    },
+    // The receiver left the party, or it broke up
+    PartyLeft {
+        party_id: PartyId,
+    },
+    // A line for the sender's party, delivered to every member, the sender
+    // included, as `PartyChatMessage`
+    PartyChat {
+        text: String,
+    },
+    PartyChatMessage {
+        party_id: PartyId,
+        from_id: usize,
+        name: Option<String>,
+        text: String,
+    },
+    // Found a guild and lead it; needs an account and no guild
+    GuildCreate {
+        name: String,
+        tag: Option<String>,
+    },
+    // Officers and the leader invite online, logged-in players
+    GuildInvite {
+        player_id: usize,
+    },
+    GuildInvited {
+        guild_id: GuildId,
+        name: String,
+        from_id: usize,
+    },
+    // Accept an invite
+    GuildJoin {
+        guild_id: GuildId,
+    },
+    GuildLeave,
+    // Officers may kick members below them
+    GuildKick {
+        account_id: AccountId,
+    },
+    // Leader only; making someone leader steps the old one down to officer
+    GuildSetRank {
+        account_id: AccountId,
+        rank: GuildRank,
+    },
+    // A line for the sender's guild, delivered to its online members, the
+    // sender included, as `GuildChatMessage`
+    GuildChat {
+        text: String,
+    },
+    GuildChatMessage {
+        guild_id: GuildId,
+        from_id: usize,
+        name: Option<String>,
+        text: String,
+    },
+    QueryGuildRoster {
+        guild_id: GuildId,
+    },
+    // Answers `QueryGuildRoster`, and goes to online members whenever their
+    // guild changes. Only members see who is online.
+    GuildRoster {
+        guild_id: GuildId,
+        name: String,
+        tag: Option<String>,
+        members: Vec<GuildMember>,
+    },
+    // The receiver left or was kicked from their guild
+    GuildLeft {
+        guild_id: GuildId,
+    },
+    // Whisper an account rather than a player; needs an account. Delivered
+    // like `Whisper` if they are online, and left in their mailbox if not.
+    WhisperAccount {
+        account_id: AccountId,
+        text: String,
+    },
+    // Everything in the receiver's mailbox, on login or as soon as it arrives
+    MailReceived {
+        mail: Vec<Mail>,
+    },
+    // `player_id` just unlocked an achievement; goes to everyone in their room,
+    // them included
+    AchievementUnlocked {
+        player_id: usize,
+        achievement_id: String,
+        name: String,
+    },
+    // Play one of the server's emotes; players nearby get `PlayerEmote`
+    Emote {
+        id: String,
+    },
+    PlayerEmote {
+        player_id: usize,
+        emote: String,
+    },
+    // A chat line for one channel; everyone else on it gets `ChatMessage`.
+    // `UpdateMessage` is the same as the global channel.
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+    // Join a team in the current room, or leave it with `None`
+    SetTeam {
+        team: Option<TeamId>,
+    },
+    // Watch the current room rather than play in it; spectators have no team
+    Spectate {
+        spectating: bool,
+    },
+    // Someone in the receiver's room changed team or started or stopped
+    // spectating; sent for everyone already on a team or spectating when the
+    // receiver enters a room
+    PlayerTeam {
+        player_id: usize,
+        team: Option<TeamId>,
+        spectating: bool,
+    },
+    // Scroll back through a channel the sender is on, answered with
+    // `ChatHistory`. `before` is a line id from an earlier answer; `None`
+    // starts from the latest line.
+    FetchChatHistory {
+        channel: ChatChannel,
+        before: Option<u64>,
+        limit: u32,
+    },
+    ChatHistory {
+        channel: ChatChannel,
+        lines: Vec<ChatLine>,
+    },
+    // Sent on joining a server that can hand its state to a successor, or
+    // move sessions between connections. After an `Upgrading` disconnect, or
+    // when the client's address changes, sending it back in `Resume` from the
+    // new connection picks the session up where it left off.
+    SessionToken {
+        token: String,
+    },
+    Resume {
+        token: String,
+    },
+    // Where a standby that can take this server's place listens. If the
+    // connection drops without a `Disconnect`, try there with `Resume`.
+    Standby {
+        addr: String,
+    },
+    // Something happened over an area the receiver can see, catching the
+    // players in `caught`
+    AreaEvent {
+        kind: String,
+        x: f32,
+        y: f32,
+        radius: f32,
+        caught: Vec<usize>,
+    },
+    // The receiver walked into or out of one of the map's triggers
+    Trigger {
+        name: String,
+        entered: bool,
+    },
+    // A team took one of the room's capture points
+    PointCaptured {
+        name: String,
+        team: TeamId,
+    },
+    // Where a capture-the-flag room's flag is: at its base, carried by
+    // `carrier`, or dropped at (x, y). Sent when it moves between them, and
+    // for every flag when the receiver enters the room.
+    Flag {
+        team: TeamId,
+        carrier: Option<usize>,
+        x: f32,
+        y: f32,
+        home: bool,
+    },
+    // A team's score this round: captures in capture the flag, points in
+    // king of the hill. Sent as it changes and on entering the room.
+    TeamScore {
+        team: TeamId,
+        score: u32,
+    },
+    // Put down the flag the sender is carrying where they stand
+    DropFlag,
+    // Retired for `RoundOver`, and never sent. It keeps its place so the
+    // variants after it keep their bincode index.
+    RoundWon {
+        team: TeamId,
+    },
+    // Who holds a king of the hill room's hill changed: `team`, or no one,
+    // with `contested` when teams are tied on it. Sent for every hill when the
+    // receiver enters the room.
+    HillControl {
+        name: String,
+        team: Option<TeamId>,
+        contested: bool,
+    },
+    // Whole seconds left in a timed round, sent as it counts down and on
+    // entering the room. In overtime with no limit it stays at 0.
+    RoundTime {
+        remaining: u32,
+        overtime: bool,
+    },
+    // A team mode room's round ended, won by `winner` or drawn. Results show
+    // until the next `RoundTime`, when scores, and flags, start over.
+    RoundOver {
+        winner: Option<TeamId>,
+    },
+    // A room with a map rotation is voting on its next map for `seconds`,
+    // and on entering while it does. Answered with `MapVote`.
+    MapVoteStarted {
+        maps: Vec<String>,
+        seconds: u32,
+    },
+    // Pick the next map by its index in `MapVoteStarted::maps`
+    MapVote {
+        choice: u32,
+    },
+    // The room moved on to another map of its rotation, and on entering one
+    MapChanged {
+        name: String,
+    },
+    // An entity appeared in the receiver's room, and for every entity there
+    // when the receiver enters it
+    EntitySpawned {
+        id: u64,
+        kind: String,
+        x: f32,
+        y: f32,
+        metadata: BTreeMap<String, String>,
+    },
+    EntityDespawned {
+        id: u64,
+    },
+    // Who simulates an entity now, to its room, and on entering one for every
+    // entity a player has authority over; `None` is the server
+    EntityAuthority {
+        id: u64,
+        owner: Option<usize>,
+    },
+    // Hand an entity the sender has authority over to another player in the
+    // room, or back to the server with `None`
+    TransferAuthority {
+        id: u64,
+        to: Option<usize>,
+    },
+    // Where the sender moved an entity they have authority over
+    MoveEntity {
+        id: u64,
+        x: f32,
+        y: f32,
+    },
+    // An entity moved, to its room
+    EntityMoved {
+        id: u64,
+        x: f32,
+        y: f32,
+    },
+    // Use an ability at (x, y), once the sender's earlier actions are done;
+    // answered with `AbilityUsed`, while the room sees the `AreaEvent` it
+    // sets off
+    UseAbility {
+        name: String,
+        x: f32,
+        y: f32,
+    },
+    // The sender's ability went off: when it is ready again and the energy
+    // left
+    AbilityUsed {
+        name: String,
+        ready_in: f32,
+        energy: u32,
+    },
+    // The receiver's XP and level, on logging in and whenever they earn XP.
+    // `next` is the total XP the next level takes, `None` at the top.
+    Experience {
+        xp: u64,
+        level: u32,
+        next: Option<u64>,
+    },
+    // A player in the receiver's room went up a level
+    LevelUp {
+        player_id: usize,
+        level: u32,
+    },
+    // The level of a logged-in player who just entered the receiver's room
+    PlayerLevel {
+        id: usize,
+        level: u32,
+    },
+    // Open a chest or pick up an item entity within `loot.reach`
+    Interact {
+        id: u64,
+    },
+    // Loot dropped near the receiver, opened by `player_id` if anyone
+    LootDropped {
+        x: f32,
+        y: f32,
+        items: Vec<ItemGrant>,
+        player_id: Option<usize>,
+    },
+    // Everything the receiver holds, a stack at a time, on logging in and
+    // whenever it changes
+    Inventory {
+        items: Vec<ItemGrant>,
+    },
+    // Craft a recipe from the sender's inventory. Like `Buy` and `Sell`,
+    // carries a `nonce` above any the session sent before, so a replayed
+    // frame is refused with `Replayed` rather than done again.
+    Craft {
+        recipe_id: String,
+        nonce: u64,
+    },
+    // The receiver's craft went ahead and gave them `items`
+    Crafted {
+        recipe_id: String,
+        items: Vec<ItemGrant>,
+    },
+    // The receiver's craft didn't go ahead; `missing` is what they are short
+    // of, by how many, for `MissingItems`
+    CraftFailed {
+        recipe_id: String,
+        code: CraftError,
+        missing: Vec<ItemGrant>,
+    },
+    // Buy from, or sell to, the shop entity within `loot.reach`
+    Buy {
+        entity: u64,
+        item: String,
+        quantity: u32,
+        nonce: u64,
+    },
+    // With `id`, from that stack of the sender's (see `crate::inventory`)
+    Sell {
+        entity: u64,
+        item: String,
+        quantity: u32,
+        id: Option<ItemId>,
+        nonce: u64,
+    },
+    // What the shop the receiver interacted with sells and buys, priced in
+    // `currency`
+    Shop {
+        entity: u64,
+        currency: String,
+        offers: Vec<ShopOffer>,
+    },
+    // The receiver's trade went ahead, for `price` in all
+    Traded {
+        entity: u64,
+        item: String,
+        quantity: u32,
+        price: u32,
+        selling: bool,
+    },
+    // The receiver's trade didn't go ahead; `missing` is what they are short
+    // of, currency or items, for `MissingItems`
+    TradeFailed {
+        entity: u64,
+        item: String,
+        code: TradeError,
+        missing: Vec<ItemGrant>,
+    },
+    // Wait in the lobby for a slot in a running match (see `crate::matchmaking`)
+    FindMatch,
+    LeaveQueue,
+    // The receiver's place in the queue, from 1, or `None` once they left it,
+    // and how many slots running matches have open
+    Queued {
+        position: Option<u32>,
+        open_slots: u32,
+    },
+    // The receiver was put in a running match, on `team`; joining the room
+    // follows
+    MatchFound {
+        room_id: RoomId,
+        team: TeamId,
+    },
+    // The client applied the snapshot for `tick` (see `crate::deltas`)
+    SnapshotAck {
+        tick: u64,
+    },
+    // For clients that negotiated delta snapshots: the players new or moved
+    // since the `baseline` tick the client acked, and the ids of those gone.
+    // With no baseline it is a keyframe, listing every player.
+    DeltaSnapshot {
+        tick: u64,
+        baseline: Option<u64>,
+        players: Vec<PlayerSnapshot>,
+        removed: Vec<usize>,
+    },
+    // An admin froze the simulation, and later let it run again; moves are
+    // dropped in between
+    SimulationPaused,
+    SimulationResumed,
+    // Simulated time runs this many times as fast as real time, from 1
+    TimeScale {
+        scale: f32,
+    },
+    // Browse the rooms matching `filters`, by id, answered with `RoomList`.
+    // `after` is the last room id of an earlier page; `None` starts from the
+    // first.
+    ListRooms {
+        filters: RoomFilters,
+        after: Option<RoomId>,
+        limit: u32,
+    },
+    // `more` says whether there are matching rooms past this page
+    RoomList {
+        rooms: Vec<RoomSummary>,
+        more: bool,
+    },
+    // Invite a friend or party member into the sender's room, answered to
+    // them with `Invite`; `AcceptInvite` takes them in past the room's
+    // password while the invite lasts
+    InviteToRoom {
+        player_id: usize,
+    },
+    Invite {
+        room_id: RoomId,
+        name: String,
+        from_id: usize,
+        from_name: Option<String>,
+    },
+    AcceptInvite {
+        room_id: RoomId,
+    },
+    // Who hosts the room, sent to its members when that changes and on
+    // entering it. Only player-created rooms have a host.
+    RoomHost {
+        room_id: RoomId,
+        player_id: Option<usize>,
+    },
+    // What only the host of the sender's room may do: send a member back to
+    // the lobby for good, change what the room was created with, and start
+    // its match over
+    KickFromRoom {
+        player_id: usize,
+    },
+    UpdateRoomSettings {
+        settings: RoomSettings,
+    },
+    StartMatch,
+    // The host changed the room's settings
+    RoomSettingsChanged {
+        room_id: RoomId,
+        settings: RoomSettings,
+    },
+    // Go into one of the server's named worlds (see `crate::worlds`),
+    // loading it first if no one is in it; `RoomJoined` follows once it is
+    EnterWorld {
+        name: String,
+    },
+    // Retired for `ModifyWorld`, and refused. It keeps its place so the
+    // variants after it keep their bincode index.
+    EditTerrain {
+        x: i32,
+        y: i32,
+        value: Option<String>,
+    },
+    // A cell of the receiver's world changed
+    TerrainChanged {
+        x: i32,
+        y: i32,
+        value: Option<String>,
+    },
+    // Retired for `TerrainChunk`, and never sent. It keeps its place so the
+    // variants after it keep their bincode index.
+    Terrain {
+        cells: Vec<TerrainCell>,
+    },
+    // Every set cell of a chunk of the receiver's world, as it comes into
+    // view; `x` and `y` are the chunk's, not a cell's (see `crate::worlds`)
+    TerrainChunk {
+        x: i32,
+        y: i32,
+        cells: Vec<TerrainCell>,
+    },
+    // A chunk passed out of view; its cells may be dropped
+    ForgetChunk {
+        x: i32,
+        y: i32,
+    },
+    // Change a terrain cell of the sender's world (see `crate::worlds`)
+    ModifyWorld {
+        position: CellPosition,
+        change: TerrainChange,
+    },
+    // One of the receiver's room's scenario objectives was set, completed or
+    // dropped (see `crate::scenarios`). Sent for every objective set so far
+    // when the receiver enters the room.
+    Objective {
+        name: String,
+        text: String,
+        state: ObjectiveState,
+    },
+    // The receiver's room's difficulty changed (see `crate::difficulty`);
+    // also sent on entering a room that isn't at 1 across the board
+    DifficultyChanged {
+        difficulty: Difficulty,
+    },
+    // How the receiver's connection is doing, for a net graph (see
+    // `crate::net_stats`): bytes each way since it connected, the round trip
+    // in milliseconds and the share of recent reports lost, from 0 to 1. Ack
+    // each with its `sequence`.
+    NetStats {
+        sequence: u64,
+        bytes_in: u64,
+        bytes_out: u64,
+        rtt: Option<u32>,
+        loss: Option<f32>,
+    },
+    NetStatsAck {
+        sequence: u64,
+    },
+    // The receiver's player UUID: who it is beyond this connection and this
+    // server process (see `state::Player::uuid`). Sent after `Hello` and again
+    // whenever logging in changes it.
+    Identity {
+        uuid: PlayerUuid,
+    },
+    // The sender's ability started casting and goes off in `cast_seconds`,
+    // followed by `AbilityUsed`; abilities without a cast time skip this
+    AbilityCasting {
+        name: String,
+        cast_seconds: f32,
+    },
+}
+
+impl ClientMessage {
+    // The longest string, in bytes, and the longest list or buffer this message
+    // carries, for checking untrusted input against `codec::DecodeLimits`
+    pub fn largest_fields(&self) -> (usize, usize) {
+        let longest = |strings: &[Option<&String>]| {
+            strings.iter().flatten().map(|s| s.len()).max().unwrap_or(0)
+        };
+        match self {
+            ClientMessage::PlayerPosition { .. }
+            | ClientMessage::AssignPlayerId { .. }
+            | ClientMessage::OtherPlayerConnected { .. }
+            | ClientMessage::Welcome { .. }
+            | ClientMessage::LeaveRoom
+            | ClientMessage::RoomLeft { .. }
+            | ClientMessage::QueryPlayerStats { .. }
+            | ClientMessage::PlayerStats { .. }
+            | ClientMessage::FriendAdd { .. }
+            | ClientMessage::FriendAccept { .. }
+            | ClientMessage::FriendRemove { .. }
+            | ClientMessage::FriendPresence { .. }
+            | ClientMessage::Mute { .. }
+            | ClientMessage::Unmute { .. }
+            | ClientMessage::PartyInvite { .. }
+            | ClientMessage::PartyAccept { .. }
+            | ClientMessage::PartyLeave
+            | ClientMessage::PartyLeft { .. }
+            | ClientMessage::GuildInvite { .. }
+            | ClientMessage::GuildJoin { .. }
+            | ClientMessage::GuildLeave
+            | ClientMessage::GuildKick { .. }
+            | ClientMessage::GuildSetRank { .. }
+            | ClientMessage::QueryGuildRoster { .. }
+            | ClientMessage::GuildLeft { .. }
+            | ClientMessage::SetTeam { .. }
+            | ClientMessage::Spectate { .. }
+            | ClientMessage::PlayerTeam { .. }
+            | ClientMessage::FetchChatHistory { .. }
+            | ClientMessage::Flag { .. }
+            | ClientMessage::TeamScore { .. }
+            | ClientMessage::DropFlag
+            | ClientMessage::RoundWon { .. }
+            | ClientMessage::RoundOver { .. }
+            | ClientMessage::RoundTime { .. }
+            | ClientMessage::MapVote { .. }
+            | ClientMessage::EntityDespawned { .. }
+            | ClientMessage::EntityAuthority { .. }
+            | ClientMessage::TransferAuthority { .. }
+            | ClientMessage::MoveEntity { .. }
+            | ClientMessage::EntityMoved { .. }
+            | ClientMessage::Experience { .. }
+            | ClientMessage::LevelUp { .. }
+            | ClientMessage::PlayerLevel { .. }
+            | ClientMessage::Interact { .. }
+            | ClientMessage::FindMatch
+            | ClientMessage::LeaveQueue
+            | ClientMessage::Queued { .. }
+            | ClientMessage::MatchFound { .. }
+            | ClientMessage::SnapshotAck { .. }
+            | ClientMessage::SimulationPaused
+            | ClientMessage::SimulationResumed
+            | ClientMessage::TimeScale { .. }
+            | ClientMessage::InviteToRoom { .. }
+            | ClientMessage::AcceptInvite { .. }
+            | ClientMessage::RoomHost { .. }
+            | ClientMessage::KickFromRoom { .. }
+            | ClientMessage::StartMatch
+            | ClientMessage::ForgetChunk { .. }
+            | ClientMessage::DifficultyChanged { .. }
+            | ClientMessage::NetStats { .. }
+            | ClientMessage::NetStatsAck { .. }
+            | ClientMessage::Identity { .. } => (0, 0),
+            ClientMessage::EnterWorld { name } => (longest(&[Some(name)]), 0),
+            ClientMessage::ModifyWorld { change, .. } => {
+                (longest(&[change.seen.as_ref(), change.value.as_ref()]), 0)
+            }
+            ClientMessage::EditTerrain { value, .. }
+            | ClientMessage::TerrainChanged { value, .. } => (longest(&[value.as_ref()]), 0),
+            ClientMessage::Terrain { cells } | ClientMessage::TerrainChunk { cells, .. } => {
+                let values: Vec<_> = cells.iter().map(|cell| Some(&cell.value)).collect();
+                (longest(&values), cells.len())
+            }
+            ClientMessage::UpdateRoomSettings { settings }
+            | ClientMessage::RoomSettingsChanged { settings, .. } => (
+                longest(&[settings.map.as_ref(), settings.scenario.as_ref()]),
+                0,
+            ),
+            ClientMessage::Invite {
+                name, from_name, ..
+            } => (longest(&[Some(name), from_name.as_ref()]), 0),
+            ClientMessage::ListRooms { filters, .. } => (longest(&[filters.name.as_ref()]), 0),
+            ClientMessage::RoomList { rooms, .. } => {
+                let names: Vec<_> = rooms.iter().map(|room| Some(&room.name)).collect();
+                (longest(&names), rooms.len())
+            }
+            ClientMessage::UpdateMessage { message: text, .. }
+            | ClientMessage::ServerError { context: text, .. }
+            | ClientMessage::Disconnected { message: text, .. }
+            | ClientMessage::RoomJoined { name: text, .. }
+            | ClientMessage::ConsoleCommand { line: text }
+            | ClientMessage::ConsoleOutput { text }
+            | ClientMessage::Announcement { text, .. }
+            | ClientMessage::SetName { name: text }
+            | ClientMessage::Whisper { text, .. }
+            | ClientMessage::WhisperAccount { text, .. }
+            | ClientMessage::Chat { text, .. }
+            | ClientMessage::Emote { id: text }
+            | ClientMessage::PlayerEmote { emote: text, .. }
+            | ClientMessage::PartyChat { text }
+            | ClientMessage::GuildChat { text }
+            | ClientMessage::GuildInvited { name: text, .. } => (text.len(), 0),
+            ClientMessage::PlayerNamed { name, tag, .. }
+            | ClientMessage::GuildCreate { name, tag } => (longest(&[Some(name), tag.as_ref()]), 0),
+            ClientMessage::PartyInvited { name, .. } => (longest(&[name.as_ref()]), 0),
+            ClientMessage::AchievementUnlocked {
+                achievement_id,
+                name,
+                ..
+            } => (longest(&[Some(achievement_id), Some(name)]), 0),
+            ClientMessage::ChatMessage { name, message, .. } => {
+                (longest(&[name.as_ref(), Some(message)]), 0)
+            }
+            ClientMessage::PartyUpdate {
+                members, invited, ..
+            } => (0, members.len().max(invited.len())),
+            ClientMessage::WhisperMessage { name, text, .. }
+            | ClientMessage::PartyChatMessage { name, text, .. }
+            | ClientMessage::GuildChatMessage { name, text, .. } => {
+                (longest(&[name.as_ref(), Some(text)]), 0)
+            }
+            ClientMessage::JoinSnapshot { players, .. } => {
+                let names: Vec<_> = players
+                    .iter()
+                    .flat_map(|p| [p.name.as_ref(), p.tag.as_ref()])
+                    .collect();
+                let accessories = players.iter().map(|p| p.appearance.accessories.len());
+                (longest(&names), accessories.fold(players.len(), usize::max))
+            }
+            ClientMessage::PlayerAppearance { accessories, .. } => (0, accessories.len()),
+            ClientMessage::AreaEvent { kind, caught, .. } => (kind.len(), caught.len()),
+            ClientMessage::Objective { name, text, .. } => (longest(&[Some(name), Some(text)]), 0),
+            ClientMessage::Register { username, password }
+            | ClientMessage::Login { username, password } => {
+                (longest(&[Some(username), Some(password)]), 0)
+            }
+            ClientMessage::LoggedIn { username, .. } => (username.len(), 0),
+            ClientMessage::TokenLogin { token: text }
+            | ClientMessage::SteamLogin { ticket: text }
+            | ClientMessage::SessionToken { token: text }
+            | ClientMessage::Resume { token: text }
+            | ClientMessage::Standby { addr: text }
+            | ClientMessage::Trigger { name: text, .. }
+            | ClientMessage::PointCaptured { name: text, .. }
+            | ClientMessage::HillControl { name: text, .. }
+            | ClientMessage::MapChanged { name: text }
+            | ClientMessage::UseAbility { name: text, .. }
+            | ClientMessage::AbilityUsed { name: text, .. }
+            | ClientMessage::AbilityCasting { name: text, .. }
+            | ClientMessage::Craft {
+                recipe_id: text, ..
+            }
+            | ClientMessage::Buy { item: text, .. }
+            | ClientMessage::Sell { item: text, .. }
+            | ClientMessage::Traded { item: text, .. } => (text.len(), 0),
+            ClientMessage::EntitySpawned { kind, metadata, .. } => {
+                let mut strings: Vec<_> = metadata
+                    .iter()
+                    .flat_map(|(k, v)| [Some(k), Some(v)])
+                    .collect();
+                strings.push(Some(kind));
+                (longest(&strings), metadata.len())
+            }
+            ClientMessage::LootDropped { items, .. } | ClientMessage::Inventory { items } => {
+                let names: Vec<_> = items.iter().map(|grant| Some(&grant.item)).collect();
+                (longest(&names), items.len())
+            }
+            ClientMessage::Crafted { recipe_id, items }
+            | ClientMessage::CraftFailed {
+                recipe_id,
+                missing: items,
+                ..
+            }
+            | ClientMessage::TradeFailed {
+                item: recipe_id,
+                missing: items,
+                ..
+            } => {
+                let mut names: Vec<_> = items.iter().map(|grant| Some(&grant.item)).collect();
+                names.push(Some(recipe_id));
+                (longest(&names), items.len())
+            }
+            ClientMessage::Shop {
+                currency, offers, ..
+            } => {
+                let mut names: Vec<_> = offers.iter().map(|offer| Some(&offer.item)).collect();
+                names.push(Some(currency));
+                (longest(&names), offers.len())
+            }
+            ClientMessage::MapVoteStarted { maps, .. } => {
+                let names: Vec<_> = maps.iter().map(Some).collect();
+                (longest(&names), maps.len())
+            }
+            ClientMessage::FriendList {
+                friends,
+                incoming,
+                outgoing,
+            } => {
+                let lists = [friends, incoming, outgoing];
+                let names: Vec<_> = lists
+                    .iter()
+                    .flat_map(|list| list.iter())
+                    .map(|f| Some(&f.username))
+                    .collect();
+                (
+                    longest(&names),
+                    lists.iter().map(|list| list.len()).fold(0, usize::max),
+                )
+            }
+            ClientMessage::GuildRoster {
+                name, tag, members, ..
+            } => {
+                let mut names: Vec<_> = members.iter().map(|m| Some(&m.username)).collect();
+                names.extend([Some(name), tag.as_ref()]);
+                (longest(&names), members.len())
+            }
+            ClientMessage::ChatHistory { lines, .. } => {
+                let strings: Vec<_> = lines
+                    .iter()
+                    .flat_map(|line| [line.name.as_ref(), Some(&line.text)])
+                    .collect();
+                (longest(&strings), lines.len())
+            }
+            ClientMessage::MailReceived { mail } => {
+                let strings: Vec<_> = mail
+                    .iter()
+                    .flat_map(|m| {
+                        let items = m.items.iter().map(|grant| Some(&grant.item));
+                        [m.from_name.as_ref(), Some(&m.text)]
+                            .into_iter()
+                            .chain(items)
+                    })
+                    .collect();
+                let lists = mail.iter().map(|m| m.items.len());
+                (longest(&strings), lists.fold(mail.len(), usize::max))
+            }
+            ClientMessage::Hello {
+                wire_formats,
+                snapshot_formats,
+                password,
+                ..
+            } => (
+                longest(&[password.as_ref()]),
+                wire_formats.len().max(snapshot_formats.len()),
+            ),
+            ClientMessage::CreateRoom {
+                name,
+                password,
+                settings,
+            } => (
+                longest(&[
+                    Some(name),
+                    password.as_ref(),
+                    settings.map.as_ref(),
+                    settings.scenario.as_ref(),
+                ]),
+                0,
+            ),
+            ClientMessage::JoinRoom { password, .. } => (longest(&[password.as_ref()]), 0),
+            ClientMessage::WorldSnapshot { players, .. } => (0, players.len()),
+            ClientMessage::DeltaSnapshot {
+                players, removed, ..
+            } => (0, players.len().max(removed.len())),
+            ClientMessage::FlatSnapshot { buffer } | ClientMessage::ArchivedSnapshot { buffer } => {
+                (0, buffer.len())
+            }
+        }
+    }
+}
+
+// Machine-readable reasons for `ServerError`. The numeric value of each code is
+// its position here, so only ever append new codes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    // The frame could not be decoded in the connection's wire format
+    MalformedMessage,
+    // The frame carried a message type the server doesn't know
+    UnknownMessage,
+    // The message is valid but only the server may send it
+    UnexpectedMessage,
+    ProtocolMismatch,
+    // The message names a player other than the sender
+    PlayerMismatch,
+    // The room does not exist
+    InvalidRoom,
+    WrongPassword,
+    // The server requires a `Hello` before anything else
+    HandshakeRequired,
+    // The request was well-formed but its contents were not acceptable
+    InvalidRequest,
+    // The sender's role does not allow this
+    PermissionDenied,
+    // Too many of these too quickly; try again later
+    RateLimited,
+    // The action's nonce was already used by the session: a replayed frame
+    Replayed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerSnapshot {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+    // Extrapolated, because the player's moves stopped arriving (see
+    // `crate::shards`)
+    pub stale: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NamedPlayer {
+    pub id: usize,
+    pub name: Option<String>,
+    // The player's guild tag, if their guild has one
+    pub tag: Option<String>,
+    pub x: f32,
+    pub y: f32,
+    pub appearance: Appearance,
+    // `None` for guests
+    pub level: Option<u32>,
+}
+
+// An entry in `GuildRoster`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GuildMember {
+    pub account_id: AccountId,
+    pub username: String,
+    pub rank: GuildRank,
+    pub online: bool,
+}
+
+// Server text as a message key with named parameters, for clients to look up
+// in their own translations (see `locale`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LocalizedText {
+    pub key: String,
+    pub params: Vec<TextParam>,
+}
+
+impl LocalizedText {
+    pub fn new(key: &str) -> Self {
+        LocalizedText {
+            key: key.to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.push(TextParam {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextParam {
+    pub name: String,
+    pub value: String,
+}
+
+// An entry in `ChatHistory`. Ids only ever grow, across all channels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatLine {
+    pub id: u64,
+    // Unix seconds
+    pub sent_at: u64,
+    pub from_id: usize,
+    pub name: Option<String>,
+    pub text: String,
+}
+
+// What `ListRooms` narrows the rooms down to; unset filters let every room
+// through
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct RoomFilters {
+    // Part of the name, in any case
+    pub name: Option<String>,
+    pub mode: Option<GameMode>,
+    // Leave out rooms with no room for one more player
+    pub not_full: bool,
+    // Only rooms that do, or don't, take a password
+    pub password: Option<bool>,
+}
+
+// An entry in `RoomList`. The lobby isn't a room, so it is never listed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomSummary {
+    pub room_id: RoomId,
+    pub name: String,
+    pub mode: GameMode,
+    pub players: u32,
+    // `None` when the room takes any number
+    pub max_players: Option<u32>,
+    pub password: bool,
+}
+
+// A cell of a world's terrain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellPosition {
+    pub x: i32,
+    pub y: i32,
+}
+
+// What `ModifyWorld` does to a cell
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TerrainChange {
+    // What the sender last saw in the cell, `None` for nothing; the change is
+    // refused if that's no longer so
+    pub seen: Option<String>,
+    // What to put there, or `None` to clear it
+    pub value: Option<String>,
+}
+
+// An entry in `TerrainChunk`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TerrainCell {
+    pub x: i32,
+    pub y: i32,
+    pub value: String,
+}
+
+// An entry in `FriendList`. Requests carry no presence: only friends get to
+// see whether an account is online and where.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Friend {
+    pub account_id: AccountId,
+    pub uuid: PlayerUuid,
+    pub username: String,
+    pub online: bool,
+    pub room_id: Option<RoomId>,
+}
+
+// How a player's avatar looks, as carried by `PlayerAppearance`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Appearance {
+    pub skin: u32,
+    pub color: u32,
+    pub accessories: Vec<u32>,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            skin: 0,
+            color: 0xffffff,
+            accessories: Vec::new(),
+        }
+    }
+}
+
+// Who a chat line is for. `Room` reaches players and spectators alike;
+// `Spectator` never reaches players. Only ever append.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatChannel {
+    // Everyone on the server
+    Global,
+    Room,
+    Team,
+    Party,
+    Spectator,
+}
+
+// Where a scenario objective stands. `Dropped` objectives, of a scenario
+// that ended or started over, are to be taken off the screen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectiveState {
+    Active,
+    Completed,
+    Dropped,
+}
+
+// Why the server closed a connection. Like `ErrorCode`, only ever append.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Kicked,
+    Banned,
+    // Nothing was received from the client for longer than the idle timeout
+    Timeout,
+    ServerShutdown,
+    // The client broke the protocol, e.g. by speaking an incompatible version
+    ProtocolError,
+    // The server is in maintenance mode and not accepting players
+    Maintenance,
+    // The server only admits whitelisted identities
+    NotWhitelisted,
+    // The server password in `Hello` was missing or wrong
+    WrongPassword,
+    // The client's address is connecting too often or holds too many
+    // connections, or the client sent too much
+    Throttled,
+    // The server is draining for a restart; reconnect once it is back
+    Restarting,
+    // The server handed its state to a new process on the same address;
+    // reconnect right away and send `Resume`
+    Upgrading,
+    // The session moved to a newer connection that sent `Resume`
+    Migrated,
+    // The server has no player id left to give
+    ServerFull,
+}
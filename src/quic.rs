@@ -0,0 +1,40 @@
+// QUIC, alongside framed TCP (`quic` feature). A client connects with ALPN
+// `ALPN`, opens one bidirectional stream and sends `Hello` on it as it would
+// over TCP: every frame on the stream is a LEB128 length followed by the
+// encoded message, in the connection's wire format. Besides the stream, each
+// QUIC datagram carries one encoded message, without a length. The server
+// sends what `reliability::default_delivery` says may be lost (snapshots and
+// net stats) as datagrams when the client accepts them and the frame fits,
+// and everything else on the stream. Clients may send positions and net
+// stats acks either way, though positions sent as datagrams are applied in
+// whatever order they arrive.
+// TLS comes from the certificate and key in `cert_file` and `key_file`.
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const ALPN: &[u8] = b"game-server";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct QuicConfig {
+    // Where to accept QUIC connections; `None` leaves QUIC off
+    pub listen_addr: Option<String>,
+    // PEM files: the certificate chain, leaf first, and its private key
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+}
+
+impl QuicConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen_addr.is_none() {
+            return Ok(());
+        }
+        if !cfg!(feature = "quic") {
+            return Err("QUIC is not available in this build".to_string());
+        }
+        if self.cert_file.is_none() || self.key_file.is_none() {
+            return Err("QUIC needs `cert_file` and `key_file`".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+// How often each category of update goes out, per second, within the tick
+// rate `snapshot_rate` sets. Positions are the room snapshots; the scoreboard
+// is team scores; the world is where entities are. A rate the tick rate
+// doesn't divide is rounded to a whole number of ticks. Categories without a
+// rate go out with every tick (positions) or as they happen (the rest). What
+// changes in between is coalesced: only the latest score of a team and the
+// latest position of an entity are sent.
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct UpdateRateConfig {
+    pub positions: Option<u32>,
+    pub scoreboard: Option<u32>,
+    pub world: Option<u32>,
+}
+
+impl UpdateRateConfig {
+    pub fn validate(&self, snapshot_rate: u32) -> Result<(), String> {
+        let rates = [
+            ("positions", self.positions),
+            ("scoreboard", self.scoreboard),
+            ("world", self.world),
+        ];
+        for (name, rate) in rates {
+            match rate {
+                Some(0) => return Err(format!("`update_rates.{}` must be at least 1", name)),
+                Some(rate) if rate > snapshot_rate => {
+                    return Err(format!(
+                        "`update_rates.{}` can't be more than `snapshot_rate` ({})",
+                        name, snapshot_rate
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Updates at `rate` go out every this many ticks
+pub fn every(rate: Option<u32>, snapshot_rate: u32) -> u64 {
+    rate.map_or(1, |rate| (snapshot_rate / rate).max(1) as u64)
+}
@@ -0,0 +1,38 @@
+// How each message type is delivered. Transports that can lose traffic on
+// purpose use this to choose: QUIC sends what may be lost as datagrams and
+// everything else on its stream, and framed TCP delivers everything anyway.
+//
+// A reliability layer of our own is deferred until there is a raw UDP
+// transport for it to serve. That layer would cover acks, resends, sequenced
+// channels and fragmenting over plain datagrams. QUIC's stream already acks,
+// resends and orders, but its datagrams are neither ordered nor sequenced:
+// what goes that way has to carry its own tick or sequence number.
+use crate::protocol::ClientMessage;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Delivery {
+    // May be lost, duplicated or arrive out of order
+    Unreliable,
+    // May be lost or arrive out of order, so it carries a tick or sequence
+    // number that lets receivers drop anything older than what they have
+    UnreliableSequenced,
+    // Resent until acknowledged, and delivered once each in the order sent
+    ReliableOrdered,
+}
+
+// Snapshots and net stats are superseded by the next one, so losing one
+// costs less than waiting for a resend; everything else has to arrive. A lost
+// net stats report is also what its loss figure counts. Relayed positions
+// carry no tick or sequence to put them back in order, so they go reliably.
+pub fn default_delivery(message: &ClientMessage) -> Delivery {
+    match message {
+        ClientMessage::WorldSnapshot { .. }
+        | ClientMessage::FlatSnapshot { .. }
+        | ClientMessage::ArchivedSnapshot { .. }
+        | ClientMessage::NetStats { .. }
+        | ClientMessage::NetStatsAck { .. } => Delivery::UnreliableSequenced,
+        _ => Delivery::ReliableOrdered,
+    }
+}
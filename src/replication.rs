@@ -0,0 +1,197 @@
+// A warm standby for failover. A primary with `replication.listen_addr` set
+// streams to every standby that sends the token matching `token_hash`: each
+// store document as it is saved, and its live rooms, parties and sessions
+// once a second. A process started with `--standby` mirrors the stores into
+// its own data directory and keeps the latest live state. If the primary goes
+// quiet without saying goodbye and doesn't come back within `takeover_secs`,
+// the standby starts serving from that state, a second or so behind, and
+// players reconnect to it (to the same address through a floating IP, or to
+// the `public_addr` they were told in `Standby`) and send `Resume`.
+use crate::handoff::Handoff;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a standby tries the primary while it can't reach it
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    // Where standbys connect to a primary; `None` turns streaming off
+    pub listen_addr: Option<String>,
+    // Argon2 PHC string (see `--hash-password`) of the token standbys send
+    pub token_hash: Option<String>,
+    // On a standby: the primary's `listen_addr` and the token to send it
+    pub primary_addr: Option<String>,
+    pub token: Option<String>,
+    // Where players can reach this standby, told to them in advance so they
+    // know where to go if the primary dies
+    pub public_addr: Option<String>,
+    // Silence from the primary for this long counts as losing it
+    pub timeout_secs: u64,
+    // How long a standby keeps trying a lost primary before taking over
+    pub takeover_secs: u64,
+    // Set by `--standby`: follow `primary_addr` instead of serving
+    #[serde(skip)]
+    pub standby: bool,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        ReplicationConfig {
+            listen_addr: None,
+            token_hash: None,
+            primary_addr: None,
+            token: None,
+            public_addr: None,
+            timeout_secs: 5,
+            takeover_secs: 5,
+            standby: false,
+        }
+    }
+}
+
+impl ReplicationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.listen_addr.is_some() && self.token_hash.is_none() {
+            return Err("`replication.listen_addr` needs `replication.token_hash`".to_string());
+        }
+        if self.standby && (self.primary_addr.is_none() || self.token.is_none()) {
+            return Err(
+                "`--standby` needs `replication.primary_addr` and `replication.token`".to_string(),
+            );
+        }
+        if self.timeout_secs == 0 || self.takeover_secs == 0 {
+            return Err(
+                "`replication.timeout_secs` and `takeover_secs` must be positive integers"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+// The first line a standby sends
+#[derive(Serialize, Deserialize)]
+pub struct Follow {
+    pub token: String,
+    pub public_addr: Option<String>,
+}
+
+// One line of the stream from the primary
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Frame {
+    // A whole store document, under its storage key
+    Store { name: String, value: Value },
+    // An entry for a store document's journal
+    Journal { name: String, entry: Value },
+    Live(Handoff),
+    // Sent instead of an unchanged `Live`, so silence means trouble
+    Heartbeat,
+    // The primary is stopping on purpose; wait for it rather than take over
+    Goodbye,
+}
+
+// The standby's side: mirror the primary into `data_dir` until it is lost,
+// then return its last live state to start serving from
+pub fn follow(config: &ReplicationConfig, data_dir: &Path) -> io::Result<Handoff> {
+    let (Some(primary), Some(token)) = (&config.primary_addr, &config.token) else {
+        return Err(io::Error::other("no primary to follow"));
+    };
+    let hello = Follow {
+        token: token.clone(),
+        public_addr: config.public_addr.clone(),
+    };
+    let storage = Storage::new(data_dir);
+    let takeover = Duration::from_secs(config.takeover_secs);
+    // The primary's state while it is up, and since when it has been lost
+    let mut live = None;
+    let mut lost: Option<Instant> = None;
+    println!("Standing by for the primary on {}", primary);
+    loop {
+        if let Ok(stream) = TcpStream::connect(primary) {
+            lost = None;
+            let timeout = Duration::from_secs(config.timeout_secs);
+            match mirror(stream, &hello, &storage, &mut live, timeout) {
+                Ok(()) => {
+                    println!("The primary stopped on purpose; waiting for it to come back");
+                    live = None;
+                }
+                Err(e) if live.is_some() => {
+                    println!("Lost the primary ({}); taking over in {:?}", e, takeover);
+                    lost = Some(Instant::now());
+                }
+                Err(_) => {}
+            }
+        }
+        if live.is_some() && lost.is_some_and(|since| since.elapsed() >= takeover) {
+            break;
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+    println!("Taking over from the lost primary");
+    live.ok_or_else(|| io::Error::other("no state from the primary"))
+}
+
+// Read frames until the primary says goodbye (`Ok`) or is lost
+fn mirror(
+    mut stream: TcpStream,
+    hello: &Follow,
+    storage: &Storage,
+    live: &mut Option<Handoff>,
+    timeout: Duration,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    serde_json::to_writer(&stream, hello)?;
+    writeln!(stream)?;
+    let mut lines = BufReader::new(stream).lines();
+    let mut synced = false;
+    loop {
+        let line = lines
+            .next()
+            .ok_or_else(|| io::Error::other("the primary closed the stream"))??;
+        let frame = serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        match frame {
+            Frame::Store { name, value } => {
+                check_store_name(&name)?;
+                if let Err(e) = storage.save(&name, &value) {
+                    eprintln!("Failed to mirror {}: {}", name, e);
+                }
+            }
+            Frame::Journal { name, entry } => {
+                check_store_name(&name)?;
+                if let Err(e) = storage.append(&name, &entry) {
+                    eprintln!("Failed to mirror {}: {}", name, e);
+                }
+            }
+            Frame::Live(handoff) => {
+                if !synced {
+                    println!("Following the primary");
+                    synced = true;
+                }
+                *live = Some(handoff);
+            }
+            Frame::Heartbeat => {}
+            Frame::Goodbye => return Ok(()),
+        }
+    }
+}
+
+// Only ever a storage key, but it becomes a file name
+fn check_store_name(name: &str) -> io::Result<()> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(io::Error::other(format!("bad store name `{}`", name)));
+    }
+    Ok(())
+}
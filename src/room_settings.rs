@@ -0,0 +1,127 @@
+// Settings a player may pick for a room they create, in `CreateRoom`,
+// instead of it playing by the server's: how many players it takes, which
+// of `room_settings.maps` it plays, which of that map's modes, whether
+// abilities catch the caster's own team, and which of `scenarios` it runs. Each is checked against the bounds
+// here; anything left unset is the server's.
+use crate::maps::MapData;
+use crate::scenarios::Scenario;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RoomSettingsConfig {
+    // The most players a room may be set to take
+    pub max_players: u32,
+    // Whether abilities catch the caster's team in rooms that don't say
+    pub friendly_fire: bool,
+    // The maps rooms may be created with, by the name players pick them by
+    pub maps: BTreeMap<String, PathBuf>,
+}
+
+impl Default for RoomSettingsConfig {
+    fn default() -> Self {
+        RoomSettingsConfig {
+            max_players: 64,
+            friendly_fire: true,
+            maps: BTreeMap::new(),
+        }
+    }
+}
+
+impl RoomSettingsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_players == 0 {
+            return Err("`room_settings.max_players` must be at least 1".to_string());
+        }
+        if self.maps.keys().any(|name| name.is_empty()) {
+            return Err("`room_settings.maps` names can't be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Which of its map's team modes a room plays
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    // Neither, even if the map sets them up
+    FreePlay,
+    CaptureTheFlag,
+    KingOfTheHill,
+}
+
+impl GameMode {
+    // Strip the map of the modes not played
+    pub fn apply(self, map: &mut MapData) -> Result<(), String> {
+        match self {
+            GameMode::FreePlay => {
+                map.ctf = None;
+                map.koth = None;
+            }
+            GameMode::CaptureTheFlag => {
+                if map.ctf.is_none() {
+                    return Err("that map has no capture the flag".to_string());
+                }
+                map.koth = None;
+            }
+            GameMode::KingOfTheHill => {
+                if map.koth.is_none() {
+                    return Err("that map has no king of the hill".to_string());
+                }
+                map.ctf = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct RoomSettings {
+    pub max_players: Option<u32>,
+    // A name from `room_settings.maps`
+    pub map: Option<String>,
+    // Every mode the map sets up, when unset
+    pub mode: Option<GameMode>,
+    pub friendly_fire: Option<bool>,
+    // A name from `scenarios`
+    pub scenario: Option<String>,
+}
+
+impl RoomSettings {
+    // The map the room plays, with its mode applied, if it has one
+    pub fn validate(
+        &self,
+        config: &RoomSettingsConfig,
+        maps: &BTreeMap<String, MapData>,
+        scenarios: &BTreeMap<String, Scenario>,
+    ) -> Result<Option<MapData>, String> {
+        if let Some(max_players) = self.max_players {
+            if max_players == 0 || max_players > config.max_players {
+                return Err(format!("rooms take 1-{} players", config.max_players));
+            }
+        }
+        if let Some(name) = &self.scenario {
+            if !scenarios.contains_key(name) {
+                return Err(format!("no scenario `{}`", name));
+            }
+        }
+        let mut map = match &self.map {
+            Some(name) => Some(
+                maps.get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("no map `{}`", name))?,
+            ),
+            None => None,
+        };
+        match (self.mode, &mut map) {
+            (Some(mode), Some(map)) => mode.apply(map)?,
+            (Some(GameMode::FreePlay) | None, None) => {}
+            (Some(_), None) => return Err("pick a map to play that mode on".to_string()),
+            (None, Some(_)) => {}
+        }
+        Ok(map)
+    }
+}
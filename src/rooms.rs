@@ -0,0 +1,150 @@
+use crate::config::RoomConfig;
+use crate::difficulty::Difficulty;
+use crate::room_settings::RoomSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type RoomId = u32;
+// Teams only mean something within one room; the game decides what they are
+pub type TeamId = u8;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Room {
+    pub id: RoomId,
+    pub name: String,
+    // Argon2 PHC string; `None` means anyone may join
+    pub password_hash: Option<String>,
+    pub members: BTreeSet<usize>,
+    // Configured rooms stay around when empty; player-created ones don't
+    pub persistent: bool,
+    // Only set on configured rooms
+    pub ranked: bool,
+    // What the creator of a player-created room picked
+    #[serde(default)]
+    pub settings: RoomSettings,
+    // Who runs a player-created room: its creator, then whoever has been
+    // on the server longest of those left
+    #[serde(default)]
+    pub host: Option<usize>,
+    // Players the host kicked, who can't come back
+    #[serde(default)]
+    pub kicked: BTreeSet<usize>,
+    // What the room's NPCs are turned up or down to (see `crate::difficulty`)
+    #[serde(default)]
+    pub difficulty: Difficulty,
+}
+
+impl Room {
+    pub fn is_protected(&self) -> bool {
+        self.password_hash.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Rooms {
+    rooms: BTreeMap<RoomId, Room>,
+    next_id: RoomId,
+}
+
+impl Rooms {
+    // Rooms are numbered from `first_id + 1`
+    pub fn from_config(configs: &[RoomConfig], first_id: RoomId) -> Self {
+        let mut rooms = Rooms {
+            next_id: first_id,
+            ..Rooms::default()
+        };
+        for config in configs {
+            let id = rooms.create(config.name.clone(), config.password_hash.clone(), true);
+            rooms.rooms.get_mut(&id).unwrap().ranked = config.ranked;
+        }
+        rooms
+    }
+
+    // Bring the configured rooms in line with a reloaded config: new ones are
+    // created and existing ones pick up password and ranking changes
+    pub fn apply_config(&mut self, configs: &[RoomConfig]) {
+        for config in configs {
+            let existing = self
+                .rooms
+                .values_mut()
+                .find(|room| room.persistent && room.name == config.name);
+            match existing {
+                Some(room) => {
+                    room.password_hash = config.password_hash.clone();
+                    room.ranked = config.ranked;
+                }
+                None => {
+                    let id = self.create(config.name.clone(), config.password_hash.clone(), true);
+                    self.rooms.get_mut(&id).unwrap().ranked = config.ranked;
+                }
+            }
+        }
+    }
+
+    pub fn create(
+        &mut self,
+        name: String,
+        password_hash: Option<String>,
+        persistent: bool,
+    ) -> RoomId {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.rooms.insert(
+            id,
+            Room {
+                id,
+                name,
+                password_hash,
+                members: BTreeSet::new(),
+                persistent,
+                ranked: false,
+                settings: RoomSettings::default(),
+                host: None,
+                kicked: BTreeSet::new(),
+                difficulty: Difficulty::default(),
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: RoomId) -> Option<&Room> {
+        self.rooms.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: RoomId) -> Option<&mut Room> {
+        self.rooms.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Room> {
+        self.rooms.values()
+    }
+
+    pub fn join(&mut self, id: RoomId, player_id: usize) -> bool {
+        match self.rooms.get_mut(&id) {
+            Some(room) => room.members.insert(player_id),
+            None => false,
+        }
+    }
+
+    // Drop a room, members and all
+    pub fn remove(&mut self, id: RoomId) -> Option<Room> {
+        self.rooms.remove(&id)
+    }
+
+    // Remove a player from a room, dropping the room if it is now empty and
+    // not persistent. Returns whether the room was dropped.
+    pub fn leave(&mut self, id: RoomId, player_id: usize) -> bool {
+        let Some(room) = self.rooms.get_mut(&id) else {
+            return false;
+        };
+        room.members.remove(&player_id);
+        if room.host == Some(player_id) {
+            room.host = room.members.first().copied();
+        }
+        if room.members.is_empty() && !room.persistent {
+            self.rooms.remove(&id);
+            return true;
+        }
+        false
+    }
+}
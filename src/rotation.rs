@@ -0,0 +1,189 @@
+// Map rotation for a configured room, from its `rotation` instead of a single
+// `map`. The room plays each map of `maps` in turn for `rounds_per_map`
+// rounds (see `crate::rounds`), starting with the first. When the last of
+// them ends and `vote` is on, the players in the room pick the next map from
+// the `candidates` that come next in the list, for as long as the results
+// show. The most votes win, ties going to the earliest in the list, and no
+// votes at all keeps to the list. The new map is loaded before the next
+// round starts. Rooms without a round clock go straight on to the next map.
+use crate::maps::MapData;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub maps: Vec<PathBuf>,
+    pub rounds_per_map: u32,
+    pub vote: bool,
+    // How many maps a vote offers
+    pub candidates: usize,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        RotationConfig {
+            maps: Vec::new(),
+            rounds_per_map: 1,
+            vote: true,
+            candidates: 3,
+        }
+    }
+}
+
+impl RotationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.maps.is_empty() {
+            return Err("`rotation.maps` needs at least one map".to_string());
+        }
+        if self.rounds_per_map == 0 {
+            return Err("`rotation.rounds_per_map` must be at least 1".to_string());
+        }
+        if self.vote && self.candidates < 2 {
+            return Err("a map vote needs at least 2 `candidates`".to_string());
+        }
+        Ok(())
+    }
+}
+
+// What a map is called to clients: its file name without the extension
+pub fn map_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[derive(Debug)]
+pub struct Vote {
+    // Indexes into the rotation's maps, in the order offered
+    pub candidates: Vec<usize>,
+    // Each voter's pick, by index into `candidates`
+    ballots: HashMap<usize, usize>,
+}
+
+// What the end of a round means for the map
+#[derive(Debug, PartialEq)]
+pub enum AfterRound {
+    Stay,
+    // The next round is on the next map in the list
+    Next,
+    // The names of the maps on offer
+    Vote(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct Rotation {
+    pub config: RotationConfig,
+    maps: Vec<(String, MapData)>,
+    current: usize,
+    // Rounds played on the current map
+    rounds: u32,
+    next: Option<usize>,
+    vote: Option<Vote>,
+}
+
+impl Rotation {
+    // Every map is loaded up front, so a bad one is found at startup
+    pub fn load(config: RotationConfig) -> Result<Self, String> {
+        let maps = config
+            .maps
+            .iter()
+            .map(|path| {
+                let map =
+                    MapData::load(path).map_err(|e| format!("map {}: {}", path.display(), e))?;
+                if map.ctf.is_none() && map.koth.is_none() {
+                    return Err(format!(
+                        "map {} has no `ctf` or `koth` for its rounds to end by",
+                        path.display()
+                    ));
+                }
+                Ok((map_name(path), map))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Rotation {
+            config,
+            maps,
+            current: 0,
+            rounds: 0,
+            next: None,
+            vote: None,
+        })
+    }
+
+    pub fn current(&self) -> (&str, &MapData) {
+        let (name, map) = &self.maps[self.current];
+        (name, map)
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.maps[index].0
+    }
+
+    pub fn voting(&self) -> Option<&Vote> {
+        self.vote.as_ref()
+    }
+
+    // A round on the current map ended. `can_vote` is whether there is time
+    // to, before the next round starts.
+    pub fn round_over(&mut self, can_vote: bool) -> AfterRound {
+        self.rounds += 1;
+        if self.rounds < self.config.rounds_per_map || self.maps.len() == 1 {
+            return AfterRound::Stay;
+        }
+        let count = self.config.candidates.min(self.maps.len() - 1);
+        if !(can_vote && self.config.vote && count >= 2) {
+            self.next = Some((self.current + 1) % self.maps.len());
+            return AfterRound::Next;
+        }
+        let candidates: Vec<usize> = (1..=count)
+            .map(|offset| (self.current + offset) % self.maps.len())
+            .collect();
+        let names = candidates
+            .iter()
+            .map(|&index| self.maps[index].0.clone())
+            .collect();
+        self.vote = Some(Vote {
+            candidates,
+            ballots: HashMap::new(),
+        });
+        AfterRound::Vote(names)
+    }
+
+    // A later vote from the same player replaces their first
+    pub fn vote(&mut self, player_id: usize, choice: usize) -> Result<(), String> {
+        let Some(vote) = &mut self.vote else {
+            return Err("there is no map vote on".to_string());
+        };
+        if choice >= vote.candidates.len() {
+            return Err(format!(
+                "there are only {} maps to pick from",
+                vote.candidates.len()
+            ));
+        }
+        vote.ballots.insert(player_id, choice);
+        Ok(())
+    }
+
+    // The next round is starting: the map it is played on, if that changes
+    pub fn next_map(&mut self) -> Option<(&str, &MapData)> {
+        let next = match self.vote.take() {
+            Some(vote) => {
+                let mut tally = vec![0; vote.candidates.len()];
+                for &choice in vote.ballots.values() {
+                    tally[choice] += 1;
+                }
+                // `max_by_key` keeps the last of equals, so go from the back
+                let winner = (0..tally.len())
+                    .rev()
+                    .max_by_key(|&choice| tally[choice])
+                    .unwrap_or(0);
+                Some(vote.candidates[winner])
+            }
+            None => self.next.take(),
+        }?;
+        self.current = next;
+        self.rounds = 0;
+        Some(self.current())
+    }
+}
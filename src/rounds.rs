@@ -0,0 +1,179 @@
+// The round clock of a team mode room (see `crate::ctf` and `crate::koth`),
+// from the `round` section of its map. A round lasts `seconds`; when time is
+// up the team with the highest score wins. If the top scores are tied,
+// `overtime` decides what happens, and after the round the room shows
+// results for `results_seconds`, during which the mode stands still, before
+// the next round starts. Reaching the mode's score limit ends a round early.
+// Rooms whose map has no `round` section play on until a score limit is hit.
+use crate::rooms::TeamId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Overtime {
+    // A tie is a draw
+    #[default]
+    None,
+    // Play on for `seconds`; still tied after that is a draw
+    Extra {
+        seconds: u32,
+    },
+    // The first team to lead wins; past `seconds` it is a draw, and 0 plays
+    // on until someone leads
+    SuddenDeath {
+        seconds: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RoundConfig {
+    pub seconds: u32,
+    pub overtime: Overtime,
+    pub results_seconds: u32,
+}
+
+impl Default for RoundConfig {
+    fn default() -> Self {
+        RoundConfig {
+            seconds: 600,
+            overtime: Overtime::None,
+            results_seconds: 10,
+        }
+    }
+}
+
+impl RoundConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.seconds == 0 {
+            return Err("`round.seconds` must be at least 1".to_string());
+        }
+        if let Overtime::Extra { seconds: 0 } = self.overtime {
+            return Err("extra time must last at least a second".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Playing,
+    Overtime,
+    Results,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoundChange {
+    Overtime,
+    // `None` is a draw
+    Over { winner: Option<TeamId> },
+    Started,
+}
+
+// The only team with the top score, if there is one
+pub fn leader(scores: &[(TeamId, u32)]) -> Option<TeamId> {
+    let top = scores.iter().map(|&(_, score)| score).max()?;
+    let mut leaders = scores.iter().filter(|&&(_, score)| score == top);
+    match (leaders.next(), leaders.next()) {
+        (Some(&(team, _)), None) => Some(team),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct Round {
+    pub config: RoundConfig,
+    phase: Phase,
+    // The tick the phase ends on; `u64::MAX` for sudden death without a limit
+    ends_at: u64,
+    // The last whole seconds left sent to clients
+    announced: Option<u32>,
+}
+
+impl Round {
+    pub fn new(config: RoundConfig, tick: u64, snapshot_rate: u32) -> Self {
+        let ends_at = tick + config.seconds as u64 * snapshot_rate as u64;
+        Round {
+            config,
+            phase: Phase::Playing,
+            ends_at,
+            announced: None,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Whole seconds left in the phase, rounded up; `None` when it has no end
+    pub fn remaining(&self, tick: u64, snapshot_rate: u32) -> Option<u32> {
+        if self.ends_at == u64::MAX {
+            return None;
+        }
+        let rate = snapshot_rate.max(1) as u64;
+        Some(self.ends_at.saturating_sub(tick).div_ceil(rate) as u32)
+    }
+
+    // The seconds left, when that has changed since it was last asked for
+    pub fn clock(&mut self, tick: u64, snapshot_rate: u32) -> Option<u32> {
+        if self.phase == Phase::Results {
+            return None;
+        }
+        let remaining = self.remaining(tick, snapshot_rate).unwrap_or(0);
+        if self.announced == Some(remaining) {
+            return None;
+        }
+        self.announced = Some(remaining);
+        Some(remaining)
+    }
+
+    fn at(&mut self, phase: Phase, tick: u64, seconds: u32, snapshot_rate: u32) {
+        self.phase = phase;
+        self.ends_at = tick + seconds as u64 * snapshot_rate as u64;
+        self.announced = None;
+    }
+
+    // The round is over before time, such as by a score limit
+    pub fn finish(&mut self, tick: u64, snapshot_rate: u32) {
+        let seconds = self.config.results_seconds;
+        self.at(Phase::Results, tick, seconds, snapshot_rate);
+    }
+
+    // Once a tick with the mode's scores
+    pub fn tick(
+        &mut self,
+        tick: u64,
+        snapshot_rate: u32,
+        scores: &[(TeamId, u32)],
+    ) -> Option<RoundChange> {
+        let leader = leader(scores);
+        let over = |round: &mut Round, winner| {
+            round.finish(tick, snapshot_rate);
+            Some(RoundChange::Over { winner })
+        };
+        match self.phase {
+            Phase::Playing if tick >= self.ends_at => match (leader, self.config.overtime) {
+                (Some(team), _) => over(self, Some(team)),
+                (None, Overtime::None) => over(self, None),
+                (None, Overtime::Extra { seconds } | Overtime::SuddenDeath { seconds }) => {
+                    self.at(Phase::Overtime, tick, seconds, snapshot_rate);
+                    if seconds == 0 {
+                        self.ends_at = u64::MAX;
+                    }
+                    Some(RoundChange::Overtime)
+                }
+            },
+            Phase::Overtime => match (leader, self.config.overtime) {
+                (Some(team), Overtime::SuddenDeath { .. }) => over(self, Some(team)),
+                _ if tick >= self.ends_at => over(self, leader),
+                _ => None,
+            },
+            Phase::Results if tick >= self.ends_at => {
+                let seconds = self.config.seconds;
+                self.at(Phase::Playing, tick, seconds, snapshot_rate);
+                Some(RoundChange::Started)
+            }
+            _ => None,
+        }
+    }
+}
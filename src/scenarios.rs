@@ -0,0 +1,289 @@
+// Scenarios: tutorials and PvE events a room plays out, authored as JSON
+// files named in `scenarios` rather than in code. A configured room runs the
+// one its config names in `scenario`, and a created room the one picked in
+// its settings. The run starts when the first player enters the room, and
+// is dropped once the room stands empty, taking what it spawned with it, so
+// the next visitor starts over; a room's host starts it over with
+// `StartMatch`.
+//
+// A scenario is a list of events, each of which does its actions when its
+// `when` comes: so many seconds into the run (`at`), every so many seconds
+// (`every`), when a player enters or leaves one of the map's triggers or a
+// team captures one, or when an objective is completed. With `delay`, the
+// actions wait that many seconds more. Events fire once a run unless they
+// `repeat`. Actions announce text to the room, set objectives and complete
+// them (sent to clients as `Objective`), spawn, move and despawn entities by
+// a name the scenario gives them (its NPCs), drop loot, turn the room's
+// difficulty (see `crate::difficulty`), and `finish` the run, which fires
+// nothing more and is recorded in match history. The room's spawn rate
+// quickens `every` events that spawn. Time is simulated time, so pausing the server
+// pauses scenarios.
+use crate::difficulty::DifficultyChange;
+use crate::entities::Entity;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scenario {
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+    pub events: Vec<ScenarioEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub name: String,
+    // What the client shows the player
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScenarioEvent {
+    pub when: When,
+    #[serde(default)]
+    pub delay: f32,
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(rename = "do")]
+    pub actions: Vec<Action>,
+}
+
+// Times are in seconds into the run, and names are of the map's triggers or
+// the scenario's objectives
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum When {
+    At(f32),
+    Every(f32),
+    Entered(String),
+    Left(String),
+    Captured(String),
+    Completed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Announce(String),
+    Objective(String),
+    Complete(String),
+    // Spawning a name already spawned replaces that entity
+    Spawn {
+        name: String,
+        #[serde(flatten)]
+        entity: Entity,
+    },
+    Move {
+        name: String,
+        x: f32,
+        y: f32,
+    },
+    Despawn(String),
+    Loot {
+        table: String,
+        x: f32,
+        y: f32,
+    },
+    Difficulty(DifficultyChange),
+    Finish,
+}
+
+// What a run reacts to, besides the clock
+#[derive(Debug, Clone, PartialEq)]
+pub enum Happening {
+    Entered(String),
+    Left(String),
+    Captured(String),
+    Completed(String),
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Scenario, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let scenario: Scenario = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names = BTreeSet::new();
+        for objective in &self.objectives {
+            if objective.name.is_empty() || !names.insert(objective.name.as_str()) {
+                return Err(format!(
+                    "objective names must be unique and not empty: `{}`",
+                    objective.name
+                ));
+            }
+        }
+        let objective = |name: &String| match names.contains(name.as_str()) {
+            true => Ok(()),
+            false => Err(format!("no objective `{}`", name)),
+        };
+        let seconds = |value: f32| value.is_finite() && value >= 0.0;
+        for (index, event) in self.events.iter().enumerate() {
+            let event_error = |e: String| format!("event {}: {}", index + 1, e);
+            match &event.when {
+                When::At(at) if !seconds(*at) => {
+                    return Err(event_error("`at` must be 0 or more".to_string()))
+                }
+                When::Every(every) if !(every.is_finite() && *every > 0.0) => {
+                    return Err(event_error("`every` must be above 0".to_string()))
+                }
+                When::Completed(name) => objective(name).map_err(event_error)?,
+                _ => {}
+            }
+            if !seconds(event.delay) {
+                return Err(event_error("`delay` must be 0 or more".to_string()));
+            }
+            for action in &event.actions {
+                match action {
+                    Action::Objective(name) | Action::Complete(name) => {
+                        objective(name).map_err(event_error)?
+                    }
+                    Action::Move { x, y, .. } | Action::Loot { x, y, .. }
+                        if !(x.is_finite() && y.is_finite()) =>
+                    {
+                        return Err(event_error("positions must be numbers".to_string()))
+                    }
+                    Action::Difficulty(change) => change.validate().map_err(event_error)?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn objective(&self, name: &str) -> Option<&Objective> {
+        self.objectives
+            .iter()
+            .find(|objective| objective.name == name)
+    }
+}
+
+impl ScenarioEvent {
+    // Ticks between the comings of an `every` event
+    fn interval(&self, every: f32, snapshot_rate: u32, spawn_rate: f32) -> u64 {
+        let spawns = self
+            .actions
+            .iter()
+            .any(|action| matches!(action, Action::Spawn { .. }));
+        let every = match spawns {
+            true => every / spawn_rate,
+            false => every,
+        };
+        ticks(every, snapshot_rate).max(1)
+    }
+}
+
+// A room's run of its scenario
+#[derive(Debug)]
+pub struct ScenarioRun {
+    pub name: String,
+    // The tick each clock event next comes due
+    next: Vec<Option<u64>>,
+    fired: Vec<bool>,
+    // Events waiting out their delay: when they are due, and which
+    waiting: Vec<(u64, usize)>,
+    // The objectives set so far, and whether each is completed
+    pub objectives: BTreeMap<String, bool>,
+    // The entities the scenario spawned, by its names for them
+    pub spawned: BTreeMap<String, u64>,
+    pub finished: bool,
+}
+
+impl ScenarioRun {
+    pub fn new(
+        name: String,
+        scenario: &Scenario,
+        tick: u64,
+        snapshot_rate: u32,
+        spawn_rate: f32,
+    ) -> Self {
+        let next = scenario
+            .events
+            .iter()
+            .map(|event| match event.when {
+                When::At(at) => Some(tick + ticks(at, snapshot_rate)),
+                When::Every(every) => Some(tick + event.interval(every, snapshot_rate, spawn_rate)),
+                _ => None,
+            })
+            .collect();
+        ScenarioRun {
+            name,
+            next,
+            fired: vec![false; scenario.events.len()],
+            waiting: Vec::new(),
+            objectives: BTreeMap::new(),
+            spawned: BTreeMap::new(),
+            finished: false,
+        }
+    }
+
+    // Note what happened; the events it sets off come due with `take_due`
+    pub fn happened(
+        &mut self,
+        scenario: &Scenario,
+        happening: &Happening,
+        tick: u64,
+        snapshot_rate: u32,
+    ) {
+        for (index, event) in scenario.events.iter().enumerate() {
+            let matches = match (&event.when, happening) {
+                (When::Entered(a), Happening::Entered(b))
+                | (When::Left(a), Happening::Left(b))
+                | (When::Captured(a), Happening::Captured(b))
+                | (When::Completed(a), Happening::Completed(b)) => a == b,
+                _ => false,
+            };
+            if matches {
+                self.fire(scenario, index, tick, snapshot_rate);
+            }
+        }
+    }
+
+    fn fire(&mut self, scenario: &Scenario, index: usize, tick: u64, snapshot_rate: u32) {
+        let event = &scenario.events[index];
+        if self.fired[index] && !event.repeat && !matches!(event.when, When::Every(_)) {
+            return;
+        }
+        self.fired[index] = true;
+        self.waiting
+            .push((tick + ticks(event.delay, snapshot_rate), index));
+    }
+
+    // The events whose actions are due by `tick`, in the order they came due,
+    // with `every` events that spawn coming `spawn_rate` times as often
+    pub fn take_due(
+        &mut self,
+        scenario: &Scenario,
+        tick: u64,
+        snapshot_rate: u32,
+        spawn_rate: f32,
+    ) -> Vec<usize> {
+        if self.finished {
+            return Vec::new();
+        }
+        for index in 0..scenario.events.len() {
+            let Some(next) = self.next[index].filter(|&next| next <= tick) else {
+                continue;
+            };
+            let event = &scenario.events[index];
+            self.next[index] = match event.when {
+                When::Every(every) => Some(next + event.interval(every, snapshot_rate, spawn_rate)),
+                _ => None,
+            };
+            self.fire(scenario, index, next, snapshot_rate);
+        }
+        self.waiting.sort_by_key(|&(due, _)| due);
+        let ready = self.waiting.partition_point(|&(due, _)| due <= tick);
+        self.waiting
+            .drain(..ready)
+            .map(|(_, index)| index)
+            .collect()
+    }
+}
+
+fn ticks(seconds: f32, snapshot_rate: u32) -> u64 {
+    (seconds * snapshot_rate as f32).round() as u64
+}
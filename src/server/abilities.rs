@@ -0,0 +1,132 @@
+// Abilities (see `crate::abilities`): queueing each use, checking it against
+// the player's cooldowns and energy as it comes off the queue, and setting off
+// its area event.
+use super::Server;
+use crate::abilities::Abilities;
+use crate::actions::Action;
+use crate::areas::AreaEvent;
+use crate::config::ServerConfig;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode};
+use std::io;
+
+pub(super) fn load_abilities(config: &ServerConfig) -> io::Result<Option<Abilities>> {
+    let Some(path) = &config.abilities.file else {
+        return Ok(None);
+    };
+    let abilities =
+        Abilities::load(config.abilities.clone(), path, &config.areas).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("abilities {}: {}", path.display(), e),
+            )
+        })?;
+    println!(
+        "Loaded {} abilities from {}",
+        abilities.abilities.len(),
+        path.display()
+    );
+    Ok(Some(abilities))
+}
+
+impl Server {
+    // Queued to go off on the tick (see `server::actions`); what can't change
+    // in the meantime is checked now
+    pub(super) fn on_use_ability(&mut self, endpoint: Endpoint, name: String, x: f32, y: f32) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let playing = self
+            .game_state
+            .players
+            .get(&own_id)
+            .is_some_and(|player| !player.spectating);
+        let checked = match &self.abilities {
+            _ if !playing => Err("only players in the game can use abilities".to_string()),
+            None => Err("this server has no abilities".to_string()),
+            Some(abilities) if !abilities.abilities.contains_key(&name) => {
+                Err(format!("there is no ability `{}`", name))
+            }
+            Some(_) if !(x.is_finite() && y.is_finite()) => {
+                Err("an ability needs a finite target".to_string())
+            }
+            Some(_) => Ok(()),
+        };
+        let queued =
+            checked.and_then(|()| self.queue_action(own_id, Action::Ability { name, x, y }));
+        if let Err(e) = queued {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+
+    // A use coming off the queue, checked against the player as they are now
+    pub(super) fn use_ability(
+        &mut self,
+        own_id: usize,
+        name: String,
+        x: f32,
+        y: f32,
+    ) -> Result<(), String> {
+        let Some(endpoint) = self.game_state.players.get(&own_id).map(|p| p.endpoint) else {
+            return Ok(());
+        };
+        let caster = self.game_state.players.get(&own_id).and_then(|player| {
+            let position = self.game_state.position(&player)?;
+            (!player.spectating).then_some((player.room, player.team, position))
+        });
+        let (room, team, from) = caster.ok_or("only players in the game can use abilities")?;
+        // A client aiming at what its player can't see draws more than it
+        // should; abuse reports look for this
+        let hidden = self.config.sight.aim
+            && self
+                .maps
+                .get(&room)
+                .is_some_and(|state| !state.map.line_of_sight(from, (x, y)));
+        if hidden {
+            self.record_action(
+                own_id,
+                format!("aimed {} through an obstacle at ({:.1}, {:.1})", name, x, y),
+            );
+            return Err("an obstacle is in the way".to_string());
+        }
+        let abilities = self
+            .abilities
+            .as_mut()
+            .ok_or("this server has no abilities")?;
+        let rate = self.config.snapshot_rate;
+        let (ability, used) =
+            abilities.use_ability(own_id, &name, from, (x, y), self.tick, rate)?;
+        let event = AreaEvent {
+            room,
+            kind: name.clone(),
+            x,
+            y,
+            radius: ability.radius,
+            effect: ability.effect,
+        };
+        // Without friendly fire, abilities pass the caster's team by
+        let friendly_fire = room
+            .and_then(|room| {
+                let rooms = self.game_state.rooms.read().unwrap();
+                rooms.get(room)?.settings.friendly_fire
+            })
+            .unwrap_or(self.config.room_settings.friendly_fire);
+        let spare = team.filter(|_| !friendly_fire);
+        self.area_event(event, spare)?;
+        self.record_action(own_id, format!("used {} at ({:.1}, {:.1})", name, x, y));
+        let message = ClientMessage::AbilityUsed {
+            name,
+            ready_in: used.ready_in,
+            energy: used.energy,
+        };
+        self.send(endpoint, &message);
+        Ok(())
+    }
+
+    // Once a second
+    pub(super) fn regenerate_energy(&mut self) {
+        if let Some(abilities) = &mut self.abilities {
+            abilities.regenerate(self.tick);
+        }
+    }
+}
@@ -0,0 +1,426 @@
+// Registration and login, and checking the server's and rooms' passwords.
+// Argon2 is slow on purpose and Steam tickets need a Web API call, so both
+// run on the blocking pool and report back as `Signal::Authenticated`; a
+// connection may only have one check in flight.
+use super::{Server, Signal};
+use crate::accounts::{AccountId, Accounts};
+use crate::achievements::GameEvent;
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::maps::MapData;
+use crate::passwords;
+use crate::protocol::{ClientMessage, DisconnectReason, ErrorCode, LocalizedText};
+use crate::room_settings::RoomSettings;
+use crate::rooms::RoomId;
+use crate::steam::SteamUser;
+use std::collections::BTreeSet;
+
+pub enum AuthOutcome {
+    Registered {
+        username: String,
+        password_hash: String,
+    },
+    LoggedIn {
+        username: String,
+    },
+    WrongPassword,
+    Steam(Result<SteamUser, String>),
+    // The server password a `Hello` came with
+    ServerPassword {
+        accepted: bool,
+        hello: Box<PendingHello>,
+    },
+    // A new room's password, hashed
+    RoomHashed {
+        password_hash: String,
+        room: Box<PendingRoom>,
+    },
+    // A `JoinRoom`'s password, checked against `password_hash`
+    RoomPassword {
+        room_id: RoomId,
+        password_hash: String,
+        accepted: bool,
+    },
+}
+
+// What a `Hello` asked for, kept while its password is checked
+pub struct PendingHello {
+    pub wire_formats: Vec<WireFormat>,
+    pub snapshot_formats: Vec<SnapshotFormat>,
+}
+
+// A `CreateRoom` that checked out, kept while its password is hashed
+pub struct PendingRoom {
+    pub name: String,
+    pub settings: RoomSettings,
+    pub map: Option<MapData>,
+}
+
+impl Server {
+    pub(super) fn on_register(&mut self, endpoint: Endpoint, username: String, password: String) {
+        if !self.config.accounts.registration {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                LocalizedText::new("error.registration_closed"),
+            );
+            return;
+        }
+        if !self.can_authenticate(endpoint) {
+            return;
+        }
+        let checked = self.config.names.check(&username).and_then(|username| {
+            self.config.accounts.check_password(&password)?;
+            Ok(username)
+        });
+        let username = match checked {
+            Ok(username) => username,
+            Err(e) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                return;
+            }
+        };
+        if self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .find(&username)
+            .is_some()
+        {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.username_taken").with("name", &username),
+            );
+            return;
+        }
+        self.authenticate(endpoint, move || AuthOutcome::Registered {
+            password_hash: passwords::hash_password(&password),
+            username,
+        });
+    }
+
+    pub(super) fn on_login(&mut self, endpoint: Endpoint, username: String, password: String) {
+        if !self.can_authenticate(endpoint) {
+            return;
+        }
+        let account = self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .find(&username)
+            .cloned();
+        let Some(account) = account else {
+            self.reject(
+                endpoint,
+                ErrorCode::WrongPassword,
+                LocalizedText::new("error.wrong_login"),
+            );
+            return;
+        };
+        self.authenticate(endpoint, move || {
+            if passwords::verify_password(&password, &account.password_hash) {
+                AuthOutcome::LoggedIn {
+                    username: account.username,
+                }
+            } else {
+                AuthOutcome::WrongPassword
+            }
+        });
+    }
+
+    // Refuse a second attempt while one is running, or once logged in
+    pub(super) fn can_authenticate(&self, endpoint: Endpoint) -> bool {
+        let logged_in = self
+            .endpoints
+            .get(&endpoint)
+            .and_then(|id| self.game_state.players.get(id).and_then(|p| p.account));
+        let problem = if logged_in.is_some() {
+            "already logged in"
+        } else if self.authenticating.contains(&endpoint) {
+            "a login is already in progress"
+        } else {
+            return true;
+        };
+        self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+        false
+    }
+
+    // For the password checks that aren't logins
+    pub(super) fn can_check_password(&self, endpoint: Endpoint) -> bool {
+        if !self.authenticating.contains(&endpoint) {
+            return true;
+        }
+        self.reject(
+            endpoint,
+            ErrorCode::InvalidRequest,
+            "a password check is already in progress",
+        );
+        false
+    }
+
+    pub(super) fn authenticate(
+        &mut self,
+        endpoint: Endpoint,
+        check: impl FnOnce() -> AuthOutcome + Send + 'static,
+    ) {
+        self.authenticating.insert(endpoint);
+        let signals = self.signals.clone();
+        tokio::task::spawn_blocking(move || {
+            signals.send(Signal::Authenticated(endpoint, check())).ok();
+        });
+    }
+
+    pub(super) fn on_authenticated(&mut self, endpoint: Endpoint, outcome: AuthOutcome) {
+        // The connection may have closed while the password was checked
+        if !self.authenticating.remove(&endpoint) {
+            return;
+        }
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let account = match outcome {
+            AuthOutcome::ServerPassword { accepted, hello } => {
+                if accepted {
+                    self.finish_hello(endpoint, *hello);
+                } else {
+                    self.disconnect(
+                        endpoint,
+                        DisconnectReason::WrongPassword,
+                        LocalizedText::new("disconnect.wrong_password"),
+                    );
+                }
+                return;
+            }
+            AuthOutcome::RoomHashed {
+                password_hash,
+                room,
+            } => {
+                self.create_room(endpoint, *room, Some(password_hash));
+                return;
+            }
+            AuthOutcome::RoomPassword {
+                room_id,
+                password_hash,
+                accepted,
+            } => {
+                if accepted {
+                    self.join_room(endpoint, room_id, None, Some(&password_hash));
+                } else {
+                    self.reject(
+                        endpoint,
+                        ErrorCode::WrongPassword,
+                        LocalizedText::new("error.room_password").with("room", room_id),
+                    );
+                }
+                return;
+            }
+            AuthOutcome::Registered {
+                username,
+                password_hash,
+            } => {
+                let account = self.update_accounts(|accounts| {
+                    accounts
+                        .create(&username, password_hash)
+                        .map(|a| (a.id, a.username.clone()))
+                });
+                let Some(account) = account else {
+                    self.reject(
+                        endpoint,
+                        ErrorCode::InvalidRequest,
+                        LocalizedText::new("error.username_taken").with("name", &username),
+                    );
+                    return;
+                };
+                println!("Player {} registered account {}", player_id, account.0);
+                account
+            }
+            AuthOutcome::LoggedIn { username } => {
+                let account = self.update_accounts(|accounts| {
+                    accounts.logged_in(&username);
+                    accounts.find(&username).map(|a| (a.id, a.username.clone()))
+                });
+                // Deleted while the password was checked
+                let Some(account) = account else {
+                    self.reject(
+                        endpoint,
+                        ErrorCode::WrongPassword,
+                        LocalizedText::new("error.wrong_login"),
+                    );
+                    return;
+                };
+                account
+            }
+            AuthOutcome::WrongPassword => {
+                self.reject(
+                    endpoint,
+                    ErrorCode::WrongPassword,
+                    LocalizedText::new("error.wrong_login"),
+                );
+                return;
+            }
+            AuthOutcome::Steam(Err(e)) => {
+                self.reject(
+                    endpoint,
+                    ErrorCode::WrongPassword,
+                    format!("Steam ticket refused: {}", e),
+                );
+                return;
+            }
+            AuthOutcome::Steam(Ok(user)) => {
+                if let Some(message) = self.config.steam.platform_ban(&user) {
+                    self.disconnect(endpoint, DisconnectReason::Banned, message);
+                    return;
+                }
+                if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+                    player.steam_id = Some(user.steam_id);
+                }
+                if user.owner_steam_id != user.steam_id {
+                    println!(
+                        "Player {} is SteamID {}, borrowing from {}",
+                        player_id, user.steam_id, user.owner_steam_id
+                    );
+                } else {
+                    println!("Player {} is SteamID {}", player_id, user.steam_id);
+                }
+                self.update_accounts(|accounts| {
+                    let account = accounts.steam_login(user.steam_id);
+                    (account.id, account.username.clone())
+                })
+            }
+        };
+        self.log_in(endpoint, player_id, account);
+    }
+
+    pub(super) fn log_in(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        (account_id, username): (AccountId, String),
+    ) {
+        // One session per account: the newest login wins
+        let elsewhere: Vec<Endpoint> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.account == Some(account_id) && p.id != player_id)
+            .map(|p| p.endpoint)
+            .collect();
+        for other in elsewhere {
+            self.disconnect(
+                other,
+                DisconnectReason::Kicked,
+                LocalizedText::new("disconnect.logged_in_elsewhere"),
+            );
+        }
+
+        let uuid = self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .by_id(account_id)
+            .map(|account| account.uuid);
+        let Some((identities, was_guest)) =
+            self.game_state.players.get_mut(&player_id).map(|mut p| {
+                let was_guest = p.joined && p.is_guest();
+                p.account = Some(account_id);
+                if let Some(uuid) = uuid {
+                    p.uuid = uuid;
+                }
+                (p.identities(), was_guest)
+            })
+        else {
+            return;
+        };
+        // Bans can name the account, which wasn't known when the player
+        // connected, and so can the whitelist that waited for it
+        let refusal = {
+            let modes = self.game_state.modes.read().unwrap();
+            let listed = |list: &BTreeSet<String>| identities.iter().any(|i| list.contains(i));
+            if listed(&modes.banned) {
+                Some((DisconnectReason::Banned, "disconnect.banned"))
+            } else if modes.whitelist_only
+                && self.config.login_required()
+                && !listed(&modes.whitelist)
+            {
+                Some((
+                    DisconnectReason::NotWhitelisted,
+                    "disconnect.not_whitelisted",
+                ))
+            } else {
+                None
+            }
+        };
+        if let Some((reason, key)) = refusal.filter(|_| !endpoint.is_local()) {
+            self.disconnect(endpoint, reason, LocalizedText::new(key));
+            return;
+        }
+        println!("Player {} logged in to account {}", player_id, account_id);
+        // Guests aren't recorded, so the session counts from here
+        if was_guest {
+            self.session_started(player_id);
+        }
+        self.send(
+            endpoint,
+            &ClientMessage::LoggedIn {
+                account_id,
+                username,
+            },
+        );
+        self.send_identity(player_id);
+        self.guild_logged_in(account_id);
+        self.admit_logged_in(endpoint, player_id);
+        self.send_friend_list(account_id);
+        self.send_guild_roster(account_id);
+        self.deliver_mail(account_id);
+        self.update_presence(player_id);
+        let xp = self.game_state.progression.read().unwrap().xp(account_id);
+        self.send_experience(player_id, xp);
+        self.send_inventory(player_id);
+        self.count_account_event(account_id, GameEvent::Login);
+    }
+
+    // Tell the player's client the UUID it goes by now (see `Player::uuid`);
+    // only clients that sent `Hello` know the message
+    pub(super) fn send_identity(&self, player_id: usize) {
+        let Some((endpoint, uuid)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.endpoint, p.uuid))
+        else {
+            return;
+        };
+        self.send(endpoint, &ClientMessage::Identity { uuid });
+    }
+
+    // When guests aren't let in, logging in is the last step of the
+    // handshake; on a password-protected server `Hello` must come first
+    fn admit_logged_in(&self, endpoint: Endpoint, player_id: usize) {
+        let admitted = self.game_state.players.get_mut(&player_id).map(|mut p| {
+            let ready =
+                !p.joined && (p.snapshot_format.is_some() || self.config.password_hash.is_none());
+            p.joined |= ready;
+            (ready, p.snapshot_format.is_some())
+        });
+        let Some((true, handshaken)) = admitted else {
+            return;
+        };
+        self.player_joined(player_id, endpoint);
+        if handshaken {
+            self.introduce(player_id);
+        }
+    }
+
+    // Apply a change to the accounts and persist the result
+    pub(super) fn update_accounts<T>(&self, change: impl FnOnce(&mut Accounts) -> T) -> T {
+        let mut accounts = self.game_state.accounts.write().unwrap();
+        let result = change(&mut accounts);
+        self.save(Accounts::STORAGE_KEY, &*accounts);
+        result
+    }
+}
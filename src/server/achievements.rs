@@ -0,0 +1,63 @@
+// Counting gameplay events towards achievements, and announcing unlocks to the
+// room the player is in
+use super::outbound::Outbound;
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::{AchievementProgress, GameEvent};
+use crate::protocol::ClientMessage;
+
+impl Server {
+    // Count `event` for `player_id`, if they are logged in
+    pub(super) fn count_event(&self, player_id: usize, event: GameEvent) {
+        let account = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.account);
+        if let Some(account) = account {
+            self.count_account_event(account, event);
+        }
+    }
+
+    // Count `event` for `account`, whether or not it is online, and award
+    // the XP it is worth
+    pub(super) fn count_account_event(&self, account: AccountId, event: GameEvent) {
+        self.award_xp(account, event);
+        let unlocked: Vec<(String, String)> = {
+            let mut progress = self.game_state.achievements.write().unwrap();
+            let unlocked = progress
+                .record(account, event, self.config.achievements.all())
+                .into_iter()
+                .map(|a| (a.id.clone(), a.name.clone()))
+                .collect();
+            self.save(AchievementProgress::STORAGE_KEY, &*progress);
+            unlocked
+        };
+        if unlocked.is_empty() {
+            return;
+        }
+        let session = self
+            .game_state
+            .players
+            .iter()
+            .find(|p| p.joined && p.account == Some(account))
+            .map(|p| (p.id, p.room));
+        for (achievement_id, name) in unlocked {
+            println!(
+                "Account {} unlocked achievement {}",
+                account, achievement_id
+            );
+            let Some((player_id, room)) = session else {
+                continue;
+            };
+            // No one has id 0, so the player hears about it too
+            let recipients = self.room_recipients(room, 0);
+            let message = ClientMessage::AchievementUnlocked {
+                player_id,
+                achievement_id,
+                name,
+            };
+            self.outbound.send(Outbound::Send(recipients, message)).ok();
+        }
+    }
+}
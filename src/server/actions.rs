@@ -0,0 +1,94 @@
+// Running each player's action queue (see `crate::actions`) once a simulated
+// tick: casts that are done go off, then whatever is next starts, as long as
+// it isn't waiting on a cooldown.
+use super::Server;
+use crate::actions::{Action, Queued};
+use crate::protocol::{ClientMessage, ErrorCode};
+
+impl Server {
+    pub(super) fn queue_action(&mut self, player_id: usize, action: Action) -> Result<(), String> {
+        let max_queued = self.config.actions.max_queued;
+        self.actions.push(player_id, action, self.tick, max_queued)
+    }
+
+    pub(super) fn run_actions(&mut self) {
+        let rate = self.config.snapshot_rate.max(1) as f32;
+        let max_wait = (self.config.actions.max_wait_seconds * rate).ceil() as u64;
+        for player_id in self.actions.players() {
+            if let Some(action) = self.actions.finish_cast(player_id, self.tick) {
+                self.perform(player_id, action);
+            }
+            while !self.actions.is_casting(player_id) {
+                let Some(queued) = self.actions.front(player_id) else {
+                    break;
+                };
+                let wait = self.cooldown_left(player_id, &queued.action);
+                if wait > 0 && self.tick + wait <= queued.queued_at + max_wait {
+                    break;
+                }
+                let Some(Queued { action, .. }) = self.actions.pop(player_id) else {
+                    break;
+                };
+                if wait > 0 {
+                    if let Action::Ability { name, .. } = &action {
+                        let e = format!("`{}` is ready in {:.1} seconds", name, wait as f32 / rate);
+                        self.action_failed(player_id, e);
+                    }
+                    continue;
+                }
+                match self.cast_ticks(&action) {
+                    0 => self.perform(player_id, action),
+                    ticks => self.start_cast(player_id, action, ticks),
+                }
+            }
+        }
+        self.actions.prune();
+    }
+
+    fn perform(&mut self, player_id: usize, action: Action) {
+        let result = match action {
+            Action::Ability { name, x, y } => self.use_ability(player_id, name, x, y),
+            Action::Interact { id } => self.interact(player_id, id),
+        };
+        if let Err(e) = result {
+            self.action_failed(player_id, e);
+        }
+    }
+
+    fn start_cast(&mut self, player_id: usize, action: Action, ticks: u64) {
+        if let Action::Ability { name, .. } = &action {
+            let message = ClientMessage::AbilityCasting {
+                name: name.clone(),
+                cast_seconds: ticks as f32 / self.config.snapshot_rate.max(1) as f32,
+            };
+            if let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) {
+                self.send(endpoint, &message);
+            }
+        }
+        self.actions.cast(player_id, action, self.tick + ticks);
+    }
+
+    fn cooldown_left(&self, player_id: usize, action: &Action) -> u64 {
+        match (action, &self.abilities) {
+            (Action::Ability { name, .. }, Some(abilities)) => {
+                abilities.ready_in(player_id, name, self.tick)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cast_ticks(&self, action: &Action) -> u64 {
+        let ability = match (action, &self.abilities) {
+            (Action::Ability { name, .. }, Some(abilities)) => abilities.abilities.get(name),
+            _ => None,
+        };
+        let rate = self.config.snapshot_rate.max(1) as f32;
+        ability.map_or(0, |ability| (ability.cast_seconds * rate).ceil() as u64)
+    }
+
+    fn action_failed(&self, player_id: usize, e: String) {
+        if let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use super::loot::describe_items;
+use super::{Server, Signal, Signals};
+use crate::admin::{AdminCommand, AdminRequest, AdminResult, HELP};
+use crate::cidr::IpRange;
+use crate::endpoint::Endpoint;
+use crate::history::MatchRecord;
+use crate::inspect::StateDump;
+use crate::locale::ServerText;
+use crate::mail::{ItemGrant, ItemId, MailKind};
+use crate::protocol::{ClientMessage, DisconnectReason, ErrorCode, LocalizedText};
+use crate::roles::Role;
+use crate::state::ServerModes;
+use crate::webhooks::WebhookEvent;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+// Read admin commands from stdin and print their results
+pub fn spawn_console(signals: Signals) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match submit_command(&signals, &line, Role::Owner) {
+                Some(Ok(text)) => println!("{}", text),
+                Some(Err(e)) => println!("error: {}", e),
+                None => break,
+            }
+        }
+    });
+}
+
+// Parse a console line and run it on the game loop, waiting for the result.
+// Returns `None` once the game loop has stopped.
+pub(super) fn submit_command(signals: &Signals, line: &str, role: Role) -> Option<AdminResult> {
+    match line.parse::<AdminCommand>() {
+        Ok(command) => submit(signals, command, role),
+        Err(e) => Some(Err(e)),
+    }
+}
+
+pub(super) fn submit(signals: &Signals, command: AdminCommand, role: Role) -> Option<AdminResult> {
+    let (reply, response) = mpsc::channel();
+    let request = AdminRequest {
+        command,
+        role,
+        reply,
+    };
+    signals.send(Signal::Admin(request)).ok()?;
+    response.recv().ok()
+}
+
+impl Server {
+    pub(super) fn on_admin_request(&mut self, request: AdminRequest) {
+        let required = request.command.required_role();
+        let result = if request.role >= required {
+            self.run_admin_command(request.command, request.role)
+        } else {
+            Err(format!("permission denied: requires the {} role", required))
+        };
+        let _ = request.reply.send(result);
+    }
+
+    // A console line sent in-game by a player with a role
+    pub(super) fn on_console_command(&mut self, endpoint: Endpoint, line: &str) {
+        let role = self
+            .endpoints
+            .get(&endpoint)
+            .and_then(|id| self.game_state.players.get(id).and_then(|p| p.role));
+        let Some(role) = role else {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "console commands need a role",
+            );
+            return;
+        };
+        let command = match line.parse::<AdminCommand>() {
+            Ok(command) => command,
+            Err(e) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                return;
+            }
+        };
+        let required = command.required_role();
+        if role < required {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                format!("requires the {} role", required),
+            );
+            return;
+        }
+        match self.run_admin_command(command, role) {
+            Ok(text) => self.send(endpoint, &ClientMessage::ConsoleOutput { text }),
+            Err(e) => self.reject(endpoint, ErrorCode::InvalidRequest, e),
+        }
+    }
+
+    // Run a command whose permission has already been checked against `role`
+    fn run_admin_command(&mut self, command: AdminCommand, role: Role) -> AdminResult {
+        let text = match command {
+            AdminCommand::Help => HELP.to_string(),
+            AdminCommand::Status => {
+                let modes = self.game_state.modes.read().unwrap();
+                let buffers = self.game_state.buffers.snapshot();
+                let draining = match self.drain_left() {
+                    Some(left) => format!("{}s left", left),
+                    None => "off".to_string(),
+                };
+                format!(
+                    "players: {}, maintenance: {}, draining: {}, paused: {}, time scale: {}x, whitelist-only: {} ({} entries), \
+                     bans: {}, temporary bans: {}, encode buffers: {}% reused, {} pooled, \
+                     load: {}",
+                    self.endpoints.len(),
+                    on_off(modes.maintenance),
+                    draining,
+                    on_off(self.paused),
+                    self.time_scale,
+                    on_off(modes.whitelist_only),
+                    modes.whitelist.len(),
+                    modes.banned.len(),
+                    self.throttle.banned(Instant::now()),
+                    buffers.reuse_percent(),
+                    buffers.pooled,
+                    self.load_status()
+                )
+            }
+            AdminCommand::Maintenance {
+                enabled,
+                drain,
+                message,
+            } => {
+                self.update_modes(|modes| {
+                    modes.maintenance = enabled;
+                    modes.maintenance_message = message.clone().unwrap_or_default();
+                });
+                if drain {
+                    let message: ServerText = match message {
+                        Some(message) => message.into(),
+                        None => LocalizedText::new("disconnect.maintenance").into(),
+                    };
+                    let endpoints: Vec<Endpoint> = self.endpoints.keys().copied().collect();
+                    for endpoint in &endpoints {
+                        self.disconnect(*endpoint, DisconnectReason::Maintenance, message.clone());
+                    }
+                    return Ok(format!(
+                        "maintenance on, drained {} players",
+                        endpoints.len()
+                    ));
+                }
+                format!("maintenance {}", on_off(enabled))
+            }
+            AdminCommand::WhitelistOnly(enabled) => {
+                self.update_modes(|modes| modes.whitelist_only = enabled);
+                format!("whitelist-only {}", on_off(enabled))
+            }
+            AdminCommand::WhitelistAdd(identity) => {
+                let added = self.update_modes(|modes| modes.whitelist.insert(identity.clone()));
+                if added {
+                    format!("added {} to the whitelist", identity)
+                } else {
+                    format!("{} is already whitelisted", identity)
+                }
+            }
+            AdminCommand::WhitelistRemove(identity) => {
+                let removed = self.update_modes(|modes| modes.whitelist.remove(&identity));
+                if !removed {
+                    return Err(format!("{} is not whitelisted", identity));
+                }
+                format!("removed {} from the whitelist", identity)
+            }
+            AdminCommand::WhitelistList => {
+                let modes = self.game_state.modes.read().unwrap();
+                if modes.whitelist.is_empty() {
+                    return Ok("the whitelist is empty".to_string());
+                }
+                modes
+                    .whitelist
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            AdminCommand::AllowAdd(range) => {
+                if self.config.ip_allow.contains(&range)
+                    || !self.update_modes(|modes| modes.ip_allow.insert(range))
+                {
+                    return Ok(format!("{} is already allowed", range));
+                }
+                format!("added {} to the allow list", range)
+            }
+            AdminCommand::AllowRemove(range) => {
+                let removed = self.update_modes(|modes| modes.ip_allow.remove(&range));
+                if !removed {
+                    return Err(not_listed(range, &self.config.ip_allow, "allow"));
+                }
+                format!("removed {} from the allow list", range)
+            }
+            AdminCommand::AllowList => {
+                let modes = self.game_state.modes.read().unwrap();
+                let list = ip_list(&self.config.ip_allow, &modes.ip_allow);
+                if list.is_empty() {
+                    return Ok("the allow list is empty, so any address may connect".to_string());
+                }
+                list
+            }
+            AdminCommand::DenyAdd(range) => {
+                if self.config.ip_deny.contains(&range)
+                    || !self.update_modes(|modes| modes.ip_deny.insert(range))
+                {
+                    return Ok(format!("{} is already denied", range));
+                }
+                let endpoints: Vec<Endpoint> = self
+                    .endpoints
+                    .keys()
+                    .filter(|e| e.addr().is_some_and(|addr| range.contains(addr.ip())))
+                    .copied()
+                    .collect();
+                for endpoint in &endpoints {
+                    self.disconnect(
+                        *endpoint,
+                        DisconnectReason::Banned,
+                        LocalizedText::new("disconnect.address_blocked"),
+                    );
+                }
+                format!(
+                    "added {} to the deny list, disconnected {} players",
+                    range,
+                    endpoints.len()
+                )
+            }
+            AdminCommand::DenyRemove(range) => {
+                let removed = self.update_modes(|modes| modes.ip_deny.remove(&range));
+                if !removed {
+                    return Err(not_listed(range, &self.config.ip_deny, "deny"));
+                }
+                format!("removed {} from the deny list", range)
+            }
+            AdminCommand::DenyList => {
+                let modes = self.game_state.modes.read().unwrap();
+                let list = ip_list(&self.config.ip_deny, &modes.ip_deny);
+                if list.is_empty() {
+                    return Ok("the deny list is empty".to_string());
+                }
+                list
+            }
+            AdminCommand::Kick { player_id, message } => {
+                let endpoint = self.moderation_target(player_id, role)?;
+                let message: ServerText = match message {
+                    Some(message) => message.into(),
+                    None => LocalizedText::new("disconnect.kicked").into(),
+                };
+                self.disconnect(endpoint, DisconnectReason::Kicked, message);
+                format!("kicked player {}", player_id)
+            }
+            AdminCommand::Ban { player_id, message } => {
+                let endpoint = self.moderation_target(player_id, role)?;
+                let identities = self
+                    .game_state
+                    .players
+                    .get(&player_id)
+                    .map(|p| p.identities())
+                    .unwrap_or_default();
+                self.update_modes(|modes| modes.banned.extend(identities.iter().cloned()));
+                self.webhooks.notify(WebhookEvent::PlayerBanned {
+                    player_id,
+                    identities: identities.clone(),
+                });
+                let message: ServerText = match message {
+                    Some(message) => message.into(),
+                    None => LocalizedText::new("disconnect.banned_by_operator").into(),
+                };
+                self.disconnect(endpoint, DisconnectReason::Banned, message);
+                format!("banned player {} ({})", player_id, identities.join(", "))
+            }
+            AdminCommand::Unban(identity) => {
+                let removed = self.update_modes(|modes| modes.banned.remove(&identity));
+                if !removed {
+                    return Err(format!("{} is not banned", identity));
+                }
+                format!("unbanned {}", identity)
+            }
+            AdminCommand::Broadcast(text) => {
+                // No sender to skip, since ids start at 1
+                let announcement = ClientMessage::Announcement {
+                    text,
+                    localized: None,
+                };
+                self.broadcast(&announcement, 0);
+                "announcement sent".to_string()
+            }
+            AdminCommand::Announce(text) => {
+                if !self.config.localization.knows(&text.key) {
+                    return Err(format!("no translation has the key `{}`", text.key));
+                }
+                self.announce(text);
+                "announcement sent".to_string()
+            }
+            AdminCommand::Reload => self.reload_config()?,
+            AdminCommand::Drain(seconds) => self.start_drain(seconds),
+            AdminCommand::DrainCancel => self.cancel_drain()?,
+            AdminCommand::Pause => self.pause()?,
+            AdminCommand::Resume => self.resume()?,
+            AdminCommand::TimeScale(scale) => self.set_time_scale(scale)?,
+            AdminCommand::Stats(None) => self.game_state.analytics.read().unwrap().summary(),
+            AdminCommand::Stats(Some(player_id)) => {
+                let stats = self
+                    .player_stats(player_id)
+                    .ok_or_else(|| format!("no player {}", player_id))?;
+                format!(
+                    "player {}: {} sessions, {}m played, {} messages sent",
+                    player_id,
+                    stats.sessions,
+                    stats.playtime_secs / 60,
+                    stats.messages_sent
+                )
+            }
+            AdminCommand::Inspect(pointer) => {
+                let dump = StateDump::capture(&self.game_state, self.tick);
+                let value = dump.to_json(pointer.as_deref())?;
+                serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?
+            }
+            AdminCommand::Grant {
+                player_id,
+                role: granted,
+            } => {
+                let endpoint = self
+                    .set_role(player_id, Some(granted))
+                    .ok_or_else(|| format!("no player {}", player_id))?;
+                self.send(
+                    endpoint,
+                    &ClientMessage::ConsoleOutput {
+                        text: format!("you were granted the {} role", granted),
+                    },
+                );
+                format!("player {} now has the {} role", player_id, granted)
+            }
+            AdminCommand::Revoke { player_id } => {
+                self.set_role(player_id, None)
+                    .ok_or_else(|| format!("no player {}", player_id))?;
+                format!("revoked player {}'s role", player_id)
+            }
+            AdminCommand::Mail { account, message } => {
+                let (account_id, username) = self.resolve_account(&account)?;
+                self.post_mail(account_id, MailKind::Notice, None, message, Vec::new());
+                format!("left a notice for {}", username)
+            }
+            AdminCommand::Give {
+                account,
+                item,
+                quantity,
+            } => {
+                let (account_id, username) = self.resolve_account(&account)?;
+                let text = format!("you received {} x {}", quantity, item);
+                let grant = ItemGrant {
+                    item,
+                    quantity,
+                    id: Some(ItemId::new_v4()),
+                };
+                self.post_mail(account_id, MailKind::Items, None, text, vec![grant]);
+                format!("sent {} an item grant", username)
+            }
+            AdminCommand::Inventory(account) => {
+                let (account_id, username) = self.resolve_account(&account)?;
+                let items = self.game_state.inventories.read().unwrap().of(account_id);
+                match items.is_empty() {
+                    true => format!("{} holds nothing", username),
+                    false => format!("{} holds {}", username, describe_items(&items)),
+                }
+            }
+            AdminCommand::History { channel, count } => self.chat_history_text(channel, count),
+            AdminCommand::Trail { player_id, seconds } => self.trail_text(player_id, seconds)?,
+            AdminCommand::Area(event) => {
+                let kind = event.kind.clone();
+                let caught = self.area_event(event, None)?;
+                let ids: Vec<String> = caught.iter().map(usize::to_string).collect();
+                match ids.is_empty() {
+                    true => format!("the {} caught nobody", kind),
+                    false => format!("the {} caught players {}", kind, ids.join(", ")),
+                }
+            }
+            AdminCommand::Spawn { room, entity } => {
+                let kind = entity.kind.clone();
+                let id = self.spawn_entity(room, entity)?;
+                format!("spawned {} {}", kind, id)
+            }
+            AdminCommand::Despawn(id) => {
+                self.despawn_entity(id)?;
+                format!("despawned entity {}", id)
+            }
+            AdminCommand::Entities(room) => self.entities_text(room),
+            AdminCommand::Loot { room, table, x, y } => {
+                let items = self.drop_loot(room, &table, (x, y), None)?;
+                match items.is_empty() {
+                    true => format!("{} dropped nothing", table),
+                    false => format!("{} dropped {}", table, describe_items(&items)),
+                }
+            }
+            AdminCommand::Authority { entity, to } => {
+                self.transfer_authority(entity, to)?;
+                match to {
+                    Some(player_id) => {
+                        format!("player {} has authority over entity {}", player_id, entity)
+                    }
+                    None => format!("the server has authority over entity {}", entity),
+                }
+            }
+            AdminCommand::AddBot { room_id, path } => {
+                self.add_bot(room_id, path)?;
+                format!("a bot is on its way into room {}", room_id)
+            }
+            AdminCommand::RemoveBot(player_id) => match (self.remove_bots(player_id)?, player_id) {
+                (_, Some(player_id)) => format!("removed bot {}", player_id),
+                (count, None) => format!("removed {} bots", count),
+            },
+            AdminCommand::Bots => self.bots_text(),
+            AdminCommand::Difficulty { room_id, change } => {
+                let difficulty = match change.is_empty() {
+                    true => {
+                        let rooms = self.game_state.rooms.read().unwrap();
+                        let room = rooms
+                            .get(room_id)
+                            .ok_or_else(|| format!("no room {}", room_id))?;
+                        room.difficulty
+                    }
+                    false => self.change_difficulty(room_id, &change)?,
+                };
+                format!("room {}: {}", room_id, difficulty.describe())
+            }
+            AdminCommand::Matches(count) => {
+                let history = self.game_state.match_history.read().unwrap();
+                let lines: Vec<String> = history
+                    .matches
+                    .iter()
+                    .rev()
+                    .take(count)
+                    .map(MatchRecord::describe)
+                    .collect();
+                match lines.is_empty() {
+                    true => "no matches recorded".to_string(),
+                    false => lines.join("\n"),
+                }
+            }
+            AdminCommand::Traffic(player_id) => self.traffic_text(player_id)?,
+            AdminCommand::Dice(count) => self.dice_text(count),
+            AdminCommand::Export(account) => {
+                let (account_id, _) = self.resolve_account(&account)?;
+                let export = self.export_account(account_id)?;
+                serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?
+            }
+            AdminCommand::Erase(account) => {
+                let (account_id, _) = self.resolve_account(&account)?;
+                let username = self.erase_account(account_id)?;
+                format!("erased {} and everything stored about it", username)
+            }
+        };
+        Ok(text)
+    }
+
+    // The endpoint of a player `role` may kick or ban. Only the owner may act
+    // on someone of equal or higher rank.
+    fn moderation_target(&self, player_id: usize, role: Role) -> Result<Endpoint, String> {
+        let (endpoint, target_role) = self
+            .player_endpoint_and_role(player_id)
+            .ok_or_else(|| format!("no player {}", player_id))?;
+        if let Some(target_role) = target_role.filter(|r| role != Role::Owner && *r >= role) {
+            return Err(format!(
+                "permission denied: player {} has the {} role",
+                player_id, target_role
+            ));
+        }
+        Ok(endpoint)
+    }
+
+    // Apply a reloaded config file. Listen addresses, the data directory, the
+    // wire format, decode limits, chaos settings and webhooks only change on
+    // restart.
+    fn reload_config(&mut self) -> AdminResult {
+        let mut config = self.config.reload()?;
+        config.listen_addr = self.config.listen_addr.clone();
+        config.listen_addrs = self.config.listen_addrs.clone();
+        config.data_dir = self.config.data_dir.clone();
+        config.rcon_listen_addr = self.config.rcon_listen_addr.clone();
+        config.rcon_password_hash = self.config.rcon_password_hash.clone();
+        config.http_listen_addr = self.config.http_listen_addr.clone();
+        config.http_token_hash = self.config.http_token_hash.clone();
+        config.webhooks = self.config.webhooks.clone();
+        // The decoder and broadcaster were started with these
+        config.wire_format = self.config.wire_format;
+        config.chaos = self.config.chaos.clone();
+        config.decode_limits = self.config.decode_limits;
+        self.game_state
+            .rooms
+            .write()
+            .unwrap()
+            .apply_config(&config.rooms);
+        self.throttle.reconfigure(config.throttle.clone());
+        self.reconfigure_jwt(config.jwt.clone());
+        self.config = config;
+        self.share_sight();
+        println!("Reloaded the config file");
+        Ok("config reloaded".to_string())
+    }
+
+    fn player_endpoint_and_role(&self, id: usize) -> Option<(Endpoint, Option<Role>)> {
+        let player = self.game_state.players.get(&id)?;
+        Some((player.endpoint, player.role))
+    }
+
+    // Change a connected player's role, returning their endpoint
+    fn set_role(&self, id: usize, role: Option<Role>) -> Option<Endpoint> {
+        let mut player = self.game_state.players.get_mut(&id)?;
+        player.role = role;
+        println!("Player {} role set to {:?}", id, role);
+        Some(player.endpoint)
+    }
+
+    // Apply a change to the server modes and persist the result
+    pub(super) fn update_modes<T>(&self, change: impl FnOnce(&mut ServerModes) -> T) -> T {
+        let mut modes = self.game_state.modes.write().unwrap();
+        let result = change(&mut modes);
+        self.save(ServerModes::STORAGE_KEY, &*modes);
+        result
+    }
+}
+
+// One range per line, the config file's first
+fn ip_list(config: &[IpRange], runtime: &BTreeSet<IpRange>) -> String {
+    config
+        .iter()
+        .map(|range| format!("{} (config file)", range))
+        .chain(runtime.iter().map(IpRange::to_string))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Why `range` can't be removed from a list
+fn not_listed(range: IpRange, config: &[IpRange], list: &str) -> String {
+    if config.contains(&range) {
+        format!("{} is in the config file's {} list", range, list)
+    } else {
+        format!("{} is not in the {} list", range, list)
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
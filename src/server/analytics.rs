@@ -0,0 +1,83 @@
+use super::Server;
+use crate::analytics::{Analytics, PlayerStats};
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::state::Player;
+
+impl Server {
+    pub(super) fn session_started(&self, player_id: usize) {
+        // Guests leave nothing behind
+        let identity = match self.game_state.players.get(&player_id) {
+            Some(player) if !player.is_guest() => player.primary_identity(),
+            _ => return,
+        };
+        self.update_analytics(|analytics| analytics.session_started(&identity));
+    }
+
+    pub(super) fn session_ended(&self, player: &Player) {
+        if player.is_guest() {
+            return;
+        }
+        self.update_analytics(|analytics| {
+            analytics.session_ended(
+                &player.primary_identity(),
+                player.connected_at.elapsed(),
+                player.messages_sent,
+            )
+        });
+    }
+
+    // A connected player's totals, including the session in progress
+    pub(super) fn player_stats(&self, player_id: usize) -> Option<PlayerStats> {
+        let player = self.game_state.players.get(&player_id)?;
+        if !player.joined {
+            return None;
+        }
+        let analytics = self.game_state.analytics.read().unwrap();
+        let mut stats = analytics
+            .players
+            .get(&player.primary_identity())
+            .cloned()
+            .unwrap_or_default();
+        stats.playtime_secs += player.connected_at.elapsed().as_secs();
+        stats.messages_sent += player.messages_sent;
+        Some(stats)
+    }
+
+    pub(super) fn on_query_player_stats(&self, endpoint: Endpoint, player_id: usize) {
+        if !self.can_look_up(endpoint, player_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "you can only look up yourself and your friends",
+            );
+            return;
+        }
+        let Some(stats) = self.player_stats(player_id) else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("no player {}", player_id),
+            );
+            return;
+        };
+        self.send(
+            endpoint,
+            &ClientMessage::PlayerStats {
+                player_id,
+                sessions: stats.sessions,
+                playtime_secs: stats.playtime_secs,
+                messages_sent: stats.messages_sent,
+                first_seen: stats.first_seen,
+                last_seen: stats.last_seen,
+            },
+        );
+    }
+
+    // Apply a change to the analytics and persist the result
+    fn update_analytics(&self, change: impl FnOnce(&mut Analytics)) {
+        let mut analytics = self.game_state.analytics.write().unwrap();
+        change(&mut analytics);
+        self.save(Analytics::STORAGE_KEY, &*analytics);
+    }
+}
@@ -0,0 +1,34 @@
+use super::outbound::Outbound;
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{Appearance, ClientMessage, ErrorCode};
+
+impl Server {
+    // Store a new avatar and show it to the room, the sender included
+    pub(super) fn on_set_appearance(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        appearance: Appearance,
+    ) {
+        if let Err(e) = self.config.appearance.check(&appearance) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+            return;
+        }
+        let Some(room) = self.game_state.players.get_mut(&player_id).map(|mut p| {
+            p.appearance = appearance.clone();
+            p.room
+        }) else {
+            return;
+        };
+        let mut recipients = self.room_recipients(room, player_id);
+        recipients.push((endpoint, self.wire_format(endpoint)));
+        let message = ClientMessage::PlayerAppearance {
+            id: player_id,
+            skin: appearance.skin,
+            color: appearance.color,
+            accessories: appearance.accessories,
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+}
@@ -0,0 +1,104 @@
+// Area events (see `crate::areas`): who they catch, what they do to them and
+// who gets to see them.
+use super::outbound::Outbound;
+use super::shards::ShardCommand;
+use super::Server;
+use crate::areas::AreaEvent;
+use crate::protocol::ClientMessage;
+use crate::rooms::TeamId;
+
+impl Server {
+    // Returns who the event caught, or why it was refused. Members of
+    // `spare`'s team are left out.
+    pub(super) fn area_event(
+        &mut self,
+        event: AreaEvent,
+        spare: Option<TeamId>,
+    ) -> Result<Vec<usize>, String> {
+        event.validate(&self.config.areas)?;
+        if let Some(room_id) = event.room {
+            if self.game_state.rooms.read().unwrap().get(room_id).is_none() {
+                return Err(format!("no room {}", room_id));
+            }
+        }
+        let room = event.room;
+        let center = (event.x, event.y);
+        let mut caught = self.game_state.players_within(room, center, event.radius);
+        if let Some(team) = spare {
+            caught.retain(|id| {
+                self.game_state
+                    .players
+                    .get(id)
+                    .is_none_or(|player| player.team != Some(team))
+            });
+        }
+        // Obstacles shelter the players behind them from the center
+        let sheltering = self.maps.get(&room).filter(|_| self.config.sight.areas);
+        if let Some(state) = sheltering {
+            caught.retain(|id| {
+                self.game_state
+                    .players
+                    .get(id)
+                    .and_then(|player| self.game_state.position(&player))
+                    .is_none_or(|position| state.map.line_of_sight(center, position))
+            });
+        }
+        let seen_within = event.radius + self.config.areas.view_distance;
+        let recipients = self
+            .game_state
+            .players_within(room, center, seen_within)
+            .into_iter()
+            .filter_map(|id| {
+                let player = self.game_state.players.get(&id)?;
+                player
+                    .snapshot_format
+                    .is_some()
+                    .then_some((player.endpoint, player.wire_format))
+            })
+            .collect();
+        let message = ClientMessage::AreaEvent {
+            kind: event.kind.clone(),
+            x: event.x,
+            y: event.y,
+            radius: event.radius,
+            caught: caught.clone(),
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+
+        for &player_id in &caught {
+            self.record_action(
+                player_id,
+                format!(
+                    "caught in {} at ({:.1}, {:.1})",
+                    event.kind, event.x, event.y
+                ),
+            );
+            let position = self
+                .game_state
+                .players
+                .get(&player_id)
+                .and_then(|player| self.game_state.position(&player));
+            let Some((x, y)) = position.and_then(|position| event.effect.apply(center, position))
+            else {
+                continue;
+            };
+            self.record_move(player_id, room, x, y);
+            let command = ShardCommand::Place {
+                room,
+                player_id,
+                x,
+                y,
+            };
+            self.shards.send(room, command);
+        }
+        println!(
+            "{} at ({}, {}) in {} caught {} players",
+            event.kind,
+            event.x,
+            event.y,
+            room.map_or("the lobby".to_string(), |room| format!("room {}", room)),
+            caught.len()
+        );
+        Ok(caught)
+    }
+}
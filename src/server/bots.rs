@@ -0,0 +1,440 @@
+// Bots (see `crate::bots`): players the server runs over in-process
+// connections, so that everything they send and are sent goes through the
+// same decoding, handling and encoding as any client's.
+use super::local::LocalClient;
+use super::Server;
+use crate::bots;
+use crate::codec::SnapshotFormat;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, DisconnectReason, LocalizedText, PROTOCOL_VERSION};
+use crate::rooms::{RoomId, TeamId};
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::f32::consts::TAU;
+use std::sync::mpsc::TryRecvError;
+
+pub(super) struct Bot {
+    client: LocalClient,
+    room_id: RoomId,
+    team: Option<TeamId>,
+    path: Option<String>,
+    // Set once the bot is seated in its room
+    player_id: Option<usize>,
+    position: (f32, f32),
+    // Where the bot was seated, which it wanders around
+    post: (f32, f32),
+    goal: Goal,
+}
+
+#[derive(Clone, Copy)]
+enum Goal {
+    // Looking for something to do
+    Idle,
+    Waypoint(usize),
+    Point((f32, f32)),
+    Chase(usize),
+}
+
+impl Bot {
+    // What to do once the goal is reached, or out of reach
+    fn next_goal(&self, config: &bots::BotConfig) -> Goal {
+        if let (Goal::Waypoint(waypoint), Some(path)) = (self.goal, self.path(config)) {
+            return Goal::Waypoint((waypoint + 1) % path.len());
+        }
+        let mut rng = rand::thread_rng();
+        let distance = config.wander * rng.gen::<f32>().sqrt();
+        let angle = rng.gen::<f32>() * TAU;
+        let (x, y) = self.post;
+        Goal::Point((x + distance * angle.cos(), y + distance * angle.sin()))
+    }
+
+    fn path<'a>(&self, config: &'a bots::BotConfig) -> Option<&'a [(f32, f32)]> {
+        let path = config.paths.get(self.path.as_ref()?)?;
+        Some(path.as_slice())
+    }
+}
+
+impl Server {
+    // A bot joins the room's smallest team, if it is playing a match
+    pub(super) fn add_bot(&mut self, room_id: RoomId, path: Option<String>) -> Result<(), String> {
+        if let Some(path) = path
+            .as_ref()
+            .filter(|path| !self.config.bots.paths.contains_key(*path))
+        {
+            return Err(format!("there is no bot path `{}`", path));
+        }
+        let team = self
+            .bot_matches()
+            .remove(&room_id)
+            .and_then(|teams| teams.into_iter().min_by_key(|&(_, count)| count))
+            .map(|(team, _)| team);
+        self.connect_bot(room_id, team, path)
+    }
+
+    // Start a bot's handshake; it is seated in the room once that is through
+    fn connect_bot(
+        &mut self,
+        room_id: RoomId,
+        team: Option<TeamId>,
+        path: Option<String>,
+    ) -> Result<(), String> {
+        if self.bots.len() >= self.config.bots.max {
+            return Err(format!("there are already {} bots", self.bots.len()));
+        }
+        if self.drain.is_some() {
+            return Err("the server is draining".to_string());
+        }
+        let rooms = self.game_state.rooms.read().unwrap();
+        let room = rooms
+            .get(room_id)
+            .ok_or_else(|| format!("there is no room {}", room_id))?;
+        let arriving = self
+            .bots
+            .values()
+            .filter(|bot| bot.player_id.is_none() && bot.room_id == room_id)
+            .count();
+        if self
+            .seats_left(room, &[])
+            .is_some_and(|left| left <= arriving)
+        {
+            return Err(format!("room {} is full", room_id));
+        }
+        drop(rooms);
+        let client = LocalClient::connect(self.inbound.clone(), self.connections.clone());
+        let format = self.config.wire_format;
+        let hello = ClientMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            wire_formats: vec![format],
+            snapshot_formats: vec![SnapshotFormat::Native],
+            password: None,
+        };
+        client.send(&format.encode(&hello));
+        let goal = match path {
+            Some(_) => Goal::Waypoint(0),
+            None => Goal::Idle,
+        };
+        let bot = Bot {
+            client,
+            room_id,
+            team,
+            path,
+            player_id: None,
+            position: (0.0, 0.0),
+            post: (0.0, 0.0),
+            goal,
+        };
+        self.bots.insert(bot.client.endpoint(), bot);
+        Ok(())
+    }
+
+    // `None` removes every bot; returns how many went
+    pub(super) fn remove_bots(&mut self, player_id: Option<usize>) -> Result<usize, String> {
+        let endpoints: Vec<Endpoint> = self
+            .bots
+            .iter()
+            .filter(|(_, bot)| player_id.is_none() || bot.player_id == player_id)
+            .map(|(&endpoint, _)| endpoint)
+            .collect();
+        if let (Some(player_id), true) = (player_id, endpoints.is_empty()) {
+            return Err(format!("player {} is not a bot", player_id));
+        }
+        for &endpoint in &endpoints {
+            self.remove_bot(endpoint);
+        }
+        Ok(endpoints.len())
+    }
+
+    fn remove_bot(&mut self, endpoint: Endpoint) {
+        if self.bots.remove(&endpoint).is_some() {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::Kicked,
+                LocalizedText::new("disconnect.bot_removed"),
+            );
+        }
+    }
+
+    pub(super) fn bots_text(&self) -> String {
+        if self.bots.is_empty() {
+            return "no bots".to_string();
+        }
+        let mut lines: Vec<String> = self
+            .bots
+            .values()
+            .map(|bot| {
+                let who = match bot.player_id {
+                    Some(player_id) => format!("player {}", player_id),
+                    None => "a bot still connecting".to_string(),
+                };
+                let team = match bot.team {
+                    Some(team) => format!(" on team {}", team),
+                    None => String::new(),
+                };
+                let doing = match (&bot.path, bot.goal) {
+                    (Some(path), _) => format!("walking path `{}`", path),
+                    (None, Goal::Chase(target)) => format!("chasing player {}", target),
+                    (None, _) => "wandering".to_string(),
+                };
+                format!("{} in room {}{}, {}", who, bot.room_id, team, doing)
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n")
+    }
+
+    // The teams of every running match and how many players each has,
+    // counting bots on their way in
+    fn bot_matches(&self) -> BTreeMap<RoomId, BTreeMap<TeamId, u32>> {
+        let mut matches = self.matches();
+        for bot in self.bots.values().filter(|bot| bot.player_id.is_none()) {
+            let count = bot
+                .team
+                .and_then(|team| matches.get_mut(&bot.room_id)?.get_mut(&team));
+            if let Some(count) = count {
+                *count += 1;
+            }
+        }
+        matches
+    }
+
+    // Each tick: take in what was sent to each bot, seat those whose
+    // handshake is through, and let go of any whose connection closed or who
+    // were put out of their room
+    pub(super) fn run_bots(&mut self) {
+        let format = self.config.wire_format;
+        let mut closed = Vec::new();
+        for (&endpoint, bot) in &mut self.bots {
+            loop {
+                let frame = match bot.client.try_recv() {
+                    Ok(frame) => frame,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        closed.push(endpoint);
+                        break;
+                    }
+                };
+                match format.decode(&frame) {
+                    // The server putting the bot somewhere, as when it walked
+                    // into an obstacle
+                    Ok(ClientMessage::PlayerPosition { id, x, y }) if bot.player_id == Some(id) => {
+                        bot.position = (x, y);
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Bot on {} couldn't read a frame: {}", endpoint, e),
+                }
+            }
+        }
+        for endpoint in closed {
+            if let Some(bot) = self.bots.remove(&endpoint) {
+                println!("Bot {:?} was disconnected", bot.player_id);
+            }
+        }
+
+        let mut arrived = Vec::new();
+        let mut unseated = Vec::new();
+        for (&endpoint, bot) in &self.bots {
+            let Some(&player_id) = self.endpoints.get(&endpoint) else {
+                continue;
+            };
+            let Some(player) = self.game_state.players.get(&player_id) else {
+                continue;
+            };
+            match bot.player_id {
+                None if player.joined => arrived.push((endpoint, player_id)),
+                Some(_) if player.room != Some(bot.room_id) => unseated.push(endpoint),
+                _ => {}
+            }
+        }
+        for (endpoint, player_id) in arrived {
+            self.seat_bot(endpoint, player_id);
+        }
+        for endpoint in unseated {
+            self.remove_bot(endpoint);
+        }
+    }
+
+    fn seat_bot(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some(&Bot { room_id, team, .. }) = self.bots.get(&endpoint) else {
+            return;
+        };
+        let full = {
+            let rooms = self.game_state.rooms.read().unwrap();
+            rooms
+                .get(room_id)
+                .is_none_or(|room| self.seats_left(room, &[player_id]) == Some(0))
+        };
+        if full {
+            println!("Bot {} found room {} gone or full", player_id, room_id);
+            self.remove_bot(endpoint);
+            return;
+        }
+        self.enter_room(endpoint, player_id, room_id);
+        if let Some(team) = team {
+            if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+                player.team = Some(team);
+            }
+            self.record_action(player_id, format!("was seated on team {}", team));
+            self.announce_team(player_id);
+        }
+        let position = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|player| self.game_state.position(&player))
+            .unwrap_or_default();
+        if let Some(bot) = self.bots.get_mut(&endpoint) {
+            bot.player_id = Some(player_id);
+            bot.position = position;
+            bot.post = position;
+        }
+        match team {
+            Some(team) => println!("Bot {} is in room {} on team {}", player_id, room_id, team),
+            None => println!("Bot {} is in room {}", player_id, room_id),
+        }
+    }
+
+    // Each simulated tick: every seated bot takes a step towards its goal
+    pub(super) fn move_bots(&mut self) {
+        let config = &self.config.bots;
+        let step = config.speed / self.config.snapshot_rate as f32;
+        let format = self.config.wire_format;
+        for bot in self.bots.values_mut() {
+            let Some(player_id) = bot.player_id else {
+                continue;
+            };
+            let target = match bot.goal {
+                Goal::Idle => None,
+                Goal::Waypoint(waypoint) => bot
+                    .path(config)
+                    .and_then(|path| path.get(waypoint).copied()),
+                Goal::Point(point) => Some(point),
+                Goal::Chase(target) => self
+                    .game_state
+                    .players
+                    .get(&target)
+                    .filter(|player| player.room == Some(bot.room_id))
+                    .and_then(|player| self.game_state.position(&player)),
+            };
+            let Some(target) = target else {
+                bot.goal = bot.next_goal(config);
+                continue;
+            };
+            // A chaser that caught up keeps to its target's heels
+            let (to, arrived) = match bot.goal {
+                Goal::Chase(_) if bots::step_toward(bot.position, target, step).1 => {
+                    (bot.position, true)
+                }
+                _ => bots::step_toward(bot.position, target, step),
+            };
+            let blocked = self
+                .maps
+                .get(&Some(bot.room_id))
+                .is_some_and(|state| state.map.blocked(to));
+            if blocked || (arrived && !matches!(bot.goal, Goal::Chase(_))) {
+                bot.goal = bot.next_goal(config);
+            }
+            if blocked {
+                continue;
+            }
+            bot.position = to;
+            let message = ClientMessage::PlayerPosition {
+                id: player_id,
+                x: to.0,
+                y: to.1,
+            };
+            bot.client.send(&format.encode(&message));
+        }
+    }
+
+    // Once a second: bots not walking a path give chase to the nearest
+    // player off their team in sight, and give up on those out of it
+    pub(super) fn think_bots(&mut self) {
+        let sight = self.config.bots.sight;
+        let mut occupants = HashMap::new();
+        for bot in self.bots.values() {
+            let room = Some(bot.room_id);
+            occupants
+                .entry(room)
+                .or_insert_with(|| self.occupants(room));
+        }
+        for bot in self.bots.values_mut() {
+            if bot.path.is_some() || bot.player_id.is_none() {
+                continue;
+            }
+            let Some(occupants) = occupants.get(&Some(bot.room_id)) else {
+                continue;
+            };
+            let (x, y) = bot.position;
+            let nearest = occupants
+                .iter()
+                .filter(|other| {
+                    Some(other.id) != bot.player_id
+                        && (bot.team.is_none() || other.team != bot.team)
+                        && self
+                            .game_state
+                            .players
+                            .get(&other.id)
+                            .is_some_and(|player| !player.spectating)
+                })
+                .map(|other| {
+                    let (ox, oy) = other.position;
+                    (other.id, (ox - x).hypot(oy - y))
+                })
+                .filter(|&(_, distance)| distance <= sight)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            bot.goal = match (nearest, bot.goal) {
+                (Some((target, _)), _) => Goal::Chase(target),
+                (None, Goal::Chase(_)) => Goal::Idle,
+                (None, goal) => goal,
+            };
+        }
+    }
+
+    // Once a second, with `bots.fill` on: teams of running matches short of
+    // players are topped up with bots, unless players are waiting to fill
+    // them, and bots leave teams players have overfilled
+    pub(super) fn fill_matches(&mut self) {
+        if !self.config.bots.fill || self.drain.is_some() {
+            return;
+        }
+        let size = self.config.matchmaking.team_size;
+        for (room_id, teams) in self.bot_matches() {
+            for (team, count) in teams {
+                if count > size {
+                    let seated = self.bots.iter().find(|(_, bot)| {
+                        bot.room_id == room_id && bot.team == Some(team) && bot.player_id.is_some()
+                    });
+                    if let Some((&endpoint, _)) = seated {
+                        self.remove_bot(endpoint);
+                    }
+                    continue;
+                }
+                if !self.match_queue.is_empty() {
+                    continue;
+                }
+                for _ in count..size {
+                    if self.connect_bot(room_id, Some(team), None).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // A seat a bot gives up to a queued player who could go in its room;
+    // the player takes its place on its team
+    pub(super) fn unseat_bot(&mut self, player_id: usize) -> Option<(RoomId, TeamId)> {
+        let (endpoint, room_id, team) = self.bots.iter().find_map(|(&endpoint, bot)| {
+            bot.player_id?;
+            let team = bot.team?;
+            self.may_join_match(player_id, bot.room_id)
+                .then_some((endpoint, bot.room_id, team))
+        })?;
+        println!(
+            "A bot gives up its seat in room {} to player {}",
+            room_id, player_id
+        );
+        self.remove_bot(endpoint);
+        Some((room_id, team))
+    }
+}
@@ -0,0 +1,317 @@
+// Chat channels, teams and spectators, and chat history. The server checks
+// that the sender belongs to a channel before anything goes out on it or is
+// read back from it, and spectator chat never reaches the players.
+use super::outbound::Outbound;
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::analytics;
+use crate::chat::{ChannelKey, ChatHistory};
+use crate::endpoint::Endpoint;
+use crate::protocol::{ChatChannel, ChatLine, ClientMessage, ErrorCode};
+use crate::rooms::TeamId;
+
+impl Server {
+    // Global chat, from `Chat` or a legacy `UpdateMessage`
+    pub(super) fn global_chat(&mut self, player_id: usize, message: String) {
+        let mut name = None;
+        if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+            player.message = message.clone();
+            player.messages_sent += 1;
+            name = player.name.clone();
+        }
+        self.record_chat(ChannelKey::Global, player_id, name.clone(), message.clone());
+        self.broadcast_chat(player_id, name, message);
+        self.count_event(player_id, GameEvent::ChatMessage);
+    }
+
+    pub(super) fn on_chat(&mut self, endpoint: Endpoint, channel: ChatChannel, text: String) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        // Commands count against the guest limit too, as some speak to the
+        // room
+        if !self.check_guest_chat(endpoint, own_id) {
+            return;
+        }
+        let Some(text) = self.slash_command(endpoint, own_id, text) else {
+            return;
+        };
+        if channel == ChatChannel::Global {
+            self.global_chat(own_id, text);
+            return;
+        }
+        let key = match self.channel_key(own_id, channel) {
+            Ok(key) => key,
+            Err(problem) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+                return;
+            }
+        };
+        let mut name = None;
+        if let Some(mut player) = self.game_state.players.get_mut(&own_id) {
+            player.messages_sent += 1;
+            name = player.name.clone();
+        }
+        let recipients = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| {
+                let member = match key {
+                    ChannelKey::Global => true,
+                    ChannelKey::Room(room) => p.room == room,
+                    ChannelKey::Team(room, team) => p.room == Some(room) && p.team == Some(team),
+                    ChannelKey::Party(party) => p.party == Some(party),
+                    ChannelKey::Spectator(room) => p.room == Some(room) && p.spectating,
+                };
+                member
+                    && p.id != own_id
+                    && p.joined
+                    && p.snapshot_format.is_some()
+                    && !p.muted.contains(&own_id)
+            })
+            .map(|p| (p.endpoint, p.wire_format))
+            .collect();
+        self.record_chat(key, own_id, name.clone(), text.clone());
+        let message = ClientMessage::ChatMessage {
+            id: own_id,
+            name,
+            message: text,
+            channel,
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+        self.count_event(own_id, GameEvent::ChatMessage);
+    }
+
+    pub(super) fn on_fetch_chat_history(
+        &self,
+        endpoint: Endpoint,
+        channel: ChatChannel,
+        before: Option<u64>,
+        limit: u32,
+    ) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let key = match self.channel_key(own_id, channel) {
+            Ok(key) => key,
+            Err(problem) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+                return;
+            }
+        };
+        let limit = (limit as usize).min(self.config.chat_history.max_fetch);
+        let mut lines = self
+            .game_state
+            .chat_history
+            .read()
+            .unwrap()
+            .fetch(key, before, limit);
+        // Lines from muted players stay hidden, here as everywhere
+        if let Some(player) = self.game_state.players.get(&own_id) {
+            lines.retain(|line| !player.muted.contains(&line.from_id));
+        }
+        self.send(endpoint, &ClientMessage::ChatHistory { channel, lines });
+    }
+
+    // The buffer `channel` means for `player_id`, or why they aren't on it
+    fn channel_key(
+        &self,
+        player_id: usize,
+        channel: ChatChannel,
+    ) -> Result<ChannelKey, &'static str> {
+        let Some(player) = self.game_state.players.get(&player_id) else {
+            return Err("not joined");
+        };
+        match channel {
+            ChatChannel::Global => Ok(ChannelKey::Global),
+            ChatChannel::Room => Ok(ChannelKey::Room(player.room)),
+            ChatChannel::Team => match (player.room, player.team) {
+                (Some(room), Some(team)) => Ok(ChannelKey::Team(room, team)),
+                _ => Err("not on a team"),
+            },
+            ChatChannel::Party => player.party.map(ChannelKey::Party).ok_or("not in a party"),
+            ChatChannel::Spectator => match player.room {
+                Some(room) if player.spectating => Ok(ChannelKey::Spectator(room)),
+                _ => Err("not spectating"),
+            },
+        }
+    }
+
+    pub(super) fn record_chat(
+        &self,
+        key: ChannelKey,
+        from_id: usize,
+        name: Option<String>,
+        text: String,
+    ) {
+        self.record_action(from_id, format!("said on {}: {}", key, text));
+        let line = ChatLine {
+            id: 0,
+            sent_at: analytics::unix_now(),
+            from_id,
+            name,
+            text,
+        };
+        let account = self
+            .game_state
+            .players
+            .get(&from_id)
+            .and_then(|p| p.account);
+        let max_lines = self.config.chat_history.max_lines;
+        self.update_chat_history(|history| history.record(key, line, account, max_lines));
+    }
+
+    // A moderator's view of any channel
+    pub(super) fn chat_history_text(&self, key: ChannelKey, limit: usize) -> String {
+        let lines = self
+            .game_state
+            .chat_history
+            .read()
+            .unwrap()
+            .fetch(key, None, limit);
+        if lines.is_empty() {
+            return format!("nothing said in {}", key);
+        }
+        lines
+            .iter()
+            .map(|line| {
+                format!(
+                    "#{} [{}] {} (player {}): {}",
+                    line.id,
+                    line.sent_at,
+                    line.name.as_deref().unwrap_or("(unnamed)"),
+                    line.from_id,
+                    line.text
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Apply a change to the chat history, persisting it if configured to
+    pub(super) fn update_chat_history(&self, change: impl FnOnce(&mut ChatHistory)) {
+        let mut history = self.game_state.chat_history.write().unwrap();
+        change(&mut history);
+        if self.config.chat_history.persist {
+            self.save(ChatHistory::STORAGE_KEY, &*history);
+        }
+    }
+
+    pub(super) fn on_set_team(&mut self, endpoint: Endpoint, team: Option<TeamId>) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let Some(mut player) = self.game_state.players.get_mut(&own_id) else {
+            return;
+        };
+        let problem = if player.room.is_none() {
+            Some("join a room to pick a team")
+        } else if player.spectating && team.is_some() {
+            Some("spectators can't join a team")
+        } else if player.team.is_some() && player.team != team && self.teams_locked(player.room) {
+            // Joining a team mid-round is fine; leaving or switching isn't
+            Some("teams are locked until the round ends")
+        } else {
+            player.team = team;
+            None
+        };
+        drop(player);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        println!("Player {} is on team {:?}", own_id, team);
+        let what = match team {
+            Some(team) => format!("joined team {}", team),
+            None => "left their team".to_string(),
+        };
+        self.record_action(own_id, what);
+        self.announce_team(own_id);
+    }
+
+    pub(super) fn on_spectate(&mut self, endpoint: Endpoint, spectating: bool) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let Some(mut player) = self.game_state.players.get_mut(&own_id) else {
+            return;
+        };
+        let problem = if player.room.is_none() {
+            Some("join a room to spectate")
+        } else if player.spectating && !spectating && self.teams_locked(player.room) {
+            Some("spectators can't rejoin until the round ends")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            drop(player);
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        player.spectating = spectating;
+        if spectating {
+            player.team = None;
+        }
+        drop(player);
+        println!(
+            "Player {} {} spectating",
+            own_id,
+            if spectating { "started" } else { "stopped" }
+        );
+        let what = if spectating {
+            "started spectating"
+        } else {
+            "stopped spectating"
+        };
+        self.record_action(own_id, what);
+        self.announce_team(own_id);
+    }
+
+    // Tell the player's room, them included, about their team
+    pub(super) fn announce_team(&self, player_id: usize) {
+        let Some((room, message)) = self.game_state.players.get(&player_id).map(|p| {
+            self.update_member(&p);
+            (
+                p.room,
+                ClientMessage::PlayerTeam {
+                    player_id,
+                    team: p.team,
+                    spectating: p.spectating,
+                },
+            )
+        }) else {
+            return;
+        };
+        // No one has id 0
+        let recipients = self.room_recipients(room, 0);
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+
+    // Catch a player who just entered a room up on its teams and spectators
+    pub(super) fn send_teams(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let teams: Vec<ClientMessage> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.id != player_id && p.room == room && (p.team.is_some() || p.spectating))
+            .map(|p| ClientMessage::PlayerTeam {
+                player_id: p.id,
+                team: p.team,
+                spectating: p.spectating,
+            })
+            .collect();
+        for message in teams {
+            self.send(endpoint, &message);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// A worker's side of `crate::cluster`: the links gateways connect over, and
+// moving players to the worker that owns the room they want.
+use super::inbound::Inbound;
+use super::outbound::Outbound;
+use super::transport::Connections;
+use super::Server;
+use crate::cluster::{self, LinkFrame};
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::handoff::Session;
+use crate::passwords;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+// How long a connecting gateway has to send its token
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A player on their way to another worker: the session, its formats, and the
+// message that sent them, which the new worker handles on arrival
+#[derive(Serialize, Deserialize)]
+pub struct Transfer {
+    session: Session,
+    wire_format: WireFormat,
+    snapshot_format: Option<SnapshotFormat>,
+    then: ClientMessage,
+}
+
+impl Transfer {
+    pub(super) fn wire_format(&self) -> WireFormat {
+        self.wire_format
+    }
+}
+
+// Accept gateway links on `addr`; each sends a token matching `token_hash`,
+// then its clients come and go like any others
+pub(super) fn spawn_links(
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    addr: &str,
+    token_hash: String,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Waiting for gateways on {}", addr);
+    let token_hash = Arc::new(token_hash);
+    let next_link = Arc::new(AtomicU32::new(1));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let inbound = inbound.clone();
+            let connections = connections.clone();
+            let token_hash = token_hash.clone();
+            let link = next_link.fetch_add(1, Ordering::Relaxed);
+            thread::spawn(move || {
+                let mut reader = BufReader::new(&stream);
+                match read_token(&stream, &mut reader) {
+                    Ok(token) if passwords::verify_password(&token, &token_hash) => {}
+                    _ => {
+                        println!("Refused a gateway without the right token");
+                        return;
+                    }
+                }
+                println!("Gateway {} linked", link);
+                let e = match stream.try_clone() {
+                    Ok(writer) => serve_link(link, reader, writer, &inbound, &connections),
+                    Err(e) => e,
+                };
+                println!("Gateway {} unlinked: {}", link, e);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn read_token(stream: &TcpStream, reader: &mut BufReader<&TcpStream>) -> io::Result<String> {
+    stream.set_read_timeout(Some(TOKEN_TIMEOUT))?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    stream.set_read_timeout(None)?;
+    Ok(line.trim().to_string())
+}
+
+// Pass the link's clients to the decoder until the link fails, then drop them
+fn serve_link(
+    link: u32,
+    mut reader: BufReader<&TcpStream>,
+    mut writer: TcpStream,
+    inbound: &UnboundedSender<Inbound>,
+    connections: &Connections,
+) -> io::Error {
+    writer.set_nodelay(true).ok();
+    let (frames, outgoing) = mpsc::channel::<LinkFrame>();
+    connections.add_gateway(link, frames);
+    thread::spawn(move || {
+        for frame in outgoing {
+            if cluster::write_frame(&mut writer, &frame).is_err() {
+                writer.shutdown(std::net::Shutdown::Both).ok();
+                break;
+            }
+        }
+    });
+
+    let endpoint = |connection, addr| Endpoint::Gateway {
+        link,
+        connection,
+        addr,
+    };
+    let e = loop {
+        let frame = match cluster::read_frame(&mut reader) {
+            Ok(frame) => frame,
+            Err(e) => break e,
+        };
+        let event = match frame {
+            LinkFrame::Open { connection, addr } => {
+                connections.opened(link, connection, addr);
+                Inbound::Accepted(endpoint(connection, addr))
+            }
+            LinkFrame::Data { connection, data } => {
+                match connections.gateway_addr(link, connection) {
+                    Some(addr) => Inbound::Frame(endpoint(connection, addr), data),
+                    None => continue,
+                }
+            }
+            LinkFrame::Close { connection } => match connections.forget(link, connection) {
+                Some(addr) => Inbound::Disconnected(endpoint(connection, addr)),
+                None => continue,
+            },
+            LinkFrame::Adopt {
+                connection,
+                addr,
+                transfer,
+            } => match bincode::deserialize::<Transfer>(&transfer) {
+                Ok(transfer) => {
+                    connections.opened(link, connection, addr);
+                    Inbound::Adopted(endpoint(connection, addr), Box::new(transfer))
+                }
+                Err(e) => {
+                    eprintln!("Dropped a player moving here: {}", e);
+                    continue;
+                }
+            },
+            LinkFrame::Move { .. } => continue,
+        };
+        inbound.send(event).ok();
+    };
+    for (connection, addr) in connections.remove_gateway(link) {
+        inbound
+            .send(Inbound::Disconnected(endpoint(connection, addr)))
+            .ok();
+    }
+    e
+}
+
+impl Server {
+    // Send the player to the worker that owns the room `then` is about; it
+    // handles `then` once they are there. Returns false when the room is here.
+    pub(super) fn move_for_room(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        room_id: u32,
+        then: ClientMessage,
+    ) -> bool {
+        let Some(worker) = self.config.cluster.remote_owner(room_id) else {
+            return false;
+        };
+        let player = self.game_state.players.get(&player_id).map(|p| p.clone());
+        let Some(player) = player.filter(|_| matches!(endpoint, Endpoint::Gateway { .. })) else {
+            // Only clients behind a gateway can be moved
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRoom,
+                LocalizedText::new("error.no_room").with("room", room_id),
+            );
+            return true;
+        };
+        // Rooms, parties and positions don't span workers
+        let mut session = self.session(&player);
+        session.room = None;
+        session.position = None;
+        session.team = None;
+        session.spectating = false;
+        session.party = None;
+        let transfer = Transfer {
+            session,
+            wire_format: player.wire_format,
+            snapshot_format: player.snapshot_format,
+            then,
+        };
+        let transfer = match bincode::serialize(&transfer) {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                eprintln!("Failed to move player {}: {}", player_id, e);
+                return true;
+            }
+        };
+        println!(
+            "Moving player {} to worker {} for room {}",
+            player_id, worker, room_id
+        );
+        self.remove_player(endpoint);
+        self.inbound.send(Inbound::Closed(endpoint)).ok();
+        self.outbound
+            .send(Outbound::Move(endpoint, worker, transfer))
+            .ok();
+        true
+    }
+
+    // A player another worker moved here
+    pub(super) fn on_adopted(&mut self, endpoint: Endpoint, transfer: Transfer) {
+        if let Some(ip) = endpoint.addr().map(|addr| addr.ip()) {
+            if let Some((reason, message)) = self.address_check(ip) {
+                self.disconnect(endpoint, reason, message);
+                return;
+            }
+            self.throttle.opened(ip);
+        }
+        let id = transfer.session.player_id;
+        println!("Player {} moved here from another worker", id);
+        self.restore_session(
+            endpoint,
+            transfer.session,
+            transfer.wire_format,
+            transfer.snapshot_format,
+        );
+        if self.endpoints.contains_key(&endpoint) {
+            self.on_message(endpoint, Ok(transfer.then));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+// Running slash commands (see `crate::commands`) out of chat. The server's
+// own commands run here on the game loop; registered ones go to whoever
+// registered them as an `Invocation`, to answer with `Invocation::reply`.
+use super::{Server, Signal, Signals};
+use crate::commands::{self, CommandSpec, Line};
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use crate::roles::Role;
+use std::collections::BTreeMap;
+use std::sync::mpsc::Sender;
+
+// A player running a registered command
+#[derive(Debug)]
+pub struct Invocation {
+    pub player_id: usize,
+    pub name: String,
+    pub args: Vec<String>,
+    signals: Signals,
+}
+
+impl Invocation {
+    // Shown to the player who ran the command as console output; nothing
+    // happens if they left in the meantime
+    pub fn reply(&self, text: impl Into<String>) {
+        let signal = Signal::CommandReply(self.player_id, text.into());
+        self.signals.send(signal).ok();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Builtin {
+    Help,
+    Who,
+    Tp,
+    Roll,
+}
+
+#[derive(Debug, Clone)]
+enum Handler {
+    Builtin(Builtin),
+    Registered(Sender<Invocation>),
+}
+
+pub(super) struct Registry {
+    commands: BTreeMap<String, (CommandSpec, Handler)>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let builtins = [
+            (
+                CommandSpec::new("help", "[command]", "list commands, or explain one", None),
+                Builtin::Help,
+            ),
+            (
+                CommandSpec::new("who", "", "list the players in your room", None),
+                Builtin::Who,
+            ),
+            (
+                CommandSpec::new(
+                    "tp",
+                    "<player>|<x> <y>",
+                    "teleport to a player in your room, or to a spot",
+                    Some(Role::Moderator),
+                ),
+                Builtin::Tp,
+            ),
+            (
+                CommandSpec::new(
+                    "roll",
+                    "[dice]",
+                    "roll dice, like 2d6+3, for your room to see; 1d100 without",
+                    None,
+                ),
+                Builtin::Roll,
+            ),
+        ];
+        let commands = builtins
+            .into_iter()
+            .map(|(spec, builtin)| (spec.name.clone(), (spec, Handler::Builtin(builtin))))
+            .collect();
+        Registry { commands }
+    }
+}
+
+impl Registry {
+    pub(super) fn register(
+        &mut self,
+        spec: CommandSpec,
+        handler: Sender<Invocation>,
+    ) -> Result<(), String> {
+        spec.validate()?;
+        if self.commands.contains_key(&spec.name) {
+            return Err(format!("/{} is already registered", spec.name));
+        }
+        println!("Registered command /{}", spec.name);
+        self.commands
+            .insert(spec.name.clone(), (spec, Handler::Registered(handler)));
+        Ok(())
+    }
+}
+
+impl Server {
+    // Run `text` if it is a command; otherwise the chat to send on
+    pub(super) fn slash_command(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        text: String,
+    ) -> Option<String> {
+        match commands::parse(&text) {
+            Line::Chat(text) => Some(text),
+            Line::Command(name, args) => {
+                self.run_command(endpoint, player_id, name, args);
+                None
+            }
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        name: String,
+        args: Vec<String>,
+    ) {
+        let role = self.game_state.players.get(&player_id).and_then(|p| p.role);
+        let Some((spec, handler)) = self.commands.commands.get(&name).cloned() else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.unknown_command").with("name", &name),
+            );
+            return;
+        };
+        if let Some(required) = spec.role.filter(|_| !spec.allows(role)) {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                LocalizedText::new("error.command_role")
+                    .with("name", &name)
+                    .with("role", required),
+            );
+            return;
+        }
+        println!("Player {} ran /{} {:?}", player_id, name, args);
+        let result = match handler {
+            Handler::Builtin(Builtin::Help) => self.help_text(role, &args),
+            Handler::Builtin(Builtin::Who) => self.who_text(player_id),
+            Handler::Builtin(Builtin::Tp) => self.tp(player_id, &args),
+            Handler::Builtin(Builtin::Roll) => self.roll_command(player_id, &args),
+            Handler::Registered(handler) => {
+                let invocation = Invocation {
+                    player_id,
+                    name: name.clone(),
+                    args,
+                    signals: self.signals.clone(),
+                };
+                // Whoever registered it stopped listening
+                if handler.send(invocation).is_err() {
+                    println!("Dropped command /{}, which nobody answers", name);
+                    self.commands.commands.remove(&name);
+                    self.reject(
+                        endpoint,
+                        ErrorCode::InvalidRequest,
+                        LocalizedText::new("error.unknown_command").with("name", &name),
+                    );
+                }
+                return;
+            }
+        };
+        match result {
+            Ok(text) => self.send(endpoint, &ClientMessage::ConsoleOutput { text }),
+            Err(e) => self.reject(endpoint, ErrorCode::InvalidRequest, e),
+        }
+    }
+
+    pub(super) fn on_command_reply(&self, player_id: usize, text: String) {
+        if let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) {
+            self.send(endpoint, &ClientMessage::ConsoleOutput { text });
+        }
+    }
+
+    // Only what the player may run
+    fn help_text(&self, role: Option<Role>, args: &[String]) -> Result<String, String> {
+        if let Some(name) = args.first() {
+            let name = name.trim_start_matches('/');
+            return match self.commands.commands.get(name) {
+                Some((spec, _)) if spec.allows(role) => {
+                    Ok(format!("{}: {}", spec.synopsis(), spec.help))
+                }
+                _ => Err(format!("no command /{}", name)),
+            };
+        }
+        let lines: Vec<String> = self
+            .commands
+            .commands
+            .values()
+            .filter(|(spec, _)| spec.allows(role))
+            .map(|(spec, _)| format!("{:<28} {}", spec.synopsis(), spec.help))
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    // Those in the same room, or the lobby
+    fn who_text(&self, player_id: usize) -> Result<String, String> {
+        let room = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| p.room)
+            .ok_or_else(|| format!("no player {}", player_id))?;
+        let mut here: Vec<(usize, String)> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.joined && p.room == room)
+            .map(|p| {
+                let label = match &p.name {
+                    Some(name) => format!("{} ({})", name, p.id),
+                    None => format!("player {}", p.id),
+                };
+                (p.id, label)
+            })
+            .collect();
+        here.sort();
+        let labels: Vec<String> = here.into_iter().map(|(_, label)| label).collect();
+        let place = match room {
+            Some(room) => format!("room {}", room),
+            None => "the lobby".to_string(),
+        };
+        Ok(format!(
+            "{} in {}: {}",
+            labels.len(),
+            place,
+            labels.join(", ")
+        ))
+    }
+
+    fn tp(&mut self, player_id: usize, args: &[String]) -> Result<String, String> {
+        let room = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| p.room)
+            .ok_or_else(|| format!("no player {}", player_id))?;
+        let (x, y) = match args {
+            [target] => {
+                let target = self.find_player(target)?;
+                let player = self.game_state.players.get(&target).unwrap();
+                if player.room != room || !player.joined {
+                    return Err(format!("player {} is not in your room", target));
+                }
+                self.game_state
+                    .position(&player)
+                    .ok_or_else(|| format!("player {} has no position", target))?
+            }
+            [x, y] => {
+                let parse = |value: &str| {
+                    value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or_else(|| format!("`{}` is not a coordinate", value))
+                };
+                (parse(x)?, parse(y)?)
+            }
+            _ => return Err("usage: /tp <player>|<x> <y>".to_string()),
+        };
+        self.teleport(player_id, room, x, y);
+        Ok(format!("teleported to ({}, {})", x, y))
+    }
+
+    // By id, or by name regardless of case
+    fn find_player(&self, target: &str) -> Result<usize, String> {
+        if let Ok(id) = target.parse::<usize>() {
+            if self.game_state.players.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+        self.game_state
+            .players
+            .iter()
+            .find(|p| {
+                p.name
+                    .as_deref()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(target))
+            })
+            .map(|p| p.id)
+            .ok_or_else(|| format!("no player `{}`", target))
+    }
+}
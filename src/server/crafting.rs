@@ -0,0 +1,88 @@
+// Crafting (see `crate::crafting`): checking a craft against the player's
+// inventory and applying it, or telling them why not.
+use super::loot::describe_items;
+use super::Server;
+use crate::config::ServerConfig;
+use crate::crafting::{CraftError, Recipes};
+use crate::endpoint::Endpoint;
+use crate::inventory::{ExchangeError, Inventories};
+use crate::mail::ItemGrant;
+use crate::protocol::ClientMessage;
+use std::io;
+
+pub(super) fn load_recipes(config: &ServerConfig) -> io::Result<Option<Recipes>> {
+    let Some(path) = &config.crafting.file else {
+        return Ok(None);
+    };
+    let recipes = Recipes::load(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("recipes {}: {}", path.display(), e),
+        )
+    })?;
+    println!(
+        "Loaded {} recipes from {}",
+        recipes.recipes.len(),
+        path.display()
+    );
+    Ok(Some(recipes))
+}
+
+impl Server {
+    pub(super) fn on_craft(&mut self, endpoint: Endpoint, recipe_id: String) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        match self.craft(own_id, &recipe_id) {
+            Ok(items) => {
+                let text = format!("crafted {} into [{}]", recipe_id, describe_items(&items));
+                self.record_action(own_id, text);
+                self.send(endpoint, &ClientMessage::Crafted { recipe_id, items });
+                self.send_inventory(own_id);
+            }
+            Err((code, missing)) => {
+                let message = ClientMessage::CraftFailed {
+                    recipe_id,
+                    code,
+                    missing,
+                };
+                self.send(endpoint, &message);
+            }
+        }
+    }
+
+    // What the craft gave, or why it didn't go ahead
+    fn craft(
+        &self,
+        player_id: usize,
+        recipe_id: &str,
+    ) -> Result<Vec<ItemGrant>, (CraftError, Vec<ItemGrant>)> {
+        let recipe = self
+            .recipes
+            .as_ref()
+            .and_then(|recipes| recipes.recipes.get(recipe_id))
+            .ok_or((CraftError::UnknownRecipe, Vec::new()))?;
+        let account = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|player| player.account)
+            .ok_or((CraftError::NotLoggedIn, Vec::new()))?;
+        let mut inventories = self.game_state.inventories.write().unwrap();
+        inventories
+            .exchange(
+                &self.config.inventory,
+                account,
+                &recipe.inputs,
+                &recipe.outputs,
+            )
+            .map_err(|e| match e {
+                ExchangeError::Missing(missing) => (CraftError::MissingItems, missing),
+                ExchangeError::Full(_) => (CraftError::InventoryFull, Vec::new()),
+                // Recipes name no stacks, so this would be a bug
+                ExchangeError::Reused(_) => (CraftError::MissingItems, Vec::new()),
+            })?;
+        self.save(Inventories::STORAGE_KEY, &*inventories);
+        Ok(recipe.outputs.clone())
+    }
+}
@@ -0,0 +1,152 @@
+// Capture the flag (see `crate::ctf`): stepping each room's game once a tick
+// and telling its members what happened.
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::ctf::{Ctf, CtfEvent, Flag, FlagState};
+use crate::endpoint::Endpoint;
+use crate::maps::MapState;
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::rooms::{RoomId, TeamId};
+use std::collections::HashMap;
+
+// A game for every room whose map has a `ctf` section
+pub(super) fn games(maps: &HashMap<Option<RoomId>, MapState>) -> HashMap<Option<RoomId>, Ctf> {
+    maps.iter()
+        .filter_map(|(&room, state)| Some((room, Ctf::new(state.map.ctf.clone()?))))
+        .collect()
+}
+
+fn flag_message(flag: &Flag) -> ClientMessage {
+    let (x, y) = flag.position();
+    ClientMessage::Flag {
+        team: flag.team,
+        carrier: flag.carrier(),
+        x,
+        y,
+        home: flag.state == FlagState::Home,
+    }
+}
+
+impl Server {
+    // Once a tick, after the triggers
+    pub(super) fn check_flags(&mut self) {
+        let rooms: Vec<Option<RoomId>> = self.ctf.keys().copied().collect();
+        for room in rooms {
+            if !self.round_playing(room) {
+                continue;
+            }
+            let occupants = self.occupants(room);
+            let Some(game) = self.ctf.get_mut(&room) else {
+                continue;
+            };
+            let events = game.step(&occupants, self.tick, self.config.snapshot_rate);
+            for event in events {
+                self.on_ctf(room, event);
+            }
+        }
+    }
+
+    pub(super) fn on_drop_flag(&mut self, endpoint: Endpoint) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let room = self.game_state.players.get(&own_id).and_then(|p| p.room);
+        let event = self
+            .ctf
+            .get_mut(&room)
+            .and_then(|game| game.drop_flag(own_id, self.tick));
+        match event {
+            Some(event) => self.on_ctf(room, event),
+            None => self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                "you aren't carrying a flag",
+            ),
+        }
+    }
+
+    fn on_ctf(&mut self, room: Option<RoomId>, event: CtfEvent) {
+        if let CtfEvent::Won { team } = event {
+            self.end_round(room, team);
+            return;
+        }
+        let Some(game) = self.ctf.get(&room) else {
+            return;
+        };
+        let place = room.map_or("the lobby".to_string(), |room| format!("room {}", room));
+        let mut messages = Vec::new();
+        let mut scored = None;
+        let moved = |flag: TeamId| game.flag(flag).map(flag_message);
+        let action = match event {
+            CtfEvent::Taken { flag, by } => {
+                messages.extend(moved(flag));
+                Some((by, format!("took team {}'s flag", flag)))
+            }
+            CtfEvent::Dropped { flag, by } => {
+                messages.extend(moved(flag));
+                Some((by, format!("dropped team {}'s flag", flag)))
+            }
+            CtfEvent::Returned { flag, by } => {
+                messages.extend(moved(flag));
+                by.map(|by| (by, "returned their flag".to_string()))
+            }
+            CtfEvent::Captured {
+                flag,
+                by,
+                team,
+                score,
+            } => {
+                println!(
+                    "Player {} captured team {}'s flag for team {} in {}",
+                    by, flag, team, place
+                );
+                messages.extend(moved(flag));
+                scored = Some((team, score));
+                self.count_event(by, GameEvent::FlagCaptured);
+                Some((by, format!("captured team {}'s flag", flag)))
+            }
+            CtfEvent::Won { .. } => None,
+        };
+        if let Some((player_id, what)) = action {
+            self.record_action(player_id, what);
+        }
+        for message in messages {
+            self.send_room(room, message);
+        }
+        if let Some((team, score)) = scored {
+            self.post_score(room, team, score);
+        }
+    }
+
+    // Where every flag is, to the whole room, as a round starts
+    pub(super) fn broadcast_flags(&self, room: Option<RoomId>) {
+        let Some(game) = self.ctf.get(&room) else {
+            return;
+        };
+        for flag in game.flags() {
+            self.send_room(room, flag_message(flag));
+        }
+    }
+
+    // Catch a player who just entered a room up on its flags and scores
+    pub(super) fn send_flags(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let Some(game) = self.ctf.get(&room) else {
+            return;
+        };
+        for flag in game.flags() {
+            self.send(endpoint, &flag_message(flag));
+        }
+        for (team, score) in game.scores() {
+            self.send(endpoint, &ClientMessage::TeamScore { team, score });
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Rolling the server's dice (see `crate::dice`) for the hosting game, players'
+// `/roll` and the `dice` audit.
+use super::Server;
+use crate::dice::{DiceExpression, DiceRoll};
+use crate::protocol::ClientMessage;
+
+impl Server {
+    pub(super) fn roll_dice(
+        &mut self,
+        expression: &str,
+        purpose: &str,
+        player_id: Option<usize>,
+    ) -> Result<DiceRoll, String> {
+        let expression: DiceExpression = expression.parse()?;
+        let roll = self.dice.roll(expression, purpose, player_id)?;
+        match player_id {
+            Some(player_id) => println!("Rolled {} for {}, player {}", roll, purpose, player_id),
+            None => println!("Rolled {} for {}", roll, purpose),
+        }
+        Ok(roll)
+    }
+
+    // Everyone in the roller's room, or the lobby, sees the result, so no one
+    // can claim a roll they didn't make
+    pub(super) fn roll_command(
+        &mut self,
+        player_id: usize,
+        args: &[String],
+    ) -> Result<String, String> {
+        let expression = match args {
+            [] => DiceExpression::default().to_string(),
+            [expression] => expression.clone(),
+            _ => return Err("usage: /roll [dice, like 2d6+3]".to_string()),
+        };
+        let roll = self.roll_dice(&expression, "/roll", Some(player_id))?;
+        let Some((room, name)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| (p.room, p.name.clone()))
+        else {
+            return Ok(roll.to_string());
+        };
+        let name = name.unwrap_or_else(|| format!("player {}", player_id));
+        let text = format!("{} rolled {}", name, roll);
+        let watchers: Vec<_> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.id != player_id && p.joined && p.room == room)
+            .map(|p| p.endpoint)
+            .collect();
+        for endpoint in watchers {
+            self.send(
+                endpoint,
+                &ClientMessage::ConsoleOutput { text: text.clone() },
+            );
+        }
+        Ok(text)
+    }
+
+    pub(super) fn dice_text(&self, count: usize) -> String {
+        let mut lines = vec![format!(
+            "seed {}, {} draws so far",
+            self.dice.seed(),
+            self.dice.draws()
+        )];
+        for draw in self.dice.recent(count) {
+            let mut line = format!("  #{} {}", draw.number, draw.purpose);
+            if let Some(player_id) = draw.player_id {
+                line.push_str(&format!(" for player {}", player_id));
+            }
+            if let Some(outcome) = &draw.outcome {
+                line.push_str(&format!(": {}", outcome));
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
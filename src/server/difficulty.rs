@@ -0,0 +1,76 @@
+// Turning rooms' difficulty (see `crate::difficulty`), and recording how
+// matches went at it
+use super::Server;
+use crate::difficulty::{Difficulty, DifficultyChange};
+use crate::history::MatchHistory;
+use crate::protocol::ClientMessage;
+use crate::rooms::{RoomId, TeamId};
+
+impl Server {
+    // The lobby is always at 1
+    pub(super) fn difficulty(&self, room: Option<RoomId>) -> Difficulty {
+        let rooms = self.game_state.rooms.read().unwrap();
+        room.and_then(|room_id| rooms.get(room_id))
+            .map(|room| room.difficulty)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn change_difficulty(
+        &mut self,
+        room_id: RoomId,
+        change: &DifficultyChange,
+    ) -> Result<Difficulty, String> {
+        change.validate()?;
+        let mut rooms = self.game_state.rooms.write().unwrap();
+        let room = rooms
+            .get_mut(room_id)
+            .ok_or_else(|| format!("no room {}", room_id))?;
+        room.difficulty.apply(change);
+        let difficulty = room.difficulty;
+        drop(rooms);
+        println!(
+            "Room {} difficulty is now {}",
+            room_id,
+            difficulty.describe()
+        );
+        self.send_room(
+            Some(room_id),
+            ClientMessage::DifficultyChanged { difficulty },
+        );
+        Ok(difficulty)
+    }
+
+    pub(super) fn send_difficulty(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let difficulty = self.difficulty(room);
+        if difficulty != Difficulty::default() {
+            self.send(endpoint, &ClientMessage::DifficultyChanged { difficulty });
+        }
+    }
+
+    // A round ended, or with `scenario` a scenario finished
+    pub(super) fn record_match(
+        &self,
+        room: Option<RoomId>,
+        scenario: Option<String>,
+        winner: Option<TeamId>,
+    ) {
+        let name = room
+            .and_then(|room_id| {
+                let rooms = self.game_state.rooms.read().unwrap();
+                rooms.get(room_id).map(|room| room.name.clone())
+            })
+            .unwrap_or_default();
+        let difficulty = self.difficulty(room);
+        let mut history = self.game_state.match_history.write().unwrap();
+        history.record(room, name, scenario, winner, difficulty);
+        self.save(MatchHistory::STORAGE_KEY, &*history);
+    }
+}
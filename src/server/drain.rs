@@ -0,0 +1,98 @@
+// Draining for a rolling restart: stop taking new players and new matches,
+// wait for the players still in rooms to finish (or for the deadline), then
+// exit with `drain.exit_code` so a supervisor starts the new binary.
+use super::{Server, Signal};
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, DisconnectReason, ErrorCode, LocalizedText};
+use std::time::{Duration, Instant};
+
+impl Server {
+    pub(super) fn start_drain(&mut self, seconds: Option<u64>) -> String {
+        let seconds = seconds.unwrap_or(self.config.drain.deadline_secs);
+        self.drain = Some(Instant::now() + Duration::from_secs(seconds));
+        println!("Draining for a restart, at most {}s", seconds);
+        self.announce(LocalizedText::new("announce.drain").with("seconds", seconds));
+        let playing = self.players_in_rooms();
+        format!(
+            "draining: {} players still in matches, restarting in at most {}s",
+            playing, seconds
+        )
+    }
+
+    pub(super) fn cancel_drain(&mut self) -> Result<String, String> {
+        if self.drain.take().is_none() {
+            return Err("the server is not draining".to_string());
+        }
+        println!("Drain cancelled");
+        self.announce(LocalizedText::new("announce.drain_cancelled"));
+        Ok("drain cancelled".to_string())
+    }
+
+    // Seconds until the drain gives up waiting, while one runs
+    pub(super) fn drain_left(&self) -> Option<u64> {
+        self.drain
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs())
+    }
+
+    // Once a second: restart when the last match is over or time is up
+    pub(super) fn check_drain(&mut self) {
+        let Some(deadline) = self.drain else {
+            return;
+        };
+        let playing = self.players_in_rooms();
+        if playing == 0 || Instant::now() >= deadline {
+            println!(
+                "Drain finished with {} players still in matches; restarting",
+                playing
+            );
+            // Cleared so the restart is only signalled once
+            self.drain = None;
+            self.signals.send(Signal::Restart).ok();
+        }
+    }
+
+    // Disconnect everyone for the restart. Their sessions are recorded as they
+    // leave, and the persister writes everything out before the process exits.
+    pub(super) fn restart(&mut self) {
+        println!("Restarting");
+        let endpoints: Vec<Endpoint> = self.endpoints.keys().copied().collect();
+        for endpoint in endpoints {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::Restarting,
+                LocalizedText::new("disconnect.restarting"),
+            );
+        }
+    }
+
+    // Rooms can't be created or joined during a drain. Returns whether the
+    // sender may go ahead.
+    pub(super) fn check_not_draining(&self, endpoint: Endpoint) -> bool {
+        if self.drain.is_some() {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                LocalizedText::new("error.draining"),
+            );
+            return false;
+        }
+        true
+    }
+
+    fn players_in_rooms(&self) -> usize {
+        self.game_state
+            .players
+            .iter()
+            .filter(|p| p.joined && p.room.is_some())
+            .count()
+    }
+
+    // Show every player translatable text
+    pub(super) fn announce(&self, text: LocalizedText) {
+        let announcement = ClientMessage::Announcement {
+            text: self.config.localization.render(&text),
+            localized: Some(text),
+        };
+        self.broadcast(&announcement, 0);
+    }
+}
@@ -0,0 +1,54 @@
+// Emote requests: checked against the configured set and the cooldown, then
+// handed to the room's shard, which knows who is close enough to see them
+use super::shards::ShardCommand;
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ErrorCode, LocalizedText};
+use std::time::Instant;
+
+impl Server {
+    pub(super) fn on_emote(&mut self, endpoint: Endpoint, id: String) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let emotes = &self.config.emotes;
+        if !emotes.knows(&id) {
+            let problem = if emotes.emotes.is_empty() {
+                "emotes are turned off".to_string()
+            } else {
+                format!("unknown emote `{}`", id)
+            };
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        let now = Instant::now();
+        let Some(mut player) = self.game_state.players.get_mut(&player_id) else {
+            return;
+        };
+        let cooling = player
+            .last_emote
+            .is_some_and(|last| now.duration_since(last) < emotes.cooldown());
+        if !cooling {
+            player.last_emote = Some(now);
+        }
+        let room = player.room;
+        drop(player);
+        if cooling {
+            self.reject(
+                endpoint,
+                ErrorCode::RateLimited,
+                LocalizedText::new("error.emote_cooldown").with("ms", emotes.cooldown_ms),
+            );
+            return;
+        }
+        self.record_action(player_id, format!("emote {}", id));
+        let command = ShardCommand::Emote {
+            room,
+            player_id,
+            emote: id,
+            radius: emotes.radius,
+            rewind: self.rewind_tick(),
+        };
+        self.shards.send(room, command);
+    }
+}
@@ -0,0 +1,217 @@
+// Entities spawned at runtime (see `crate::entities`): keeping them, telling
+// rooms about them, who gets to move them and letting them go with their
+// rooms.
+use super::rounds::describe;
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::entities::Entity;
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::rooms::RoomId;
+
+fn spawned_message(id: u64, entity: &Entity) -> ClientMessage {
+    ClientMessage::EntitySpawned {
+        id,
+        kind: entity.kind.clone(),
+        x: entity.x,
+        y: entity.y,
+        metadata: entity.metadata.clone(),
+    }
+}
+
+impl Server {
+    // Returns the new entity's id, or why it was refused
+    pub(super) fn spawn_entity(
+        &mut self,
+        room: Option<RoomId>,
+        entity: Entity,
+    ) -> Result<u64, String> {
+        if let Some(room_id) = room {
+            if self.game_state.rooms.read().unwrap().get(room_id).is_none() {
+                return Err(format!("no room {}", room_id));
+            }
+        }
+        let id = self
+            .entities
+            .spawn(&self.config.entities, room, entity.clone())?;
+        println!("Spawned {} {} in {}", entity.kind, id, describe(room));
+        self.send_room(room, spawned_message(id, &entity));
+        Ok(id)
+    }
+
+    pub(super) fn despawn_entity(&mut self, id: u64) -> Result<(), String> {
+        let (room, entity) = self
+            .entities
+            .despawn(id)
+            .ok_or_else(|| format!("no entity {}", id))?;
+        println!("Despawned {} {} in {}", entity.kind, id, describe(room));
+        self.forget_entity_move(id);
+        self.send_room(room, ClientMessage::EntityDespawned { id });
+        Ok(())
+    }
+
+    // Catch a player who just entered a room up on its entities
+    pub(super) fn send_entities(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        for (id, entity) in self.entities.in_room(room) {
+            self.send(endpoint, &spawned_message(id, entity));
+            if let Some(owner) = self.entities.authority(id) {
+                let message = ClientMessage::EntityAuthority {
+                    id,
+                    owner: Some(owner),
+                };
+                self.send(endpoint, &message);
+            }
+        }
+    }
+
+    // Give a player in the entity's room authority over it, or give it back
+    // to the server with `None`
+    pub(super) fn transfer_authority(&mut self, id: u64, to: Option<usize>) -> Result<(), String> {
+        let room = self
+            .entities
+            .room_of(id)
+            .ok_or_else(|| format!("no entity {}", id))?;
+        if let Some(player_id) = to {
+            let present = self
+                .game_state
+                .players
+                .get(&player_id)
+                .is_some_and(|p| p.snapshot_format.is_some() && p.room == room);
+            if !present {
+                return Err(format!(
+                    "player {} is not in {} with entity {}",
+                    player_id,
+                    describe(room),
+                    id
+                ));
+            }
+        }
+        if self.entities.authority(id) == to {
+            return Ok(());
+        }
+        self.entities.set_authority(id, to);
+        match to {
+            Some(player_id) => {
+                println!("Player {} has authority over entity {}", player_id, id);
+                self.record_action(player_id, format!("took authority over entity {}", id));
+            }
+            None => println!("The server took back entity {}", id),
+        }
+        self.send_room(room, ClientMessage::EntityAuthority { id, owner: to });
+        Ok(())
+    }
+
+    // The game hosting the server moves the entities it has authority over
+    pub(super) fn move_entity(&mut self, id: u64, x: f32, y: f32) -> Result<(), String> {
+        if let Some(owner) = self.entities.authority(id) {
+            return Err(format!("player {} has authority over entity {}", owner, id));
+        }
+        self.place_entity(id, x, y)
+    }
+
+    fn place_entity(&mut self, id: u64, x: f32, y: f32) -> Result<(), String> {
+        if !(x.is_finite() && y.is_finite()) {
+            return Err("an entity needs a finite position".to_string());
+        }
+        let room = self
+            .entities
+            .move_to(id, x, y)
+            .ok_or_else(|| format!("no entity {}", id))?;
+        self.post_entity_move(room, id, x, y);
+        Ok(())
+    }
+
+    // Whether the sender has authority over the entity, telling them if not
+    fn check_authority(&self, endpoint: Endpoint, id: u64) -> bool {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return false;
+        };
+        if self.entities.authority(id) == Some(own_id) {
+            return true;
+        }
+        self.reject(
+            endpoint,
+            ErrorCode::InvalidRequest,
+            format!("you don't have authority over entity {}", id),
+        );
+        false
+    }
+
+    pub(super) fn on_transfer_authority(&mut self, endpoint: Endpoint, id: u64, to: Option<usize>) {
+        if !self.check_authority(endpoint, id) {
+            return;
+        }
+        if let Err(e) = self.transfer_authority(id, to) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+
+    pub(super) fn on_move_entity(&mut self, endpoint: Endpoint, id: u64, x: f32, y: f32) {
+        if !self.check_authority(endpoint, id) {
+            return;
+        }
+        if let Err(e) = self.place_entity(id, x, y) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+
+    // The server takes back what a player has authority over outside the
+    // room they are in now, and everything once they are gone
+    pub(super) fn reclaim_entities(&mut self, player_id: usize) {
+        let room = self.game_state.players.get(&player_id).map(|p| p.room);
+        for id in self.entities.held_by(player_id) {
+            if room.is_some() && self.entities.room_of(id) == room {
+                continue;
+            }
+            self.transfer_authority(id, None).ok();
+        }
+    }
+
+    // Once a second: the entities of rooms that have closed go with them
+    pub(super) fn prune_entities(&mut self) {
+        let rooms = self.game_state.rooms.read().unwrap();
+        let dropped = self.entities.retain_rooms(|room| rooms.get(room).is_some());
+        if dropped > 0 {
+            println!("Let go of {} entities of closed rooms", dropped);
+        }
+    }
+
+    pub(super) fn entities_text(&self, room: Option<RoomId>) -> String {
+        let lines: Vec<String> = self
+            .entities
+            .in_room(room)
+            .map(|(id, entity)| {
+                let metadata: Vec<String> = entity
+                    .metadata
+                    .iter()
+                    .map(|(key, value)| format!(" {}={}", key, value))
+                    .collect();
+                let authority = self
+                    .entities
+                    .authority(id)
+                    .map_or(String::new(), |owner| format!(", player {}", owner));
+                format!(
+                    "{} {} at ({:.1}, {:.1}){}{}",
+                    id,
+                    entity.kind,
+                    entity.x,
+                    entity.y,
+                    authority,
+                    metadata.concat()
+                )
+            })
+            .collect();
+        if lines.is_empty() {
+            return format!("No entities in {}", describe(room));
+        }
+        lines.join("\n")
+    }
+}
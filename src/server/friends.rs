@@ -0,0 +1,255 @@
+// Friend requests and presence. Friends are accounts, so guests have none;
+// whether an account is online, and in which room, only reaches its friends.
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::GameEvent;
+use crate::endpoint::Endpoint;
+use crate::friends::Friends;
+use crate::protocol::{ClientMessage, ErrorCode, Friend};
+use crate::rooms::RoomId;
+
+impl Server {
+    pub(super) fn on_friend_add(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some(me) = self.friends_account(endpoint) else {
+            return;
+        };
+        let them = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined)
+            .and_then(|p| p.account);
+        let Some(them) = them else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("player {} is not online or not logged in", player_id),
+            );
+            return;
+        };
+        let limits = &self.config.friends;
+        let friends = self.game_state.friends.read().unwrap();
+        let mutual = friends.has_requested(them, me);
+        let problem = if them == me {
+            Some("you can't befriend yourself")
+        } else if friends.are_friends(me, them) {
+            Some("you are already friends")
+        } else if friends.has_requested(me, them) {
+            Some("you already sent that player a friend request")
+        } else if !mutual && friends.incoming(them).len() >= limits.max_requests {
+            Some("that player has too many pending friend requests")
+        } else if mutual && !self.room_for_friend(&friends, me, them) {
+            Some("one of your friend lists is full")
+        } else {
+            None
+        };
+        drop(friends);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        self.update_friends(|friends| {
+            if mutual {
+                friends.accept(me, them);
+            } else {
+                friends.request(me, them);
+            }
+        });
+        if mutual {
+            println!("Accounts {} and {} are now friends", me, them);
+            self.count_account_event(me, GameEvent::FriendMade);
+            self.count_account_event(them, GameEvent::FriendMade);
+        } else {
+            println!("Account {} sent a friend request to account {}", me, them);
+        }
+        self.friends_changed(&[me, them]);
+    }
+
+    pub(super) fn on_friend_accept(&mut self, endpoint: Endpoint, account_id: AccountId) {
+        let Some(me) = self.friends_account(endpoint) else {
+            return;
+        };
+        let friends = self.game_state.friends.read().unwrap();
+        let problem = if !friends.has_requested(account_id, me) {
+            Some(format!("no friend request from account {}", account_id))
+        } else if !self.room_for_friend(&friends, me, account_id) {
+            Some("one of your friend lists is full".to_string())
+        } else {
+            None
+        };
+        drop(friends);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        self.update_friends(|friends| friends.accept(me, account_id));
+        println!("Accounts {} and {} are now friends", me, account_id);
+        self.friends_changed(&[me, account_id]);
+        self.count_account_event(me, GameEvent::FriendMade);
+        self.count_account_event(account_id, GameEvent::FriendMade);
+    }
+
+    pub(super) fn on_friend_remove(&mut self, endpoint: Endpoint, account_id: AccountId) {
+        let Some(me) = self.friends_account(endpoint) else {
+            return;
+        };
+        if !self.update_friends(|friends| friends.remove(me, account_id)) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("nothing between you and account {}", account_id),
+            );
+            return;
+        }
+        self.friends_changed(&[me, account_id]);
+    }
+
+    // The sender's account; friends need one
+    fn friends_account(&self, endpoint: Endpoint) -> Option<AccountId> {
+        let account = self
+            .endpoints
+            .get(&endpoint)
+            .and_then(|id| self.game_state.players.get(id).and_then(|p| p.account));
+        if account.is_none() {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "log in to have friends",
+            );
+        }
+        account
+    }
+
+    fn room_for_friend(&self, friends: &Friends, a: AccountId, b: AccountId) -> bool {
+        let max = self.config.friends.max_friends;
+        friends.of(a).len() < max && friends.of(b).len() < max
+    }
+
+    // Whether the sender may see `player_id`'s details: their own, a friend's,
+    // or anyone's for staff
+    pub(super) fn can_look_up(&self, endpoint: Endpoint, player_id: usize) -> bool {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return false;
+        };
+        let Some((role, me)) = self
+            .game_state
+            .players
+            .get(&own_id)
+            .map(|p| (p.role, p.account))
+        else {
+            return false;
+        };
+        let them = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.account);
+        own_id == player_id
+            || role.is_some()
+            || me.zip(them).is_some_and(|(me, them)| {
+                self.game_state
+                    .friends
+                    .read()
+                    .unwrap()
+                    .are_friends(me, them)
+            })
+    }
+
+    // Resend the friend list to whichever of `accounts` are online
+    fn friends_changed(&self, accounts: &[AccountId]) {
+        for &account in accounts {
+            self.send_friend_list(account);
+        }
+    }
+
+    pub(super) fn send_friend_list(&self, account: AccountId) {
+        let Some(endpoint) = self.session_of(account).map(|(endpoint, _)| endpoint) else {
+            return;
+        };
+        let friends = self.game_state.friends.read().unwrap();
+        let (accepted, incoming, outgoing) = (
+            friends.of(account),
+            friends.incoming(account),
+            friends.outgoing(account),
+        );
+        drop(friends);
+        let entries = |accounts: Vec<AccountId>, presence: bool| {
+            accounts
+                .into_iter()
+                .map(|id| self.friend_entry(id, presence))
+                .collect()
+        };
+        self.send(
+            endpoint,
+            &ClientMessage::FriendList {
+                friends: entries(accepted, true),
+                incoming: entries(incoming, false),
+                outgoing: entries(outgoing, false),
+            },
+        );
+    }
+
+    fn friend_entry(&self, account_id: AccountId, presence: bool) -> Friend {
+        let (username, uuid) = self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .by_id(account_id)
+            .map(|a| (a.username.clone(), a.uuid))
+            .unwrap_or_default();
+        let session = self.session_of(account_id).filter(|_| presence);
+        Friend {
+            account_id,
+            uuid,
+            username,
+            online: session.is_some(),
+            room_id: session.and_then(|(_, room)| room),
+        }
+    }
+
+    // Tell `player_id`'s online friends where they are now
+    pub(super) fn update_presence(&self, player_id: usize) {
+        let Some((account, room)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined)
+            .and_then(|p| Some((p.account?, p.room)))
+        else {
+            return;
+        };
+        self.announce_presence(account, true, room);
+    }
+
+    pub(super) fn announce_presence(&self, account: AccountId, online: bool, room: Option<RoomId>) {
+        let friends = self.game_state.friends.read().unwrap().of(account);
+        let message = ClientMessage::FriendPresence {
+            account_id: account,
+            online,
+            room_id: room,
+        };
+        for friend in friends {
+            if let Some((endpoint, _)) = self.session_of(friend) {
+                self.send(endpoint, &message);
+            }
+        }
+    }
+
+    // The joined connection logged in to `account`, and the room it is in
+    pub(super) fn session_of(&self, account: AccountId) -> Option<(Endpoint, Option<RoomId>)> {
+        self.game_state
+            .players
+            .iter()
+            .find(|p| p.joined && p.account == Some(account))
+            .map(|p| (p.endpoint, p.room))
+    }
+
+    // Apply a change to the friend lists and persist the result
+    pub(super) fn update_friends<T>(&self, change: impl FnOnce(&mut Friends) -> T) -> T {
+        let mut friends = self.game_state.friends.write().unwrap();
+        let result = change(&mut friends);
+        self.save(Friends::STORAGE_KEY, &*friends);
+        result
+    }
+}
@@ -0,0 +1,33 @@
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ErrorCode, LocalizedText};
+use crate::throttle;
+use std::time::Instant;
+
+impl Server {
+    // Whether a chat line from `player_id` may go out; guests get
+    // `guests.chat_messages` per window and are told when they run out
+    pub(super) fn check_guest_chat(&self, endpoint: Endpoint, player_id: usize) -> bool {
+        let guests = &self.config.guests;
+        let now = Instant::now();
+        let allowed = match self.game_state.players.get_mut(&player_id) {
+            Some(mut player) if player.is_guest() => throttle::take_slot(
+                &mut player.recent_chat,
+                guests.chat_messages,
+                guests.chat_window(),
+                now,
+            ),
+            _ => true,
+        };
+        if !allowed {
+            self.reject(
+                endpoint,
+                ErrorCode::RateLimited,
+                LocalizedText::new("error.guest_chat_rate")
+                    .with("count", guests.chat_messages)
+                    .with("seconds", guests.chat_window_secs),
+            );
+        }
+        allowed
+    }
+}
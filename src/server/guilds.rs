@@ -0,0 +1,457 @@
+// Guild membership, ranks, guild chat and rosters. Guilds belong to accounts,
+// so guests can't join one; a member's guild tag follows their display name
+// to everyone in the room.
+use super::outbound::Outbound;
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::GameEvent;
+use crate::endpoint::Endpoint;
+use crate::guilds::{GuildId, GuildRank, Guilds};
+use crate::protocol::{ClientMessage, ErrorCode, GuildMember, LocalizedText};
+
+impl Server {
+    pub(super) fn on_guild_create(&mut self, endpoint: Endpoint, name: &str, tag: Option<&str>) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let config = &self.config.guilds;
+        let checked = config.check_name(name).and_then(|name| {
+            let tag = tag.map(|tag| config.check_tag(tag)).transpose()?;
+            Ok((name, tag))
+        });
+        let (name, tag) = match checked {
+            Ok(checked) => checked,
+            Err(e) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                return;
+            }
+        };
+        let guilds = self.game_state.guilds.read().unwrap();
+        let problem = if guilds.of(me).is_some() {
+            Some("leave your guild first")
+        } else {
+            guilds.taken(&name, tag.as_deref())
+        };
+        drop(guilds);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        let guild_id = self.update_guilds(|guilds| guilds.create(name.clone(), tag, me));
+        println!("Player {} founded guild {} ({})", player_id, guild_id, name);
+        self.set_session_guild(me, Some(guild_id));
+        self.guild_changed(guild_id);
+        self.count_account_event(me, GameEvent::GuildJoined);
+    }
+
+    pub(super) fn on_guild_invite(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some((own_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let target = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined)
+            .map(|p| {
+                (
+                    p.endpoint,
+                    p.account,
+                    p.snapshot_format.is_some(),
+                    p.muted.contains(&own_id),
+                )
+            });
+        let guilds = self.game_state.guilds.read().unwrap();
+        let Some(guild) = guilds.of(me) else {
+            drop(guilds);
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.not_in_guild"),
+            );
+            return;
+        };
+        let (guild_id, guild_name) = (guild.id, guild.name.clone());
+        let problem = match target {
+            _ if guild.rank(me) < Some(GuildRank::Officer) => Some((
+                ErrorCode::PermissionDenied,
+                "only officers and the leader can invite".to_string(),
+            )),
+            None | Some((_, None, _, _)) => Some((
+                ErrorCode::InvalidRequest,
+                format!("player {} is not online or not logged in", player_id),
+            )),
+            Some((_, _, false, _)) => Some((
+                ErrorCode::InvalidRequest,
+                format!("player {} can't join guilds", player_id),
+            )),
+            Some((_, Some(them), _, _)) if guilds.of(them).is_some() => Some((
+                ErrorCode::InvalidRequest,
+                format!("player {} is already in a guild", player_id),
+            )),
+            Some((_, Some(them), _, _)) if guild.invited.contains(&them) => Some((
+                ErrorCode::InvalidRequest,
+                format!("you already invited player {}", player_id),
+            )),
+            _ if guild.size() >= self.config.guilds.max_members => {
+                Some((ErrorCode::InvalidRequest, "your guild is full".to_string()))
+            }
+            _ => None,
+        };
+        drop(guilds);
+        if let Some((code, problem)) = problem {
+            self.reject(endpoint, code, problem);
+            return;
+        }
+        let Some((target_endpoint, Some(them), _, muted)) = target else {
+            return;
+        };
+        self.update_guilds(|guilds| guilds.invite(guild_id, them));
+        // As with whispers, an invite from someone muted goes nowhere
+        if !muted {
+            self.send(
+                target_endpoint,
+                &ClientMessage::GuildInvited {
+                    guild_id,
+                    name: guild_name,
+                    from_id: own_id,
+                },
+            );
+        }
+        println!(
+            "Player {} invited player {} to guild {}",
+            own_id, player_id, guild_id
+        );
+        self.guild_changed(guild_id);
+    }
+
+    pub(super) fn on_guild_join(&mut self, endpoint: Endpoint, guild_id: GuildId) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let guilds = self.game_state.guilds.read().unwrap();
+        let problem = if guilds.of(me).is_some() {
+            Some("leave your guild first".to_string())
+        } else if !guilds
+            .get(guild_id)
+            .is_some_and(|guild| guild.invited.contains(&me))
+        {
+            Some(format!("no invite from guild {}", guild_id))
+        } else {
+            None
+        };
+        drop(guilds);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        self.update_guilds(|guilds| guilds.join(guild_id, me));
+        println!("Player {} joined guild {}", player_id, guild_id);
+        self.set_session_guild(me, Some(guild_id));
+        self.guild_changed(guild_id);
+        self.count_account_event(me, GameEvent::GuildJoined);
+    }
+
+    pub(super) fn on_guild_leave(&mut self, endpoint: Endpoint) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let Some(guild_id) = self.game_state.guilds.read().unwrap().of(me).map(|g| g.id) else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.not_in_guild"),
+            );
+            return;
+        };
+        println!("Player {} left guild {}", player_id, guild_id);
+        self.remove_from_guild(guild_id, me);
+    }
+
+    pub(super) fn on_guild_kick(&mut self, endpoint: Endpoint, account_id: AccountId) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let guilds = self.game_state.guilds.read().unwrap();
+        let guild = guilds.of(me);
+        let guild_id = guild.map(|g| g.id);
+        let ranks = guild.map(|g| (g.rank(me), g.rank(account_id)));
+        drop(guilds);
+        let problem = match ranks {
+            None => Some((
+                ErrorCode::InvalidRequest,
+                "you are not in a guild".to_string(),
+            )),
+            Some((_, None)) => Some((
+                ErrorCode::InvalidRequest,
+                format!("account {} is not in your guild", account_id),
+            )),
+            Some((mine, theirs)) if mine < Some(GuildRank::Officer) || theirs >= mine => Some((
+                ErrorCode::PermissionDenied,
+                "you may only kick members ranked below you".to_string(),
+            )),
+            _ => None,
+        };
+        if let Some((code, problem)) = problem {
+            self.reject(endpoint, code, problem);
+            return;
+        }
+        let Some(guild_id) = guild_id else {
+            return;
+        };
+        println!(
+            "Player {} kicked account {} from guild {}",
+            player_id, account_id, guild_id
+        );
+        self.remove_from_guild(guild_id, account_id);
+    }
+
+    pub(super) fn on_guild_set_rank(
+        &mut self,
+        endpoint: Endpoint,
+        account_id: AccountId,
+        rank: GuildRank,
+    ) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let guilds = self.game_state.guilds.read().unwrap();
+        let guild = guilds.of(me);
+        let guild_id = guild.map(|g| g.id);
+        let problem = match guild {
+            None => Some((
+                ErrorCode::InvalidRequest,
+                "you are not in a guild".to_string(),
+            )),
+            Some(guild) if guild.rank(me) != Some(GuildRank::Leader) => Some((
+                ErrorCode::PermissionDenied,
+                "only the guild leader can set ranks".to_string(),
+            )),
+            Some(guild) if guild.rank(account_id).is_none() => Some((
+                ErrorCode::InvalidRequest,
+                format!("account {} is not in your guild", account_id),
+            )),
+            _ if account_id == me => Some((
+                ErrorCode::InvalidRequest,
+                "hand the lead to someone else instead".to_string(),
+            )),
+            _ => None,
+        };
+        drop(guilds);
+        if let Some((code, problem)) = problem {
+            self.reject(endpoint, code, problem);
+            return;
+        }
+        let Some(guild_id) = guild_id else {
+            return;
+        };
+        self.update_guilds(|guilds| guilds.set_rank(guild_id, account_id, rank));
+        println!(
+            "Player {} made account {} {:?} of guild {}",
+            player_id, account_id, rank, guild_id
+        );
+        self.guild_changed(guild_id);
+    }
+
+    pub(super) fn on_guild_chat(&mut self, endpoint: Endpoint, text: String) {
+        let Some((player_id, me)) = self.guild_account(endpoint) else {
+            return;
+        };
+        let guilds = self.game_state.guilds.read().unwrap();
+        let Some(guild) = guilds.of(me) else {
+            drop(guilds);
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.not_in_guild"),
+            );
+            return;
+        };
+        let guild_id = guild.id;
+        let members: Vec<AccountId> = guild.members.keys().copied().collect();
+        drop(guilds);
+        let name = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.name.clone());
+        let message = ClientMessage::GuildChatMessage {
+            guild_id,
+            from_id: player_id,
+            name,
+            text,
+        };
+        for account in members {
+            let Some((recipient, _)) = self.session_of(account) else {
+                continue;
+            };
+            let muted = self
+                .endpoints
+                .get(&recipient)
+                .and_then(|id| self.game_state.players.get(id))
+                .is_some_and(|p| p.muted.contains(&player_id));
+            if !muted {
+                self.send(recipient, &message);
+            }
+        }
+    }
+
+    pub(super) fn on_query_guild_roster(&self, endpoint: Endpoint, guild_id: GuildId) {
+        let member = self
+            .endpoints
+            .get(&endpoint)
+            .and_then(|id| self.game_state.players.get(id).and_then(|p| p.guild));
+        match self.guild_roster(guild_id, member == Some(guild_id)) {
+            Some(roster) => self.send(endpoint, &roster),
+            None => self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("guild {} does not exist", guild_id),
+            ),
+        }
+    }
+
+    // Pick up the guild of an account that just logged in, before the player
+    // is introduced to anyone
+    pub(super) fn guild_logged_in(&self, account: AccountId) {
+        let guild_id = self
+            .game_state
+            .guilds
+            .read()
+            .unwrap()
+            .of(account)
+            .map(|g| g.id);
+        if guild_id.is_some() {
+            self.set_session_guild(account, guild_id);
+        }
+    }
+
+    pub(super) fn send_guild_roster(&self, account: AccountId) {
+        let guild_id = self
+            .game_state
+            .guilds
+            .read()
+            .unwrap()
+            .of(account)
+            .map(|g| g.id);
+        let roster = guild_id.and_then(|guild_id| self.guild_roster(guild_id, true));
+        if let (Some(roster), Some((endpoint, _))) = (roster, self.session_of(account)) {
+            self.send(endpoint, &roster);
+        }
+    }
+
+    // The tag shown next to members' names, unless tags are turned off
+    pub(super) fn guild_tag(&self, guild: Option<GuildId>) -> Option<String> {
+        if !self.config.guilds.show_tags {
+            return None;
+        }
+        let guilds = self.game_state.guilds.read().unwrap();
+        guilds.get(guild?)?.tag.clone()
+    }
+
+    // The sender's player id and account; guilds need one
+    fn guild_account(&self, endpoint: Endpoint) -> Option<(usize, AccountId)> {
+        let found = self.endpoints.get(&endpoint).and_then(|&id| {
+            let account = self.game_state.players.get(&id)?.account?;
+            Some((id, account))
+        });
+        if found.is_none() {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "log in to join a guild",
+            );
+        }
+        found
+    }
+
+    // Take `account` out of `guild_id`, telling them if they are online
+    pub(super) fn remove_from_guild(&self, guild_id: GuildId, account: AccountId) {
+        let remains = self.update_guilds(|guilds| guilds.leave(guild_id, account));
+        self.set_session_guild(account, None);
+        if let Some((endpoint, _)) = self.session_of(account) {
+            self.send(endpoint, &ClientMessage::GuildLeft { guild_id });
+        }
+        if remains {
+            self.guild_changed(guild_id);
+        } else {
+            println!("Guild {} disbanded", guild_id);
+        }
+    }
+
+    // Point the online session of `account` at its new guild and show the
+    // room its tag
+    fn set_session_guild(&self, account: AccountId, guild: Option<GuildId>) {
+        let changed = self
+            .game_state
+            .players
+            .iter_mut()
+            .find(|p| p.account == Some(account))
+            .map(|mut p| {
+                p.guild = guild;
+                (p.id, p.room, p.name.clone(), p.endpoint, p.joined)
+            });
+        let Some((player_id, room, Some(name), endpoint, true)) = changed else {
+            return;
+        };
+        let mut recipients = self.room_recipients(room, player_id);
+        recipients.push((endpoint, self.wire_format(endpoint)));
+        let message = ClientMessage::PlayerNamed {
+            id: player_id,
+            name,
+            tag: self.guild_tag(guild),
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+
+    fn guild_roster(&self, guild_id: GuildId, presence: bool) -> Option<ClientMessage> {
+        let guilds = self.game_state.guilds.read().unwrap();
+        let guild = guilds.get(guild_id)?;
+        let (name, tag) = (guild.name.clone(), guild.tag.clone());
+        let ranks: Vec<(AccountId, GuildRank)> =
+            guild.members.iter().map(|(&a, &r)| (a, r)).collect();
+        drop(guilds);
+        let accounts = self.game_state.accounts.read().unwrap();
+        let members = ranks
+            .into_iter()
+            .map(|(account_id, rank)| GuildMember {
+                account_id,
+                username: accounts
+                    .by_id(account_id)
+                    .map(|a| a.username.clone())
+                    .unwrap_or_default(),
+                rank,
+                online: presence && self.session_of(account_id).is_some(),
+            })
+            .collect();
+        Some(ClientMessage::GuildRoster {
+            guild_id,
+            name,
+            tag,
+            members,
+        })
+    }
+
+    // Resend the roster to every online member
+    fn guild_changed(&self, guild_id: GuildId) {
+        let Some(roster) = self.guild_roster(guild_id, true) else {
+            return;
+        };
+        let ClientMessage::GuildRoster { members, .. } = &roster else {
+            return;
+        };
+        for member in members {
+            if let Some((endpoint, _)) = self.session_of(member.account_id) {
+                self.send(endpoint, &roster);
+            }
+        }
+    }
+
+    // Apply a change to the guilds and persist the result
+    pub(super) fn update_guilds<T>(&self, change: impl FnOnce(&mut Guilds) -> T) -> T {
+        let mut guilds = self.game_state.guilds.write().unwrap();
+        let result = change(&mut guilds);
+        self.save(Guilds::STORAGE_KEY, &*guilds);
+        result
+    }
+}
@@ -0,0 +1,451 @@
+// Both ends of an upgrade (see `crate::handoff`): the running server waiting
+// for a successor and handing its state over, and the successor letting
+// players resume the sessions it took over.
+use super::shards::ShardCommand;
+use super::{Server, Signal, Signals};
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::handoff::{Handoff, Session, SECRET_FILE};
+use crate::parties::PartyId;
+use crate::protocol::{ClientMessage, DisconnectReason, ErrorCode, LocalizedText};
+use crate::rooms::RoomId;
+use crate::state::Player;
+use crate::webhooks::WebhookEvent;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// How long a connecting successor has to send the secret
+const SECRET_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Wait on `addr` for a successor. The first one to send the secret written to
+// `data_dir` gets the state, and the listener closes so the successor can wait
+// on the same address for the upgrade after.
+pub(super) fn spawn_listener(signals: Signals, addr: &str, data_dir: &Path) -> io::Result<()> {
+    let secret = Uuid::new_v4().to_string();
+    fs::create_dir_all(data_dir)?;
+    fs::write(data_dir.join(SECRET_FILE), &secret)?;
+    let listener = TcpListener::bind(addr)?;
+    println!("Waiting for a successor on {}", addr);
+    thread::spawn(move || {
+        let successor = loop {
+            let Ok((stream, _)) = listener.accept() else {
+                continue;
+            };
+            match read_secret(&stream) {
+                Ok(sent) if sent == secret => break stream,
+                _ => println!("Refused a handoff without the right secret"),
+            }
+        };
+        drop(listener);
+        signals.send(Signal::HandOff(successor)).ok();
+    });
+    Ok(())
+}
+
+fn read_secret(stream: &TcpStream) -> io::Result<String> {
+    stream.set_read_timeout(Some(SECRET_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+impl Server {
+    // Give the state to the successor, then send every player over to it.
+    // Returns whether the successor got it; if not, this server carries on.
+    pub(super) fn hand_off(&mut self, mut successor: &TcpStream) -> bool {
+        self.save_worlds();
+        let handoff = self.handoff_state();
+        let sent = serde_json::to_writer(successor, &handoff)
+            .map_err(io::Error::from)
+            .and_then(|()| successor.flush());
+        if let Err(e) = sent {
+            eprintln!("Handoff failed, carrying on: {}", e);
+            return false;
+        }
+        println!("Handed {} sessions to a successor", handoff.sessions.len());
+        let endpoints: Vec<Endpoint> = self.endpoints.keys().copied().collect();
+        for endpoint in endpoints {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::Upgrading,
+                LocalizedText::new("disconnect.upgrading"),
+            );
+        }
+        true
+    }
+
+    pub(super) fn handoff_state(&self) -> Handoff {
+        // Copied out first so no player stays locked while the shards are read
+        let players: Vec<Player> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.joined)
+            .map(|p| p.clone())
+            .collect();
+        let sessions = players.iter().map(|p| self.session(p)).collect();
+        // Worlds are loaded again from disk as players come back to them
+        let mut rooms = self.game_state.rooms.read().unwrap().clone();
+        for room_id in self.world_rooms() {
+            rooms.remove(room_id);
+        }
+        Handoff {
+            next_player_id: self.game_state.player_ids.read().unwrap().next(),
+            rooms,
+            parties: self.game_state.parties.read().unwrap().clone(),
+            chat_history: self.game_state.chat_history.read().unwrap().clone(),
+            sessions,
+        }
+    }
+
+    pub(super) fn session(&self, p: &Player) -> Session {
+        Session {
+            token: p.session_token.clone(),
+            player_id: p.id,
+            uuid: p.uuid,
+            name: p.name.clone(),
+            appearance: p.appearance.clone(),
+            account: p.account,
+            steam_id: p.steam_id,
+            role: p.role,
+            room: p.room,
+            position: self.game_state.position(p),
+            team: p.team,
+            spectating: p.spectating,
+            muted: p.muted.clone(),
+            party: p.party,
+            last_nonce: p.last_nonce,
+        }
+    }
+
+    // Only servers that can hand over, fail over or migrate sessions have a
+    // use for one, and
+    // only clients that sent `Hello` know the message
+    pub(super) fn send_session_token(&self, player_id: usize) {
+        if self.config.handoff.listen_addr.is_none()
+            && self.replicate.is_none()
+            && !self.config.migration.enabled
+        {
+            return;
+        }
+        let Some((endpoint, token)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.endpoint, p.session_token.clone()))
+        else {
+            return;
+        };
+        self.send(endpoint, &ClientMessage::SessionToken { token });
+        self.send_standby_addr(player_id);
+    }
+
+    // The connection started out as a new player; it becomes the resumed
+    // session's player instead, back in its room, party and account
+    pub(super) fn on_resume(&mut self, endpoint: Endpoint, token: &str) {
+        let Some(&stand_in) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let Some((wire_format, snapshot_format, untouched)) =
+            self.game_state.players.get(&stand_in).map(|p| {
+                (
+                    p.wire_format,
+                    p.snapshot_format,
+                    p.room.is_none() && p.party.is_none() && p.account.is_none(),
+                )
+            })
+        else {
+            return;
+        };
+        if !untouched || self.authenticating.contains(&endpoint) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.resume_first"),
+            );
+            return;
+        }
+        if let Some(player_id) = self.migratable(token) {
+            self.retire_stand_in(endpoint, stand_in);
+            self.migrate_player(endpoint, player_id, wire_format, snapshot_format);
+            return;
+        }
+        let Some(session) = self.resumable.remove(token) else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.no_session"),
+            );
+            return;
+        };
+        self.retire_stand_in(endpoint, stand_in);
+        println!("Player {} resumed their session", session.player_id);
+        self.restore_session(endpoint, session, wire_format, snapshot_format);
+    }
+
+    // Make the connection the session's player, back in its room, party and
+    // account
+    pub(super) fn restore_session(
+        &mut self,
+        endpoint: Endpoint,
+        mut session: Session,
+        wire_format: WireFormat,
+        snapshot_format: Option<SnapshotFormat>,
+    ) {
+        // Someone else has the id, e.g. after a handoff that lost count: better
+        // a new one, outside the session's party, than two players sharing it
+        if self.game_state.players.contains_key(&session.player_id) {
+            let Some(fresh) = self.game_state.player_ids.write().unwrap().allocate() else {
+                self.disconnect(
+                    endpoint,
+                    DisconnectReason::ServerFull,
+                    LocalizedText::new("disconnect.server_full"),
+                );
+                return;
+            };
+            println!(
+                "Player id {} is taken; the session carries on as player {}",
+                session.player_id, fresh
+            );
+            session.player_id = fresh;
+            session.party = None;
+        } else {
+            self.game_state
+                .player_ids
+                .write()
+                .unwrap()
+                .claim(session.player_id);
+        }
+        let id = session.player_id;
+        let mut player = Player::new(id, endpoint, wire_format);
+        player.snapshot_format = snapshot_format;
+        player.joined = true;
+        player.session_token = session.token;
+        if !session.uuid.is_nil() {
+            player.uuid = session.uuid;
+        }
+        // Someone new may have taken the name while the session was away
+        player.name = session
+            .name
+            .filter(|name| !self.name_taken(session.room, name, id));
+        player.appearance = session.appearance;
+        player.steam_id = session.steam_id;
+        player.role = session.role;
+        player.muted = session.muted;
+        player.last_nonce = session.last_nonce;
+        self.game_state.players.insert(id, player);
+        self.endpoints.insert(endpoint, id);
+        self.send(endpoint, &ClientMessage::AssignPlayerId { id });
+        self.send_identity(id);
+        self.player_joined(id, endpoint);
+
+        // Logging back in also catches bans placed on the account meanwhile
+        let account = session.account.and_then(|account_id| {
+            let accounts = self.game_state.accounts.read().unwrap();
+            let username = accounts.by_id(account_id)?.username.clone();
+            Some((account_id, username))
+        });
+        if let Some(account) = account {
+            self.log_in(endpoint, id, account);
+            if !self.endpoints.contains_key(&endpoint) {
+                self.release_session(id, session.room, session.party);
+                return;
+            }
+        }
+
+        let room = session
+            .room
+            .filter(|&room_id| self.game_state.rooms.read().unwrap().get(room_id).is_some());
+        match room {
+            Some(room_id) => {
+                self.enter_room(endpoint, id, room_id);
+                if let Some(mut player) = self.game_state.players.get_mut(&id) {
+                    player.team = session.team;
+                    player.spectating = session.spectating;
+                }
+                self.announce_team(id);
+                if let Some((x, y)) = session.position {
+                    let command = ShardCommand::Move {
+                        room,
+                        player_id: id,
+                        x,
+                        y,
+                    };
+                    self.shards.send(room, command);
+                }
+            }
+            None => {
+                self.introduce(id);
+                self.update_presence(id);
+            }
+        }
+
+        let in_party = session.party.filter(|&party_id| {
+            self.game_state
+                .parties
+                .read()
+                .unwrap()
+                .get(party_id)
+                .is_some_and(|party| party.members.contains(&id))
+        });
+        if let Some(party_id) = in_party {
+            if let Some(mut player) = self.game_state.players.get_mut(&id) {
+                player.party = Some(party_id);
+            }
+            self.party_changed(party_id);
+        }
+    }
+
+    // Forget the player a connection started out as, without the goodbyes
+    // `remove_player` says: the connection itself stays
+    fn retire_stand_in(&mut self, endpoint: Endpoint, stand_in: usize) {
+        self.endpoints.remove(&endpoint);
+        let Some((_, player)) = self.game_state.players.remove(&stand_in) else {
+            return;
+        };
+        self.game_state
+            .player_ids
+            .write()
+            .unwrap()
+            .release(stand_in);
+        if player.joined {
+            self.remove_member(player.room, stand_in);
+            self.game_state.rebuild_roster();
+            self.webhooks.notify(WebhookEvent::PlayerLeft {
+                player_id: stand_in,
+            });
+        }
+    }
+
+    // Once a second: after `handoff.resume_secs`, let go of the sessions
+    // nobody came back for
+    pub(super) fn expire_sessions(&mut self) {
+        match self.resume_until {
+            Some(until) if Instant::now() >= until => self.resume_until = None,
+            _ => return,
+        }
+        let sessions: Vec<Session> = self.resumable.drain().map(|(_, s)| s).collect();
+        if !sessions.is_empty() {
+            println!("{} sessions were not resumed", sessions.len());
+        }
+        for session in sessions {
+            self.release_session(session.player_id, session.room, session.party);
+        }
+    }
+
+    // A joined player's connection dropped: keep the player in the game for
+    // `migration.grace_secs`, for a new connection to take over
+    pub(super) fn hold_player(&mut self, endpoint: Endpoint) -> bool {
+        let migration = &self.config.migration;
+        if !migration.enabled || endpoint.is_local() {
+            return false;
+        }
+        let Some(&id) = self.endpoints.get(&endpoint) else {
+            return false;
+        };
+        if !self.game_state.players.get(&id).is_some_and(|p| p.joined) {
+            return false;
+        }
+        let grace = migration.grace_secs;
+        self.detach(endpoint);
+        self.held
+            .insert(id, Instant::now() + Duration::from_secs(grace));
+        println!("Holding player {} for {}s to resume", id, grace);
+        true
+    }
+
+    // Once a second: held players nobody came back for leave the game
+    pub(super) fn expire_held(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<usize> = self
+            .held
+            .iter()
+            .filter(|&(_, &until)| now >= until)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            println!("Player {} was not resumed", id);
+            self.forget_player(id);
+        }
+    }
+
+    // The joined player, held or still connected, a `Resume` with `token`
+    // moves to its connection
+    fn migratable(&self, token: &str) -> Option<usize> {
+        if !self.config.migration.enabled {
+            return None;
+        }
+        self.game_state
+            .players
+            .iter()
+            .find(|p| p.joined && p.session_token == token)
+            .map(|p| p.id)
+    }
+
+    // Rebind the player to `endpoint`, in the same room and position, closing
+    // the connection it had if that is still open
+    fn migrate_player(
+        &mut self,
+        endpoint: Endpoint,
+        player_id: usize,
+        wire_format: WireFormat,
+        snapshot_format: Option<SnapshotFormat>,
+    ) {
+        let Some(old) = self.game_state.players.get(&player_id).map(|p| p.endpoint) else {
+            return;
+        };
+        if self.held.remove(&player_id).is_none() {
+            self.close_connection(
+                old,
+                DisconnectReason::Migrated,
+                LocalizedText::new("disconnect.migrated"),
+            );
+            self.detach(old);
+        }
+        let player = {
+            let Some(mut player) = self.game_state.players.get_mut(&player_id) else {
+                return;
+            };
+            player.endpoint = endpoint;
+            player.wire_format = wire_format;
+            player.snapshot_format = snapshot_format;
+            player.last_seen = Instant::now();
+            player.clone()
+        };
+        self.endpoints.insert(endpoint, player_id);
+        self.rebind_member(&player);
+        println!("Player {} moved to {:?}", player_id, endpoint);
+        self.send(endpoint, &ClientMessage::AssignPlayerId { id: player_id });
+        self.send_identity(player_id);
+    }
+
+    // Take a session out of the room and party it was handed over in
+    fn release_session(&mut self, player_id: usize, room: Option<RoomId>, party: Option<PartyId>) {
+        self.game_state
+            .player_ids
+            .write()
+            .unwrap()
+            .release(player_id);
+        if let Some(room_id) = room {
+            self.remove_from_room(room_id, player_id);
+        }
+        let inviting = self
+            .game_state
+            .parties
+            .write()
+            .unwrap()
+            .forget_invites(player_id);
+        for party_id in inviting {
+            self.party_changed(party_id);
+        }
+        if let Some(party_id) = party {
+            self.drop_from_party(player_id, party_id);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// `/healthz` and `/readyz`, open to anyone who can reach them: probes and
+// watchdogs don't carry tokens, and nothing here is secret. Both answer 200
+// or 503 with the numbers behind the verdict.
+use crate::health::HealthConfig;
+use crate::state::GameState;
+use crate::storage::Storage;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone)]
+pub(super) struct Probe {
+    pub(super) game_state: Arc<GameState>,
+    pub(super) storage: Storage,
+    pub(super) config: HealthConfig,
+}
+
+pub(super) fn routes<S>(probe: Probe) -> Router<S> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(probe)
+}
+
+// Serve the probes alone on `addr`
+pub(super) fn spawn_health(addr: &str, probe: Probe) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    println!("Health checks on {}", addr);
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let app: Router = routes(probe);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            eprintln!("Health checks stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+// Alive as long as the game loop keeps ticking
+async fn healthz(State(probe): State<Probe>) -> (StatusCode, Json<Value>) {
+    let health = &probe.game_state.health;
+    let since = health.since_last_tick();
+    let alive = since < Duration::from_secs(probe.config.stall_secs);
+    let body = json!({
+        "status": if alive { "ok" } else { "stalled" },
+        "last_tick_ms": since.as_millis() as u64,
+        "tick_lag_ms": health.tick_lag().as_millis() as u64,
+        "tick_cost_ms": health.tick_cost().as_millis() as u64,
+        "shedding": health.shedding(),
+        "overruns": health.overruns(),
+    });
+    (status(alive), Json(body))
+}
+
+// Ready for players: alive, keeping up, able to write its stores and not
+// draining for a restart
+async fn readyz(State(probe): State<Probe>) -> (StatusCode, Json<Value>) {
+    let health = &probe.game_state.health;
+    let since = health.since_last_tick();
+    let lag = health.tick_lag();
+    let storage = probe.storage.clone();
+    let storage = tokio::task::spawn_blocking(move || storage.probe())
+        .await
+        .map_err(io::Error::other)
+        .and_then(|probed| probed)
+        .map_err(|e| e.to_string())
+        .and(health.storage_error().map_or(Ok(()), Err));
+
+    let mut problems = Vec::new();
+    if since >= Duration::from_secs(probe.config.stall_secs) {
+        problems.push("the game loop is stalled".to_string());
+    }
+    if lag > Duration::from_millis(probe.config.max_tick_lag_ms) {
+        problems.push(format!("ticks run {}ms late", lag.as_millis()));
+    }
+    if let Err(e) = &storage {
+        problems.push(format!("storage: {}", e));
+    }
+    if health.shedding() > 0 {
+        problems.push(format!("overloaded, shedding {} steps", health.shedding()));
+    }
+    if health.draining() {
+        problems.push("draining for a restart".to_string());
+    }
+    let body = json!({
+        "ready": problems.is_empty(),
+        "problems": problems,
+        "last_tick_ms": since.as_millis() as u64,
+        "tick_lag_ms": lag.as_millis() as u64,
+        "tick_cost_ms": health.tick_cost().as_millis() as u64,
+        "shedding": health.shedding(),
+        "overruns": health.overruns(),
+        "storage": storage.err().unwrap_or_else(|| "ok".to_string()),
+        "players": probe.game_state.roster().len(),
+        "connections": probe.game_state.players.len(),
+        "draining": health.draining(),
+    });
+    (status(problems.is_empty()), Json(body))
+}
+
+fn status(healthy: bool) -> StatusCode {
+    if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
@@ -0,0 +1,191 @@
+// Room hosts: the player running a player-created room (see `Room::host`).
+// The host may kick members, change the room's settings and start its match
+// over; when they leave, the room passes to another member instead of being
+// left without anyone to run it.
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use crate::room_settings::RoomSettings;
+use crate::rooms::RoomId;
+
+impl Server {
+    // Tell the room who hosts it now
+    pub(super) fn host_changed(&self, room_id: RoomId, host: Option<usize>) {
+        match host {
+            Some(host) => println!("Player {} now hosts room {}", host, room_id),
+            None => println!("Room {} has no host", room_id),
+        }
+        let message = ClientMessage::RoomHost {
+            room_id,
+            player_id: host,
+        };
+        self.send_room(Some(room_id), message);
+    }
+
+    // Catch a player who just entered a room up on who hosts it
+    pub(super) fn send_host(&self, player_id: usize) {
+        let Some((Some(room_id), endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let host = self
+            .game_state
+            .rooms
+            .read()
+            .unwrap()
+            .get(room_id)
+            .and_then(|room| room.host);
+        if host.is_some() {
+            let message = ClientMessage::RoomHost {
+                room_id,
+                player_id: host,
+            };
+            self.send(endpoint, &message);
+        }
+    }
+
+    // The sender and the room they host, or `None` having told them they
+    // don't host one
+    fn hosted_room(&self, endpoint: Endpoint) -> Option<(usize, RoomId)> {
+        let &own_id = self.endpoints.get(&endpoint)?;
+        let room_id = self.game_state.players.get(&own_id).and_then(|p| p.room);
+        let hosts = room_id.is_some_and(|room_id| {
+            let rooms = self.game_state.rooms.read().unwrap();
+            rooms.get(room_id).and_then(|room| room.host) == Some(own_id)
+        });
+        match room_id {
+            Some(room_id) if hosts => Some((own_id, room_id)),
+            _ => {
+                self.reject(
+                    endpoint,
+                    ErrorCode::PermissionDenied,
+                    LocalizedText::new("error.not_host"),
+                );
+                None
+            }
+        }
+    }
+
+    pub(super) fn on_kick_from_room(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some((own_id, room_id)) = self.hosted_room(endpoint) else {
+            return;
+        };
+        let target = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.room == Some(room_id))
+            .map(|p| (p.endpoint, p.name.clone()));
+        let problem = match &target {
+            _ if player_id == own_id => Some("you can't kick yourself".to_string()),
+            None => Some(format!("player {} is not in room {}", player_id, room_id)),
+            // Names only have to be unique within a room
+            Some((_, Some(name))) if self.name_taken(None, name, player_id) => Some(format!(
+                "`{}` is taken in the lobby; player {} can't be sent there",
+                name, player_id
+            )),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        let Some((target_endpoint, _)) = target else {
+            return;
+        };
+        if let Some(room) = self.game_state.rooms.write().unwrap().get_mut(room_id) {
+            room.kicked.insert(player_id);
+        }
+        self.leave_current_room(target_endpoint, player_id);
+        self.introduce(player_id);
+        self.update_presence(player_id);
+        println!(
+            "Player {} kicked player {} from room {}",
+            own_id, player_id, room_id
+        );
+    }
+
+    pub(super) fn on_update_room_settings(&mut self, endpoint: Endpoint, settings: RoomSettings) {
+        let Some((own_id, room_id)) = self.hosted_room(endpoint) else {
+            return;
+        };
+        let map =
+            match settings.validate(&self.config.room_settings, &self.room_maps, &self.scenarios) {
+                Ok(map) => map,
+                Err(e) => {
+                    self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                    return;
+                }
+            };
+        let mut rooms = self.game_state.rooms.write().unwrap();
+        let Some(room) = rooms.get_mut(room_id) else {
+            return;
+        };
+        let members = room.members.len();
+        if settings
+            .max_players
+            .is_some_and(|max| (max as usize) < members)
+        {
+            drop(rooms);
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("room {} already has {} players", room_id, members),
+            );
+            return;
+        }
+        let new_map = room.settings.map != settings.map || room.settings.mode != settings.mode;
+        let new_scenario = room.settings.scenario != settings.scenario;
+        room.settings = settings.clone();
+        drop(rooms);
+        println!("Player {} changed the settings of room {}", own_id, room_id);
+        let room = Some(room_id);
+        self.send_room(
+            room,
+            ClientMessage::RoomSettingsChanged {
+                room_id,
+                settings: settings.clone(),
+            },
+        );
+        if new_map {
+            self.install_map(room, map);
+            if let Some(name) = settings.map {
+                self.send_room(room, ClientMessage::MapChanged { name });
+            }
+            self.broadcast_flags(room);
+            self.broadcast_hills(room);
+            self.match_started(room);
+        }
+        if new_scenario {
+            self.start_scenario(room_id);
+        }
+    }
+
+    pub(super) fn on_start_match(&mut self, endpoint: Endpoint) {
+        let Some((own_id, room_id)) = self.hosted_room(endpoint) else {
+            return;
+        };
+        let room = Some(room_id);
+        let scenario = self.scenario_runs.contains_key(&room_id);
+        if !self.ctf.contains_key(&room) && !self.koth.contains_key(&room) && !scenario {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("room {} has no match to start", room_id),
+            );
+            return;
+        }
+        println!(
+            "Player {} started the match in room {} over",
+            own_id, room_id
+        );
+        self.restart_round(room);
+        if scenario {
+            self.start_scenario(room_id);
+        }
+    }
+}
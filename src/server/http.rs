@@ -0,0 +1,340 @@
+// JSON admin API for web dashboards. Player and room lists come straight from
+// `GameState`; everything else goes through the admin command queue.
+use super::admin::submit;
+use super::health::{self, Probe};
+use super::Signals;
+use crate::admin::{AdminCommand, AdminResult};
+use crate::analytics::Analytics;
+use crate::history::MatchHistory;
+use crate::inspect::{PlayerInfo, RoomInfo};
+use crate::passwords;
+use crate::protocol::LocalizedText;
+use crate::roles::Role;
+use crate::state::GameState;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+#[derive(Clone)]
+struct Api {
+    signals: Signals,
+    game_state: Arc<GameState>,
+    token_hash: Arc<String>,
+    // The token once it has matched `token_hash`, so later requests skip argon2
+    verified: Arc<Mutex<Option<String>>>,
+    // Wrong tokens are hashed one at a time, however many requests bring them
+    verifying: Arc<Semaphore>,
+}
+
+#[derive(Deserialize)]
+struct MessageBody {
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GiveBody {
+    item: String,
+    quantity: Option<u32>,
+}
+
+// A message key from the translation files, with its parameters
+#[derive(Deserialize)]
+struct AnnounceBody {
+    key: String,
+    #[serde(default)]
+    params: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct StateQuery {
+    pointer: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DrainQuery {
+    seconds: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TimeScaleQuery {
+    scale: f32,
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult = Result<Json<serde_json::Value>, ApiError>;
+
+// Serve the API on `addr`; every request but the health checks needs the
+// bearer token matching `token_hash`, and commands run as `Role::Owner`
+pub fn spawn_http_api(
+    signals: Signals,
+    game_state: Arc<GameState>,
+    addr: &str,
+    token_hash: String,
+    probe: Probe,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    println!("HTTP API listening on {}", addr);
+
+    let api = Api {
+        signals,
+        game_state,
+        token_hash: Arc::new(token_hash),
+        verified: Arc::default(),
+        verifying: Arc::new(Semaphore::new(1)),
+    };
+    let app = Router::new()
+        .route("/players", get(players))
+        .route("/players/{id}/kick", post(kick))
+        .route("/players/{id}/ban", post(ban))
+        .route("/accounts/{account}", get(export).delete(erase))
+        .route("/accounts/{account}/mail", post(mail))
+        .route("/accounts/{account}/items", post(give))
+        .route("/rooms", get(rooms))
+        .route("/state", get(state))
+        .route("/stats", get(stats))
+        .route("/matches", get(matches))
+        .route("/broadcast", post(broadcast))
+        .route("/announce", post(announce))
+        .route("/config/reload", post(reload))
+        .route("/drain", post(drain).delete(cancel_drain))
+        .route("/pause", post(pause).delete(resume))
+        .route("/time-scale", post(time_scale))
+        .layer(middleware::from_fn_with_state(api.clone(), authenticate))
+        .merge(health::routes(probe))
+        .with_state(api);
+
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            eprintln!("HTTP API stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn authenticate(State(api): State<Api>, request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let authorized = match token {
+        Some(token) => verify_token(&api, token).await,
+        None => false,
+    };
+    if !authorized {
+        return ApiError(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong token".to_string(),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+async fn verify_token(api: &Api, token: String) -> bool {
+    let known = |token: &str| {
+        let verified = api.verified.lock().unwrap();
+        verified
+            .as_deref()
+            .is_some_and(|verified| passwords::same_bytes(verified.as_bytes(), token.as_bytes()))
+    };
+    if known(&token) {
+        return true;
+    }
+    let Ok(_permit) = api.verifying.acquire().await else {
+        return false;
+    };
+    // Verified by another request while this one waited
+    if known(&token) {
+        return true;
+    }
+    let token_hash = api.token_hash.clone();
+    let checked = token.clone();
+    let matches =
+        tokio::task::spawn_blocking(move || passwords::verify_password(&checked, &token_hash))
+            .await
+            .unwrap_or(false);
+    if matches {
+        *api.verified.lock().unwrap() = Some(token);
+    }
+    matches
+}
+
+async fn players(State(api): State<Api>) -> Json<Vec<PlayerInfo>> {
+    let mut list: Vec<PlayerInfo> = api
+        .game_state
+        .players
+        .iter()
+        .map(|p| PlayerInfo::new(&api.game_state, &p))
+        .collect();
+    list.sort_by_key(|p| p.id);
+    Json(list)
+}
+
+async fn rooms(State(api): State<Api>) -> Json<Vec<RoomInfo>> {
+    let rooms = api.game_state.rooms.read().unwrap();
+    Json(rooms.iter().map(RoomInfo::from).collect())
+}
+
+// Lifetime totals for every identity that has ever joined
+async fn stats(State(api): State<Api>) -> Json<Analytics> {
+    Json(api.game_state.analytics.read().unwrap().clone())
+}
+
+// The latest rounds and scenarios, oldest first
+async fn matches(State(api): State<Api>) -> Json<MatchHistory> {
+    Json(api.game_state.match_history.read().unwrap().clone())
+}
+
+// `GET /state?pointer=/players/3` returns part of the `inspect` dump
+async fn state(State(api): State<Api>, Query(query): Query<StateQuery>) -> ApiResult {
+    let Json(response) = run(&api, AdminCommand::Inspect(query.pointer)).await?;
+    let text = response["result"].as_str().unwrap_or_default();
+    serde_json::from_str(text)
+        .map(Json)
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn kick(
+    State(api): State<Api>,
+    Path(player_id): Path<usize>,
+    body: Option<Json<MessageBody>>,
+) -> ApiResult {
+    let message = body.and_then(|Json(body)| body.message);
+    run(&api, AdminCommand::Kick { player_id, message }).await
+}
+
+async fn ban(
+    State(api): State<Api>,
+    Path(player_id): Path<usize>,
+    body: Option<Json<MessageBody>>,
+) -> ApiResult {
+    let message = body.and_then(|Json(body)| body.message);
+    run(&api, AdminCommand::Ban { player_id, message }).await
+}
+
+async fn broadcast(State(api): State<Api>, Json(body): Json<MessageBody>) -> ApiResult {
+    let message = body
+        .message
+        .ok_or_else(|| ApiError(StatusCode::BAD_REQUEST, "missing `message`".to_string()))?;
+    run(&api, AdminCommand::Broadcast(message)).await
+}
+
+// `GET /accounts/{account}` returns everything stored about the account
+async fn export(State(api): State<Api>, Path(account): Path<String>) -> ApiResult {
+    let Json(response) = run(&api, AdminCommand::Export(account)).await?;
+    let text = response["result"].as_str().unwrap_or_default();
+    serde_json::from_str(text)
+        .map(Json)
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn erase(State(api): State<Api>, Path(account): Path<String>) -> ApiResult {
+    run(&api, AdminCommand::Erase(account)).await
+}
+
+async fn announce(State(api): State<Api>, Json(body): Json<AnnounceBody>) -> ApiResult {
+    let text = body
+        .params
+        .iter()
+        .fold(LocalizedText::new(&body.key), |text, (name, value)| {
+            text.with(name, value)
+        });
+    run(&api, AdminCommand::Announce(text)).await
+}
+
+// `account` is an account id or a username
+async fn mail(
+    State(api): State<Api>,
+    Path(account): Path<String>,
+    Json(body): Json<MessageBody>,
+) -> ApiResult {
+    let message = body
+        .message
+        .ok_or_else(|| ApiError(StatusCode::BAD_REQUEST, "missing `message`".to_string()))?;
+    run(&api, AdminCommand::Mail { account, message }).await
+}
+
+async fn give(
+    State(api): State<Api>,
+    Path(account): Path<String>,
+    Json(body): Json<GiveBody>,
+) -> ApiResult {
+    let quantity = body.quantity.unwrap_or(1);
+    if quantity == 0 {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "`quantity` must be positive".to_string(),
+        ));
+    }
+    let command = AdminCommand::Give {
+        account,
+        item: body.item,
+        quantity,
+    };
+    run(&api, command).await
+}
+
+async fn reload(State(api): State<Api>) -> ApiResult {
+    run(&api, AdminCommand::Reload).await
+}
+
+// `POST /drain?seconds=N` starts a drain; `DELETE /drain` calls it off
+async fn drain(State(api): State<Api>, Query(query): Query<DrainQuery>) -> ApiResult {
+    run(&api, AdminCommand::Drain(query.seconds)).await
+}
+
+async fn cancel_drain(State(api): State<Api>) -> ApiResult {
+    run(&api, AdminCommand::DrainCancel).await
+}
+
+// `POST /pause` freezes the simulation; `DELETE /pause` lets it run again
+async fn pause(State(api): State<Api>) -> ApiResult {
+    run(&api, AdminCommand::Pause).await
+}
+
+async fn resume(State(api): State<Api>) -> ApiResult {
+    run(&api, AdminCommand::Resume).await
+}
+
+// `POST /time-scale?scale=0.5` runs the simulation at half speed
+async fn time_scale(State(api): State<Api>, Query(query): Query<TimeScaleQuery>) -> ApiResult {
+    run(&api, AdminCommand::TimeScale(query.scale)).await
+}
+
+// Run a command on the game loop without blocking the runtime
+async fn run(api: &Api, command: AdminCommand) -> ApiResult {
+    let signals = api.signals.clone();
+    let result: Option<AdminResult> =
+        tokio::task::spawn_blocking(move || submit(&signals, command, Role::Owner))
+            .await
+            .unwrap_or(None);
+    match result {
+        Some(Ok(text)) => Ok(Json(json!({ "result": text }))),
+        Some(Err(e)) => Err(ApiError(StatusCode::BAD_REQUEST, e)),
+        None => Err(ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the server is shutting down".to_string(),
+        )),
+    }
+}
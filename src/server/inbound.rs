@@ -0,0 +1,109 @@
+// The decoder stage: records and decodes every frame clients send, applies
+// inbound chaos, and hands the results to the game loop.
+use super::cluster::Transfer;
+use super::persistence::Persist;
+use super::Signal;
+use crate::capture::CaptureEvent;
+use crate::chaos::ChaosConfig;
+use crate::codec::{DecodeLimits, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::traffic::{Traffic, UNDECODABLE};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+pub enum Inbound {
+    Accepted(Endpoint),
+    Frame(Endpoint, Vec<u8>),
+    Disconnected(Endpoint),
+    // From the game loop: frames after this one use the negotiated format
+    Negotiated(Endpoint, WireFormat),
+    // From the game loop: the server closed the connection
+    Closed(Endpoint),
+    // A player another worker moved here, already in its wire format
+    Adopted(Endpoint, Box<Transfer>),
+}
+
+pub(super) struct Decoder {
+    pub(super) signals: UnboundedSender<Signal>,
+    pub(super) persist: UnboundedSender<Persist>,
+    pub(super) chaos: Option<ChaosConfig>,
+    // Used until a connection negotiates its own
+    pub(super) wire_format: WireFormat,
+    pub(super) limits: DecodeLimits,
+    pub(super) formats: HashMap<Endpoint, WireFormat>,
+    pub(super) traffic: Arc<Traffic>,
+}
+
+impl Decoder {
+    pub(super) async fn run(mut self, mut inbound: UnboundedReceiver<Inbound>) {
+        while let Some(event) = inbound.recv().await {
+            match event {
+                Inbound::Accepted(endpoint) => {
+                    self.record(endpoint, CaptureEvent::Connected(endpoint.to_string()));
+                    self.traffic.opened(endpoint);
+                    self.signals.send(Signal::Accepted(endpoint)).ok();
+                }
+                Inbound::Frame(endpoint, data) => self.on_frame(endpoint, data),
+                Inbound::Disconnected(endpoint) => {
+                    self.record(endpoint, CaptureEvent::Disconnected);
+                    self.formats.remove(&endpoint);
+                    self.traffic.closed(endpoint);
+                    self.signals.send(Signal::Disconnected(endpoint)).ok();
+                }
+                Inbound::Negotiated(endpoint, format) => {
+                    self.formats.insert(endpoint, format);
+                }
+                Inbound::Closed(endpoint) => {
+                    self.formats.remove(&endpoint);
+                    self.traffic.closed(endpoint);
+                }
+                Inbound::Adopted(endpoint, transfer) => {
+                    self.record(endpoint, CaptureEvent::Connected(endpoint.to_string()));
+                    self.formats.insert(endpoint, transfer.wire_format());
+                    self.traffic.opened(endpoint);
+                    self.signals.send(Signal::Adopted(endpoint, transfer)).ok();
+                }
+            }
+        }
+    }
+
+    fn record(&self, endpoint: Endpoint, event: CaptureEvent) {
+        self.persist
+            .send(Persist::Capture(endpoint.raw_id(), event))
+            .ok();
+    }
+
+    fn on_frame(&self, endpoint: Endpoint, data: Vec<u8>) {
+        self.record(endpoint, CaptureEvent::Frame(data.clone()));
+        let format = self
+            .formats
+            .get(&endpoint)
+            .copied()
+            .unwrap_or(self.wire_format);
+        let delays = match self.chaos.as_ref().filter(|c| c.inbound) {
+            Some(chaos) => chaos.plan(),
+            None => vec![Duration::ZERO],
+        };
+        for (copy, delay) in delays.into_iter().enumerate() {
+            // Decoded per copy, since a decode error can't be cloned
+            let message = format.decode_within(&data, &self.limits);
+            if copy == 0 {
+                let kind = message
+                    .as_ref()
+                    .map_or(UNDECODABLE, |message| message.into());
+                self.traffic.received(endpoint, kind, data.len());
+            }
+            if delay.is_zero() {
+                self.signals.send(Signal::Message(endpoint, message)).ok();
+            } else {
+                let signals = self.signals.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    signals.send(Signal::Message(endpoint, message)).ok();
+                });
+            }
+        }
+    }
+}
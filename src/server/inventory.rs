@@ -0,0 +1,50 @@
+// Inventories (see `crate::inventory`): putting items in and keeping the
+// player's client up to date with what they hold.
+use super::Server;
+use crate::accounts::AccountId;
+use crate::inventory::{ExchangeError, Inventories};
+use crate::mail::ItemGrant;
+use crate::protocol::ClientMessage;
+
+impl Server {
+    // Whether or not `account` is online; nothing is granted unless it all
+    // fits
+    pub(super) fn grant_items(
+        &self,
+        account: AccountId,
+        items: &[ItemGrant],
+    ) -> Result<(), ExchangeError> {
+        {
+            let mut inventories = self.game_state.inventories.write().unwrap();
+            inventories.add(&self.config.inventory, account, items)?;
+            self.save(Inventories::STORAGE_KEY, &*inventories);
+        }
+        self.send_inventory_of(account);
+        Ok(())
+    }
+
+    pub(super) fn send_inventory_of(&self, account: AccountId) {
+        let player_id = self
+            .game_state
+            .players
+            .iter()
+            .find(|p| p.joined && p.account == Some(account))
+            .map(|p| p.id);
+        if let Some(player_id) = player_id {
+            self.send_inventory(player_id);
+        }
+    }
+
+    pub(super) fn send_inventory(&self, player_id: usize) {
+        let Some((endpoint, account)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| Some((p.endpoint, p.account?)))
+        else {
+            return;
+        };
+        let items = self.game_state.inventories.read().unwrap().of(account);
+        self.send(endpoint, &ClientMessage::Inventory { items });
+    }
+}
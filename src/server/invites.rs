@@ -0,0 +1,149 @@
+// Inviting friends and party members into the inviter's room. An invite
+// holds a seat in a room that caps its players until it is accepted or runs
+// out, so strangers find the room full before an invitee does, and it lets
+// the invitee past the room's password.
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use crate::rooms::{Room, RoomId};
+use std::time::{Duration, Instant};
+
+// How long an invite holds its seat
+const INVITE_SECS: u64 = 60;
+
+impl Server {
+    pub(super) fn on_invite_to_room(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let (room_id, own_party, own_account, from_name) = self
+            .game_state
+            .players
+            .get(&own_id)
+            .map(|p| (p.room, p.party, p.account, p.name.clone()))
+            .unwrap_or_default();
+        let target = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined)
+            .map(|p| {
+                (
+                    p.endpoint,
+                    p.room,
+                    p.party,
+                    p.account,
+                    p.muted.contains(&own_id),
+                )
+            });
+        let close = target.is_some_and(|(_, _, party, account, _)| {
+            (own_party.is_some() && party == own_party)
+                || own_account.zip(account).is_some_and(|(me, them)| {
+                    self.game_state
+                        .friends
+                        .read()
+                        .unwrap()
+                        .are_friends(me, them)
+                })
+        });
+        let rooms = self.game_state.rooms.read().unwrap();
+        let room = room_id.and_then(|room_id| rooms.get(room_id));
+        let problem = match (room, target) {
+            (None, _) => Some("you can only invite into a room you're in".to_string()),
+            _ if player_id == own_id => Some("you can't invite yourself".to_string()),
+            (_, None) => Some(format!("player {} is not online", player_id)),
+            (Some(room), Some((_, in_room, ..))) if in_room == Some(room.id) => Some(format!(
+                "player {} is already in room {}",
+                player_id, room.id
+            )),
+            _ if !close => Some("you can only invite friends and party members".to_string()),
+            (Some(room), _) if room.kicked.contains(&player_id) => Some(format!(
+                "player {} was kicked from room {}",
+                player_id, room.id
+            )),
+            (Some(room), _) if self.seats_left(room, &[player_id]) == Some(0) => {
+                Some(format!("room {} is full", room.id))
+            }
+            _ => None,
+        };
+        let room_name = room.map(|room| room.name.clone()).unwrap_or_default();
+        drop(rooms);
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        let (Some(room_id), Some((target_endpoint, _, _, _, muted))) = (room_id, target) else {
+            return;
+        };
+        let expires = Instant::now() + Duration::from_secs(INVITE_SECS);
+        self.room_invites.insert((player_id, room_id), expires);
+        println!(
+            "Player {} invited player {} to room {}",
+            own_id, player_id, room_id
+        );
+        // As with party invites, an invite from someone muted goes nowhere
+        if !muted {
+            let message = ClientMessage::Invite {
+                room_id,
+                name: room_name,
+                from_id: own_id,
+                from_name,
+            };
+            self.send(target_endpoint, &message);
+        }
+    }
+
+    pub(super) fn on_accept_invite(&mut self, endpoint: Endpoint, room_id: RoomId) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.invited(own_id, room_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.no_invite").with("room", room_id),
+            );
+            return;
+        }
+        self.on_join_room(endpoint, room_id, None);
+    }
+
+    // Whether `player_id` holds a live invite to the room
+    pub(super) fn invited(&self, player_id: usize, room_id: RoomId) -> bool {
+        self.room_invites
+            .get(&(player_id, room_id))
+            .is_some_and(|&expires| expires > Instant::now())
+    }
+
+    // How many more of `movers` the room takes, leaving the seats held for
+    // everyone else invited; `None` when it takes any number
+    pub(super) fn seats_left(&self, room: &Room, movers: &[usize]) -> Option<usize> {
+        let max = room.settings.max_players? as usize;
+        let now = Instant::now();
+        let held = self
+            .room_invites
+            .iter()
+            .filter(|&(&(player_id, room_id), &expires)| {
+                room_id == room.id
+                    && expires > now
+                    && !movers.contains(&player_id)
+                    && !room.members.contains(&player_id)
+            })
+            .count();
+        Some(max.saturating_sub(room.members.len() + held))
+    }
+
+    // Once a second: invites that ran out, or whose invitee or room is gone
+    pub(super) fn prune_invites(&mut self) {
+        let now = Instant::now();
+        let rooms = self.game_state.rooms.read().unwrap();
+        self.room_invites
+            .retain(|&(player_id, room_id), &mut expires| {
+                expires > now
+                    && self.game_state.players.contains_key(&player_id)
+                    && rooms
+                        .get(room_id)
+                        .is_some_and(|room| !room.members.contains(&player_id))
+            });
+    }
+}
@@ -0,0 +1,109 @@
+// Token login: checking `TokenLogin` against the identity provider's keys and
+// keeping the provider's JWKS fresh
+use super::{Server, Signal};
+use crate::endpoint::Endpoint;
+use crate::jwt::JwtConfig;
+use crate::protocol::ErrorCode;
+use jsonwebtoken::jwk::JwkSet;
+use std::time::{Duration, Instant};
+
+// How soon a failed JWKS fetch is retried, unless refreshes are more frequent
+const JWKS_RETRY: Duration = Duration::from_secs(60);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl Server {
+    pub(super) fn on_token_login(&mut self, endpoint: Endpoint, token: &str) {
+        if !self.config.jwt.enabled() {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "token login is not enabled on this server",
+            );
+            return;
+        }
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.can_authenticate(endpoint) {
+            return;
+        }
+        let subject = match self.jwt.verify(token) {
+            Ok(subject) => subject,
+            Err(e) => {
+                self.reject(
+                    endpoint,
+                    ErrorCode::WrongPassword,
+                    format!("token refused: {}", e),
+                );
+                return;
+            }
+        };
+        let account = self.update_accounts(|accounts| {
+            let account = accounts.external_login(&subject);
+            (account.id, account.username.clone())
+        });
+        self.log_in(endpoint, player_id, account);
+    }
+
+    // Fetch the provider's key set when it is due; called once a second
+    pub(super) fn refresh_jwks(&mut self) {
+        let Some(url) = self.config.jwt.jwks_url.clone() else {
+            return;
+        };
+        if self.jwks_due.is_none_or(|due| Instant::now() < due) {
+            return;
+        }
+        // No fetch is due while one is in flight
+        self.jwks_due = None;
+        let signals = self.signals.clone();
+        tokio::task::spawn_blocking(move || {
+            let jwks = fetch_jwks(&url);
+            signals.send(Signal::Jwks(url, jwks)).ok();
+        });
+    }
+
+    pub(super) fn on_jwks(&mut self, url: String, jwks: Result<JwkSet, String>) {
+        if self.config.jwt.jwks_url.as_ref() != Some(&url) {
+            // The URL was changed by a reload while this was fetched
+            self.jwks_due = Some(Instant::now());
+            return;
+        }
+        let refresh = Duration::from_secs(self.config.jwt.jwks_refresh_secs);
+        let wait = match jwks {
+            Ok(jwks) => {
+                let keys = self.jwt.set_jwks(&jwks);
+                println!("Loaded {} signing keys from {}", keys, url);
+                refresh
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch the JWKS from {}: {}", url, e);
+                JWKS_RETRY.min(refresh)
+            }
+        };
+        self.jwks_due = Some(Instant::now() + wait);
+    }
+
+    // Apply reloaded token settings, dropping the old provider's keys if the
+    // JWKS moved
+    pub(super) fn reconfigure_jwt(&mut self, config: JwtConfig) {
+        if config.jwks_url != self.config.jwt.jwks_url {
+            self.jwt.set_jwks(&JwkSet { keys: Vec::new() });
+            // A fetch in flight sees the new URL when it lands and refetches
+            if self.jwks_due.is_some() {
+                self.jwks_due = Some(Instant::now());
+            }
+        }
+        self.jwt.reconfigure(config);
+    }
+}
+
+fn fetch_jwks(url: &str) -> Result<JwkSet, String> {
+    ureq::AgentBuilder::new()
+        .timeout(JWKS_TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| e.to_string())
+}
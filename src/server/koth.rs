@@ -0,0 +1,94 @@
+// King of the hill (see `crate::koth`): stepping each room's game once a tick
+// and telling its members who holds what and who is winning.
+use super::Server;
+use crate::koth::{Control, Hill, Koth, KothEvent};
+use crate::maps::MapState;
+use crate::protocol::ClientMessage;
+use crate::rooms::RoomId;
+use std::collections::HashMap;
+
+// A game for every room whose map has a `koth` section
+pub(super) fn games(maps: &HashMap<Option<RoomId>, MapState>) -> HashMap<Option<RoomId>, Koth> {
+    maps.iter()
+        .filter_map(|(&room, state)| Some((room, Koth::new(state.map.koth.clone()?))))
+        .collect()
+}
+
+fn control_message(hill: &Hill, control: Control) -> ClientMessage {
+    ClientMessage::HillControl {
+        name: hill.name.clone(),
+        team: match control {
+            Control::Held(team) => Some(team),
+            Control::Empty | Control::Contested => None,
+        },
+        contested: control == Control::Contested,
+    }
+}
+
+impl Server {
+    // Once a tick, after the triggers
+    pub(super) fn check_hills(&mut self) {
+        let rooms: Vec<Option<RoomId>> = self.koth.keys().copied().collect();
+        for room in rooms {
+            if !self.round_playing(room) {
+                continue;
+            }
+            let occupants = self.occupants(room);
+            let Some(game) = self.koth.get_mut(&room) else {
+                continue;
+            };
+            let events = game.step(&occupants, self.config.snapshot_rate);
+            for event in events {
+                self.on_koth(room, event);
+            }
+        }
+    }
+
+    fn on_koth(&mut self, room: Option<RoomId>, event: KothEvent) {
+        let Some(game) = self.koth.get(&room) else {
+            return;
+        };
+        match event {
+            KothEvent::Control { index, control } => {
+                let message = control_message(&game.map.hills[index], control);
+                self.send_room(room, message);
+            }
+            KothEvent::Scored { team, score } => {
+                self.post_score(room, team, score);
+            }
+            KothEvent::Won { team } => self.end_round(room, team),
+        }
+    }
+
+    // Who holds each hill, to the whole room, as a round starts
+    pub(super) fn broadcast_hills(&self, room: Option<RoomId>) {
+        let Some(game) = self.koth.get(&room) else {
+            return;
+        };
+        for (hill, control) in game.hills() {
+            self.send_room(room, control_message(hill, control));
+        }
+    }
+
+    // Catch a player who just entered a room up on its hills and scores
+    pub(super) fn send_hills(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let Some(game) = self.koth.get(&room) else {
+            return;
+        };
+        for (hill, control) in game.hills() {
+            self.send(endpoint, &control_message(hill, control));
+        }
+        for (team, score) in game.scores() {
+            self.send(endpoint, &ClientMessage::TeamScore { team, score });
+        }
+    }
+}
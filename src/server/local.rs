@@ -0,0 +1,67 @@
+// The in-process transport: a client running in the same process as the
+// server exchanges frames with it over channels instead of sockets, so a game
+// can host its own server and play on it while remote players use TCP.
+use super::inbound::Inbound;
+use super::transport::Connections;
+use crate::endpoint::Endpoint;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+// A connection to an embedded server. Frames are encoded exactly as they
+// would be over the network; dropping the client disconnects it.
+pub struct LocalClient {
+    id: u64,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    frames: Receiver<Vec<u8>>,
+}
+
+impl LocalClient {
+    pub(super) fn connect(inbound: UnboundedSender<Inbound>, connections: Connections) -> Self {
+        let id = connections.next_local_id();
+        let (sender, frames) = mpsc::channel();
+        connections.add_local(id, sender);
+        inbound.send(Inbound::Accepted(Endpoint::Local(id))).ok();
+        LocalClient {
+            id,
+            inbound,
+            connections,
+            frames,
+        }
+    }
+
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::Local(self.id)
+    }
+
+    pub fn send(&self, data: &[u8]) {
+        self.inbound
+            .send(Inbound::Frame(self.endpoint(), data.to_vec()))
+            .ok();
+    }
+
+    // Wait for the next frame; `None` once the server has closed the connection
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.frames.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Result<Vec<u8>, TryRecvError> {
+        self.frames.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        self.frames.recv_timeout(timeout)
+    }
+}
+
+impl Drop for LocalClient {
+    fn drop(&mut self) {
+        // Like a socket the server closed, a closed local connection reports nothing
+        if self.connections.remove_local(self.id) {
+            self.inbound
+                .send(Inbound::Disconnected(self.endpoint()))
+                .ok();
+        }
+    }
+}
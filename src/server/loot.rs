@@ -0,0 +1,231 @@
+// Loot (see `crate::loot`): rolling tables where something dropped them,
+// handing out what they drop, and chests, items and shops players interact
+// with.
+use super::outbound::Outbound;
+use super::rounds::describe;
+use super::Server;
+use crate::accounts::AccountId;
+use crate::actions::Action;
+use crate::config::ServerConfig;
+use crate::endpoint::Endpoint;
+use crate::entities::Entity;
+use crate::inventory::ExchangeError;
+use crate::loot::{Delivery, LootTables, ITEM_ID_KEY, ITEM_KEY, ITEM_KIND, LOOT_KEY, QUANTITY_KEY};
+use crate::mail::{ItemGrant, ItemId};
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::rooms::RoomId;
+use crate::shops::SHOP_KEY;
+use std::collections::BTreeMap;
+use std::io;
+
+pub(super) fn load_loot(config: &ServerConfig) -> io::Result<Option<LootTables>> {
+    let Some(path) = &config.loot.file else {
+        return Ok(None);
+    };
+    let loot = LootTables::load(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("loot {}: {}", path.display(), e),
+        )
+    })?;
+    println!(
+        "Loaded {} loot tables from {}",
+        loot.tables.len(),
+        path.display()
+    );
+    Ok(Some(loot))
+}
+
+pub(super) fn describe_items(items: &[ItemGrant]) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|grant| format!("{} x {}", grant.quantity, grant.item))
+        .collect();
+    items.join(", ")
+}
+
+impl Server {
+    // Roll `table` at `at`, for `player_id` if they opened it. Returns what
+    // dropped.
+    pub(super) fn drop_loot(
+        &mut self,
+        room: Option<RoomId>,
+        table: &str,
+        at: (f32, f32),
+        player_id: Option<usize>,
+    ) -> Result<Vec<ItemGrant>, String> {
+        if let Some(room_id) = room {
+            if self.game_state.rooms.read().unwrap().get(room_id).is_none() {
+                return Err(format!("no room {}", room_id));
+            }
+        }
+        if !(at.0.is_finite() && at.1.is_finite()) {
+            return Err("loot needs a finite position".to_string());
+        }
+        let loot = self.loot.as_ref().ok_or("this server has no loot tables")?;
+        if !loot.tables.contains_key(table) {
+            return Err(format!("there is no loot table `{}`", table));
+        }
+        let (number, mut rng) = self.dice.draw(&format!("loot {}", table), player_id);
+        let (delivery, mut items) = loot.roll(table, &mut rng)?;
+        println!(
+            "{} dropped [{}] at ({}, {}) in {} (draw #{})",
+            table,
+            describe_items(&items),
+            at.0,
+            at.1,
+            describe(room),
+            number
+        );
+        if items.is_empty() {
+            return Ok(items);
+        }
+        let account = player_id
+            .and_then(|id| self.game_state.players.get(&id))
+            .and_then(|player| player.account);
+        // A grant that doesn't fit the inventory drops instead
+        let granted = match (delivery, account) {
+            (Delivery::Grant, Some(account)) => self.grant_items(account, &items).is_ok(),
+            _ => false,
+        };
+        if !granted {
+            for grant in &mut items {
+                let id = ItemId::new_v4();
+                grant.id = Some(id);
+                let metadata = BTreeMap::from([
+                    (ITEM_KEY.to_string(), grant.item.clone()),
+                    (QUANTITY_KEY.to_string(), grant.quantity.to_string()),
+                    (ITEM_ID_KEY.to_string(), id.to_string()),
+                ]);
+                let entity = Entity {
+                    kind: ITEM_KIND.to_string(),
+                    x: at.0,
+                    y: at.1,
+                    metadata,
+                };
+                if let Err(e) = self.spawn_entity(room, entity) {
+                    eprintln!("Couldn't spawn {} from {}: {}", grant.item, table, e);
+                }
+            }
+        }
+        let recipients = self
+            .game_state
+            .players_within(room, at, self.config.areas.view_distance)
+            .into_iter()
+            .filter_map(|id| {
+                let player = self.game_state.players.get(&id)?;
+                player
+                    .snapshot_format
+                    .is_some()
+                    .then_some((player.endpoint, player.wire_format))
+            })
+            .collect();
+        let message = ClientMessage::LootDropped {
+            x: at.0,
+            y: at.1,
+            items: items.clone(),
+            player_id,
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+        Ok(items)
+    }
+
+    // Open a chest or pick up an item, once the player's earlier actions are done
+    pub(super) fn on_interact(&mut self, endpoint: Endpoint, id: u64) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let queued = match self.entities.get(id) {
+            Some(_) => self.queue_action(own_id, Action::Interact { id }),
+            None => Err(format!("no entity {}", id)),
+        };
+        if let Err(e) = queued {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+
+    // The entity `player_id` is close enough to interact with, and where
+    // they are and who they are logged in as
+    pub(super) fn within_reach(
+        &self,
+        player_id: usize,
+        id: u64,
+    ) -> Result<(&Entity, Option<RoomId>, Option<AccountId>), String> {
+        let (room, account, position) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|player| !player.spectating)
+            .and_then(|player| {
+                let position = self.game_state.position(&player)?;
+                Some((player.room, player.account, position))
+            })
+            .ok_or("only players in the game can interact with entities")?;
+        let (entity_room, entity) = self
+            .entities
+            .get(id)
+            .ok_or_else(|| format!("no entity {}", id))?;
+        let distance = (entity.x - position.0).hypot(entity.y - position.1);
+        if entity_room != room || distance > self.config.loot.reach {
+            return Err(format!("entity {} is out of reach", id));
+        }
+        Ok((entity, room, account))
+    }
+
+    pub(super) fn interact(&mut self, player_id: usize, id: u64) -> Result<(), String> {
+        let (entity, room, account) = self.within_reach(player_id, id)?;
+        let at = (entity.x, entity.y);
+        if let Some(shop) = entity.metadata.get(SHOP_KEY) {
+            return self.open_shop(player_id, id, &shop.clone());
+        }
+        if let Some(table) = entity.metadata.get(LOOT_KEY) {
+            let table = table.clone();
+            self.drop_loot(room, &table, at, Some(player_id))?;
+            self.despawn_entity(id)?;
+            self.record_action(player_id, format!("opened {} chest {}", table, id));
+            return Ok(());
+        }
+        let item = entity
+            .metadata
+            .get(ITEM_KEY)
+            .filter(|_| entity.kind == ITEM_KIND)
+            .ok_or_else(|| format!("there is nothing to do with entity {}", id))?;
+        let Some(account) = account else {
+            return Err("log in to pick items up".to_string());
+        };
+        let quantity = entity
+            .metadata
+            .get(QUANTITY_KEY)
+            .and_then(|quantity| quantity.parse().ok())
+            .unwrap_or(1);
+        // Items spawned without an id are given one on pickup
+        let item_id = match entity.metadata.get(ITEM_ID_KEY) {
+            Some(item_id) => Some(
+                item_id
+                    .parse()
+                    .map_err(|_| format!("entity {} has a bad item id", id))?,
+            ),
+            None => None,
+        };
+        let grant = ItemGrant {
+            item: item.clone(),
+            quantity,
+            id: item_id,
+        };
+        let text = format!("picked up {} x {}", grant.quantity, grant.item);
+        self.grant_items(account, &[grant]).map_err(|e| match e {
+            ExchangeError::Full(e) => format!("no room to pick that up: {}", e),
+            ExchangeError::Reused(item_id) => {
+                eprintln!(
+                    "Player {} picked up entity {}, whose item {} is held or used up",
+                    player_id, id, item_id
+                );
+                format!("item {} already exists", item_id)
+            }
+            ExchangeError::Missing(_) => unreachable!("nothing was taken"),
+        })?;
+        self.despawn_entity(id)?;
+        self.record_action(player_id, text);
+        Ok(())
+    }
+}
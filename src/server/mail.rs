@@ -0,0 +1,143 @@
+// Mailboxes: whispers to accounts that are offline, notices and item grants
+// wait here until the account is online, then go out in one `MailReceived`.
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::GameEvent;
+use crate::analytics;
+use crate::endpoint::Endpoint;
+use crate::mail::{ItemGrant, Mail, MailKind, Mailboxes};
+use crate::protocol::{ClientMessage, ErrorCode};
+
+impl Server {
+    pub(super) fn on_whisper_account(
+        &mut self,
+        endpoint: Endpoint,
+        account_id: AccountId,
+        text: String,
+    ) {
+        let Some(&sender_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let Some(sender) = self
+            .game_state
+            .players
+            .get(&sender_id)
+            .and_then(|p| p.account)
+        else {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "log in to whisper accounts",
+            );
+            return;
+        };
+        // Someone online gets an ordinary whisper, mutes and all
+        if let Some((target_endpoint, _)) = self.session_of(account_id) {
+            if let Some(&target_id) = self.endpoints.get(&target_endpoint) {
+                self.on_whisper(endpoint, target_id, text);
+            }
+            return;
+        }
+        let username = self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .by_id(sender)
+            .map(|a| a.username.clone());
+        let known = self
+            .game_state
+            .accounts
+            .read()
+            .unwrap()
+            .by_id(account_id)
+            .is_some();
+        let waiting = self
+            .game_state
+            .mail
+            .read()
+            .unwrap()
+            .count(account_id, MailKind::Whisper);
+        let problem = if account_id == sender {
+            Some("you can't whisper to yourself".to_string())
+        } else if !known {
+            Some(format!("no account {}", account_id))
+        } else if waiting >= self.config.mail.max_whispers {
+            Some(format!("account {}'s mailbox is full", account_id))
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        if !self.check_whisper_rate(endpoint, sender_id) {
+            return;
+        }
+        self.post_mail(
+            account_id,
+            MailKind::Whisper,
+            Some((sender, username.unwrap_or_default())),
+            text,
+            Vec::new(),
+        );
+        self.count_event(sender_id, GameEvent::Whisper);
+    }
+
+    // Leave mail for `account`, handing it over at once if they are online
+    pub(super) fn post_mail(
+        &self,
+        account: AccountId,
+        kind: MailKind,
+        from: Option<(AccountId, String)>,
+        text: String,
+        items: Vec<ItemGrant>,
+    ) {
+        let (from_account, from_name) = from.unzip();
+        let mail = Mail {
+            id: 0,
+            kind,
+            sent_at: analytics::unix_now(),
+            from_account,
+            from_name,
+            text,
+            items,
+        };
+        let id = self.update_mail(|mailboxes| mailboxes.post(account, mail));
+        println!("Mail {} left for account {}", id, account);
+        self.deliver_mail(account);
+    }
+
+    // Send everything waiting for `account`, if they are online
+    pub(super) fn deliver_mail(&self, account: AccountId) {
+        let Some((endpoint, _)) = self.session_of(account) else {
+            return;
+        };
+        let mail = self.update_mail(|mailboxes| mailboxes.take(account));
+        if mail.is_empty() {
+            return;
+        }
+        println!("Delivered {} mail to account {}", mail.len(), account);
+        self.send(endpoint, &ClientMessage::MailReceived { mail });
+    }
+
+    // An account by id or username, with its username
+    pub(super) fn resolve_account(&self, name: &str) -> Result<(AccountId, String), String> {
+        let accounts = self.game_state.accounts.read().unwrap();
+        let account = match name.parse() {
+            Ok(id) => accounts.by_id(id),
+            Err(_) => accounts.find(name),
+        };
+        account
+            .map(|a| (a.id, a.username.clone()))
+            .ok_or_else(|| format!("no account {}", name))
+    }
+
+    // Apply a change to the mailboxes and persist the result
+    pub(super) fn update_mail<T>(&self, change: impl FnOnce(&mut Mailboxes) -> T) -> T {
+        let mut mailboxes = self.game_state.mail.write().unwrap();
+        let result = change(&mut mailboxes);
+        self.save(Mailboxes::STORAGE_KEY, &*mailboxes);
+        result
+    }
+}
@@ -0,0 +1,287 @@
+// Rooms' map data (see `crate::maps`): loading it, keeping moves out of
+// obstacles, and checking triggers every tick.
+use super::shards::ShardCommand;
+use super::Server;
+use crate::config::ServerConfig;
+use crate::ctf::Ctf;
+use crate::endpoint::Endpoint;
+use crate::koth::Koth;
+use crate::maps::{MapData, MapState, Occupant, TriggerChange, TriggerEvent, TriggerKind};
+use crate::protocol::ClientMessage;
+use crate::rooms::{RoomId, Rooms};
+use crate::rounds::Round;
+use crate::scenarios::Happening;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+
+// The maps of the configured rooms that name one, and of the lobby
+pub(super) fn load_maps(
+    config: &ServerConfig,
+    rooms: &Rooms,
+) -> io::Result<HashMap<Option<RoomId>, MapState>> {
+    let load = |path: &Path| {
+        let map = MapData::load(path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("map {}: {}", path.display(), e),
+            )
+        })?;
+        println!(
+            "Loaded map {} ({} obstacles, {} triggers)",
+            path.display(),
+            map.obstacles.len(),
+            map.triggers.len()
+        );
+        Ok::<_, io::Error>(MapState::new(map))
+    };
+    let mut maps = HashMap::new();
+    if let Some(path) = &config.lobby_map {
+        let state = load(path)?;
+        if state.map.ctf.is_some() || state.map.koth.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the lobby has no teams to play a team mode",
+            ));
+        }
+        maps.insert(None, state);
+    }
+    for room_config in &config.rooms {
+        let Some(path) = &room_config.map else {
+            continue;
+        };
+        let room = rooms
+            .iter()
+            .find(|room| room.persistent && room.name == room_config.name);
+        if let Some(room) = room {
+            maps.insert(Some(room.id), load(path)?);
+        }
+    }
+    Ok(maps)
+}
+
+pub(super) fn load_room_maps(config: &ServerConfig) -> io::Result<BTreeMap<String, MapData>> {
+    let mut maps = BTreeMap::new();
+    for (name, path) in &config.room_settings.maps {
+        let map = MapData::load(path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("map {}: {}", path.display(), e),
+            )
+        })?;
+        println!("Loaded room map {} from {}", name, path.display());
+        maps.insert(name.clone(), map);
+    }
+    Ok(maps)
+}
+
+impl Server {
+    // Once a second: the maps and games of rooms that have closed go with
+    // them
+    pub(super) fn prune_maps(&mut self) {
+        let rooms = self.game_state.rooms.read().unwrap();
+        let open = |room: &Option<RoomId>| room.is_none_or(|room| rooms.get(room).is_some());
+        let before = self.maps.len();
+        self.maps.retain(|room, _| open(room));
+        self.ctf.retain(|room, _| open(room));
+        self.koth.retain(|room, _| open(room));
+        self.rounds.retain(|room, _| open(room));
+        for shard in &self.game_state.shards {
+            shard
+                .write()
+                .unwrap()
+                .obstacles
+                .retain(|room, _| open(room));
+        }
+        let dropped = before - self.maps.len();
+        if dropped > 0 {
+            println!("Let go of the maps of {} closed rooms", dropped);
+        }
+    }
+
+    // Play `map` in the room from now on, with a fresh game of its mode, or
+    // no map at all
+    pub(super) fn install_map(&mut self, room: Option<RoomId>, map: Option<MapData>) {
+        self.ctf.remove(&room);
+        self.koth.remove(&room);
+        self.rounds.remove(&room);
+        let Some(map) = map else {
+            self.maps.remove(&room);
+            self.share_obstacles(room);
+            return;
+        };
+        if let Some(ctf) = &map.ctf {
+            self.ctf.insert(room, Ctf::new(ctf.clone()));
+        }
+        if let Some(koth) = &map.koth {
+            self.koth.insert(room, Koth::new(koth.clone()));
+        }
+        if let Some(config) = &map.round {
+            let round = Round::new(config.clone(), self.tick, self.config.snapshot_rate);
+            self.rounds.insert(room, round);
+        }
+        self.maps.insert(room, MapState::new(map));
+        self.share_obstacles(room);
+    }
+
+    // Hand the shards the `sight` config and every map's obstacles, on
+    // starting and when the config may have changed
+    pub(super) fn share_sight(&self) {
+        let fog = self.config.sight.fog();
+        for shard in &self.game_state.shards {
+            shard.write().unwrap().fog = fog;
+        }
+        for &room in self.maps.keys() {
+            self.share_obstacles(room);
+        }
+    }
+
+    // Hand the shard the room's obstacles to hide players behind, or take
+    // them back
+    fn share_obstacles(&self, room: Option<RoomId>) {
+        let obstacles = self
+            .maps
+            .get(&room)
+            .filter(|state| self.config.sight.snapshots && !state.map.obstacles.is_empty())
+            .map(|state| state.map.obstacles.clone());
+        let mut shard = self.game_state.shard(room).write().unwrap();
+        match obstacles {
+            Some(obstacles) => shard.obstacles.insert(room, obstacles),
+            None => shard.obstacles.remove(&room),
+        };
+    }
+
+    // A move ending inside an obstacle is turned down, and the player is put
+    // back where they were. Only where a move ends is checked.
+    pub(super) fn check_obstacles(
+        &self,
+        endpoint: Endpoint,
+        player_id: usize,
+        room: Option<RoomId>,
+        to: (f32, f32),
+    ) -> bool {
+        let Some(state) = self.maps.get(&room) else {
+            return true;
+        };
+        if !state.map.blocked(to) {
+            return true;
+        }
+        let position = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|player| self.game_state.position(&player));
+        if let Some((x, y)) = position {
+            let message = ClientMessage::PlayerPosition {
+                id: player_id,
+                x,
+                y,
+            };
+            self.send(endpoint, &message);
+        }
+        false
+    }
+
+    // Where the room's members are and what team each is on
+    pub(super) fn occupants(&self, room: Option<RoomId>) -> Vec<Occupant> {
+        let shard = self.game_state.shard(room).read().unwrap();
+        let Some(members) = shard.rooms.get(&room) else {
+            return Vec::new();
+        };
+        let positions: Vec<(usize, f32, f32)> = members
+            .ids()
+            .iter()
+            .zip(members.xs().iter().zip(members.ys()))
+            .map(|(&id, (&x, &y))| (id, x, y))
+            .collect();
+        drop(shard);
+        positions
+            .into_iter()
+            .map(|(id, x, y)| Occupant {
+                id,
+                position: (x, y),
+                team: self.game_state.players.get(&id).and_then(|p| p.team),
+            })
+            .collect()
+    }
+
+    // Once a tick: who entered and left each trigger, and what that does
+    pub(super) fn check_triggers(&mut self) {
+        let rooms: Vec<Option<RoomId>> = self.maps.keys().copied().collect();
+        let mut events = Vec::new();
+        for room in rooms {
+            let occupants = self.occupants(room);
+            if let Some(state) = self.maps.get_mut(&room) {
+                events.extend(state.evaluate(room, &occupants, self.config.snapshot_rate));
+            }
+        }
+        for event in events {
+            self.on_trigger(event);
+        }
+    }
+
+    fn on_trigger(&mut self, event: TriggerEvent) {
+        match event.change {
+            TriggerChange::Entered(player_id) | TriggerChange::Left(player_id) => {
+                let entered = matches!(event.change, TriggerChange::Entered(_));
+                let what = match entered {
+                    true => format!("entered {}", event.trigger),
+                    false => format!("left {}", event.trigger),
+                };
+                self.record_action(player_id, what);
+                let endpoint = self
+                    .game_state
+                    .players
+                    .get(&player_id)
+                    .filter(|player| player.snapshot_format.is_some())
+                    .map(|player| player.endpoint);
+                if let Some(endpoint) = endpoint {
+                    let message = ClientMessage::Trigger {
+                        name: event.trigger.clone(),
+                        entered,
+                    };
+                    self.send(endpoint, &message);
+                }
+                if let (true, TriggerKind::Teleport { to: (x, y) }) = (entered, &event.kind) {
+                    self.teleport(player_id, event.room, *x, *y);
+                }
+            }
+            TriggerChange::Captured(team) => {
+                println!(
+                    "Team {} captured {} in {}",
+                    team,
+                    event.trigger,
+                    event
+                        .room
+                        .map_or("the lobby".to_string(), |room| format!("room {}", room))
+                );
+                let message = ClientMessage::PointCaptured {
+                    name: event.trigger.clone(),
+                    team,
+                };
+                self.send_room(event.room, message);
+            }
+        }
+        if let Some(room_id) = event.room {
+            let happening = match event.change {
+                TriggerChange::Entered(_) => Happening::Entered(event.trigger.clone()),
+                TriggerChange::Left(_) => Happening::Left(event.trigger.clone()),
+                TriggerChange::Captured(_) => Happening::Captured(event.trigger.clone()),
+            };
+            self.scenario_happened(room_id, happening);
+        }
+        self.trigger_listeners
+            .retain(|listener| listener.send(event.clone()).is_ok());
+    }
+
+    pub(super) fn teleport(&mut self, player_id: usize, room: Option<RoomId>, x: f32, y: f32) {
+        self.record_move(player_id, room, x, y);
+        let command = ShardCommand::Place {
+            room,
+            player_id,
+            x,
+            y,
+        };
+        self.shards.send(room, command);
+    }
+}
@@ -0,0 +1,257 @@
+// Backfill (see `crate::matchmaking`): queueing lobby players and putting
+// them into the open slots of running matches.
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::rooms::{RoomId, TeamId};
+use std::collections::BTreeMap;
+
+impl Server {
+    pub(super) fn on_find_match(&mut self, endpoint: Endpoint) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.check_not_draining(endpoint) {
+            return;
+        }
+        let in_lobby = self
+            .game_state
+            .players
+            .get(&own_id)
+            .is_some_and(|p| p.joined && p.room.is_none());
+        if !in_lobby {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                "leave your room to find a match",
+            );
+            return;
+        }
+        if !self.match_queue.join(own_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                "you are already queued",
+            );
+            return;
+        }
+        println!("Player {} is looking for a match", own_id);
+        self.backfill();
+        self.advertise_queue();
+    }
+
+    // Once a second
+    pub(super) fn check_queue(&mut self) {
+        self.backfill();
+        self.advertise_queue();
+    }
+
+    pub(super) fn on_leave_queue(&mut self, endpoint: Endpoint) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if self.match_queue.leave(own_id) {
+            let open_slots = self.match_queue.advertised;
+            let message = ClientMessage::Queued {
+                position: None,
+                open_slots,
+            };
+            self.send(endpoint, &message);
+            self.advertise_queue();
+        }
+    }
+
+    // The teams of every running match and how many players each has, by
+    // room
+    pub(super) fn matches(&self) -> BTreeMap<RoomId, BTreeMap<TeamId, u32>> {
+        let rooms = self.ctf.keys().chain(self.koth.keys()).flatten();
+        let mut matches: BTreeMap<RoomId, BTreeMap<TeamId, u32>> = rooms
+            .map(|&room_id| {
+                let teams: Vec<TeamId> = match self.ctf.get(&Some(room_id)) {
+                    Some(game) => game.scores().map(|(team, _)| team).collect(),
+                    None => self.config.matchmaking.teams.clone(),
+                };
+                (room_id, teams.into_iter().map(|team| (team, 0)).collect())
+            })
+            .collect();
+        for player in self.game_state.players.iter() {
+            let (Some(room_id), Some(team)) = (player.room, player.team) else {
+                continue;
+            };
+            let count = matches
+                .get_mut(&room_id)
+                .and_then(|teams| teams.get_mut(&team));
+            if let Some(count) = count {
+                *count += 1;
+            }
+        }
+        matches
+    }
+
+    fn open_slots(&self, teams: &BTreeMap<TeamId, u32>) -> u32 {
+        let size = self.config.matchmaking.team_size;
+        teams
+            .values()
+            .map(|&count| size.saturating_sub(count))
+            .sum()
+    }
+
+    // Who goes into `room_id` with `player_id`: them and, if they lead a
+    // party, the rest of it
+    fn match_unit(&self, player_id: usize, room_id: RoomId) -> Vec<usize> {
+        let mut unit = vec![player_id];
+        unit.extend(self.party_followers(player_id, Some(room_id)));
+        unit
+    }
+
+    // Whether `player_id` leads a party with anyone else in it
+    fn leads_party(&self, player_id: usize) -> bool {
+        let Some(party_id) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.party)
+        else {
+            return false;
+        };
+        let parties = self.game_state.parties.read().unwrap();
+        parties
+            .get(party_id)
+            .is_some_and(|party| party.leader == player_id && party.members.len() > 1)
+    }
+
+    // The match `player_id`, with their party, may be put in, and on which
+    // team: the first with room for them all on one team, on the smallest
+    // such team
+    fn find_slot(
+        &self,
+        player_id: usize,
+        matches: &BTreeMap<RoomId, BTreeMap<TeamId, u32>>,
+    ) -> Option<(RoomId, TeamId, Vec<usize>)> {
+        matches.iter().find_map(|(&room_id, teams)| {
+            let unit = self.match_unit(player_id, room_id);
+            let seated = {
+                let rooms = self.game_state.rooms.read().unwrap();
+                let room = rooms.get(room_id)?;
+                self.seats_left(room, &unit)
+                    .is_some_and(|free| free < unit.len())
+            };
+            if seated
+                || !unit.iter().all(|&id| self.may_join_match(id, room_id))
+                || self.party_name_clash(&unit).is_some()
+            {
+                return None;
+            }
+            let size = unit.len() as u32;
+            let (&team, _) = teams
+                .iter()
+                .filter(|&(_, &count)| count + size <= self.config.matchmaking.team_size)
+                .min_by_key(|&(_, &count)| count)?;
+            Some((room_id, team, unit))
+        })
+    }
+
+    // Whether the room would take `player_id`, were there a seat for them
+    pub(super) fn may_join_match(&self, player_id: usize, room_id: RoomId) -> bool {
+        let Some((guest, name)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| (p.is_guest(), p.name.clone()))
+        else {
+            return false;
+        };
+        let open = {
+            let rooms = self.game_state.rooms.read().unwrap();
+            rooms
+                .get(room_id)
+                .is_some_and(|room| !(room.is_protected() || (room.ranked && guest)))
+        };
+        open && name.is_none_or(|name| !self.name_taken(Some(room_id), &name, player_id))
+    }
+
+    // Fill what open slots there are from the queue, longest-waiting first
+    fn backfill(&mut self) {
+        if self.match_queue.is_empty() || self.drain.is_some() {
+            return;
+        }
+        let mut matches = self.matches();
+        let waiting: Vec<usize> = self.match_queue.waiting().collect();
+        for player_id in waiting {
+            // Seated already, with their party's leader
+            if !self.match_queue.contains(player_id) {
+                continue;
+            }
+            // A bot's seat is taken over rather than added to, which only
+            // makes room for one
+            let (slot, from_bot) = match self.find_slot(player_id, &matches) {
+                Some(slot) => (Some(slot), false),
+                None if !self.leads_party(player_id) => {
+                    let slot = self.unseat_bot(player_id);
+                    (
+                        slot.map(|(room_id, team)| (room_id, team, vec![player_id])),
+                        true,
+                    )
+                }
+                None => (None, false),
+            };
+            let Some((room_id, team, unit)) = slot else {
+                continue;
+            };
+            for &id in &unit {
+                let Some(endpoint) = self.game_state.players.get(&id).map(|p| p.endpoint) else {
+                    continue;
+                };
+                // Entering the room takes them out of the queue
+                println!(
+                    "Backfilled player {} into room {} on team {}",
+                    id, room_id, team
+                );
+                self.send(endpoint, &ClientMessage::MatchFound { room_id, team });
+                self.enter_room(endpoint, id, room_id);
+                if let Some(mut player) = self.game_state.players.get_mut(&id) {
+                    player.team = Some(team);
+                }
+                self.record_action(id, format!("was backfilled onto team {}", team));
+                self.announce_team(id);
+            }
+            if let (Some(count), false) = (
+                matches
+                    .get_mut(&room_id)
+                    .and_then(|teams| teams.get_mut(&team)),
+                from_bot,
+            ) {
+                *count += unit.len() as u32;
+            }
+        }
+    }
+
+    // Tell every queued player their place and the open slots, if either
+    // changed
+    fn advertise_queue(&mut self) {
+        if self.match_queue.is_empty() {
+            return;
+        }
+        let open_slots = self
+            .matches()
+            .values()
+            .map(|teams| self.open_slots(teams))
+            .sum();
+        let queue = &mut self.match_queue;
+        if !queue.changed && open_slots == queue.advertised {
+            return;
+        }
+        queue.changed = false;
+        queue.advertised = open_slots;
+        for (position, player_id) in self.match_queue.waiting().enumerate() {
+            let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) else {
+                continue;
+            };
+            let message = ClientMessage::Queued {
+                position: Some(position as u32 + 1),
+                open_slots,
+            };
+            self.send(endpoint, &message);
+        }
+    }
+}
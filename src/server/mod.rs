@@ -0,0 +1,1907 @@
+use crate::abilities::Abilities;
+use crate::accounts::Accounts;
+use crate::achievements::AchievementProgress;
+use crate::actions::ActionQueues;
+use crate::admin::AdminRequest;
+use crate::analytics::Analytics;
+use crate::areas::AreaEvent;
+use crate::buffers::BufferPool;
+use crate::capture::CaptureWriter;
+use crate::chat::ChatHistory;
+use crate::cidr::IpRange;
+use crate::codec::{self, DecodeError, SnapshotFormat, WireFormat};
+use crate::commands::CommandSpec;
+use crate::config::ServerConfig;
+use crate::crafting::Recipes;
+use crate::ctf::Ctf;
+use crate::dice::{Dice, DiceRoll};
+use crate::endpoint::{family, Endpoint};
+use crate::entities::{Entities, Entity};
+use crate::friends::Friends;
+use crate::guilds::Guilds;
+use crate::handoff::{Handoff, Session};
+use crate::health::Watchdog;
+use crate::history::MatchHistory;
+use crate::ids::PlayerIds;
+use crate::inventory::Inventories;
+use crate::jwt::Verifier;
+use crate::koth::Koth;
+use crate::locale::ServerText;
+use crate::loot::LootTables;
+use crate::mail::Mailboxes;
+use crate::maps::{MapData, MapState, TriggerEvent};
+use crate::matchmaking::MatchQueue;
+use crate::overload::Overload;
+use crate::parties::Parties;
+use crate::passwords;
+use crate::progression::Progression;
+use crate::protocol::{
+    Appearance, ChatChannel, ClientMessage, DisconnectReason, ErrorCode, LocalizedText,
+    PROTOCOL_VERSION,
+};
+use crate::replication::Frame;
+use crate::rooms::{RoomId, Rooms, TeamId};
+use crate::rotation::Rotation;
+use crate::rounds::Round;
+use crate::scenarios::{Scenario, ScenarioRun};
+use crate::shops::Shops;
+use crate::state::{GameState, Player, Recipient, ServerModes};
+use crate::storage::Storage;
+use crate::throttle::{Refusal, Throttle};
+use crate::webhooks::{WebhookEvent, Webhooks};
+use crate::worlds::WorldSave;
+use accounts::{AuthOutcome, PendingHello};
+use bots::Bot;
+use cluster::Transfer;
+use inbound::{Decoder, Inbound};
+use jsonwebtoken::jwk::JwkSet;
+use outbound::{Broadcaster, Outbound};
+use persistence::{Persist, Persister};
+use replication::{Replicate, Replicator};
+use serde::Serialize;
+use shards::{ShardCommand, ShardRouter};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, TcpStream};
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use transport::Connections;
+use worlds::{LoadedWorld, StreamedChunks};
+
+pub use commands::Invocation;
+pub use local::LocalClient;
+
+mod abilities;
+mod accounts;
+mod achievements;
+mod actions;
+mod admin;
+mod analytics;
+mod appearance;
+mod areas;
+mod bots;
+mod chat;
+mod cluster;
+mod commands;
+mod crafting;
+mod ctf;
+mod dice;
+mod difficulty;
+mod drain;
+mod emotes;
+mod entities;
+mod friends;
+mod guests;
+mod guilds;
+mod handoff;
+#[cfg(feature = "http-api")]
+mod health;
+mod hosts;
+#[cfg(feature = "http-api")]
+mod http;
+mod inbound;
+mod inventory;
+mod invites;
+mod jwt;
+mod koth;
+mod local;
+mod loot;
+mod mail;
+mod maps;
+mod matchmaking;
+mod names;
+mod net_stats;
+mod nonces;
+mod outbound;
+mod overload;
+mod parties;
+mod pause;
+mod persistence;
+mod privacy;
+mod progression;
+#[cfg(feature = "quic")]
+mod quic;
+mod rates;
+mod rcon;
+mod replication;
+mod rooms;
+mod rotation;
+mod rounds;
+mod scenarios;
+mod shards;
+mod shops;
+mod steam;
+mod tcp;
+mod traffic;
+mod trails;
+mod transport;
+#[cfg(unix)]
+mod unix;
+mod whispers;
+mod worlds;
+
+// How long a connection may stay un-joined on a password- or token-protected
+// server
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Events for the game loop, from the decoder, its own timers and the admin interfaces
+pub enum Signal {
+    Accepted(Endpoint),
+    // A player another worker moved to this one
+    Adopted(Endpoint, Box<Transfer>),
+    Message(Endpoint, Result<ClientMessage, DecodeError>),
+    Disconnected(Endpoint),
+    Tick,
+    Shutdown,
+    // A drain finished; shut down and exit with the restart code
+    Restart,
+    // A successor sent the handoff secret and waits for the state
+    HandOff(TcpStream),
+    // A standby sent the replication token, maybe with where players can reach it
+    Standby(TcpStream, Option<String>),
+    Admin(AdminRequest),
+    // A password hash or check finished off the game loop
+    Authenticated(Endpoint, AuthOutcome),
+    // The identity provider's key set, fetched off the game loop from this URL
+    Jwks(String, Result<JwkSet, String>),
+    // An area event raised by the game hosting the server
+    Area(AreaEvent),
+    // The game hosting the server wants trigger events sent here
+    ListenTriggers(std::sync::mpsc::Sender<TriggerEvent>),
+    // The game hosting the server spawns an entity, and hears back its id
+    Spawn(
+        Option<RoomId>,
+        Entity,
+        std::sync::mpsc::Sender<Result<u64, String>>,
+    ),
+    Despawn(u64),
+    Authority(u64, Option<usize>),
+    MoveEntity(u64, f32, f32),
+    // The game hosting the server drops loot, such as where an NPC died
+    Loot {
+        room: Option<RoomId>,
+        table: String,
+        x: f32,
+        y: f32,
+        player_id: Option<usize>,
+    },
+    // The game hosting the server, or a script, slows the simulation down or
+    // speeds it up
+    TimeScale(f32),
+    // The persistence stage read a world from disk
+    WorldLoaded(String, WorldSave),
+    // The game hosting the server rolls dice, such as for a crit
+    Roll {
+        expression: String,
+        purpose: String,
+        player_id: Option<usize>,
+        reply: std::sync::mpsc::Sender<Result<DiceRoll, String>>,
+    },
+    // The game hosting the server adds a slash command, whose invocations
+    // are sent here
+    RegisterCommand(
+        CommandSpec,
+        std::sync::mpsc::Sender<Invocation>,
+        std::sync::mpsc::Sender<Result<(), String>>,
+    ),
+    // An answer to a registered command, for the player who ran it
+    CommandReply(usize, String),
+}
+
+pub type Signals = UnboundedSender<Signal>;
+
+// The game loop. Decoding, broadcasting and file writes run as their own
+// tasks, fed through the channels held here.
+pub struct Server {
+    signals: Signals,
+    inbound: UnboundedSender<Inbound>,
+    outbound: UnboundedSender<Outbound>,
+    persist: UnboundedSender<Persist>,
+    game_state: Arc<GameState>,
+    config: ServerConfig,
+    endpoints: HashMap<Endpoint, usize>,
+    // The simulation's tick, which stands still while it is paused and runs
+    // at `time_scale` otherwise, and every tick the loop has run, which
+    // housekeeping is timed by
+    tick: u64,
+    ticks_run: u64,
+    paused: bool,
+    time_scale: f32,
+    // Simulated time built up towards the next tick
+    time_carry: f32,
+    // The last tick snapshots went out for
+    snapshotted: u64,
+    webhooks: Webhooks,
+    shards: ShardRouter,
+    throttle: Throttle,
+    // Connections with a `Register` or `Login` being checked
+    authenticating: HashSet<Endpoint>,
+    jwt: Verifier,
+    // When the JWKS should next be fetched; `None` while a fetch is running
+    jwks_due: Option<Instant>,
+    // While a `drain` runs, when it gives up waiting for matches to end
+    drain: Option<Instant>,
+    // Sessions taken over from the previous process, by token, and when the
+    // ones not resumed by then are let go
+    resumable: HashMap<String, Session>,
+    resume_until: Option<Instant>,
+    // Players whose connection dropped, and until when they wait for a new
+    // one (see `migration`)
+    held: HashMap<usize, Instant>,
+    // The replication stage, when standbys may follow this server, and where
+    // the last standby to give one said players can find it
+    replicate: Option<UnboundedSender<Replicate>>,
+    standby_addr: Option<String>,
+    // When the next tick should run, to tell how late it does
+    tick_due: Instant,
+    // When the next `NetStats` reports go out
+    net_stats_due: Instant,
+    // When traffic was last checked against the throttle's message limits
+    traffic_checked: Instant,
+    // Set when systemd watches the process
+    watchdog: Option<Watchdog>,
+    // Ticks over budget and what is shed because of them, and when that was
+    // last warned about
+    overload: Overload,
+    overload_warned: Option<Instant>,
+    // Map data of the rooms that have it, and who wants to hear about triggers
+    maps: HashMap<Option<RoomId>, MapState>,
+    trigger_listeners: Vec<std::sync::mpsc::Sender<TriggerEvent>>,
+    // Slash commands, the server's own and those the hosting game registered
+    commands: commands::Registry,
+    // Capture the flag and king of the hill, in the rooms whose maps set
+    // them up
+    ctf: HashMap<Option<RoomId>, Ctf>,
+    koth: HashMap<Option<RoomId>, Koth>,
+    // The round clocks of the team mode rooms that time their rounds
+    rounds: HashMap<Option<RoomId>, Round>,
+    // The rooms that play a rotation of maps
+    rotations: HashMap<Option<RoomId>, Rotation>,
+    // The maps players may create rooms with, by name
+    room_maps: BTreeMap<String, MapData>,
+    // What has been spawned into rooms at runtime
+    entities: Entities,
+    // `None` when no ability file is configured
+    abilities: Option<Abilities>,
+    // `None` when no loot file is configured
+    loot: Option<LootTables>,
+    // Abilities and interactions waiting for the tick
+    actions: ActionQueues,
+    dice: Dice,
+    // `None` when no recipe file is configured
+    recipes: Option<Recipes>,
+    // `None` when no shop file is configured
+    shops: Option<Shops>,
+    // Lobby players waiting for a slot in a running match
+    match_queue: MatchQueue,
+    // Updates held back to `update_rates`: the latest score of each team by
+    // room, and the latest position of each entity
+    pending_scores: BTreeMap<(Option<RoomId>, TeamId), u32>,
+    pending_moves: BTreeMap<u64, (Option<RoomId>, f32, f32)>,
+    // Outstanding room invites, by invitee and room, until they run out
+    room_invites: HashMap<(usize, RoomId), Instant>,
+    // The worlds in memory, by name, and the players waiting on those
+    // still being read from disk
+    worlds: HashMap<String, LoadedWorld>,
+    loading_worlds: HashMap<String, Vec<usize>>,
+    // The terrain chunks each player in a world was sent
+    streamed: HashMap<usize, StreamedChunks>,
+    // Server-run players, by the endpoint of their in-process connection
+    bots: HashMap<Endpoint, Bot>,
+    connections: Connections,
+    scenarios: BTreeMap<String, Scenario>,
+    // Each room's run of its scenario, while anyone is in it
+    scenario_runs: HashMap<RoomId, ScenarioRun>,
+}
+
+// How the game loop ended, so `main` can pick an exit code
+#[derive(Debug)]
+pub enum Exit {
+    Shutdown,
+    // A drain finished and the process should exit with `drain.exit_code`
+    Restart,
+    // The state went to a successor, which waits for this connection to close
+    // before it binds the ports
+    HandedOff(TcpStream),
+}
+
+// Everything `start` set running, kept until the game loop ends
+struct Pipeline {
+    server: Server,
+    signals: UnboundedReceiver<Signal>,
+    connections: Connections,
+    listeners: Vec<JoinHandle<()>>,
+    decoder: JoinHandle<()>,
+    broadcaster: JoinHandle<()>,
+    persister: JoinHandle<()>,
+    replicator: Option<JoinHandle<()>>,
+}
+
+// Bind the listener and run the server until it is shut down or drained. A
+// `--standby` only gets that far once its primary is lost.
+pub fn run(config: ServerConfig) -> io::Result<Exit> {
+    let inherited = match config.replication.standby {
+        true => Some(crate::replication::follow(
+            &config.replication,
+            &config.data_dir,
+        )?),
+        false => None,
+    };
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let pipeline = start(config, inherited)?;
+        if let Some(watchdog) = &pipeline.server.watchdog {
+            watchdog.ready();
+        }
+        watch_ctrl_c(pipeline.server.signals.clone());
+        admin::spawn_console(pipeline.server.signals.clone());
+        Ok(pipeline.run().await)
+    })
+}
+
+// Run a server on a background thread, for games that host their own. The
+// host plays through `ServerHandle::connect_local` while remote players
+// connect over the network as usual.
+pub fn spawn(config: ServerConfig) -> io::Result<ServerHandle> {
+    let runtime = Runtime::new()?;
+    let pipeline = {
+        let _context = runtime.enter();
+        start(config, None)?
+    };
+    let signals = pipeline.server.signals.clone();
+    let inbound = pipeline.server.inbound.clone();
+    let connections = pipeline.connections.clone();
+    let thread = thread::spawn(move || {
+        runtime.block_on(pipeline.run());
+    });
+    Ok(ServerHandle {
+        signals,
+        inbound,
+        connections,
+        thread: Some(thread),
+    })
+}
+
+// Owns a server started with `spawn`; dropping it shuts the server down
+pub struct ServerHandle {
+    signals: Signals,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    pub fn connect_local(&self) -> LocalClient {
+        LocalClient::connect(self.inbound.clone(), self.connections.clone())
+    }
+
+    // Set off an area event on the next turn of the game loop; events that
+    // don't check out are logged and dropped
+    pub fn area_event(&self, event: AreaEvent) {
+        self.signals.send(Signal::Area(event)).ok();
+    }
+
+    // Every trigger event from now on, for gameplay systems and scripts
+    pub fn trigger_events(&self) -> std::sync::mpsc::Receiver<TriggerEvent> {
+        let (listener, events) = std::sync::mpsc::channel();
+        self.signals.send(Signal::ListenTriggers(listener)).ok();
+        events
+    }
+
+    // Spawn an entity into a room, or the lobby, as the next turn of the game
+    // loop comes round; returns its id
+    pub fn spawn_entity(&self, room: Option<RoomId>, entity: Entity) -> Result<u64, String> {
+        let (reply, result) = std::sync::mpsc::channel();
+        self.signals
+            .send(Signal::Spawn(room, entity, reply))
+            .map_err(|_| "the server is shutting down".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "the server is shutting down".to_string())?
+    }
+
+    // Unknown ids are logged and dropped
+    pub fn despawn_entity(&self, id: u64) {
+        self.signals.send(Signal::Despawn(id)).ok();
+    }
+
+    // Hand an entity to a player in its room to simulate, or take it back
+    // with `None`
+    pub fn transfer_authority(&self, id: u64, to: Option<usize>) {
+        self.signals.send(Signal::Authority(id, to)).ok();
+    }
+
+    // Only entities the server has authority over move this way
+    pub fn move_entity(&self, id: u64, x: f32, y: f32) {
+        self.signals.send(Signal::MoveEntity(id, x, y)).ok();
+    }
+
+    // Roll a loot table at (x, y), such as where an NPC died; `player_id` is
+    // who gets what it grants, like whoever killed the NPC
+    pub fn drop_loot(
+        &self,
+        room: Option<RoomId>,
+        table: &str,
+        x: f32,
+        y: f32,
+        player_id: Option<usize>,
+    ) {
+        let table = table.to_string();
+        let signal = Signal::Loot {
+            room,
+            table,
+            x,
+            y,
+            player_id,
+        };
+        self.signals.send(signal).ok();
+    }
+
+    // Scales out of range are logged and dropped
+    pub fn set_time_scale(&self, scale: f32) {
+        self.signals.send(Signal::TimeScale(scale)).ok();
+    }
+
+    // Roll dice in notation like `2d6+3` with the server's seeded RNG, for
+    // outcomes clients shouldn't be trusted with, such as crits; `purpose`
+    // and `player_id` go in the log and the `dice` audit
+    pub fn roll(
+        &self,
+        expression: &str,
+        purpose: &str,
+        player_id: Option<usize>,
+    ) -> Result<DiceRoll, String> {
+        let (reply, result) = std::sync::mpsc::channel();
+        let signal = Signal::Roll {
+            expression: expression.to_string(),
+            purpose: purpose.to_string(),
+            player_id,
+            reply,
+        };
+        self.signals
+            .send(signal)
+            .map_err(|_| "the server is shutting down".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "the server is shutting down".to_string())?
+    }
+
+    // Add a slash command players can run from chat; each time one does, an
+    // `Invocation` arrives on the returned receiver. Dropping the receiver
+    // takes the command away again.
+    pub fn register_command(
+        &self,
+        spec: CommandSpec,
+    ) -> Result<std::sync::mpsc::Receiver<Invocation>, String> {
+        let (handler, invocations) = std::sync::mpsc::channel();
+        let (reply, result) = std::sync::mpsc::channel();
+        self.signals
+            .send(Signal::RegisterCommand(spec, handler, reply))
+            .map_err(|_| "the server is shutting down".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "the server is shutting down".to_string())??;
+        Ok(invocations)
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.signals.send(Signal::Shutdown).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+// Bind the listener and spawn every stage; must be called inside the runtime.
+// `inherited` is the state of a lost primary, for a standby taking over.
+fn start(config: ServerConfig, inherited: Option<Handoff>) -> io::Result<Pipeline> {
+    let handoff = match &config.handoff.listen_addr {
+        _ if inherited.is_some() => inherited,
+        Some(addr) if config.handoff.take_over => {
+            println!("Taking over from the server waiting on {}", addr);
+            Some(crate::handoff::take_over(addr, &config.data_dir)?)
+        }
+        _ => None,
+    };
+    let (inbound, inbound_rx) = mpsc::unbounded_channel();
+    let (connections, listeners, bound) = transport::listen(
+        &config.bind_addrs(),
+        inbound.clone(),
+        config.decode_limits.max_frame_bytes,
+    )?;
+    for addr in bound {
+        println!(
+            "Listening on {} ({}, {} wire format)",
+            addr,
+            family(addr),
+            config.wire_format
+        );
+    }
+
+    let storage = Storage::new(&config.data_dir);
+    let mut rooms = Rooms::from_config(&config.rooms, config.cluster.first_room_id());
+    let mut chat_history = ChatHistory::default();
+    if config.chat_history.persist {
+        chat_history = storage.load::<ChatHistory>(ChatHistory::STORAGE_KEY);
+        chat_history.retain_lasting(|room| rooms.get(room).is_some());
+    }
+    let mut inventories = storage.load::<Inventories>(Inventories::STORAGE_KEY);
+    inventories.migrate();
+    let mut accounts = storage.load::<Accounts>(Accounts::STORAGE_KEY);
+    if accounts.assign_uuids() {
+        if let Err(e) = storage.save(Accounts::STORAGE_KEY, &accounts) {
+            eprintln!("Failed to save the accounts' new player UUIDs: {}", e);
+        }
+    }
+    let mut parties = Parties::default();
+    let mut player_ids = PlayerIds::new(config.cluster.player_ids());
+    let mut resumable = HashMap::new();
+    if let Some(handoff) = handoff {
+        println!("Took over {} sessions", handoff.sessions.len());
+        rooms = handoff.rooms;
+        rooms.apply_config(&config.rooms);
+        parties = handoff.parties;
+        chat_history = handoff.chat_history;
+        player_ids.resume_at(handoff.next_player_id);
+        resumable = handoff
+            .sessions
+            .into_iter()
+            .filter(|session| {
+                let claimed = player_ids.claim(session.player_id);
+                if !claimed {
+                    eprintln!("Dropping a second session for player {}", session.player_id);
+                }
+                claimed
+            })
+            .map(|session| (session.token.clone(), session))
+            .collect();
+    }
+    let resume_until = (!resumable.is_empty())
+        .then(|| Instant::now() + Duration::from_secs(config.handoff.resume_secs));
+    let mut maps = maps::load_maps(&config, &rooms)?;
+    let rotations = rotation::load_rotations(&config, &rooms, &mut maps)?;
+    let room_maps = maps::load_room_maps(&config)?;
+    let abilities = abilities::load_abilities(&config)?;
+    let loot = loot::load_loot(&config)?;
+    let dice = Dice::new(config.dice.clone(), config.loot.seed);
+    println!("Dice seeded with {}", dice.seed());
+    let recipes = crafting::load_recipes(&config)?;
+    let shops = shops::load_shops(&config)?;
+    let scenarios = scenarios::load_scenarios(&config)?;
+    let game_state = GameState {
+        modes: storage.load::<ServerModes>(ServerModes::STORAGE_KEY).into(),
+        rooms: rooms.into(),
+        parties: parties.into(),
+        analytics: storage.load::<Analytics>(Analytics::STORAGE_KEY).into(),
+        accounts: accounts.into(),
+        friends: storage.load::<Friends>(Friends::STORAGE_KEY).into(),
+        guilds: storage.load::<Guilds>(Guilds::STORAGE_KEY).into(),
+        mail: storage.load::<Mailboxes>(Mailboxes::STORAGE_KEY).into(),
+        achievements: storage
+            .load::<AchievementProgress>(AchievementProgress::STORAGE_KEY)
+            .into(),
+        progression: storage.load::<Progression>(Progression::STORAGE_KEY).into(),
+        inventories: inventories.into(),
+        chat_history: chat_history.into(),
+        match_history: storage
+            .load::<MatchHistory>(MatchHistory::STORAGE_KEY)
+            .into(),
+        shards: (0..config.shards).map(|_| Default::default()).collect(),
+        player_ids: player_ids.into(),
+        ..GameState::default()
+    };
+
+    if let Some(chaos) = &config.chaos {
+        println!("Chaos mode on: {:?}", chaos);
+    }
+    let capture = match &config.capture_path {
+        Some(path) => {
+            println!("Capturing inbound traffic to {}", path.display());
+            Some(CaptureWriter::create(path)?)
+        }
+        None => None,
+    };
+    let webhooks = Webhooks::new(&config.webhooks);
+    report_crashes(webhooks.clone());
+
+    let (signals, signals_rx) = mpsc::unbounded_channel();
+    let (outbound, outbound_rx) = mpsc::unbounded_channel();
+    let (persist, persist_rx) = mpsc::unbounded_channel();
+    let decoder = Decoder {
+        signals: signals.clone(),
+        persist: persist.clone(),
+        chaos: config.chaos.clone(),
+        wire_format: config.wire_format,
+        limits: config.decode_limits,
+        formats: HashMap::new(),
+        traffic: game_state.traffic.clone(),
+    };
+    let decoder = tokio::spawn(decoder.run(inbound_rx));
+    let broadcaster = Broadcaster {
+        connections: connections.clone(),
+        chaos: config.chaos.clone(),
+        pool: BufferPool::new(game_state.buffers.clone()),
+        traffic: game_state.traffic.clone(),
+    };
+    let broadcaster = tokio::spawn(broadcaster.run(outbound_rx));
+    let persister = Persister {
+        storage,
+        capture,
+        health: game_state.health.clone(),
+        signals: signals.clone(),
+    };
+    let persister = tokio::task::spawn_blocking(move || persister.run(persist_rx));
+    let (replicate, replicator) = match config.replication.listen_addr {
+        Some(_) => {
+            let (replicate, replicate_rx) = mpsc::unbounded_channel();
+            let replicator = Replicator::default();
+            let replicator = tokio::task::spawn_blocking(move || replicator.run(replicate_rx));
+            (Some(replicate), Some(replicator))
+        }
+        None => (None, None),
+    };
+
+    let game_state = Arc::new(game_state);
+    #[cfg(feature = "http-api")]
+    let probe = health::Probe {
+        game_state: game_state.clone(),
+        storage: Storage::new(&config.data_dir),
+        config: config.health.clone(),
+    };
+    let dead_reckoning = config.dead_reckoning.in_ticks(config.snapshot_rate);
+    let delta_window = config.deltas.window(config.snapshot_rate);
+    let shards = shards::spawn_shards(&game_state, &outbound, dead_reckoning, delta_window)?;
+    let mut server = Server {
+        signals,
+        inbound,
+        outbound,
+        persist,
+        game_state,
+        throttle: Throttle::new(config.throttle.clone()),
+        authenticating: HashSet::new(),
+        jwt: Verifier::new(config.jwt.clone()),
+        jwks_due: Some(Instant::now()),
+        tick_due: Instant::now(),
+        net_stats_due: Instant::now(),
+        traffic_checked: Instant::now(),
+        watchdog: Watchdog::from_env(),
+        overload: Overload::default(),
+        overload_warned: None,
+        ctf: ctf::games(&maps),
+        koth: koth::games(&maps),
+        rounds: rounds::clocks(&maps, 0, config.snapshot_rate),
+        rotations,
+        room_maps,
+        entities: Entities::default(),
+        abilities,
+        loot,
+        actions: ActionQueues::default(),
+        dice,
+        recipes,
+        shops,
+        match_queue: MatchQueue::default(),
+        pending_scores: BTreeMap::new(),
+        pending_moves: BTreeMap::new(),
+        room_invites: HashMap::new(),
+        worlds: HashMap::new(),
+        loading_worlds: HashMap::new(),
+        streamed: HashMap::new(),
+        bots: HashMap::new(),
+        connections: connections.clone(),
+        scenarios,
+        scenario_runs: HashMap::new(),
+        maps,
+        trigger_listeners: Vec::new(),
+        commands: commands::Registry::default(),
+        drain: None,
+        resumable,
+        resume_until,
+        held: HashMap::new(),
+        replicate,
+        standby_addr: None,
+        config,
+        endpoints: HashMap::new(),
+        tick: 0,
+        ticks_run: 0,
+        paused: false,
+        time_scale: 1.0,
+        time_carry: 0.0,
+        snapshotted: 0,
+        webhooks,
+        shards,
+    };
+    server.share_sight();
+    server.schedule_tick();
+    if let (Some(addr), Some(hash)) = (
+        &server.config.rcon_listen_addr,
+        &server.config.rcon_password_hash,
+    ) {
+        rcon::spawn_rcon(server.signals.clone(), addr, hash.clone())?;
+    }
+    if let Some(addr) = &server.config.handoff.listen_addr {
+        handoff::spawn_listener(server.signals.clone(), addr, &server.config.data_dir)?;
+    }
+    if let (Some(index), Some(hash)) = (
+        server.config.cluster.worker,
+        &server.config.cluster.token_hash,
+    ) {
+        let addr = &server.config.cluster.workers[index].link_addr;
+        cluster::spawn_links(
+            server.inbound.clone(),
+            connections.clone(),
+            addr,
+            hash.clone(),
+        )?;
+    }
+    if let (Some(addr), Some(hash)) = (
+        &server.config.replication.listen_addr,
+        &server.config.replication.token_hash,
+    ) {
+        replication::spawn_listener(server.signals.clone(), addr, hash.clone())?;
+    }
+    #[cfg(feature = "http-api")]
+    if let (Some(addr), Some(hash)) = (
+        &server.config.http_listen_addr,
+        &server.config.http_token_hash,
+    ) {
+        http::spawn_http_api(
+            server.signals.clone(),
+            server.game_state.clone(),
+            addr,
+            hash.clone(),
+            probe.clone(),
+        )?;
+    }
+    #[cfg(feature = "http-api")]
+    if let Some(addr) = &server.config.health.listen_addr {
+        health::spawn_health(addr, probe)?;
+    }
+    #[cfg(unix)]
+    if let Some(path) = &server.config.unix_socket {
+        unix::spawn_listener(
+            path,
+            server.inbound.clone(),
+            connections.clone(),
+            server.config.decode_limits.max_frame_bytes,
+        )?;
+    }
+    #[cfg(feature = "quic")]
+    if let Some(addr) = &server.config.quic.listen_addr {
+        quic::spawn_listener(
+            &server.config.quic,
+            addr,
+            server.inbound.clone(),
+            connections.clone(),
+            server.config.decode_limits.max_frame_bytes,
+        )?;
+    }
+    Ok(Pipeline {
+        server,
+        signals: signals_rx,
+        connections,
+        listeners,
+        decoder,
+        broadcaster,
+        persister,
+        replicator,
+    })
+}
+
+impl Pipeline {
+    async fn run(self) -> Exit {
+        let Pipeline {
+            mut server,
+            mut signals,
+            connections,
+            listeners,
+            decoder,
+            broadcaster,
+            persister,
+            replicator,
+        } = self;
+        let mut exit = Exit::Shutdown;
+        while let Some(signal) = signals.recv().await {
+            match signal {
+                Signal::Accepted(endpoint) => server.on_accepted(endpoint),
+                Signal::Adopted(endpoint, transfer) => server.on_adopted(endpoint, *transfer),
+                Signal::Message(endpoint, message) => server.on_message(endpoint, message),
+                Signal::Disconnected(endpoint) => server.on_disconnected(endpoint),
+                Signal::Tick => server.on_tick(),
+                Signal::Admin(request) => server.on_admin_request(request),
+                Signal::Authenticated(endpoint, outcome) => {
+                    server.on_authenticated(endpoint, outcome)
+                }
+                Signal::Jwks(url, jwks) => server.on_jwks(url, jwks),
+                Signal::ListenTriggers(listener) => server.trigger_listeners.push(listener),
+                Signal::RegisterCommand(spec, handler, reply) => {
+                    reply.send(server.commands.register(spec, handler)).ok();
+                }
+                Signal::Roll {
+                    expression,
+                    purpose,
+                    player_id,
+                    reply,
+                } => {
+                    reply
+                        .send(server.roll_dice(&expression, &purpose, player_id))
+                        .ok();
+                }
+                Signal::CommandReply(player_id, text) => server.on_command_reply(player_id, text),
+                Signal::Area(event) => {
+                    if let Err(e) = server.area_event(event, None) {
+                        eprintln!("Dropped an area event: {}", e);
+                    }
+                }
+                Signal::Spawn(room, entity, reply) => {
+                    reply.send(server.spawn_entity(room, entity)).ok();
+                }
+                Signal::Despawn(id) => {
+                    if let Err(e) = server.despawn_entity(id) {
+                        eprintln!("Dropped a despawn: {}", e);
+                    }
+                }
+                Signal::Authority(id, to) => {
+                    if let Err(e) = server.transfer_authority(id, to) {
+                        eprintln!("Dropped an authority transfer: {}", e);
+                    }
+                }
+                Signal::MoveEntity(id, x, y) => {
+                    if let Err(e) = server.move_entity(id, x, y) {
+                        eprintln!("Dropped an entity move: {}", e);
+                    }
+                }
+                Signal::Loot {
+                    room,
+                    table,
+                    x,
+                    y,
+                    player_id,
+                } => {
+                    if let Err(e) = server.drop_loot(room, &table, (x, y), player_id) {
+                        eprintln!("Dropped a loot drop: {}", e);
+                    }
+                }
+                Signal::TimeScale(scale) => {
+                    if let Err(e) = server.set_time_scale(scale) {
+                        eprintln!("Dropped a time scale change: {}", e);
+                    }
+                }
+                Signal::WorldLoaded(name, save) => server.on_world_loaded(name, save),
+                Signal::Shutdown => {
+                    server.shutdown();
+                    break;
+                }
+                Signal::Restart => {
+                    server.restart();
+                    exit = Exit::Restart;
+                    break;
+                }
+                Signal::HandOff(successor) => {
+                    if server.hand_off(&successor) {
+                        exit = Exit::HandedOff(successor);
+                        break;
+                    }
+                }
+                Signal::Standby(stream, public_addr) => server.on_standby(stream, public_addr),
+            }
+        }
+        // Every way out of the loop is on purpose, so standbys wait for this
+        // server to come back rather than take over
+        server.replicate(Frame::Goodbye);
+
+        // Let the last frames out before the sockets close, then the last writes
+        drop(signals);
+        drop(server);
+        broadcaster.await.ok();
+        for listener in listeners {
+            listener.abort();
+        }
+        connections.stop();
+        decoder.abort();
+        decoder.await.ok();
+        persister.await.ok();
+        if let Some(replicator) = replicator {
+            replicator.await.ok();
+        }
+        exit
+    }
+}
+
+// Tell the webhooks about panics before the default hook prints them
+fn report_crashes(webhooks: Webhooks) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        webhooks.notify_now(WebhookEvent::Crash {
+            message: info.to_string(),
+        });
+        default_hook(info);
+    }));
+}
+
+// Turn Ctrl-C into a `Shutdown` signal so clients are told before the process exits
+fn watch_ctrl_c(signals: Signals) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            signals.send(Signal::Shutdown).ok();
+        }
+    });
+}
+
+impl Server {
+    fn schedule_tick(&mut self) {
+        let interval = Duration::from_secs(1) / self.config.snapshot_rate;
+        self.tick_due = Instant::now() + interval;
+        let signals = self.signals.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(interval).await;
+            signals.send(Signal::Tick).ok();
+        });
+    }
+
+    fn wire_format(&self, endpoint: Endpoint) -> WireFormat {
+        self.endpoints
+            .get(&endpoint)
+            .and_then(|id| self.game_state.players.get(id).map(|p| p.wire_format))
+            .unwrap_or(self.config.wire_format)
+    }
+
+    fn send(&self, endpoint: Endpoint, message: &ClientMessage) {
+        let recipient = (endpoint, self.wire_format(endpoint));
+        self.outbound
+            .send(Outbound::Send(vec![recipient], message.clone()))
+            .ok();
+    }
+
+    // Broadcast a message to all joined clients except the sender
+    fn broadcast(&self, message: &ClientMessage, sender_id: usize) {
+        let recipients = self
+            .game_state
+            .roster()
+            .iter()
+            .filter(|r| r.id != sender_id)
+            .map(|r| (r.endpoint, r.wire_format))
+            .collect();
+        self.outbound
+            .send(Outbound::Send(recipients, message.clone()))
+            .ok();
+    }
+
+    // Chat goes to everyone but the sender and those who muted them, with the
+    // sender's name for clients that sent `Hello`
+    fn broadcast_chat(&self, sender_id: usize, name: Option<String>, message: String) {
+        let muting: HashSet<usize> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| p.muted.contains(&sender_id))
+            .map(|p| p.id)
+            .collect();
+        let roster = self.game_state.roster();
+        let (named, legacy): (Vec<_>, Vec<_>) = roster
+            .iter()
+            .filter(|r| r.id != sender_id && !muting.contains(&r.id))
+            .partition(|r| r.handshaken);
+        let recipients = |list: Vec<&Recipient>| -> Vec<(Endpoint, WireFormat)> {
+            list.iter().map(|r| (r.endpoint, r.wire_format)).collect()
+        };
+        if !legacy.is_empty() {
+            let update = ClientMessage::UpdateMessage {
+                id: sender_id,
+                message: message.clone(),
+            };
+            self.outbound
+                .send(Outbound::Send(recipients(legacy), update))
+                .ok();
+        }
+        let chat = ClientMessage::ChatMessage {
+            id: sender_id,
+            name,
+            message,
+            channel: ChatChannel::Global,
+        };
+        self.outbound
+            .send(Outbound::Send(recipients(named), chat))
+            .ok();
+    }
+
+    // Hand a storage document to the persistence stage
+    fn save(&self, name: &str, value: &impl Serialize) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                if self.replicate.is_some() {
+                    let frame = Frame::Store {
+                        name: name.to_string(),
+                        value: value.clone(),
+                    };
+                    self.replicate(frame);
+                }
+                self.persist
+                    .send(Persist::Save(name.to_string(), value))
+                    .ok();
+            }
+            Err(e) => eprintln!("Failed to persist {}: {}", name, e),
+        }
+    }
+
+    // Hand an entry for a storage document's journal to the persistence stage
+    fn journal(&self, name: &str, entry: &impl Serialize) {
+        match serde_json::to_value(entry) {
+            Ok(entry) => {
+                if self.replicate.is_some() {
+                    let frame = Frame::Journal {
+                        name: name.to_string(),
+                        entry: entry.clone(),
+                    };
+                    self.replicate(frame);
+                }
+                self.persist
+                    .send(Persist::Journal(name.to_string(), entry))
+                    .ok();
+            }
+            Err(e) => eprintln!("Failed to journal {}: {}", name, e),
+        }
+    }
+
+    // Tell the client why its request was refused
+    fn reject(&self, endpoint: Endpoint, code: ErrorCode, context: impl Into<ServerText>) {
+        let (context, localized) = self.localize(context.into());
+        println!(
+            "Rejected request from {:?}: {:?} ({})",
+            endpoint, code, context
+        );
+        self.send(
+            endpoint,
+            &ClientMessage::ServerError {
+                code,
+                context,
+                localized,
+            },
+        );
+    }
+
+    // Text for a player as the plain-text fallback and, if it has one, the key
+    fn localize(&self, text: ServerText) -> (String, Option<LocalizedText>) {
+        match text {
+            ServerText::Keyed(text) => (self.config.localization.render(&text), Some(text)),
+            ServerText::Plain(text) => (text, None),
+        }
+    }
+
+    // Whether the endpoint is allowed to act for the player id it sent
+    fn check_sender(&self, endpoint: Endpoint, id: usize) -> bool {
+        if self.endpoints.get(&endpoint) == Some(&id) {
+            return true;
+        }
+        self.reject(
+            endpoint,
+            ErrorCode::PlayerMismatch,
+            format!("connection does not own player {}", id),
+        );
+        false
+    }
+
+    // Whether the server's join restrictions turn this player away
+    fn admission_check(&self, player: &Player) -> Option<(DisconnectReason, ServerText)> {
+        let modes = self.game_state.modes.read().unwrap();
+        let identities = player.identities();
+        if identities
+            .iter()
+            .any(|identity| modes.banned.contains(identity))
+        {
+            return Some((
+                DisconnectReason::Banned,
+                LocalizedText::new("disconnect.banned").into(),
+            ));
+        }
+        if modes.maintenance {
+            // Only the default message has a key; an operator's own is sent as written
+            let message = if modes.maintenance_message.is_empty() {
+                LocalizedText::new("disconnect.maintenance").into()
+            } else {
+                modes.maintenance_message.clone().into()
+            };
+            return Some((DisconnectReason::Maintenance, message));
+        }
+        // Where everyone logs in, the whitelist waits for the account (see
+        // `log_in`); only then are all of the player's identities known
+        if modes.whitelist_only
+            && !self.config.login_required()
+            && !identities
+                .iter()
+                .any(|identity| modes.whitelist.contains(identity))
+        {
+            return Some((
+                DisconnectReason::NotWhitelisted,
+                LocalizedText::new("disconnect.not_whitelisted").into(),
+            ));
+        }
+        None
+    }
+
+    // Whether the address allow and deny lists turn this address away
+    fn address_check(&self, ip: IpAddr) -> Option<(DisconnectReason, LocalizedText)> {
+        let modes = self.game_state.modes.read().unwrap();
+        let listed = |config: &[IpRange], runtime: &BTreeSet<IpRange>| {
+            config.iter().chain(runtime).any(|range| range.contains(ip))
+        };
+        if listed(&self.config.ip_deny, &modes.ip_deny) {
+            return Some((
+                DisconnectReason::Banned,
+                LocalizedText::new("disconnect.address_blocked"),
+            ));
+        }
+        let allow_only = !self.config.ip_allow.is_empty() || !modes.ip_allow.is_empty();
+        if allow_only && !listed(&self.config.ip_allow, &modes.ip_allow) {
+            return Some((
+                DisconnectReason::NotWhitelisted,
+                LocalizedText::new("disconnect.address_not_allowed"),
+            ));
+        }
+        None
+    }
+
+    fn on_accepted(&mut self, endpoint: Endpoint) {
+        let ip = endpoint.addr().map(|addr| addr.ip());
+        if let Some(ip) = ip {
+            if let Some((reason, message)) = self.address_check(ip) {
+                self.disconnect(endpoint, reason, message);
+                return;
+            }
+            if let Err(refusal) = self.throttle.check(ip, Instant::now()) {
+                let message = match refusal {
+                    Refusal::TooFast => LocalizedText::new("disconnect.too_fast"),
+                    Refusal::TooMany => LocalizedText::new("disconnect.too_many"),
+                    Refusal::Banned(left) => LocalizedText::new("disconnect.throttled")
+                        .with("seconds", left.as_secs_f64().ceil()),
+                };
+                self.disconnect(endpoint, DisconnectReason::Throttled, message);
+                return;
+            }
+        }
+        println!("Client connected: {:?}", endpoint);
+        // The host's own client is trusted: no password and no join restrictions
+        let local = endpoint.is_local();
+        let open = self.config.password_hash.is_none() && !self.config.login_required();
+        let Some(id) = self.game_state.player_ids.write().unwrap().allocate() else {
+            eprintln!("Out of player ids; turning {:?} away", endpoint);
+            self.disconnect(
+                endpoint,
+                DisconnectReason::ServerFull,
+                LocalizedText::new("disconnect.server_full"),
+            );
+            return;
+        };
+        let mut player = Player::new(id, endpoint, self.config.wire_format);
+        player.joined = local || open;
+        let refusal = match self.drain {
+            Some(_) if !local => Some((
+                DisconnectReason::Restarting,
+                LocalizedText::new("disconnect.restarting").into(),
+            )),
+            _ => self.admission_check(&player).filter(|_| !local),
+        };
+        if let Some((reason, message)) = refusal {
+            self.game_state.player_ids.write().unwrap().release(id);
+            self.disconnect(endpoint, reason, message);
+            return;
+        }
+        self.game_state.players.insert(id, player);
+        self.endpoints.insert(endpoint, id);
+        if let Some(ip) = ip {
+            self.throttle.opened(ip);
+        }
+        self.send(endpoint, &ClientMessage::AssignPlayerId { id });
+        if local || open {
+            self.player_joined(id, endpoint);
+        }
+    }
+
+    fn on_message(&mut self, endpoint: Endpoint, message: Result<ClientMessage, DecodeError>) {
+        // Frames still in flight when the server closed the connection
+        let Some(id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let mut joined = false;
+        let mut handshaken = false;
+        if let Some(mut player) = self.game_state.players.get_mut(id) {
+            player.last_seen = Instant::now();
+            joined = player.joined;
+            handshaken = player.snapshot_format.is_some();
+        }
+        // Logging in may be part of joining, but only past the server password
+        let can_log_in = joined || handshaken || self.config.password_hash.is_none();
+        let message = match message {
+            Ok(message) => message,
+            Err(e @ DecodeError::OverLimit(_)) => {
+                self.disconnect(endpoint, DisconnectReason::ProtocolError, e.to_string());
+                return;
+            }
+            Err(e @ DecodeError::UnknownMessage(_)) => {
+                // Newer clients may send messages this build doesn't know yet,
+                // so skip the frame and keep the connection
+                self.reject(endpoint, ErrorCode::UnknownMessage, e.to_string());
+                return;
+            }
+            Err(e) => {
+                self.reject(endpoint, ErrorCode::MalformedMessage, e.to_string());
+                return;
+            }
+        };
+        match message {
+            ClientMessage::Hello {
+                protocol_version,
+                wire_formats,
+                snapshot_formats,
+                password,
+            } => self.on_hello(
+                endpoint,
+                protocol_version,
+                &wire_formats,
+                &snapshot_formats,
+                password.as_deref(),
+            ),
+            ClientMessage::Register { .. }
+            | ClientMessage::Login { .. }
+            | ClientMessage::TokenLogin { .. }
+            | ClientMessage::SteamLogin { .. }
+            | ClientMessage::Resume { .. }
+                if !can_log_in =>
+            {
+                self.reject(
+                    endpoint,
+                    ErrorCode::HandshakeRequired,
+                    LocalizedText::new("error.handshake_required"),
+                )
+            }
+            ClientMessage::Register { username, password } => {
+                self.on_register(endpoint, username, password)
+            }
+            ClientMessage::Login { username, password } => {
+                self.on_login(endpoint, username, password)
+            }
+            ClientMessage::TokenLogin { token } => self.on_token_login(endpoint, &token),
+            ClientMessage::SteamLogin { ticket } => self.on_steam_login(endpoint, ticket),
+            ClientMessage::Resume { token } => self.on_resume(endpoint, &token),
+            _ if !joined => self.reject(
+                endpoint,
+                ErrorCode::HandshakeRequired,
+                LocalizedText::new(if self.config.login_required() {
+                    "error.log_in_first"
+                } else {
+                    "error.handshake_required"
+                }),
+            ),
+            ClientMessage::PlayerPosition { id, .. }
+            | ClientMessage::UpdateMessage { id, .. }
+            | ClientMessage::PlayerAppearance { id, .. }
+                if !self.check_sender(endpoint, id) => {}
+            // Nothing moves while the simulation is paused
+            ClientMessage::PlayerPosition { .. }
+            | ClientMessage::MoveEntity { .. }
+            | ClientMessage::UseAbility { .. }
+                if self.paused => {}
+            ClientMessage::PlayerPosition { id, x, y } => {
+                // The room's shard updates the position and tells the rest of the room
+                println!("Player position: {:?}", (id, x, y));
+                // NaN would slip past every range check: out of sight of
+                // everyone, yet still in play
+                if !(x.is_finite() && y.is_finite()) {
+                    self.reject(
+                        endpoint,
+                        ErrorCode::InvalidRequest,
+                        "a position needs finite coordinates",
+                    );
+                    return;
+                }
+                let Some(room) = self.game_state.players.get(&id).map(|p| p.room) else {
+                    return;
+                };
+                if !self.check_obstacles(endpoint, id, room, (x, y)) {
+                    return;
+                }
+                self.record_move(id, room, x, y);
+                let command = ShardCommand::Move {
+                    room,
+                    player_id: id,
+                    x,
+                    y,
+                };
+                self.shards.send(room, command);
+            }
+            ClientMessage::UpdateMessage { id, .. } if !self.check_guest_chat(endpoint, id) => {}
+            ClientMessage::UpdateMessage { id, message } => {
+                let Some(message) = self.slash_command(endpoint, id, message) else {
+                    return;
+                };
+                let message_start_time = std::time::Instant::now();
+                self.global_chat(id, message);
+                println!(
+                    "Message processing time: {:?}",
+                    message_start_time.elapsed()
+                );
+            }
+            ClientMessage::CreateRoom {
+                name,
+                password,
+                settings,
+            } => self.on_create_room(endpoint, name, password.as_deref(), settings),
+            ClientMessage::JoinRoom { room_id, password } => {
+                self.on_join_room(endpoint, room_id, password.as_deref())
+            }
+            ClientMessage::LeaveRoom => self.on_leave_room(endpoint),
+            ClientMessage::ConsoleCommand { line } => self.on_console_command(endpoint, &line),
+            ClientMessage::QueryPlayerStats { player_id } => {
+                self.on_query_player_stats(endpoint, player_id)
+            }
+            ClientMessage::SetName { name } => self.on_set_name(endpoint, &name),
+            ClientMessage::FriendAdd { player_id } => self.on_friend_add(endpoint, player_id),
+            ClientMessage::FriendAccept { account_id } => {
+                self.on_friend_accept(endpoint, account_id)
+            }
+            ClientMessage::FriendRemove { account_id } => {
+                self.on_friend_remove(endpoint, account_id)
+            }
+            ClientMessage::Whisper { target_id, text } => {
+                self.on_whisper(endpoint, target_id, text)
+            }
+            ClientMessage::WhisperAccount { account_id, text } => {
+                self.on_whisper_account(endpoint, account_id, text)
+            }
+            ClientMessage::Mute { player_id } => self.on_mute(endpoint, player_id, true),
+            ClientMessage::Unmute { player_id } => self.on_mute(endpoint, player_id, false),
+            ClientMessage::PartyInvite { player_id } => self.on_party_invite(endpoint, player_id),
+            ClientMessage::PartyAccept { party_id } => self.on_party_accept(endpoint, party_id),
+            ClientMessage::PartyLeave => self.on_party_leave(endpoint),
+            ClientMessage::PartyChat { text } => self.on_party_chat(endpoint, text),
+            ClientMessage::GuildCreate { name, tag } => {
+                self.on_guild_create(endpoint, &name, tag.as_deref())
+            }
+            ClientMessage::GuildInvite { player_id } => self.on_guild_invite(endpoint, player_id),
+            ClientMessage::GuildJoin { guild_id } => self.on_guild_join(endpoint, guild_id),
+            ClientMessage::GuildLeave => self.on_guild_leave(endpoint),
+            ClientMessage::GuildKick { account_id } => self.on_guild_kick(endpoint, account_id),
+            ClientMessage::GuildSetRank { account_id, rank } => {
+                self.on_guild_set_rank(endpoint, account_id, rank)
+            }
+            ClientMessage::GuildChat { text } => self.on_guild_chat(endpoint, text),
+            ClientMessage::QueryGuildRoster { guild_id } => {
+                self.on_query_guild_roster(endpoint, guild_id)
+            }
+            ClientMessage::Emote { id } => self.on_emote(endpoint, id),
+            ClientMessage::Chat { channel, text } => self.on_chat(endpoint, channel, text),
+            ClientMessage::SetTeam { team } => self.on_set_team(endpoint, team),
+            ClientMessage::DropFlag => self.on_drop_flag(endpoint),
+            ClientMessage::TransferAuthority { id, to } => {
+                self.on_transfer_authority(endpoint, id, to)
+            }
+            ClientMessage::MoveEntity { id, x, y } => self.on_move_entity(endpoint, id, x, y),
+            ClientMessage::UseAbility { name, x, y } => self.on_use_ability(endpoint, name, x, y),
+            ClientMessage::Interact { id } => self.on_interact(endpoint, id),
+            ClientMessage::Craft { recipe_id, nonce } => {
+                if self.check_nonce(endpoint, nonce) {
+                    self.on_craft(endpoint, recipe_id)
+                }
+            }
+            ClientMessage::FindMatch => self.on_find_match(endpoint),
+            ClientMessage::LeaveQueue => self.on_leave_queue(endpoint),
+            ClientMessage::SnapshotAck { tick } => self.on_snapshot_ack(endpoint, tick),
+            ClientMessage::NetStatsAck { sequence } => self.on_net_stats_ack(endpoint, sequence),
+            ClientMessage::ListRooms {
+                filters,
+                after,
+                limit,
+            } => self.on_list_rooms(endpoint, filters, after, limit),
+            ClientMessage::InviteToRoom { player_id } => {
+                self.on_invite_to_room(endpoint, player_id)
+            }
+            ClientMessage::AcceptInvite { room_id } => self.on_accept_invite(endpoint, room_id),
+            ClientMessage::KickFromRoom { player_id } => {
+                self.on_kick_from_room(endpoint, player_id)
+            }
+            ClientMessage::UpdateRoomSettings { settings } => {
+                self.on_update_room_settings(endpoint, settings)
+            }
+            ClientMessage::StartMatch => self.on_start_match(endpoint),
+            ClientMessage::EnterWorld { name } => self.on_enter_world(endpoint, name),
+            ClientMessage::ModifyWorld { position, change } => {
+                self.on_modify_world(endpoint, position, change)
+            }
+            ClientMessage::EditTerrain { .. } => self.reject(
+                endpoint,
+                ErrorCode::UnexpectedMessage,
+                LocalizedText::new("error.retired_message").with("use", "ModifyWorld"),
+            ),
+            ClientMessage::Buy {
+                entity,
+                item,
+                quantity,
+                nonce,
+            } => {
+                if self.check_nonce(endpoint, nonce) {
+                    self.on_trade(endpoint, entity, item, quantity, false, None)
+                }
+            }
+            ClientMessage::Sell {
+                entity,
+                item,
+                quantity,
+                id,
+                nonce,
+            } => {
+                if self.check_nonce(endpoint, nonce) {
+                    self.on_trade(endpoint, entity, item, quantity, true, id)
+                }
+            }
+            ClientMessage::MapVote { choice } => self.on_map_vote(endpoint, choice),
+            ClientMessage::Spectate { spectating } => self.on_spectate(endpoint, spectating),
+            ClientMessage::FetchChatHistory {
+                channel,
+                before,
+                limit,
+            } => self.on_fetch_chat_history(endpoint, channel, before, limit),
+            ClientMessage::PlayerAppearance {
+                id,
+                skin,
+                color,
+                accessories,
+            } => self.on_set_appearance(
+                endpoint,
+                id,
+                Appearance {
+                    skin,
+                    color,
+                    accessories,
+                },
+            ),
+            // Server-to-client messages have no meaning when sent by a client
+            ClientMessage::AssignPlayerId { .. }
+            | ClientMessage::OtherPlayerConnected { .. }
+            | ClientMessage::Welcome { .. }
+            | ClientMessage::WorldSnapshot { .. }
+            | ClientMessage::FlatSnapshot { .. }
+            | ClientMessage::ServerError { .. }
+            | ClientMessage::Disconnected { .. }
+            | ClientMessage::RoomJoined { .. }
+            | ClientMessage::RoomLeft { .. }
+            | ClientMessage::ConsoleOutput { .. }
+            | ClientMessage::Announcement { .. }
+            | ClientMessage::PlayerStats { .. }
+            | ClientMessage::ArchivedSnapshot { .. }
+            | ClientMessage::PlayerNamed { .. }
+            | ClientMessage::ChatMessage { .. }
+            | ClientMessage::JoinSnapshot { .. }
+            | ClientMessage::LoggedIn { .. }
+            | ClientMessage::FriendList { .. }
+            | ClientMessage::FriendPresence { .. }
+            | ClientMessage::WhisperMessage { .. }
+            | ClientMessage::PartyInvited { .. }
+            | ClientMessage::PartyUpdate { .. }
+            | ClientMessage::PartyLeft { .. }
+            | ClientMessage::PartyChatMessage { .. }
+            | ClientMessage::GuildInvited { .. }
+            | ClientMessage::GuildChatMessage { .. }
+            | ClientMessage::GuildRoster { .. }
+            | ClientMessage::GuildLeft { .. }
+            | ClientMessage::MailReceived { .. }
+            | ClientMessage::AchievementUnlocked { .. }
+            | ClientMessage::PlayerEmote { .. }
+            | ClientMessage::PlayerTeam { .. }
+            | ClientMessage::ChatHistory { .. }
+            | ClientMessage::SessionToken { .. }
+            | ClientMessage::Standby { .. }
+            | ClientMessage::AreaEvent { .. }
+            | ClientMessage::Trigger { .. }
+            | ClientMessage::PointCaptured { .. }
+            | ClientMessage::Flag { .. }
+            | ClientMessage::TeamScore { .. }
+            | ClientMessage::RoundWon { .. }
+            | ClientMessage::RoundOver { .. }
+            | ClientMessage::RoundTime { .. }
+            | ClientMessage::MapVoteStarted { .. }
+            | ClientMessage::MapChanged { .. }
+            | ClientMessage::EntitySpawned { .. }
+            | ClientMessage::EntityDespawned { .. }
+            | ClientMessage::EntityAuthority { .. }
+            | ClientMessage::EntityMoved { .. }
+            | ClientMessage::AbilityUsed { .. }
+            | ClientMessage::Experience { .. }
+            | ClientMessage::LevelUp { .. }
+            | ClientMessage::PlayerLevel { .. }
+            | ClientMessage::LootDropped { .. }
+            | ClientMessage::Inventory { .. }
+            | ClientMessage::Crafted { .. }
+            | ClientMessage::CraftFailed { .. }
+            | ClientMessage::Shop { .. }
+            | ClientMessage::Traded { .. }
+            | ClientMessage::TradeFailed { .. }
+            | ClientMessage::Queued { .. }
+            | ClientMessage::MatchFound { .. }
+            | ClientMessage::DeltaSnapshot { .. }
+            | ClientMessage::SimulationPaused
+            | ClientMessage::SimulationResumed
+            | ClientMessage::TimeScale { .. }
+            | ClientMessage::RoomList { .. }
+            | ClientMessage::Invite { .. }
+            | ClientMessage::RoomHost { .. }
+            | ClientMessage::RoomSettingsChanged { .. }
+            | ClientMessage::TerrainChanged { .. }
+            | ClientMessage::Terrain { .. }
+            | ClientMessage::TerrainChunk { .. }
+            | ClientMessage::ForgetChunk { .. }
+            | ClientMessage::HillControl { .. }
+            | ClientMessage::Objective { .. }
+            | ClientMessage::DifficultyChanged { .. }
+            | ClientMessage::NetStats { .. }
+            | ClientMessage::Identity { .. }
+            | ClientMessage::AbilityCasting { .. } => self.reject(
+                endpoint,
+                ErrorCode::UnexpectedMessage,
+                LocalizedText::new("error.server_only"),
+            ),
+        }
+    }
+
+    fn on_hello(
+        &mut self,
+        endpoint: Endpoint,
+        protocol_version: u32,
+        wire_formats: &[WireFormat],
+        snapshot_formats: &[SnapshotFormat],
+        password: Option<&str>,
+    ) {
+        if !self.endpoints.contains_key(&endpoint) {
+            return;
+        }
+        if protocol_version != PROTOCOL_VERSION {
+            self.reject(
+                endpoint,
+                ErrorCode::ProtocolMismatch,
+                LocalizedText::new("error.protocol_version")
+                    .with("client", protocol_version)
+                    .with("server", PROTOCOL_VERSION),
+            );
+            self.disconnect(
+                endpoint,
+                DisconnectReason::ProtocolError,
+                LocalizedText::new("disconnect.protocol_version"),
+            );
+            return;
+        }
+        let password_hash = self
+            .config
+            .password_hash
+            .as_ref()
+            .filter(|_| !endpoint.is_local());
+        let hello = PendingHello {
+            wire_formats: wire_formats.to_vec(),
+            snapshot_formats: snapshot_formats.to_vec(),
+        };
+        let Some(hash) = password_hash.cloned() else {
+            self.finish_hello(endpoint, hello);
+            return;
+        };
+        let Some(password) = password.map(str::to_string) else {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::WrongPassword,
+                LocalizedText::new("disconnect.wrong_password"),
+            );
+            return;
+        };
+        if !self.can_check_password(endpoint) {
+            return;
+        }
+        self.authenticate(endpoint, move || AuthOutcome::ServerPassword {
+            accepted: passwords::verify_password(&password, &hash),
+            hello: Box::new(hello),
+        });
+    }
+
+    // Once any server password checks out, off the game loop
+    fn finish_hello(&mut self, endpoint: Endpoint, hello: PendingHello) {
+        let Some(&id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let PendingHello {
+            wire_formats,
+            snapshot_formats,
+        } = hello;
+        let wire_format = codec::negotiate(
+            &wire_formats,
+            WireFormat::is_available,
+            self.config.wire_format,
+        );
+        let snapshot_format = codec::negotiate(
+            &snapshot_formats,
+            SnapshotFormat::is_available,
+            SnapshotFormat::Native,
+        );
+
+        // The decoder switches formats before the client can see the reply,
+        // which still goes out in the pre-handshake format
+        self.inbound
+            .send(Inbound::Negotiated(endpoint, wire_format))
+            .ok();
+        self.send(
+            endpoint,
+            &ClientMessage::Welcome {
+                id,
+                wire_format,
+                snapshot_format,
+            },
+        );
+        if self.paused {
+            self.send(endpoint, &ClientMessage::SimulationPaused);
+        }
+        if self.time_scale != 1.0 {
+            let scale = self.time_scale;
+            self.send(endpoint, &ClientMessage::TimeScale { scale });
+        }
+        // When guests aren't let in, a login still has to pass
+        let awaiting_login = self.config.login_required() && !endpoint.is_local();
+        let (newly_joined, joined) = match self.game_state.players.get_mut(&id) {
+            Some(mut player) => {
+                player.wire_format = wire_format;
+                player.snapshot_format = Some(snapshot_format);
+                if awaiting_login && player.account.is_none() {
+                    (false, player.joined)
+                } else {
+                    (!std::mem::replace(&mut player.joined, true), true)
+                }
+            }
+            None => (false, false),
+        };
+        self.send_identity(id);
+        if newly_joined {
+            self.player_joined(id, endpoint);
+        } else if let Some(player) = self.game_state.players.get(&id).filter(|_| joined) {
+            self.update_member(&player);
+            drop(player);
+            self.game_state.rebuild_roster();
+            self.send_session_token(id);
+        }
+        println!(
+            "Player {} negotiated {} messages and {:?} snapshots",
+            id, wire_format, snapshot_format
+        );
+        if joined {
+            self.introduce(id);
+        }
+    }
+
+    fn player_joined(&self, id: usize, endpoint: Endpoint) {
+        if let Some(player) = self.game_state.players.get(&id) {
+            self.add_member(&player);
+        }
+        self.game_state.rebuild_roster();
+        self.session_started(id);
+        self.send_session_token(id);
+        self.webhooks.notify(WebhookEvent::PlayerJoined {
+            player_id: id,
+            address: endpoint
+                .addr()
+                .map_or("local".to_string(), |addr| addr.ip().to_string()),
+        });
+    }
+
+    fn on_disconnected(&mut self, endpoint: Endpoint) {
+        println!("Client disconnected: {:?}", endpoint);
+        if !self.hold_player(endpoint) {
+            self.remove_player(endpoint);
+        }
+    }
+
+    fn remove_player(&mut self, endpoint: Endpoint) {
+        if let Some(id) = self.detach(endpoint) {
+            self.forget_player(id);
+        }
+    }
+
+    // Let go of the connection, leaving its player (if any) in the game
+    fn detach(&mut self, endpoint: Endpoint) -> Option<usize> {
+        self.authenticating.remove(&endpoint);
+        let id = self.endpoints.remove(&endpoint)?;
+        if let Some(addr) = endpoint.addr() {
+            let joined = self.game_state.players.get(&id).is_some_and(|p| p.joined);
+            self.throttle.closed(addr.ip(), joined, Instant::now());
+        }
+        Some(id)
+    }
+
+    // The player leaves the game, whether or not it is still connected
+    fn forget_player(&mut self, id: usize) {
+        self.held.remove(&id);
+        let Some((_, player)) = self.game_state.players.remove(&id) else {
+            return;
+        };
+        self.game_state.player_ids.write().unwrap().release(id);
+        if let Some(room_id) = player.room {
+            self.remove_from_room(room_id, id);
+        }
+        self.reclaim_entities(id);
+        self.match_queue.leave(id);
+        if let Some(abilities) = &mut self.abilities {
+            abilities.forget(id);
+        }
+        self.actions.forget(id);
+        let inviting = self.game_state.parties.write().unwrap().forget_invites(id);
+        for party_id in inviting {
+            self.party_changed(party_id);
+        }
+        if let Some(party_id) = player.party {
+            self.drop_from_party(id, party_id);
+        }
+        if player.joined {
+            self.remove_member(player.room, id);
+            self.game_state.rebuild_roster();
+            self.session_ended(&player);
+            self.record_action(id, "left the game");
+            if let Some(account) = player.account {
+                self.announce_presence(account, false, None);
+            }
+            self.webhooks
+                .notify(WebhookEvent::PlayerLeft { player_id: id });
+        }
+    }
+
+    // Tell the client why it is being dropped, then close the connection
+    fn disconnect(
+        &mut self,
+        endpoint: Endpoint,
+        reason: DisconnectReason,
+        message: impl Into<ServerText>,
+    ) {
+        self.close_connection(endpoint, reason, message);
+        self.remove_player(endpoint);
+    }
+
+    // `disconnect` without removing the connection's player
+    fn close_connection(
+        &self,
+        endpoint: Endpoint,
+        reason: DisconnectReason,
+        message: impl Into<ServerText>,
+    ) {
+        let (message, localized) = self.localize(message.into());
+        println!("Disconnecting {:?}: {:?} ({})", endpoint, reason, message);
+        let goodbye = ClientMessage::Disconnected {
+            reason,
+            message,
+            localized,
+        };
+        self.outbound
+            .send(Outbound::Close(
+                endpoint,
+                self.wire_format(endpoint),
+                goodbye,
+            ))
+            .ok();
+        self.inbound.send(Inbound::Closed(endpoint)).ok();
+    }
+
+    fn shutdown(&mut self) {
+        println!("Shutting down");
+        self.save_worlds();
+        let endpoints: Vec<Endpoint> = self.endpoints.keys().copied().collect();
+        for endpoint in endpoints {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::ServerShutdown,
+                LocalizedText::new("disconnect.shutdown"),
+            );
+        }
+    }
+
+    fn drop_idle_players(&mut self) {
+        let idle_timeout = self.config.idle_timeout();
+        let idle: Vec<(Endpoint, &str)> = self
+            .game_state
+            .players
+            .iter()
+            .filter(|p| !self.held.contains_key(&p.id))
+            .filter_map(|p| {
+                if !p.joined && p.connected_at.elapsed() > HANDSHAKE_TIMEOUT {
+                    Some((p.endpoint, "disconnect.handshake_timeout"))
+                } else if idle_timeout.is_some_and(|timeout| p.last_seen.elapsed() > timeout) {
+                    Some((p.endpoint, "disconnect.idle"))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (endpoint, key) in idle {
+            self.disconnect(endpoint, DisconnectReason::Timeout, LocalizedText::new(key));
+        }
+    }
+
+    fn on_tick(&mut self) {
+        let lag = Instant::now().saturating_duration_since(self.tick_due);
+        self.game_state.health.ticked(lag, self.drain.is_some());
+        self.schedule_tick();
+        self.ticks_run += 1;
+        self.check_overload(lag);
+        for _ in 0..self.simulation_steps() {
+            self.step_simulation();
+        }
+        let housekeeping = self.housekeeping_every();
+        if self.ticks_run.is_multiple_of(housekeeping) {
+            self.drop_idle_players();
+        }
+        self.persist.send(Persist::Flush).ok();
+        // Once a second
+        let second = self.config.snapshot_rate as u64;
+        if self.ticks_run.is_multiple_of(second) {
+            if self.ticks_run.is_multiple_of(housekeeping.max(second)) {
+                self.throttle.prune(Instant::now());
+                self.expire_sessions();
+                self.expire_held();
+                self.prune_trails();
+                self.prune_entities();
+                self.prune_maps();
+                self.prune_invites();
+                self.check_worlds();
+            }
+            if !self.paused {
+                self.check_queue();
+                self.think_bots();
+                self.fill_matches();
+            }
+            self.check_traffic();
+            self.refresh_jwks();
+            self.check_drain();
+            self.replicate_live();
+            if let Some(watchdog) = &self.watchdog {
+                watchdog.alive();
+            }
+        }
+
+        self.report_net_stats();
+        self.run_bots();
+        self.stream_chunks();
+        self.tick_shards();
+    }
+
+    // One tick of simulated time, however long it takes in real time
+    fn step_simulation(&mut self) {
+        self.tick += 1;
+        self.check_triggers();
+        self.check_flags();
+        self.check_hills();
+        self.check_rounds();
+        self.run_scenarios();
+        self.run_actions();
+        self.flush_updates();
+        self.move_bots();
+        // Once a simulated second
+        if self.tick.is_multiple_of(self.config.snapshot_rate as u64) {
+            self.regenerate_energy();
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Display names: checking `SetName`, keeping names unique within a room, and
+// telling players who is who, and what they look like, as they move between
+// rooms
+use super::outbound::Outbound;
+use super::shards;
+use super::Server;
+use crate::codec::WireFormat;
+use crate::endpoint::Endpoint;
+use crate::names;
+use crate::protocol::{ClientMessage, ErrorCode, NamedPlayer};
+use crate::rooms::RoomId;
+
+impl Server {
+    pub(super) fn on_set_name(&mut self, endpoint: Endpoint, name: &str) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let name = match self.config.names.check(name) {
+            Ok(name) => name,
+            Err(e) => {
+                self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                return;
+            }
+        };
+        let Some(room) = self.game_state.players.get(&player_id).map(|p| p.room) else {
+            return;
+        };
+        if self.name_taken(room, &name, player_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("`{}` is already taken", name),
+            );
+            return;
+        }
+        if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+            player.name = Some(name.clone());
+        }
+        println!("Player {} is now called {}", player_id, name);
+
+        // The sender asked, so it understands the answer even without `Hello`
+        let mut recipients = self.room_recipients(room, player_id);
+        recipients.push((endpoint, self.wire_format(endpoint)));
+        let tag = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.guild);
+        let message = ClientMessage::PlayerNamed {
+            id: player_id,
+            name,
+            tag: self.guild_tag(tag),
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+
+    // Whether someone other than `player_id` in `room` already goes by `name`
+    pub(super) fn name_taken(&self, room: Option<RoomId>, name: &str, player_id: usize) -> bool {
+        self.game_state.players.iter().any(|p| {
+            p.id != player_id
+                && p.joined
+                && p.room == room
+                && p.name.as_deref().is_some_and(|n| names::same_name(n, name))
+        })
+    }
+
+    // Refuse a move into `room` that would give it two players of the same name
+    pub(super) fn check_name_free(
+        &self,
+        endpoint: Endpoint,
+        player_id: usize,
+        room: Option<RoomId>,
+    ) -> bool {
+        let name = self
+            .game_state
+            .players
+            .get(&player_id)
+            .and_then(|p| p.name.clone());
+        let Some(name) = name.filter(|name| self.name_taken(room, name, player_id)) else {
+            return true;
+        };
+        let place = match room {
+            Some(room_id) => format!("room {}", room_id),
+            None => "the lobby".to_string(),
+        };
+        self.reject(
+            endpoint,
+            ErrorCode::InvalidRequest,
+            format!(
+                "`{}` is already taken in {}; pick another name first",
+                name, place
+            ),
+        );
+        false
+    }
+
+    // Show a player who and what is in the room they just entered, and show
+    // the room their name
+    pub(super) fn introduce(&self, player_id: usize) {
+        let Some((room, endpoint, wire_format, handshaken)) =
+            self.game_state.players.get(&player_id).map(|p| {
+                (
+                    p.room,
+                    p.endpoint,
+                    p.wire_format,
+                    p.snapshot_format.is_some(),
+                )
+            })
+        else {
+            return;
+        };
+        if handshaken {
+            let (positions, hidden) = self.positions_seen_by(room, player_id);
+            let players = positions
+                .into_iter()
+                .filter_map(|(id, x, y)| {
+                    let player = self.game_state.players.get(&id)?;
+                    Some(NamedPlayer {
+                        id,
+                        name: player.name.clone(),
+                        tag: self.guild_tag(player.guild),
+                        x,
+                        y,
+                        appearance: player.appearance.clone(),
+                        level: self.level_of(player.account),
+                    })
+                })
+                .collect();
+            self.send(
+                endpoint,
+                &ClientMessage::JoinSnapshot {
+                    room_id: room,
+                    players,
+                },
+            );
+            // The rest are named without giving away where they are; the
+            // snapshots they show up in bring their positions
+            for id in hidden {
+                self.describe_player(id, vec![(endpoint, wire_format)]);
+            }
+            self.send_entities(player_id);
+        }
+        let recipients = self.room_recipients(room, player_id);
+        if !recipients.is_empty() {
+            self.describe_player(player_id, recipients);
+        }
+    }
+
+    // Where the others in the room are, for those `player_id` sees under the
+    // room's fog of war, and the ids of those it doesn't
+    fn positions_seen_by(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+    ) -> (Vec<(usize, f32, f32)>, Vec<usize>) {
+        let shard = self.game_state.shard(room).read().unwrap();
+        let Some(members) = shard.rooms.get(&room) else {
+            return (Vec::new(), Vec::new());
+        };
+        let seen = shard
+            .fog_over(room)
+            .map(|(fog, obstacles)| shards::seen_by(members, player_id, fog, obstacles));
+        let mut positions = Vec::new();
+        let mut hidden = Vec::new();
+        let others = members.ids().iter().zip(members.xs()).zip(members.ys());
+        for ((&id, &x), &y) in others.filter(|((&id, _), _)| id != player_id) {
+            match seen.as_ref().is_none_or(|seen| seen.contains(&id)) {
+                true => positions.push((id, x, y)),
+                false => hidden.push(id),
+            }
+        }
+        (positions, hidden)
+    }
+
+    // Send `recipients` the player's name, appearance and level
+    fn describe_player(&self, player_id: usize, recipients: Vec<(Endpoint, WireFormat)>) {
+        let Some((name, appearance, guild, account)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|p| (p.name.clone(), p.appearance.clone(), p.guild, p.account))
+        else {
+            return;
+        };
+        if let Some(name) = name {
+            let message = ClientMessage::PlayerNamed {
+                id: player_id,
+                name,
+                tag: self.guild_tag(guild),
+            };
+            self.outbound
+                .send(Outbound::Send(recipients.clone(), message))
+                .ok();
+        }
+        let message = ClientMessage::PlayerAppearance {
+            id: player_id,
+            skin: appearance.skin,
+            color: appearance.color,
+            accessories: appearance.accessories,
+        };
+        self.outbound
+            .send(Outbound::Send(recipients.clone(), message))
+            .ok();
+        if let Some(level) = self.level_of(account) {
+            let message = ClientMessage::PlayerLevel {
+                id: player_id,
+                level,
+            };
+            self.outbound.send(Outbound::Send(recipients, message)).ok();
+        }
+    }
+
+    // Everyone in `room` but `player_id` who understands the naming and
+    // appearance messages
+    pub(super) fn room_recipients(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+    ) -> Vec<(Endpoint, WireFormat)> {
+        self.game_state
+            .players
+            .iter()
+            .filter(|p| {
+                p.id != player_id && p.joined && p.room == room && p.snapshot_format.is_some()
+            })
+            .map(|p| (p.endpoint, p.wire_format))
+            .collect()
+    }
+
+    // Everyone in `room`, for the messages of its game
+    pub(super) fn send_room(&self, room: Option<RoomId>, message: ClientMessage) {
+        // No one has id 0
+        let recipients = self.room_recipients(room, 0);
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+}
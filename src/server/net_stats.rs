@@ -0,0 +1,46 @@
+// Sending each joined client its `NetStats` and timing the acks that come
+// back (see `crate::net_stats`).
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::ClientMessage;
+use std::time::{Duration, Instant};
+
+impl Server {
+    pub(super) fn report_net_stats(&mut self) {
+        let now = Instant::now();
+        if !self.config.net_stats.enabled || now < self.net_stats_due {
+            return;
+        }
+        self.net_stats_due = now + Duration::from_millis(self.config.net_stats.interval_ms);
+        let mut reports = Vec::new();
+        for mut player in self.game_state.players.iter_mut() {
+            // Only connections the decoder counts; bots have none
+            let Some(counters) = self.game_state.traffic.get(player.endpoint) else {
+                continue;
+            };
+            if !player.joined {
+                continue;
+            }
+            let report = ClientMessage::NetStats {
+                sequence: player.net_probe.report(now),
+                bytes_in: counters.bytes_in,
+                bytes_out: counters.bytes_out,
+                rtt: player.net_probe.rtt(),
+                loss: player.net_probe.loss(now),
+            };
+            reports.push((player.endpoint, report));
+        }
+        for (endpoint, report) in reports {
+            self.send(endpoint, &report);
+        }
+    }
+
+    pub(super) fn on_net_stats_ack(&mut self, endpoint: Endpoint, sequence: u64) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+            player.net_probe.acked(sequence, Instant::now());
+        }
+    }
+}
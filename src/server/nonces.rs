@@ -0,0 +1,85 @@
+// Replay protection for actions that change what a player holds: each
+// `Craft`, `Buy` and `Sell` carries a nonce above any the session sent
+// before, so a captured frame sent again is refused instead of done twice.
+// Nonces need only increase; clients are free to skip numbers.
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ErrorCode, LocalizedText};
+
+impl Server {
+    // Whether the action carrying `nonce` may go ahead, noting it if so
+    pub(super) fn check_nonce(&self, endpoint: Endpoint, nonce: u64) -> bool {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return false;
+        };
+        let Some(mut player) = self.game_state.players.get_mut(&player_id) else {
+            return false;
+        };
+        let last = player.last_nonce;
+        if nonce > last {
+            player.last_nonce = nonce;
+            return true;
+        }
+        drop(player);
+        eprintln!(
+            "Player {} sent nonce {} after {}; replayed frame?",
+            player_id, nonce, last
+        );
+        self.reject(
+            endpoint,
+            ErrorCode::Replayed,
+            LocalizedText::new("error.replayed")
+                .with("nonce", nonce)
+                .with("last", last),
+        );
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::WireFormat;
+    use crate::config::ServerConfig;
+    use crate::protocol::{ClientMessage, ErrorCode};
+    use crate::server::{spawn, LocalClient};
+    use std::time::Duration;
+
+    // Whether the server went ahead with a `Craft` carrying `nonce`, which
+    // fails either way: there are no recipes
+    fn crafted(client: &LocalClient, nonce: u64) -> bool {
+        let craft = ClientMessage::Craft {
+            recipe_id: "bread".to_string(),
+            nonce,
+        };
+        client.send(&WireFormat::Bincode.encode(&craft));
+        loop {
+            let frame = client.recv_timeout(Duration::from_secs(5)).unwrap();
+            match WireFormat::Bincode.decode(&frame).unwrap() {
+                ClientMessage::CraftFailed { .. } => return true,
+                ClientMessage::ServerError {
+                    code: ErrorCode::Replayed,
+                    ..
+                } => return false,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn nonces_must_keep_increasing() {
+        let data_dir = std::env::temp_dir().join(format!("nonces-{}", std::process::id()));
+        let mut config = ServerConfig::default();
+        config.listen_addr = "127.0.0.1:0".to_string();
+        config.data_dir = data_dir.clone();
+        let server = spawn(config).unwrap();
+        let client = server.connect_local();
+        assert!(crafted(&client, 5));
+        assert!(!crafted(&client, 5));
+        assert!(!crafted(&client, 3));
+        // Skipping numbers is fine
+        assert!(crafted(&client, 9));
+        drop(client);
+        drop(server);
+        std::fs::remove_dir_all(data_dir).ok();
+    }
+}
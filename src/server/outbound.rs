@@ -0,0 +1,133 @@
+// The broadcast stage: encodes what the game loop sends, at most once per
+// wire format and into pooled buffers, applies outbound chaos, and writes the
+// frames, sending those that may be lost the unreliable way where the
+// transport has one.
+use super::transport::Connections;
+use crate::buffers::BufferPool;
+use crate::chaos::ChaosConfig;
+use crate::codec::WireFormat;
+use crate::endpoint::Endpoint;
+use crate::protocol::ClientMessage;
+use crate::reliability::{default_delivery, Delivery};
+use crate::traffic::Traffic;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+pub enum Outbound {
+    Send(Vec<(Endpoint, WireFormat)>, ClientMessage),
+    // Sent around chaos mode, since the connection is closed right after
+    Close(Endpoint, WireFormat, ClientMessage),
+    // Hand a connection behind a gateway to another worker, after whatever
+    // was sent to it before
+    Move(Endpoint, usize, Vec<u8>),
+}
+
+pub(super) struct Broadcaster {
+    pub(super) connections: Connections,
+    pub(super) chaos: Option<ChaosConfig>,
+    pub(super) pool: BufferPool,
+    pub(super) traffic: Arc<Traffic>,
+}
+
+impl Broadcaster {
+    pub(super) async fn run(mut self, mut outbound: UnboundedReceiver<Outbound>) {
+        while let Some(command) = outbound.recv().await {
+            match command {
+                Outbound::Send(recipients, message) => {
+                    let reliable = default_delivery(&message) == Delivery::ReliableOrdered;
+                    let kind: &'static str = (&message).into();
+                    let mut frames = FrameCache::new(message);
+                    for (endpoint, format) in recipients {
+                        let frame = frames.get(format, &mut self.pool);
+                        self.traffic.sent(endpoint, kind, frame.len());
+                        self.transmit(endpoint, frame, reliable);
+                    }
+                    frames.recycle(&mut self.pool);
+                }
+                Outbound::Close(endpoint, format, message) => {
+                    let mut frame = self.pool.take();
+                    format.encode_into(&message, &mut frame);
+                    self.traffic.sent(endpoint, (&message).into(), frame.len());
+                    self.connections.send(endpoint, &frame);
+                    self.connections.close(endpoint);
+                    self.pool.give_back(frame);
+                }
+                Outbound::Move(endpoint, worker, transfer) => {
+                    self.traffic.closed(endpoint);
+                    self.connections.move_to(endpoint, worker, transfer);
+                }
+            }
+        }
+    }
+
+    fn transmit(&self, endpoint: Endpoint, data: &[u8], reliable: bool) {
+        let Some(chaos) = self.chaos.as_ref().filter(|c| c.outbound) else {
+            send(&self.connections, endpoint, data, reliable);
+            return;
+        };
+        for delay in chaos.plan() {
+            if delay.is_zero() {
+                send(&self.connections, endpoint, data, reliable);
+            } else {
+                let connections = self.connections.clone();
+                hold(connections, endpoint, data.to_vec(), reliable, delay);
+            }
+        }
+    }
+}
+
+fn hold(
+    connections: Connections,
+    endpoint: Endpoint,
+    data: Vec<u8>,
+    reliable: bool,
+    delay: Duration,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        send(&connections, endpoint, &data, reliable);
+    });
+}
+
+fn send(connections: &Connections, endpoint: Endpoint, data: &[u8], reliable: bool) {
+    match reliable {
+        true => connections.send(endpoint, data),
+        false => connections.send_unreliable(endpoint, data),
+    }
+}
+
+// Encodes one message lazily, at most once per wire format
+struct FrameCache {
+    message: ClientMessage,
+    frames: Vec<(WireFormat, Vec<u8>)>,
+}
+
+impl FrameCache {
+    fn new(message: ClientMessage) -> Self {
+        FrameCache {
+            message,
+            frames: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, format: WireFormat, pool: &mut BufferPool) -> &[u8] {
+        let index = match self.frames.iter().position(|(f, _)| *f == format) {
+            Some(index) => index,
+            None => {
+                let mut frame = pool.take();
+                format.encode_into(&self.message, &mut frame);
+                self.frames.push((format, frame));
+                self.frames.len() - 1
+            }
+        };
+        &self.frames[index].1
+    }
+
+    // Frames are copied out by the time they are sent, so the buffers can go back
+    fn recycle(self, pool: &mut BufferPool) {
+        for (_, frame) in self.frames {
+            pool.give_back(frame);
+        }
+    }
+}
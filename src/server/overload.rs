@@ -0,0 +1,100 @@
+// Watching what each tick costs against its budget and shedding work while it
+// runs over (see `crate::overload`).
+use super::Server;
+use crate::overload::{Change, Shed};
+use std::time::{Duration, Instant};
+
+// How often to keep warning while ticks overrun
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+// Shed housekeeping runs this many seconds apart
+const HOUSEKEEPING_SECS: u64 = 5;
+
+impl Server {
+    pub(super) fn tick_budget(&self) -> Duration {
+        self.config.overload.budget_ms.map_or(
+            Duration::from_secs(1) / self.config.snapshot_rate,
+            Duration::from_millis,
+        )
+    }
+
+    // Count this tick against the budget, given how late it ran
+    pub(super) fn check_overload(&mut self, lag: Duration) {
+        let health = self.game_state.health.clone();
+        let cost = lag.max(health.take_shard_tick());
+        let budget = self.tick_budget();
+        let config = &self.config.overload;
+        match self.overload.record(config, cost, budget) {
+            Some(Change::Shed(step)) => eprintln!(
+                "OVERLOADED: {} ticks in a row over the {:.1?} budget (the last took {:.1?}); \
+                 shedding {}, {} of {} steps",
+                config.overrun_ticks,
+                budget,
+                cost,
+                step.name(),
+                self.overload.level(),
+                config.shed.len()
+            ),
+            Some(Change::Restored(step)) => println!(
+                "Ticks are back within budget; restored {}, {} steps still shed",
+                step.name(),
+                self.overload.level()
+            ),
+            None => {}
+        }
+        if cost > budget
+            && self
+                .overload_warned
+                .is_none_or(|warned| warned.elapsed() >= WARNING_INTERVAL)
+        {
+            eprintln!(
+                "WARNING: tick {} took {:.1?} of a {:.1?} budget ({} overruns so far, {} steps shed)",
+                self.tick,
+                cost,
+                budget,
+                self.overload.overruns(),
+                self.overload.level()
+            );
+            self.overload_warned = Some(Instant::now());
+        }
+        health.loaded(cost, self.overload.level(), self.overload.overruns());
+    }
+
+    // Idle players are dropped every this many ticks; the rest of housekeeping
+    // runs once a second, or this often when that is less
+    pub(super) fn housekeeping_every(&self) -> u64 {
+        if self
+            .overload
+            .shedding(&self.config.overload, Shed::Housekeeping)
+        {
+            self.config.snapshot_rate as u64 * HOUSEKEEPING_SECS
+        } else {
+            1
+        }
+    }
+
+    // Send this tick's snapshots, unless they are being sent less often,
+    // through `update_rates.positions` or shedding, or the simulation hasn't
+    // moved on since the last
+    pub(super) fn tick_shards(&mut self) {
+        let config = &self.config.overload;
+        let positions_every = self.positions_every();
+        let rate = self.config.snapshot_rate / positions_every as u32;
+        let every = positions_every * self.overload.snapshot_every(config, rate);
+        if self.ticks_run.is_multiple_of(every) && self.tick > self.snapshotted {
+            let lobby = !self.overload.shedding(config, Shed::LobbySnapshots);
+            self.shards.tick(self.tick, lobby);
+            self.snapshotted = self.tick;
+        }
+    }
+
+    pub(super) fn load_status(&self) -> String {
+        format!(
+            "{:.1?} a tick of {:.1?}, shedding {} of {} steps, {} overruns",
+            self.game_state.health.tick_cost(),
+            self.tick_budget(),
+            self.overload.level(),
+            self.config.overload.shed.len(),
+            self.overload.overruns()
+        )
+    }
+}
@@ -0,0 +1,412 @@
+// Parties: invites, membership and party chat, and moving the party along
+// with its leader. Followers skip room passwords; the leader vouched for them
+// by bringing them.
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::chat::ChannelKey;
+use crate::endpoint::Endpoint;
+use crate::names;
+use crate::parties::{Left, PartyId};
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use crate::rooms::RoomId;
+
+impl Server {
+    pub(super) fn on_party_invite(&mut self, endpoint: Endpoint, player_id: usize) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let target = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined)
+            .map(|p| {
+                (
+                    p.endpoint,
+                    p.snapshot_format.is_some(),
+                    p.party.is_some(),
+                    p.muted.contains(&own_id),
+                )
+            });
+        let (own_party, name) = self
+            .game_state
+            .players
+            .get(&own_id)
+            .map(|p| (p.party, p.name.clone()))
+            .unwrap_or_default();
+        let parties = self.game_state.parties.read().unwrap();
+        let party = own_party.and_then(|id| parties.get(id));
+        let invalid = |problem: String| Some((ErrorCode::InvalidRequest, problem));
+        let problem = match target {
+            _ if player_id == own_id => invalid("you can't invite yourself".to_string()),
+            None => invalid(format!("player {} is not online", player_id)),
+            Some((_, false, _, _)) => invalid(format!("player {} can't join parties", player_id)),
+            Some((_, _, true, _)) => invalid(format!("player {} is already in a party", player_id)),
+            _ => match party {
+                Some(party) if party.leader != own_id => Some((
+                    ErrorCode::PermissionDenied,
+                    "only the party leader can invite".to_string(),
+                )),
+                Some(party) if party.invited.contains(&player_id) => {
+                    invalid(format!("you already invited player {}", player_id))
+                }
+                Some(party) if party.size() >= self.config.parties.max_size => {
+                    invalid("your party is full".to_string())
+                }
+                _ => None,
+            },
+        };
+        drop(parties);
+        if let Some((code, problem)) = problem {
+            self.reject(endpoint, code, problem);
+            return;
+        }
+        let Some((target_endpoint, _, _, muted)) = target else {
+            return;
+        };
+        let mut parties = self.game_state.parties.write().unwrap();
+        let party_id = match own_party {
+            Some(party_id) => party_id,
+            None => {
+                let party_id = parties.create(own_id);
+                println!("Player {} started party {}", own_id, party_id);
+                party_id
+            }
+        };
+        parties.invite(party_id, player_id);
+        drop(parties);
+        if let Some(mut player) = self.game_state.players.get_mut(&own_id) {
+            player.party = Some(party_id);
+        }
+        // As with whispers, an invite from someone muted goes nowhere
+        if !muted {
+            self.send(
+                target_endpoint,
+                &ClientMessage::PartyInvited {
+                    party_id,
+                    from_id: own_id,
+                    name,
+                },
+            );
+        }
+        println!(
+            "Player {} invited player {} to party {}",
+            own_id, player_id, party_id
+        );
+        self.party_changed(party_id);
+    }
+
+    pub(super) fn on_party_accept(&mut self, endpoint: Endpoint, party_id: PartyId) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let in_party = self
+            .game_state
+            .players
+            .get(&own_id)
+            .is_some_and(|p| p.party.is_some());
+        let leader = self
+            .game_state
+            .parties
+            .read()
+            .unwrap()
+            .get(party_id)
+            .filter(|party| party.invited.contains(&own_id))
+            .map(|party| party.leader);
+        let leader_room =
+            leader.and_then(|leader| self.game_state.players.get(&leader).map(|p| p.room));
+        let problem = match leader_room {
+            _ if in_party => Some("leave your party first".to_string()),
+            None => Some(format!("no invite from party {}", party_id)),
+            Some(room) => self.party_blocker(&[own_id], room),
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        self.game_state
+            .parties
+            .write()
+            .unwrap()
+            .join(party_id, own_id);
+        if let Some(mut player) = self.game_state.players.get_mut(&own_id) {
+            player.party = Some(party_id);
+        }
+        println!("Player {} joined party {}", own_id, party_id);
+        if let Some(room) = leader_room {
+            self.party_move(&[own_id], room);
+        }
+        self.party_changed(party_id);
+        self.count_event(own_id, GameEvent::PartyJoined);
+    }
+
+    pub(super) fn on_party_leave(&mut self, endpoint: Endpoint) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.leave_party(own_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.not_in_party"),
+            );
+        }
+    }
+
+    pub(super) fn on_party_chat(&mut self, endpoint: Endpoint, text: String) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let Some((party_id, name)) = self
+            .game_state
+            .players
+            .get(&own_id)
+            .and_then(|p| Some((p.party?, p.name.clone())))
+        else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.not_in_party"),
+            );
+            return;
+        };
+        if !self.check_guest_chat(endpoint, own_id) {
+            return;
+        }
+        self.record_chat(
+            ChannelKey::Party(party_id),
+            own_id,
+            name.clone(),
+            text.clone(),
+        );
+        let message = ClientMessage::PartyChatMessage {
+            party_id,
+            from_id: own_id,
+            name,
+            text,
+        };
+        for (member, member_endpoint) in self.party_endpoints(party_id) {
+            let muted = self
+                .game_state
+                .players
+                .get(&member)
+                .is_some_and(|p| p.muted.contains(&own_id));
+            if !muted {
+                self.send(member_endpoint, &message);
+            }
+        }
+    }
+
+    // Take a player out of their party, telling them and whoever is left.
+    // Returns whether they were in one.
+    fn leave_party(&mut self, player_id: usize) -> bool {
+        let Some((party_id, endpoint)) = self
+            .game_state
+            .players
+            .get_mut(&player_id)
+            .and_then(|mut p| Some((p.party.take()?, p.endpoint)))
+        else {
+            return false;
+        };
+        self.send(endpoint, &ClientMessage::PartyLeft { party_id });
+        self.drop_from_party(player_id, party_id);
+        true
+    }
+
+    // Remove a player who left or disconnected from `party_id`, telling
+    // whoever is left
+    pub(super) fn drop_from_party(&mut self, player_id: usize, party_id: PartyId) {
+        let left = self
+            .game_state
+            .parties
+            .write()
+            .unwrap()
+            .leave(party_id, player_id);
+        println!("Player {} left party {}", player_id, party_id);
+        match left {
+            Left::Remaining => self.party_changed(party_id),
+            Left::Disbanded(last) => {
+                println!("Party {} disbanded", party_id);
+                self.update_chat_history(|history| history.forget_party(party_id));
+                let last = last.and_then(|id| {
+                    let mut player = self.game_state.players.get_mut(&id)?;
+                    player.party = None;
+                    Some(player.endpoint)
+                });
+                if let Some(endpoint) = last {
+                    self.send(endpoint, &ClientMessage::PartyLeft { party_id });
+                }
+            }
+            Left::Gone => {}
+        }
+    }
+
+    // Why the rest of `leader_id`'s party can't follow them into `room`, if
+    // they lead one and it can't. `new_room` is set for a room that is about
+    // to be created.
+    pub(super) fn party_follow_blocker(
+        &self,
+        leader_id: usize,
+        room: Option<RoomId>,
+        new_room: bool,
+    ) -> Option<String> {
+        let followers = self.party_followers(leader_id, room);
+        if followers.is_empty() {
+            return None;
+        }
+        let mut movers = followers;
+        movers.push(leader_id);
+        if new_room {
+            self.party_name_clash(&movers)
+        } else {
+            self.party_blocker(&movers, room)
+        }
+    }
+
+    // Bring the rest of `leader_id`'s party to wherever the leader just went
+    pub(super) fn party_follow(&mut self, leader_id: usize) {
+        let Some(room) = self.game_state.players.get(&leader_id).map(|p| p.room) else {
+            return;
+        };
+        let followers = self.party_followers(leader_id, room);
+        self.party_move(&followers, room);
+    }
+
+    // The members of the party `leader_id` leads who aren't already in `room`
+    pub(super) fn party_followers(&self, leader_id: usize, room: Option<RoomId>) -> Vec<usize> {
+        let Some(party_id) = self
+            .game_state
+            .players
+            .get(&leader_id)
+            .and_then(|p| p.party)
+        else {
+            return Vec::new();
+        };
+        let parties = self.game_state.parties.read().unwrap();
+        let Some(party) = parties.get(party_id).filter(|p| p.leader == leader_id) else {
+            return Vec::new();
+        };
+        party
+            .members
+            .iter()
+            .copied()
+            .filter(|&id| {
+                id != leader_id
+                    && self
+                        .game_state
+                        .players
+                        .get(&id)
+                        .is_some_and(|p| p.room != room)
+            })
+            .collect()
+    }
+
+    // Why `movers` can't all go into `room`: ranked rooms keep guests out, and
+    // names stay unique within the room
+    fn party_blocker(&self, movers: &[usize], room: Option<RoomId>) -> Option<String> {
+        let (ranked, free) = room
+            .and_then(|room_id| {
+                let rooms = self.game_state.rooms.read().unwrap();
+                let room = rooms.get(room_id)?;
+                Some((room.ranked, self.seats_left(room, movers)))
+            })
+            .unwrap_or((false, None));
+        let place = match room {
+            Some(room_id) => format!("room {}", room_id),
+            None => "the lobby".to_string(),
+        };
+        let moving = movers
+            .iter()
+            .filter(|&&id| {
+                self.game_state
+                    .players
+                    .get(&id)
+                    .is_some_and(|p| p.room != room)
+            })
+            .count();
+        if let Some(free) = free.filter(|&free| moving > free) {
+            return Some(format!("{} only has room for {} more", place, free));
+        }
+        for &id in movers {
+            let Some(player) = self.game_state.players.get(&id) else {
+                continue;
+            };
+            if player.room == room {
+                continue;
+            }
+            if ranked && player.is_guest() {
+                return Some(format!(
+                    "player {} is a guest and can't join ranked {}",
+                    id, place
+                ));
+            }
+            let name = player.name.clone();
+            drop(player);
+            if let Some(name) = name.filter(|name| self.name_taken(room, name, id)) {
+                return Some(format!("`{}` is already taken in {}", name, place));
+            }
+        }
+        self.party_name_clash(movers)
+    }
+
+    // Two of `movers` going by the same name can't end up in one room
+    pub(super) fn party_name_clash(&self, movers: &[usize]) -> Option<String> {
+        let names: Vec<String> = movers
+            .iter()
+            .filter_map(|id| self.game_state.players.get(id)?.name.clone())
+            .collect();
+        names.iter().enumerate().find_map(|(i, name)| {
+            names[i + 1..]
+                .iter()
+                .any(|other| names::same_name(name, other))
+                .then(|| format!("two party members go by `{}`", name))
+        })
+    }
+
+    fn party_move(&mut self, players: &[usize], room: Option<RoomId>) {
+        for &id in players {
+            let Some(endpoint) = self.game_state.players.get(&id).map(|p| p.endpoint) else {
+                continue;
+            };
+            match room {
+                Some(room_id) => self.enter_room(endpoint, id, room_id),
+                None => {
+                    if self.leave_current_room(endpoint, id) {
+                        self.introduce(id);
+                        self.update_presence(id);
+                    }
+                }
+            }
+        }
+    }
+
+    // Tell every member how the party looks now
+    pub(super) fn party_changed(&self, party_id: PartyId) {
+        let parties = self.game_state.parties.read().unwrap();
+        let Some(party) = parties.get(party_id) else {
+            return;
+        };
+        let message = ClientMessage::PartyUpdate {
+            party_id,
+            leader_id: party.leader,
+            members: party.members.iter().copied().collect(),
+            invited: party.invited.iter().copied().collect(),
+        };
+        drop(parties);
+        for (_, endpoint) in self.party_endpoints(party_id) {
+            self.send(endpoint, &message);
+        }
+    }
+
+    fn party_endpoints(&self, party_id: PartyId) -> Vec<(usize, Endpoint)> {
+        let parties = self.game_state.parties.read().unwrap();
+        let Some(party) = parties.get(party_id) else {
+            return Vec::new();
+        };
+        party
+            .members
+            .iter()
+            .filter_map(|&id| Some((id, self.game_state.players.get(&id)?.endpoint)))
+            .collect()
+    }
+}
@@ -0,0 +1,59 @@
+// Pausing the simulation, for tournaments and for looking into live issues,
+// and running it slower or faster than real time. While paused the game loop
+// keeps running housekeeping, but the simulation tick stands still: triggers,
+// modes, round clocks, energy and backfill wait, no snapshots go out and
+// players' moves are dropped. The time scale sets how many simulation ticks
+// each tick of the loop is worth, so cooldowns, timers and dead reckoning
+// all run at its pace; clients are told it to slow down their own.
+use super::Server;
+use crate::protocol::ClientMessage;
+
+// The slowest and fastest the simulation may run, against real time
+const MIN_TIME_SCALE: f32 = 0.1;
+const MAX_TIME_SCALE: f32 = 4.0;
+
+impl Server {
+    pub(super) fn pause(&mut self) -> Result<String, String> {
+        if self.paused {
+            return Err("the simulation is already paused".to_string());
+        }
+        self.paused = true;
+        println!("Simulation paused at tick {}", self.tick);
+        self.broadcast(&ClientMessage::SimulationPaused, 0);
+        Ok(format!("paused at tick {}", self.tick))
+    }
+
+    pub(super) fn resume(&mut self) -> Result<String, String> {
+        if !self.paused {
+            return Err("the simulation is not paused".to_string());
+        }
+        self.paused = false;
+        println!("Simulation resumed at tick {}", self.tick);
+        self.broadcast(&ClientMessage::SimulationResumed, 0);
+        Ok(format!("resumed at tick {}", self.tick))
+    }
+
+    pub(super) fn set_time_scale(&mut self, scale: f32) -> Result<String, String> {
+        if !(MIN_TIME_SCALE..=MAX_TIME_SCALE).contains(&scale) {
+            return Err(format!(
+                "the time scale must be between {} and {}",
+                MIN_TIME_SCALE, MAX_TIME_SCALE
+            ));
+        }
+        self.time_scale = scale;
+        println!("Simulation runs at {}x", scale);
+        self.broadcast(&ClientMessage::TimeScale { scale }, 0);
+        Ok(format!("the simulation runs at {}x", scale))
+    }
+
+    // How many simulation ticks this tick of the loop is worth
+    pub(super) fn simulation_steps(&mut self) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        self.time_carry += self.time_scale;
+        let steps = self.time_carry.floor();
+        self.time_carry -= steps;
+        steps as u32
+    }
+}
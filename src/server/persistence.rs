@@ -0,0 +1,86 @@
+// The persistence stage: all file writes happen here, on a blocking thread,
+// so a slow disk never holds up the game loop. So do the reads of worlds
+// loaded on demand, which come back to the loop as signals; queued behind
+// the writes, they never read a world from before its last save.
+use super::{Signal, Signals};
+use crate::capture::{CaptureEvent, CaptureWriter};
+use crate::health::Health;
+use crate::storage::Storage;
+use crate::worlds::WorldSave;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+pub enum Persist {
+    // A storage document, already serialized by the game loop
+    Save(String, Value),
+    // An entry for a storage document's journal
+    Journal(String, Value),
+    Capture(u64, CaptureEvent),
+    Flush,
+    LoadWorld(String),
+}
+
+pub(super) struct Persister {
+    pub(super) storage: Storage,
+    pub(super) capture: Option<CaptureWriter>,
+    pub(super) health: Arc<Health>,
+    pub(super) signals: Signals,
+}
+
+impl Persister {
+    // Runs until every sender is gone
+    pub(super) fn run(mut self, mut persist: UnboundedReceiver<Persist>) {
+        while let Some(job) = persist.blocking_recv() {
+            match job {
+                Persist::Save(name, value) => {
+                    let saved = self.storage.save(&name, &value);
+                    if let Err(e) = &saved {
+                        eprintln!("Failed to persist {}: {}", name, e);
+                    }
+                    self.health
+                        .stored(saved.map_err(|e| format!("{}: {}", name, e)));
+                }
+                Persist::Journal(name, entry) => {
+                    let saved = self.storage.append(&name, &entry);
+                    if let Err(e) = &saved {
+                        eprintln!("Failed to journal {}: {}", name, e);
+                    }
+                    self.health
+                        .stored(saved.map_err(|e| format!("{}: {}", name, e)));
+                }
+                Persist::Capture(connection, event) => {
+                    if let Some(Err(e)) = self
+                        .capture
+                        .as_mut()
+                        .map(|capture| capture.record(connection, &event))
+                    {
+                        eprintln!("Stopping capture: {}", e);
+                        self.capture = None;
+                    }
+                }
+                Persist::Flush => self.flush_capture(),
+                Persist::LoadWorld(name) => {
+                    let key = WorldSave::storage_key(&name);
+                    let mut save: WorldSave = self.storage.load(&key);
+                    // Fold the journal in before the world is changed again
+                    if let Some(journal) = self.storage.journal(&key) {
+                        save.replay(journal);
+                        if let Err(e) = self.storage.save(&key, &save) {
+                            eprintln!("Failed to persist {}: {}", key, e);
+                        }
+                    }
+                    self.signals.send(Signal::WorldLoaded(name, save)).ok();
+                }
+            }
+        }
+        self.flush_capture();
+    }
+
+    fn flush_capture(&mut self) {
+        if let Some(Err(e)) = self.capture.as_mut().map(CaptureWriter::flush) {
+            eprintln!("Stopping capture: {}", e);
+            self.capture = None;
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Data requests for one account: everything stored about it as a JSON
+// document, and erasure. Erasing deletes what is the account's alone and
+// strips it from what it shares with others, so a whisper it left stays in
+// the recipient's mailbox with no sender.
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::AchievementProgress;
+use crate::inventory::Inventories;
+use crate::progression::Progression;
+use crate::protocol::{DisconnectReason, LocalizedText};
+use serde_json::{json, Value};
+
+impl Server {
+    pub(super) fn export_account(&self, account_id: AccountId) -> Result<Value, String> {
+        let accounts = self.game_state.accounts.read().unwrap();
+        let Some(account) = accounts.by_id(account_id) else {
+            return Err(format!("no account {}", account_id));
+        };
+        let login = if accounts
+            .find(&account.username)
+            .is_some_and(|a| a.id == account_id)
+        {
+            "password"
+        } else if accounts.steam.values().any(|a| a.id == account_id) {
+            "steam"
+        } else {
+            "external"
+        };
+        // Never the password hash
+        let profile = json!({
+            "id": account.id,
+            "username": account.username,
+            "login": login,
+            "created": account.created,
+            "last_login": account.last_login,
+        });
+        drop(accounts);
+
+        let friends = self.game_state.friends.read().unwrap();
+        let friends = json!({
+            "friends": friends.of(account_id),
+            "incoming": friends.incoming(account_id),
+            "outgoing": friends.outgoing(account_id),
+        });
+        let guilds = self.game_state.guilds.read().unwrap();
+        let guild = guilds.of(account_id).map(|guild| {
+            json!({
+                "id": guild.id,
+                "name": guild.name,
+                "tag": guild.tag,
+                "rank": guild.rank(account_id),
+            })
+        });
+        let invites: Vec<_> = guilds
+            .guilds
+            .values()
+            .filter(|guild| guild.invited.contains(&account_id))
+            .map(|guild| guild.id)
+            .collect();
+        drop(guilds);
+        let mail = self.game_state.mail.read().unwrap();
+        let mail = json!({
+            "mailbox": mail.boxes.get(&account_id).cloned().unwrap_or_default(),
+            "sent": mail.sent_by(account_id),
+        });
+        let progress = self.game_state.achievements.read().unwrap();
+        let achievements = json!({
+            "counts": progress.counts.get(&account_id),
+            "unlocked": progress.unlocked.get(&account_id),
+        });
+        drop(progress);
+        let xp = self.game_state.progression.read().unwrap().xp(account_id);
+        let progression = json!({
+            "xp": xp,
+            "level": self.config.progression.level(xp),
+        });
+        let inventory = self.game_state.inventories.read().unwrap().of(account_id);
+        let chat: Vec<Value> = self
+            .game_state
+            .chat_history
+            .read()
+            .unwrap()
+            .sent_by(account_id)
+            .into_iter()
+            .map(|(channel, line)| json!({ "channel": channel, "line": line }))
+            .collect();
+        let identity = format!("account:{}", account_id);
+        let modes = self.game_state.modes.read().unwrap();
+        let restrictions = json!({
+            "whitelisted": modes.whitelist.contains(&identity),
+            "banned": modes.banned.contains(&identity),
+        });
+        Ok(json!({
+            "account": profile,
+            "friends": friends,
+            "guild": guild,
+            "guild_invites": invites,
+            "mail": mail,
+            "achievements": achievements,
+            "progression": progression,
+            "inventory": inventory,
+            "chat": chat,
+            "restrictions": restrictions,
+        }))
+    }
+
+    // Irreversibly remove `account_id`, dropping its session first if it is
+    // online. Returns its username.
+    pub(super) fn erase_account(&mut self, account_id: AccountId) -> Result<String, String> {
+        let session = self.session_of(account_id).map(|(endpoint, _)| endpoint);
+        if let Some(endpoint) = session {
+            self.disconnect(
+                endpoint,
+                DisconnectReason::Kicked,
+                LocalizedText::new("disconnect.account_deleted"),
+            );
+        }
+        let Some(account) = self.update_accounts(|accounts| accounts.remove(account_id)) else {
+            return Err(format!("no account {}", account_id));
+        };
+
+        let others = self.update_friends(|friends| friends.forget(account_id));
+        for other in others {
+            self.send_friend_list(other);
+        }
+        let guild = self
+            .game_state
+            .guilds
+            .read()
+            .unwrap()
+            .of(account_id)
+            .map(|guild| guild.id);
+        if let Some(guild_id) = guild {
+            self.remove_from_guild(guild_id, account_id);
+        }
+        self.update_guilds(|guilds| guilds.uninvite(account_id));
+        self.update_mail(|mail| {
+            mail.take(account_id);
+            mail.anonymize_sender(account_id);
+        });
+        {
+            let mut progress = self.game_state.achievements.write().unwrap();
+            progress.forget(account_id);
+            self.save(AchievementProgress::STORAGE_KEY, &*progress);
+        }
+        {
+            let mut progression = self.game_state.progression.write().unwrap();
+            progression.forget(account_id);
+            self.save(Progression::STORAGE_KEY, &*progression);
+        }
+        {
+            let mut inventories = self.game_state.inventories.write().unwrap();
+            inventories.forget(account_id);
+            self.save(Inventories::STORAGE_KEY, &*inventories);
+        }
+        self.update_chat_history(|history| {
+            history.forget_account(account_id);
+        });
+        let identity = format!("account:{}", account_id);
+        self.update_modes(|modes| {
+            modes.whitelist.remove(&identity);
+            modes.banned.remove(&identity);
+        });
+        println!("Erased account {} ({})", account_id, account.username);
+        Ok(account.username)
+    }
+}
@@ -0,0 +1,59 @@
+// XP and levels (see `crate::progression`): awarding XP for gameplay events,
+// telling the player, and their room when they level up.
+use super::Server;
+use crate::accounts::AccountId;
+use crate::achievements::GameEvent;
+use crate::progression::Progression;
+use crate::protocol::ClientMessage;
+
+impl Server {
+    // Whatever `event` is worth, for `account`, whether or not it is online
+    pub(super) fn award_xp(&self, account: AccountId, event: GameEvent) {
+        let Some(&amount) = self.config.progression.xp.get(&event) else {
+            return;
+        };
+        if amount == 0 {
+            return;
+        }
+        let (before, after) = {
+            let mut progression = self.game_state.progression.write().unwrap();
+            let xp = progression.award(account, amount);
+            self.save(Progression::STORAGE_KEY, &*progression);
+            xp
+        };
+        let config = &self.config.progression;
+        let (was, level) = (config.level(before), config.level(after));
+        if level > was {
+            println!("Account {} reached level {}", account, level);
+        }
+        let Some((player_id, room)) = self
+            .game_state
+            .players
+            .iter()
+            .find(|p| p.joined && p.account == Some(account))
+            .map(|p| (p.id, p.room))
+        else {
+            return;
+        };
+        self.send_experience(player_id, after);
+        if level > was {
+            self.record_action(player_id, format!("reached level {}", level));
+            self.send_room(room, ClientMessage::LevelUp { player_id, level });
+        }
+    }
+
+    pub(super) fn send_experience(&self, player_id: usize, xp: u64) {
+        let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) else {
+            return;
+        };
+        let level = self.config.progression.level(xp);
+        let next = self.config.progression.next(level);
+        self.send(endpoint, &ClientMessage::Experience { xp, level, next });
+    }
+
+    // Guests have no level
+    pub(super) fn level_of(&self, account: Option<AccountId>) -> Option<u32> {
+        let xp = self.game_state.progression.read().unwrap().xp(account?);
+        Some(self.config.progression.level(xp))
+    }
+}
@@ -0,0 +1,161 @@
+// Accepting clients over QUIC (see `crate::quic`). Each connection has a task
+// reading its stream and one reading its datagrams, both turning what arrives
+// into the same `Inbound` events TCP connections do, and a writer
+// emptying its queue in `Connections`.
+use super::inbound::Inbound;
+use super::transport::{read_frame, write_frame, Connections, QuicFrame};
+use crate::endpoint::Endpoint;
+use crate::quic::{QuicConfig, ALPN};
+use quinn::{Connection, Incoming, SendStream};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::io;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+// How long a client has after the handshake to open its stream
+const STREAM_TIMEOUT: Duration = Duration::from_secs(10);
+// How long what was queued before the server closed a connection gets to
+// reach the client
+const CLOSE_LINGER: Duration = Duration::from_secs(1);
+
+// Accept QUIC connections on `config.listen_addr` for the rest of the run.
+// Stream frames bigger than `max_frame_bytes` close the connection.
+pub(super) fn spawn_listener(
+    config: &QuicConfig,
+    addr: &str,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) -> io::Result<()> {
+    let server_config = server_config(config)?;
+    let socket_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("QUIC address {} resolves to nothing", addr),
+        )
+    })?;
+    let endpoint = quinn::Endpoint::server(server_config, socket_addr)?;
+    println!("QUIC listening on {}", addr);
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            let (inbound, connections) = (inbound.clone(), connections.clone());
+            tokio::spawn(serve(incoming, inbound, connections, max_frame_bytes));
+        }
+    });
+    Ok(())
+}
+
+fn server_config(config: &QuicConfig) -> io::Result<quinn::ServerConfig> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let (Some(cert_file), Some(key_file)) = (&config.cert_file, &config.key_file) else {
+        return Err(invalid("QUIC needs `cert_file` and `key_file`".to_string()));
+    };
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(format!("{}: {}", cert_file.display(), e)))?;
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| invalid(format!("{}: {}", key_file.display(), e)))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| invalid(e.to_string()))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(format!("{}: {}", cert_file.display(), e)))?;
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+async fn serve(
+    incoming: Incoming,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) {
+    let connection = match incoming.await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("QUIC handshake failed: {}", e);
+            return;
+        }
+    };
+    let (send, recv) = match tokio::time::timeout(STREAM_TIMEOUT, connection.accept_bi()).await {
+        Ok(Ok(streams)) => streams,
+        _ => {
+            connection.close(0u32.into(), b"no stream");
+            return;
+        }
+    };
+    let id = connections.next_quic_id();
+    let endpoint = Endpoint::Quic {
+        connection: id,
+        addr: connection.remote_address(),
+    };
+    let (frames, queue) = mpsc::unbounded_channel();
+    connections.add_quic(id, frames);
+    inbound.send(Inbound::Accepted(endpoint)).ok();
+    tokio::spawn(write(connection.clone(), send, queue));
+    let datagrams = tokio::spawn(read_datagrams(
+        connection.clone(),
+        endpoint,
+        inbound.clone(),
+    ));
+
+    let mut stream = BufReader::new(recv);
+    while let Ok(Some(frame)) = read_frame(&mut stream, max_frame_bytes).await {
+        inbound.send(Inbound::Frame(endpoint, frame)).ok();
+    }
+    datagrams.abort();
+    // Unless the server closed it first
+    if connections.remove_quic(id) {
+        connection.close(0u32.into(), b"closed");
+        inbound.send(Inbound::Disconnected(endpoint)).ok();
+    }
+}
+
+async fn read_datagrams(
+    connection: Connection,
+    endpoint: Endpoint,
+    inbound: UnboundedSender<Inbound>,
+) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        inbound
+            .send(Inbound::Frame(endpoint, datagram.to_vec()))
+            .ok();
+    }
+}
+
+async fn write(
+    connection: Connection,
+    mut send: SendStream,
+    mut queue: UnboundedReceiver<QuicFrame>,
+) {
+    while let Some(frame) = queue.recv().await {
+        let data = match frame {
+            QuicFrame::Datagram(data)
+                if connection
+                    .max_datagram_size()
+                    .is_some_and(|max| data.len() <= max) =>
+            {
+                connection.send_datagram(data.into()).ok();
+                continue;
+            }
+            QuicFrame::Stream(data) | QuicFrame::Datagram(data) => data,
+        };
+        if write_frame(&mut send, &data).await.is_err() {
+            return;
+        }
+    }
+    // The server closed the connection
+    send.finish().ok();
+    tokio::time::timeout(CLOSE_LINGER, send.stopped())
+        .await
+        .ok();
+    connection.close(0u32.into(), b"closed");
+}
@@ -0,0 +1,55 @@
+// Holding updates back to their category's rate (see `crate::rates`) and
+// sending what is left once it comes round.
+use super::Server;
+use crate::protocol::ClientMessage;
+use crate::rates;
+use crate::rooms::{RoomId, TeamId};
+
+impl Server {
+    pub(super) fn positions_every(&self) -> u64 {
+        rates::every(
+            self.config.update_rates.positions,
+            self.config.snapshot_rate,
+        )
+    }
+
+    pub(super) fn post_score(&mut self, room: Option<RoomId>, team: TeamId, score: u32) {
+        if self.config.update_rates.scoreboard.is_none() {
+            self.send_room(room, ClientMessage::TeamScore { team, score });
+            return;
+        }
+        self.pending_scores.insert((room, team), score);
+    }
+
+    pub(super) fn post_entity_move(&mut self, room: Option<RoomId>, id: u64, x: f32, y: f32) {
+        if self.config.update_rates.world.is_none() {
+            self.send_room(room, ClientMessage::EntityMoved { id, x, y });
+            return;
+        }
+        self.pending_moves.insert(id, (room, x, y));
+    }
+
+    // A despawned entity's last move isn't sent after it is gone
+    pub(super) fn forget_entity_move(&mut self, id: u64) {
+        self.pending_moves.remove(&id);
+    }
+
+    // Once a tick, sending what is due
+    pub(super) fn flush_updates(&mut self) {
+        let rate = self.config.snapshot_rate;
+        let rates = &self.config.update_rates;
+        if self
+            .tick
+            .is_multiple_of(rates::every(rates.scoreboard, rate))
+        {
+            for ((room, team), score) in std::mem::take(&mut self.pending_scores) {
+                self.send_room(room, ClientMessage::TeamScore { team, score });
+            }
+        }
+        if self.tick.is_multiple_of(rates::every(rates.world, rate)) {
+            for (id, (room, x, y)) in std::mem::take(&mut self.pending_moves) {
+                self.send_room(room, ClientMessage::EntityMoved { id, x, y });
+            }
+        }
+    }
+}
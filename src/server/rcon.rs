@@ -0,0 +1,173 @@
+// Source RCON (https://developer.valvesoftware.com/wiki/Source_RCON_Protocol),
+// so hosting panels and tools like mcrcon can run admin commands remotely
+use super::admin::submit_command;
+use super::Signals;
+use crate::passwords;
+use crate::roles::Role;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const SERVERDATA_AUTH: i32 = 3;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+
+// Packets carry at most 4096 bytes after the size field: id, type, body and two nulls
+const MAX_PACKET_SIZE: usize = 4096;
+const MAX_BODY_SIZE: usize = MAX_PACKET_SIZE - 10;
+
+// Connections open at once; more are closed as soon as they are accepted
+const MAX_CONNECTIONS: usize = 4;
+// Wrong passwords a connection may send before it is closed, each answered
+// only after `AUTH_FAILURE_DELAY`
+const MAX_AUTH_ATTEMPTS: u32 = 3;
+const AUTH_FAILURE_DELAY: Duration = Duration::from_secs(2);
+// How long a connection may sit without authenticating
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+// Accept RCON connections on `addr`; each one authenticates with the password
+// matching `password_hash` and then runs commands as `Role::Owner`
+pub fn spawn_rcon(signals: Signals, addr: &str, password_hash: String) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("RCON listening on {}", addr);
+    let password_hash = Arc::new(password_hash);
+    let open = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if open.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                open.fetch_sub(1, Ordering::Relaxed);
+                println!("RCON client {} refused: too many connections", peer);
+                continue;
+            }
+            let signals = signals.clone();
+            let password_hash = password_hash.clone();
+            let open = open.clone();
+            thread::spawn(move || {
+                println!("RCON client connected: {}", peer);
+                match serve(stream, &signals, &password_hash) {
+                    Err(e) if e.kind() != io::ErrorKind::UnexpectedEof => {
+                        println!("RCON client {} disconnected: {}", peer, e)
+                    }
+                    _ => println!("RCON client {} disconnected", peer),
+                }
+                open.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, signals: &Signals, password_hash: &str) -> io::Result<()> {
+    let mut authenticated = false;
+    let mut failures = 0;
+    stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    loop {
+        let packet = read_packet(&mut stream)?;
+        match packet.kind {
+            SERVERDATA_AUTH => {
+                authenticated = passwords::verify_password(&packet.body, password_hash);
+                if !authenticated {
+                    failures += 1;
+                    println!("RCON authentication failed");
+                    thread::sleep(AUTH_FAILURE_DELAY);
+                }
+                // Source servers send an empty response before the auth result,
+                // and some clients wait for it
+                write_packet(&mut stream, packet.id, SERVERDATA_RESPONSE_VALUE, "")?;
+                let id = if authenticated { packet.id } else { -1 };
+                write_packet(&mut stream, id, SERVERDATA_AUTH_RESPONSE, "")?;
+                if authenticated {
+                    stream.set_read_timeout(None)?;
+                } else if failures >= MAX_AUTH_ATTEMPTS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "too many failed authentication attempts",
+                    ));
+                }
+            }
+            SERVERDATA_EXECCOMMAND if authenticated => {
+                let text = match submit_command(signals, &packet.body, Role::Owner) {
+                    Some(Ok(text)) => text,
+                    Some(Err(e)) => format!("error: {}", e),
+                    None => return Err(io::Error::other("the server is shutting down")),
+                };
+                write_response(&mut stream, packet.id, &text)?;
+            }
+            SERVERDATA_EXECCOMMAND => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "command sent before authenticating",
+                ))
+            }
+            // Clients send an empty `RESPONSE_VALUE` after a command and wait for
+            // its echo to know a multi-packet response is complete
+            _ => write_packet(&mut stream, packet.id, SERVERDATA_RESPONSE_VALUE, "")?,
+        }
+    }
+}
+
+fn read_packet(stream: &mut impl Read) -> io::Result<Packet> {
+    let mut size = [0; 4];
+    stream.read_exact(&mut size)?;
+    let size = i32::from_le_bytes(size);
+    if !(10..=MAX_PACKET_SIZE as i32).contains(&size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid packet size {}", size),
+        ));
+    }
+    let mut data = vec![0; size as usize];
+    stream.read_exact(&mut data)?;
+
+    let field = |i: usize| i32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+    // The body is null-terminated and followed by an empty string
+    let body = &data[8..data.len() - 2];
+    Ok(Packet {
+        id: field(0),
+        kind: field(4),
+        body: String::from_utf8_lossy(body)
+            .trim_end_matches('\0')
+            .to_string(),
+    })
+}
+
+fn write_packet(stream: &mut impl Write, id: i32, kind: i32, body: &str) -> io::Result<()> {
+    let mut data = Vec::with_capacity(body.len() + 14);
+    data.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    data.extend_from_slice(&id.to_le_bytes());
+    data.extend_from_slice(&kind.to_le_bytes());
+    data.extend_from_slice(body.as_bytes());
+    data.extend_from_slice(&[0, 0]);
+    stream.write_all(&data)
+}
+
+// Split a command's output across as many packets as it needs
+fn write_response(stream: &mut impl Write, id: i32, text: &str) -> io::Result<()> {
+    let mut rest = text;
+    loop {
+        let mut end = rest.len().min(MAX_BODY_SIZE);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        write_packet(stream, id, SERVERDATA_RESPONSE_VALUE, &rest[..end])?;
+        rest = &rest[end..];
+        if rest.is_empty() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// The primary's side of `crate::replication`: letting standbys in, and the
+// replication stage, which writes the stream to each of them on a blocking
+// thread so a slow standby never holds up the game loop.
+use super::{Server, Signal, Signals};
+use crate::accounts::Accounts;
+use crate::achievements::AchievementProgress;
+use crate::analytics::Analytics;
+use crate::chat::ChatHistory;
+use crate::friends::Friends;
+use crate::guilds::Guilds;
+use crate::history::MatchHistory;
+use crate::inventory::Inventories;
+use crate::mail::Mailboxes;
+use crate::passwords;
+use crate::progression::Progression;
+use crate::protocol::ClientMessage;
+use crate::replication::{Follow, Frame};
+use crate::state::ServerModes;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+// How long a connecting standby has to send its token
+const FOLLOW_TIMEOUT: Duration = Duration::from_secs(5);
+// A standby that takes longer than this over a frame is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub enum Replicate {
+    // A standby that sent the right token, and the full state to start it on
+    Attach(TcpStream, Vec<Frame>),
+    Send(Frame),
+}
+
+// Accept standbys on `addr`; each sends a token matching `token_hash`
+pub(super) fn spawn_listener(signals: Signals, addr: &str, token_hash: String) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Streaming to standbys on {}", addr);
+    let token_hash = Arc::new(token_hash);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let signals = signals.clone();
+            let token_hash = token_hash.clone();
+            thread::spawn(move || match read_follow(&stream) {
+                Ok(follow) if passwords::verify_password(&follow.token, &token_hash) => {
+                    signals
+                        .send(Signal::Standby(stream, follow.public_addr))
+                        .ok();
+                }
+                _ => println!("Refused a standby without the right token"),
+            });
+        }
+    });
+    Ok(())
+}
+
+fn read_follow(stream: &TcpStream) -> io::Result<Follow> {
+    stream.set_read_timeout(Some(FOLLOW_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(io::Error::from)
+}
+
+#[derive(Default)]
+pub(super) struct Replicator {
+    standbys: Vec<TcpStream>,
+    // The last `Live` line written, so an unchanged one goes out as a heartbeat
+    last_live: String,
+}
+
+impl Replicator {
+    // Runs until every sender is gone
+    pub(super) fn run(mut self, mut replicate: UnboundedReceiver<Replicate>) {
+        while let Some(job) = replicate.blocking_recv() {
+            match job {
+                Replicate::Attach(stream, frames) => {
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+                    let peer = stream
+                        .peer_addr()
+                        .map(|a| a.to_string())
+                        .unwrap_or_default();
+                    let mut standby = vec![stream];
+                    for frame in &frames {
+                        write_line(&mut standby, &encode(frame));
+                    }
+                    if !standby.is_empty() {
+                        println!("Standby {} is following", peer);
+                        self.standbys.append(&mut standby);
+                    }
+                }
+                Replicate::Send(frame) => {
+                    let mut line = encode(&frame);
+                    if matches!(frame, Frame::Live(_)) {
+                        if line == self.last_live {
+                            line = encode(&Frame::Heartbeat);
+                        } else {
+                            self.last_live = line.clone();
+                        }
+                    }
+                    write_line(&mut self.standbys, &line);
+                }
+            }
+        }
+    }
+}
+
+fn encode(frame: &Frame) -> String {
+    let mut line = serde_json::to_string(frame).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+// Write to every standby, dropping the ones that fail
+fn write_line(standbys: &mut Vec<TcpStream>, line: &str) {
+    standbys.retain_mut(|standby| match standby.write_all(line.as_bytes()) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("Dropped a standby: {}", e);
+            false
+        }
+    });
+}
+
+impl Server {
+    // Start a new standby off with every store and the live state
+    pub(super) fn on_standby(&mut self, stream: TcpStream, public_addr: Option<String>) {
+        let Some(replicate) = &self.replicate else {
+            return;
+        };
+        let mut frames = self.stores();
+        frames.push(Frame::Live(self.handoff_state()));
+        replicate.send(Replicate::Attach(stream, frames)).ok();
+        if let Some(addr) = public_addr {
+            self.standby_addr = Some(addr);
+            let ids: Vec<usize> = self.game_state.players.iter().map(|p| p.id).collect();
+            for id in ids {
+                self.send_standby_addr(id);
+            }
+        }
+    }
+
+    // Tell a player where to resume if this server dies, once a standby
+    // gave an address. Only clients that sent `Hello` know the message.
+    pub(super) fn send_standby_addr(&self, player_id: usize) {
+        let Some(addr) = &self.standby_addr else {
+            return;
+        };
+        let Some(endpoint) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.joined && p.snapshot_format.is_some())
+            .map(|p| p.endpoint)
+        else {
+            return;
+        };
+        let addr = addr.clone();
+        self.send(endpoint, &ClientMessage::Standby { addr });
+    }
+
+    // Every store, as `save` would hand it to the persister
+    fn stores(&self) -> Vec<Frame> {
+        let state = &self.game_state;
+        let mut stores = vec![
+            store(ServerModes::STORAGE_KEY, &*state.modes.read().unwrap()),
+            store(Analytics::STORAGE_KEY, &*state.analytics.read().unwrap()),
+            store(Accounts::STORAGE_KEY, &*state.accounts.read().unwrap()),
+            store(Friends::STORAGE_KEY, &*state.friends.read().unwrap()),
+            store(Guilds::STORAGE_KEY, &*state.guilds.read().unwrap()),
+            store(Mailboxes::STORAGE_KEY, &*state.mail.read().unwrap()),
+            store(
+                AchievementProgress::STORAGE_KEY,
+                &*state.achievements.read().unwrap(),
+            ),
+            store(
+                Progression::STORAGE_KEY,
+                &*state.progression.read().unwrap(),
+            ),
+            store(
+                Inventories::STORAGE_KEY,
+                &*state.inventories.read().unwrap(),
+            ),
+            store(
+                MatchHistory::STORAGE_KEY,
+                &*state.match_history.read().unwrap(),
+            ),
+        ];
+        if self.config.chat_history.persist {
+            let history = state.chat_history.read().unwrap();
+            stores.push(store(ChatHistory::STORAGE_KEY, &*history));
+        }
+        stores.into_iter().flatten().collect()
+    }
+
+    pub(super) fn replicate(&self, frame: Frame) {
+        if let Some(replicate) = &self.replicate {
+            replicate.send(Replicate::Send(frame)).ok();
+        }
+    }
+
+    // Once a second, which doubles as the heartbeat
+    pub(super) fn replicate_live(&self) {
+        if self.replicate.is_some() {
+            self.replicate(Frame::Live(self.handoff_state()));
+        }
+    }
+}
+
+fn store(name: &str, value: &impl Serialize) -> Option<Frame> {
+    match serde_json::to_value(value) {
+        Ok(value) => Some(Frame::Store {
+            name: name.to_string(),
+            value,
+        }),
+        Err(e) => {
+            eprintln!("Failed to replicate {}: {}", name, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use super::accounts::{AuthOutcome, PendingRoom};
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::endpoint::Endpoint;
+use crate::passwords;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText, RoomFilters, RoomSummary};
+use crate::room_settings::{GameMode, RoomSettings};
+use crate::rooms::{RoomId, Rooms};
+use crate::webhooks::WebhookEvent;
+
+const MAX_ROOM_NAME_LEN: usize = 32;
+// The most rooms a `RoomList` page carries
+const MAX_ROOM_PAGE: usize = 50;
+
+impl Server {
+    pub(super) fn on_create_room(
+        &mut self,
+        endpoint: Endpoint,
+        name: String,
+        password: Option<&str>,
+        settings: RoomSettings,
+    ) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.check_not_draining(endpoint) {
+            return;
+        }
+        let map =
+            match settings.validate(&self.config.room_settings, &self.room_maps, &self.scenarios) {
+                Ok(map) => map,
+                Err(e) => {
+                    self.reject(endpoint, ErrorCode::InvalidRequest, e);
+                    return;
+                }
+            };
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_ROOM_NAME_LEN {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("room names must be 1-{} characters", MAX_ROOM_NAME_LEN),
+            );
+            return;
+        }
+        if let Some(problem) = self.party_follow_blocker(player_id, None, true) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        let room = PendingRoom {
+            name,
+            settings,
+            map,
+        };
+        match password.filter(|p| !p.is_empty()) {
+            Some(password) => {
+                if !self.can_check_password(endpoint) {
+                    return;
+                }
+                let password = password.to_string();
+                self.authenticate(endpoint, move || AuthOutcome::RoomHashed {
+                    password_hash: passwords::hash_password(&password),
+                    room: Box::new(room),
+                });
+            }
+            None => self.create_room(endpoint, room, None),
+        }
+    }
+
+    // Once any password is hashed, off the game loop
+    pub(super) fn create_room(
+        &mut self,
+        endpoint: Endpoint,
+        room: PendingRoom,
+        password_hash: Option<String>,
+    ) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let PendingRoom {
+            name,
+            settings,
+            map,
+        } = room;
+        // Things may have moved on while the password was hashed
+        if password_hash.is_some() {
+            if !self.check_not_draining(endpoint) {
+                return;
+            }
+            if let Some(problem) = self.party_follow_blocker(player_id, None, true) {
+                self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+                return;
+            }
+        }
+        let mut rooms = self.game_state.rooms.write().unwrap();
+        let room_id = rooms.create(name.clone(), password_hash, false);
+        if let Some(room) = rooms.get_mut(room_id) {
+            room.settings = settings;
+            room.host = Some(player_id);
+        }
+        drop(rooms);
+        if map.is_some() {
+            self.install_map(Some(room_id), map);
+        }
+        self.webhooks
+            .notify(WebhookEvent::RoomCreated { room_id, name });
+        self.match_started(Some(room_id));
+        self.enter_room(endpoint, player_id, room_id);
+        self.count_event(player_id, GameEvent::RoomCreated);
+        self.party_follow(player_id);
+    }
+
+    pub(super) fn on_join_room(
+        &mut self,
+        endpoint: Endpoint,
+        room_id: RoomId,
+        password: Option<&str>,
+    ) {
+        self.join_room(endpoint, room_id, password, None);
+    }
+
+    // `checked` is the password hash the password was already found to
+    // match, off the game loop; the room's hash must still be that one
+    pub(super) fn join_room(
+        &mut self,
+        endpoint: Endpoint,
+        room_id: RoomId,
+        password: Option<&str>,
+        checked: Option<&str>,
+    ) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.check_not_draining(endpoint) {
+            return;
+        }
+        let then = ClientMessage::JoinRoom {
+            room_id,
+            password: password.map(str::to_string),
+        };
+        if self.move_for_room(endpoint, player_id, room_id, then) {
+            return;
+        }
+        let rooms = self.game_state.rooms.read().unwrap();
+        let Some(room) = rooms.get(room_id) else {
+            drop(rooms);
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRoom,
+                LocalizedText::new("error.no_room").with("room", room_id),
+            );
+            return;
+        };
+        let guest = self
+            .game_state
+            .players
+            .get(&player_id)
+            .is_some_and(|p| p.is_guest());
+        if room.ranked && guest {
+            drop(rooms);
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                LocalizedText::new("error.ranked_room").with("room", room_id),
+            );
+            return;
+        }
+        if room.kicked.contains(&player_id) {
+            drop(rooms);
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                LocalizedText::new("error.kicked_from_room").with("room", room_id),
+            );
+            return;
+        }
+        let invited = self.invited(player_id, room_id);
+        if self.seats_left(room, &[player_id]) == Some(0) && !room.members.contains(&player_id) {
+            drop(rooms);
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                LocalizedText::new("error.room_full").with("room", room_id),
+            );
+            return;
+        }
+        let password_hash = room
+            .password_hash
+            .clone()
+            .filter(|hash| !invited && checked != Some(hash.as_str()));
+        drop(rooms);
+        if let Some(password_hash) = password_hash {
+            let Some(password) = password.map(str::to_string) else {
+                self.reject(
+                    endpoint,
+                    ErrorCode::WrongPassword,
+                    LocalizedText::new("error.room_password").with("room", room_id),
+                );
+                return;
+            };
+            if !self.can_check_password(endpoint) {
+                return;
+            }
+            self.authenticate(endpoint, move || AuthOutcome::RoomPassword {
+                room_id,
+                accepted: passwords::verify_password(&password, &password_hash),
+                password_hash,
+            });
+            return;
+        }
+        if !self.check_name_free(endpoint, player_id, Some(room_id)) {
+            return;
+        }
+        if let Some(problem) = self.party_follow_blocker(player_id, Some(room_id), false) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        self.enter_room(endpoint, player_id, room_id);
+        self.party_follow(player_id);
+    }
+
+    pub(super) fn on_leave_room(&mut self, endpoint: Endpoint) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let in_room = self
+            .game_state
+            .players
+            .get(&player_id)
+            .is_some_and(|p| p.room.is_some());
+        if in_room && !self.check_name_free(endpoint, player_id, None) {
+            return;
+        }
+        if let Some(problem) = self.party_follow_blocker(player_id, None, false) {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        if !self.leave_current_room(endpoint, player_id) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRoom,
+                LocalizedText::new("error.not_in_room"),
+            );
+            return;
+        }
+        self.introduce(player_id);
+        self.update_presence(player_id);
+        self.party_follow(player_id);
+    }
+
+    pub(super) fn enter_room(&mut self, endpoint: Endpoint, player_id: usize, room_id: RoomId) {
+        self.leave_current_room(endpoint, player_id);
+        self.match_queue.leave(player_id);
+        self.room_invites.remove(&(player_id, room_id));
+
+        let mut rooms = self.game_state.rooms.write().unwrap();
+        rooms.join(room_id, player_id);
+        let name = rooms
+            .get(room_id)
+            .map(|r| r.name.clone())
+            .unwrap_or_default();
+        drop(rooms);
+
+        if let Some(mut player) = self.game_state.players.get_mut(&player_id) {
+            player.room = Some(room_id);
+        }
+        self.reclaim_entities(player_id);
+        self.move_member(player_id, None, Some(room_id));
+        println!("Player {} joined room {} ({})", player_id, room_id, name);
+        self.record_action(player_id, format!("joined room {} ({})", room_id, name));
+        self.send(endpoint, &ClientMessage::RoomJoined { room_id, name });
+        self.introduce(player_id);
+        self.send_teams(player_id);
+        self.send_map(player_id);
+        self.send_flags(player_id);
+        self.send_hills(player_id);
+        self.send_round(player_id);
+        self.send_host(player_id);
+        self.send_difficulty(player_id);
+        self.enter_scenario(player_id, room_id);
+        self.update_presence(player_id);
+        self.count_event(player_id, GameEvent::RoomJoined);
+    }
+
+    // Returns whether the player was in a room
+    pub(super) fn leave_current_room(&mut self, endpoint: Endpoint, player_id: usize) -> bool {
+        let room_id = self
+            .game_state
+            .players
+            .get_mut(&player_id)
+            .and_then(|mut p| {
+                p.team = None;
+                p.spectating = false;
+                p.room.take()
+            });
+        let Some(room_id) = room_id else {
+            return false;
+        };
+        self.remove_from_room(room_id, player_id);
+        self.move_member(player_id, Some(room_id), None);
+        self.record_action(player_id, format!("left room {}", room_id));
+        self.send(endpoint, &ClientMessage::RoomLeft { room_id });
+        self.reclaim_entities(player_id);
+        true
+    }
+
+    pub(super) fn remove_from_room(&self, room_id: RoomId, player_id: usize) {
+        let mut rooms = self.game_state.rooms.write().unwrap();
+        let host = |rooms: &Rooms| rooms.get(room_id).and_then(|room| room.host);
+        let was_host = host(&rooms) == Some(player_id);
+        let closed = rooms.leave(room_id, player_id);
+        let new_host = host(&rooms);
+        drop(rooms);
+        if closed {
+            println!("Room {} closed", room_id);
+            self.update_chat_history(|history| history.forget_room(room_id));
+            self.webhooks.notify(WebhookEvent::RoomClosed { room_id });
+        } else if was_host {
+            self.host_changed(room_id, new_host);
+        }
+    }
+
+    pub(super) fn on_list_rooms(
+        &self,
+        endpoint: Endpoint,
+        filters: RoomFilters,
+        after: Option<RoomId>,
+        limit: u32,
+    ) {
+        let limit = (limit as usize).min(MAX_ROOM_PAGE);
+        let name = filters.name.as_ref().map(|name| name.to_lowercase());
+        let rooms = self.game_state.rooms.read().unwrap();
+        let mut matching = rooms
+            .iter()
+            .filter(|room| after.is_none_or(|after| room.id > after))
+            .map(|room| RoomSummary {
+                room_id: room.id,
+                name: room.name.clone(),
+                mode: self.room_mode(room.id),
+                players: room.members.len() as u32,
+                max_players: room.settings.max_players,
+                password: room.is_protected(),
+            })
+            .filter(|summary| {
+                let full = summary
+                    .max_players
+                    .is_some_and(|max| summary.players >= max);
+                name.as_ref()
+                    .is_none_or(|name| summary.name.to_lowercase().contains(name))
+                    && filters.mode.is_none_or(|mode| summary.mode == mode)
+                    && filters
+                        .password
+                        .is_none_or(|password| summary.password == password)
+                    && !(filters.not_full && full)
+            });
+        let page = matching.by_ref().take(limit).collect();
+        let more = matching.next().is_some();
+        let message = ClientMessage::RoomList { rooms: page, more };
+        self.send(endpoint, &message);
+    }
+
+    // The team mode the room's map has it playing, if any
+    fn room_mode(&self, room_id: RoomId) -> GameMode {
+        if self.ctf.contains_key(&Some(room_id)) {
+            GameMode::CaptureTheFlag
+        } else if self.koth.contains_key(&Some(room_id)) {
+            GameMode::KingOfTheHill
+        } else {
+            GameMode::FreePlay
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Map rotation and voting (see `crate::rotation`): moving a room on to its
+// next map between rounds.
+use super::rounds::describe;
+use super::Server;
+use crate::config::ServerConfig;
+use crate::endpoint::Endpoint;
+use crate::maps::MapState;
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::rooms::{RoomId, Rooms};
+use crate::rotation::{AfterRound, Rotation};
+use crate::rounds::Phase;
+use std::collections::HashMap;
+use std::io;
+
+// The rotations of the configured rooms that have one. Each room's first map
+// goes into `maps`.
+pub(super) fn load_rotations(
+    config: &ServerConfig,
+    rooms: &Rooms,
+    maps: &mut HashMap<Option<RoomId>, MapState>,
+) -> io::Result<HashMap<Option<RoomId>, Rotation>> {
+    let mut rotations = HashMap::new();
+    for room_config in &config.rooms {
+        let Some(rotation) = &room_config.rotation else {
+            continue;
+        };
+        let room = rooms
+            .iter()
+            .find(|room| room.persistent && room.name == room_config.name);
+        let Some(room) = room else {
+            continue;
+        };
+        let rotation = Rotation::load(rotation.clone()).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("room `{}`: {}", room.name, e),
+            )
+        })?;
+        let (name, map) = rotation.current();
+        println!(
+            "Room {} rotates {} maps, starting with {}",
+            room.id,
+            rotation.config.maps.len(),
+            name
+        );
+        maps.insert(Some(room.id), MapState::new(map.clone()));
+        rotations.insert(Some(room.id), rotation);
+    }
+    Ok(rotations)
+}
+
+impl Server {
+    // A round in the room ended: open the vote on the next map, when it is
+    // time for one
+    pub(super) fn rotate_after_round(&mut self, room: Option<RoomId>) {
+        // Votes run while the results show
+        let seconds = self
+            .rounds
+            .get(&room)
+            .filter(|round| round.phase() == Phase::Results)
+            .map_or(0, |round| round.config.results_seconds);
+        let Some(rotation) = self.rotations.get_mut(&room) else {
+            return;
+        };
+        if let AfterRound::Vote(maps) = rotation.round_over(seconds > 0) {
+            self.send_room(room, ClientMessage::MapVoteStarted { maps, seconds });
+        }
+    }
+
+    pub(super) fn on_map_vote(&mut self, endpoint: Endpoint, choice: u32) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let room = self.game_state.players.get(&own_id).and_then(|p| p.room);
+        let result = match self.rotations.get_mut(&room) {
+            Some(rotation) => rotation.vote(own_id, choice as usize),
+            None => Err("this room has no map rotation".to_string()),
+        };
+        if let Err(e) = result {
+            self.reject(endpoint, ErrorCode::InvalidRequest, e);
+        }
+    }
+
+    // The next round is starting: load the map it is played on, if that
+    // changes, with a fresh game of its mode
+    pub(super) fn change_map(&mut self, room: Option<RoomId>) {
+        let Some(rotation) = self.rotations.get_mut(&room) else {
+            return;
+        };
+        let Some((name, map)) = rotation.next_map() else {
+            return;
+        };
+        let (name, map) = (name.to_string(), map.clone());
+        println!("Moving {} on to map {}", describe(room), name);
+        self.install_map(room, Some(map));
+        self.send_room(room, ClientMessage::MapChanged { name });
+    }
+
+    // Catch a player who just entered a room up on its map and any vote
+    pub(super) fn send_map(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let Some(rotation) = self.rotations.get(&room) else {
+            // A created room plays the map it was created with
+            let picked = room.and_then(|room_id| {
+                let rooms = self.game_state.rooms.read().unwrap();
+                rooms.get(room_id)?.settings.map.clone()
+            });
+            if let Some(name) = picked {
+                self.send(endpoint, &ClientMessage::MapChanged { name });
+            }
+            return;
+        };
+        let name = rotation.current().0.to_string();
+        self.send(endpoint, &ClientMessage::MapChanged { name });
+        let Some(vote) = rotation.voting() else {
+            return;
+        };
+        let seconds = self
+            .rounds
+            .get(&room)
+            .and_then(|round| round.remaining(self.tick, self.config.snapshot_rate))
+            .unwrap_or(0);
+        let maps = vote
+            .candidates
+            .iter()
+            .map(|&index| rotation.name(index).to_string())
+            .collect();
+        self.send(endpoint, &ClientMessage::MapVoteStarted { maps, seconds });
+    }
+}
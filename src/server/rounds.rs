@@ -0,0 +1,189 @@
+// Rounds of the team mode rooms (see `crate::rounds`): running their clocks,
+// ending them, and starting the next.
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::maps::MapState;
+use crate::protocol::ClientMessage;
+use crate::rooms::{RoomId, TeamId};
+use crate::rounds::{Phase, Round, RoundChange};
+use crate::webhooks::WebhookEvent;
+use std::collections::HashMap;
+
+// A clock for every room whose map times its rounds, started at `tick`
+pub(super) fn clocks(
+    maps: &HashMap<Option<RoomId>, MapState>,
+    tick: u64,
+    snapshot_rate: u32,
+) -> HashMap<Option<RoomId>, Round> {
+    maps.iter()
+        .filter_map(|(&room, state)| {
+            let config = state.map.round.clone()?;
+            Some((room, Round::new(config, tick, snapshot_rate)))
+        })
+        .collect()
+}
+
+impl Server {
+    // Whether the room's mode should be stepped: not while results show
+    pub(super) fn round_playing(&self, room: Option<RoomId>) -> bool {
+        self.rounds
+            .get(&room)
+            .is_none_or(|round| round.phase() != Phase::Results)
+    }
+
+    // Whether the room is mid-round: players keep their teams, and
+    // spectators stay out, until its results show
+    pub(super) fn teams_locked(&self, room: Option<RoomId>) -> bool {
+        self.rounds
+            .get(&room)
+            .is_some_and(|round| round.phase() != Phase::Results)
+    }
+
+    // The scores of the room's mode, whichever it plays
+    fn mode_scores(&self, room: Option<RoomId>) -> Vec<(TeamId, u32)> {
+        if let Some(game) = self.ctf.get(&room) {
+            return game.scores().collect();
+        }
+        if let Some(game) = self.koth.get(&room) {
+            return game.scores().collect();
+        }
+        Vec::new()
+    }
+
+    fn round_message(&self, round: &Round) -> ClientMessage {
+        ClientMessage::RoundTime {
+            remaining: round
+                .remaining(self.tick, self.config.snapshot_rate)
+                .unwrap_or(0),
+            overtime: round.phase() == Phase::Overtime,
+        }
+    }
+
+    // Once a tick, after the modes
+    pub(super) fn check_rounds(&mut self) {
+        let rooms: Vec<Option<RoomId>> = self.rounds.keys().copied().collect();
+        for room in rooms {
+            let scores = self.mode_scores(room);
+            let (tick, rate) = (self.tick, self.config.snapshot_rate);
+            let Some(round) = self.rounds.get_mut(&room) else {
+                continue;
+            };
+            match round.tick(tick, rate, &scores) {
+                Some(RoundChange::Overtime) => {
+                    println!("Round in {} went to overtime", describe(room));
+                }
+                Some(RoundChange::Over { winner }) => self.announce_round_over(room, winner),
+                Some(RoundChange::Started) => self.start_round(room),
+                None => {}
+            }
+            let ticked = self
+                .rounds
+                .get_mut(&room)
+                .and_then(|round| round.clock(tick, rate));
+            if let Some(round) = ticked.and(self.rounds.get(&room)) {
+                self.send_room(room, self.round_message(round));
+            }
+        }
+    }
+
+    // The room's mode hit its score limit
+    pub(super) fn end_round(&mut self, room: Option<RoomId>, winner: TeamId) {
+        let (tick, rate) = (self.tick, self.config.snapshot_rate);
+        let timed = match self.rounds.get_mut(&room) {
+            Some(round) => {
+                round.finish(tick, rate);
+                true
+            }
+            None => false,
+        };
+        self.announce_round_over(room, Some(winner));
+        // Without a clock there are no results to show
+        if !timed {
+            self.start_round(room);
+        }
+    }
+
+    fn announce_round_over(&mut self, room: Option<RoomId>, winner: Option<TeamId>) {
+        match winner {
+            Some(team) => println!("Team {} won the round in {}", team, describe(room)),
+            None => println!("The round in {} was a draw", describe(room)),
+        }
+        self.send_room(room, ClientMessage::RoundOver { winner });
+        self.webhooks.notify(WebhookEvent::MatchEnded {
+            room_id: room,
+            winner,
+            scores: self.mode_scores(room),
+        });
+        self.record_match(room, None, winner);
+        if let Some(team) = winner {
+            for occupant in self.occupants(room) {
+                if occupant.team == Some(team) {
+                    self.count_event(occupant.id, GameEvent::RoundWon);
+                }
+            }
+        }
+        self.rotate_after_round(room);
+    }
+
+    // Start the room's match over from the top of a fresh round, on the
+    // host's say
+    pub(super) fn restart_round(&mut self, room: Option<RoomId>) {
+        let (tick, rate) = (self.tick, self.config.snapshot_rate);
+        if let Some(round) = self.rounds.get_mut(&room) {
+            *round = Round::new(round.config.clone(), tick, rate);
+        }
+        self.start_round(room);
+    }
+
+    // Scores, and flags, start over, on the next map if the room rotates
+    fn start_round(&mut self, room: Option<RoomId>) {
+        self.change_map(room);
+        if let Some(game) = self.ctf.get_mut(&room) {
+            game.reset();
+        }
+        if let Some(game) = self.koth.get_mut(&room) {
+            game.reset();
+        }
+        self.broadcast_flags(room);
+        self.broadcast_hills(room);
+        for (team, score) in self.mode_scores(room) {
+            self.post_score(room, team, score);
+        }
+        self.match_started(room);
+    }
+
+    // For a room whose map just went in, or whose round started over, if it
+    // plays a mode at all
+    pub(super) fn match_started(&self, room: Option<RoomId>) {
+        let playing = self.rounds.contains_key(&room)
+            || self.ctf.contains_key(&room)
+            || self.koth.contains_key(&room);
+        if playing {
+            self.webhooks
+                .notify(WebhookEvent::MatchStarted { room_id: room });
+        }
+    }
+
+    // Catch a player who just entered a room up on its round clock
+    pub(super) fn send_round(&self, player_id: usize) {
+        let Some((room, endpoint)) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| p.snapshot_format.is_some())
+            .map(|p| (p.room, p.endpoint))
+        else {
+            return;
+        };
+        let Some(round) = self.rounds.get(&room) else {
+            return;
+        };
+        if round.phase() != Phase::Results {
+            self.send(endpoint, &self.round_message(round));
+        }
+    }
+}
+
+pub(super) fn describe(room: Option<RoomId>) -> String {
+    room.map_or("the lobby".to_string(), |room| format!("room {}", room))
+}
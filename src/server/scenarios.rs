@@ -0,0 +1,272 @@
+// Running rooms' scenarios (see `crate::scenarios`)
+use super::Server;
+use crate::config::ServerConfig;
+use crate::protocol::{ClientMessage, ObjectiveState};
+use crate::rooms::RoomId;
+use crate::scenarios::{Action, Happening, Scenario, ScenarioRun};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::io;
+
+pub(super) fn load_scenarios(config: &ServerConfig) -> io::Result<BTreeMap<String, Scenario>> {
+    let mut scenarios = BTreeMap::new();
+    for (name, path) in &config.scenarios {
+        let scenario = Scenario::load(path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("scenario {}: {}", path.display(), e),
+            )
+        })?;
+        println!(
+            "Loaded scenario {} from {} ({} events)",
+            name,
+            path.display(),
+            scenario.events.len()
+        );
+        scenarios.insert(name.clone(), scenario);
+    }
+    Ok(scenarios)
+}
+
+impl Server {
+    // The scenario a room plays: a configured room's from its config, a
+    // created room's from its settings
+    fn room_scenario(&self, room_id: RoomId) -> Option<String> {
+        let rooms = self.game_state.rooms.read().unwrap();
+        let room = rooms.get(room_id)?;
+        match room.persistent {
+            true => self
+                .config
+                .rooms
+                .iter()
+                .find(|config| config.name == room.name)?
+                .scenario
+                .clone(),
+            false => room.settings.scenario.clone(),
+        }
+    }
+
+    // A player entered the room: start its scenario if it isn't running, and
+    // show the player the objectives set so far
+    pub(super) fn enter_scenario(&mut self, player_id: usize, room_id: RoomId) {
+        if !self.scenario_runs.contains_key(&room_id) {
+            self.start_scenario(room_id);
+        }
+        let Some(run) = self.scenario_runs.get(&room_id) else {
+            return;
+        };
+        let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) else {
+            return;
+        };
+        let messages: Vec<ClientMessage> = run
+            .objectives
+            .iter()
+            .map(|(name, &completed)| {
+                let state = match completed {
+                    true => ObjectiveState::Completed,
+                    false => ObjectiveState::Active,
+                };
+                self.objective_message(&run.name, name, state)
+            })
+            .collect();
+        for message in messages {
+            self.send(endpoint, &message);
+        }
+    }
+
+    // Start the room's scenario over, or stop it when the room no longer
+    // plays one
+    pub(super) fn start_scenario(&mut self, room_id: RoomId) {
+        self.stop_scenario(room_id);
+        let Some(name) = self.room_scenario(room_id) else {
+            return;
+        };
+        let Some(scenario) = self.scenarios.get(&name) else {
+            return;
+        };
+        println!("Room {} started scenario {}", room_id, name);
+        let spawn_rate = self.difficulty(Some(room_id)).spawn_rate;
+        let run = ScenarioRun::new(
+            name,
+            scenario,
+            self.tick,
+            self.config.snapshot_rate,
+            spawn_rate,
+        );
+        self.scenario_runs.insert(room_id, run);
+    }
+
+    // Drop the room's run, despawning what it spawned and taking its
+    // objectives off everyone's screens
+    fn stop_scenario(&mut self, room_id: RoomId) {
+        let Some(run) = self.scenario_runs.remove(&room_id) else {
+            return;
+        };
+        for id in run.spawned.values() {
+            // Something else may have despawned it already
+            self.despawn_entity(*id).ok();
+        }
+        for name in run.objectives.keys() {
+            let message = self.objective_message(&run.name, name, ObjectiveState::Dropped);
+            self.send_room(Some(room_id), message);
+        }
+        println!("Room {} stopped scenario {}", room_id, run.name);
+    }
+
+    fn objective_message(
+        &self,
+        scenario: &str,
+        name: &str,
+        state: ObjectiveState,
+    ) -> ClientMessage {
+        let text = self
+            .scenarios
+            .get(scenario)
+            .and_then(|scenario| scenario.objective(name))
+            .map(|objective| objective.text.clone())
+            .unwrap_or_default();
+        ClientMessage::Objective {
+            name: name.to_string(),
+            text,
+            state,
+        }
+    }
+
+    // Something a room's scenario may react to happened in it
+    pub(super) fn scenario_happened(&mut self, room_id: RoomId, happening: Happening) {
+        let Some(run) = self.scenario_runs.get_mut(&room_id) else {
+            return;
+        };
+        let Some(scenario) = self.scenarios.get(&run.name) else {
+            return;
+        };
+        run.happened(scenario, &happening, self.tick, self.config.snapshot_rate);
+    }
+
+    // Each simulated tick: stop the runs of rooms left empty, and do what
+    // the rest have come due
+    pub(super) fn run_scenarios(&mut self) {
+        let rooms = self.game_state.rooms.read().unwrap();
+        let (empty, running): (Vec<RoomId>, Vec<RoomId>) =
+            self.scenario_runs.keys().partition(|&&room_id| {
+                rooms
+                    .get(room_id)
+                    .is_none_or(|room| room.members.is_empty())
+            });
+        drop(rooms);
+        for room_id in empty {
+            self.stop_scenario(room_id);
+        }
+        let (tick, rate) = (self.tick, self.config.snapshot_rate);
+        for room_id in running {
+            let spawn_rate = self.difficulty(Some(room_id)).spawn_rate;
+            let Some(run) = self.scenario_runs.get_mut(&room_id) else {
+                continue;
+            };
+            let Some(scenario) = self.scenarios.get(&run.name) else {
+                continue;
+            };
+            let actions: Vec<Action> = run
+                .take_due(scenario, tick, rate, spawn_rate)
+                .into_iter()
+                .flat_map(|index| scenario.events[index].actions.clone())
+                .collect();
+            for action in actions {
+                self.act(room_id, action);
+            }
+        }
+    }
+
+    fn act(&mut self, room_id: RoomId, action: Action) {
+        let room = Some(room_id);
+        let Some(run) = self
+            .scenario_runs
+            .get_mut(&room_id)
+            .filter(|run| !run.finished)
+        else {
+            return;
+        };
+        let scenario = run.name.clone();
+        match action {
+            Action::Announce(text) => {
+                let message = ClientMessage::Announcement {
+                    text,
+                    localized: None,
+                };
+                self.send_room(room, message);
+            }
+            Action::Objective(name) => {
+                if let Entry::Vacant(entry) = run.objectives.entry(name.clone()) {
+                    entry.insert(false);
+                    let message = self.objective_message(&scenario, &name, ObjectiveState::Active);
+                    self.send_room(room, message);
+                }
+            }
+            Action::Complete(name) => {
+                if run.objectives.insert(name.clone(), true) == Some(true) {
+                    return;
+                }
+                println!(
+                    "Room {} completed objective {} of scenario {}",
+                    room_id, name, scenario
+                );
+                let message = self.objective_message(&scenario, &name, ObjectiveState::Completed);
+                self.send_room(room, message);
+                self.scenario_happened(room_id, Happening::Completed(name));
+            }
+            Action::Spawn { name, entity } => {
+                if let Some(old) = run.spawned.remove(&name) {
+                    self.despawn_entity(old).ok();
+                }
+                match self.spawn_entity(room, entity) {
+                    Ok(id) => {
+                        if let Some(run) = self.scenario_runs.get_mut(&room_id) {
+                            run.spawned.insert(name, id);
+                        }
+                    }
+                    Err(e) => eprintln!(
+                        "Scenario {} in room {} couldn't spawn {}: {}",
+                        scenario, room_id, name, e
+                    ),
+                }
+            }
+            Action::Move { name, x, y } => {
+                let Some(&id) = run.spawned.get(&name) else {
+                    return;
+                };
+                if let Err(e) = self.move_entity(id, x, y) {
+                    eprintln!(
+                        "Scenario {} in room {} couldn't move {}: {}",
+                        scenario, room_id, name, e
+                    );
+                }
+            }
+            Action::Despawn(name) => {
+                if let Some(id) = run.spawned.remove(&name) {
+                    self.despawn_entity(id).ok();
+                }
+            }
+            Action::Loot { table, x, y } => {
+                if let Err(e) = self.drop_loot(room, &table, (x, y), None) {
+                    eprintln!(
+                        "Scenario {} in room {} couldn't drop loot: {}",
+                        scenario, room_id, e
+                    );
+                }
+            }
+            Action::Difficulty(change) => {
+                if let Err(e) = self.change_difficulty(room_id, &change) {
+                    eprintln!(
+                        "Scenario {} in room {} couldn't change difficulty: {}",
+                        scenario, room_id, e
+                    );
+                }
+            }
+            Action::Finish => {
+                run.finished = true;
+                println!("Room {} finished scenario {}", room_id, scenario);
+                self.record_match(room, Some(scenario), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,726 @@
+// Worker threads simulating the rooms on each shard. The game loop keeps
+// shard membership up to date and routes moves and ticks by room id; each
+// worker only ever locks its own shard.
+use super::outbound::Outbound;
+use super::Server;
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::deltas::{self, Baselines};
+use crate::endpoint::Endpoint;
+use crate::maps::Shape;
+use crate::protocol::{ClientMessage, PlayerSnapshot};
+use crate::rooms::{RoomId, TeamId};
+use crate::shards::{self, DeadReckoning, Link, Member, Members, Shard};
+use crate::sight::Fog;
+use crate::state::{GameState, Player};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub enum ShardCommand {
+    Move {
+        room: Option<RoomId>,
+        player_id: usize,
+        x: f32,
+        y: f32,
+    },
+    // Put the player where the server says, telling them as well as the room
+    Place {
+        room: Option<RoomId>,
+        player_id: usize,
+        x: f32,
+        y: f32,
+    },
+    // Show `emote` to the members within `radius` of the player, judging
+    // where the others are by their trails at `rewind` when it is set
+    Emote {
+        room: Option<RoomId>,
+        player_id: usize,
+        emote: String,
+        radius: f32,
+        rewind: Option<u64>,
+    },
+    // The player applied the snapshot for `tick`
+    Ack {
+        room: Option<RoomId>,
+        player_id: usize,
+        tick: u64,
+    },
+    // Snapshots for `tick`, leaving out the lobby's unless `lobby` is set
+    Tick {
+        tick: u64,
+        lobby: bool,
+    },
+}
+
+// Sends commands to the worker owning each room; the workers exit once it is dropped
+pub(super) struct ShardRouter {
+    workers: Vec<Sender<ShardCommand>>,
+}
+
+impl ShardRouter {
+    pub(super) fn send(&self, room: Option<RoomId>, command: ShardCommand) {
+        let index = shards::shard_index(room, self.workers.len());
+        self.workers[index].send(command).ok();
+    }
+
+    pub(super) fn tick(&self, tick: u64, lobby: bool) {
+        for worker in &self.workers {
+            worker.send(ShardCommand::Tick { tick, lobby }).ok();
+        }
+    }
+}
+
+// Start one worker per shard in `game_state`
+pub(super) fn spawn_shards(
+    game_state: &Arc<GameState>,
+    outbound: &UnboundedSender<Outbound>,
+    dead_reckoning: DeadReckoning,
+    delta_window: u64,
+) -> io::Result<ShardRouter> {
+    let mut workers = Vec::new();
+    for index in 0..game_state.shards.len() {
+        let (sender, commands) = mpsc::channel();
+        let worker = Worker {
+            index,
+            game_state: game_state.clone(),
+            outbound: outbound.clone(),
+            dead_reckoning,
+            tick: Cell::new(0),
+            delta_window,
+            baselines: RefCell::default(),
+        };
+        thread::Builder::new()
+            .name(format!("shard-{}", index))
+            .spawn(move || worker.run(commands))?;
+        workers.push(sender);
+    }
+    Ok(ShardRouter { workers })
+}
+
+struct Worker {
+    index: usize,
+    game_state: Arc<GameState>,
+    outbound: UnboundedSender<Outbound>,
+    dead_reckoning: DeadReckoning,
+    // The last tick snapshotted, which moves since are timed by
+    tick: Cell<u64>,
+    // How many ticks of snapshots delta clients may ack
+    delta_window: u64,
+    baselines: RefCell<Baselines>,
+}
+
+impl Worker {
+    fn shard(&self) -> &RwLock<Shard> {
+        &self.game_state.shards[self.index]
+    }
+
+    fn run(self, commands: Receiver<ShardCommand>) {
+        for command in commands {
+            match command {
+                ShardCommand::Move {
+                    room,
+                    player_id,
+                    x,
+                    y,
+                } => self.on_move(room, player_id, x, y, false),
+                ShardCommand::Place {
+                    room,
+                    player_id,
+                    x,
+                    y,
+                } => self.on_move(room, player_id, x, y, true),
+                ShardCommand::Emote {
+                    room,
+                    player_id,
+                    emote,
+                    radius,
+                    rewind,
+                } => self.on_emote(room, player_id, emote, radius, rewind),
+                ShardCommand::Ack {
+                    room,
+                    player_id,
+                    tick,
+                } => self.baselines.borrow_mut().ack(room, player_id, tick),
+                ShardCommand::Tick { tick, lobby } => self.on_tick(tick, lobby),
+            }
+        }
+    }
+
+    // Update the position and tell everyone else in the room, and the player
+    // too when `echo` is set
+    fn on_move(&self, room: Option<RoomId>, player_id: usize, x: f32, y: f32, echo: bool) {
+        let mut shard = self.shard().write().unwrap();
+        // Moves queued before a room change find the player gone
+        let Some(members) = shard.rooms.get_mut(&room) else {
+            return;
+        };
+        let moved = match echo {
+            true => members.set_position(player_id, x, y),
+            false => members.record_move(player_id, x, y, self.tick.get()),
+        };
+        let (Some(members), true) = (shard.rooms.get(&room), moved) else {
+            return;
+        };
+        // Under fog of war, only those who can see the player get the move
+        let watching = shard
+            .fog_over(room)
+            .map(|(fog, obstacles)| watching(members, player_id, (x, y), fog, obstacles));
+        let recipients = members
+            .ids()
+            .iter()
+            .zip(members.links())
+            .filter(|(id, _)| echo || **id != player_id)
+            .filter(|(id, _)| {
+                watching
+                    .as_ref()
+                    .is_none_or(|watching| watching.contains(id))
+            })
+            .map(|(_, link)| (link.endpoint, link.wire_format))
+            .collect();
+        let message = ClientMessage::PlayerPosition {
+            id: player_id,
+            x,
+            y,
+        };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+
+    // Interest management: only members close enough to see the emote hear
+    // about it, the sender included
+    fn on_emote(
+        &self,
+        room: Option<RoomId>,
+        player_id: usize,
+        emote: String,
+        radius: f32,
+        rewind: Option<u64>,
+    ) {
+        let shard = self.shard().read().unwrap();
+        let Some(members) = shard.rooms.get(&room) else {
+            return;
+        };
+        let Some(center) = members.position(player_id) else {
+            return;
+        };
+        let nearby = match rewind {
+            Some(tick) => self.within_then(members, room, player_id, center, radius, tick),
+            None => members.within(center, radius),
+        };
+        let recipients = nearby
+            .into_iter()
+            .filter_map(|id| members.link(id))
+            .filter(|link| link.snapshot_format.is_some())
+            .map(|link| (link.endpoint, link.wire_format))
+            .collect();
+        drop(shard);
+        let message = ClientMessage::PlayerEmote { player_id, emote };
+        self.outbound.send(Outbound::Send(recipients, message)).ok();
+    }
+
+    // Lag compensation: the sender saw everyone else where they were at
+    // `tick`, so that is where they are measured from. Players who have not
+    // moved since are where they are now; players who were elsewhere then
+    // couldn't have been seen.
+    fn within_then(
+        &self,
+        members: &Members,
+        room: Option<RoomId>,
+        player_id: usize,
+        center: (f32, f32),
+        radius: f32,
+        tick: u64,
+    ) -> Vec<usize> {
+        let trails = &self.game_state.trails;
+        let then = |id: usize| match trails.get(&id).and_then(|trail| trail.position_at(tick)) {
+            Some(sample) => (sample.room == room).then_some((sample.x, sample.y)),
+            None => members.position(id),
+        };
+        members
+            .ids()
+            .iter()
+            .copied()
+            .filter(|&id| {
+                id == player_id
+                    || then(id).is_some_and(|(x, y)| (x - center.0).hypot(y - center.1) <= radius)
+            })
+            .collect()
+    }
+
+    // Every room, and the lobby, gets a snapshot of only its own players.
+    // How long that takes counts towards the tick's cost.
+    fn on_tick(&self, tick: u64, lobby: bool) {
+        let started = Instant::now();
+        self.tick.set(tick);
+        let shard = self.shard().read().unwrap();
+        for (&room, members) in shard.rooms.iter() {
+            if !(room.is_some() || lobby) {
+                continue;
+            }
+            match shard.fog_over(room) {
+                Some((fog, obstacles)) => {
+                    self.send_fogged_snapshots(tick, room, members, fog, obstacles)
+                }
+                None => self.send_snapshot(tick, room, members),
+            }
+        }
+        self.baselines.borrow_mut().retain(|room, player_id| {
+            shard
+                .rooms
+                .get(&room)
+                .is_some_and(|members| members.link(player_id).is_some())
+        });
+        drop(shard);
+        self.game_state.health.shard_ticked(started.elapsed());
+    }
+
+    fn send_snapshot(&self, tick: u64, room: Option<RoomId>, members: &Members) {
+        let snapshot = members.snapshot(tick, self.dead_reckoning);
+
+        let mut native = Vec::new();
+        let mut flat = Vec::new();
+        let mut archived = Vec::new();
+        let mut delta = Vec::new();
+        for (&id, link) in members.ids().iter().zip(members.links()) {
+            let recipients = match link.snapshot_format {
+                None => continue,
+                Some(SnapshotFormat::Native) => &mut native,
+                Some(SnapshotFormat::FlatBuffers) => &mut flat,
+                Some(SnapshotFormat::Rkyv) => &mut archived,
+                Some(SnapshotFormat::Delta) => {
+                    delta.push((id, link.endpoint, link.wire_format));
+                    continue;
+                }
+            };
+            recipients.push((link.endpoint, link.wire_format));
+        }
+        if !delta.is_empty() {
+            self.send_deltas(tick, room, &snapshot, delta);
+        }
+        // Each snapshot is built once for the room; the broadcaster then encodes
+        // it once per wire format and sends those same bytes to every recipient
+        if !flat.is_empty() {
+            let message = flat_snapshot(tick, &snapshot);
+            self.outbound.send(Outbound::Send(flat, message)).ok();
+        }
+        if !archived.is_empty() {
+            let message = archived_snapshot(tick, &snapshot);
+            self.outbound.send(Outbound::Send(archived, message)).ok();
+        }
+        if !native.is_empty() {
+            let message = ClientMessage::WorldSnapshot {
+                tick,
+                players: snapshot,
+            };
+            self.outbound.send(Outbound::Send(native, message)).ok();
+        }
+    }
+
+    // Fog of war: each member is sent a snapshot of their own, of only the
+    // players they can see
+    fn send_fogged_snapshots(
+        &self,
+        tick: u64,
+        room: Option<RoomId>,
+        members: &Members,
+        fog: Fog,
+        obstacles: &[Shape],
+    ) {
+        let snapshot = members.snapshot(tick, self.dead_reckoning);
+        let (ids, links) = (members.ids(), members.links());
+        let groups: Vec<VisionGroup> = ids
+            .iter()
+            .zip(links)
+            .map(|(&id, link)| vision_group(fog, id, link))
+            .collect();
+        // Whom each vision group sees, by row
+        let mut sights: HashMap<VisionGroup, Vec<bool>> = HashMap::new();
+        let mut recorded = false;
+        for (row, (&id, link)) in ids.iter().zip(links).enumerate() {
+            let Some(format) = link.snapshot_format else {
+                continue;
+            };
+            let sighted = sights.entry(groups[row]).or_insert_with(|| {
+                let eyes: Vec<(f32, f32)> = (0..snapshot.len())
+                    .filter(|&eye| groups[eye] == groups[row] && !links[eye].spectating)
+                    .map(|eye| (snapshot[eye].x, snapshot[eye].y))
+                    .collect();
+                snapshot
+                    .iter()
+                    .map(|other| {
+                        let to = (other.x, other.y);
+                        eyes.iter().any(|&from| fog.sees(obstacles, from, to))
+                    })
+                    .collect()
+            });
+            let mut seen = Vec::new();
+            let mut hidden = Vec::new();
+            for (other_row, other) in snapshot.iter().enumerate() {
+                let shown =
+                    sighted[other_row] || always_sees(id, link, other.id, &links[other_row]);
+                match shown {
+                    true => seen.push(*other),
+                    false => hidden.push(other.id),
+                }
+            }
+            let message = match format {
+                SnapshotFormat::Native => ClientMessage::WorldSnapshot {
+                    tick,
+                    players: seen,
+                },
+                SnapshotFormat::FlatBuffers => flat_snapshot(tick, &seen),
+                SnapshotFormat::Rkyv => archived_snapshot(tick, &seen),
+                SnapshotFormat::Delta => {
+                    let mut baselines = self.baselines.borrow_mut();
+                    if !recorded {
+                        baselines.record(room, tick, &snapshot, self.delta_window);
+                        recorded = true;
+                    }
+                    baselines.hide(room, id, tick, hidden, self.delta_window);
+                    let baseline = baselines.seen_baseline(room, id);
+                    let (players, removed) = match &baseline {
+                        Some((_, before)) => deltas::delta(before, &seen),
+                        None => (seen, Vec::new()),
+                    };
+                    ClientMessage::DeltaSnapshot {
+                        tick,
+                        baseline: baseline.map(|(acked, _)| acked),
+                        players,
+                        removed,
+                    }
+                }
+            };
+            let recipients = vec![(link.endpoint, link.wire_format)];
+            self.outbound.send(Outbound::Send(recipients, message)).ok();
+        }
+    }
+
+    // Clients that acked the same snapshot get the same delta; those whose
+    // ack lapsed, or who never acked, get a keyframe
+    fn send_deltas(
+        &self,
+        tick: u64,
+        room: Option<RoomId>,
+        snapshot: &[PlayerSnapshot],
+        recipients: Vec<(usize, Endpoint, WireFormat)>,
+    ) {
+        self.baselines
+            .borrow_mut()
+            .record(room, tick, snapshot, self.delta_window);
+        let baselines = self.baselines.borrow();
+        let mut by_baseline: BTreeMap<Option<u64>, (Option<&[PlayerSnapshot]>, Vec<_>)> =
+            BTreeMap::new();
+        for (id, endpoint, wire_format) in recipients {
+            let baseline = baselines.baseline(room, id);
+            by_baseline
+                .entry(baseline.map(|(acked, _)| acked))
+                .or_insert((baseline.map(|(_, before)| before), Vec::new()))
+                .1
+                .push((endpoint, wire_format));
+        }
+        for (baseline, (before, recipients)) in by_baseline {
+            let (players, removed) = match before {
+                Some(before) => deltas::delta(before, snapshot),
+                None => (snapshot.to_vec(), Vec::new()),
+            };
+            let message = ClientMessage::DeltaSnapshot {
+                tick,
+                baseline,
+                players,
+                removed,
+            };
+            self.outbound.send(Outbound::Send(recipients, message)).ok();
+        }
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+fn flat_snapshot(tick: u64, players: &[PlayerSnapshot]) -> ClientMessage {
+    ClientMessage::FlatSnapshot {
+        buffer: crate::codec::flatbuffer::build_snapshot(tick, players),
+    }
+}
+
+#[cfg(not(feature = "flatbuffers"))]
+fn flat_snapshot(_tick: u64, _players: &[PlayerSnapshot]) -> ClientMessage {
+    unreachable!("flatbuffers snapshots are never negotiated without the feature")
+}
+
+#[cfg(feature = "rkyv")]
+fn archived_snapshot(tick: u64, players: &[PlayerSnapshot]) -> ClientMessage {
+    ClientMessage::ArchivedSnapshot {
+        buffer: crate::codec::archive::build_snapshot(tick, players),
+    }
+}
+
+#[cfg(not(feature = "rkyv"))]
+fn archived_snapshot(_tick: u64, _players: &[PlayerSnapshot]) -> ClientMessage {
+    unreachable!("rkyv snapshots are never negotiated without the feature")
+}
+
+// Who shares sight under fog of war: a team, with team vision, or else a
+// player on their own
+type VisionGroup = (Option<TeamId>, usize);
+
+fn vision_group(fog: Fog, id: usize, link: &Link) -> VisionGroup {
+    match link.team.filter(|_| fog.team_vision) {
+        Some(team) => (Some(team), 0),
+        None => (None, id),
+    }
+}
+
+// Whether a player is shown another whatever the fog: themselves, their
+// teammates, and anyone at all while spectating
+fn always_sees(id: usize, link: &Link, other_id: usize, other: &Link) -> bool {
+    link.spectating || id == other_id || (link.team.is_some() && link.team == other.team)
+}
+
+// The members who see the player at `at`
+fn watching(
+    members: &Members,
+    player_id: usize,
+    at: (f32, f32),
+    fog: Fog,
+    obstacles: &[Shape],
+) -> HashSet<usize> {
+    let (ids, links) = (members.ids(), members.links());
+    let Some(mover) = members.link(player_id) else {
+        return HashSet::new();
+    };
+    let positions = members.xs().iter().zip(members.ys());
+    let seeing: HashSet<VisionGroup> = ids
+        .iter()
+        .zip(links)
+        .zip(positions)
+        .filter(|((_, link), (&x, &y))| !link.spectating && fog.sees(obstacles, (x, y), at))
+        .map(|((&id, link), _)| vision_group(fog, id, link))
+        .collect();
+    ids.iter()
+        .zip(links)
+        .filter(|&(&id, link)| {
+            always_sees(id, link, player_id, mover) || seeing.contains(&vision_group(fog, id, link))
+        })
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+// The members `viewer_id` sees: the other side of `watching`
+pub(super) fn seen_by(
+    members: &Members,
+    viewer_id: usize,
+    fog: Fog,
+    obstacles: &[Shape],
+) -> HashSet<usize> {
+    let (ids, links) = (members.ids(), members.links());
+    let Some(viewer) = members.link(viewer_id) else {
+        return HashSet::new();
+    };
+    let group = vision_group(fog, viewer_id, viewer);
+    let positions: Vec<(f32, f32)> = members
+        .xs()
+        .iter()
+        .copied()
+        .zip(members.ys().iter().copied())
+        .collect();
+    let eyes: Vec<(f32, f32)> = ids
+        .iter()
+        .zip(links)
+        .zip(&positions)
+        .filter(|((&id, link), _)| !link.spectating && vision_group(fog, id, link) == group)
+        .map(|(_, &at)| at)
+        .collect();
+    ids.iter()
+        .zip(links)
+        .zip(&positions)
+        .filter(|((&id, link), &to)| {
+            always_sees(viewer_id, viewer, id, link)
+                || eyes.iter().any(|&from| fog.sees(obstacles, from, to))
+        })
+        .map(|((&id, _), _)| id)
+        .collect()
+}
+
+// Membership changes are applied by the game loop itself, so they take effect
+// before any move it routes afterwards
+impl Server {
+    // A player who just joined starts out visible in their room (or the lobby)
+    pub(super) fn add_member(&self, player: &Player) {
+        let member = Member {
+            endpoint: player.endpoint,
+            wire_format: player.wire_format,
+            snapshot_format: player.snapshot_format,
+            team: player.team,
+            spectating: player.spectating,
+            x: 0.0,
+            y: 0.0,
+        };
+        let mut shard = self.game_state.shard(player.room).write().unwrap();
+        shard
+            .rooms
+            .entry(player.room)
+            .or_default()
+            .insert(player.id, member);
+    }
+
+    pub(super) fn remove_member(&self, room: Option<RoomId>, player_id: usize) -> Option<Member> {
+        let mut shard = self.game_state.shard(room).write().unwrap();
+        let members = shard.rooms.get_mut(&room)?;
+        let member = members.remove(player_id);
+        if members.is_empty() {
+            shard.rooms.remove(&room);
+        }
+        member
+    }
+
+    // Snapshots and moves for the player go to its new connection from now on
+    pub(super) fn rebind_member(&self, player: &Player) {
+        let Some(mut member) = self.remove_member(player.room, player.id) else {
+            return;
+        };
+        member.endpoint = player.endpoint;
+        member.wire_format = player.wire_format;
+        member.snapshot_format = player.snapshot_format;
+        let mut shard = self.game_state.shard(player.room).write().unwrap();
+        shard
+            .rooms
+            .entry(player.room)
+            .or_default()
+            .insert(player.id, member);
+    }
+
+    // Carry a player's position over to their new room, whichever shard it
+    // is on. They arrive on no team.
+    pub(super) fn move_member(&self, player_id: usize, from: Option<RoomId>, to: Option<RoomId>) {
+        let Some(mut member) = self.remove_member(from, player_id) else {
+            return;
+        };
+        member.team = None;
+        member.spectating = false;
+        let mut shard = self.game_state.shard(to).write().unwrap();
+        shard.rooms.entry(to).or_default().insert(player_id, member);
+    }
+
+    // Formats negotiated by a player who was already in the game, or the
+    // team they changed to
+    pub(super) fn update_member(&self, player: &Player) {
+        let mut shard = self.game_state.shard(player.room).write().unwrap();
+        let link = shard
+            .rooms
+            .get_mut(&player.room)
+            .and_then(|members| members.link_mut(player.id));
+        if let Some(link) = link {
+            link.wire_format = player.wire_format;
+            link.snapshot_format = player.snapshot_format;
+            link.team = player.team;
+            link.spectating = player.spectating;
+        }
+    }
+
+    // Acks from clients not on delta snapshots are ignored
+    pub(super) fn on_snapshot_ack(&mut self, endpoint: Endpoint, tick: u64) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let room = match self.game_state.players.get(&player_id) {
+            Some(p) if p.snapshot_format == Some(SnapshotFormat::Delta) => p.room,
+            _ => return,
+        };
+        let command = ShardCommand::Ack {
+            room,
+            player_id,
+            tick,
+        };
+        self.shards.send(room, command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(players: &[(usize, Option<TeamId>, bool, f32, f32)]) -> Members {
+        let mut members = Members::default();
+        for &(id, team, spectating, x, y) in players {
+            let member = Member {
+                endpoint: Endpoint::Local(id as u64),
+                wire_format: WireFormat::Bincode,
+                snapshot_format: None,
+                team,
+                spectating,
+                x,
+                y,
+            };
+            members.insert(id, member);
+        }
+        members
+    }
+
+    const FOG: Fog = Fog {
+        vision: Some(10.0),
+        team_vision: true,
+    };
+
+    fn sorted(ids: HashSet<usize>) -> Vec<usize> {
+        let mut ids: Vec<usize> = ids.into_iter().collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn teams_share_sight_and_spectators_see_everyone() {
+        let members = members(&[
+            (1, Some(1), false, 0.0, 0.0),
+            (2, Some(2), false, 5.0, 0.0),
+            (3, Some(2), false, 50.0, 0.0),
+            (4, Some(1), false, 60.0, 0.0),
+            (5, None, true, 100.0, 100.0),
+        ]);
+        // 3 is near 1's teammate 4; the spectator is near no one on team 1
+        assert_eq!(sorted(seen_by(&members, 1, FOG, &[])), [1, 2, 3, 4]);
+        assert_eq!(sorted(seen_by(&members, 5, FOG, &[])), [1, 2, 3, 4, 5]);
+        // Spectators are seen by no one else
+        assert_eq!(sorted(seen_by(&members, 2, FOG, &[])), [1, 2, 3, 4]);
+
+        let alone = Fog {
+            team_vision: false,
+            ..FOG
+        };
+        assert_eq!(sorted(seen_by(&members, 1, alone, &[])), [1, 2, 4]);
+    }
+
+    #[test]
+    fn seen_by_agrees_with_watching() {
+        let members = members(&[
+            (1, Some(1), false, 0.0, 0.0),
+            (2, Some(2), false, 8.0, 0.0),
+            (3, None, false, 15.0, 0.0),
+            (4, Some(1), false, 25.0, 0.0),
+            (5, None, true, 40.0, 0.0),
+        ]);
+        for fog in [
+            FOG,
+            Fog {
+                team_vision: false,
+                ..FOG
+            },
+        ] {
+            for (row, &target) in members.ids().iter().enumerate() {
+                let at = (members.xs()[row], members.ys()[row]);
+                let watchers = watching(&members, target, at, fog, &[]);
+                for &viewer in members.ids() {
+                    assert_eq!(
+                        seen_by(&members, viewer, fog, &[]).contains(&target),
+                        watchers.contains(&viewer),
+                        "{} seeing {}",
+                        viewer,
+                        target
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Shops (see `crate::shops`): showing a vendor's offers to the player who
+// interacted with it, and checking and applying their trades.
+use super::Server;
+use crate::config::ServerConfig;
+use crate::endpoint::Endpoint;
+use crate::inventory::{ExchangeError, Inventories};
+use crate::mail::{ItemGrant, ItemId};
+use crate::protocol::{ClientMessage, ErrorCode};
+use crate::shops::{Shops, TradeError, SHOP_KEY};
+use std::io;
+
+pub(super) fn load_shops(config: &ServerConfig) -> io::Result<Option<Shops>> {
+    let Some(path) = &config.shops.file else {
+        return Ok(None);
+    };
+    let shops = Shops::load(&config.shops, path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("shops {}: {}", path.display(), e),
+        )
+    })?;
+    println!("Loaded {} shops from {}", shops.shops.len(), path.display());
+    Ok(Some(shops))
+}
+
+impl Server {
+    pub(super) fn open_shop(
+        &self,
+        player_id: usize,
+        entity: u64,
+        shop: &str,
+    ) -> Result<(), String> {
+        let offers = self
+            .shops
+            .as_ref()
+            .and_then(|shops| shops.offers(shop))
+            .ok_or_else(|| format!("there is no shop `{}`", shop))?;
+        if let Some(endpoint) = self.game_state.players.get(&player_id).map(|p| p.endpoint) {
+            let message = ClientMessage::Shop {
+                entity,
+                currency: self.config.shops.currency.clone(),
+                offers,
+            };
+            self.send(endpoint, &message);
+        }
+        Ok(())
+    }
+
+    // Buy `quantity` of `item` from the shop `entity` is, or sell it when
+    // `selling`, from `stack` if given
+    pub(super) fn on_trade(
+        &mut self,
+        endpoint: Endpoint,
+        entity: u64,
+        item: String,
+        quantity: u32,
+        selling: bool,
+        stack: Option<ItemId>,
+    ) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if quantity == 0 {
+            self.reject(endpoint, ErrorCode::InvalidRequest, "trade at least one");
+            return;
+        }
+        match self.trade(own_id, entity, &item, quantity, selling, stack) {
+            Ok(price) => {
+                let verb = if selling { "sold" } else { "bought" };
+                let text = format!("{} {} x {} for {}", verb, quantity, item, price);
+                self.record_action(own_id, text);
+                let message = ClientMessage::Traded {
+                    entity,
+                    item,
+                    quantity,
+                    price,
+                    selling,
+                };
+                self.send(endpoint, &message);
+                self.send_inventory(own_id);
+            }
+            Err((code, missing)) => {
+                let message = ClientMessage::TradeFailed {
+                    entity,
+                    item,
+                    code,
+                    missing,
+                };
+                self.send(endpoint, &message);
+            }
+        }
+    }
+
+    // The price paid or received, or why the trade didn't go ahead
+    fn trade(
+        &self,
+        player_id: usize,
+        entity: u64,
+        item: &str,
+        quantity: u32,
+        selling: bool,
+        stack: Option<ItemId>,
+    ) -> Result<u32, (TradeError, Vec<ItemGrant>)> {
+        let fail = |code| (code, Vec::new());
+        let (vendor, _, account) = self
+            .within_reach(player_id, entity)
+            .map_err(|_| fail(TradeError::OutOfReach))?;
+        let (Some(shops), Some(shop)) = (&self.shops, vendor.metadata.get(SHOP_KEY)) else {
+            return Err(fail(TradeError::NoShop));
+        };
+        if !shops.shops.contains_key(shop) {
+            return Err(fail(TradeError::NoShop));
+        }
+        let currency = &self.config.shops.currency;
+        let (mut give, get) = shops
+            .quote(currency, shop, item, quantity, selling)
+            .ok_or(fail(TradeError::NotTraded))?;
+        if selling {
+            give.id = stack;
+        }
+        let account = account.ok_or(fail(TradeError::NotLoggedIn))?;
+        let price = if selling { get.quantity } else { give.quantity };
+        let mut inventories = self.game_state.inventories.write().unwrap();
+        inventories
+            .exchange(&self.config.inventory, account, &[give.clone()], &[get])
+            .map_err(|e| match e {
+                ExchangeError::Missing(missing) => (TradeError::MissingItems, missing),
+                ExchangeError::Full(_) => fail(TradeError::InventoryFull),
+                ExchangeError::Reused(id) => {
+                    eprintln!(
+                        "Player {} tried to sell item {}, which is used up; replayed?",
+                        player_id, id
+                    );
+                    (TradeError::MissingItems, vec![give.clone()])
+                }
+            })?;
+        self.save(Inventories::STORAGE_KEY, &*inventories);
+        Ok(price)
+    }
+}
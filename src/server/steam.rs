@@ -0,0 +1,33 @@
+use super::accounts::AuthOutcome;
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::ErrorCode;
+use crate::steam;
+
+impl Server {
+    pub(super) fn on_steam_login(&mut self, endpoint: Endpoint, ticket: String) {
+        if !self.config.steam.enabled() {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "Steam login is not enabled on this server",
+            );
+            return;
+        }
+        if ticket.is_empty() || !ticket.bytes().all(|b| b.is_ascii_hexdigit()) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                "Steam tickets must be hex-encoded",
+            );
+            return;
+        }
+        if !self.can_authenticate(endpoint) {
+            return;
+        }
+        let config = self.config.steam.clone();
+        self.authenticate(endpoint, move || {
+            AuthOutcome::Steam(steam::authenticate_ticket(&config, &ticket))
+        });
+    }
+}
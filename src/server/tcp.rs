@@ -0,0 +1,95 @@
+// Accepting clients over framed TCP. Frames are read with `read_frame`, so a
+// length prefix over `max_frame_bytes` closes the connection before anything
+// of the frame is buffered, rather than after it has all been read.
+use super::inbound::Inbound;
+use super::transport::{read_frame, write_frame, Connections};
+use crate::endpoint::Endpoint;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::BufReader;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+// Bind `addr`; the returned task accepts on it until aborted
+pub(super) fn spawn_listener(
+    addr: &str,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) -> io::Result<(JoinHandle<()>, SocketAddr)> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(listener)?;
+    let bound = listener.local_addr()?;
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    let (inbound, connections) = (inbound.clone(), connections.clone());
+                    tokio::spawn(serve(stream, addr, inbound, connections, max_frame_bytes));
+                }
+                Err(e) => eprintln!("TCP accept on {} failed: {}", bound, e),
+            }
+        }
+    });
+    Ok((task, bound))
+}
+
+async fn serve(
+    stream: TcpStream,
+    addr: SocketAddr,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) {
+    let connection = connections.next_tcp_id();
+    let endpoint = Endpoint::Tcp { connection, addr };
+    let (read, write) = stream.into_split();
+    let (frames, queue) = mpsc::unbounded_channel();
+    connections.add_tcp(connection, frames);
+    inbound.send(Inbound::Accepted(endpoint)).ok();
+    let reader = tokio::spawn(read_frames(
+        read,
+        connection,
+        addr,
+        inbound,
+        connections,
+        max_frame_bytes,
+    ));
+    // The server closed the connection, rather than the client going away
+    if write_frames(write, queue).await.is_ok() {
+        reader.abort();
+    }
+}
+
+async fn read_frames(
+    read: OwnedReadHalf,
+    connection: u64,
+    addr: SocketAddr,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) {
+    let endpoint = Endpoint::Tcp { connection, addr };
+    let mut read = BufReader::new(read);
+    while let Ok(Some(frame)) = read_frame(&mut read, max_frame_bytes).await {
+        inbound.send(Inbound::Frame(endpoint, frame)).ok();
+    }
+    if connections.remove_tcp(connection) {
+        inbound.send(Inbound::Disconnected(endpoint)).ok();
+    }
+}
+
+// Until the server closes the connection; the socket shuts down once what
+// was queued is written
+async fn write_frames(
+    mut write: OwnedWriteHalf,
+    mut queue: UnboundedReceiver<Vec<u8>>,
+) -> io::Result<()> {
+    while let Some(frame) = queue.recv().await {
+        write_frame(&mut write, &frame).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+// Acting on what `crate::traffic` counts: dropping connections that send more
+// than `throttle` allows, and showing the counts with `traffic [player]`.
+use super::Server;
+use crate::protocol::{DisconnectReason, LocalizedText};
+use crate::traffic::Counters;
+use std::time::Instant;
+
+impl Server {
+    // Once a second or so; the host's own client is never limited
+    pub(super) fn check_traffic(&mut self) {
+        let now = Instant::now();
+        let secs = now
+            .duration_since(self.traffic_checked)
+            .as_secs_f64()
+            .max(1.0);
+        self.traffic_checked = now;
+        let (max_messages, max_bytes) = (
+            self.config.throttle.max_messages_per_sec,
+            self.config.throttle.max_bytes_per_sec,
+        );
+        for (endpoint, usage) in self.game_state.traffic.take_recent() {
+            let messages = usage.total.messages_in as f64 / secs;
+            let bytes = usage.total.bytes_in as f64 / secs;
+            let over = (max_messages > 0 && messages > max_messages as f64)
+                || (max_bytes > 0 && bytes > max_bytes as f64);
+            if !over || endpoint.is_local() {
+                continue;
+            }
+            println!(
+                "{:?} sent {:.0} messages and {:.0} bytes a second, mostly {}",
+                endpoint,
+                messages,
+                bytes,
+                usage.busiest_in().unwrap_or("nothing")
+            );
+            if let Some(addr) = endpoint.addr() {
+                self.throttle.flooded(addr.ip(), now);
+            }
+            self.disconnect(
+                endpoint,
+                DisconnectReason::Throttled,
+                LocalizedText::new("disconnect.flooding"),
+            );
+        }
+    }
+
+    pub(super) fn traffic_text(&self, player_id: Option<usize>) -> Result<String, String> {
+        let Some(player_id) = player_id else {
+            let mut lines: Vec<(Counters, String)> = self
+                .game_state
+                .players
+                .iter()
+                .filter_map(|player| {
+                    let counters = self.game_state.traffic.get(player.endpoint)?;
+                    let line = format!("player {}: {}", player.id, describe(&counters));
+                    Some((counters, line))
+                })
+                .collect();
+            if lines.is_empty() {
+                return Ok("no connections counted".to_string());
+            }
+            lines.sort_by_key(|(counters, _)| std::cmp::Reverse(counters.bytes_in));
+            let lines: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+            return Ok(lines.join("\n"));
+        };
+        let endpoint = self
+            .game_state
+            .players
+            .get(&player_id)
+            .map(|player| player.endpoint)
+            .ok_or_else(|| format!("no player {}", player_id))?;
+        let usage = self
+            .game_state
+            .traffic
+            .usage(endpoint)
+            .ok_or_else(|| format!("no traffic counted for player {}", player_id))?;
+        let mut lines = vec![format!(
+            "player {} ({:?}): {}",
+            player_id,
+            endpoint,
+            describe(&usage.total)
+        )];
+        let mut by_type: Vec<_> = usage.by_type.into_iter().collect();
+        by_type
+            .sort_by_key(|(_, counters)| std::cmp::Reverse(counters.bytes_in + counters.bytes_out));
+        for (kind, counters) in by_type {
+            lines.push(format!("  {:<24} {}", kind, describe(&counters)));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+fn describe(counters: &Counters) -> String {
+    format!(
+        "in {} messages, {} bytes; out {} messages, {} bytes",
+        counters.messages_in, counters.bytes_in, counters.messages_out, counters.bytes_out
+    )
+}
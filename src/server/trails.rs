@@ -0,0 +1,126 @@
+// Recording each player's trail as they move and act, and showing it with
+// `trail <player>` (see `crate::trails`).
+use super::Server;
+use crate::rooms::RoomId;
+use crate::trails::Sample;
+
+impl Server {
+    // How many ticks of each trail are kept
+    fn trail_window(&self) -> u64 {
+        self.config.trails.seconds * self.config.snapshot_rate as u64
+    }
+
+    pub(super) fn record_move(&self, player_id: usize, room: Option<RoomId>, x: f32, y: f32) {
+        if self.config.trails.seconds == 0 {
+            return;
+        }
+        let sample = Sample {
+            tick: self.tick,
+            room,
+            x,
+            y,
+        };
+        self.game_state
+            .trails
+            .entry(player_id)
+            .or_default()
+            .moved(self.trail_window(), sample);
+    }
+
+    pub(super) fn record_action(&self, player_id: usize, what: impl Into<String>) {
+        if self.config.trails.seconds == 0 {
+            return;
+        }
+        self.game_state.trails.entry(player_id).or_default().acted(
+            self.trail_window(),
+            self.tick,
+            what.into(),
+        );
+    }
+
+    // The tick emotes are judged at, when they are judged by trails
+    pub(super) fn rewind_tick(&self) -> Option<u64> {
+        let trails = &self.config.trails;
+        (trails.seconds > 0 && trails.rewind_ticks > 0)
+            .then(|| self.tick.saturating_sub(trails.rewind_ticks))
+    }
+
+    // Once a second: let go of players gone for longer than a trail lasts
+    pub(super) fn prune_trails(&self) {
+        let cutoff = self.tick.saturating_sub(self.trail_window());
+        let players = &self.game_state.players;
+        self.game_state
+            .trails
+            .retain(|id, trail| players.contains_key(id) || trail.last_tick() >= cutoff);
+    }
+
+    // Everything on the player's trail, or its last `seconds`, oldest first
+    pub(super) fn trail_text(
+        &self,
+        player_id: usize,
+        seconds: Option<u64>,
+    ) -> Result<String, String> {
+        if self.config.trails.seconds == 0 {
+            return Err("trails are turned off".to_string());
+        }
+        let trail = self
+            .game_state
+            .trails
+            .get(&player_id)
+            .ok_or_else(|| format!("no trail for player {}", player_id))?;
+        let rate = self.config.snapshot_rate as f32;
+        let from = seconds.map_or(0, |seconds| {
+            self.tick
+                .saturating_sub(seconds * self.config.snapshot_rate as u64)
+        });
+        let ago = |tick: u64| self.tick.saturating_sub(tick) as f32 / rate;
+
+        let mut lines = Vec::new();
+        let mut last: Option<&Sample> = None;
+        for sample in trail.positions() {
+            if sample.tick >= from {
+                let place = match sample.room {
+                    Some(room) => format!("room {}", room),
+                    None => "the lobby".to_string(),
+                };
+                // How fast they went since the last position in the same room
+                let speed =
+                    last.filter(|last| last.room == sample.room)
+                        .map_or(String::new(), |last| {
+                            let distance = (sample.x - last.x).hypot(sample.y - last.y);
+                            let secs = (sample.tick - last.tick) as f32 / rate;
+                            format!(", {:.1}/s", distance / secs)
+                        });
+                let line = format!(
+                    "{:>7.2}s ago  at ({:.1}, {:.1}) in {}{}",
+                    ago(sample.tick),
+                    sample.x,
+                    sample.y,
+                    place,
+                    speed
+                );
+                lines.push((sample.tick, line));
+            }
+            last = Some(sample);
+        }
+        for action in trail.actions().filter(|action| action.tick >= from) {
+            let line = format!("{:>7.2}s ago  {}", ago(action.tick), action.what);
+            lines.push((action.tick, line));
+        }
+        if lines.is_empty() {
+            return Ok(format!(
+                "nothing on player {}'s trail in that time",
+                player_id
+            ));
+        }
+        // Stable, so a tick's move stays ahead of what was done on it
+        lines.sort_by_key(|(tick, _)| *tick);
+        let lines: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+        Ok(format!(
+            "player {}, as of tick {}:\n{}",
+            player_id,
+            self.tick,
+            lines.join("\n")
+        ))
+    }
+}
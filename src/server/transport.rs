@@ -0,0 +1,357 @@
+// The sockets and channels frames travel over. Every stream connection (TCP,
+// QUIC's stream, the Unix socket) has a task reading it that hands frames to
+// the decoder, and a writer emptying its queue; sending goes through a
+// cloneable `Connections` so any task can write frames. Frames go as
+// message_io's framed TCP sends them, with `read_frame` and `write_frame`.
+use super::inbound::Inbound;
+use super::tcp;
+use crate::cluster::LinkFrame;
+use crate::endpoint::Endpoint;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+#[derive(Clone)]
+pub(super) struct Connections {
+    // The queues to open TCP connections' writers, by connection number
+    tcp: Arc<Mutex<HashMap<u64, UnboundedSender<Vec<u8>>>>>,
+    next_tcp_id: Arc<AtomicU64>,
+    // Frames back to in-process clients, by local connection id
+    local: Arc<Mutex<HashMap<u64, Sender<Vec<u8>>>>>,
+    // Shared by the host's local clients and the server's bots
+    next_local_id: Arc<AtomicU64>,
+    // Gateway links by number
+    gateways: Arc<Mutex<HashMap<u32, GatewayLink>>>,
+    // The queues to open QUIC connections' writers, by connection number
+    quic: Arc<Mutex<HashMap<u64, UnboundedSender<QuicFrame>>>>,
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    next_quic_id: Arc<AtomicU64>,
+    // The same for connections on the Unix domain socket
+    unix: Arc<Mutex<HashMap<u64, UnboundedSender<Vec<u8>>>>>,
+    next_unix_id: Arc<AtomicU64>,
+}
+
+// The queue to a gateway link's writer, and the gateway's connections this
+// server still has open, with their addresses
+struct GatewayLink {
+    frames: Sender<LinkFrame>,
+    open: HashMap<u64, SocketAddr>,
+}
+
+// What a QUIC connection's writer sends, and how
+#[cfg_attr(not(feature = "quic"), allow(dead_code))]
+pub(super) enum QuicFrame {
+    Stream(Vec<u8>),
+    // Falls back to the stream when the peer takes no datagrams this big
+    Datagram(Vec<u8>),
+}
+
+impl Connections {
+    pub(super) fn send(&self, endpoint: Endpoint, data: &[u8]) {
+        match endpoint {
+            Endpoint::Tcp { connection, .. } => {
+                if let Some(frames) = self.tcp.lock().unwrap().get(&connection) {
+                    frames.send(data.to_vec()).ok();
+                }
+            }
+            Endpoint::Local(id) => {
+                if let Some(sender) = self.local.lock().unwrap().get(&id) {
+                    sender.send(data.to_vec()).ok();
+                }
+            }
+            Endpoint::Gateway {
+                link, connection, ..
+            } => {
+                let data = data.to_vec();
+                self.to_gateway(link, LinkFrame::Data { connection, data });
+            }
+            Endpoint::Quic { connection, .. } => {
+                self.to_quic(connection, QuicFrame::Stream(data.to_vec()));
+            }
+            Endpoint::Unix(id) => {
+                if let Some(frames) = self.unix.lock().unwrap().get(&id) {
+                    frames.send(data.to_vec()).ok();
+                }
+            }
+        }
+    }
+
+    // Like `send`, for messages that may be lost: QUIC connections carry them
+    // in datagrams, and everything else the usual way
+    pub(super) fn send_unreliable(&self, endpoint: Endpoint, data: &[u8]) {
+        match endpoint {
+            Endpoint::Quic { connection, .. } => {
+                self.to_quic(connection, QuicFrame::Datagram(data.to_vec()));
+            }
+            _ => self.send(endpoint, data),
+        }
+    }
+
+    fn to_quic(&self, connection: u64, frame: QuicFrame) {
+        if let Some(frames) = self.quic.lock().unwrap().get(&connection) {
+            frames.send(frame).ok();
+        }
+    }
+
+    pub(super) fn close(&self, endpoint: Endpoint) {
+        match endpoint {
+            // The writer sends what is queued, then closes the connection
+            Endpoint::Tcp { connection, .. } => {
+                self.remove_tcp(connection);
+            }
+            // The client sees its receiver close once the sender is gone
+            Endpoint::Local(id) => {
+                self.local.lock().unwrap().remove(&id);
+            }
+            Endpoint::Gateway {
+                link, connection, ..
+            } => {
+                self.forget(link, connection);
+                self.to_gateway(link, LinkFrame::Close { connection });
+            }
+            // The writer sends what is queued, then closes the connection
+            Endpoint::Quic { connection, .. } => {
+                self.remove_quic(connection);
+            }
+            Endpoint::Unix(id) => {
+                self.remove_unix(id);
+            }
+        }
+    }
+
+    // Have the gateway carry the connection over to `worker`
+    pub(super) fn move_to(&self, endpoint: Endpoint, worker: usize, transfer: Vec<u8>) {
+        if let Endpoint::Gateway {
+            link, connection, ..
+        } = endpoint
+        {
+            self.forget(link, connection);
+            let frame = LinkFrame::Move {
+                connection,
+                worker,
+                transfer,
+            };
+            self.to_gateway(link, frame);
+        }
+    }
+
+    fn to_gateway(&self, link: u32, frame: LinkFrame) {
+        if let Some(gateway) = self.gateways.lock().unwrap().get(&link) {
+            gateway.frames.send(frame).ok();
+        }
+    }
+
+    pub(super) fn add_gateway(&self, link: u32, frames: Sender<LinkFrame>) {
+        let gateway = GatewayLink {
+            frames,
+            open: HashMap::new(),
+        };
+        self.gateways.lock().unwrap().insert(link, gateway);
+    }
+
+    // The connections still open when the link went down
+    pub(super) fn remove_gateway(&self, link: u32) -> Vec<(u64, SocketAddr)> {
+        let gateway = self.gateways.lock().unwrap().remove(&link);
+        gateway.map_or(Vec::new(), |gateway| gateway.open.into_iter().collect())
+    }
+
+    pub(super) fn opened(&self, link: u32, connection: u64, addr: SocketAddr) {
+        if let Some(gateway) = self.gateways.lock().unwrap().get_mut(&link) {
+            gateway.open.insert(connection, addr);
+        }
+    }
+
+    // Where an open connection behind a gateway comes from
+    pub(super) fn gateway_addr(&self, link: u32, connection: u64) -> Option<SocketAddr> {
+        let gateways = self.gateways.lock().unwrap();
+        gateways.get(&link)?.open.get(&connection).copied()
+    }
+
+    // Returns the address while the connection was open
+    pub(super) fn forget(&self, link: u32, connection: u64) -> Option<SocketAddr> {
+        let mut gateways = self.gateways.lock().unwrap();
+        gateways.get_mut(&link)?.open.remove(&connection)
+    }
+
+    pub(super) fn next_tcp_id(&self) -> u64 {
+        self.next_tcp_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(super) fn add_tcp(&self, connection: u64, frames: UnboundedSender<Vec<u8>>) {
+        self.tcp.lock().unwrap().insert(connection, frames);
+    }
+
+    // Whether the connection was still open
+    pub(super) fn remove_tcp(&self, connection: u64) -> bool {
+        self.tcp.lock().unwrap().remove(&connection).is_some()
+    }
+
+    pub(super) fn next_local_id(&self) -> u64 {
+        self.next_local_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(super) fn add_local(&self, id: u64, sender: Sender<Vec<u8>>) {
+        self.local.lock().unwrap().insert(id, sender);
+    }
+
+    // Whether the connection was still open
+    pub(super) fn remove_local(&self, id: u64) -> bool {
+        self.local.lock().unwrap().remove(&id).is_some()
+    }
+
+    #[cfg(feature = "quic")]
+    pub(super) fn next_quic_id(&self) -> u64 {
+        self.next_quic_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "quic")]
+    pub(super) fn add_quic(&self, connection: u64, frames: UnboundedSender<QuicFrame>) {
+        self.quic.lock().unwrap().insert(connection, frames);
+    }
+
+    // Whether the connection was still open
+    pub(super) fn remove_quic(&self, connection: u64) -> bool {
+        self.quic.lock().unwrap().remove(&connection).is_some()
+    }
+
+    pub(super) fn next_unix_id(&self) -> u64 {
+        self.next_unix_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(super) fn add_unix(&self, id: u64, frames: UnboundedSender<Vec<u8>>) {
+        self.unix.lock().unwrap().insert(id, frames);
+    }
+
+    // Whether the connection was still open
+    pub(super) fn remove_unix(&self, id: u64) -> bool {
+        self.unix.lock().unwrap().remove(&id).is_some()
+    }
+
+    pub(super) fn stop(&self) {
+        self.tcp.lock().unwrap().clear();
+        self.quic.lock().unwrap().clear();
+        self.unix.lock().unwrap().clear();
+    }
+}
+
+// Listen on every one of `addrs` and forward what clients send to the decoder;
+// also returns the accepting tasks, to abort on shutdown, and the addresses
+// bound. TCP frames bigger than `max_frame_bytes` close the connection.
+pub(super) fn listen(
+    addrs: &[&str],
+    inbound: UnboundedSender<Inbound>,
+    max_frame_bytes: usize,
+) -> io::Result<(Connections, Vec<JoinHandle<()>>, Vec<SocketAddr>)> {
+    let connections = Connections {
+        tcp: Arc::default(),
+        next_tcp_id: Arc::new(AtomicU64::new(1)),
+        local: Arc::default(),
+        next_local_id: Arc::new(AtomicU64::new(1)),
+        gateways: Arc::default(),
+        quic: Arc::default(),
+        next_quic_id: Arc::new(AtomicU64::new(1)),
+        unix: Arc::default(),
+        next_unix_id: Arc::new(AtomicU64::new(1)),
+    };
+    let (mut listeners, mut bound) = (Vec::new(), Vec::new());
+    for addr in addrs {
+        let (listener, addr) =
+            tcp::spawn_listener(addr, inbound.clone(), connections.clone(), max_frame_bytes)?;
+        listeners.push(listener);
+        bound.push(addr);
+    }
+    Ok((connections, listeners, bound))
+}
+
+// A LEB128 length, then that many bytes; `None` once the peer ends the stream
+// cleanly. Frames bigger than `max_frame_bytes` are an error.
+pub(super) async fn read_frame(
+    stream: &mut (impl AsyncRead + Unpin),
+    max_frame_bytes: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut len = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = match stream.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && shift == 0 => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if shift >= u64::BITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame length too long",
+            ));
+        }
+        len |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if len > max_frame_bytes as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes", len),
+        ));
+    }
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+pub(super) async fn write_frame(
+    stream: &mut (impl AsyncWrite + Unpin),
+    data: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(data.len() + 10);
+    let mut len = data.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            frame.push(byte);
+            break;
+        }
+        frame.push(byte | 0x80);
+    }
+    frame.extend_from_slice(data);
+    stream.write_all(&frame).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_round_trip() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, &[7; 300]).await.unwrap();
+        write_frame(&mut stream, &[]).await.unwrap();
+        let mut read = stream.as_slice();
+        assert_eq!(
+            read_frame(&mut read, 300).await.unwrap(),
+            Some(vec![7; 300])
+        );
+        assert_eq!(read_frame(&mut read, 300).await.unwrap(), Some(vec![]));
+        assert_eq!(read_frame(&mut read, 300).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_refused_from_their_length() {
+        // A prefix promising a gigabyte, and none of it following
+        let mut read: &[u8] = &[0x80, 0x80, 0x80, 0x80, 0x04];
+        let e = read_frame(&mut read, 64 * 1024).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let mut read: &[u8] = &[0xff; 11];
+        let e = read_frame(&mut read, usize::MAX).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,88 @@
+// The Unix domain socket listener (`unix_socket`): frames as over framed
+// TCP, for local tools that shouldn't need a network port. The socket file
+// is made readable and writable by the server's user only.
+use super::inbound::Inbound;
+use super::transport::{read_frame, write_frame, Connections};
+use crate::endpoint::Endpoint;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::io::BufReader;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+pub(super) fn spawn_listener(
+    path: &Path,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) -> io::Result<()> {
+    // Left behind by an earlier run; anything else there is not ours to remove
+    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    println!("Listening on Unix socket {}", path.display());
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let (inbound, connections) = (inbound.clone(), connections.clone());
+                    tokio::spawn(serve(stream, inbound, connections, max_frame_bytes));
+                }
+                Err(e) => eprintln!("Unix socket accept failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn serve(
+    stream: UnixStream,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) {
+    let id = connections.next_unix_id();
+    let endpoint = Endpoint::Unix(id);
+    let (read, write) = stream.into_split();
+    let (frames, queue) = mpsc::unbounded_channel();
+    connections.add_unix(id, frames);
+    inbound.send(Inbound::Accepted(endpoint)).ok();
+    let reader = tokio::spawn(read_frames(read, id, inbound, connections, max_frame_bytes));
+    // The server closed the connection, rather than the client going away
+    if write_frames(write, queue).await.is_ok() {
+        reader.abort();
+    }
+}
+
+async fn read_frames(
+    read: OwnedReadHalf,
+    id: u64,
+    inbound: UnboundedSender<Inbound>,
+    connections: Connections,
+    max_frame_bytes: usize,
+) {
+    let mut read = BufReader::new(read);
+    while let Ok(Some(frame)) = read_frame(&mut read, max_frame_bytes).await {
+        inbound.send(Inbound::Frame(Endpoint::Unix(id), frame)).ok();
+    }
+    if connections.remove_unix(id) {
+        inbound.send(Inbound::Disconnected(Endpoint::Unix(id))).ok();
+    }
+}
+
+// Until the server closes the connection; the socket shuts down once what
+// was queued is written
+async fn write_frames(
+    mut write: OwnedWriteHalf,
+    mut queue: UnboundedReceiver<Vec<u8>>,
+) -> io::Result<()> {
+    while let Some(frame) = queue.recv().await {
+        write_frame(&mut write, &frame).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Whispers go to one player and back to the sender, and nowhere else. A
+// whisper to someone who muted the sender is echoed as if it went out, so
+// muting can't be detected.
+use super::Server;
+use crate::achievements::GameEvent;
+use crate::endpoint::Endpoint;
+use crate::protocol::{ClientMessage, ErrorCode, LocalizedText};
+use crate::throttle;
+use std::time::Instant;
+
+impl Server {
+    pub(super) fn on_whisper(&mut self, endpoint: Endpoint, target_id: usize, text: String) {
+        let Some(&sender_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let target = self
+            .game_state
+            .players
+            .get(&target_id)
+            .filter(|p| p.joined)
+            .map(|p| {
+                (
+                    p.endpoint,
+                    p.snapshot_format.is_some(),
+                    p.muted.contains(&sender_id),
+                )
+            });
+        let problem = match target {
+            _ if target_id == sender_id => Some("you can't whisper to yourself".to_string()),
+            None => Some(format!("player {} is not online", target_id)),
+            Some((_, false, _)) => Some(format!("player {} can't receive whispers", target_id)),
+            Some(_) => None,
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        if !self.check_whisper_rate(endpoint, sender_id) {
+            return;
+        }
+        let Some((target_endpoint, _, muted)) = target else {
+            return;
+        };
+        let name = self
+            .game_state
+            .players
+            .get(&sender_id)
+            .and_then(|p| p.name.clone());
+        let message = ClientMessage::WhisperMessage {
+            from_id: sender_id,
+            to_id: target_id,
+            name,
+            text,
+        };
+        if !muted {
+            self.send(target_endpoint, &message);
+        }
+        self.send(endpoint, &message);
+        println!("Player {} whispered to player {}", sender_id, target_id);
+        self.count_event(sender_id, GameEvent::Whisper);
+    }
+
+    // Whispers have a limit of their own, for everyone, apart from guest chat
+    pub(super) fn check_whisper_rate(&self, endpoint: Endpoint, player_id: usize) -> bool {
+        let whispers = &self.config.whispers;
+        let allowed = self
+            .game_state
+            .players
+            .get_mut(&player_id)
+            .is_some_and(|mut player| {
+                throttle::take_slot(
+                    &mut player.recent_whispers,
+                    whispers.messages,
+                    whispers.window(),
+                    Instant::now(),
+                )
+            });
+        if !allowed {
+            self.reject(
+                endpoint,
+                ErrorCode::RateLimited,
+                LocalizedText::new("error.whisper_rate")
+                    .with("count", whispers.messages)
+                    .with("seconds", whispers.window_secs),
+            );
+        }
+        allowed
+    }
+
+    pub(super) fn on_mute(&mut self, endpoint: Endpoint, player_id: usize, mute: bool) {
+        let Some(&own_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        // Unmuting someone who has since left is fine
+        let problem = if player_id == own_id {
+            Some("you can't mute yourself".to_string())
+        } else if mute
+            && !self
+                .game_state
+                .players
+                .get(&player_id)
+                .is_some_and(|p| p.joined)
+        {
+            Some(format!("player {} is not online", player_id))
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        if let Some(mut player) = self.game_state.players.get_mut(&own_id) {
+            if mute {
+                player.muted.insert(player_id);
+            } else {
+                player.muted.remove(&player_id);
+            }
+        }
+        println!(
+            "Player {} {} player {}",
+            own_id,
+            if mute { "muted" } else { "unmuted" },
+            player_id
+        );
+    }
+}
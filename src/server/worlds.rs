@@ -0,0 +1,417 @@
+// Named worlds (see `crate::worlds`): loading them as players come, streaming
+// each player the terrain around them, saving what changes, and letting them
+// go once they stand empty.
+use super::persistence::Persist;
+use super::Server;
+use crate::endpoint::Endpoint;
+use crate::protocol::{CellPosition, ClientMessage, ErrorCode, TerrainCell, TerrainChange};
+use crate::rooms::RoomId;
+use crate::throttle;
+use crate::worlds::{TerrainEdit, WorldConfig, WorldSave, MAX_CELL_LEN};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+pub(super) struct LoadedWorld {
+    room_id: RoomId,
+    terrain: BTreeMap<(i32, i32), String>,
+    // What is on disk, or on its way there
+    saved: WorldSave,
+    // Changes journaled since it was last saved whole
+    journaled: usize,
+    // When the last player left, while no one is in it
+    empty_since: Option<Instant>,
+}
+
+pub(super) struct StreamedChunks {
+    room_id: RoomId,
+    // The chunk the player stood in when last streamed to
+    center: Option<(i32, i32)>,
+    chunks: BTreeSet<(i32, i32)>,
+}
+
+impl Server {
+    pub(super) fn on_enter_world(&mut self, endpoint: Endpoint, name: String) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        if !self.config.worlds.names.contains(&name) {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("no world `{}`", name),
+            );
+            return;
+        }
+        if let Some(world) = self.worlds.get(&name) {
+            let room_id = world.room_id;
+            self.on_join_room(endpoint, room_id, None);
+            return;
+        }
+        let waiting = self.loading_worlds.entry(name.clone()).or_default();
+        if !waiting.contains(&player_id) {
+            waiting.push(player_id);
+        }
+        if waiting.len() == 1 {
+            println!("Loading world {}", name);
+            self.persist.send(Persist::LoadWorld(name)).ok();
+        }
+    }
+
+    // The persistence stage read the world; bring in whoever asked for it
+    pub(super) fn on_world_loaded(&mut self, name: String, save: WorldSave) {
+        let waiting = self.loading_worlds.remove(&name).unwrap_or_default();
+        if self.worlds.contains_key(&name) {
+            return;
+        }
+        let room_id = self
+            .game_state
+            .rooms
+            .write()
+            .unwrap()
+            .create(name.clone(), None, true);
+        let room = Some(room_id);
+        for entity in &save.entities {
+            if let Err(e) = self
+                .entities
+                .spawn(&self.config.entities, room, entity.clone())
+            {
+                eprintln!("Dropped an entity of world {}: {}", name, e);
+            }
+        }
+        let terrain = save
+            .terrain
+            .iter()
+            .map(|cell| ((cell.x, cell.y), cell.value.clone()))
+            .collect();
+        println!(
+            "Loaded world {} into room {} ({} entities, {} terrain cells)",
+            name,
+            room_id,
+            save.entities.len(),
+            save.terrain.len()
+        );
+        let world = LoadedWorld {
+            room_id,
+            terrain,
+            saved: save,
+            journaled: 0,
+            empty_since: Some(Instant::now()),
+        };
+        self.worlds.insert(name, world);
+        for player_id in waiting {
+            let endpoint = self.game_state.players.get(&player_id).map(|p| p.endpoint);
+            if let Some(endpoint) = endpoint {
+                self.on_join_room(endpoint, room_id, None);
+            }
+        }
+    }
+
+    pub(super) fn on_modify_world(
+        &mut self,
+        endpoint: Endpoint,
+        position: CellPosition,
+        change: TerrainChange,
+    ) {
+        let Some(&player_id) = self.endpoints.get(&endpoint) else {
+            return;
+        };
+        let config = &self.config.worlds;
+        let (room, at, guest) = self
+            .game_state
+            .players
+            .get(&player_id)
+            .filter(|p| !p.spectating)
+            .map(|p| (p.room, self.game_state.position(&p), p.is_guest()))
+            .unwrap_or_default();
+        let Some(name) = self
+            .worlds
+            .iter()
+            .find(|(_, world)| room == Some(world.room_id))
+            .map(|(name, _)| name.clone())
+        else {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                "only players in a world can change its terrain",
+            );
+            return;
+        };
+        if guest && !config.guests_edit {
+            self.reject(
+                endpoint,
+                ErrorCode::PermissionDenied,
+                "guests can't change terrain here",
+            );
+            return;
+        }
+        let allowed = self
+            .game_state
+            .players
+            .get_mut(&player_id)
+            .is_some_and(|mut player| {
+                throttle::take_slot(
+                    &mut player.recent_edits,
+                    config.edits,
+                    config.edit_window(),
+                    Instant::now(),
+                )
+            });
+        if !allowed {
+            self.reject(
+                endpoint,
+                ErrorCode::RateLimited,
+                format!(
+                    "at most {} terrain changes every {}s",
+                    config.edits, config.edit_window_secs
+                ),
+            );
+            return;
+        }
+        let CellPosition { x, y } = position;
+        // Players without a position yet stand at the origin
+        let (at_x, at_y) = at.unwrap_or((0.0, 0.0));
+        let (cell_x, cell_y) = config.cell_center((x, y));
+        let in_reach = (cell_x - at_x).hypot(cell_y - at_y) <= config.reach;
+        let max_cells = config.max_cells;
+        let Some(world) = self.worlds.get_mut(&name) else {
+            return;
+        };
+        let current = world.terrain.get(&(x, y)).cloned();
+        let problem = match &change.value {
+            _ if !in_reach => Some(format!("cell ({}, {}) is out of reach", x, y)),
+            Some(value) if value.is_empty() || value.len() > MAX_CELL_LEN => Some(format!(
+                "terrain values must be 1 to {} bytes",
+                MAX_CELL_LEN
+            )),
+            Some(_) if current.is_none() && world.terrain.len() >= max_cells => Some(format!(
+                "a world can hold at most {} terrain cells",
+                max_cells
+            )),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            self.reject(endpoint, ErrorCode::InvalidRequest, problem);
+            return;
+        }
+        // Another change got there first; show the sender what it made
+        if change.seen != current {
+            self.reject(
+                endpoint,
+                ErrorCode::InvalidRequest,
+                format!("cell ({}, {}) changed since you saw it", x, y),
+            );
+            let message = ClientMessage::TerrainChanged {
+                x,
+                y,
+                value: current,
+            };
+            self.send(endpoint, &message);
+            return;
+        }
+        if change.value == current {
+            return;
+        }
+        let value = change.value;
+        match &value {
+            Some(value) => world.terrain.insert((x, y), value.clone()),
+            None => world.terrain.remove(&(x, y)),
+        };
+        world.journaled += 1;
+        let room_id = world.room_id;
+        let edit = TerrainEdit {
+            x,
+            y,
+            value: value.clone(),
+        };
+        self.journal(&WorldSave::storage_key(&name), &edit);
+        self.record_action(player_id, format!("terrain ({}, {}) in {}", x, y, name));
+        // Only those who were sent the chunk hear of it
+        let chunk = self.config.worlds.chunk_of((x, y));
+        let message = ClientMessage::TerrainChanged { x, y, value };
+        for (player_id, streamed) in &self.streamed {
+            if streamed.room_id != room_id || !streamed.chunks.contains(&chunk) {
+                continue;
+            }
+            let endpoint = self.game_state.players.get(player_id).map(|p| p.endpoint);
+            if let Some(endpoint) = endpoint {
+                self.send(endpoint, &message);
+            }
+        }
+    }
+
+    // Each tick: send every player in a world the chunks that came into view
+    // as they moved, and have them forget those that went well out of it
+    pub(super) fn stream_chunks(&mut self) {
+        let config = &self.config.worlds;
+        let mut in_worlds = HashMap::new();
+        for player in self.game_state.players.iter() {
+            let Some(room_id) = player.room.filter(|_| player.snapshot_format.is_some()) else {
+                continue;
+            };
+            if !self.worlds.values().any(|world| world.room_id == room_id) {
+                continue;
+            }
+            // Players without a position yet stand at the origin
+            let at = self.game_state.position(&player).unwrap_or((0.0, 0.0));
+            let center = config.chunk_of(config.cell_at(at));
+            in_worlds.insert(player.id, (player.endpoint, room_id, center));
+        }
+        // Players who left the world, or the server, start over on entering
+        self.streamed.retain(|player_id, streamed| {
+            in_worlds
+                .get(player_id)
+                .is_some_and(|&(_, room_id, _)| room_id == streamed.room_id)
+        });
+        let view = config.view_chunks as i32;
+        let mut messages = Vec::new();
+        for (player_id, (endpoint, room_id, center)) in in_worlds {
+            let Some(world) = self.worlds.values().find(|world| world.room_id == room_id) else {
+                continue;
+            };
+            let streamed = self
+                .streamed
+                .entry(player_id)
+                .or_insert_with(|| StreamedChunks {
+                    room_id,
+                    center: None,
+                    chunks: BTreeSet::new(),
+                });
+            if streamed.center == Some(center) {
+                continue;
+            }
+            streamed.center = Some(center);
+            streamed.chunks.retain(|&(x, y)| {
+                let keep = chunk_distance((x, y), center) <= view as u32 + 1;
+                if !keep {
+                    messages.push((endpoint, ClientMessage::ForgetChunk { x, y }));
+                }
+                keep
+            });
+            for x in center.0.saturating_sub(view)..=center.0.saturating_add(view) {
+                for y in center.1.saturating_sub(view)..=center.1.saturating_add(view) {
+                    if streamed.chunks.insert((x, y)) {
+                        let cells = chunk_cells(config, &world.terrain, (x, y));
+                        messages.push((endpoint, ClientMessage::TerrainChunk { x, y, cells }));
+                    }
+                }
+            }
+        }
+        for (endpoint, message) in messages {
+            self.send(endpoint, &message);
+        }
+    }
+
+    // Save every world whole that changed since it last was
+    pub(super) fn save_worlds(&mut self) {
+        let names: Vec<String> = self.worlds.keys().cloned().collect();
+        for name in names {
+            self.save_world(&name, true);
+        }
+    }
+
+    // Terrain changes are already in the journal, so short of `whole` they
+    // only get the world saved once there are `compact_after` of them
+    fn save_world(&mut self, name: &str, whole: bool) {
+        let compact_after = self.config.worlds.compact_after;
+        let Some(world) = self.worlds.get(name) else {
+            return;
+        };
+        let save = WorldSave {
+            entities: self
+                .entities
+                .in_room(Some(world.room_id))
+                .map(|(_, entity)| entity.clone())
+                .collect(),
+            terrain: terrain_cells(&world.terrain),
+        };
+        let due = save.entities != world.saved.entities
+            || world.journaled >= compact_after
+            || (whole && save != world.saved);
+        if !due {
+            return;
+        }
+        self.save(&WorldSave::storage_key(name), &save);
+        if let Some(world) = self.worlds.get_mut(name) {
+            world.saved = save;
+            world.journaled = 0;
+        }
+    }
+
+    // Once a second: save what is due to be, and let go of worlds empty for
+    // `worlds.idle_secs`
+    pub(super) fn check_worlds(&mut self) {
+        let names: Vec<String> = self.worlds.keys().cloned().collect();
+        for name in names {
+            self.save_world(&name, false);
+        }
+        let idle = Duration::from_secs(self.config.worlds.idle_secs);
+        let now = Instant::now();
+        let rooms = self.game_state.rooms.read().unwrap();
+        let mut unload = Vec::new();
+        for (name, world) in &mut self.worlds {
+            let empty = rooms
+                .get(world.room_id)
+                .is_none_or(|room| room.members.is_empty());
+            if !empty {
+                world.empty_since = None;
+                continue;
+            }
+            let since = *world.empty_since.get_or_insert(now);
+            if now.duration_since(since) >= idle {
+                unload.push(name.clone());
+            }
+        }
+        drop(rooms);
+        for name in unload {
+            self.save_world(&name, true);
+            let Some(world) = self.worlds.remove(&name) else {
+                continue;
+            };
+            self.game_state.rooms.write().unwrap().remove(world.room_id);
+            self.room_invites
+                .retain(|&(_, room_id), _| room_id != world.room_id);
+            println!("Unloaded world {} from room {}", name, world.room_id);
+        }
+    }
+
+    // The rooms the loaded worlds are played in
+    pub(super) fn world_rooms(&self) -> Vec<RoomId> {
+        self.worlds.values().map(|world| world.room_id).collect()
+    }
+}
+
+// How many chunks apart two chunks are, counting diagonal steps as one
+fn chunk_distance(a: (i32, i32), b: (i32, i32)) -> u32 {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+// The set cells of a chunk
+fn chunk_cells(
+    config: &WorldConfig,
+    terrain: &BTreeMap<(i32, i32), String>,
+    (x, y): (i32, i32),
+) -> Vec<TerrainCell> {
+    let size = config.chunk_size as i32;
+    let (left, top) = (x.saturating_mul(size), y.saturating_mul(size));
+    let (right, bottom) = (left.saturating_add(size - 1), top.saturating_add(size - 1));
+    terrain
+        .range((left, top)..=(right, bottom))
+        .filter(|(&(_, cell_y), _)| (top..=bottom).contains(&cell_y))
+        .map(|(&(x, y), value)| TerrainCell {
+            x,
+            y,
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn terrain_cells(terrain: &BTreeMap<(i32, i32), String>) -> Vec<TerrainCell> {
+    terrain
+        .iter()
+        .map(|(&(x, y), value)| TerrainCell {
+            x,
+            y,
+            value: value.clone(),
+        })
+        .collect()
+}
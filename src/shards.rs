@@ -0,0 +1,324 @@
+// Per-room simulation state. Rooms are spread across shards, each owned by
+// its own worker thread, so independent matches never contend on one lock.
+//
+// Snapshots dead-reckon players whose moves stop arriving: once a moving
+// player has sent nothing for `dead_reckoning.stale_after_ms`, they are shown
+// carrying on at the velocity of their last moves, for up to
+// `dead_reckoning.extrapolate_ms` past the last one, and flagged `stale`
+// until they are heard from again. Only snapshots are guessed; the server
+// itself still has them where they last said.
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::maps::Shape;
+use crate::protocol::PlayerSnapshot;
+use crate::rooms::{RoomId, TeamId};
+use crate::sight::Fog;
+use crate::spatial::{self, Grid};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DeadReckoningConfig {
+    pub stale_after_ms: u64,
+    // 0 flags stale players without moving them
+    pub extrapolate_ms: u64,
+}
+
+impl Default for DeadReckoningConfig {
+    fn default() -> Self {
+        DeadReckoningConfig {
+            stale_after_ms: 150,
+            extrapolate_ms: 500,
+        }
+    }
+}
+
+impl DeadReckoningConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stale_after_ms == 0 {
+            return Err("`dead_reckoning.stale_after_ms` must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    // The same limits in ticks, at least one
+    pub fn in_ticks(&self, snapshot_rate: u32) -> DeadReckoning {
+        let ticks = |ms: u64| (ms * snapshot_rate as u64).div_ceil(1000);
+        DeadReckoning {
+            stale_after: ticks(self.stale_after_ms).max(1),
+            extrapolate: ticks(self.extrapolate_ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeadReckoning {
+    pub stale_after: u64,
+    pub extrapolate: u64,
+}
+
+// A joined player as the shard sees them: where they are and how to reach them
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub endpoint: Endpoint,
+    pub wire_format: WireFormat,
+    pub snapshot_format: Option<SnapshotFormat>,
+    pub team: Option<TeamId>,
+    pub spectating: bool,
+    pub x: f32,
+    pub y: f32,
+}
+
+// How the shard reaches a member, kept apart from the hot position columns
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    pub endpoint: Endpoint,
+    pub wire_format: WireFormat,
+    pub snapshot_format: Option<SnapshotFormat>,
+    // For fog of war (see `crate::sight`)
+    pub team: Option<TeamId>,
+    pub spectating: bool,
+}
+
+// One room's members in parallel columns, so that building snapshots and
+// scanning positions walks dense arrays instead of chasing map entries.
+// `index` maps a player id to its row; removing a row swaps the last one in.
+#[derive(Debug, Default)]
+pub struct Members {
+    ids: Vec<usize>,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    // Per tick, from the player's last two moves, and the tick of the last
+    velocities: Vec<(f32, f32)>,
+    moved_at: Vec<u64>,
+    links: Vec<Link>,
+    index: HashMap<usize, usize>,
+    grid: Grid,
+}
+
+impl Members {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn ids(&self) -> &[usize] {
+        &self.ids
+    }
+
+    pub fn xs(&self) -> &[f32] {
+        &self.xs
+    }
+
+    pub fn ys(&self) -> &[f32] {
+        &self.ys
+    }
+
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    // Replaces the player's row if they are already a member
+    pub fn insert(&mut self, player_id: usize, member: Member) {
+        let link = Link {
+            endpoint: member.endpoint,
+            wire_format: member.wire_format,
+            snapshot_format: member.snapshot_format,
+            team: member.team,
+            spectating: member.spectating,
+        };
+        if let Some(&row) = self.index.get(&player_id) {
+            self.set_position(player_id, member.x, member.y);
+            self.links[row] = link;
+            return;
+        }
+        self.grid.insert(player_id, member.x, member.y);
+        self.index.insert(player_id, self.ids.len());
+        self.ids.push(player_id);
+        self.xs.push(member.x);
+        self.ys.push(member.y);
+        self.velocities.push((0.0, 0.0));
+        self.moved_at.push(0);
+        self.links.push(link);
+    }
+
+    pub fn remove(&mut self, player_id: usize) -> Option<Member> {
+        let row = self.index.remove(&player_id)?;
+        self.ids.swap_remove(row);
+        let x = self.xs.swap_remove(row);
+        let y = self.ys.swap_remove(row);
+        self.velocities.swap_remove(row);
+        self.moved_at.swap_remove(row);
+        let link = self.links.swap_remove(row);
+        if let Some(&moved) = self.ids.get(row) {
+            self.index.insert(moved, row);
+        }
+        self.grid.remove(player_id, x, y);
+        Some(Member {
+            endpoint: link.endpoint,
+            wire_format: link.wire_format,
+            snapshot_format: link.snapshot_format,
+            team: link.team,
+            spectating: link.spectating,
+            x,
+            y,
+        })
+    }
+
+    pub fn position(&self, player_id: usize) -> Option<(f32, f32)> {
+        let row = *self.index.get(&player_id)?;
+        Some((self.xs[row], self.ys[row]))
+    }
+
+    // Put the player somewhere, at a standstill. Returns whether the player
+    // is a member.
+    pub fn set_position(&mut self, player_id: usize, x: f32, y: f32) -> bool {
+        let Some(&row) = self.index.get(&player_id) else {
+            return false;
+        };
+        self.grid
+            .relocate(player_id, (self.xs[row], self.ys[row]), (x, y));
+        self.xs[row] = x;
+        self.ys[row] = y;
+        self.velocities[row] = (0.0, 0.0);
+        true
+    }
+
+    // A move the player sent at `tick`, which their velocity is measured by
+    pub fn record_move(&mut self, player_id: usize, x: f32, y: f32, tick: u64) -> bool {
+        let Some(&row) = self.index.get(&player_id) else {
+            return false;
+        };
+        let (from, velocity) = ((self.xs[row], self.ys[row]), self.velocities[row]);
+        let elapsed = tick.saturating_sub(self.moved_at[row]);
+        self.set_position(player_id, x, y);
+        self.velocities[row] = match elapsed {
+            // Moves within one tick keep the velocity they had
+            0 => velocity,
+            _ => ((x - from.0) / elapsed as f32, (y - from.1) / elapsed as f32),
+        };
+        self.moved_at[row] = tick;
+        true
+    }
+
+    // Every member as snapshots show them at `tick`. Players standing still
+    // are never stale; there is nothing to guess.
+    pub fn snapshot(&self, tick: u64, limits: DeadReckoning) -> Vec<PlayerSnapshot> {
+        (0..self.ids.len())
+            .map(|row| {
+                let (x, y) = (self.xs[row], self.ys[row]);
+                let (vx, vy) = self.velocities[row];
+                let silent = tick.saturating_sub(self.moved_at[row]);
+                let stale = silent >= limits.stale_after && (vx != 0.0 || vy != 0.0);
+                let ahead = if stale {
+                    silent.min(limits.extrapolate) as f32
+                } else {
+                    0.0
+                };
+                PlayerSnapshot {
+                    id: self.ids[row],
+                    x: x + vx * ahead,
+                    y: y + vy * ahead,
+                    stale,
+                }
+            })
+            .collect()
+    }
+
+    pub fn link(&self, player_id: usize) -> Option<&Link> {
+        let row = *self.index.get(&player_id)?;
+        Some(&self.links[row])
+    }
+
+    pub fn link_mut(&mut self, player_id: usize) -> Option<&mut Link> {
+        let row = *self.index.get(&player_id)?;
+        Some(&mut self.links[row])
+    }
+
+    // Members no further than `radius` from `center`, in no particular order
+    pub fn within(&self, center: (f32, f32), radius: f32) -> Vec<usize> {
+        let min = (center.0 - radius, center.1 - radius);
+        let max = (center.0 + radius, center.1 + radius);
+        let mut found = self.grid.candidates(min, max);
+        found.retain(|&id| self.distance_squared(id, center) <= radius * radius);
+        found
+    }
+
+    // The `n` members closest to `center`, nearest first
+    pub fn nearest(&self, center: (f32, f32), n: usize) -> Vec<usize> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut found: Vec<(f32, usize)> = Vec::new();
+        let origin = spatial::cell_of(center.0, center.1);
+        let mut seen = 0;
+        for ring in 0.. {
+            // Once a ring has more cells than are occupied, scanning the
+            // position columns directly is cheaper than widening the search
+            if ring > 0 && ring as usize * 8 > self.grid.occupied() {
+                found = self.scan_distances(center);
+                break;
+            }
+            for id in self.grid.ring(origin, ring) {
+                found.push((self.distance_squared(id, center), id));
+                seen += 1;
+            }
+            found.sort_by(|a, b| a.0.total_cmp(&b.0));
+            found.truncate(n);
+            // Nobody in a further ring can be closer than this
+            let reach = ring as f32 * spatial::CELL_SIZE;
+            let settled = found.len() == n && found[n - 1].0 <= reach * reach;
+            if seen == self.len() || settled {
+                break;
+            }
+        }
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found.into_iter().take(n).map(|(_, id)| id).collect()
+    }
+
+    fn distance_squared(&self, player_id: usize, center: (f32, f32)) -> f32 {
+        let (x, y) = self.position(player_id).unwrap_or((f32::NAN, f32::NAN));
+        (x - center.0).powi(2) + (y - center.1).powi(2)
+    }
+
+    fn scan_distances(&self, center: (f32, f32)) -> Vec<(f32, usize)> {
+        self.xs
+            .iter()
+            .zip(&self.ys)
+            .zip(&self.ids)
+            .map(|((x, y), &id)| ((x - center.0).powi(2) + (y - center.1).powi(2), id))
+            .collect()
+    }
+}
+
+// The members of every room on one shard, by room (`None` is the lobby)
+#[derive(Debug, Default)]
+pub struct Shard {
+    pub rooms: HashMap<Option<RoomId>, Members>,
+    // With `sight.snapshots` on (see `crate::sight`): what players may see
+    // of each other, and the obstacles in each room that hide them
+    pub fog: Option<Fog>,
+    pub obstacles: HashMap<Option<RoomId>, Vec<Shape>>,
+}
+
+impl Shard {
+    // The fog of war over a room, unless nothing in it could be hidden
+    pub fn fog_over(&self, room: Option<RoomId>) -> Option<(Fog, &[Shape])> {
+        let fog = self.fog?;
+        let obstacles = self.obstacles.get(&room).map_or(&[][..], Vec::as_slice);
+        (fog.vision.is_some() || !obstacles.is_empty()).then_some((fog, obstacles))
+    }
+
+    pub fn position(&self, room: Option<RoomId>, player_id: usize) -> Option<(f32, f32)> {
+        self.rooms.get(&room)?.position(player_id)
+    }
+}
+
+// Which of `count` shards a room lives on; the lobby is always on the first
+pub fn shard_index(room: Option<RoomId>, count: usize) -> usize {
+    room.map_or(0, |id| id as usize % count)
+}
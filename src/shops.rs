@@ -0,0 +1,145 @@
+// Shops, by name, from the JSON file `shops.file` points at, each with what
+// it sells and buys back and for how much. A shop is attached to an entity
+// (see `crate::entities`), such as a vendor NPC the hosting game simulates,
+// by its `shop` metadata; interacting with it shows its offers. Prices are
+// in one currency, itself an item in the player's inventory (see
+// `crate::inventory`), and a trade swaps items and currency all at once or
+// not at all.
+use crate::mail::ItemGrant;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// The entity metadata naming a vendor's shop
+pub const SHOP_KEY: &str = "shop";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ShopConfig {
+    // No file means no shops
+    pub file: Option<PathBuf>,
+    // The inventory item prices are paid in
+    pub currency: String,
+}
+
+impl Default for ShopConfig {
+    fn default() -> Self {
+        ShopConfig {
+            file: None,
+            currency: "gold".to_string(),
+        }
+    }
+}
+
+impl ShopConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.currency.is_empty() {
+            return Err("`shops.currency` can't be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// What a shop charges for one of an item, and pays for one back. `None`
+// means it doesn't.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct Price {
+    pub buy: Option<u32>,
+    pub sell: Option<u32>,
+}
+
+// One item of a shop's, as players are shown it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShopOffer {
+    pub item: String,
+    pub buy: Option<u32>,
+    pub sell: Option<u32>,
+}
+
+// Why a trade didn't go ahead
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeError {
+    // The entity isn't a shop, or there are none
+    NoShop,
+    OutOfReach,
+    // The shop doesn't sell, or doesn't buy, the item
+    NotTraded,
+    // Guests have no inventory to trade from
+    NotLoggedIn,
+    MissingItems,
+    // What it pays wouldn't fit (see `crate::inventory`)
+    InventoryFull,
+}
+
+#[derive(Debug, Default)]
+pub struct Shops {
+    pub shops: BTreeMap<String, BTreeMap<String, Price>>,
+}
+
+impl Shops {
+    pub fn load(config: &ShopConfig, path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let shops: BTreeMap<String, BTreeMap<String, Price>> =
+            serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        for (name, items) in &shops {
+            if items.is_empty() {
+                return Err(format!("shop `{}` has nothing to trade", name));
+            }
+            if items.contains_key(&config.currency) {
+                return Err(format!("shop `{}` trades the currency itself", name));
+            }
+            for (item, price) in items {
+                if price.buy.is_none() && price.sell.is_none() {
+                    return Err(format!("shop `{}` neither sells nor buys `{}`", name, item));
+                }
+            }
+        }
+        Ok(Shops { shops })
+    }
+
+    pub fn offers(&self, shop: &str) -> Option<Vec<ShopOffer>> {
+        let items = self.shops.get(shop)?;
+        let offers = items
+            .iter()
+            .map(|(item, price)| ShopOffer {
+                item: item.clone(),
+                buy: price.buy,
+                sell: price.sell,
+            })
+            .collect();
+        Some(offers)
+    }
+
+    // What trading `quantity` of `item` with `shop` costs, or pays when
+    // `selling`: what the player gives and what they get
+    pub fn quote(
+        &self,
+        currency: &str,
+        shop: &str,
+        item: &str,
+        quantity: u32,
+        selling: bool,
+    ) -> Option<(ItemGrant, ItemGrant)> {
+        let price = self.shops.get(shop)?.get(item)?;
+        let each = match selling {
+            true => price.sell?,
+            false => price.buy?,
+        };
+        let goods = ItemGrant {
+            item: item.to_string(),
+            quantity,
+            id: None,
+        };
+        let money = ItemGrant {
+            item: currency.to_string(),
+            quantity: each.checked_mul(quantity)?,
+            id: None,
+        };
+        Some(match selling {
+            true => (goods, money),
+            false => (money, goods),
+        })
+    }
+}
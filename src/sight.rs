@@ -0,0 +1,87 @@
+// Line of sight through a map's obstacles (see `crate::maps`), which block
+// sight as well as moves. `raycast` finds where a line first meets one, for
+// gameplay to ask whether something can reach or see a point. With `aim` on,
+// abilities have to be aimed at a point their caster can see, so an ability
+// aimed through a wall (a sign of a client drawing what it shouldn't) is
+// refused and noted in the caster's trail; with `areas` on, area events don't
+// catch players sheltered behind an obstacle from their center.
+//
+// With `snapshots` on, replication is under fog of war: a client is only told
+// where the players are that it could legitimately draw, so a hacked one that
+// draws everything learns nothing it shouldn't. Each player sees themselves,
+// their teammates, and whoever they, or with `team_vision` any teammate, have
+// a line of sight to within `vision` (0 for no limit). Spectators see
+// everyone. That holds for snapshots and for moves as they are relayed. It
+// costs every recipient a snapshot of their own, and lines cast between
+// members every tick.
+use crate::maps::Shape;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SightConfig {
+    pub aim: bool,
+    pub areas: bool,
+    pub snapshots: bool,
+    pub vision: f32,
+    pub team_vision: bool,
+}
+
+impl Default for SightConfig {
+    fn default() -> Self {
+        SightConfig {
+            aim: true,
+            areas: true,
+            snapshots: false,
+            vision: 0.0,
+            team_vision: true,
+        }
+    }
+}
+
+impl SightConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.vision.is_finite() && self.vision >= 0.0) {
+            return Err("`sight.vision` must be 0 or more".to_string());
+        }
+        Ok(())
+    }
+
+    // What the shard workers go by, with fog of war on
+    pub fn fog(&self) -> Option<Fog> {
+        self.snapshots.then_some(Fog {
+            vision: (self.vision > 0.0).then_some(self.vision),
+            team_vision: self.team_vision,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub vision: Option<f32>,
+    pub team_vision: bool,
+}
+
+impl Fog {
+    // Whether a player at `from` sees one at `to`
+    pub fn sees(&self, obstacles: &[Shape], from: (f32, f32), to: (f32, f32)) -> bool {
+        let near = self
+            .vision
+            .is_none_or(|vision| (to.0 - from.0).hypot(to.1 - from.1) <= vision);
+        near && line_of_sight(obstacles, from, to)
+    }
+}
+
+// Where the line from `from` to `to` first meets an obstacle, if it does.
+// Obstacles the line starts inside don't stop it.
+pub fn raycast(obstacles: &[Shape], from: (f32, f32), to: (f32, f32)) -> Option<(f32, f32)> {
+    let t = obstacles
+        .iter()
+        .filter_map(|obstacle| obstacle.entered_by(from, to))
+        .min_by(f32::total_cmp)?;
+    Some((from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t))
+}
+
+pub fn line_of_sight(obstacles: &[Shape], from: (f32, f32), to: (f32, f32)) -> bool {
+    raycast(obstacles, from, to).is_none()
+}
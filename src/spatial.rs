@@ -0,0 +1,109 @@
+// A uniform grid over one room's positions. Player ids are bucketed by cell,
+// so a range query only visits the cells its circle overlaps; `Members`
+// keeps the grid in step with its position columns.
+use std::collections::HashMap;
+
+// World units per cell side. Queries with a radius much smaller than this
+// still visit a whole cell; much larger ones fall back to scanning every cell.
+pub const CELL_SIZE: f32 = 32.0;
+
+type Cell = (i32, i32);
+
+pub fn cell_of(x: f32, y: f32) -> Cell {
+    // `as` saturates, so far-off or non-finite positions share the edge cells
+    (
+        (x / CELL_SIZE).floor() as i32,
+        (y / CELL_SIZE).floor() as i32,
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct Grid {
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl Grid {
+    pub fn insert(&mut self, player_id: usize, x: f32, y: f32) {
+        self.cells.entry(cell_of(x, y)).or_default().push(player_id);
+    }
+
+    pub fn remove(&mut self, player_id: usize, x: f32, y: f32) {
+        let cell = cell_of(x, y);
+        let Some(ids) = self.cells.get_mut(&cell) else {
+            return;
+        };
+        if let Some(slot) = ids.iter().position(|&id| id == player_id) {
+            ids.swap_remove(slot);
+        }
+        if ids.is_empty() {
+            self.cells.remove(&cell);
+        }
+    }
+
+    pub fn relocate(&mut self, player_id: usize, from: (f32, f32), to: (f32, f32)) {
+        if cell_of(from.0, from.1) != cell_of(to.0, to.1) {
+            self.remove(player_id, from.0, from.1);
+            self.insert(player_id, to.0, to.1);
+        }
+    }
+
+    // Everyone in a cell overlapping the square from `min` to `max`; the
+    // caller still has to check exact distances
+    pub fn candidates(&self, min: (f32, f32), max: (f32, f32)) -> Vec<usize> {
+        let (low, high) = (cell_of(min.0, min.1), cell_of(max.0, max.1));
+        let span = |a: i32, b: i32| (b as i64 - a as i64 + 1) as u64;
+        let area = span(low.0, high.0).saturating_mul(span(low.1, high.1));
+        let inside =
+            |cell: &Cell| (low.0..=high.0).contains(&cell.0) && (low.1..=high.1).contains(&cell.1);
+
+        // A square covering more cells than are occupied is cheaper to check
+        // against the occupied cells than cell by cell
+        if area > self.cells.len() as u64 {
+            return self
+                .cells
+                .iter()
+                .filter(|(cell, _)| inside(cell))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+        }
+        let mut found = Vec::new();
+        for cx in low.0..=high.0 {
+            for cy in low.1..=high.1 {
+                if let Some(ids) = self.cells.get(&(cx, cy)) {
+                    found.extend_from_slice(ids);
+                }
+            }
+        }
+        found
+    }
+
+    // Everyone in the cells exactly `ring` steps from `center`, counting
+    // diagonal steps as one
+    pub fn ring(&self, center: Cell, ring: i32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut visit = |cell: Cell| {
+            if let Some(ids) = self.cells.get(&cell) {
+                found.extend_from_slice(ids);
+            }
+        };
+        if ring == 0 {
+            visit(center);
+            return found;
+        }
+        let (cx, cy) = center;
+        for dx in -ring..=ring {
+            visit((cx.saturating_add(dx), cy.saturating_sub(ring)));
+            visit((cx.saturating_add(dx), cy.saturating_add(ring)));
+        }
+        for dy in -ring + 1..ring {
+            visit((cx.saturating_sub(ring), cy.saturating_add(dy)));
+            visit((cx.saturating_add(ring), cy.saturating_add(dy)));
+        }
+        found
+    }
+
+    // How many cells hold anyone
+    pub fn occupied(&self) -> usize {
+        self.cells.len()
+    }
+}
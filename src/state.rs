@@ -0,0 +1,273 @@
+use crate::accounts::{AccountId, Accounts, PlayerUuid};
+use crate::achievements::AchievementProgress;
+use crate::analytics::Analytics;
+use crate::buffers::BufferStats;
+use crate::chat::ChatHistory;
+use crate::cidr::IpRange;
+use crate::codec::{SnapshotFormat, WireFormat};
+use crate::endpoint::Endpoint;
+use crate::friends::Friends;
+use crate::guilds::{GuildId, Guilds};
+use crate::health::Health;
+use crate::history::MatchHistory;
+use crate::ids::PlayerIds;
+use crate::inventory::Inventories;
+use crate::mail::Mailboxes;
+use crate::net_stats::NetProbe;
+use crate::parties::{Parties, PartyId};
+use crate::progression::Progression;
+use crate::protocol::Appearance;
+use crate::roles::Role;
+use crate::rooms::{RoomId, Rooms, TeamId};
+use crate::shards::{self, Shard};
+use crate::traffic::Traffic;
+use crate::trails::Trail;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct Player {
+    pub id: usize,
+    pub endpoint: Endpoint,
+    pub message: String,
+    pub wire_format: WireFormat,
+    // Set once the client completes the `Hello` handshake; legacy clients that
+    // skip it never receive world snapshots
+    pub snapshot_format: Option<SnapshotFormat>,
+    // When the last frame from this client arrived, for the idle timeout
+    pub last_seen: Instant,
+    pub connected_at: Instant,
+    // Whether the player is in the game. Password-protected servers only set
+    // this once `Hello` carries the right password; until then the player
+    // neither sees nor is seen by anyone.
+    pub joined: bool,
+    // The room the player is in; `None` is the lobby
+    pub room: Option<RoomId>,
+    // Granted at runtime with `grant`; lets the player send console commands
+    pub role: Option<Role>,
+    // Chat messages sent this session, for analytics
+    pub messages_sent: u64,
+    // Set with `SetName`; unique within the player's room
+    pub name: Option<String>,
+    pub appearance: Appearance,
+    // Set once the player logs in
+    pub account: Option<AccountId>,
+    // Set once a Steam ticket checks out
+    pub steam_id: Option<u64>,
+    // Made when the client connects, carried in its session, and swapped for
+    // the account's on login. Unlike `id`, it is never given to anyone else.
+    pub uuid: PlayerUuid,
+    // When a guest's recent chat lines were sent, oldest first
+    pub recent_chat: VecDeque<Instant>,
+    // When this player's recent whispers were sent, oldest first
+    pub recent_whispers: VecDeque<Instant>,
+    // When this player's recent `ModifyWorld` changes were made, oldest first
+    pub recent_edits: VecDeque<Instant>,
+    // Players whose whispers and chat this player doesn't want
+    pub muted: BTreeSet<usize>,
+    pub party: Option<PartyId>,
+    // Kept in step with `GameState::guilds` while the player is logged in
+    pub guild: Option<GuildId>,
+    // For the emote cooldown
+    pub last_emote: Option<Instant>,
+    // Both reset whenever the player changes room
+    pub team: Option<TeamId>,
+    pub spectating: bool,
+    // Sent to the client in `SessionToken`, to resume with after an upgrade
+    pub session_token: String,
+    // The highest nonce the session's trades and crafts carried so far
+    pub last_nonce: u64,
+    // The `NetStats` reports sent to this player and which came back
+    pub net_probe: NetProbe,
+}
+
+impl Player {
+    // A freshly connected client that has not joined yet
+    pub fn new(id: usize, endpoint: Endpoint, wire_format: WireFormat) -> Self {
+        Player {
+            id,
+            endpoint,
+            message: String::new(),
+            wire_format,
+            snapshot_format: None,
+            last_seen: Instant::now(),
+            connected_at: Instant::now(),
+            joined: false,
+            room: None,
+            role: None,
+            messages_sent: 0,
+            name: None,
+            appearance: Appearance::default(),
+            account: None,
+            steam_id: None,
+            uuid: PlayerUuid::new_v4(),
+            recent_chat: VecDeque::new(),
+            recent_whispers: VecDeque::new(),
+            recent_edits: VecDeque::new(),
+            muted: BTreeSet::new(),
+            party: None,
+            guild: None,
+            last_emote: None,
+            team: None,
+            spectating: false,
+            session_token: Uuid::new_v4().to_string(),
+            last_nonce: 0,
+            net_probe: NetProbe::default(),
+        }
+    }
+
+    // Guests haven't logged in; the host's own client never counts as one
+    pub fn is_guest(&self) -> bool {
+        self.account.is_none() && !self.endpoint.is_local()
+    }
+
+    // Everything a whitelist or ban entry can match for this player
+    pub fn identities(&self) -> Vec<String> {
+        let mut identities = match self.endpoint.addr() {
+            Some(addr) => vec![addr.ip().to_string()],
+            // Whoever hosts an embedded server plays on it as "local"
+            None => vec!["local".to_string()],
+        };
+        if let Some(account) = self.account {
+            identities.push(format!("account:{}", account));
+        }
+        if let Some(steam_id) = self.steam_id {
+            identities.push(format!("steam:{}", steam_id));
+        }
+        identities.push(format!("player:{}", self.uuid));
+        identities
+    }
+
+    // The identity analytics are recorded under
+    pub fn primary_identity(&self) -> String {
+        self.identities().remove(0)
+    }
+}
+
+// Admin-controlled join restrictions, persisted across restarts
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ServerModes {
+    pub maintenance: bool,
+    // Empty for the default message, which clients can translate
+    pub maintenance_message: String,
+    pub whitelist_only: bool,
+    pub whitelist: BTreeSet<String>,
+    // Identities that are never admitted
+    pub banned: BTreeSet<String>,
+    // Address ranges added with `allow` and `deny`, on top of the config file's
+    pub ip_allow: BTreeSet<IpRange>,
+    pub ip_deny: BTreeSet<IpRange>,
+}
+
+impl ServerModes {
+    pub const STORAGE_KEY: &'static str = "modes";
+}
+
+// Someone broadcasts reach
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub id: usize,
+    pub endpoint: Endpoint,
+    pub wire_format: WireFormat,
+    // Whether they sent `Hello`, and so understand messages newer than it
+    pub handshaken: bool,
+}
+
+#[derive(Default)]
+pub struct GameState {
+    // Locked per entry, so updating one player never blocks the others
+    pub players: DashMap<usize, Player>,
+    // Every joined player, copied on write so broadcasts can walk it without
+    // touching `players`
+    pub roster: RwLock<Arc<Vec<Recipient>>>,
+    pub modes: RwLock<ServerModes>,
+    pub rooms: RwLock<Rooms>,
+    pub analytics: RwLock<Analytics>,
+    pub accounts: RwLock<Accounts>,
+    pub friends: RwLock<Friends>,
+    pub parties: RwLock<Parties>,
+    pub guilds: RwLock<Guilds>,
+    pub mail: RwLock<Mailboxes>,
+    pub achievements: RwLock<AchievementProgress>,
+    pub progression: RwLock<Progression>,
+    pub inventories: RwLock<Inventories>,
+    pub chat_history: RwLock<ChatHistory>,
+    pub match_history: RwLock<MatchHistory>,
+    // What the game loop and persister report for `/healthz` and `/readyz`
+    pub health: Arc<Health>,
+    // Where each player has been lately and what they did, recorded by the
+    // game loop; kept a while after they leave
+    pub trails: DashMap<usize, Trail>,
+    // Positions live with the room, on the shard that simulates it
+    pub shards: Vec<RwLock<Shard>>,
+    pub buffers: Arc<BufferStats>,
+    // Shared with the decoder and broadcaster, which do the counting
+    pub traffic: Arc<Traffic>,
+    // Which player ids are taken, by players and sessions that may come back
+    pub player_ids: RwLock<PlayerIds>,
+}
+
+impl GameState {
+    pub fn shard(&self, room: Option<RoomId>) -> &RwLock<Shard> {
+        &self.shards[shards::shard_index(room, self.shards.len())]
+    }
+
+    // Where a joined player is; `None` until they join
+    pub fn position(&self, player: &Player) -> Option<(f32, f32)> {
+        let shard = self.shard(player.room).read().unwrap();
+        shard.position(player.room, player.id)
+    }
+
+    // Spatial queries for gameplay systems such as area effects, aggro or
+    // proximity chat. Both only see joined players in `room`, including anyone
+    // standing exactly at `center`; the caller filters out whoever is asking.
+    pub fn players_within(
+        &self,
+        room: Option<RoomId>,
+        center: (f32, f32),
+        radius: f32,
+    ) -> Vec<usize> {
+        let shard = self.shard(room).read().unwrap();
+        shard
+            .rooms
+            .get(&room)
+            .map(|members| members.within(center, radius))
+            .unwrap_or_default()
+    }
+
+    // Nearest first
+    pub fn nearest_n(&self, room: Option<RoomId>, center: (f32, f32), n: usize) -> Vec<usize> {
+        let shard = self.shard(room).read().unwrap();
+        shard
+            .rooms
+            .get(&room)
+            .map(|members| members.nearest(center, n))
+            .unwrap_or_default()
+    }
+
+    pub fn roster(&self) -> Arc<Vec<Recipient>> {
+        self.roster.read().unwrap().clone()
+    }
+
+    // Call after anyone joins, leaves or changes wire format
+    pub fn rebuild_roster(&self) {
+        let mut roster: Vec<Recipient> = self
+            .players
+            .iter()
+            .filter(|p| p.joined)
+            .map(|p| Recipient {
+                id: p.id,
+                endpoint: p.endpoint,
+                wire_format: p.wire_format,
+                handshaken: p.snapshot_format.is_some(),
+            })
+            .collect();
+        roster.sort_by_key(|r| r.id);
+        *self.roster.write().unwrap() = Arc::new(roster);
+    }
+}
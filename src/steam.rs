@@ -0,0 +1,147 @@
+// Steam session tickets. The client gets a ticket from the Steam client
+// library and sends it hex-encoded; the server asks the Steam Web API whose
+// ticket it is, which yields a SteamID the player can be known and banned by.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SteamConfig {
+    // Remote players must send a valid `SteamLogin` before they join
+    pub required: bool,
+    // A publisher Web API key and the game's app id; both turn Steam login on
+    pub api_key: Option<String>,
+    pub app_id: Option<u32>,
+    // The identity string clients pass to `GetAuthTicketForWebApi`, if any
+    pub identity: Option<String>,
+    // Refuse players Steam has flagged
+    pub deny_vac_banned: bool,
+    pub deny_publisher_banned: bool,
+    pub api_url: String,
+}
+
+impl Default for SteamConfig {
+    fn default() -> Self {
+        SteamConfig {
+            required: false,
+            api_key: None,
+            app_id: None,
+            identity: None,
+            deny_vac_banned: false,
+            deny_publisher_banned: true,
+            api_url: "https://partner.steam-api.com".to_string(),
+        }
+    }
+}
+
+impl SteamConfig {
+    pub fn enabled(&self) -> bool {
+        self.api_key.is_some() && self.app_id.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.api_key.is_some() != self.app_id.is_some() {
+            return Err("`steam.api_key` and `steam.app_id` must be set together".to_string());
+        }
+        if self.required && !self.enabled() {
+            return Err("`steam.required` needs `steam.api_key` and `steam.app_id`".to_string());
+        }
+        Ok(())
+    }
+
+    // Why Steam's flags keep `user` out, if they do
+    pub fn platform_ban(&self, user: &SteamUser) -> Option<&'static str> {
+        if self.deny_publisher_banned && user.publisher_banned {
+            Some("this Steam account is banned from the game")
+        } else if self.deny_vac_banned && user.vac_banned {
+            Some("VAC-banned Steam accounts may not play on this server")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SteamUser {
+    pub steam_id: u64,
+    // Differs from `steam_id` when the game is borrowed through Family Sharing
+    pub owner_steam_id: u64,
+    pub vac_banned: bool,
+    pub publisher_banned: bool,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    response: Response,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    params: Option<Params>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct Params {
+    result: String,
+    steamid: String,
+    ownersteamid: String,
+    #[serde(default)]
+    vacbanned: bool,
+    #[serde(default)]
+    publisherbanned: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    errorcode: i32,
+    errordesc: String,
+}
+
+// Ask Steam who `ticket` belongs to. Blocks on the HTTP request.
+pub fn authenticate_ticket(config: &SteamConfig, ticket: &str) -> Result<SteamUser, String> {
+    let (Some(key), Some(app_id)) = (&config.api_key, config.app_id) else {
+        return Err("Steam login is not configured".to_string());
+    };
+    let url = format!(
+        "{}/ISteamUserAuth/AuthenticateUserTicket/v1/",
+        config.api_url.trim_end_matches('/')
+    );
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .build()
+        .get(&url)
+        .query("key", key)
+        .query("appid", &app_id.to_string())
+        .query("ticket", ticket);
+    if let Some(identity) = &config.identity {
+        request = request.query("identity", identity);
+    }
+    let reply: Reply = request
+        .call()
+        .map_err(|e| format!("Steam Web API request failed: {}", e))?
+        .into_json()
+        .map_err(|e| format!("unexpected Steam Web API reply: {}", e))?;
+    if let Some(error) = reply.response.error {
+        return Err(format!("{} (error {})", error.errordesc, error.errorcode));
+    }
+    let params = reply
+        .response
+        .params
+        .ok_or("unexpected Steam Web API reply: no result")?;
+    if params.result != "OK" {
+        return Err(format!("ticket not accepted: {}", params.result));
+    }
+    let parse = |id: &str| {
+        id.parse::<u64>()
+            .map_err(|_| format!("Steam returned a malformed SteamID `{}`", id))
+    };
+    Ok(SteamUser {
+        steam_id: parse(&params.steamid)?,
+        owner_steam_id: parse(&params.ownersteamid)?,
+        vac_banned: params.vacbanned,
+        publisher_banned: params.publisherbanned,
+    })
+}
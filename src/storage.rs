@@ -0,0 +1,103 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+
+// JSON documents persisted under the server's data directory, one file per
+// name. A document may also keep a journal beside it, of changes too frequent
+// to rewrite it for: one JSON line each, until the document is next saved.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Storage { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn journal_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.journal", name))
+    }
+
+    // Load a document, falling back to the default when it is missing or unreadable
+    pub fn load<T: DeserializeOwned + Default>(&self, name: &str) -> T {
+        let path = self.path(name);
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                eprintln!("Ignoring corrupt {}: {}", path.display(), e);
+                T::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => T::default(),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                T::default()
+            }
+        }
+    }
+
+    // Check the directory takes writes, for `/readyz`
+    pub fn probe(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(".probe");
+        fs::write(&path, b"ok")?;
+        fs::remove_file(path)
+    }
+
+    // Write through a temporary file so a crash never leaves a half-written
+    // document. The document now holds everything its journal did.
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(name);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(value)?)?;
+        fs::rename(tmp, path)?;
+        match fs::remove_file(self.journal_path(name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn append<T: Serialize>(&self, name: &str, entry: &T) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path(name))?
+            .write_all(&line)
+    }
+
+    // A document's journal, oldest entry first, or `None` when it has none.
+    // A line a crash cut short is the last one, and is skipped; appending
+    // after it would run on from it, so save the document before adding more.
+    pub fn journal<T: DeserializeOwned>(&self, name: &str) -> Option<Vec<T>> {
+        let path = self.journal_path(name);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                }
+                return None;
+            }
+        };
+        let entries = data
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    eprintln!("Skipping a corrupt entry of {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Some(entries)
+    }
+}
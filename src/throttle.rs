@@ -0,0 +1,339 @@
+// Per-address limits on new connections. An address may only open so many
+// connections in a window and hold so many at once; one that keeps getting
+// refused, keeps dropping connections before finishing the handshake, or has
+// connections dropped for sending more than `max_messages_per_sec` or
+// `max_bytes_per_sec` (see `crate::traffic`), collects strikes and is turned
+// away entirely for a while.
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// Any limit set to 0 is off
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    // Connection attempts an address may make per window
+    pub max_connects: usize,
+    pub window_secs: u64,
+    // Connections an address may hold open at once
+    pub max_connections_per_ip: usize,
+    // Strikes within one window that get an address banned for `ban_secs`
+    pub ban_strikes: usize,
+    pub ban_secs: u64,
+    // What one connection may send, averaged over about a second
+    pub max_messages_per_sec: u64,
+    pub max_bytes_per_sec: u64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        ThrottleConfig {
+            max_connects: 20,
+            window_secs: 10,
+            max_connections_per_ip: 16,
+            ban_strikes: 10,
+            ban_secs: 300,
+            max_messages_per_sec: 500,
+            max_bytes_per_sec: 1024 * 1024,
+        }
+    }
+}
+
+impl ThrottleConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_secs == 0 {
+            return Err("`throttle.window_secs` must be a positive integer".to_string());
+        }
+        if self.ban_strikes > 0 && self.ban_secs == 0 {
+            return Err("`throttle.ban_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    TooFast,
+    TooMany,
+    // Still banned for this long
+    Banned(Duration),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::TooFast => write!(f, "too many connection attempts from your address"),
+            Refusal::TooMany => write!(f, "too many connections from your address"),
+            Refusal::Banned(left) => write!(
+                f,
+                "too many connection attempts; try again in {}s",
+                left.as_secs_f64().ceil()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Address {
+    attempts: VecDeque<Instant>,
+    strikes: VecDeque<Instant>,
+    open: usize,
+    banned_until: Option<Instant>,
+}
+
+impl Address {
+    fn forget_before(&mut self, cutoff: Instant) {
+        while self.attempts.front().is_some_and(|t| *t < cutoff) {
+            self.attempts.pop_front();
+        }
+        while self.strikes.front().is_some_and(|t| *t < cutoff) {
+            self.strikes.pop_front();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Throttle {
+    config: ThrottleConfig,
+    addresses: HashMap<IpAddr, Address>,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Throttle {
+            config,
+            addresses: HashMap::new(),
+        }
+    }
+
+    // New limits apply to the next attempt; bans already handed out stand
+    pub fn reconfigure(&mut self, config: ThrottleConfig) {
+        self.config = config;
+    }
+
+    // Record a connection attempt, refusing it if the address is over a limit
+    pub fn check(&mut self, ip: IpAddr, now: Instant) -> Result<(), Refusal> {
+        let config = &self.config;
+        let address = self.addresses.entry(ip).or_default();
+        if let Some(until) = address.banned_until {
+            if now < until {
+                return Err(Refusal::Banned(until - now));
+            }
+            address.banned_until = None;
+        }
+        address.forget_before(now.checked_sub(config.window()).unwrap_or(now));
+
+        let refusal = if config.max_connects > 0 && address.attempts.len() >= config.max_connects {
+            Some(Refusal::TooFast)
+        } else if config.max_connections_per_ip > 0 && address.open >= config.max_connections_per_ip
+        {
+            Some(Refusal::TooMany)
+        } else {
+            None
+        };
+        match refusal {
+            Some(refusal) => Err(self.strike(ip, now).unwrap_or(refusal)),
+            None => {
+                address.attempts.push_back(now);
+                Ok(())
+            }
+        }
+    }
+
+    // A connection `check` let through was accepted
+    pub fn opened(&mut self, ip: IpAddr) {
+        self.addresses.entry(ip).or_default().open += 1;
+    }
+
+    // An opened connection closed; dropping one before finishing the
+    // handshake counts as a strike
+    pub fn closed(&mut self, ip: IpAddr, handshaken: bool, now: Instant) {
+        if let Some(address) = self.addresses.get_mut(&ip) {
+            address.open = address.open.saturating_sub(1);
+        }
+        if !handshaken {
+            self.strike(ip, now);
+        }
+    }
+
+    // A connection from the address was dropped for sending too much
+    pub fn flooded(&mut self, ip: IpAddr, now: Instant) {
+        self.strike(ip, now);
+    }
+
+    // Count a strike, banning the address once it has too many. Returns the
+    // ban if this strike started one.
+    fn strike(&mut self, ip: IpAddr, now: Instant) -> Option<Refusal> {
+        let config = &self.config;
+        if config.ban_strikes == 0 {
+            return None;
+        }
+        let address = self.addresses.entry(ip).or_default();
+        address.strikes.push_back(now);
+        if address.strikes.len() < config.ban_strikes {
+            return None;
+        }
+        let ban = Duration::from_secs(config.ban_secs);
+        address.strikes.clear();
+        address.banned_until = Some(now + ban);
+        println!("Temporarily banned {} for {}s", ip, config.ban_secs);
+        Some(Refusal::Banned(ban))
+    }
+
+    // Forget addresses with nothing open, no ban and no recent attempts
+    pub fn prune(&mut self, now: Instant) {
+        let cutoff = now.checked_sub(self.config.window()).unwrap_or(now);
+        self.addresses.retain(|_, address| {
+            address.forget_before(cutoff);
+            address.open > 0
+                || address.banned_until.is_some_and(|until| now < until)
+                || !address.attempts.is_empty()
+                || !address.strikes.is_empty()
+        });
+    }
+
+    // Addresses banned right now
+    pub fn banned(&self, now: Instant) -> usize {
+        self.addresses
+            .values()
+            .filter(|address| address.banned_until.is_some_and(|until| now < until))
+            .count()
+    }
+}
+
+// Record a message sent `now` if fewer than `limit` of the times in `recent`
+// fall within `window`, forgetting the older ones; for per-player chat limits
+pub fn take_slot(
+    recent: &mut VecDeque<Instant>,
+    limit: usize,
+    window: Duration,
+    now: Instant,
+) -> bool {
+    while recent
+        .front()
+        .is_some_and(|&sent| now.duration_since(sent) >= window)
+    {
+        recent.pop_front();
+    }
+    let allowed = recent.len() < limit;
+    if allowed {
+        recent.push_back(now);
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 2));
+
+    fn config() -> ThrottleConfig {
+        ThrottleConfig {
+            max_connects: 3,
+            window_secs: 10,
+            max_connections_per_ip: 2,
+            ban_strikes: 3,
+            ban_secs: 60,
+            ..ThrottleConfig::default()
+        }
+    }
+
+    #[test]
+    fn limits_attempts_per_window() {
+        let mut throttle = Throttle::new(ThrottleConfig {
+            ban_strikes: 0,
+            ..config()
+        });
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(throttle.check(IP, now), Ok(()));
+        }
+        assert_eq!(throttle.check(IP, now), Err(Refusal::TooFast));
+        assert_eq!(throttle.check(OTHER, now), Ok(()));
+        assert_eq!(throttle.check(IP, now + Duration::from_secs(11)), Ok(()));
+    }
+
+    #[test]
+    fn limits_open_connections() {
+        let mut throttle = Throttle::new(ThrottleConfig {
+            ban_strikes: 0,
+            max_connects: 0,
+            ..config()
+        });
+        let now = Instant::now();
+        for _ in 0..2 {
+            throttle.check(IP, now).unwrap();
+            throttle.opened(IP);
+        }
+        assert_eq!(throttle.check(IP, now), Err(Refusal::TooMany));
+        throttle.closed(IP, true, now);
+        assert_eq!(throttle.check(IP, now), Ok(()));
+    }
+
+    #[test]
+    fn strikes_lead_to_a_ban_that_runs_out() {
+        let mut throttle = Throttle::new(config());
+        let now = Instant::now();
+        throttle.flooded(IP, now);
+        throttle.closed(IP, false, now);
+        assert_eq!(throttle.banned(now), 0);
+        throttle.flooded(IP, now);
+        assert_eq!(throttle.banned(now), 1);
+        let later = now + Duration::from_secs(30);
+        assert_eq!(
+            throttle.check(IP, later),
+            Err(Refusal::Banned(Duration::from_secs(30)))
+        );
+        let after = now + Duration::from_secs(61);
+        assert_eq!(throttle.check(IP, after), Ok(()));
+        assert_eq!(throttle.banned(after), 0);
+    }
+
+    #[test]
+    fn refusals_count_as_strikes() {
+        let mut throttle = Throttle::new(ThrottleConfig {
+            max_connects: 1,
+            ..config()
+        });
+        let now = Instant::now();
+        throttle.check(IP, now).unwrap();
+        assert_eq!(throttle.check(IP, now), Err(Refusal::TooFast));
+        assert_eq!(throttle.check(IP, now), Err(Refusal::TooFast));
+        assert_eq!(
+            throttle.check(IP, now),
+            Err(Refusal::Banned(Duration::from_secs(60)))
+        );
+    }
+
+    #[test]
+    fn prune_keeps_only_live_addresses() {
+        let mut throttle = Throttle::new(config());
+        let now = Instant::now();
+        throttle.check(IP, now).unwrap();
+        throttle.opened(IP);
+        throttle.check(OTHER, now).unwrap();
+        throttle.prune(now + Duration::from_secs(11));
+        assert_eq!(throttle.addresses.len(), 1);
+        assert!(throttle.addresses.contains_key(&IP));
+    }
+
+    #[test]
+    fn take_slot_limits_within_the_window() {
+        let mut recent = VecDeque::new();
+        let window = Duration::from_secs(1);
+        let now = Instant::now();
+        assert!(take_slot(&mut recent, 2, window, now));
+        assert!(take_slot(&mut recent, 2, window, now));
+        assert!(!take_slot(&mut recent, 2, window, now));
+        assert!(take_slot(&mut recent, 2, window, now + window));
+    }
+}
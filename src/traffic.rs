@@ -0,0 +1,109 @@
+// What each open connection has sent and been sent: frames and their bytes,
+// in total and by `ClientMessage` variant, counted where they are decoded and
+// written rather than by the game loop, so frames that fail to decode count
+// too. Connections are counted from when the decoder sees them accepted until
+// they close. Besides the totals, each connection has what it did since the
+// game loop last took it, for `throttle`'s message limits.
+use crate::endpoint::Endpoint;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// What frames that don't decode are counted as
+pub const UNDECODABLE: &str = "undecodable";
+
+#[derive(Default)]
+pub struct Traffic {
+    endpoints: DashMap<Endpoint, Entry>,
+}
+
+#[derive(Default)]
+struct Entry {
+    since_opened: Usage,
+    recent: Usage,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Usage {
+    pub total: Counters,
+    pub by_type: BTreeMap<&'static str, Counters>,
+}
+
+impl Usage {
+    fn received(&mut self, kind: &'static str, bytes: u64) {
+        for counters in [&mut self.total, self.by_type.entry(kind).or_default()] {
+            counters.bytes_in += bytes;
+            counters.messages_in += 1;
+        }
+    }
+
+    fn sent(&mut self, kind: &'static str, bytes: u64) {
+        for counters in [&mut self.total, self.by_type.entry(kind).or_default()] {
+            counters.bytes_out += bytes;
+            counters.messages_out += 1;
+        }
+    }
+
+    // The message type received most often
+    pub fn busiest_in(&self) -> Option<&'static str> {
+        self.by_type
+            .iter()
+            .filter(|(_, counters)| counters.messages_in > 0)
+            .max_by_key(|(_, counters)| counters.messages_in)
+            .map(|(&kind, _)| kind)
+    }
+}
+
+impl Traffic {
+    pub fn opened(&self, endpoint: Endpoint) {
+        self.endpoints.insert(endpoint, Entry::default());
+    }
+
+    pub fn closed(&self, endpoint: Endpoint) {
+        self.endpoints.remove(&endpoint);
+    }
+
+    pub fn received(&self, endpoint: Endpoint, kind: &'static str, bytes: usize) {
+        if let Some(mut entry) = self.endpoints.get_mut(&endpoint) {
+            entry.since_opened.received(kind, bytes as u64);
+            entry.recent.received(kind, bytes as u64);
+        }
+    }
+
+    // Frames written after the connection closed, like its `Disconnected`,
+    // aren't counted
+    pub fn sent(&self, endpoint: Endpoint, kind: &'static str, bytes: usize) {
+        if let Some(mut entry) = self.endpoints.get_mut(&endpoint) {
+            entry.since_opened.sent(kind, bytes as u64);
+            entry.recent.sent(kind, bytes as u64);
+        }
+    }
+
+    pub fn get(&self, endpoint: Endpoint) -> Option<Counters> {
+        self.endpoints
+            .get(&endpoint)
+            .map(|entry| entry.since_opened.total)
+    }
+
+    pub fn usage(&self, endpoint: Endpoint) -> Option<Usage> {
+        self.endpoints
+            .get(&endpoint)
+            .map(|entry| entry.since_opened.clone())
+    }
+
+    // What every connection did since the last call, starting the next window
+    pub fn take_recent(&self) -> Vec<(Endpoint, Usage)> {
+        self.endpoints
+            .iter_mut()
+            .map(|mut entry| (*entry.key(), std::mem::take(&mut entry.recent)))
+            .collect()
+    }
+}
@@ -0,0 +1,107 @@
+// A rolling record of where each player has been and what they did, kept for
+// `trails.seconds`. Moderators dump it with `trail <player>` to look into
+// speed or teleport cheats, and emotes are judged against it: clients draw
+// other players `rewind_ticks` behind, so who is close enough to see an emote
+// depends on where they were then rather than where they are now.
+use crate::rooms::RoomId;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// No more actions than this are kept for a player, however fast they come
+const MAX_ACTIONS: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrailConfig {
+    // How much to keep; 0 turns trails, and judging emotes by them, off
+    pub seconds: u64,
+    // How far behind clients draw other players, in ticks
+    pub rewind_ticks: u64,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        TrailConfig {
+            seconds: 30,
+            rewind_ticks: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub tick: u64,
+    pub room: Option<RoomId>,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    pub tick: u64,
+    pub what: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Trail {
+    positions: VecDeque<Sample>,
+    actions: VecDeque<Action>,
+}
+
+impl Trail {
+    // Moves within one tick only keep the last. Anything older than `window`
+    // ticks goes, except the last position before it, which says where the
+    // player still was when the window starts.
+    pub fn moved(&mut self, window: u64, sample: Sample) {
+        if self
+            .positions
+            .back()
+            .is_some_and(|last| last.tick == sample.tick)
+        {
+            self.positions.pop_back();
+        }
+        self.positions.push_back(sample);
+        let cutoff = sample.tick.saturating_sub(window);
+        while self
+            .positions
+            .get(1)
+            .is_some_and(|next| next.tick <= cutoff)
+        {
+            self.positions.pop_front();
+        }
+    }
+
+    pub fn acted(&mut self, window: u64, tick: u64, what: String) {
+        self.actions.push_back(Action { tick, what });
+        let cutoff = tick.saturating_sub(window);
+        while self.actions.len() > MAX_ACTIONS
+            || self
+                .actions
+                .front()
+                .is_some_and(|first| first.tick < cutoff)
+        {
+            self.actions.pop_front();
+        }
+    }
+
+    // Where the player was at `tick`, if they have moved since the trail began
+    pub fn position_at(&self, tick: u64) -> Option<Sample> {
+        let after = self.positions.partition_point(|sample| sample.tick <= tick);
+        after.checked_sub(1).map(|at| self.positions[at])
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &Sample> {
+        self.positions.iter()
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.actions.iter()
+    }
+
+    // The tick of the last thing recorded
+    pub fn last_tick(&self) -> u64 {
+        let moved = self.positions.back().map_or(0, |sample| sample.tick);
+        let acted = self.actions.back().map_or(0, |action| action.tick);
+        moved.max(acted)
+    }
+}
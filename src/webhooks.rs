@@ -0,0 +1,234 @@
+// Outgoing webhooks for server events. Each hook has its own worker thread and
+// bounded queue, so a slow or unreachable endpoint only ever loses its own
+// events and never holds up the game loop.
+use crate::config::WebhookConfig;
+use crate::rooms::{RoomId, TeamId};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Events waiting per hook before new ones are dropped
+const QUEUE_LEN: usize = 256;
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(5);
+// A crash gets one short try, so it doesn't hold up the exit
+const CRASH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    PlayerJoined {
+        player_id: usize,
+        address: String,
+    },
+    PlayerLeft {
+        player_id: usize,
+    },
+    PlayerBanned {
+        player_id: usize,
+        identities: Vec<String>,
+    },
+    RoomCreated {
+        room_id: RoomId,
+        name: String,
+    },
+    RoomClosed {
+        room_id: RoomId,
+    },
+    // `room_id` is none for the lobby
+    MatchStarted {
+        room_id: Option<RoomId>,
+    },
+    MatchEnded {
+        room_id: Option<RoomId>,
+        // None for a draw
+        winner: Option<TeamId>,
+        scores: Vec<(TeamId, u32)>,
+    },
+    Crash {
+        message: String,
+    },
+}
+
+impl WebhookEvent {
+    // Names accepted in a hook's `events` filter
+    pub const NAMES: &'static [&'static str] = &[
+        "player_joined",
+        "player_left",
+        "player_banned",
+        "room_created",
+        "room_closed",
+        "match_started",
+        "match_ended",
+        "crash",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::PlayerJoined { .. } => "player_joined",
+            WebhookEvent::PlayerLeft { .. } => "player_left",
+            WebhookEvent::PlayerBanned { .. } => "player_banned",
+            WebhookEvent::RoomCreated { .. } => "room_created",
+            WebhookEvent::RoomClosed { .. } => "room_closed",
+            WebhookEvent::MatchStarted { .. } => "match_started",
+            WebhookEvent::MatchEnded { .. } => "match_ended",
+            WebhookEvent::Crash { .. } => "crash",
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            WebhookEvent::PlayerJoined { player_id, address } => {
+                format!("Player {} joined from {}", player_id, address)
+            }
+            WebhookEvent::PlayerLeft { player_id } => format!("Player {} left", player_id),
+            WebhookEvent::PlayerBanned {
+                player_id,
+                identities,
+            } => format!(
+                "Player {} was banned ({})",
+                player_id,
+                identities.join(", ")
+            ),
+            WebhookEvent::RoomCreated { room_id, name } => {
+                format!("Room {} ({}) was created", room_id, name)
+            }
+            WebhookEvent::RoomClosed { room_id } => format!("Room {} closed", room_id),
+            WebhookEvent::MatchStarted { room_id } => {
+                format!("A match started in {}", place(*room_id))
+            }
+            WebhookEvent::MatchEnded {
+                room_id,
+                winner,
+                scores,
+            } => {
+                let scores: Vec<String> = scores
+                    .iter()
+                    .map(|(team, score)| format!("team {}: {}", team, score))
+                    .collect();
+                let outcome = match winner {
+                    Some(team) => format!("team {} won", team),
+                    None => "it was a draw".to_string(),
+                };
+                match scores.is_empty() {
+                    true => format!("The match in {} ended: {}", place(*room_id), outcome),
+                    false => format!(
+                        "The match in {} ended: {} ({})",
+                        place(*room_id),
+                        outcome,
+                        scores.join(", ")
+                    ),
+                }
+            }
+            WebhookEvent::Crash { message } => format!("The server crashed: {}", message),
+        }
+    }
+
+    // `content` is what Discord shows and `text` what Slack shows; the event
+    // itself rides along for anything that wants structured data
+    fn payload(&self) -> serde_json::Value {
+        let text = self.describe();
+        json!({ "content": text, "text": text, "data": self })
+    }
+}
+
+struct Hook {
+    url: String,
+    events: Vec<String>,
+    queue: SyncSender<WebhookEvent>,
+}
+
+impl Hook {
+    fn wants(&self, event: &WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.iter().any(|name| name == event.name())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Webhooks {
+    hooks: Arc<Vec<Hook>>,
+}
+
+impl Webhooks {
+    pub fn new(configs: &[WebhookConfig]) -> Self {
+        let hooks = configs
+            .iter()
+            .map(|config| {
+                let (queue, events) = mpsc::sync_channel::<WebhookEvent>(QUEUE_LEN);
+                let url = config.url.clone();
+                thread::spawn(move || {
+                    let agent = agent(TIMEOUT);
+                    for event in events {
+                        deliver(&agent, &url, &event, ATTEMPTS);
+                    }
+                });
+                Hook {
+                    url: config.url.clone(),
+                    events: config.events.clone(),
+                    queue,
+                }
+            })
+            .collect();
+        Webhooks {
+            hooks: Arc::new(hooks),
+        }
+    }
+
+    // Queue an event for every hook that wants it, dropping it for hooks
+    // whose queue is full
+    pub fn notify(&self, event: WebhookEvent) {
+        for hook in self.hooks.iter().filter(|hook| hook.wants(&event)) {
+            if let Err(TrySendError::Full(event)) = hook.queue.try_send(event.clone()) {
+                eprintln!(
+                    "Webhook queue for {} is full, dropping {}",
+                    hook.url,
+                    event.name()
+                );
+            }
+        }
+    }
+
+    // Deliver an event right away on the calling thread, for when the process
+    // is about to exit and the workers won't get to it. One attempt per hook
+    // with a short timeout, without retries.
+    pub fn notify_now(&self, event: WebhookEvent) {
+        let agent = agent(CRASH_TIMEOUT);
+        for hook in self.hooks.iter().filter(|hook| hook.wants(&event)) {
+            deliver(&agent, &hook.url, &event, 1);
+        }
+    }
+}
+
+fn place(room: Option<RoomId>) -> String {
+    match room {
+        Some(room) => format!("room {}", room),
+        None => "the lobby".to_string(),
+    }
+}
+
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(timeout).build()
+}
+
+fn deliver(agent: &ureq::Agent, url: &str, event: &WebhookEvent, attempts: u32) {
+    let payload = event.payload();
+    for attempt in 1..=attempts {
+        match agent.post(url).send_json(&payload) {
+            Ok(_) => return,
+            // Client errors won't go away by retrying
+            Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) && code != 429 => {
+                eprintln!("Webhook {} rejected {}: HTTP {}", url, event.name(), code);
+                return;
+            }
+            Err(e) if attempt < attempts => {
+                eprintln!("Webhook {} failed ({}), retrying", url, e);
+                thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+            }
+            Err(e) => eprintln!("Webhook {} failed, dropping {}: {}", url, event.name(), e),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+// Private messages between joined players. Whispers have their own rate
+// limit, apart from public chat, and never reach a player who muted the sender.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WhisperConfig {
+    // Whispers anyone may send per `window_secs`
+    pub messages: usize,
+    pub window_secs: u64,
+}
+
+impl Default for WhisperConfig {
+    fn default() -> Self {
+        WhisperConfig {
+            messages: 10,
+            window_secs: 10,
+        }
+    }
+}
+
+impl WhisperConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_secs == 0 {
+            return Err("`whispers.window_secs` must be a positive integer".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
@@ -0,0 +1,181 @@
+// Named persistent worlds, for sandbox games. Each name in `worlds.names` is
+// a room that keeps its entities and terrain on disk across restarts. Nothing
+// of it is in memory until the first player enters it with `EnterWorld`, and
+// it is unloaded once it has stood empty for `worlds.idle_secs`. Terrain is a
+// sparse grid of cells, each holding whatever the game puts there.
+//
+// Players change terrain with `ModifyWorld`, one cell at a time, within
+// `reach` of where they stand and no more than `edits` per
+// `edit_window_secs`; guests only if `guests_edit`. Each change names what
+// its sender saw in the cell, and the first of two changes made from the
+// same sight wins: the second is refused and its sender is sent the cell as
+// it is. Changes are journaled as they are made; the world is saved whole
+// when its entities change, once `compact_after` changes piled up in the
+// journal, and on unloading.
+//
+// Terrain is streamed rather than sent whole: it is cut into square chunks of
+// `chunk_size` cells, and each player is sent the chunks within
+// `view_chunks` of the one they stand in as they move, with `TerrainChunk`.
+// Chunks a step further out than that are kept, so walking back and forth
+// over a chunk border doesn't resend them; beyond, the client is told to
+// `ForgetChunk`, and isn't sent its edits until it is streamed again.
+use crate::entities::Entity;
+use crate::protocol::TerrainCell;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+// Longest world name and terrain cell value accepted, in bytes
+const MAX_NAME_LEN: usize = 32;
+pub const MAX_CELL_LEN: usize = 64;
+// Bounds on chunks, so a player entering a world isn't sent all of it at once
+const MAX_CHUNK_SIZE: u32 = 256;
+const MAX_VIEW_CHUNKS: u32 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub names: BTreeSet<String>,
+    pub idle_secs: u64,
+    // The most terrain cells a world keeps edits for
+    pub max_cells: usize,
+    // How many cells wide a chunk is, and how many world units a cell is
+    pub chunk_size: u32,
+    pub cell_size: f32,
+    // How many chunks out from their own each player is sent
+    pub view_chunks: u32,
+    // How far from a cell's center, in world units, a player may change it
+    pub reach: f32,
+    pub edits: usize,
+    pub edit_window_secs: u64,
+    pub guests_edit: bool,
+    pub compact_after: usize,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            names: BTreeSet::new(),
+            idle_secs: 300,
+            max_cells: 65536,
+            chunk_size: 16,
+            cell_size: 1.0,
+            view_chunks: 2,
+            reach: 8.0,
+            edits: 20,
+            edit_window_secs: 1,
+            guests_edit: false,
+            compact_after: 1024,
+        }
+    }
+}
+
+impl WorldConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.idle_secs == 0 {
+            return Err("`worlds.idle_secs` must be at least 1".to_string());
+        }
+        if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_SIZE {
+            return Err(format!("`worlds.chunk_size` must be 1-{}", MAX_CHUNK_SIZE));
+        }
+        if !(self.cell_size.is_finite() && self.cell_size > 0.0) {
+            return Err("`worlds.cell_size` must be above 0".to_string());
+        }
+        if self.view_chunks > MAX_VIEW_CHUNKS {
+            return Err(format!(
+                "`worlds.view_chunks` must be at most {}",
+                MAX_VIEW_CHUNKS
+            ));
+        }
+        if !(self.reach.is_finite() && self.reach >= 0.0) {
+            return Err("`worlds.reach` must be 0 or more".to_string());
+        }
+        if self.edit_window_secs == 0 {
+            return Err("`worlds.edit_window_secs` must be at least 1".to_string());
+        }
+        // Names end up in file names
+        let valid = |name: &String| {
+            (1..=MAX_NAME_LEN).contains(&name.len())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+        if let Some(name) = self.names.iter().find(|name| !valid(name)) {
+            return Err(format!(
+                "world `{}`: names must be 1-{} letters, digits, `-` or `_`",
+                name, MAX_NAME_LEN
+            ));
+        }
+        Ok(())
+    }
+
+    // The chunk holding a cell
+    pub fn chunk_of(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        let size = self.chunk_size as i32;
+        (x.div_euclid(size), y.div_euclid(size))
+    }
+
+    pub fn edit_window(&self) -> Duration {
+        Duration::from_secs(self.edit_window_secs)
+    }
+
+    // The cell a point in the world lies in
+    pub fn cell_at(&self, (x, y): (f32, f32)) -> (i32, i32) {
+        (
+            (x / self.cell_size).floor() as i32,
+            (y / self.cell_size).floor() as i32,
+        )
+    }
+
+    // Where a cell's center lies in the world
+    pub fn cell_center(&self, (x, y): (i32, i32)) -> (f32, f32) {
+        (
+            (x as f32 + 0.5) * self.cell_size,
+            (y as f32 + 0.5) * self.cell_size,
+        )
+    }
+}
+
+// What is kept of a world on disk
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct WorldSave {
+    pub entities: Vec<Entity>,
+    pub terrain: Vec<TerrainCell>,
+}
+
+impl WorldSave {
+    pub fn storage_key(name: &str) -> String {
+        format!("world-{}", name)
+    }
+
+    // Bring a save up to date with its journal
+    pub fn replay(&mut self, journal: Vec<TerrainEdit>) {
+        if journal.is_empty() {
+            return;
+        }
+        let mut terrain: BTreeMap<(i32, i32), String> = self
+            .terrain
+            .drain(..)
+            .map(|cell| ((cell.x, cell.y), cell.value))
+            .collect();
+        for edit in journal {
+            match edit.value {
+                Some(value) => terrain.insert((edit.x, edit.y), value),
+                None => terrain.remove(&(edit.x, edit.y)),
+            };
+        }
+        self.terrain = terrain
+            .into_iter()
+            .map(|((x, y), value)| TerrainCell { x, y, value })
+            .collect();
+    }
+}
+
+// A journal entry: a cell was set, or cleared with `None`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TerrainEdit {
+    pub x: i32,
+    pub y: i32,
+    pub value: Option<String>,
+}